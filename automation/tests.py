@@ -28,6 +28,7 @@ Other Modes:
     - swiftlint
     - swiftformat
     - nss-bindings
+    - wasm-check
     - gradle
     - ios-tests
     - python-tests
@@ -357,6 +358,21 @@ def run_nss_bindings_test():
     )
 
 
+def run_wasm_check():
+    # Only rc_crypto is expected to build for wasm32 so far; add crates here
+    # as they gain support.
+    run_command(
+        [
+            "cargo",
+            "check",
+            "--target",
+            "wasm32-unknown-unknown",
+            "-p",
+            "rc_crypto",
+        ]
+    )
+
+
 def run_clippy(package, features):
     run_command(
         [
@@ -576,6 +592,9 @@ def calc_steps(args):
     elif args.mode == "nss-bindings":
         print_rust_environment()
         yield Step("NSS bindings test", run_nss_bindings_test)
+    elif args.mode == "wasm-check":
+        print_rust_environment()
+        yield Step("wasm32 check for rc_crypto", run_wasm_check)
     elif args.mode == "gradle":
         yield Step("gradle tests", run_gradle_tests)
     elif args.mode == "ios-tests":
@@ -6,8 +6,10 @@ extern crate bhttp;
 extern crate ohttp;
 extern crate rusqlite;
 
-use parking_lot::Mutex;
-use std::collections::HashMap;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, thiserror::Error)]
 pub enum OhttpError {
@@ -20,6 +22,9 @@ pub enum OhttpError {
     #[error("Unsupported OHTTP encryption algorithm")]
     UnsupportedKeyConfig,
 
+    #[error("Gateway rejected the OHTTP key config")]
+    KeyConfigRejected,
+
     #[error("OhttpSession is in invalid state")]
     InvalidSession,
 
@@ -36,6 +41,105 @@ pub enum OhttpError {
     DuplicateHeaders,
 }
 
+impl From<uniffi::UnexpectedUniFFICallbackError> for OhttpError {
+    fn from(_: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        OhttpError::KeyFetchFailed
+    }
+}
+
+/// The statuses the relay passes back from a gateway that couldn't decrypt a
+/// request, for example because it has rotated its keys.
+const KEY_CONFIG_ERROR_STATUSES: [u16; 2] = [400, 401];
+
+/// How long a fetched key config is used for if the fetcher doesn't say.
+const DEFAULT_KEY_CONFIG_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// A gateway key config, as returned by a [KeyConfigFetcher].
+pub struct FetchedKeyConfig {
+    pub config: Vec<u8>,
+    /// How long the config can be cached for, typically from the
+    /// `Cache-Control: max-age` of the response.
+    pub max_age_secs: Option<u64>,
+}
+
+/// Fetches gateway key configs from the network. This crate doesn't make
+/// network requests itself, so the application supplies one with
+/// [set_key_config_fetcher].
+pub trait KeyConfigFetcher: Send + Sync {
+    fn fetch_key_config(&self, url: String) -> Result<FetchedKeyConfig, OhttpError>;
+}
+
+static KEY_CONFIG_FETCHER: RwLock<Option<Arc<dyn KeyConfigFetcher>>> =
+    parking_lot::const_rwlock(None);
+static KEY_CONFIG_CACHE: KeyConfigCache = KeyConfigCache::new();
+
+/// Set the fetcher used by [OhttpSession::fetch_key_config].
+pub fn set_key_config_fetcher(fetcher: Box<dyn KeyConfigFetcher>) {
+    *KEY_CONFIG_FETCHER.write() = Some(Arc::from(fetcher));
+}
+
+/// Fetch the gateway key config at `url`. See [OhttpSession::fetch_key_config].
+pub fn fetch_key_config(url: &str) -> Result<Vec<u8>, OhttpError> {
+    OhttpSession::fetch_key_config(url)
+}
+
+/// Forget any cached key config for `url`, so the next call to
+/// [OhttpSession::fetch_key_config] fetches it again. Sessions created with
+/// [OhttpSession::new_with_key_config_url] do this for themselves in
+/// [OhttpSession::check_relay_response].
+pub fn invalidate_key_config(url: &str) {
+    KEY_CONFIG_CACHE.invalidate(url);
+}
+
+struct CachedKeyConfig {
+    config: Vec<u8>,
+    expires_at: Instant,
+}
+
+/// Key configs by URL, each kept until it expires or is invalidated.
+struct KeyConfigCache {
+    entries: Mutex<BTreeMap<String, CachedKeyConfig>>,
+}
+
+impl KeyConfigCache {
+    const fn new() -> Self {
+        KeyConfigCache {
+            entries: parking_lot::const_mutex(BTreeMap::new()),
+        }
+    }
+
+    fn get(
+        &self,
+        url: &str,
+        now: Instant,
+        fetcher: &dyn KeyConfigFetcher,
+    ) -> Result<Vec<u8>, OhttpError> {
+        if let Some(cached) = self.entries.lock().get(url) {
+            if now < cached.expires_at {
+                return Ok(cached.config.clone());
+            }
+        }
+        // Don't hold the lock while fetching; at worst, concurrent callers
+        // fetch the same config more than once.
+        let fetched = fetcher.fetch_key_config(url.to_string())?;
+        let max_age = fetched
+            .max_age_secs
+            .map_or(DEFAULT_KEY_CONFIG_MAX_AGE, Duration::from_secs);
+        self.entries.lock().insert(
+            url.to_string(),
+            CachedKeyConfig {
+                config: fetched.config.clone(),
+                expires_at: now + max_age,
+            },
+        );
+        Ok(fetched.config)
+    }
+
+    fn invalidate(&self, url: &str) {
+        self.entries.lock().remove(url);
+    }
+}
+
 #[derive(Default)]
 enum ExchangeState {
     #[default]
@@ -46,12 +150,14 @@ enum ExchangeState {
 
 pub struct OhttpSession {
     state: Mutex<ExchangeState>,
+    // Where the key config came from, if it was fetched for us.
+    key_config_url: Option<String>,
 }
 
 pub struct OhttpResponse {
-    status_code: u16,
-    headers: HashMap<String, String>,
-    payload: Vec<u8>,
+    pub status_code: u16,
+    pub headers: HashMap<String, String>,
+    pub payload: Vec<u8>,
 }
 
 /// Transform the headers from a BHTTP message into a HashMap for use from Swift
@@ -89,7 +195,33 @@ impl OhttpSession {
         })?;
 
         let state = Mutex::new(ExchangeState::Request(request));
-        Ok(OhttpSession { state })
+        Ok(OhttpSession {
+            state,
+            key_config_url: None,
+        })
+    }
+
+    /// Create a new encryption session using the key config fetched from
+    /// `url`. If the response can't be decrypted, the gateway may have
+    /// changed its keys, so the cached config is dropped and the next
+    /// session fetches it again.
+    pub fn new_with_key_config_url(url: &str) -> Result<Self, OhttpError> {
+        let config = Self::fetch_key_config(url)?;
+        Ok(OhttpSession {
+            key_config_url: Some(url.to_string()),
+            ..Self::new(&config)?
+        })
+    }
+
+    /// Fetch the gateway key config at `url` with the fetcher set by
+    /// [set_key_config_fetcher], or return the cached copy if it hasn't
+    /// expired yet.
+    pub fn fetch_key_config(url: &str) -> Result<Vec<u8>, OhttpError> {
+        let fetcher = KEY_CONFIG_FETCHER
+            .read()
+            .clone()
+            .ok_or(OhttpError::KeyFetchFailed)?;
+        KEY_CONFIG_CACHE.get(url, Instant::now(), &*fetcher)
     }
 
     /// Encode an HTTP request in Binary HTTP format and then encrypt it into an
@@ -130,6 +262,24 @@ impl OhttpSession {
         Ok(capsule)
     }
 
+    /// Check the status of the relay's response to a request encoded on this
+    /// session, before decapsulating it. A gateway that can't decrypt the
+    /// request answers with an error status rather than an OHTTP response, in
+    /// which case the cached config is dropped so that the next session
+    /// fetches it again, and `KeyConfigRejected` is returned.
+    pub fn check_relay_response(&self, status_code: u16) -> Result<(), OhttpError> {
+        if status_code == 200 {
+            return Ok(());
+        }
+        if KEY_CONFIG_ERROR_STATUSES.contains(&status_code) {
+            if let Some(url) = &self.key_config_url {
+                KEY_CONFIG_CACHE.invalidate(url);
+            }
+            return Err(OhttpError::KeyConfigRejected);
+        }
+        Err(OhttpError::RelayFailed)
+    }
+
     /// Decode an OHTTP response returned in response to a request encoded on
     /// this session.
     pub fn decapsulate(&self, encoded: &[u8]) -> Result<OhttpResponse, OhttpError> {
@@ -138,9 +288,12 @@ impl OhttpSession {
             ExchangeState::Response(response) => response,
             _ => return Err(OhttpError::InvalidSession),
         };
-        let binary = decoder
-            .decapsulate(encoded)
-            .map_err(|_| OhttpError::MalformedMessage)?;
+        let binary = decoder.decapsulate(encoded).map_err(|_| {
+            if let Some(url) = &self.key_config_url {
+                KEY_CONFIG_CACHE.invalidate(url);
+            }
+            OhttpError::MalformedMessage
+        })?;
 
         let mut cursor = std::io::Cursor::new(binary);
         let message =
@@ -302,6 +455,123 @@ mod tests {
         assert_eq!(response.headers, header);
         assert_eq!(response.payload, body);
     }
+
+    struct TestFetcher {
+        config: Vec<u8>,
+        max_age_secs: Option<u64>,
+        fetches: Mutex<Vec<String>>,
+    }
+
+    impl TestFetcher {
+        fn new(config: Vec<u8>, max_age_secs: Option<u64>) -> Self {
+            TestFetcher {
+                config,
+                max_age_secs,
+                fetches: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl KeyConfigFetcher for TestFetcher {
+        fn fetch_key_config(&self, url: String) -> Result<FetchedKeyConfig, OhttpError> {
+            self.fetches.lock().push(url);
+            Ok(FetchedKeyConfig {
+                config: self.config.clone(),
+                max_age_secs: self.max_age_secs,
+            })
+        }
+    }
+
+    #[test]
+    fn test_key_config_cache() {
+        let cache = KeyConfigCache::new();
+        let fetcher = TestFetcher::new(vec![1, 2, 3], None);
+        let now = Instant::now();
+
+        assert_eq!(cache.get("a", now, &fetcher).unwrap(), vec![1, 2, 3]);
+        assert_eq!(cache.get("a", now, &fetcher).unwrap(), vec![1, 2, 3]);
+        assert_eq!(*fetcher.fetches.lock(), vec!["a"]);
+
+        // Each URL is cached separately.
+        cache.get("b", now, &fetcher).unwrap();
+        assert_eq!(*fetcher.fetches.lock(), vec!["a", "b"]);
+
+        // Expired configs are fetched again.
+        let later = now + DEFAULT_KEY_CONFIG_MAX_AGE;
+        cache.get("a", later, &fetcher).unwrap();
+        cache.get("a", later, &fetcher).unwrap();
+        assert_eq!(*fetcher.fetches.lock(), vec!["a", "b", "a"]);
+
+        // As are invalidated ones.
+        cache.invalidate("a");
+        cache.get("a", later, &fetcher).unwrap();
+        assert_eq!(*fetcher.fetches.lock(), vec!["a", "b", "a", "a"]);
+    }
+
+    #[test]
+    fn test_key_config_max_age() {
+        let cache = KeyConfigCache::new();
+        let fetcher = TestFetcher::new(vec![1, 2, 3], Some(10));
+        let now = Instant::now();
+
+        cache.get("a", now, &fetcher).unwrap();
+        cache
+            .get("a", now + Duration::from_secs(9), &fetcher)
+            .unwrap();
+        assert_eq!(fetcher.fetches.lock().len(), 1);
+        cache
+            .get("a", now + Duration::from_secs(10), &fetcher)
+            .unwrap();
+        assert_eq!(fetcher.fetches.lock().len(), 2);
+    }
+
+    #[test]
+    fn test_key_config_url_session() {
+        let server = OhttpTestServer::new();
+        let url = "https://gateway.example.com/ohttp-configs";
+        set_key_config_fetcher(Box::new(TestFetcher::new(server.get_config(), None)));
+
+        let session = OhttpSession::new_with_key_config_url(url).unwrap();
+        let message = session
+            .encapsulate("GET", "https", "example.com", "/api", HashMap::new(), &[])
+            .unwrap();
+        server.receive(&message).unwrap();
+        assert!(KEY_CONFIG_CACHE.entries.lock().contains_key(url));
+
+        // A response we can't decrypt drops the cached config.
+        assert!(matches!(
+            session.decapsulate(&[0x00, 0x01, 0x02]),
+            Err(OhttpError::MalformedMessage)
+        ));
+        assert!(!KEY_CONFIG_CACHE.entries.lock().contains_key(url));
+    }
+
+    #[test]
+    fn test_check_relay_response() {
+        let server = OhttpTestServer::new();
+        let url = "https://rotated-gateway.example.com/ohttp-configs";
+        // Don't replace the global fetcher, which other tests use.
+        let fetcher = TestFetcher::new(server.get_config(), None);
+        let config = KEY_CONFIG_CACHE.get(url, Instant::now(), &fetcher).unwrap();
+        let session = OhttpSession {
+            key_config_url: Some(url.to_string()),
+            ..OhttpSession::new(&config).unwrap()
+        };
+
+        session.check_relay_response(200).unwrap();
+        assert!(matches!(
+            session.check_relay_response(502),
+            Err(OhttpError::RelayFailed)
+        ));
+        assert!(KEY_CONFIG_CACHE.entries.lock().contains_key(url));
+
+        // The gateway couldn't decrypt the request with the cached config.
+        assert!(matches!(
+            session.check_relay_response(400),
+            Err(OhttpError::KeyConfigRejected)
+        ));
+        assert!(!KEY_CONFIG_CACHE.entries.lock().contains_key(url));
+    }
 }
 
 uniffi::include_scaffolding!("as_ohttp_client");
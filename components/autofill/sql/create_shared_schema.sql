@@ -65,7 +65,11 @@ CREATE TABLE IF NOT EXISTS credit_cards_data (
     times_used          INTEGER NOT NULL,
 
     /* Same "sync change counter" strategy used by other components. */
-    sync_change_counter INTEGER NOT NULL
+    sync_change_counter INTEGER NOT NULL,
+
+    -- The token the app's `CreditCardTokenizer` gave us for the card number.
+    -- When this is set, `cc_number_enc` is blank.
+    cc_number_token     TEXT
 );
 
 CREATE TABLE IF NOT EXISTS credit_cards_mirror (
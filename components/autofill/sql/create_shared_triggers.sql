@@ -41,6 +41,22 @@ BEGIN
     SELECT RAISE(FAIL, 'guid exists in `credit_cards_data`');
 END;
 
+-- A tokenized card keeps its token in `cc_number_token`, and mustn't also have
+-- an encrypted number, so that neither can be mistaken for the other.
+CREATE TEMP TRIGGER IF NOT EXISTS credit_cards_data_token_insert_trigger
+BEFORE INSERT ON credit_cards_data
+WHEN NEW.cc_number_token IS NOT NULL AND NEW.cc_number_enc <> ''
+BEGIN
+    SELECT RAISE(FAIL, 'tokenized credit card has an encrypted number');
+END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS credit_cards_data_token_update_trigger
+BEFORE UPDATE OF cc_number_enc, cc_number_token ON credit_cards_data
+WHEN NEW.cc_number_token IS NOT NULL AND NEW.cc_number_enc <> ''
+BEGIN
+    SELECT RAISE(FAIL, 'tokenized credit card has an encrypted number');
+END;
+
 CREATE TEMP TRIGGER IF NOT EXISTS credit_cards_tombstones_create_trigger
 AFTER DELETE ON credit_cards_data
 WHEN OLD.guid IN (SELECT guid FROM credit_cards_mirror)
@@ -0,0 +1,380 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Per-country address formats, so form-fill UIs can render the right fields,
+// in the right order, with the right labels.
+//
+// The data here is a subset of Google's libaddressinput metadata
+// (https://chromium-i18n.appspot.com/ssl-address), and uses the same
+// conventions:
+// * `fmt` describes the layout, with `%n` separating lines and `%X` codes for
+//   the fields. Codes we don't store (eg, `%X` for a sorting code) are skipped.
+// * `require` and `upper` are strings of those same codes.
+// * The labels are libaddressinput's "name types" (eg, "state", "province" or
+//   "prefecture"), for the app to localize.
+
+use crate::db::models::address::UpdatableAddressFields;
+
+/// A field of an address, as laid out by an `AddressFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressField {
+    Name,
+    Organization,
+    StreetAddress,
+    AddressLevel3,
+    AddressLevel2,
+    AddressLevel1,
+    PostalCode,
+}
+
+impl AddressField {
+    fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            'N' => Self::Name,
+            'O' => Self::Organization,
+            'A' => Self::StreetAddress,
+            'D' => Self::AddressLevel3,
+            'C' => Self::AddressLevel2,
+            'S' => Self::AddressLevel1,
+            'Z' => Self::PostalCode,
+            _ => return None,
+        })
+    }
+
+    fn value(self, address: &UpdatableAddressFields) -> &str {
+        match self {
+            Self::Name => &address.name,
+            Self::Organization => &address.organization,
+            Self::StreetAddress => &address.street_address,
+            Self::AddressLevel3 => &address.address_level3,
+            Self::AddressLevel2 => &address.address_level2,
+            Self::AddressLevel1 => &address.address_level1,
+            Self::PostalCode => &address.postal_code,
+        }
+    }
+}
+
+/// How addresses are written in a country.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressFormat {
+    /// The ISO 3166-1 alpha-2 code of the country, or "ZZ" for the format used
+    /// for countries we don't have specific data for.
+    pub country: String,
+    /// The fields to show, a line at a time, in order.
+    pub lines: Vec<Vec<AddressField>>,
+    pub required_fields: Vec<AddressField>,
+    /// Fields which are conventionally written in upper case.
+    pub uppercase_fields: Vec<AddressField>,
+    pub address_level1_label: String,
+    pub address_level2_label: String,
+    pub address_level3_label: String,
+    pub postal_code_label: String,
+}
+
+struct FormatData {
+    country: &'static str,
+    fmt: &'static str,
+    require: &'static str,
+    upper: &'static str,
+    address_level1_label: &'static str,
+    address_level2_label: &'static str,
+    address_level3_label: &'static str,
+    postal_code_label: &'static str,
+}
+
+const DEFAULT_FORMAT: FormatData = FormatData {
+    country: "ZZ",
+    fmt: "%N%n%O%n%A%n%C",
+    require: "AC",
+    upper: "C",
+    address_level1_label: "province",
+    address_level2_label: "city",
+    address_level3_label: "suburb",
+    postal_code_label: "postal",
+};
+
+// Sorted by country.
+const FORMATS: &[FormatData] = &[
+    FormatData {
+        country: "AU",
+        fmt: "%O%n%N%n%A%n%C %S %Z",
+        require: "ACSZ",
+        upper: "CS",
+        address_level1_label: "state",
+        address_level2_label: "suburb",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "BR",
+        fmt: "%O%n%N%n%A%n%D%n%C-%S%n%Z",
+        require: "ASCZ",
+        upper: "CS",
+        address_level1_label: "state",
+        address_level3_label: "neighborhood",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "CA",
+        fmt: "%N%n%O%n%A%n%C %S %Z",
+        require: "ACSZ",
+        upper: "ACNOSZ",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "CN",
+        fmt: "%Z%n%S%C%D%n%A%n%O%n%N",
+        require: "ACSZ",
+        upper: "",
+        address_level3_label: "district",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "DE",
+        fmt: "%N%n%O%n%A%n%Z %C",
+        require: "ACZ",
+        upper: "",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "FR",
+        fmt: "%O%n%N%n%A%n%Z %C",
+        require: "ACZ",
+        upper: "C",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "GB",
+        fmt: "%N%n%O%n%A%n%C%n%Z",
+        require: "ACZ",
+        upper: "CZ",
+        address_level2_label: "post_town",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "IN",
+        fmt: "%N%n%O%n%A%n%T%n%F%n%L%n%C %Z%n%S",
+        require: "ACSZ",
+        upper: "",
+        address_level1_label: "state",
+        postal_code_label: "pin",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "IT",
+        fmt: "%N%n%O%n%A%n%Z %C %S",
+        require: "ACSZ",
+        upper: "CS",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "JP",
+        fmt: "〒%Z%n%S%n%A%n%O%n%N",
+        require: "ASZ",
+        upper: "",
+        address_level1_label: "prefecture",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "MX",
+        fmt: "%N%n%O%n%A%n%D%n%Z %C, %S",
+        require: "ACZ",
+        upper: "CSZ",
+        address_level1_label: "state",
+        address_level3_label: "neighborhood",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "NZ",
+        fmt: "%N%n%O%n%A%n%D%n%C %Z",
+        require: "ACZ",
+        upper: "",
+        ..DEFAULT_FORMAT
+    },
+    FormatData {
+        country: "US",
+        fmt: "%N%n%O%n%A%n%C, %S %Z",
+        require: "ACSZ",
+        upper: "CS",
+        address_level1_label: "state",
+        postal_code_label: "zip",
+        ..DEFAULT_FORMAT
+    },
+];
+
+fn find_format_data(country: &str) -> Option<&'static FormatData> {
+    FORMATS
+        .iter()
+        .find(|data| data.country.eq_ignore_ascii_case(country.trim()))
+}
+
+// Returns the fields for the `%X` codes in `s`.
+fn parse_fields(s: &str) -> Vec<AddressField> {
+    let mut fields = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            if let Some(field) = chars.next().and_then(AddressField::from_code) {
+                fields.push(field);
+            }
+        }
+    }
+    fields
+}
+
+// Required or upper-case fields are listed without the `%`.
+fn parse_codes(s: &str) -> Vec<AddressField> {
+    s.chars().filter_map(AddressField::from_code).collect()
+}
+
+impl From<&FormatData> for AddressFormat {
+    fn from(data: &FormatData) -> Self {
+        Self {
+            country: data.country.to_string(),
+            lines: data
+                .fmt
+                .split("%n")
+                .map(parse_fields)
+                .filter(|line| !line.is_empty())
+                .collect(),
+            required_fields: parse_codes(data.require),
+            uppercase_fields: parse_codes(data.upper),
+            address_level1_label: data.address_level1_label.to_string(),
+            address_level2_label: data.address_level2_label.to_string(),
+            address_level3_label: data.address_level3_label.to_string(),
+            postal_code_label: data.postal_code_label.to_string(),
+        }
+    }
+}
+
+/// Returns the address format for `country`, an ISO 3166-1 alpha-2 code, or
+/// a generic format if we don't have one for it.
+pub fn get_address_format(country: String) -> AddressFormat {
+    find_format_data(&country).unwrap_or(&DEFAULT_FORMAT).into()
+}
+
+/// Tidies up an address before it's stored.
+///
+/// Addresses missing fields their country requires are still stored, since
+/// people save partial addresses and we've always accepted them, but we log a
+/// warning. Apps which want to insist on them can check `required_fields`.
+/// Records from sync aren't normalized, since they may have come from clients
+/// which don't do this.
+pub(crate) fn normalize_address(mut address: UpdatableAddressFields) -> UpdatableAddressFields {
+    for value in [
+        &mut address.name,
+        &mut address.organization,
+        &mut address.street_address,
+        &mut address.address_level3,
+        &mut address.address_level2,
+        &mut address.address_level1,
+        &mut address.postal_code,
+        &mut address.country,
+        &mut address.tel,
+        &mut address.email,
+    ] {
+        let trimmed = value.trim();
+        if trimmed.len() != value.len() {
+            *value = trimmed.to_string();
+        }
+    }
+    let Some(data) = find_format_data(&address.country) else {
+        return address;
+    };
+    address.country = data.country.to_string();
+    let format = AddressFormat::from(data);
+    if format.uppercase_fields.contains(&AddressField::PostalCode) {
+        address.postal_code = address.postal_code.to_uppercase();
+    }
+    let missing = format
+        .required_fields
+        .into_iter()
+        .filter(|field| field.value(&address).is_empty())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        log::warn!(
+            "address is missing required fields for {}: {:?}",
+            address.country,
+            missing
+        );
+    }
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_address_format() {
+        let us = get_address_format("us".to_string());
+        assert_eq!(us.country, "US");
+        assert_eq!(
+            us.lines,
+            vec![
+                vec![AddressField::Name],
+                vec![AddressField::Organization],
+                vec![AddressField::StreetAddress],
+                vec![
+                    AddressField::AddressLevel2,
+                    AddressField::AddressLevel1,
+                    AddressField::PostalCode
+                ],
+            ]
+        );
+        assert_eq!(us.address_level1_label, "state");
+        assert_eq!(us.postal_code_label, "zip");
+
+        // Unknown codes in the format are skipped, as are lines with only those.
+        let india = get_address_format("IN".to_string());
+        assert_eq!(india.lines.len(), 5);
+        assert_eq!(india.postal_code_label, "pin");
+
+        let jp = get_address_format("JP".to_string());
+        assert_eq!(jp.lines[0], vec![AddressField::PostalCode]);
+        assert_eq!(jp.address_level1_label, "prefecture");
+
+        let unknown = get_address_format("United States".to_string());
+        assert_eq!(unknown.country, "ZZ");
+        assert_eq!(
+            unknown.required_fields,
+            vec![AddressField::StreetAddress, AddressField::AddressLevel2]
+        );
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        let address = UpdatableAddressFields {
+            name: " Jane Doe ".to_string(),
+            street_address: "1 Main St".to_string(),
+            address_level2: "Ottawa".to_string(),
+            address_level1: "ON".to_string(),
+            postal_code: "k1a 0b1".to_string(),
+            country: "ca".to_string(),
+            ..Default::default()
+        };
+        let normalized = normalize_address(address.clone());
+        assert_eq!(normalized.name, "Jane Doe");
+        assert_eq!(normalized.postal_code, "K1A 0B1");
+        assert_eq!(normalized.country, "CA");
+
+        // Partial addresses are still normalized, not rejected.
+        let missing = UpdatableAddressFields {
+            postal_code: String::new(),
+            ..address
+        };
+        let normalized = normalize_address(missing);
+        assert_eq!(normalized.name, "Jane Doe");
+        assert_eq!(normalized.postal_code, "");
+        assert_eq!(normalized.country, "CA");
+
+        // We don't know what to require for a country name.
+        let unknown = UpdatableAddressFields {
+            name: "Jane Doe".to_string(),
+            country: "United States".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(normalize_address(unknown).country, "United States");
+    }
+}
@@ -3,6 +3,7 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/.
 */
 
+use crate::address_format::normalize_address;
 use crate::db::{
     models::{
         address::{InternalAddress, UpdatableAddressFields},
@@ -20,6 +21,7 @@ pub(crate) fn add_address(
     conn: &Connection,
     new: UpdatableAddressFields,
 ) -> Result<InternalAddress> {
+    let new = normalize_address(new);
     let tx = conn.unchecked_transaction()?;
     let now = Timestamp::now();
 
@@ -122,6 +124,7 @@ pub(crate) fn update_address(
     guid: &Guid,
     address: &UpdatableAddressFields,
 ) -> Result<()> {
+    let address = normalize_address(address.clone());
     let tx = conn.unchecked_transaction()?;
     tx.execute(
         "UPDATE addresses_data
@@ -337,6 +340,39 @@ mod tests {
         assert!(get_address(&db, &saved_address.guid).is_err());
     }
 
+    #[test]
+    fn test_address_normalized() {
+        let db = new_mem_db();
+        let fields = UpdatableAddressFields {
+            name: "jane doe ".to_string(),
+            street_address: "123 Main Street".to_string(),
+            address_level2: "Seattle".to_string(),
+            address_level1: "WA".to_string(),
+            postal_code: "98101".to_string(),
+            country: "us".to_string(),
+            ..UpdatableAddressFields::default()
+        };
+        let saved_address = add_address(&db, fields.clone()).expect("should be valid");
+        let retrieved_address = get_address(&db, &saved_address.guid).unwrap();
+        assert_eq!(retrieved_address.name, "jane doe");
+        assert_eq!(retrieved_address.country, "US");
+
+        // US addresses need a state, but we still save them without one.
+        update_address(
+            &db,
+            &saved_address.guid,
+            &UpdatableAddressFields {
+                name: " john doe".to_string(),
+                address_level1: String::new(),
+                ..fields
+            },
+        )
+        .expect("partial addresses should be saved");
+        let retrieved_address = get_address(&db, &saved_address.guid).unwrap();
+        assert_eq!(retrieved_address.name, "john doe");
+        assert_eq!(retrieved_address.address_level1, "");
+    }
+
     #[test]
     fn test_address_missing_guid() {
         let db = new_mem_db();
@@ -5,20 +5,37 @@
 
 use crate::db::{
     models::{
-        credit_card::{InternalCreditCard, UpdatableCreditCardFields},
+        credit_card::{
+            CreditCardTokenizer, DedupedCreditCard, InternalCreditCard, UpdatableCreditCardFields,
+        },
         Metadata,
     },
     schema::{CREDIT_CARD_COMMON_COLS, CREDIT_CARD_COMMON_VALS},
 };
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 
+use rc_crypto::digest;
 use rusqlite::{Connection, Transaction};
+use sql_support::ConnExt;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use sync_guid::Guid;
 use types::Timestamp;
 
 pub(crate) fn add_credit_card(
     conn: &Connection,
     new_credit_card_fields: UpdatableCreditCardFields,
+) -> Result<InternalCreditCard> {
+    add_credit_card_with_token(conn, new_credit_card_fields, None)
+}
+
+/// Like `add_credit_card()`, but for a card whose number was replaced by
+/// `cc_number_token`, in which case `cc_number_enc` is blank.
+pub(crate) fn add_credit_card_with_token(
+    conn: &Connection,
+    new_credit_card_fields: UpdatableCreditCardFields,
+    cc_number_token: Option<String>,
 ) -> Result<InternalCreditCard> {
     let now = Timestamp::now();
 
@@ -39,6 +56,7 @@ pub(crate) fn add_credit_card(
             time_last_modified: now,
             ..Default::default()
         },
+        cc_number_token,
     };
 
     let tx = conn.unchecked_transaction()?;
@@ -76,6 +94,7 @@ pub(crate) fn add_internal_credit_card(
             ":time_last_modified": card.metadata.time_last_modified,
             ":times_used": card.metadata.times_used,
             ":sync_change_counter": card.metadata.sync_change_counter,
+            ":cc_number_token": card.cc_number_token,
         },
     )?;
     Ok(())
@@ -118,6 +137,20 @@ pub fn update_credit_card(
     conn: &Connection,
     guid: &Guid,
     credit_card: &UpdatableCreditCardFields,
+) -> Result<()> {
+    update_credit_card_with_token(conn, guid, credit_card, None)
+}
+
+/// Like `update_credit_card()`, but for a card whose number was replaced by
+/// `cc_number_token`, in which case `cc_number_enc` is blank.
+///
+/// Updating a tokenized card with a blank `cc_number_enc` and no new token
+/// keeps its existing token.
+pub(crate) fn update_credit_card_with_token(
+    conn: &Connection,
+    guid: &Guid,
+    credit_card: &UpdatableCreditCardFields,
+    cc_number_token: Option<&str>,
 ) -> Result<()> {
     let tx = conn.unchecked_transaction()?;
     tx.execute(
@@ -128,6 +161,10 @@ pub fn update_credit_card(
             cc_exp_month                = :cc_exp_month,
             cc_exp_year                 = :cc_exp_year,
             cc_type                     = :cc_type,
+            cc_number_token             = IFNULL(
+                                              :cc_number_token,
+                                              CASE WHEN :cc_number_enc = '' THEN cc_number_token END
+                                          ),
             time_last_modified          = :time_last_modified,
             sync_change_counter         = sync_change_counter + 1
         WHERE guid                      = :guid",
@@ -138,6 +175,7 @@ pub fn update_credit_card(
             ":cc_exp_month": credit_card.cc_exp_month,
             ":cc_exp_year": credit_card.cc_exp_year,
             ":cc_type": credit_card.cc_type,
+            ":cc_number_token": cc_number_token,
             ":time_last_modified": Timestamp::now(),
             ":guid": guid,
         },
@@ -168,6 +206,7 @@ pub(crate) fn update_internal_credit_card(
             time_last_used              = :time_last_used,
             time_last_modified          = :time_last_modified,
             times_used                  = :times_used,
+            cc_number_token             = :cc_number_token,
             sync_change_counter         = sync_change_counter + :change_incr
         WHERE guid                      = :guid",
         rusqlite::named_params! {
@@ -181,6 +220,7 @@ pub(crate) fn update_internal_credit_card(
             ":time_last_used": card.metadata.time_last_used,
             ":time_last_modified": card.metadata.time_last_modified,
             ":times_used": card.metadata.times_used,
+            ":cc_number_token": card.cc_number_token,
             ":change_incr": change_counter_increment,
             ":guid": card.guid,
         },
@@ -211,6 +251,54 @@ pub fn scrub_encrypted_credit_card_data(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+/// Returns the guids and encrypted numbers of the cards which haven't been
+/// tokenized.
+///
+/// The app's tokenizer mustn't be called while we hold the database, so
+/// callers tokenize these numbers and then store the tokens with
+/// `set_cc_number_token()`.
+pub(crate) fn get_untokenized_credit_cards(conn: &Connection) -> Result<Vec<(Guid, String)>> {
+    conn.query_rows_and_then(
+        "SELECT guid, cc_number_enc FROM credit_cards_data WHERE cc_number_enc <> ''",
+        [],
+        |row| -> Result<(Guid, String)> { Ok((row.get(0)?, row.get(1)?)) },
+    )
+}
+
+/// Replaces the number of a card with `cc_number_token`, returning false if
+/// the card was deleted or its number changed since `cc_number_enc` was read.
+///
+/// The card isn't flagged as changed, since its number hasn't.
+pub(crate) fn set_cc_number_token(
+    conn: &Connection,
+    guid: &Guid,
+    cc_number_enc: &str,
+    cc_number_token: &str,
+) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE credit_cards_data
+        SET cc_number_enc       = '',
+            cc_number_token     = :cc_number_token
+        WHERE guid              = :guid
+            AND cc_number_enc   = :cc_number_enc",
+        rusqlite::named_params! {
+            ":cc_number_token": cc_number_token,
+            ":guid": guid,
+            ":cc_number_enc": cc_number_enc,
+        },
+    )?;
+    Ok(changed == 1)
+}
+
+pub(crate) fn tokenize(
+    tokenizer: &dyn CreditCardTokenizer,
+    cc_number_enc: String,
+) -> Result<String> {
+    tokenizer
+        .tokenize(cc_number_enc)
+        .map_err(|e| Error::TokenizationError(e.to_string()))
+}
+
 pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     let tx = conn.unchecked_transaction()?;
     let now_ms = Timestamp::now();
@@ -231,6 +319,92 @@ pub fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
     Ok(())
 }
 
+/// Returns a hash of a (decrypted) credit card number which ignores how the
+/// number was formatted, so we can compare numbers without holding on to them.
+pub(crate) fn cc_number_hash(cc_number: &str) -> Result<Vec<u8>> {
+    let normalized = cc_number
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    Ok(digest::digest(&digest::SHA256, normalized.as_bytes())?
+        .as_ref()
+        .to_vec())
+}
+
+/// Merges credit cards with the same number and expiry date, returning the
+/// cards which were removed and the cards they were merged into.
+///
+/// For each set of duplicates we keep the card the server already knows about,
+/// if there is one, and otherwise the most recently used. The usage metadata of
+/// the others is merged into it before they're deleted, which leaves tombstones
+/// for any which had been synced.
+pub(crate) fn dedupe_credit_cards(
+    conn: &Connection,
+    encdec: &EncryptorDecryptor,
+) -> Result<Vec<DedupedCreditCard>> {
+    let tx = conn.unchecked_transaction()?;
+    let synced = tx
+        .query_rows_and_then("SELECT guid FROM credit_cards_mirror", [], |row| {
+            row.get::<_, Guid>(0)
+        })?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut by_card: HashMap<(Vec<u8>, i64, i64), Vec<InternalCreditCard>> = HashMap::new();
+    for card in get_all_credit_cards(&tx)? {
+        // We can't tell what the number of a scrubbed card is.
+        if card.has_scrubbed_data() {
+            continue;
+        }
+        let hash = cc_number_hash(&encdec.decrypt(&card.cc_number_enc, "cc_number")?)?;
+        by_card
+            .entry((hash, card.cc_exp_month, card.cc_exp_year))
+            .or_default()
+            .push(card);
+    }
+
+    let mut deduped = vec![];
+    for mut cards in by_card.into_values() {
+        if cards.len() < 2 {
+            continue;
+        }
+        cards.sort_by_key(|card| {
+            (
+                Reverse(synced.contains(&card.guid)),
+                Reverse(card.metadata.time_last_used),
+                card.metadata.time_created,
+            )
+        });
+        let mut kept = cards.remove(0);
+        for dupe in cards {
+            if kept.cc_name.is_empty() {
+                kept.cc_name = dupe.cc_name;
+            }
+            if kept.cc_type.is_empty() {
+                kept.cc_type = dupe.cc_type;
+            }
+            kept.metadata.time_created = kept.metadata.time_created.min(dupe.metadata.time_created);
+            kept.metadata.time_last_used = kept
+                .metadata
+                .time_last_used
+                .max(dupe.metadata.time_last_used);
+            kept.metadata.times_used += dupe.metadata.times_used;
+            tx.execute(
+                "DELETE FROM credit_cards_data WHERE guid = :guid",
+                rusqlite::named_params! { ":guid": dupe.guid },
+            )?;
+            deduped.push(DedupedCreditCard {
+                removed_guid: dupe.guid.to_string(),
+                kept_guid: kept.guid.to_string(),
+            });
+        }
+        kept.metadata.time_last_modified = Timestamp::now();
+        update_internal_credit_card(&tx, &kept, true)?;
+    }
+    tx.commit()?;
+    Ok(deduped)
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -707,4 +881,56 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_dedupe_credit_cards() -> Result<()> {
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let add = |name: &str, number: &str, exp_year: i64| {
+            add_credit_card(
+                &db,
+                UpdatableCreditCardFields {
+                    cc_name: name.to_string(),
+                    cc_number_enc: encdec.encrypt(number, "cc_number").unwrap(),
+                    cc_number_last_4: number[number.len() - 4..].to_string(),
+                    cc_exp_month: 10,
+                    cc_exp_year: exp_year,
+                    cc_type: "visa".to_string(),
+                },
+            )
+            .unwrap()
+        };
+        let synced = add("", "4111 1111 1111 1111", 2025);
+        let dupe = add("Jane Doe", "4111-1111-1111-1111", 2025);
+        let other_expiry = add("Jane Doe", "4111111111111111", 2026);
+        let other_number = add("Jane Doe", "4242424242424242", 2025);
+        touch(&db, &dupe.guid)?;
+        test_insert_mirror_record(
+            &db,
+            synced
+                .clone()
+                .into_test_incoming_bso(&encdec, Default::default()),
+        );
+
+        assert_eq!(
+            dedupe_credit_cards(&db, &encdec)?,
+            vec![DedupedCreditCard {
+                removed_guid: dupe.guid.to_string(),
+                kept_guid: synced.guid.to_string(),
+            }]
+        );
+        // The synced card was kept, even though the dupe was used more
+        // recently, and it picked up the name and usage of the dupe.
+        let kept = get_credit_card(&db, &synced.guid)?;
+        assert_eq!(kept.cc_name, "Jane Doe");
+        assert_eq!(kept.metadata.times_used, 1);
+        assert_eq!(kept.metadata.sync_change_counter, 1);
+        assert!(get_credit_card(&db, &dupe.guid).is_err());
+        assert!(get_credit_card(&db, &other_expiry.guid).is_ok());
+        assert!(get_credit_card(&db, &other_number.guid).is_ok());
+
+        // Nothing left to do.
+        assert!(dedupe_credit_cards(&db, &encdec)?.is_empty());
+        Ok(())
+    }
 }
@@ -4,6 +4,7 @@
 */
 
 use super::Metadata;
+use crate::error::ApiResult;
 use rusqlite::Row;
 use sync_guid::Guid;
 
@@ -37,12 +38,18 @@ pub struct CreditCard {
     pub time_last_used: Option<i64>,
     pub time_last_modified: i64,
     pub times_used: i64,
+
+    // If true, the card number was replaced by `cc_number_token`, the token from the app's
+    // `CreditCardTokenizer`, and `cc_number_enc` is blank.
+    pub needs_detokenization: bool,
+    pub cc_number_token: Option<String>,
 }
 
 // This is used to "externalize" a credit-card, suitable for handing back to
 // consumers.
 impl From<InternalCreditCard> for CreditCard {
     fn from(icc: InternalCreditCard) -> Self {
+        let needs_detokenization = icc.is_tokenized();
         CreditCard {
             guid: icc.guid.to_string(),
             cc_name: icc.cc_name,
@@ -60,10 +67,30 @@ impl From<InternalCreditCard> for CreditCard {
             },
             time_last_modified: u64::from(icc.metadata.time_last_modified) as i64,
             times_used: icc.metadata.times_used,
+            needs_detokenization,
+            cc_number_token: icc.cc_number_token,
         }
     }
 }
 
+/// Implemented by apps which mustn't store credit card numbers locally, even
+/// encrypted. See `Store::register_credit_card_tokenizer()`.
+pub trait CreditCardTokenizer: Send + Sync {
+    /// Returns a token to store instead of the card number, which is passed
+    /// encrypted with the app's key, as in `UpdatableCreditCardFields`.
+    fn tokenize(&self, cc_number_enc: String) -> ApiResult<String>;
+}
+
+/// A credit card which `dedupe_credit_cards()` removed because it was a
+/// duplicate of another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupedCreditCard {
+    /// The guid of the card which was removed.
+    pub removed_guid: String,
+    /// The guid of the card it was merged into.
+    pub kept_guid: String,
+}
+
 // NOTE: No `PartialEq` here because the same card number will encrypt to a
 // different value each time it is encrypted, making it meaningless to compare.
 #[derive(Debug, Clone, Default)]
@@ -78,6 +105,8 @@ pub struct InternalCreditCard {
     // (https://searchfox.org/mozilla-central/rev/7ef5cefd0468b8f509efe38e0212de2398f4c8b3/toolkit/modules/CreditCard.jsm#9-22)
     pub cc_type: String,
     pub metadata: Metadata,
+    // Set instead of `cc_number_enc` for cards whose number was tokenized.
+    pub cc_number_token: Option<String>,
 }
 
 impl InternalCreditCard {
@@ -97,10 +126,17 @@ impl InternalCreditCard {
                 times_used: row.get("times_used")?,
                 sync_change_counter: row.get("sync_change_counter")?,
             },
+            cc_number_token: row.get("cc_number_token")?,
         })
     }
 
+    // Tokenized cards look scrubbed to sync, so their numbers are only ever
+    // replaced by the server's.
     pub fn has_scrubbed_data(&self) -> bool {
         self.cc_number_enc.is_empty()
     }
+
+    pub fn is_tokenized(&self) -> bool {
+        self.cc_number_enc.is_empty() && self.cc_number_token.is_some()
+    }
 }
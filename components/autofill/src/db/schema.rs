@@ -52,7 +52,8 @@ pub const CREDIT_CARD_COMMON_COLS: &str = "
     time_created,
     time_last_used,
     time_last_modified,
-    times_used";
+    times_used,
+    cc_number_token";
 
 pub const CREDIT_CARD_COMMON_VALS: &str = "
     :guid,
@@ -65,7 +66,8 @@ pub const CREDIT_CARD_COMMON_VALS: &str = "
     :time_created,
     :time_last_used,
     :time_last_modified,
-    :times_used";
+    :times_used,
+    :cc_number_token";
 
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
 const CREATE_SHARED_TRIGGERS_SQL: &str = include_str!("../../sql/create_shared_triggers.sql");
@@ -75,7 +77,7 @@ pub struct AutofillConnectionInitializer;
 
 impl ConnectionInitializer for AutofillConnectionInitializer {
     const NAME: &'static str = "autofill db";
-    const END_VERSION: u32 = 3;
+    const END_VERSION: u32 = 4;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> Result<()> {
         define_functions(conn)?;
@@ -105,6 +107,7 @@ impl ConnectionInitializer for AutofillConnectionInitializer {
             0 => upgrade_from_v0(db),
             1 => upgrade_from_v1(db),
             2 => upgrade_from_v2(db),
+            3 => upgrade_from_v3(db),
             _ => Err(Error::IncompatibleVersion(version)),
         }
     }
@@ -230,6 +233,11 @@ fn upgrade_from_v2(db: &Connection) -> Result<()> {
     Ok(())
 }
 
+fn upgrade_from_v3(db: &Connection) -> Result<()> {
+    db.execute_batch("ALTER TABLE credit_cards_data ADD COLUMN cc_number_token TEXT")?;
+    Ok(())
+}
+
 pub fn create_empty_sync_temp_tables(db: &Connection) -> Result<()> {
     log::debug!("Initializing sync temp tables");
     db.execute_batch(CREATE_SYNC_TEMP_TABLES_SQL)?;
@@ -283,6 +291,7 @@ mod tests {
         assert_eq!(cc.metadata.time_last_modified, Timestamp(2));
         assert_eq!(cc.metadata.times_used, 3);
         assert_eq!(cc.metadata.sync_change_counter, 0);
+        assert_eq!(cc.cc_number_token, None);
 
         let address = get_address(&conn, &Guid::new("A")).unwrap();
         assert_eq!(address.guid, "A");
@@ -3,8 +3,11 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::db::models::address::{Address, UpdatableAddressFields};
-use crate::db::models::credit_card::{CreditCard, UpdatableCreditCardFields};
+use crate::db::models::credit_card::{
+    CreditCard, CreditCardTokenizer, DedupedCreditCard, UpdatableCreditCardFields,
+};
 use crate::db::{addresses, credit_cards, AutofillDb};
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use error_support::handle_error;
 use rusqlite::{
@@ -46,6 +49,9 @@ pub fn get_registered_sync_engine(engine_id: &SyncEngineId) -> Option<Box<dyn Sy
 // This is the type that uniffi exposes.
 pub struct Store {
     pub(crate) db: Mutex<AutofillDb>,
+    // The tokenizer is foreign code, which may call back into the store, so we never call it
+    // while holding either lock.
+    tokenizer: Mutex<Option<Arc<dyn CreditCardTokenizer>>>,
 }
 
 impl Store {
@@ -53,6 +59,7 @@ impl Store {
     pub fn new(db_path: impl AsRef<Path>) -> ApiResult<Self> {
         Ok(Self {
             db: Mutex::new(AutofillDb::new(db_path)?),
+            tokenizer: Mutex::new(None),
         })
     }
 
@@ -61,6 +68,7 @@ impl Store {
     pub fn new_memory() -> Self {
         Self {
             db: Mutex::new(crate::db::test::new_mem_db()),
+            tokenizer: Mutex::new(None),
         }
     }
 
@@ -69,12 +77,28 @@ impl Store {
     pub fn new_shared_memory(db_name: &str) -> ApiResult<Self> {
         Ok(Self {
             db: Mutex::new(AutofillDb::new_memory(db_name)?),
+            tokenizer: Mutex::new(None),
         })
     }
 
+    pub(crate) fn tokenizer(&self) -> Option<Arc<dyn CreditCardTokenizer>> {
+        self.tokenizer.lock().unwrap().clone()
+    }
+
     #[handle_error(Error)]
-    pub fn add_credit_card(&self, fields: UpdatableCreditCardFields) -> ApiResult<CreditCard> {
-        let credit_card = credit_cards::add_credit_card(&self.db.lock().unwrap().writer, fields)?;
+    pub fn add_credit_card(&self, mut fields: UpdatableCreditCardFields) -> ApiResult<CreditCard> {
+        let token = match self.tokenizer() {
+            Some(tokenizer) if !fields.cc_number_enc.is_empty() => Some(credit_cards::tokenize(
+                tokenizer.as_ref(),
+                std::mem::take(&mut fields.cc_number_enc),
+            )?),
+            _ => None,
+        };
+        let credit_card = credit_cards::add_credit_card_with_token(
+            &self.db.lock().unwrap().writer,
+            fields,
+            token,
+        )?;
         Ok(credit_card.into())
     }
 
@@ -98,15 +122,66 @@ impl Store {
     pub fn update_credit_card(
         &self,
         guid: String,
-        credit_card: UpdatableCreditCardFields,
+        mut credit_card: UpdatableCreditCardFields,
     ) -> ApiResult<()> {
-        credit_cards::update_credit_card(
+        // Tokenized cards are returned with a blank `cc_number_enc`, which apps pass back to keep
+        // the token, so there's only something to tokenize if they pass a new number.
+        let token = match self.tokenizer() {
+            Some(tokenizer) if !credit_card.cc_number_enc.is_empty() => {
+                Some(credit_cards::tokenize(
+                    tokenizer.as_ref(),
+                    std::mem::take(&mut credit_card.cc_number_enc),
+                )?)
+            }
+            _ => None,
+        };
+        credit_cards::update_credit_card_with_token(
             &self.db.lock().unwrap().writer,
             &Guid::new(&guid),
             &credit_card,
+            token.as_deref(),
         )
     }
 
+    /// Registers a tokenizer which replaces the numbers of credit cards with
+    /// tokens, for apps which mustn't store the numbers locally, even
+    /// encrypted. The numbers of the cards we already have are tokenized
+    /// straight away, and we return how many there were.
+    ///
+    /// Tokenized cards are returned with `needs_detokenization` set, their
+    /// token in `cc_number_token` and a blank `cc_number_enc`.
+    #[handle_error(Error)]
+    pub fn register_credit_card_tokenizer(
+        &self,
+        tokenizer: Box<dyn CreditCardTokenizer>,
+    ) -> ApiResult<u32> {
+        let tokenizer: Arc<dyn CreditCardTokenizer> = tokenizer.into();
+        *self.tokenizer.lock().unwrap() = Some(Arc::clone(&tokenizer));
+        self.tokenize_credit_cards(tokenizer.as_ref())
+    }
+
+    /// Tokenizes the numbers of all the cards which have one, returning how
+    /// many were tokenized. The database is only locked to read the numbers
+    /// and to store each token, never while `tokenizer` runs.
+    pub(crate) fn tokenize_credit_cards(&self, tokenizer: &dyn CreditCardTokenizer) -> Result<u32> {
+        let cards = credit_cards::get_untokenized_credit_cards(&self.db.lock().unwrap().writer)?;
+        let mut tokenized = 0;
+        for (guid, cc_number_enc) in cards {
+            let token = credit_cards::tokenize(tokenizer, cc_number_enc.clone())?;
+            // If the card changed while we were tokenizing, we skip it; a new number would
+            // have been tokenized by whoever changed it.
+            if credit_cards::set_cc_number_token(
+                &self.db.lock().unwrap().writer,
+                &guid,
+                &cc_number_enc,
+                &token,
+            )? {
+                tokenized += 1;
+            }
+        }
+        Ok(tokenized)
+    }
+
     #[handle_error(Error)]
     pub fn delete_credit_card(&self, guid: String) -> ApiResult<bool> {
         credit_cards::delete_credit_card(&self.db.lock().unwrap().writer, &Guid::new(&guid))
@@ -117,6 +192,18 @@ impl Store {
         credit_cards::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
     }
 
+    /// Merges credit cards with the same number and expiry date, returning
+    /// which cards were removed and which they were merged into. Needs the
+    /// local encryption key to compare the numbers.
+    #[handle_error(Error)]
+    pub fn dedupe_credit_cards(
+        &self,
+        local_encryption_key: String,
+    ) -> ApiResult<Vec<DedupedCreditCard>> {
+        let encdec = EncryptorDecryptor::new(&local_encryption_key)?;
+        credit_cards::dedupe_credit_cards(&self.db.lock().unwrap().writer, &encdec)
+    }
+
     #[handle_error(Error)]
     pub fn add_address(&self, new_address: UpdatableAddressFields) -> ApiResult<Address> {
         Ok(addresses::add_address(&self.db.lock().unwrap().writer, new_address)?.into())
@@ -249,6 +336,134 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_credit_card_tokenizer_can_use_store() {
+        // A tokenizer which reads from the store, as an app's implementation might.
+        struct ReentrantTokenizer {
+            store: Mutex<Weak<Store>>,
+        }
+        impl CreditCardTokenizer for ReentrantTokenizer {
+            fn tokenize(&self, _cc_number_enc: String) -> ApiResult<String> {
+                let store = self.store.lock().unwrap().upgrade().unwrap();
+                let count = store.get_all_credit_cards()?.len();
+                Ok(format!("token-{count}"))
+            }
+        }
+
+        let store = Arc::new(Store::new_memory());
+        let fields = UpdatableCreditCardFields {
+            cc_name: "Jane Doe".to_string(),
+            cc_number_enc: "A".repeat(30),
+            cc_number_last_4: "1234".to_string(),
+            cc_exp_month: 1,
+            cc_exp_year: 2030,
+            cc_type: "visa".to_string(),
+        };
+        store.add_credit_card(fields.clone()).unwrap();
+        let tokenizer = ReentrantTokenizer {
+            store: Mutex::new(Arc::downgrade(&store)),
+        };
+        assert_eq!(
+            store
+                .register_credit_card_tokenizer(Box::new(tokenizer))
+                .unwrap(),
+            1
+        );
+        let card = store.add_credit_card(fields).unwrap();
+        assert_eq!(card.cc_number_token.as_deref(), Some("token-1"));
+        store
+            .update_credit_card(
+                card.guid.clone(),
+                UpdatableCreditCardFields {
+                    cc_number_enc: "B".repeat(30),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let card = store.get_credit_card(card.guid).unwrap();
+        assert_eq!(card.cc_number_token.as_deref(), Some("token-2"));
+    }
+
+    #[test]
+    fn test_credit_card_tokenizer() {
+        #[derive(Default)]
+        struct TestTokenizer {
+            count: Mutex<u32>,
+        }
+        impl CreditCardTokenizer for TestTokenizer {
+            fn tokenize(&self, cc_number_enc: String) -> ApiResult<String> {
+                assert!(!cc_number_enc.is_empty());
+                let mut count = self.count.lock().unwrap();
+                *count += 1;
+                Ok(format!("token-{count}"))
+            }
+        }
+        fn fields(cc_number_enc: &str) -> UpdatableCreditCardFields {
+            UpdatableCreditCardFields {
+                cc_name: "Jane Doe".to_string(),
+                cc_number_enc: cc_number_enc.to_string(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: 1,
+                cc_exp_year: 2030,
+                cc_type: "visa".to_string(),
+            }
+        }
+
+        let store = Store::new_memory();
+        let existing = store.add_credit_card(fields(&"A".repeat(30))).unwrap();
+        assert!(!existing.needs_detokenization);
+
+        // Existing cards are tokenized when the tokenizer is registered.
+        let tokenized = store
+            .register_credit_card_tokenizer(Box::<TestTokenizer>::default())
+            .unwrap();
+        assert_eq!(tokenized, 1);
+        let existing = store.get_credit_card(existing.guid).unwrap();
+        assert!(existing.needs_detokenization);
+        assert_eq!(existing.cc_number_token.as_deref(), Some("token-1"));
+        assert_eq!(existing.cc_number_enc, "");
+        let stored_enc: String = store
+            .db
+            .lock()
+            .unwrap()
+            .query_one("SELECT cc_number_enc FROM credit_cards_data")
+            .unwrap();
+        assert_eq!(stored_enc, "");
+
+        // New cards are tokenized before they're stored.
+        let card = store.add_credit_card(fields(&"B".repeat(30))).unwrap();
+        assert!(card.needs_detokenization);
+        assert_eq!(card.cc_number_token.as_deref(), Some("token-2"));
+
+        // Passing back the blank number keeps the token...
+        let mut updated = fields(&card.cc_number_enc);
+        updated.cc_name = "John Doe".to_string();
+        store
+            .update_credit_card(card.guid.clone(), updated)
+            .unwrap();
+        let card = store.get_credit_card(card.guid).unwrap();
+        assert_eq!(card.cc_name, "John Doe");
+        assert_eq!(card.cc_number_token.as_deref(), Some("token-2"));
+
+        // ...but a new number is tokenized.
+        store
+            .update_credit_card(card.guid.clone(), fields(&"C".repeat(30)))
+            .unwrap();
+        let card = store.get_credit_card(card.guid).unwrap();
+        assert!(card.needs_detokenization);
+        assert_eq!(card.cc_number_token.as_deref(), Some("token-3"));
+        assert_eq!(card.cc_number_enc, "");
+
+        // The database won't store a token alongside an encrypted number.
+        let db = store.db.lock().unwrap();
+        assert!(db
+            .execute(
+                "UPDATE credit_cards_data SET cc_number_enc = 'not-blank' WHERE guid = :guid",
+                rusqlite::named_params! { ":guid": card.guid },
+            )
+            .is_err());
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(Store::new_shared_memory("sync-mgr-test").unwrap());
@@ -27,6 +27,9 @@ pub enum AutofillApiError {
     #[error("No record with guid exists: {guid}")]
     NoSuchRecord { guid: String },
 
+    #[error("Tokenization error: {reason}")]
+    TokenizationError { reason: String },
+
     #[error("Unexpected Error: {reason}")]
     UnexpectedAutofillApiError { reason: String },
 }
@@ -60,11 +63,24 @@ pub enum Error {
     #[error("Crypto Error: {0}")]
     CryptoError(#[from] jwcrypto::EncryptorDecryptorError),
 
+    #[error("Hashing error: {0}")]
+    HashError(#[from] rc_crypto::Error),
+
     #[error("Missing local encryption key")]
     MissingEncryptionKey,
 
     #[error("No record with guid exists: {0}")]
     NoSuchRecord(String),
+
+    #[error("The credit card tokenizer failed: {0}")]
+    TokenizationError(String),
+}
+
+// Errors from the app's `CreditCardTokenizer` which it didn't declare.
+impl From<uniffi::UnexpectedUniFFICallbackError> for AutofillApiError {
+    fn from(e: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        AutofillApiError::TokenizationError { reason: e.reason }
+    }
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -117,6 +133,11 @@ impl GetErrorHandling for Error {
             })
             .report_error("autofill-crypto-error"),
 
+            Self::HashError(e) => ErrorHandling::convert(AutofillApiError::CryptoError {
+                reason: e.to_string(),
+            })
+            .report_error("autofill-hash-error"),
+
             Self::MissingEncryptionKey => ErrorHandling::convert(AutofillApiError::CryptoError {
                 reason: "Missing encryption key".to_string(),
             })
@@ -126,6 +147,13 @@ impl GetErrorHandling for Error {
                 ErrorHandling::convert(AutofillApiError::NoSuchRecord { guid: guid.clone() })
                     .log_warning()
             }
+
+            Self::TokenizationError(reason) => {
+                ErrorHandling::convert(AutofillApiError::TokenizationError {
+                    reason: reason.clone(),
+                })
+                .log_warning()
+            }
         }
     }
 }
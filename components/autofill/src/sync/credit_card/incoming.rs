@@ -4,7 +4,9 @@
 */
 
 use super::CreditCardPayload;
-use crate::db::credit_cards::{add_internal_credit_card, update_internal_credit_card};
+use crate::db::credit_cards::{
+    add_internal_credit_card, cc_number_hash, update_internal_credit_card,
+};
 use crate::db::models::credit_card::InternalCreditCard;
 use crate::db::schema::CREDIT_CARD_COMMON_COLS;
 use crate::encryption::EncryptorDecryptor;
@@ -61,6 +63,52 @@ pub(super) struct IncomingCreditCardsImpl {
     pub(super) encdec: EncryptorDecryptor,
 }
 
+impl IncomingCreditCardsImpl {
+    /// If the card we're about to store was tokenized, and still has the number in the mirror,
+    /// which its token stands for, stores the token again instead of the number.
+    ///
+    /// Otherwise the card has a new number, which is stored until the sync finishes, when
+    /// `Store::tokenize_credit_cards()` replaces it with a token.
+    fn retokenize(&self, tx: &Transaction<'_>, record: &mut InternalCreditCard) -> Result<()> {
+        if record.cc_number_enc.is_empty() {
+            return Ok(());
+        }
+        let token = match record.cc_number_token.take() {
+            Some(token) => Some(token),
+            None => tx
+                .try_query_one::<Option<String>, _>(
+                    "SELECT cc_number_token FROM credit_cards_data WHERE guid = :guid",
+                    named_params! { ":guid": record.guid },
+                    true,
+                )?
+                .flatten(),
+        };
+        let Some(token) = token else {
+            return Ok(());
+        };
+        let mirror_payload: Option<String> = tx.try_query_one(
+            "SELECT payload FROM credit_cards_mirror WHERE guid = :guid",
+            named_params! { ":guid": record.guid },
+            true,
+        )?;
+        let Some(mirror_payload) = mirror_payload else {
+            return Ok(());
+        };
+        let mirror_number =
+            match raw_payload_to_incoming(record.guid.clone(), mirror_payload, &self.encdec)?
+                .content()
+            {
+                Some(mirror) => self.encdec.decrypt(&mirror.cc_number_enc, "cc_number")?,
+                None => return Ok(()),
+            };
+        if self.encdec.decrypt(&record.cc_number_enc, "cc_number")? == mirror_number {
+            record.cc_number_enc = String::new();
+            record.cc_number_token = Some(token);
+        }
+        Ok(())
+    }
+}
+
 impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
     type Record = InternalCreditCard;
 
@@ -111,7 +159,8 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
             l.time_last_used,
             l.time_last_modified,
             l.times_used,
-            l.sync_change_counter
+            l.sync_change_counter,
+            l.cc_number_token
         FROM temp.credit_cards_sync_staging s
         LEFT JOIN credit_cards_mirror m ON s.guid = m.guid
         LEFT JOIN credit_cards_data l ON s.guid = l.guid
@@ -122,13 +171,28 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
             let guid: SyncGuid = row.get("guid")?;
             let incoming =
                 raw_payload_to_incoming(guid.clone(), row.get("s_payload")?, &self.encdec)?;
+            let mirror = match row.get::<_, Option<String>>("m_payload")? {
+                Some(m_payload) => {
+                    // a tombstone in the mirror can be treated as though it's missing.
+                    raw_payload_to_incoming(guid.clone(), m_payload, &self.encdec)?.content()
+                }
+                None => None,
+            };
             Ok(IncomingState {
                 incoming,
                 local: match row.get_unwrap::<_, Option<String>>("l_guid") {
                     Some(l_guid) => {
                         assert_eq!(l_guid, guid);
                         // local record exists, check the state.
-                        let record = InternalCreditCard::from_row(row)?;
+                        let mut record = InternalCreditCard::from_row(row)?;
+                        // A tokenized card only has a token locally, which stands for the number
+                        // we last synced, so we merge it using the number from the mirror.
+                        // `retokenize()` swaps the token back in when we store it.
+                        if record.is_tokenized() {
+                            if let Some(mirror) = &mirror {
+                                record.cc_number_enc = mirror.cc_number_enc.clone();
+                            }
+                        }
                         if record.has_scrubbed_data() {
                             LocalRecordInfo::Scrubbed { record }
                         } else {
@@ -151,22 +215,17 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
                         }
                     }
                 },
-                mirror: {
-                    match row.get::<_, Option<String>>("m_payload")? {
-                        Some(m_payload) => {
-                            // a tombstone in the mirror can be treated as though it's missing.
-                            raw_payload_to_incoming(guid, m_payload, &self.encdec)?.content()
-                        }
-                        None => None,
-                    }
-                },
+                mirror,
             })
         })
     }
 
-    /// Returns a local record that has the same values as the given incoming record (with the exception
+    /// Returns a local record for the same card as the given incoming record (with the exception
     /// of the `guid` values which should differ) that will be used as a local duplicate record for
     /// syncing.
+    ///
+    /// This is fuzzy - two devices may well have saved the same card with, say, a different name or
+    /// with spaces in the number - so we only require the number and expiry date to match.
     fn get_local_dupe(
         &self,
         tx: &Transaction<'_>,
@@ -185,32 +244,33 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
                     SELECT guid
                     FROM credit_cards_mirror
                 )
-                -- and sql can check the field values (but note we can not meaningfully
-                -- check the encrypted value, as it's different each time it is encrypted)
-                AND cc_name == :cc_name
-                AND cc_number_last_4 == :cc_number_last_4
+                -- and sql can check the expiry (but note we can not meaningfully
+                -- check the encrypted number, as it's different each time it is encrypted)
                 AND cc_exp_month == :cc_exp_month
-                AND cc_exp_year == :cc_exp_year
-                AND cc_type == :cc_type", common_cols = CREDIT_CARD_COMMON_COLS);
+                AND cc_exp_year == :cc_exp_year", common_cols = CREDIT_CARD_COMMON_COLS);
 
         let params = named_params! {
             ":guid": incoming.guid,
-            ":cc_name": incoming.cc_name,
-            ":cc_number_last_4": incoming.cc_number_last_4,
             ":cc_exp_month": incoming.cc_exp_month,
             ":cc_exp_year": incoming.cc_exp_year,
-            ":cc_type": incoming.cc_type,
         };
 
         // Because we can't check the number in the sql, we fetch all matching
-        // rows and decrypt the numbers here.
+        // rows and compare hashes of the decrypted, normalized, numbers here.
         let records = tx.query_rows_and_then(&sql, params, |row| -> Result<Self::Record> {
             Ok(Self::Record::from_row(row)?)
         })?;
 
-        let incoming_cc_number = self.encdec.decrypt(&incoming.cc_number_enc, "cc_number")?;
+        let incoming_hash =
+            cc_number_hash(&self.encdec.decrypt(&incoming.cc_number_enc, "cc_number")?)?;
         for record in records {
-            if self.encdec.decrypt(&record.cc_number_enc, "cc_number")? == incoming_cc_number {
+            // A scrubbed record has no number to compare.
+            if record.has_scrubbed_data() {
+                continue;
+            }
+            if cc_number_hash(&self.encdec.decrypt(&record.cc_number_enc, "cc_number")?)?
+                == incoming_hash
+            {
                 return Ok(Some(record));
             }
         }
@@ -220,14 +280,20 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
     fn update_local_record(
         &self,
         tx: &Transaction<'_>,
-        new_record: Self::Record,
+        mut new_record: Self::Record,
         flag_as_changed: bool,
     ) -> Result<()> {
+        self.retokenize(tx, &mut new_record)?;
         update_internal_credit_card(tx, &new_record, flag_as_changed)?;
         Ok(())
     }
 
-    fn insert_local_record(&self, tx: &Transaction<'_>, new_record: Self::Record) -> Result<()> {
+    fn insert_local_record(
+        &self,
+        tx: &Transaction<'_>,
+        mut new_record: Self::Record,
+    ) -> Result<()> {
+        self.retokenize(tx, &mut new_record)?;
         add_internal_credit_card(tx, &new_record)?;
         Ok(())
     }
@@ -539,6 +605,32 @@ mod tests {
         assert_eq!(dupe.guid, local_guid);
     }
 
+    #[test]
+    fn test_find_fuzzy_dupe() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let ci = IncomingCreditCardsImpl { encdec };
+        let local_record = test_record('C', &ci.encdec);
+        let local_guid = local_record.guid.clone();
+        ci.insert_local_record(&tx, local_record.clone()).unwrap();
+
+        // The same card, saved with a different name and a formatted number.
+        let mut incoming_record = local_record;
+        incoming_record.guid = SyncGuid::random();
+        incoming_record.cc_name = "MR ME ANOTHER PERSON".to_string();
+        incoming_record.cc_number_enc = ci
+            .encdec
+            .encrypt("8765 4321 1234 5678", "cc_number")
+            .unwrap();
+        let dupe = ci.get_local_dupe(&tx, &incoming_record).unwrap().unwrap();
+        assert_eq!(dupe.guid, local_guid);
+
+        // But a different expiry date means a different card.
+        incoming_record.cc_exp_year += 1;
+        assert!(ci.get_local_dupe(&tx, &incoming_record).unwrap().is_none());
+    }
+
     // largely the same test as above, but going through the entire plan + apply
     // cycle.
     #[test]
@@ -589,6 +681,59 @@ mod tests {
         assert!(get_credit_card(&db.writer, &incoming_guid).is_ok());
     }
 
+    #[test]
+    fn test_incoming_tokenized() {
+        // (incoming number, whether the local token should survive)
+        let cases = [("8765432112345678", true), ("1111222233334444", false)];
+        for (incoming_number, keeps_token) in cases {
+            let mut db = new_syncable_mem_db();
+            let tx = db.transaction().expect("should get tx");
+            let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+            // A synced card which has since been tokenized.
+            let mirror = test_json_record('C');
+            tx.execute(
+                "INSERT INTO credit_cards_mirror (guid, payload) VALUES (:guid, :payload)",
+                rusqlite::named_params! {
+                    ":guid": mirror["id"].as_str().unwrap(),
+                    ":payload": encdec.encrypt(&mirror.to_string(), "payload").unwrap(),
+                },
+            )
+            .expect("should insert mirror record");
+            let mut local = test_record('C', &encdec);
+            local.cc_number_enc = String::new();
+            local.cc_number_token = Some("token".to_string());
+            add_internal_credit_card(&tx, &local).unwrap();
+
+            let mut incoming = test_json_record('C');
+            incoming["entry"]["cc-name"] = json!("New Name");
+            incoming["entry"]["cc-number"] = json!(incoming_number);
+            let ci = IncomingCreditCardsImpl { encdec };
+            ci.stage_incoming(&tx, array_to_incoming(vec![incoming]), &NeverInterrupts)
+                .unwrap();
+            for state in ci.fetch_incoming_states(&tx).unwrap() {
+                let action =
+                    crate::sync::plan_incoming(&ci, &tx, state).expect("should get action");
+                crate::sync::apply_incoming_action(&ci, &tx, action).expect("should apply");
+            }
+
+            let stored = get_credit_card(&tx, &local.guid).unwrap();
+            assert_eq!(stored.cc_name, "New Name");
+            if keeps_token {
+                assert_eq!(stored.cc_number_token.as_deref(), Some("token"));
+                assert!(stored.cc_number_enc.is_empty());
+            } else {
+                assert_eq!(stored.cc_number_token, None);
+                assert_eq!(
+                    ci.encdec
+                        .decrypt(&stored.cc_number_enc, "cc_number")
+                        .unwrap(),
+                    incoming_number
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_get_incoming_unknown_fields() {
         let json = test_json_record('D');
@@ -69,6 +69,14 @@ impl SyncEngineStorageImpl<InternalCreditCard> for CreditCardsEngineStorageImpl
         let encdec = EncryptorDecryptor::new(enc_key)?;
         Ok(Box::new(OutgoingCreditCardsImpl { encdec }))
     }
+
+    // Numbers we downloaded for tokenized cards are only stored until the sync finishes.
+    fn sync_finished(&self, store: &crate::Store) -> Result<()> {
+        if let Some(tokenizer) = store.tokenizer() {
+            store.tokenize_credit_cards(tokenizer.as_ref())?;
+        }
+        Ok(())
+    }
 }
 
 // These structs are a representation of what's stored on the sync server for non-tombstone records.
@@ -141,12 +149,18 @@ impl InternalCreditCard {
                 times_used: p.entry.times_used,
                 sync_change_counter: 0,
             },
+            cc_number_token: None,
         })
     }
 
     pub(crate) fn into_payload(self, encdec: &EncryptorDecryptor) -> Result<CreditCardPayload> {
         let cc_number = encdec.decrypt(&self.cc_number_enc, "cc_number")?;
-        Ok(CreditCardPayload {
+        Ok(self.into_payload_with_number(cc_number))
+    }
+
+    // For tokenized cards, which don't have an encrypted number to decrypt.
+    pub(crate) fn into_payload_with_number(self, cc_number: String) -> CreditCardPayload {
+        CreditCardPayload {
             id: self.guid,
             entry: PayloadEntry {
                 cc_name: self.cc_name,
@@ -161,7 +175,7 @@ impl InternalCreditCard {
                 version: 3,
                 unknown_fields: Default::default(),
             },
-        })
+        }
     }
 }
 
@@ -35,7 +35,10 @@ impl ProcessOutgoingRecordImpl for OutgoingCreditCardsImpl {
             LEFT JOIN credit_cards_mirror m
             ON l.guid = m.guid
             WHERE
-                l.cc_number_enc <> ''
+                -- Scrubbed cards have no number to upload, and neither do tokenized cards which
+                -- were never synced. Other tokenized cards upload the number in the mirror.
+                (l.cc_number_enc <> '' OR
+                    (l.cc_number_token IS NOT NULL AND m.payload IS NOT NULL))
             AND
                 (
                     sync_change_counter > 0 OR
@@ -47,12 +50,24 @@ impl ProcessOutgoingRecordImpl for OutgoingCreditCardsImpl {
             common_cols = CREDIT_CARD_COMMON_COLS,
         );
         let record_from_data_row: &dyn Fn(&Row<'_>) -> Result<(OutgoingBso, i64)> = &|row| {
-            let mut record = InternalCreditCard::from_row(row)?.into_payload(&self.encdec)?;
+            let card = InternalCreditCard::from_row(row)?;
+            // The full payload in the credit cards mirror is encrypted
+            let mirror_payload = match row.get::<_, Option<String>>("payload")? {
+                Some(enc_s) => Some(serde_json::from_str::<CreditCardPayload>(
+                    &self.encdec.decrypt(&enc_s, "cc payload")?,
+                )?),
+                None => None,
+            };
+            let mut record = match &mirror_payload {
+                // We only have the token for a tokenized card's number, which stands for the
+                // number we last synced.
+                Some(mirror_payload) if card.is_tokenized() => {
+                    card.into_payload_with_number(mirror_payload.entry.cc_number.clone())
+                }
+                _ => card.into_payload(&self.encdec)?,
+            };
             // If the server had unknown fields we fetch it and add it to the record
-            if let Some(enc_s) = row.get::<_, Option<String>>("payload")? {
-                // The full payload in the credit cards mirror is encrypted
-                let mirror_payload: CreditCardPayload =
-                    serde_json::from_str(&self.encdec.decrypt(&enc_s, "cc payload")?)?;
+            if let Some(mirror_payload) = mirror_payload {
                 record.entry.unknown_fields = mirror_payload.entry.unknown_fields;
             };
 
@@ -330,4 +345,40 @@ mod tests {
             STAGING_TABLE_NAME,
         );
     }
+
+    #[test]
+    fn test_outgoing_tokenized() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let co = OutgoingCreditCardsImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+
+        // A synced card, tokenized and then changed locally.
+        let synced = test_record('C', &co.encdec);
+        let mut bso = synced
+            .clone()
+            .into_test_incoming_bso(&co.encdec, Default::default());
+        bso.payload = co.encdec.encrypt(&bso.payload, "bso payload").unwrap();
+        test_insert_mirror_record(&tx, bso);
+        let mut tokenized = synced;
+        tokenized.cc_name = "New Name".to_string();
+        tokenized.cc_number_enc = String::new();
+        tokenized.cc_number_token = Some("token".to_string());
+        tokenized.metadata.sync_change_counter = 1;
+        add_internal_credit_card(&tx, &tokenized).unwrap();
+
+        // A card tokenized before it was ever synced, which we have no number for.
+        let mut never_synced = test_record('D', &co.encdec);
+        never_synced.cc_number_enc = String::new();
+        never_synced.cc_number_token = Some("other token".to_string());
+        add_internal_credit_card(&tx, &never_synced).unwrap();
+
+        let outgoing = co.fetch_outgoing_records(&tx).unwrap();
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].envelope.id, tokenized.guid);
+        let payload: CreditCardPayload = serde_json::from_str(&outgoing[0].payload).unwrap();
+        assert_eq!(payload.entry.cc_name, "New Name");
+        assert_eq!(payload.entry.cc_number, "8765432112345678");
+    }
 }
@@ -5,6 +5,7 @@
 use super::{plan_incoming, ProcessIncomingRecordImpl, ProcessOutgoingRecordImpl, SyncRecord};
 use crate::error::*;
 use crate::Store;
+use interrupt_support::SqlInterruptHandle;
 use rusqlite::{
     types::{FromSql, ToSql},
     Connection, Transaction,
@@ -38,6 +39,10 @@ pub trait SyncEngineStorageImpl<T> {
         &self,
         enc_key: &Option<String>,
     ) -> Result<Box<dyn ProcessOutgoingRecordImpl<Record = T>>>;
+    // Called once a sync has finished, without the database locked.
+    fn sync_finished(&self, _store: &Store) -> Result<()> {
+        Ok(())
+    }
 }
 
 // A sync engine that gets functionality from an EngineConfig.
@@ -89,6 +94,10 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
         self.config.collection.clone()
     }
 
+    fn sql_interrupt_handle(&self) -> Option<Arc<SqlInterruptHandle>> {
+        Some(self.store.db.lock().unwrap().new_interrupt_handle())
+    }
+
     fn set_local_encryption_key(&mut self, key: &str) -> anyhow::Result<()> {
         self.local_enc_key = Some(key.to_string());
         Ok(())
@@ -171,6 +180,11 @@ impl<T: SyncRecord + std::fmt::Debug> SyncEngine for ConfigSyncEngine<T> {
         Ok(())
     }
 
+    fn sync_finished(&self) -> anyhow::Result<()> {
+        self.storage_impl.sync_finished(&self.store)?;
+        Ok(())
+    }
+
     fn get_collection_request(
         &self,
         server_timestamp: ServerTimestamp,
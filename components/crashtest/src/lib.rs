@@ -12,6 +12,9 @@
 // https://github.com/mozilla/uniffi-rs/issues/1018
 #![allow(clippy::redundant_closure)]
 
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 use thiserror::Error;
 
 #[cfg(test)]
@@ -57,6 +60,103 @@ pub fn trigger_rust_error() -> Result<(), CrashTestError> {
     Err(CrashTestError::ErrorFromTheRustCode)
 }
 
+/// The kind of crash to trigger, for the scenarios that support more than one.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashKind {
+    /// Crash as if by calling [`trigger_rust_panic`].
+    Panic,
+    /// Crash as if by calling [`trigger_rust_abort`].
+    Abort,
+}
+
+fn crash(kind: CrashKind) {
+    match kind {
+        CrashKind::Panic => trigger_rust_panic(),
+        CrashKind::Abort => trigger_rust_abort(),
+    }
+}
+
+/// Trigger a crash on a background thread inside the Rust code.
+///
+/// This function simulates a crash in some work that the Rust code is doing
+/// off the calling thread. It returns immediately, without waiting for the
+/// crash. A panic on a background thread isn't translated into a foreign
+/// exception, so your crash reporter should see it via the panic hook, while
+/// an abort should halt your application as usual.
+///
+pub fn trigger_rust_crash_on_background_thread(kind: CrashKind) {
+    spawn_crash(kind, 0);
+}
+
+/// Trigger a crash on a background thread after a delay inside the Rust code.
+///
+/// Like [`trigger_rust_crash_on_background_thread`], but the crash happens
+/// `delay_ms` milliseconds later, which lets you check that crashes are
+/// still reported after your application has moved on to something else
+/// (for example, been backgrounded).
+///
+pub fn trigger_rust_crash_after_delay(kind: CrashKind, delay_ms: u64) {
+    spawn_crash(kind, delay_ms);
+}
+
+fn spawn_crash(kind: CrashKind, delay_ms: u64) -> thread::JoinHandle<()> {
+    log::error!(
+        "Now scheduling a {:?} on a background thread in {}ms",
+        kind,
+        delay_ms
+    );
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(delay_ms));
+        crash(kind);
+    })
+}
+
+/// A callback implemented by the foreign language code.
+///
+pub trait CrashTestCallback: Send + Sync {
+    /// Called by [`trigger_rust_crash_in_callback`]. To crash while the
+    /// callback is running, this should call back into one of the
+    /// `trigger_rust_*` functions.
+    fn invoke(&self);
+}
+
+/// Trigger a crash inside the Rust code during an FFI callback.
+///
+/// This function calls `callback`, which is expected to call back into one
+/// of the other `trigger_rust_*` functions. That simulates a crash in Rust
+/// code which was called by foreign code which was itself called by Rust
+/// code, as happens with e.g. logging and HTTP callbacks. After a panic, you
+/// should expect the foreign-language exception to propagate out of the
+/// callback, and then be reported as an "internal error" from this function.
+///
+pub fn trigger_rust_crash_in_callback(callback: Box<dyn CrashTestCallback>) {
+    log::error!("Now invoking a callback that should crash inside the Rust code");
+    callback.invoke();
+}
+
+// Stands in for the lock guarding a map of handles to Rust objects, which
+// is what most of our FFI calls used to hold while calling into a component.
+static HANDLE_MAP_LOCK: Mutex<()> = Mutex::new(());
+
+/// Trigger a crash inside the Rust code while holding a handle-map lock.
+///
+/// This function simulates a crash in Rust code that's holding the lock
+/// which guards a shared map of handles to Rust objects. After a panic the
+/// lock is left poisoned, but later calls ignore that and crash the same way
+/// again, so this can be called repeatedly to check how your application
+/// copes with each crash.
+///
+pub fn trigger_rust_crash_holding_lock(kind: CrashKind) {
+    // A previous panic may have left the lock poisoned, but we want to crash
+    // again anyway.
+    let _guard = HANDLE_MAP_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    log::error!("Now triggering a {:?} while holding a lock", kind);
+    crash(kind);
+}
+
 /// An error that can be returned from Rust code.
 ///
 #[derive(Debug, Error)]
@@ -19,4 +19,46 @@ fn test_trigger_error() {
     ));
 }
 
-// We can't test `trigger_rust_abort()` here because it's a hard error.
+#[test]
+fn test_trigger_panic_on_background_thread() {
+    // The panic only takes down the background thread.
+    let handle = crate::spawn_crash(crate::CrashKind::Panic, 0);
+    let panic = handle
+        .join()
+        .expect_err("the background thread should panic");
+    assert_eq!(
+        panic.downcast_ref::<&str>(),
+        Some(&"Panic! In The Rust Code.")
+    );
+}
+
+struct PanickingCallback;
+
+impl crate::CrashTestCallback for PanickingCallback {
+    fn invoke(&self) {
+        crate::trigger_rust_panic();
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_trigger_panic_in_callback() {
+    crate::trigger_rust_crash_in_callback(Box::new(PanickingCallback));
+}
+
+#[test]
+fn test_trigger_panic_holding_lock() {
+    let result = std::panic::catch_unwind(|| {
+        crate::trigger_rust_crash_holding_lock(crate::CrashKind::Panic)
+    });
+    assert!(result.is_err());
+    assert!(crate::HANDLE_MAP_LOCK.is_poisoned());
+    // Crashing again works even though the lock is poisoned.
+    let result = std::panic::catch_unwind(|| {
+        crate::trigger_rust_crash_holding_lock(crate::CrashKind::Panic)
+    });
+    assert!(result.is_err());
+}
+
+// We can't test `trigger_rust_abort()` here because it's a hard error, and
+// the same goes for `CrashKind::Abort`.
@@ -72,4 +72,105 @@ impl FirefoxAccount {
     pub fn get_manage_devices_url(&self, entrypoint: &str) -> ApiResult<String> {
         self.internal.lock().get_manage_devices_url(entrypoint)
     }
+
+    /// Get a URL at which the user can create an account recovery key.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// An account recovery key lets the user keep their synced data if they forget
+    /// their password. Applications which don't have the user's kB should link the user
+    /// out to this URL rather than using [`create_recovery_key`](FirefoxAccount::create_recovery_key).
+    /// [`has_recovery_key`](FirefoxAccount::has_recovery_key) says whether the user already
+    /// has one.
+    ///
+    /// # Arguments
+    ///
+    ///   - `entrypoint` - metrics identifier for UX entrypoint.
+    ///       - This parameter is used for metrics purposes, to identify the
+    ///         UX entrypoint from which the user followed the link.
+    #[handle_error(Error)]
+    pub fn get_manage_recovery_key_url(&self, entrypoint: &str) -> ApiResult<String> {
+        self.internal.lock().get_manage_recovery_key_url(entrypoint)
+    }
+
+    /// Check whether the user has an account recovery key.
+    ///
+    /// Applications can use this to prompt users without a recovery key to create one,
+    /// with [`get_manage_recovery_key_url`](FirefoxAccount::get_manage_recovery_key_url),
+    /// so that they don't lose their synced data if they forget their password.
+    ///
+    /// # Notes
+    ///
+    ///    - This requires a session token, so it's only available to applications
+    ///      which were signed in with one.
+    #[handle_error(Error)]
+    pub fn has_recovery_key(&self) -> ApiResult<bool> {
+        let mut internal = self.internal.lock();
+        let result = internal.has_recovery_key();
+        internal.track_auth_recovery(result)
+    }
+
+    /// Create an account recovery key for the user to write down.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// The recovery key wraps the account's kB, so the user keeps their synced data if
+    /// they reset their password. It replaces any recovery key the user already has once
+    /// it's been confirmed with [`verify_recovery_key`](FirefoxAccount::verify_recovery_key),
+    /// which applications should do by asking the user to enter it.
+    ///
+    /// # Arguments
+    ///
+    ///   - `kb` - The account's kB, hex-encoded.
+    ///
+    /// # Notes
+    ///
+    ///    - This requires a verified session token, so it's only available to applications
+    ///      which were signed in with one.
+    #[handle_error(Error)]
+    pub fn create_recovery_key(&self, kb: &str) -> ApiResult<String> {
+        let kb = hex::decode(kb)?;
+        let mut internal = self.internal.lock();
+        let result = internal.create_recovery_key(&kb);
+        internal.track_auth_recovery(result)
+    }
+
+    /// Confirm the recovery key returned by [`create_recovery_key`](FirefoxAccount::create_recovery_key),
+    /// as entered by the user, and enable it.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// Throws [`FxaError::InvalidRecoveryKey`] if the user entered a different key.
+    #[handle_error(Error)]
+    pub fn verify_recovery_key(&self, recovery_key: &str) -> ApiResult<()> {
+        let mut internal = self.internal.lock();
+        let result = internal.verify_recovery_key(recovery_key);
+        internal.track_auth_recovery(result)
+    }
+
+    /// Use an account recovery key to get back the account's kB while resetting its password.
+    ///
+    /// Returns kB, hex-encoded, which should be wrapped with the new password when the
+    /// password reset is completed, so the user keeps their synced data.
+    ///
+    /// # Arguments
+    ///
+    ///   - `uid` - The uid of the account whose password is being reset.
+    ///   - `recovery_key` - The recovery key, as entered by the user.
+    ///   - `account_reset_token` - The account reset token from the password reset flow.
+    ///
+    /// Throws [`FxaError::InvalidRecoveryKey`] if the account has no such recovery key.
+    #[handle_error(Error)]
+    pub fn consume_recovery_key(
+        &self,
+        uid: &str,
+        recovery_key: &str,
+        account_reset_token: &str,
+    ) -> ApiResult<String> {
+        let kb =
+            self.internal
+                .lock()
+                .consume_recovery_key(uid, recovery_key, account_reset_token)?;
+        Ok(hex::encode(kb))
+    }
 }
@@ -193,6 +193,22 @@ impl FirefoxAccount {
         self.internal.lock().on_auth_issues()
     }
 
+    /// Check whether the user needs to re-authenticate, and why.
+    ///
+    /// Refresh token revocation, changes to the account keys and missing scopes all end up
+    /// requiring the user to sign in again, but are reported through different errors depending
+    /// on which method observed them.  Whenever a token, profile or device operation fails for one
+    /// of these reasons, the account records an [`AuthRecoveryNeeded`] describing the cause and
+    /// what the application should do about it.  Applications can call this method after catching
+    /// an [`Authentication`](crate::FxaError::Authentication) error to drive a single recovery UX.
+    ///
+    /// The recorded value is cleared after a successful OAuth flow, on disconnect, or when
+    /// [`check_authorization_status`](FirefoxAccount::check_authorization_status) reports that
+    /// the tokens are still active.
+    pub fn get_auth_recovery_needed(&self) -> Option<AuthRecoveryNeeded> {
+        self.internal.lock().get_auth_recovery_needed()
+    }
+
     /// Used by the application to test auth token issues
     pub fn simulate_temporary_auth_token_issue(&self) {
         self.internal.lock().simulate_temporary_auth_token_issue()
@@ -212,6 +228,63 @@ pub struct AuthorizationInfo {
     pub active: bool,
 }
 
+/// Signal that the user must re-authenticate before the account can be used again.
+///
+/// See [`FirefoxAccount::get_auth_recovery_needed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthRecoveryNeeded {
+    /// Why the account needs recovery.
+    pub cause: AuthRecoveryCause,
+    /// What the application should do next.
+    pub recommended_action: AuthRecoveryAction,
+}
+
+impl AuthRecoveryNeeded {
+    pub(crate) fn new(cause: AuthRecoveryCause) -> Self {
+        Self {
+            recommended_action: cause.default_action(),
+            cause,
+        }
+    }
+}
+
+/// The underlying reason that re-authentication is needed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthRecoveryCause {
+    /// The server rejected our refresh or session token, most likely because it was revoked
+    /// (e.g. after a password change or the device being disconnected remotely).
+    TokenRejected,
+    /// We don't hold the refresh or session token needed for the operation.
+    MissingCredentials,
+    /// The account keys changed, or the keys for a scope we need are missing.
+    KeysChanged,
+    /// The refresh token was not granted a scope that the operation requires.
+    ScopeNotGranted,
+}
+
+impl AuthRecoveryCause {
+    fn default_action(&self) -> AuthRecoveryAction {
+        match self {
+            // A rejected token could be a transient server issue, so double-check first.
+            Self::TokenRejected => AuthRecoveryAction::CheckAuthorizationStatus,
+            Self::MissingCredentials | Self::KeysChanged | Self::ScopeNotGranted => {
+                AuthRecoveryAction::Reauthenticate
+            }
+        }
+    }
+}
+
+/// The action recommended to recover from an [`AuthRecoveryNeeded`] signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthRecoveryAction {
+    /// Call [`check_authorization_status`](FirefoxAccount::check_authorization_status) (or send
+    /// [`FxaEvent::CheckAuthorizationStatus`]) to find out whether the account is really
+    /// disconnected.
+    CheckAuthorizationStatus,
+    /// Send the user through an OAuth flow again, requesting all the scopes the application needs.
+    Reauthenticate,
+}
+
 /// High-level view of the authorization state
 ///
 /// This is named `FxaRustAuthState` because it doesn't track all the states we want yet and needs
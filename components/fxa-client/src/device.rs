@@ -55,9 +55,9 @@ impl FirefoxAccount {
         // UniFFI doesn't have good handling of lists of references, work around it.
         let supported_capabilities: Vec<_> =
             supported_capabilities.into_iter().map(Into::into).collect();
-        self.internal
-            .lock()
-            .initialize_device(name, device_type, &supported_capabilities)
+        let mut internal = self.internal.lock();
+        let result = internal.initialize_device(name, device_type, &supported_capabilities);
+        internal.track_auth_recovery(result)
     }
 
     /// Get the device id registered for this application.
@@ -93,9 +93,10 @@ impl FirefoxAccount {
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     #[handle_error(Error)]
     pub fn get_devices(&self, ignore_cache: bool) -> ApiResult<Vec<Device>> {
-        self.internal
-            .lock()
-            .get_devices(ignore_cache)?
+        let mut internal = self.internal.lock();
+        let result = internal.get_devices(ignore_cache);
+        internal
+            .track_auth_recovery(result)?
             .into_iter()
             .map(TryInto::try_into)
             .collect::<Result<_, _>>()
@@ -117,9 +118,10 @@ impl FirefoxAccount {
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     #[handle_error(Error)]
     pub fn get_attached_clients(&self) -> ApiResult<Vec<AttachedClient>> {
-        self.internal
-            .lock()
-            .get_attached_clients()?
+        let mut internal = self.internal.lock();
+        let result = internal.get_attached_clients();
+        internal
+            .track_auth_recovery(result)?
             .into_iter()
             .map(TryInto::try_into)
             .collect::<Result<_, _>>()
@@ -142,7 +144,9 @@ impl FirefoxAccount {
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     #[handle_error(Error)]
     pub fn set_device_name(&self, display_name: &str) -> ApiResult<LocalDevice> {
-        self.internal.lock().set_device_name(display_name)
+        let mut internal = self.internal.lock();
+        let result = internal.set_device_name(display_name);
+        internal.track_auth_recovery(result)
     }
 
     /// Clear any custom display name used for this application instance.
@@ -159,7 +163,9 @@ impl FirefoxAccount {
     ///      granted the `https://identity.mozilla.com/apps/oldsync` scope.
     #[handle_error(Error)]
     pub fn clear_device_name(&self) -> ApiResult<()> {
-        self.internal.lock().clear_device_name()
+        let mut internal = self.internal.lock();
+        let result = internal.clear_device_name();
+        internal.track_auth_recovery(result)
     }
 
     /// Ensure that the device record has a specific set of capabilities.
@@ -190,12 +196,26 @@ impl FirefoxAccount {
     ) -> ApiResult<LocalDevice> {
         let supported_capabilities: Vec<_> =
             supported_capabilities.into_iter().map(Into::into).collect();
-        self.internal
-            .lock()
-            .ensure_capabilities(&supported_capabilities)
+        let mut internal = self.internal.lock();
+        let result = internal.ensure_capabilities(&supported_capabilities);
+        internal.track_auth_recovery(result)
     }
 }
 
+/// Filter out the devices that haven't been used in the last `days` days.
+///
+/// This lets applications hide stale devices, e.g. from a list of "send tab" targets,
+/// without each of them re-implementing the policy for which devices are stale.
+///
+/// # Notes
+///
+///    - The current device is always kept.
+///    - Devices without a [`last_access_time`](Device::last_access_time) are kept, since
+///      we can't tell how long ago they were used.
+pub fn filter_inactive_devices(devices: Vec<Device>, days: u32) -> Vec<Device> {
+    crate::internal::device::filter_inactive(devices, days)
+}
+
 /// Device configuration
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DeviceConfig {
@@ -227,8 +247,13 @@ pub struct Device {
     pub device_type: sync15::DeviceType,
     pub capabilities: Vec<DeviceCapability>,
     pub push_subscription: Option<DevicePushSubscription>,
+    /// Whether the device's push subscription has expired, in which case it won't
+    /// receive commands until it's used again. Applications may want to prompt the
+    /// user to re-open or re-pair the device.
     pub push_endpoint_expired: bool,
     pub is_current_device: bool,
+    /// When the device last used the account, in milliseconds since the epoch, if known.
+    /// See also [`filter_inactive_devices`].
     pub last_access_time: Option<i64>,
 }
 
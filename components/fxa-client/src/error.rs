@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::AuthRecoveryCause;
 use error_support::{ErrorHandling, GetErrorHandling};
 use rc_crypto::hawk;
 use std::string;
@@ -47,6 +48,10 @@ pub enum FxaError {
     /// A scoped key was missing in the server response when requesting the OLD_SYNC scope.
     #[error("The sync scoped key was missing")]
     SyncScopedKeyMissingInServerResponse,
+    /// Thrown if an account recovery key is malformed, or isn't the account's recovery key.
+    /// The application should ask the user to check the key they entered.
+    #[error("invalid recovery key")]
+    InvalidRecoveryKey,
     /// Thrown if there is a panic in the underlying Rust code.
     ///
     /// **Note:** This error is currently only thrown in the Kotlin language bindings.
@@ -193,6 +198,28 @@ pub enum Error {
 
     #[error("Internal error in the state machine: {0}")]
     StateMachineLogicError(String),
+
+    #[error("Invalid account recovery key")]
+    InvalidRecoveryKey,
+}
+
+impl Error {
+    /// If this error means that the user needs to re-authenticate, returns the reason why.
+    pub(crate) fn auth_recovery_cause(&self) -> Option<AuthRecoveryCause> {
+        match self {
+            Error::RemoteError { code: 401, .. } => Some(AuthRecoveryCause::TokenRejected),
+            Error::NoRefreshToken | Error::NoSessionToken => {
+                Some(AuthRecoveryCause::MissingCredentials)
+            }
+            Error::NoCachedToken(_) | Error::ScopeNotAllowed(_, _) => {
+                Some(AuthRecoveryCause::ScopeNotGranted)
+            }
+            Error::NoScopedKey(_)
+            | Error::MismatchedKeys
+            | Error::SyncScopedKeyMissingInServerResponse => Some(AuthRecoveryCause::KeysChanged),
+            _ => None,
+        }
+    }
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -223,6 +250,9 @@ impl GetErrorHandling for Error {
                     .report_error("fxa-state-machine-error")
             }
             Error::OriginMismatch(_) => ErrorHandling::convert(FxaError::OriginMismatch),
+            Error::InvalidRecoveryKey => {
+                ErrorHandling::convert(FxaError::InvalidRecoveryKey).log_warning()
+            }
             _ => ErrorHandling::convert(FxaError::Other(self.to_string()))
                 .report_error("fxa-client-other-error"),
         }
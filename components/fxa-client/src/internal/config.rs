@@ -3,11 +3,19 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use super::http_client;
-use crate::{FxaConfig, Result};
+use crate::{FxaConfig, Result, ServerCapability};
 use serde_derive::{Deserialize, Serialize};
-use std::{cell::RefCell, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use url::Url;
 
+// How long we trust the server's advertised features before fetching them again.
+const SERVER_FEATURES_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     content_url: String,
@@ -37,6 +45,8 @@ pub struct RemoteConfig {
     token_endpoint: String,
     userinfo_endpoint: String,
     introspection_endpoint: String,
+    features: HashSet<ServerCapability>,
+    fetched_at: Instant,
 }
 
 pub(crate) const CONTENT_URL_RELEASE: &str = "https://accounts.firefox.com";
@@ -47,7 +57,10 @@ impl Config {
         if let Some(remote_config) = self.remote_config.borrow().clone() {
             return Ok(remote_config);
         }
+        self.fetch_remote_config()
+    }
 
+    fn fetch_remote_config(&self) -> Result<Arc<RemoteConfig>> {
         let client_config = http_client::fxa_client_configuration(self.client_config_url()?)?;
         let openid_config = http_client::openid_configuration(self.openid_config_url()?)?;
 
@@ -65,10 +78,29 @@ impl Config {
             token_endpoint: format!("{}/v1/oauth/token", client_config.auth_server_base_url),
             userinfo_endpoint: openid_config.userinfo_endpoint,
             introspection_endpoint: openid_config.introspection_endpoint,
+            features: client_config
+                .features
+                .iter()
+                .filter(|(_, supported)| **supported)
+                .filter_map(|(key, _)| ServerCapability::from_key(key))
+                .collect(),
+            fetched_at: Instant::now(),
         });
         Ok(remote_config)
     }
 
+    pub fn server_supports(&self, capability: ServerCapability) -> Result<bool> {
+        let mut remote_config = self.remote_config()?;
+        if remote_config.fetched_at.elapsed() > SERVER_FEATURES_MAX_AGE {
+            // If the refresh fails, stale features are better than none.
+            match self.fetch_remote_config() {
+                Ok(fresh) => remote_config = fresh,
+                Err(e) => log::warn!("Failed to refresh the FxA configuration: {}", e),
+            }
+        }
+        Ok(remote_config.features.contains(&capability))
+    }
+
     fn set_remote_config(&self, remote_config: RemoteConfig) -> Arc<RemoteConfig> {
         let rc = Arc::new(remote_config);
         let result = rc.clone();
@@ -119,6 +151,11 @@ impl Config {
             .map_err(Into::into)
     }
 
+    pub fn settings_account_recovery_url(&self) -> Result<Url> {
+        self.content_url_path("settings/account_recovery")
+            .map_err(Into::into)
+    }
+
     pub fn auth_url(&self) -> Result<Url> {
         Url::parse(&self.remote_config()?.auth_url).map_err(Into::into)
     }
@@ -248,6 +285,8 @@ mod tests {
             token_endpoint: "https://stable.dev.lcip.org/auth/v1/oauth/token".to_string(),
             introspection_endpoint: "https://oauth-stable.dev.lcip.org/v1/introspect".to_string(),
             userinfo_endpoint: "https://stable.dev.lcip.org/profile/v1/profile".to_string(),
+            features: HashSet::new(),
+            fetched_at: Instant::now(),
         };
 
         let config = Config {
@@ -285,6 +324,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_server_supports() {
+        let remote_config = RemoteConfig {
+            auth_url: "https://stable.dev.lcip.org/auth/".to_string(),
+            oauth_url: "https://oauth-stable.dev.lcip.org/".to_string(),
+            profile_url: "https://stable.dev.lcip.org/profile/".to_string(),
+            token_server_endpoint_url: "https://stable.dev.lcip.org/syncserver/token/1.0/sync/1.5"
+                .to_string(),
+            authorization_endpoint: "https://oauth-stable.dev.lcip.org/v1/authorization"
+                .to_string(),
+            issuer: "https://dev.lcip.org/".to_string(),
+            jwks_uri: "https://oauth-stable.dev.lcip.org/v1/jwks".to_string(),
+            token_endpoint: "https://stable.dev.lcip.org/auth/v1/oauth/token".to_string(),
+            introspection_endpoint: "https://oauth-stable.dev.lcip.org/v1/introspect".to_string(),
+            userinfo_endpoint: "https://stable.dev.lcip.org/profile/v1/profile".to_string(),
+            features: HashSet::from([ServerCapability::Pairing]),
+            fetched_at: Instant::now(),
+        };
+
+        let config = Config {
+            content_url: "https://stable.dev.lcip.org/".to_string(),
+            remote_config: RefCell::new(Some(Arc::new(remote_config))),
+            client_id: "263ceaa5546dce83".to_string(),
+            redirect_uri: "https://127.0.0.1:8080".to_string(),
+            token_server_url_override: None,
+        };
+        assert!(config.server_supports(ServerCapability::Pairing).unwrap());
+        assert!(!config
+            .server_supports(ServerCapability::EcosystemTelemetry)
+            .unwrap());
+    }
+
     #[test]
     fn test_tokenserver_url_override() {
         let remote_config = RemoteConfig {
@@ -300,6 +371,8 @@ mod tests {
             token_endpoint: "https://stable.dev.lcip.org/auth/v1/oauth/token".to_string(),
             introspection_endpoint: "https://oauth-stable.dev.lcip.org/v1/introspect".to_string(),
             userinfo_endpoint: "https://stable.dev.lcip.org/profile/v1/profile".to_string(),
+            features: HashSet::new(),
+            fetched_at: Instant::now(),
         };
 
         let mut config = Config {
@@ -332,6 +405,8 @@ mod tests {
             token_endpoint: "https://stable.dev.lcip.org/auth/v1/oauth/token".to_string(),
             introspection_endpoint: "https://oauth-stable.dev.lcip.org/v1/introspect".to_string(),
             userinfo_endpoint: "https://stable.dev.lcip.org/profile/v1/profile".to_string(),
+            features: HashSet::new(),
+            fetched_at: Instant::now(),
         };
 
         let mut config = Config {
@@ -11,7 +11,8 @@ pub use super::http_client::{GetDeviceResponse as Device, PushSubscription};
 use super::{
     commands::{self, IncomingDeviceCommand, PrivateCommandKeys, PublicCommandKeys},
     http_client::{
-        DeviceUpdateRequest, DeviceUpdateRequestBuilder, PendingCommand, UpdateDeviceResponse,
+        DeviceCredentials, DeviceUpdateRequest, DeviceUpdateRequestBuilder, PendingCommand,
+        UpdateDeviceResponse,
     },
     scopes, telemetry, util, CachedResponse, FirefoxAccount,
 };
@@ -51,10 +52,8 @@ impl FirefoxAccount {
             }
         }
 
-        let refresh_token = self.get_refresh_token()?;
-        let response = self
-            .client
-            .get_devices(self.state.config(), refresh_token)?;
+        let credentials = self.get_device_credentials()?;
+        let response = self.client.get_devices(self.state.config(), &credentials)?;
 
         self.devices_cache = Some(CachedResponse {
             response: response.clone(),
@@ -65,6 +64,20 @@ impl FirefoxAccount {
         Ok(response)
     }
 
+    /// The credentials for device and command requests: our refresh token if we have one,
+    /// otherwise our session token, for embedders which haven't migrated to OAuth.
+    fn get_device_credentials(&self) -> Result<DeviceCredentials> {
+        match (self.state.refresh_token(), self.state.session_token()) {
+            (Some(refresh_token), _) => {
+                Ok(DeviceCredentials::RefreshToken(refresh_token.token.clone()))
+            }
+            (None, Some(session_token)) => {
+                Ok(DeviceCredentials::SessionToken(session_token.to_owned()))
+            }
+            (None, None) => Err(Error::NoRefreshToken),
+        }
+    }
+
     pub fn get_current_device(&mut self) -> Result<Option<Device>> {
         Ok(self
             .get_devices(false)?
@@ -168,10 +181,10 @@ impl FirefoxAccount {
         payload: &serde_json::Value,
         ttl: Option<u64>,
     ) -> Result<()> {
-        let refresh_token = self.get_refresh_token()?;
+        let credentials = self.get_device_credentials()?;
         self.client.invoke_command(
             self.state.config(),
-            refresh_token,
+            &credentials,
             command,
             &target.id,
             payload,
@@ -196,10 +209,10 @@ impl FirefoxAccount {
     }
 
     pub fn get_command_for_index(&mut self, index: u64) -> Result<IncomingDeviceCommand> {
-        let refresh_token = self.get_refresh_token()?;
+        let credentials = self.get_device_credentials()?;
         let pending_commands =
             self.client
-                .get_pending_commands(self.state.config(), refresh_token, index, Some(1))?;
+                .get_pending_commands(self.state.config(), &credentials, index, Some(1))?;
         self.parse_commands_messages(pending_commands.messages, CommandFetchReason::Push(index))?
             .into_iter()
             .next()
@@ -212,10 +225,10 @@ impl FirefoxAccount {
         limit: Option<u64>,
         reason: CommandFetchReason,
     ) -> Result<Vec<IncomingDeviceCommand>> {
-        let refresh_token = self.get_refresh_token()?;
+        let credentials = self.get_device_credentials()?;
         let pending_commands =
             self.client
-                .get_pending_commands(self.state.config(), refresh_token, index, limit)?;
+                .get_pending_commands(self.state.config(), &credentials, index, limit)?;
         if pending_commands.messages.is_empty() {
             return Ok(Vec::new());
         }
@@ -320,10 +333,10 @@ impl FirefoxAccount {
     }
 
     fn update_device(&mut self, update: DeviceUpdateRequest<'_>) -> Result<LocalDevice> {
-        let refresh_token = self.get_refresh_token()?;
+        let credentials = self.get_device_credentials()?;
         let res = self
             .client
-            .update_device_record(self.state.config(), refresh_token, update);
+            .update_device_record(self.state.config(), &credentials, update);
         match res {
             Ok(resp) => {
                 self.state.set_current_device_id(resp.id.clone());
@@ -432,6 +445,24 @@ impl TryFrom<Device> for crate::Device {
     }
 }
 
+/// Drops the devices which haven't been used in the last `days` days.
+pub fn filter_inactive(devices: Vec<crate::Device>, days: u32) -> Vec<crate::Device> {
+    filter_accessed_before(devices, util::past_timestamp(days.into()))
+}
+
+fn filter_accessed_before(devices: Vec<crate::Device>, cutoff_ms: u64) -> Vec<crate::Device> {
+    devices
+        .into_iter()
+        .filter(|d| {
+            // We can't tell how long ago a device without a last access time
+            // was used, so keep it, and always keep the current device.
+            d.is_current_device
+                || d.last_access_time
+                    .map_or(true, |t| t >= 0 && t as u64 >= cutoff_ms)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -470,7 +501,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -512,7 +547,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -533,7 +572,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -559,7 +602,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
                     id: "device1".to_string(),
@@ -588,7 +635,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -613,7 +664,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -635,7 +690,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -660,7 +719,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -743,7 +806,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("newRefreshTok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("newRefreshTok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -771,7 +838,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Err(Error::RemoteError {
@@ -791,7 +862,11 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_update_device_record()
-            .with(always(), eq("refreshtok"), always())
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                always(),
+            )
             .times(1)
             .returning(|_, _, _| {
                 Ok(UpdateDeviceResponse {
@@ -812,6 +887,51 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_session_token_only() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        // Without a refresh token or session token, there's nothing to authenticate with.
+        assert!(matches!(fxa.get_devices(true), Err(Error::NoRefreshToken)));
+
+        // Embedders which haven't migrated to OAuth only have a session token.
+        fxa.set_session_token("session");
+        let credentials = DeviceCredentials::SessionToken("session".to_string());
+        let mut client = MockFxAClient::new();
+        client
+            .expect_update_device_record()
+            .with(always(), eq(credentials.clone()), always())
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(UpdateDeviceResponse {
+                    id: "device1".to_string(),
+                    display_name: "".to_string(),
+                    device_type: DeviceType::Desktop,
+                    push_subscription: None,
+                    available_commands: HashMap::new(),
+                    push_endpoint_expired: false,
+                })
+            });
+        client
+            .expect_get_pending_commands()
+            .with(always(), eq(credentials), always(), always())
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(PendingCommandsResponse {
+                    index: 0,
+                    last: Some(true),
+                    messages: vec![],
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        fxa.set_device_name("My Device").unwrap();
+        assert!(fxa
+            .poll_device_commands(CommandFetchReason::Poll)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_get_devices() {
         let mut fxa = setup();
@@ -892,4 +1012,46 @@ mod tests {
         assert!(res.is_err());
         assert!(fxa.devices_cache.is_none());
     }
+
+    #[test]
+    fn test_filter_inactive() {
+        let device = |id: &str, is_current_device, last_access_time| crate::Device {
+            id: id.to_string(),
+            display_name: id.to_string(),
+            device_type: DeviceType::Desktop,
+            capabilities: vec![],
+            push_subscription: None,
+            push_endpoint_expired: false,
+            is_current_device,
+            last_access_time,
+        };
+        let devices = vec![
+            device("recent", false, Some(2000)),
+            device("cutoff", false, Some(1000)),
+            device("stale", false, Some(999)),
+            device("unknown", false, None),
+            device("current", true, Some(0)),
+        ];
+        let ids: Vec<_> = filter_accessed_before(devices, 1000)
+            .into_iter()
+            .map(|d| d.id)
+            .collect();
+        assert_eq!(ids, vec!["recent", "cutoff", "unknown", "current"]);
+    }
+
+    #[test]
+    fn test_filter_inactive_huge_days() {
+        let devices = vec![crate::Device {
+            id: "ancient".to_string(),
+            display_name: "ancient".to_string(),
+            device_type: DeviceType::Desktop,
+            capabilities: vec![],
+            push_subscription: None,
+            push_endpoint_expired: false,
+            is_current_device: false,
+            last_access_time: Some(0),
+        }];
+        // The cutoff is clamped to the epoch, so nothing is dropped.
+        assert_eq!(filter_inactive(devices, u32::MAX).len(), 1);
+    }
 }
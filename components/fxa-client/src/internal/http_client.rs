@@ -113,14 +113,14 @@ pub(crate) trait FxAClient {
     fn get_pending_commands(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         index: u64,
         limit: Option<u64>,
     ) -> Result<PendingCommandsResponse>;
     fn invoke_command(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         command: &str,
         target: &str,
         payload: &serde_json::Value,
@@ -129,11 +129,20 @@ pub(crate) trait FxAClient {
     fn update_device_record<'a>(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         update: DeviceUpdateRequest<'a>,
     ) -> Result<UpdateDeviceResponse>;
-    fn destroy_device_record(&self, config: &Config, refresh_token: &str, id: &str) -> Result<()>;
-    fn get_devices(&self, config: &Config, refresh_token: &str) -> Result<Vec<GetDeviceResponse>>;
+    fn destroy_device_record(
+        &self,
+        config: &Config,
+        credentials: &DeviceCredentials,
+        id: &str,
+    ) -> Result<()>;
+    fn get_devices(
+        &self,
+        config: &Config,
+        credentials: &DeviceCredentials,
+    ) -> Result<Vec<GetDeviceResponse>>;
     fn get_attached_clients(
         &self,
         config: &Config,
@@ -146,6 +155,26 @@ pub(crate) trait FxAClient {
         client_id: &str,
         scope: &str,
     ) -> Result<HashMap<String, ScopedKeyDataResponse>>;
+    fn check_recovery_key_exists(&self, config: &Config, session_token: &str) -> Result<bool>;
+    fn create_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+        recovery_data: &str,
+    ) -> Result<()>;
+    fn verify_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+    ) -> Result<()>;
+    fn get_recovery_key_data(
+        &self,
+        config: &Config,
+        account_reset_token: &str,
+        recovery_key_id: &str,
+    ) -> Result<String>;
     #[allow(dead_code)]
     fn get_fxa_client_configuration(&self, config: &Config) -> Result<ClientConfigurationResponse>;
     #[allow(dead_code)]
@@ -342,80 +371,82 @@ impl FxAClient for Client {
     fn get_pending_commands(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         index: u64,
         limit: Option<u64>,
     ) -> Result<PendingCommandsResponse> {
-        let url = config.auth_url_path("v1/account/device/commands")?;
-        let mut request = Request::get(url)
-            .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?
-            .query(&[("index", &index.to_string())]);
+        let mut url = config.auth_url_path("v1/account/device/commands")?;
+        url.query_pairs_mut()
+            .append_pair("index", &index.to_string());
         if let Some(limit) = limit {
-            request = request.query(&[("limit", &limit.to_string())])
+            url.query_pairs_mut()
+                .append_pair("limit", &limit.to_string());
         }
+        let request = credentials.build_request(Method::Get, url, None)?;
         Ok(self.make_request(request)?.json()?)
     }
 
     fn invoke_command(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         command: &str,
         target: &str,
         payload: &serde_json::Value,
         ttl: Option<u64>,
     ) -> Result<()> {
-        let body = serde_json::to_string(&InvokeCommandRequest {
+        let body = serde_json::to_value(InvokeCommandRequest {
             command,
             target,
             payload,
             ttl,
         })?;
         let url = config.auth_url_path("v1/account/devices/invoke_command")?;
-        let request = Request::post(url)
-            .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?
-            .header(header_names::CONTENT_TYPE, "application/json")?
-            .body(body);
+        let request = credentials.build_request(Method::Post, url, Some(body))?;
         self.make_request(request)?;
         Ok(())
     }
 
-    fn get_devices(&self, config: &Config, refresh_token: &str) -> Result<Vec<GetDeviceResponse>> {
-        let url = config.auth_url_path("v1/account/devices")?;
+    fn get_devices(
+        &self,
+        config: &Config,
+        credentials: &DeviceCredentials,
+    ) -> Result<Vec<GetDeviceResponse>> {
+        let mut url = config.auth_url_path("v1/account/devices")?;
         let timestamp = util::past_timestamp(DEVICES_FILTER_DAYS).to_string();
         breadcrumb!(
-            "get_devices timestamp: {timestamp}, refresh len: {}",
-            refresh_token.len()
+            "get_devices timestamp: {timestamp}, token len: {}",
+            credentials.token_len()
         );
-        let request = Request::get(url)
-            .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?
-            .query(&[("filterIdleDevicesTimestamp", &timestamp)]);
+        url.query_pairs_mut()
+            .append_pair("filterIdleDevicesTimestamp", &timestamp);
+        let request = credentials.build_request(Method::Get, url, None)?;
         Ok(self.make_request(request)?.json()?)
     }
 
     fn update_device_record(
         &self,
         config: &Config,
-        refresh_token: &str,
+        credentials: &DeviceCredentials,
         update: DeviceUpdateRequest<'_>,
     ) -> Result<UpdateDeviceResponse> {
         let url = config.auth_url_path("v1/account/device")?;
-        let request = Request::post(url)
-            .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?
-            .header(header_names::CONTENT_TYPE, "application/json")?
-            .body(serde_json::to_string(&update)?);
+        let request =
+            credentials.build_request(Method::Post, url, Some(serde_json::to_value(&update)?))?;
         Ok(self.make_request(request)?.json()?)
     }
 
-    fn destroy_device_record(&self, config: &Config, refresh_token: &str, id: &str) -> Result<()> {
+    fn destroy_device_record(
+        &self,
+        config: &Config,
+        credentials: &DeviceCredentials,
+        id: &str,
+    ) -> Result<()> {
         let body = json!({
             "id": id,
         });
         let url = config.auth_url_path("v1/account/device/destroy")?;
-        let request = Request::post(url)
-            .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?
-            .header(header_names::CONTENT_TYPE, "application/json")?
-            .body(body.to_string());
+        let request = credentials.build_request(Method::Post, url, Some(body))?;
 
         self.make_request(request)?;
         Ok(())
@@ -451,6 +482,69 @@ impl FxAClient for Client {
         self.make_request(request)?.json().map_err(|e| e.into())
     }
 
+    fn check_recovery_key_exists(&self, config: &Config, session_token: &str) -> Result<bool> {
+        let url = config.auth_url_path("v1/recoveryKey/exists")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(json!({}))
+            .build()?;
+        let resp: RecoveryKeyExistsResponse = self.make_request(request)?.json()?;
+        Ok(resp.exists)
+    }
+
+    fn create_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+        recovery_data: &str,
+    ) -> Result<()> {
+        let body = json!({
+            "recoveryKeyId": recovery_key_id,
+            "recoveryData": recovery_data,
+            // The key replaces any existing one once it's been verified.
+            "enabled": false,
+        });
+        let url = config.auth_url_path("v1/recoveryKey")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(body)
+            .build()?;
+        self.make_request(request)?;
+        Ok(())
+    }
+
+    fn verify_recovery_key(
+        &self,
+        config: &Config,
+        session_token: &str,
+        recovery_key_id: &str,
+    ) -> Result<()> {
+        let body = json!({
+            "recoveryKeyId": recovery_key_id,
+        });
+        let url = config.auth_url_path("v1/recoveryKey/verify")?;
+        let key = derive_auth_key_from_session_token(session_token)?;
+        let request = HawkRequestBuilder::new(Method::Post, url, &key)
+            .body(body)
+            .build()?;
+        self.make_request(request)?;
+        Ok(())
+    }
+
+    fn get_recovery_key_data(
+        &self,
+        config: &Config,
+        account_reset_token: &str,
+        recovery_key_id: &str,
+    ) -> Result<String> {
+        let url = config.auth_url_path(&format!("v1/recoveryKey/{}", recovery_key_id))?;
+        let key = derive_auth_key_from_token(account_reset_token, "accountResetToken")?;
+        let request = HawkRequestBuilder::new(Method::Get, url, &key).build()?;
+        let resp: RecoveryKeyDataResponse = self.make_request(request)?.json()?;
+        Ok(resp.recovery_data)
+    }
+
     fn simulate_network_error(&self) {
         self.simulate_network_error.store(true, Ordering::Relaxed);
     }
@@ -578,14 +672,65 @@ fn kw(name: &str) -> Vec<u8> {
 }
 
 pub fn derive_auth_key_from_session_token(session_token: &str) -> Result<Vec<u8>> {
-    let session_token_bytes = hex::decode(session_token)?;
-    let context_info = kw("sessionToken");
+    derive_auth_key_from_token(session_token, "sessionToken")
+}
+
+fn derive_auth_key_from_token(token: &str, context: &str) -> Result<Vec<u8>> {
+    let token_bytes = hex::decode(token)?;
+    let context_info = kw(context);
     let salt = hmac::SigningKey::new(&digest::SHA256, &HAWK_HKDF_SALT);
     let mut out = vec![0u8; HAWK_KEY_LENGTH * 2];
-    hkdf::extract_and_expand(&salt, &session_token_bytes, &context_info, &mut out)?;
+    hkdf::extract_and_expand(&salt, &token_bytes, &context_info, &mut out)?;
     Ok(out)
 }
 
+/// The credentials used to authenticate requests to the device and command endpoints.
+///
+/// These are normally authenticated with our refresh token, but the server also accepts a
+/// HAWK-signed session token, which lets embedders that haven't migrated to OAuth (and so only
+/// hold a session token) register a device and send and receive commands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum DeviceCredentials {
+    RefreshToken(String),
+    SessionToken(String),
+}
+
+impl DeviceCredentials {
+    fn build_request(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<serde_json::Value>,
+    ) -> Result<Request> {
+        match self {
+            Self::RefreshToken(refresh_token) => {
+                let mut request = Request::new(method, url)
+                    .header(header_names::AUTHORIZATION, bearer_token(refresh_token))?;
+                if let Some(body) = body {
+                    request = request
+                        .header(header_names::CONTENT_TYPE, "application/json")?
+                        .body(body.to_string());
+                }
+                Ok(request)
+            }
+            Self::SessionToken(session_token) => {
+                let key = derive_auth_key_from_session_token(session_token)?;
+                let mut builder = HawkRequestBuilder::new(method, url, &key);
+                if let Some(body) = body {
+                    builder = builder.body(body);
+                }
+                builder.build()
+            }
+        }
+    }
+
+    fn token_len(&self) -> usize {
+        match self {
+            Self::RefreshToken(token) | Self::SessionToken(token) => token.len(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AuthorizationRequestParameters {
     pub client_id: String,
@@ -661,6 +806,10 @@ pub(crate) struct ClientConfigurationResponse {
     pub(crate) oauth_server_base_url: String,
     pub(crate) profile_server_base_url: String,
     pub(crate) sync_tokenserver_base_url: String,
+    // Optional features supported by the server, eg `{"pairing": true}`. Most
+    // servers don't send this yet.
+    #[serde(default)]
+    pub(crate) features: HashMap<String, bool>,
 }
 
 #[derive(Deserialize)]
@@ -851,6 +1000,17 @@ pub struct DeviceResponseCommon {
     pub push_endpoint_expired: bool,
 }
 
+#[derive(Deserialize)]
+struct RecoveryKeyExistsResponse {
+    exists: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecoveryKeyDataResponse {
+    recovery_data: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetAttachedClientResponse {
@@ -1041,6 +1201,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_device_credentials() {
+        let url = Url::parse("https://stable.dev.lcip.org/v1/account/device").unwrap();
+        let request = DeviceCredentials::RefreshToken("refreshtok".to_string())
+            .build_request(Method::Post, url.clone(), Some(json!({"name": "foo"})))
+            .unwrap();
+        assert_eq!(
+            request.headers.get(header_names::AUTHORIZATION),
+            Some("Bearer refreshtok")
+        );
+        assert_eq!(request.body, Some(br#"{"name":"foo"}"#.to_vec()));
+
+        let request = DeviceCredentials::SessionToken("ab".repeat(32))
+            .build_request(Method::Post, url, Some(json!({"name": "foo"})))
+            .unwrap();
+        assert!(request
+            .headers
+            .get(header_names::AUTHORIZATION)
+            .unwrap()
+            .starts_with("Hawk "));
+        assert_eq!(request.body, Some(br#"{"name":"foo"}"#.to_vec()));
+    }
+
     #[test]
     fn test_backoff() {
         viaduct_reqwest::use_reqwest_backend();
@@ -6,12 +6,16 @@
 
 use self::{
     config::Config,
+    http_client::DeviceCredentials,
     oauth::{AuthCircuitBreaker, OAuthFlow, OAUTH_WEBCHANNEL_REDIRECT},
     state_manager::StateManager,
     state_persistence::PersistedState,
     telemetry::FxaTelemetry,
 };
-use crate::{DeviceConfig, Error, FxaConfig, FxaRustAuthState, FxaState, Result};
+use crate::{
+    AuthRecoveryAction, AuthRecoveryCause, AuthRecoveryNeeded, DeviceConfig, Error, FxaConfig,
+    FxaRustAuthState, FxaState, PersistedStateField, Result, ServerCapability,
+};
 use serde_derive::*;
 use std::{
     collections::{HashMap, HashSet},
@@ -30,9 +34,11 @@ mod http_client;
 mod oauth;
 mod profile;
 mod push;
+mod recovery_key;
 mod scoped_keys;
-mod scopes;
+pub(crate) mod scopes;
 mod send_tab;
+mod state_debug_log;
 mod state_manager;
 mod state_persistence;
 mod telemetry;
@@ -61,6 +67,8 @@ pub struct FirefoxAccount {
     pub(crate) auth_state: FxaState,
     // Set via `FxaEvent::Initialize`
     pub(crate) device_config: Option<DeviceConfig>,
+    // Set when an operation fails in a way that requires the user to re-authenticate.
+    auth_recovery_needed: Option<AuthRecoveryNeeded>,
 }
 
 impl FirefoxAccount {
@@ -74,6 +82,7 @@ impl FirefoxAccount {
             telemetry: FxaTelemetry::new(),
             auth_state: FxaState::Uninitialized,
             device_config: None,
+            auth_recovery_needed: None,
         }
     }
 
@@ -112,12 +121,27 @@ impl FirefoxAccount {
         Ok(Self::from_state(state))
     }
 
+    /// Like `from_json`, but salvages what it can from corrupt state.
+    /// Also returns the fields which couldn't be recovered.
+    pub fn from_json_with_recovery(data: &str) -> Result<(Self, Vec<PersistedStateField>)> {
+        let (state, lost) = state_persistence::state_from_json_with_recovery(data)?;
+        Ok((Self::from_state(state), lost))
+    }
+
     /// Serialize a `FirefoxAccount` instance internal state
     /// to be restored later using `from_json`.
     pub fn to_json(&self) -> Result<String> {
         self.state.serialize_persisted_state()
     }
 
+    pub fn set_state_debug_log_enabled(&mut self, enabled: bool) {
+        self.state.set_debug_log_enabled(enabled)
+    }
+
+    pub fn get_state_debug_log(&self) -> Vec<crate::StateDebugLogEntry> {
+        self.state.debug_log()
+    }
+
     /// Clear the attached clients and devices cache
     pub fn clear_devices_and_attached_clients_cache(&mut self) {
         self.attached_clients_cache = None;
@@ -129,6 +153,10 @@ impl FirefoxAccount {
         Ok(self.state.config().token_server_endpoint_url()?.into())
     }
 
+    pub fn server_supports(&self, capability: ServerCapability) -> Result<bool> {
+        self.state.config().server_supports(capability)
+    }
+
     /// Get the pairing URL to navigate to on the Auth side (typically
     /// a computer).
     pub fn get_pairing_authority_url(&self) -> Result<String> {
@@ -184,6 +212,17 @@ impl FirefoxAccount {
         self.add_account_identifiers_to_url(url)
     }
 
+    /// Get the "account recovery" settings page URL, where the user can create
+    /// an account recovery key.
+    ///
+    /// * `entrypoint` - Application-provided string identifying the UI touchpoint
+    ///                  through which the page was accessed, for metrics purposes.
+    pub fn get_manage_recovery_key_url(&mut self, entrypoint: &str) -> Result<String> {
+        let mut url = self.state.config().settings_account_recovery_url()?;
+        url.query_pairs_mut().append_pair("entrypoint", entrypoint);
+        self.add_account_identifiers_to_url(url)
+    }
+
     fn add_account_identifiers_to_url(&mut self, mut url: Url) -> Result<String> {
         let profile = self.get_profile(false)?;
         url.query_pairs_mut()
@@ -192,13 +231,6 @@ impl FirefoxAccount {
         Ok(url.into())
     }
 
-    fn get_refresh_token(&self) -> Result<&str> {
-        match self.state.refresh_token() {
-            Some(token_info) => Ok(&token_info.token),
-            None => Err(Error::NoRefreshToken),
-        }
-    }
-
     pub fn get_auth_state(&self) -> FxaRustAuthState {
         self.state.get_auth_state()
     }
@@ -223,7 +255,7 @@ impl FirefoxAccount {
                 // still try to delete the refresh token itself.
                 Ok(Some(device)) => self.client.destroy_device_record(
                     self.state.config(),
-                    &refresh_token.token,
+                    &DeviceCredentials::RefreshToken(refresh_token.token.clone()),
                     &device.id,
                 ),
                 _ => self
@@ -233,10 +265,31 @@ impl FirefoxAccount {
             if let Err(e) = destroy_result {
                 log::warn!("Error while destroying the device: {}", e);
             }
+        } else if let (Some(session_token), Ok(Some(device))) =
+            (self.state.session_token(), current_device_result)
+        {
+            // We registered the device with our session token, so destroy it with that.
+            if let Err(e) = self.client.destroy_device_record(
+                self.state.config(),
+                &DeviceCredentials::SessionToken(session_token.to_owned()),
+                &device.id,
+            ) {
+                log::warn!("Error while destroying the device: {}", e);
+            }
         }
+        self.disconnect_locally();
+    }
+
+    /// Forget the account's tokens and everything we've cached about it, without telling the
+    /// server. Used by [FirefoxAccount::disconnect] once it has destroyed the device, and when
+    /// the account no longer exists.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub(crate) fn disconnect_locally(&mut self) {
         self.state.disconnect();
         self.clear_devices_and_attached_clients_cache();
         self.telemetry = FxaTelemetry::new();
+        self.auth_recovery_needed = None;
     }
 
     /// Update the state based on authentication issues.
@@ -251,6 +304,49 @@ impl FirefoxAccount {
         self.telemetry = FxaTelemetry::new();
     }
 
+    pub fn get_auth_recovery_needed(&self) -> Option<AuthRecoveryNeeded> {
+        self.auth_recovery_needed.clone()
+    }
+
+    /// Record an [AuthRecoveryNeeded] signal if `result` failed because of an auth issue.
+    ///
+    /// This is applied to the token, profile and device operations so that consumers see the
+    /// same signal regardless of which operation first noticed the problem.
+    pub(crate) fn track_auth_recovery<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(e) = &result {
+            if let Some(cause) = e.auth_recovery_cause() {
+                log::info!("Auth recovery needed: {:?}", cause);
+                self.auth_recovery_needed = Some(AuthRecoveryNeeded::new(cause));
+            }
+        }
+        result
+    }
+
+    pub(crate) fn clear_auth_recovery_needed(&mut self) {
+        self.auth_recovery_needed = None;
+    }
+
+    /// Update the auth recovery signal after checking the refresh token status with the server.
+    pub(crate) fn on_authorization_status_checked(&mut self, active: bool) {
+        if active {
+            // A previously rejected token turned out to be fine, so there's nothing to recover.
+            if matches!(
+                self.auth_recovery_needed,
+                Some(AuthRecoveryNeeded {
+                    cause: AuthRecoveryCause::TokenRejected,
+                    ..
+                })
+            ) {
+                self.auth_recovery_needed = None;
+            }
+        } else {
+            self.auth_recovery_needed = Some(AuthRecoveryNeeded {
+                cause: AuthRecoveryCause::TokenRejected,
+                recommended_action: AuthRecoveryAction::Reauthenticate,
+            });
+        }
+    }
+
     pub fn simulate_network_error(&mut self) {
         self.client.simulate_network_error();
     }
@@ -345,6 +441,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_manage_recovery_key_url() {
+        let config = Config::new("https://stable.dev.lcip.org", "12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.add_cached_profile("123", "test@example.com");
+        let url = fxa.get_manage_recovery_key_url("test").unwrap();
+        assert_eq!(
+            url,
+            "https://stable.dev.lcip.org/settings/account_recovery?entrypoint=test&uid=123&email=test%40example.com"
+                .to_string()
+        );
+    }
+
     #[test]
     fn test_get_manage_devices_url() {
         let config = Config::new("https://stable.dev.lcip.org", "12345678", "https://foo.bar");
@@ -387,6 +496,44 @@ mod tests {
         assert!(fxa.state.is_access_token_cache_empty());
     }
 
+    #[test]
+    fn test_state_debug_log() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_string(),
+            scopes: HashSet::default(),
+        });
+
+        // Nothing is recorded until the log is enabled.
+        fxa.state.set_current_device_id("device-id".to_string());
+        assert!(fxa.get_state_debug_log().is_empty());
+
+        fxa.set_state_debug_log_enabled(true);
+        fxa.on_auth_issues();
+        let log = fxa.get_state_debug_log();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].operation, "on_auth_issues");
+        assert_eq!(
+            log[0].changes,
+            vec![
+                crate::StateFieldChange {
+                    field: crate::PersistedStateField::RefreshToken,
+                    was_set: true,
+                    is_set: false,
+                },
+                crate::StateFieldChange {
+                    field: crate::PersistedStateField::LoggedOutFromAuthIssues,
+                    was_set: false,
+                    is_set: true,
+                },
+            ]
+        );
+
+        fxa.set_state_debug_log_enabled(false);
+        assert!(fxa.get_state_debug_log().is_empty());
+    }
+
     #[test]
     fn test_disconnect_device() {
         let config = Config::stable_dev("12345678", "https://foo.bar");
@@ -400,7 +547,10 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_get_devices()
-            .with(always(), eq("refreshtok"))
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+            )
             .times(1)
             .returning(|_, _| {
                 Ok(vec![
@@ -444,7 +594,11 @@ mod tests {
             });
         client
             .expect_destroy_device_record()
-            .with(always(), eq("refreshtok"), eq("1234a"))
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+                eq("1234a"),
+            )
             .times(1)
             .returning(|_, _, _| Ok(()));
         fxa.set_client(Arc::new(client));
@@ -467,7 +621,10 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_get_devices()
-            .with(always(), eq("refreshtok"))
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+            )
             .times(1)
             .returning(|_, _| {
                 Ok(vec![Device {
@@ -514,7 +671,10 @@ mod tests {
         let mut client = MockFxAClient::new();
         client
             .expect_get_devices()
-            .with(always(), eq("refreshtok"))
+            .with(
+                always(),
+                eq(DeviceCredentials::RefreshToken("refreshtok".to_string())),
+            )
             .times(1)
             .returning(|_, _| Ok(vec![]));
         client
@@ -568,6 +728,57 @@ mod tests {
         assert_eq!(fxa.state.current_device_id(), Some("original-device-id"));
     }
 
+    #[test]
+    fn test_track_auth_recovery() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        assert_eq!(fxa.get_auth_recovery_needed(), None);
+
+        // Errors unrelated to auth don't trigger recovery.
+        let _ = fxa.track_auth_recovery::<()>(Err(Error::CommandNotFound));
+        assert_eq!(fxa.get_auth_recovery_needed(), None);
+
+        let _ = fxa.track_auth_recovery::<()>(Err(Error::RemoteError {
+            code: 401,
+            errno: 110,
+            error: "Unauthorized".to_owned(),
+            message: "Invalid authentication token".to_owned(),
+            info: "".to_owned(),
+        }));
+        assert_eq!(
+            fxa.get_auth_recovery_needed(),
+            Some(AuthRecoveryNeeded {
+                cause: AuthRecoveryCause::TokenRejected,
+                recommended_action: AuthRecoveryAction::CheckAuthorizationStatus,
+            })
+        );
+        // The server confirmed the token is dead.
+        fxa.on_authorization_status_checked(false);
+        assert_eq!(
+            fxa.get_auth_recovery_needed(),
+            Some(AuthRecoveryNeeded {
+                cause: AuthRecoveryCause::TokenRejected,
+                recommended_action: AuthRecoveryAction::Reauthenticate,
+            })
+        );
+
+        let _ = fxa.track_auth_recovery::<()>(Err(Error::MismatchedKeys));
+        assert_eq!(
+            fxa.get_auth_recovery_needed(),
+            Some(AuthRecoveryNeeded {
+                cause: AuthRecoveryCause::KeysChanged,
+                recommended_action: AuthRecoveryAction::Reauthenticate,
+            })
+        );
+        // An active token doesn't fix changed keys.
+        fxa.on_authorization_status_checked(true);
+        assert!(fxa.get_auth_recovery_needed().is_some());
+
+        fxa.set_client(Arc::new(MockFxAClient::new()));
+        fxa.disconnect();
+        assert_eq!(fxa.get_auth_recovery_needed(), None);
+    }
+
     #[test]
     fn test_get_auth_state() {
         let config = Config::new("https://stable.dev.lcip.org", "12345678", "https://foo.bar");
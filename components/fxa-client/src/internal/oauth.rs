@@ -45,7 +45,23 @@ impl FirefoxAccount {
         if scope.contains(' ') {
             return Err(Error::MultipleScopesRequested);
         }
-        if let Some(oauth_info) = self.state.get_cached_access_token(scope) {
+        self.get_access_token_for_scopes(&[scope], ttl)
+    }
+
+    /// Fetch a short-lived access token bearing all of `scopes`.
+    ///
+    /// The first scope is the one the token is for: its scoped key, if any, is attached to the
+    /// result.  The remaining scopes are claims that the service accepting the token requires
+    /// it to carry.  Tokens are cached under the space-separated list of scopes.
+    ///
+    /// **💾 This method may alter the persisted account state.**
+    pub(crate) fn get_access_token_for_scopes(
+        &mut self,
+        scopes: &[&str],
+        ttl: Option<u64>,
+    ) -> Result<AccessTokenInfo> {
+        let cache_key = scopes.join(" ");
+        if let Some(oauth_info) = self.state.get_cached_access_token(&cache_key) {
             if oauth_info.expires_at > util::now_secs() + OAUTH_MIN_TIME_LEFT {
                 // If the cached key is missing the required sync scoped key, try to fetch it again
                 if oauth_info.check_missing_sync_scoped_key().is_ok() {
@@ -55,24 +71,24 @@ impl FirefoxAccount {
         }
         let resp = match self.state.refresh_token() {
             Some(refresh_token) => {
-                if refresh_token.scopes.contains(scope) {
+                if scopes.iter().all(|s| refresh_token.scopes.contains(*s)) {
                     self.client.create_access_token_using_refresh_token(
                         self.state.config(),
                         &refresh_token.token,
                         ttl,
-                        &[scope],
+                        scopes,
                     )?
                 } else {
-                    return Err(Error::NoCachedToken(scope.to_string()));
+                    return Err(Error::NoCachedToken(cache_key));
                 }
             }
             None => match self.state.session_token() {
                 Some(session_token) => self.client.create_access_token_using_session_token(
                     self.state.config(),
                     session_token,
-                    &[scope],
+                    scopes,
                 )?,
-                None => return Err(Error::NoCachedToken(scope.to_string())),
+                None => return Err(Error::NoCachedToken(cache_key)),
             },
         };
         let since_epoch = SystemTime::now()
@@ -82,11 +98,11 @@ impl FirefoxAccount {
         let token_info = AccessTokenInfo {
             scope: resp.scope,
             token: resp.access_token,
-            key: self.state.get_scoped_key(scope).cloned(),
+            key: self.state.get_scoped_key(scopes[0]).cloned(),
             expires_at,
         };
         self.state
-            .add_cached_access_token(scope, token_info.clone());
+            .add_cached_access_token(cache_key, token_info.clone());
         token_info.check_missing_sync_scoped_key()?;
         Ok(token_info)
     }
@@ -117,6 +133,7 @@ impl FirefoxAccount {
             }
             None => return Err(Error::NoRefreshToken),
         };
+        self.on_authorization_status_checked(resp.active);
         Ok(IntrospectInfo {
             active: resp.active,
         })
@@ -417,6 +434,7 @@ impl FirefoxAccount {
             },
             resp.session_token,
         );
+        self.clear_auth_recovery_needed();
         Ok(())
     }
 
@@ -1120,4 +1138,47 @@ mod tests {
         fxa.complete_oauth_flow("mock_code", state.1.as_ref())
             .unwrap();
     }
+
+    #[test]
+    fn test_get_access_token_for_scopes() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refreshtok".to_owned(),
+            scopes: HashSet::from_iter(vec!["profile".to_owned(), "relay".to_owned()]),
+        });
+
+        let mut client = MockFxAClient::new();
+        client
+            .expect_create_access_token_using_refresh_token()
+            .withf(|_, token, _, scopes| token == "refreshtok" && *scopes == ["relay", "profile"])
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(OAuthTokenResponse {
+                    keys_jwe: None,
+                    refresh_token: None,
+                    session_token: None,
+                    expires_in: 6_000_000,
+                    scope: "relay profile".to_owned(),
+                    access_token: "relay_token".to_owned(),
+                })
+            });
+        fxa.set_client(Arc::new(client));
+
+        let token = fxa
+            .get_access_token_for_scopes(&["relay", "profile"], None)
+            .unwrap();
+        assert_eq!(token.token, "relay_token");
+        // The second request is served from the cache.
+        let token = fxa
+            .get_access_token_for_scopes(&["relay", "profile"], None)
+            .unwrap();
+        assert_eq!(token.token, "relay_token");
+
+        // The refresh token must carry every requested scope.
+        match fxa.get_access_token_for_scopes(&["vpn", "profile"], None) {
+            Err(Error::NoCachedToken(scope)) => assert_eq!(scope, "vpn profile"),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
 }
@@ -60,17 +60,22 @@ impl FirefoxAccount {
                     None => false,
                     Some(profile) => profile.response.uid == account_uid,
                 };
-                Ok(if is_local_account {
-                    AccountEvent::AccountDestroyed
-                } else {
+                if !is_local_account {
                     return Err(Error::InvalidPushEvent);
-                })
+                }
+                // The account no longer exists, so there's nothing to tell the server: just
+                // forget our tokens so that we stop making calls that can only fail.
+                self.disconnect_locally();
+                Ok(AccountEvent::AccountDestroyed)
             }
             PushPayload::PasswordChanged | PushPayload::PasswordReset => {
                 let status = self.check_authorization_status()?;
                 // clear any device or client data due to password change.
                 self.clear_devices_and_attached_clients_cache();
                 Ok(if !status.active {
+                    // Our tokens were revoked, so move to the auth issues state now rather than
+                    // waiting for the next call to fail.
+                    self.on_auth_issues();
                     AccountEvent::AccountAuthStateChanged
                 } else {
                     log::info!("Password change event, but no action required");
@@ -143,6 +148,7 @@ mod tests {
     use crate::internal::oauth::RefreshToken;
     use crate::internal::CachedResponse;
     use crate::internal::Config;
+    use crate::FxaRustAuthState;
     use mockall::predicate::always;
     use mockall::predicate::eq;
     use std::sync::Arc;
@@ -219,6 +225,33 @@ mod tests {
         let event = fxa.handle_push_message(json).unwrap();
         assert!(matches!(event, AccountEvent::AccountAuthStateChanged));
         assert!(fxa.devices_cache.is_none());
+        assert!(fxa.state.refresh_token().is_none());
+        assert_eq!(fxa.get_auth_state(), FxaRustAuthState::AuthIssues);
+        // The device id is kept so that we can reuse the device record after re-authenticating.
+        assert_eq!(fxa.get_current_device_id().unwrap(), "my_id");
+    }
+
+    #[test]
+    fn test_push_account_destroyed() {
+        let mut fxa =
+            FirefoxAccount::with_config(Config::stable_dev("12345678", "https://foo.bar"));
+        fxa.state.force_refresh_token(RefreshToken {
+            token: "refresh_token".to_owned(),
+            scopes: std::collections::HashSet::new(),
+        });
+        fxa.add_cached_profile("123", "test@example.com");
+
+        // Events for other accounts are rejected, and leave our state alone.
+        let json = "{\"version\":1,\"command\":\"fxaccounts:account_destroyed\",\"data\":{\"uid\":\"456\"}}";
+        fxa.handle_push_message(json).unwrap_err();
+        assert!(fxa.state.refresh_token().is_some());
+
+        let json = "{\"version\":1,\"command\":\"fxaccounts:account_destroyed\",\"data\":{\"uid\":\"123\"}}";
+        let event = fxa.handle_push_message(json).unwrap();
+        assert!(matches!(event, AccountEvent::AccountDestroyed));
+        assert!(fxa.state.refresh_token().is_none());
+        assert!(fxa.state.last_seen_profile().is_none());
+        assert_eq!(fxa.get_auth_state(), FxaRustAuthState::Disconnected);
     }
 
     #[test]
@@ -0,0 +1,356 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Account recovery keys.
+//!
+//! A recovery key is a random code the user writes down, which lets them keep their synced data
+//! if they forget their password. The code is never sent to the server. Instead, we derive a key
+//! from it and the account's uid, use that to encrypt kB, and store the encrypted kB (the
+//! "recovery data") on the server under a fingerprint of the code. When the user resets their
+//! password, the code gets kB back, so the account keeps its encryption keys.
+//!
+//! This follows the protocol used by the FxA web content, so recovery keys created here can be
+//! used on the web and vice versa.
+
+use super::FirefoxAccount;
+use crate::{Error, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jwcrypto::{DecryptionParameters, EncryptionAlgorithm, EncryptionParameters, Jwk};
+use rc_crypto::{digest, hkdf, hmac, rand};
+use serde_derive::{Deserialize, Serialize};
+
+// The code is 20 random bytes, which is 32 characters of Crockford's base32.
+const RECOVERY_CODE_LENGTH: usize = 20;
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+// The server rejects a recovery key it doesn't know with this errno.
+const ERRNO_RECOVERY_KEY_INVALID: u64 = 159;
+
+/// The recovery data stored on the server, before it's encrypted.
+#[derive(Serialize, Deserialize)]
+struct RecoveryData {
+    #[serde(rename = "kB")]
+    kb: String,
+}
+
+/// The keys derived from a recovery code for a particular account.
+struct RecoveryKeyMaterial {
+    // The fingerprint the server stores the recovery data under.
+    id: String,
+    encryption_key: Jwk,
+}
+
+impl RecoveryKeyMaterial {
+    fn new(code: &[u8], uid: &str) -> Result<Self> {
+        let uid = hex::decode(uid)?;
+        let id = hex::encode(derive_key(code, &uid, "fxa recovery fingerprint", 16)?);
+        let key = derive_key(code, &uid, "fxa recovery encrypt key", 32)?;
+        Ok(Self {
+            encryption_key: Jwk {
+                kid: Some(id.clone()),
+                key_parameters: jwcrypto::JwkKeyParameters::Direct {
+                    k: URL_SAFE_NO_PAD.encode(key),
+                },
+            },
+            id,
+        })
+    }
+
+    fn wrap_kb(&self, kb: &[u8]) -> Result<String> {
+        let data = serde_json::to_string(&RecoveryData {
+            kb: hex::encode(kb),
+        })?;
+        Ok(jwcrypto::encrypt_to_jwe(
+            data.as_bytes(),
+            EncryptionParameters::Direct {
+                enc: EncryptionAlgorithm::A256GCM,
+                jwk: &self.encryption_key,
+            },
+        )?)
+    }
+
+    fn unwrap_kb(&self, recovery_data: &str) -> Result<Vec<u8>> {
+        let data = jwcrypto::decrypt_jwe(
+            recovery_data,
+            DecryptionParameters::Direct {
+                jwk: self.encryption_key.clone(),
+            },
+        )
+        // The server only returns recovery data for a matching fingerprint, so this only fails
+        // if the data is corrupt.
+        .map_err(|_| Error::InvalidRecoveryKey)?;
+        let data: RecoveryData = serde_json::from_str(&data)?;
+        Ok(hex::decode(data.kb)?)
+    }
+}
+
+impl FirefoxAccount {
+    /// Check whether the user has an account recovery key.
+    pub fn has_recovery_key(&self) -> Result<bool> {
+        let session_token = self.get_session_token()?;
+        self.client
+            .check_recovery_key_exists(self.state.config(), &session_token)
+    }
+
+    /// Create a new account recovery key, which wraps `kb`, the account's kB.
+    ///
+    /// Returns the recovery key for the user to write down. It replaces any key the user already
+    /// had, but only once it's been confirmed with [`FirefoxAccount::verify_recovery_key`].
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn create_recovery_key(&mut self, kb: &[u8]) -> Result<String> {
+        let session_token = self.get_session_token()?;
+        let uid = self.get_profile(false)?.uid;
+        let mut code = [0u8; RECOVERY_CODE_LENGTH];
+        rand::fill(&mut code)?;
+        let material = RecoveryKeyMaterial::new(&code, &uid)?;
+        let recovery_data = material.wrap_kb(kb)?;
+        self.client.create_recovery_key(
+            self.state.config(),
+            &session_token,
+            &material.id,
+            &recovery_data,
+        )?;
+        Ok(encode_recovery_code(&code))
+    }
+
+    /// Check that the user wrote down the recovery key returned by
+    /// [`FirefoxAccount::create_recovery_key`], and enable it.
+    ///
+    /// Fails with [`Error::InvalidRecoveryKey`] if `recovery_key` isn't the one that was created.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    pub fn verify_recovery_key(&mut self, recovery_key: &str) -> Result<()> {
+        let session_token = self.get_session_token()?;
+        let uid = self.get_profile(false)?.uid;
+        let material = RecoveryKeyMaterial::new(&decode_recovery_code(recovery_key)?, &uid)?;
+        self.client
+            .verify_recovery_key(self.state.config(), &session_token, &material.id)
+            .map_err(invalid_recovery_key_error)
+    }
+
+    /// Use a recovery key to get back kB while resetting the password for the account `uid`.
+    ///
+    /// `account_reset_token` comes from the password reset flow. The returned kB should be
+    /// wrapped with the new password when the reset is completed, so the account keeps its
+    /// encryption keys.
+    ///
+    /// Fails with [`Error::InvalidRecoveryKey`] if the account has no such recovery key.
+    pub fn consume_recovery_key(
+        &self,
+        uid: &str,
+        recovery_key: &str,
+        account_reset_token: &str,
+    ) -> Result<Vec<u8>> {
+        let material = RecoveryKeyMaterial::new(&decode_recovery_code(recovery_key)?, uid)?;
+        let recovery_data = self
+            .client
+            .get_recovery_key_data(self.state.config(), account_reset_token, &material.id)
+            .map_err(invalid_recovery_key_error)?;
+        material.unwrap_kb(&recovery_data)
+    }
+}
+
+fn invalid_recovery_key_error(e: Error) -> Error {
+    match e {
+        Error::RemoteError {
+            code: 400 | 404,
+            errno: ERRNO_RECOVERY_KEY_INVALID,
+            ..
+        } => Error::InvalidRecoveryKey,
+        e => e,
+    }
+}
+
+fn derive_key(code: &[u8], uid: &[u8], info: &str, len: usize) -> Result<Vec<u8>> {
+    let salt = hmac::SigningKey::new(&digest::SHA256, uid);
+    let mut out = vec![0u8; len];
+    hkdf::extract_and_expand(&salt, code, info.as_bytes(), &mut out)?;
+    Ok(out)
+}
+
+fn encode_recovery_code(code: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in code {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(CROCKFORD_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(CROCKFORD_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Decodes a recovery key as the user typed it. Crockford's base32 is case-insensitive, and
+/// treats the characters which are easily confused with digits as those digits. Spaces and
+/// dashes, which users might add to group the characters, are ignored.
+fn decode_recovery_code(recovery_key: &str) -> Result<Vec<u8>> {
+    let mut code = Vec::with_capacity(RECOVERY_CODE_LENGTH);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in recovery_key.chars().filter(|c| !matches!(c, ' ' | '-')) {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let value = CROCKFORD_ALPHABET
+            .iter()
+            .position(|&a| a as char == c)
+            .ok_or(Error::InvalidRecoveryKey)?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            code.push((buffer >> bits) as u8);
+        }
+    }
+    if code.len() != RECOVERY_CODE_LENGTH {
+        return Err(Error::InvalidRecoveryKey);
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{config::Config, http_client::MockFxAClient};
+    use mockall::predicate::{always, eq};
+    use std::sync::{Arc, Mutex};
+
+    const UID: &str = "123456789abcdef0123456789abcdef0";
+
+    #[test]
+    fn test_recovery_code_encoding() {
+        let code: Vec<u8> = (0..RECOVERY_CODE_LENGTH as u8).collect();
+        let encoded = encode_recovery_code(&code);
+        assert_eq!(encoded.len(), 32);
+        assert_eq!(decode_recovery_code(&encoded).unwrap(), code);
+        // Users may type it in lower case, grouped, and with confusable characters.
+        let typed = encoded
+            .to_lowercase()
+            .replace('0', "o")
+            .replace('1', "l")
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(decode_recovery_code(&typed).unwrap(), code);
+        assert!(matches!(
+            decode_recovery_code(&encoded[1..]),
+            Err(Error::InvalidRecoveryKey)
+        ));
+        assert!(matches!(
+            decode_recovery_code(&encoded.replace('2', "U")),
+            Err(Error::InvalidRecoveryKey)
+        ));
+    }
+
+    #[test]
+    fn test_wrap_and_unwrap_kb() {
+        let code = [7u8; RECOVERY_CODE_LENGTH];
+        let kb = [42u8; 32];
+        let material = RecoveryKeyMaterial::new(&code, UID).unwrap();
+        let recovery_data = material.wrap_kb(&kb).unwrap();
+        assert_eq!(material.unwrap_kb(&recovery_data).unwrap(), kb);
+        // Another account's key can't unwrap it, and has a different fingerprint.
+        let other = RecoveryKeyMaterial::new(&code, "00000000000000000000000000000000").unwrap();
+        assert_ne!(other.id, material.id);
+        assert!(matches!(
+            other.unwrap_kb(&recovery_data),
+            Err(Error::InvalidRecoveryKey)
+        ));
+    }
+
+    #[test]
+    fn test_has_recovery_key() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        // No session token -> Error.
+        assert!(matches!(
+            fxa.has_recovery_key().unwrap_err(),
+            Error::NoSessionToken
+        ));
+
+        fxa.set_session_token("session");
+        let mut client = MockFxAClient::new();
+        client
+            .expect_check_recovery_key_exists()
+            .with(always(), eq("session"))
+            .times(1)
+            .returning(|_, _| Ok(true));
+        fxa.set_client(Arc::new(client));
+        assert!(fxa.has_recovery_key().unwrap());
+    }
+
+    #[test]
+    fn test_create_verify_and_consume_recovery_key() {
+        let config = Config::stable_dev("12345678", "https://foo.bar");
+        let mut fxa = FirefoxAccount::with_config(config);
+        fxa.set_session_token("session");
+        fxa.add_cached_profile(UID, "test@example.com");
+
+        let kb = [42u8; 32];
+        let stored = Arc::new(Mutex::new(None::<(String, String)>));
+        let mut client = MockFxAClient::new();
+        let stored_by_create = Arc::clone(&stored);
+        client
+            .expect_create_recovery_key()
+            .with(always(), eq("session"), always(), always())
+            .times(1)
+            .returning(move |_, _, id, data| {
+                *stored_by_create.lock().unwrap() = Some((id.to_string(), data.to_string()));
+                Ok(())
+            });
+        let stored_by_verify = Arc::clone(&stored);
+        client
+            .expect_verify_recovery_key()
+            .with(always(), eq("session"), always())
+            .times(2)
+            .returning(move |_, _, id| {
+                if stored_by_verify.lock().unwrap().as_ref().unwrap().0 == id {
+                    Ok(())
+                } else {
+                    Err(Error::RemoteError {
+                        code: 400,
+                        errno: ERRNO_RECOVERY_KEY_INVALID,
+                        error: "Bad Request".to_string(),
+                        message: "Recovery key is not valid".to_string(),
+                        info: String::new(),
+                    })
+                }
+            });
+        let stored_by_get = Arc::clone(&stored);
+        client
+            .expect_get_recovery_key_data()
+            .with(always(), eq("reset-token"), always())
+            .times(1)
+            .returning(move |_, _, id| {
+                let stored = stored_by_get.lock().unwrap();
+                let (stored_id, data) = stored.as_ref().unwrap();
+                assert_eq!(stored_id, id);
+                Ok(data.clone())
+            });
+        fxa.set_client(Arc::new(client));
+
+        let recovery_key = fxa.create_recovery_key(&kb).unwrap();
+        assert!(matches!(
+            fxa.verify_recovery_key(&encode_recovery_code(&[0u8; RECOVERY_CODE_LENGTH])),
+            Err(Error::InvalidRecoveryKey)
+        ));
+        fxa.verify_recovery_key(&recovery_key.to_lowercase())
+            .unwrap();
+        assert_eq!(
+            fxa.consume_recovery_key(UID, &recovery_key, "reset-token")
+                .unwrap(),
+            kb
+        );
+    }
+}
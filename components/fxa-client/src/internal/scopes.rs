@@ -4,3 +4,5 @@
 
 pub const PROFILE: &str = "profile";
 pub const OLD_SYNC: &str = "https://identity.mozilla.com/apps/oldsync";
+pub const RELAY: &str = "https://identity.mozilla.com/apps/relay";
+pub const VPN: &str = "https://identity.mozilla.com/apps/vpn";
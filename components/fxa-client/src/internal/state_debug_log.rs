@@ -0,0 +1,263 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An opt-in, in-memory log of changes to the persisted account state.
+//!
+//! This exists to help diagnose reports of users being unexpectedly logged out. Each entry
+//! records which operation changed the state and which fields it changed, but never their
+//! values: fields are only described as set or unset.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{util, PersistedState};
+use crate::{PersistedStateField, StateDebugLogEntry, StateFieldChange};
+
+/// The maximum number of entries we keep. Older entries are dropped.
+const MAX_ENTRIES: usize = 100;
+
+/// The fields of the persisted state, as JSON values so that they can be compared. These are
+/// only held for the duration of a single state mutation.
+pub(crate) struct StateSnapshot(Vec<(PersistedStateField, Value)>);
+
+impl StateSnapshot {
+    pub(crate) fn new(state: &PersistedState) -> Self {
+        // Capabilities are stored in a `HashSet`, so sort them to make the comparison
+        // independent of the iteration order.
+        let mut device_capabilities: Vec<_> = state
+            .device_capabilities
+            .iter()
+            .map(|c| format!("{c:?}"))
+            .collect();
+        device_capabilities.sort();
+        Self(vec![
+            (
+                PersistedStateField::CurrentDeviceId,
+                to_value(&state.current_device_id),
+            ),
+            (
+                PersistedStateField::RefreshToken,
+                to_value(&state.refresh_token),
+            ),
+            (
+                PersistedStateField::ScopedKeys,
+                to_value(&state.scoped_keys),
+            ),
+            (
+                PersistedStateField::LastHandledCommand,
+                to_value(&state.last_handled_command),
+            ),
+            (
+                PersistedStateField::CommandsData,
+                to_value(&state.commands_data),
+            ),
+            (
+                PersistedStateField::DeviceCapabilities,
+                to_value(&device_capabilities),
+            ),
+            (
+                PersistedStateField::AccessTokenCache,
+                to_value(&state.access_token_cache),
+            ),
+            (
+                PersistedStateField::SessionToken,
+                to_value(&state.session_token),
+            ),
+            (
+                PersistedStateField::LastSeenProfile,
+                to_value(&state.last_seen_profile),
+            ),
+            (
+                PersistedStateField::ServerLocalDeviceInfo,
+                to_value(&state.server_local_device_info),
+            ),
+            (
+                PersistedStateField::LoggedOutFromAuthIssues,
+                to_value(&state.logged_out_from_auth_issues),
+            ),
+        ])
+    }
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    // None of the state's fields can fail to serialize, since the whole state is serialized
+    // the same way when it's persisted.
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// Whether a field has a value, as opposed to being empty or unset.
+fn is_set(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+        Value::Number(_) | Value::String(_) => true,
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct StateDebugLog {
+    entries: VecDeque<StateDebugLogEntry>,
+}
+
+impl StateDebugLog {
+    /// Record the changes `operation` made between the `before` and `after` snapshots. Nothing
+    /// is recorded if the operation didn't change anything.
+    pub(crate) fn record(&mut self, operation: &str, before: StateSnapshot, after: StateSnapshot) {
+        let changes: Vec<_> = before
+            .0
+            .into_iter()
+            .zip(after.0)
+            .filter(|((_, before), (_, after))| before != after)
+            .map(|((field, before), (_, after))| StateFieldChange {
+                field,
+                was_set: is_set(&before),
+                is_set: is_set(&after),
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StateDebugLogEntry {
+            timestamp: util::now() as i64,
+            operation: operation.to_string(),
+            changes,
+        });
+    }
+
+    pub(crate) fn entries(&self) -> Vec<StateDebugLogEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{oauth::RefreshToken, Config};
+    use crate::DeviceCapability;
+    use std::collections::{HashMap, HashSet};
+
+    fn new_state() -> PersistedState {
+        PersistedState {
+            config: Config::stable_dev("12345678", "https://foo.bar"),
+            refresh_token: None,
+            scoped_keys: HashMap::new(),
+            last_handled_command: None,
+            commands_data: HashMap::new(),
+            device_capabilities: HashSet::new(),
+            server_local_device_info: None,
+            session_token: None,
+            current_device_id: None,
+            last_seen_profile: None,
+            access_token_cache: HashMap::new(),
+            logged_out_from_auth_issues: false,
+        }
+    }
+
+    #[test]
+    fn test_record_changes() {
+        let mut log = StateDebugLog::default();
+        let mut state = new_state();
+
+        let before = StateSnapshot::new(&state);
+        state.refresh_token = Some(RefreshToken {
+            token: "refresh-token".to_string(),
+            scopes: HashSet::new(),
+        });
+        state.session_token = Some("session-token".to_string());
+        log.record("complete_oauth_flow", before, StateSnapshot::new(&state));
+
+        let before = StateSnapshot::new(&state);
+        state.session_token = Some("another-session-token".to_string());
+        log.record("update_tokens", before, StateSnapshot::new(&state));
+
+        // Operations that don't change anything aren't recorded.
+        let before = StateSnapshot::new(&state);
+        log.record("disconnect", before, StateSnapshot::new(&state));
+
+        let before = StateSnapshot::new(&state);
+        state.refresh_token = None;
+        state.logged_out_from_auth_issues = true;
+        log.record("on_auth_issues", before, StateSnapshot::new(&state));
+
+        let entries = log.entries();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.operation.as_str(),
+                    e.changes
+                        .iter()
+                        .map(|c| (c.field, c.was_set, c.is_set))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "complete_oauth_flow",
+                    vec![
+                        (PersistedStateField::RefreshToken, false, true),
+                        (PersistedStateField::SessionToken, false, true),
+                    ]
+                ),
+                (
+                    "update_tokens",
+                    vec![(PersistedStateField::SessionToken, true, true)]
+                ),
+                (
+                    "on_auth_issues",
+                    vec![
+                        (PersistedStateField::RefreshToken, true, false),
+                        (PersistedStateField::LoggedOutFromAuthIssues, false, true),
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capability_order_ignored() {
+        let mut state = new_state();
+        state.device_capabilities =
+            HashSet::from([DeviceCapability::SendTab, DeviceCapability::CloseTabs]);
+        let before = StateSnapshot::new(&state);
+        state.device_capabilities =
+            HashSet::from([DeviceCapability::CloseTabs, DeviceCapability::SendTab]);
+        let mut log = StateDebugLog::default();
+        log.record(
+            "set_device_capabilities",
+            before,
+            StateSnapshot::new(&state),
+        );
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn test_bounded() {
+        let mut log = StateDebugLog::default();
+        let mut state = new_state();
+        for i in 0..(MAX_ENTRIES + 10) {
+            let before = StateSnapshot::new(&state);
+            state.last_handled_command = Some(i as u64);
+            log.record(
+                "set_last_handled_command_index",
+                before,
+                StateSnapshot::new(&state),
+            );
+        }
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        // The first entry, when the field was first set, has been dropped.
+        assert!(entries[0].changes[0].was_set);
+    }
+}
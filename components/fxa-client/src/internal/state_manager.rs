@@ -8,10 +8,11 @@ use crate::{
     internal::{
         oauth::{AccessTokenInfo, RefreshToken},
         profile::Profile,
+        state_debug_log::{StateDebugLog, StateSnapshot},
         state_persistence::state_to_json,
         CachedResponse, Config, OAuthFlow, PersistedState,
     },
-    DeviceCapability, FxaRustAuthState, LocalDevice, Result, ScopedKey,
+    DeviceCapability, FxaRustAuthState, LocalDevice, Result, ScopedKey, StateDebugLogEntry,
 };
 
 /// Stores and manages the current state of the FxA client
@@ -23,6 +24,8 @@ pub struct StateManager {
     persisted_state: PersistedState,
     /// In-progress OAuth flows
     flow_store: HashMap<String, OAuthFlow>,
+    /// Changes to `persisted_state`, if the application has asked us to record them
+    debug_log: Option<StateDebugLog>,
 }
 
 impl StateManager {
@@ -30,9 +33,42 @@ impl StateManager {
         Self {
             persisted_state,
             flow_store: HashMap::new(),
+            debug_log: None,
         }
     }
 
+    /// Mutate the persisted state, recording the changes in the debug log if it's enabled.
+    ///
+    /// All changes to `persisted_state` should go through this, named after the public
+    /// `StateManager` method making them.
+    fn mutate<T>(&mut self, operation: &str, f: impl FnOnce(&mut PersistedState) -> T) -> T {
+        let before = self
+            .debug_log
+            .as_ref()
+            .map(|_| StateSnapshot::new(&self.persisted_state));
+        let result = f(&mut self.persisted_state);
+        if let (Some(debug_log), Some(before)) = (self.debug_log.as_mut(), before) {
+            debug_log.record(operation, before, StateSnapshot::new(&self.persisted_state));
+        }
+        result
+    }
+
+    /// Start or stop recording changes to the persisted state. Stopping discards the log.
+    pub fn set_debug_log_enabled(&mut self, enabled: bool) {
+        match (enabled, &self.debug_log) {
+            (true, None) => self.debug_log = Some(StateDebugLog::default()),
+            (false, Some(_)) => self.debug_log = None,
+            _ => (),
+        }
+    }
+
+    pub fn debug_log(&self) -> Vec<StateDebugLogEntry> {
+        self.debug_log
+            .as_ref()
+            .map(StateDebugLog::entries)
+            .unwrap_or_default()
+    }
+
     pub fn serialize_persisted_state(&self) -> Result<String> {
         state_to_json(&self.persisted_state)
     }
@@ -61,7 +97,9 @@ impl StateManager {
         &mut self,
         capabilities_set: impl IntoIterator<Item = DeviceCapability>,
     ) {
-        self.persisted_state.device_capabilities = HashSet::from_iter(capabilities_set);
+        self.mutate("set_device_capabilities", |state| {
+            state.device_capabilities = HashSet::from_iter(capabilities_set)
+        });
     }
 
     /// Get the last known LocalDevice info sent back from the server
@@ -71,7 +109,9 @@ impl StateManager {
 
     /// Update the last known LocalDevice info when getting one back from the server
     pub fn update_server_local_device_info(&mut self, local_device: LocalDevice) {
-        self.persisted_state.server_local_device_info = Some(local_device)
+        self.mutate("update_server_local_device_info", |state| {
+            state.server_local_device_info = Some(local_device)
+        })
     }
 
     /// Clear out the last known LocalDevice info. This means that the next call to
@@ -80,7 +120,9 @@ impl StateManager {
     /// This is typically called when something may invalidate the server's knowledge of our
     /// local device capabilities, for example replacing our device info.
     pub fn clear_server_local_device_info(&mut self) {
-        self.persisted_state.server_local_device_info = None
+        self.mutate("clear_server_local_device_info", |state| {
+            state.server_local_device_info = None
+        })
     }
 
     pub fn get_commands_data(&self, key: &str) -> Option<&str> {
@@ -91,13 +133,15 @@ impl StateManager {
     }
 
     pub fn set_commands_data(&mut self, key: &str, data: String) {
-        self.persisted_state
-            .commands_data
-            .insert(key.to_string(), data);
+        self.mutate("set_commands_data", |state| {
+            state.commands_data.insert(key.to_string(), data)
+        });
     }
 
     pub fn clear_commands_data(&mut self, key: &str) {
-        self.persisted_state.commands_data.remove(key);
+        self.mutate("clear_commands_data", |state| {
+            state.commands_data.remove(key)
+        });
     }
 
     pub fn last_handled_command_index(&self) -> Option<u64> {
@@ -105,7 +149,9 @@ impl StateManager {
     }
 
     pub fn set_last_handled_command_index(&mut self, idx: u64) {
-        self.persisted_state.last_handled_command = Some(idx)
+        self.mutate("set_last_handled_command_index", |state| {
+            state.last_handled_command = Some(idx)
+        })
     }
 
     pub fn current_device_id(&self) -> Option<&str> {
@@ -113,7 +159,9 @@ impl StateManager {
     }
 
     pub fn set_current_device_id(&mut self, device_id: String) {
-        self.persisted_state.current_device_id = Some(device_id);
+        self.mutate("set_current_device_id", |state| {
+            state.current_device_id = Some(device_id)
+        });
     }
 
     pub fn get_scoped_key(&self, scope: &str) -> Option<&ScopedKey> {
@@ -125,11 +173,15 @@ impl StateManager {
     }
 
     pub(crate) fn set_last_seen_profile(&mut self, profile: CachedResponse<Profile>) {
-        self.persisted_state.last_seen_profile = Some(profile)
+        self.mutate("set_last_seen_profile", |state| {
+            state.last_seen_profile = Some(profile)
+        })
     }
 
     pub fn clear_last_seen_profile(&mut self) {
-        self.persisted_state.last_seen_profile = None
+        self.mutate("clear_last_seen_profile", |state| {
+            state.last_seen_profile = None
+        })
     }
 
     pub fn get_cached_access_token(&mut self, scope: &str) -> Option<&AccessTokenInfo> {
@@ -137,13 +189,15 @@ impl StateManager {
     }
 
     pub fn add_cached_access_token(&mut self, scope: impl Into<String>, token: AccessTokenInfo) {
-        self.persisted_state
-            .access_token_cache
-            .insert(scope.into(), token);
+        self.mutate("add_cached_access_token", |state| {
+            state.access_token_cache.insert(scope.into(), token)
+        });
     }
 
     pub fn clear_access_token_cache(&mut self) {
-        self.persisted_state.access_token_cache.clear()
+        self.mutate("clear_access_token_cache", |state| {
+            state.access_token_cache.clear()
+        })
     }
 
     /// Begin an OAuth flow.  This saves the OAuthFlow for later.  `state` must be unique to this
@@ -167,36 +221,40 @@ impl StateManager {
         refresh_token: RefreshToken,
         new_session_token: Option<String>,
     ) {
-        // When our keys change, we might need to re-register device capabilities with the server.
-        // Ensure that this happens on the next call to ensure_capabilities.
-        self.clear_server_local_device_info();
-
-        for (scope, key) in scoped_keys {
-            self.persisted_state.scoped_keys.insert(scope, key);
-        }
-        self.persisted_state.refresh_token = Some(refresh_token);
-        // We prioritize the existing session token if we already have one, because we might have
-        // acquired a session token before the oauth flow
-        if let (None, Some(new_session_token)) = (self.session_token(), new_session_token) {
-            self.set_session_token(new_session_token)
-        }
-        self.persisted_state.logged_out_from_auth_issues = false;
+        self.mutate("complete_oauth_flow", |state| {
+            // When our keys change, we might need to re-register device capabilities with the
+            // server. Ensure that this happens on the next call to ensure_capabilities.
+            state.server_local_device_info = None;
+
+            for (scope, key) in scoped_keys {
+                state.scoped_keys.insert(scope, key);
+            }
+            state.refresh_token = Some(refresh_token);
+            // We prioritize the existing session token if we already have one, because we might
+            // have acquired a session token before the oauth flow
+            if state.session_token.is_none() {
+                state.session_token = new_session_token;
+            }
+            state.logged_out_from_auth_issues = false;
+        });
         self.flow_store.clear();
     }
 
     /// Called when the account is disconnected.  This clears most of the auth state, but keeps
     /// some information in order to eventually reconnect to the same user account later.
     pub fn disconnect(&mut self) {
-        self.persisted_state.current_device_id = None;
-        self.persisted_state.refresh_token = None;
-        self.persisted_state.scoped_keys = HashMap::new();
-        self.persisted_state.last_handled_command = None;
-        self.persisted_state.commands_data = HashMap::new();
-        self.persisted_state.access_token_cache = HashMap::new();
-        self.persisted_state.device_capabilities = HashSet::new();
-        self.persisted_state.server_local_device_info = None;
-        self.persisted_state.session_token = None;
-        self.persisted_state.logged_out_from_auth_issues = false;
+        self.mutate("disconnect", |state| {
+            state.current_device_id = None;
+            state.refresh_token = None;
+            state.scoped_keys = HashMap::new();
+            state.last_handled_command = None;
+            state.commands_data = HashMap::new();
+            state.access_token_cache = HashMap::new();
+            state.device_capabilities = HashSet::new();
+            state.server_local_device_info = None;
+            state.session_token = None;
+            state.logged_out_from_auth_issues = false;
+        });
         self.flow_store.clear();
     }
 
@@ -210,13 +268,15 @@ impl StateManager {
     ///   * `device_capabilities`
     ///   * `last_handled_command`
     pub fn on_auth_issues(&mut self) {
-        self.persisted_state.refresh_token = None;
-        self.persisted_state.scoped_keys = HashMap::new();
-        self.persisted_state.commands_data = HashMap::new();
-        self.persisted_state.access_token_cache = HashMap::new();
-        self.persisted_state.server_local_device_info = None;
-        self.persisted_state.session_token = None;
-        self.persisted_state.logged_out_from_auth_issues = true;
+        self.mutate("on_auth_issues", |state| {
+            state.refresh_token = None;
+            state.scoped_keys = HashMap::new();
+            state.commands_data = HashMap::new();
+            state.access_token_cache = HashMap::new();
+            state.server_local_device_info = None;
+            state.session_token = None;
+            state.logged_out_from_auth_issues = true;
+        });
         self.flow_store.clear();
     }
 
@@ -226,11 +286,13 @@ impl StateManager {
     /// particular, it clears the session token to avoid
     /// https://bugzilla.mozilla.org/show_bug.cgi?id=1887071.
     pub fn on_begin_oauth(&mut self) {
-        self.persisted_state.refresh_token = None;
-        self.persisted_state.scoped_keys = HashMap::new();
-        self.persisted_state.commands_data = HashMap::new();
-        self.persisted_state.access_token_cache = HashMap::new();
-        self.persisted_state.session_token = None;
+        self.mutate("on_begin_oauth", |state| {
+            state.refresh_token = None;
+            state.scoped_keys = HashMap::new();
+            state.commands_data = HashMap::new();
+            state.access_token_cache = HashMap::new();
+            state.session_token = None;
+        });
     }
 
     pub fn get_auth_state(&self) -> FxaRustAuthState {
@@ -248,27 +310,35 @@ impl StateManager {
     /// This method updates the token data and clears out data that may be invalidated with the
     /// token changes.
     pub fn update_tokens(&mut self, session_token: String, refresh_token: RefreshToken) {
-        self.persisted_state.session_token = Some(session_token);
-        self.persisted_state.refresh_token = Some(refresh_token);
-        self.persisted_state.access_token_cache.clear();
-        self.persisted_state.server_local_device_info = None;
+        self.mutate("update_tokens", |state| {
+            state.session_token = Some(session_token);
+            state.refresh_token = Some(refresh_token);
+            state.access_token_cache.clear();
+            state.server_local_device_info = None;
+        });
     }
 
     /// Used by the application to test auth token issues
     pub fn simulate_temporary_auth_token_issue(&mut self) {
-        for (_, access_token) in self.persisted_state.access_token_cache.iter_mut() {
-            "invalid-data".clone_into(&mut access_token.token)
-        }
+        self.mutate("simulate_temporary_auth_token_issue", |state| {
+            for (_, access_token) in state.access_token_cache.iter_mut() {
+                "invalid-data".clone_into(&mut access_token.token)
+            }
+        });
     }
 
     /// Used by the application to test auth token issues
     pub fn simulate_permanent_auth_token_issue(&mut self) {
-        self.persisted_state.session_token = None;
-        self.persisted_state.refresh_token = None;
-        self.persisted_state.access_token_cache.clear();
+        self.mutate("simulate_permanent_auth_token_issue", |state| {
+            state.session_token = None;
+            state.refresh_token = None;
+            state.access_token_cache.clear();
+        });
     }
     pub fn set_session_token(&mut self, token: String) {
-        self.persisted_state.session_token = Some(token)
+        self.mutate("set_session_token", |state| {
+            state.session_token = Some(token)
+        })
     }
 }
 
@@ -29,7 +29,9 @@
 //! For an example how the conversion works, [we can look at `StateV1` which was deliberately removed](https://github.com/mozilla/application-services/issues/3912)
 //! The code that was deleted demonstrates how we can implement the migration
 
+use serde::de::DeserializeOwned;
 use serde_derive::*;
+use serde_json::{Map, Value};
 use std::collections::{HashMap, HashSet};
 
 use super::{
@@ -38,7 +40,7 @@ use super::{
     profile::Profile,
     CachedResponse, Result,
 };
-use crate::{DeviceCapability, LocalDevice, ScopedKey};
+use crate::{DeviceCapability, LocalDevice, PersistedStateField, ScopedKey};
 
 // These are the public API for working with the persisted state.
 
@@ -51,6 +53,27 @@ pub(crate) fn state_from_json(data: &str) -> Result<PersistedState> {
     upgrade_state(stored_state)
 }
 
+/// Parse a `State` from a JSON string like `state_from_json`, but if the data is corrupt, salvage
+/// what we can from it. Returns the state and the fields we couldn't recover, which are reset to
+/// their defaults.
+///
+/// We can't do without the config, so if that's lost we return the original error.
+pub(crate) fn state_from_json_with_recovery(
+    data: &str,
+) -> Result<(PersistedState, Vec<PersistedStateField>)> {
+    let err = match state_from_json(data) {
+        Ok(state) => return Ok((state, Vec::new())),
+        Err(e) => e,
+    };
+    match salvage_state(data) {
+        Some((state, lost)) => {
+            log::warn!("Recovered corrupt account state ({err}), lost: {lost:?}");
+            Ok((state, lost))
+        }
+        None => Err(err),
+    }
+}
+
 /// Serialize a `State` to a JSON string.
 ///
 pub(crate) fn state_to_json(state: &PersistedState) -> Result<String> {
@@ -64,6 +87,109 @@ fn upgrade_state(in_state: PersistedStateTagged) -> Result<PersistedState> {
     }
 }
 
+fn salvage_state(data: &str) -> Option<(PersistedState, Vec<PersistedStateField>)> {
+    let (fields, truncated) = salvage_top_level_object(data)?;
+    if fields.get("schema_version").and_then(Value::as_str) != Some("V2") {
+        return None;
+    }
+    let mut salvager = Salvager {
+        fields,
+        truncated,
+        lost: Vec::new(),
+    };
+    let config = serde_json::from_value(salvager.fields.remove("config")?).ok()?;
+    let state = StateV2 {
+        config,
+        current_device_id: salvager.take("current_device_id", PersistedStateField::CurrentDeviceId),
+        refresh_token: salvager.take("refresh_token", PersistedStateField::RefreshToken),
+        scoped_keys: salvager.take("scoped_keys", PersistedStateField::ScopedKeys),
+        last_handled_command: salvager.take(
+            "last_handled_command",
+            PersistedStateField::LastHandledCommand,
+        ),
+        commands_data: salvager.take("commands_data", PersistedStateField::CommandsData),
+        device_capabilities: salvager.take(
+            "device_capabilities",
+            PersistedStateField::DeviceCapabilities,
+        ),
+        access_token_cache: salvager
+            .take("access_token_cache", PersistedStateField::AccessTokenCache),
+        session_token: salvager.take("session_token", PersistedStateField::SessionToken),
+        last_seen_profile: salvager.take("last_seen_profile", PersistedStateField::LastSeenProfile),
+        server_local_device_info: salvager.take(
+            "server_local_device_info",
+            PersistedStateField::ServerLocalDeviceInfo,
+        ),
+        logged_out_from_auth_issues: salvager.take(
+            "logged_out_from_auth_issues",
+            PersistedStateField::LoggedOutFromAuthIssues,
+        ),
+    };
+    Some((state, salvager.lost))
+}
+
+/// Returns the members of the top-level JSON object in `data`, and whether it was damaged.
+///
+/// If `data` isn't valid JSON, we assume it was truncated or corrupted partway through, and
+/// return the members before the damage. Members which were only partly written are dropped
+/// rather than partly restored.
+fn salvage_top_level_object(data: &str) -> Option<(Map<String, Value>, bool)> {
+    if let Ok(Value::Object(fields)) = serde_json::from_str(data) {
+        return Some((fields, false));
+    }
+    // Find the commas between the members of the top-level object, then find the longest run of
+    // members that parses.
+    let mut member_ends = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in data.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            ',' if depth == 1 => member_ends.push(i),
+            _ => (),
+        }
+    }
+    member_ends.into_iter().rev().find_map(|end| {
+        match serde_json::from_str(&format!("{}}}", &data[..end])) {
+            Ok(Value::Object(fields)) => Some((fields, true)),
+            _ => None,
+        }
+    })
+}
+
+struct Salvager {
+    fields: Map<String, Value>,
+    truncated: bool,
+    lost: Vec<PersistedStateField>,
+}
+
+impl Salvager {
+    /// Takes the value of a field, or its default if we couldn't recover it.
+    fn take<T: DeserializeOwned + Default>(&mut self, name: &str, field: PersistedStateField) -> T {
+        match self.fields.remove(name).map(serde_json::from_value) {
+            Some(Ok(value)) => value,
+            // A field missing from intact data is one we didn't write, which is fine.
+            None if !self.truncated => T::default(),
+            _ => {
+                self.lost.push(field);
+                T::default()
+            }
+        }
+    }
+}
+
 /// `PersistedStateTagged` is a tagged container for one of the state versions.
 /// Serde picks the right `StructVX` to deserialized based on the schema_version tag.
 ///
@@ -148,4 +274,73 @@ mod tests {
         );
         assert_eq!(state.access_token_cache.len(), 0);
     }
+
+    // A snapshot of intact state, with each field set.
+    const STATE_JSON: &str = r#"{"schema_version":"V2","config":{"content_url":"https://accounts.firefox.com","token_server_url_override":null,"client_id":"98adfa37698f255b","redirect_uri":"https://lockbox.firefox.com/fxa/ios-redirect.html"},"current_device_id":"device-id","refresh_token":{"token":"bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188","scopes":["https://identity.mozilla.com/apps/oldsync"]},"scoped_keys":{"https://identity.mozilla.com/apps/oldsync":{"kty":"oct","scope":"https://identity.mozilla.com/apps/oldsync","k":"kMtwpVC0ZaYFJymPza8rXK_0CgCp3KMwRStwGfBRBDtL6hXRDVJgQFaoOQ2dimw0Bko5WVv2gNTy7RX5zFYZHg","kid":"1542236016429-Ox1FbJfFfwTe5t-xq4v2hQ"}},"last_handled_command":3,"commands_data":{},"device_capabilities":[],"access_token_cache":{},"session_token":"abcd\"1234","last_seen_profile":null,"server_local_device_info":null,"logged_out_from_auth_issues":false}"#;
+
+    #[test]
+    fn test_recovery_of_intact_state() {
+        let (state, lost) = state_from_json_with_recovery(STATE_JSON).unwrap();
+        assert_eq!(lost, vec![]);
+        assert_eq!(state.current_device_id.as_deref(), Some("device-id"));
+        assert_eq!(state.session_token.as_deref(), Some("abcd\"1234"));
+    }
+
+    #[test]
+    fn test_recovery_of_truncated_state() {
+        // Truncate the state partway through the scoped keys.
+        let truncated = &STATE_JSON[..STATE_JSON.find("\"kty\"").unwrap()];
+        assert!(state_from_json(truncated).is_err());
+        let (state, lost) = state_from_json_with_recovery(truncated).unwrap();
+        assert_eq!(state.config.client_id, "98adfa37698f255b");
+        assert_eq!(state.current_device_id.as_deref(), Some("device-id"));
+        assert_eq!(
+            state.refresh_token.unwrap().token,
+            "bed5532f4fea7e39c5c4f609f53603ee7518fd1c103cc4034da3618f786ed188"
+        );
+        // A partly-written field is dropped, rather than partly restored.
+        assert!(state.scoped_keys.is_empty());
+        assert_eq!(
+            lost,
+            vec![
+                PersistedStateField::ScopedKeys,
+                PersistedStateField::LastHandledCommand,
+                PersistedStateField::CommandsData,
+                PersistedStateField::DeviceCapabilities,
+                PersistedStateField::AccessTokenCache,
+                PersistedStateField::SessionToken,
+                PersistedStateField::LastSeenProfile,
+                PersistedStateField::ServerLocalDeviceInfo,
+                PersistedStateField::LoggedOutFromAuthIssues,
+            ]
+        );
+
+        // Truncating inside a string containing an escaped quote.
+        let truncated = &STATE_JSON[..STATE_JSON.find("1234").unwrap()];
+        let (state, lost) = state_from_json_with_recovery(truncated).unwrap();
+        assert_eq!(state.scoped_keys.len(), 1);
+        assert_eq!(state.session_token, None);
+        assert_eq!(lost[0], PersistedStateField::SessionToken);
+    }
+
+    #[test]
+    fn test_recovery_of_invalid_field() {
+        let corrupt = STATE_JSON.replace(
+            r#""last_handled_command":3"#,
+            r#""last_handled_command":"x""#,
+        );
+        assert!(state_from_json(&corrupt).is_err());
+        let (state, lost) = state_from_json_with_recovery(&corrupt).unwrap();
+        assert_eq!(lost, vec![PersistedStateField::LastHandledCommand]);
+        assert_eq!(state.last_handled_command, None);
+        assert_eq!(state.scoped_keys.len(), 1);
+    }
+
+    #[test]
+    fn test_recovery_needs_config() {
+        let truncated = &STATE_JSON[..STATE_JSON.find("client_id").unwrap()];
+        assert!(state_from_json_with_recovery(truncated).is_err());
+        assert!(state_from_json_with_recovery("").is_err());
+        assert!(state_from_json_with_recovery("not json").is_err());
+    }
 }
@@ -24,8 +24,9 @@ pub fn now_secs() -> u64 {
 
 /// Gets unix timestamp at `days` days ago
 pub fn past_timestamp(days: u64) -> u64 {
-    // 1000 milliseconds, 60 seconds, 60 minutes, 24 hours
-    now() - (1000 * 60 * 60 * 24 * days)
+    // 1000 milliseconds, 60 seconds, 60 minutes, 24 hours. Clamp to the epoch rather than
+    // overflowing when `days` is huge.
+    now().saturating_sub(days.saturating_mul(1000 * 60 * 60 * 24))
 }
 
 pub fn random_base64_url_string(len: usize) -> Result<String> {
@@ -53,18 +53,29 @@ use std::fmt;
 pub use sync15::DeviceType;
 use url::Url;
 
-pub use auth::{AuthorizationInfo, FxaEvent, FxaRustAuthState, FxaState, UserData};
+pub use auth::{
+    AuthRecoveryAction, AuthRecoveryCause, AuthRecoveryNeeded, AuthorizationInfo, FxaEvent,
+    FxaRustAuthState, FxaState, UserData,
+};
 pub use device::{
-    AttachedClient, CloseTabsResult, Device, DeviceCapability, DeviceConfig, LocalDevice,
+    filter_inactive_devices, AttachedClient, CloseTabsResult, Device, DeviceCapability,
+    DeviceConfig, LocalDevice,
 };
 pub use error::{Error, FxaError};
+use error_support::handle_error;
 use parking_lot::Mutex;
 pub use profile::Profile;
 pub use push::{
     AccountEvent, CloseTabsPayload, DevicePushSubscription, IncomingDeviceCommand, SendTabPayload,
     TabHistoryEntry,
 };
-pub use token::{AccessTokenInfo, AuthorizationParameters, ScopedKey};
+pub use storage::{
+    restore_from_json, PersistedStateField, RestoredAccount, StateDebugLogEntry, StateFieldChange,
+    StateRestoreOutcome,
+};
+pub use token::{
+    get_service_scopes, AccessTokenInfo, AuthorizationParameters, ScopedKey, SubscriptionService,
+};
 
 // Used for auth state checking.  Remove this once firefox-android and firefox-ios are migrated to
 // using FxaAuthStateMachine
@@ -107,6 +118,18 @@ impl FirefoxAccount {
     pub fn simulate_network_error(&self) {
         self.internal.lock().simulate_network_error()
     }
+
+    /// Check whether the FxA server supports an optional feature.
+    ///
+    /// Servers advertise the features they support in their `.well-known/fxa-client-configuration`
+    /// document, so applications can use this rather than hard-coding assumptions about
+    /// each environment. Features which the server doesn't mention are assumed to be unsupported.
+    ///
+    /// The configuration document is cached, and re-fetched at most once a day.
+    #[handle_error(Error)]
+    pub fn server_supports(&self, capability: ServerCapability) -> ApiResult<bool> {
+        self.internal.lock().server_supports(capability)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -191,6 +214,26 @@ impl fmt::Display for FxaServer {
     }
 }
 
+/// Optional features which an FxA server may advertise support for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerCapability {
+    /// Pairing a new device by scanning a QR code.
+    Pairing,
+    /// Ecosystem telemetry, keyed by the account's ecosystem anonymous ID.
+    EcosystemTelemetry,
+}
+
+impl ServerCapability {
+    /// The key identifying this capability in the server's `features` map.
+    pub(crate) fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "pairing" => Some(Self::Pairing),
+            "ecosystem_telemetry" => Some(Self::EcosystemTelemetry),
+            _ => None,
+        }
+    }
+}
+
 impl FxaConfig {
     pub fn release(client_id: impl ToString, redirect_uri: impl ToString) -> Self {
         Self {
@@ -264,4 +307,25 @@ mod tests {
             assert_eq!(FxaServer::from(&url), expected_result);
         }
     }
+
+    #[test]
+    fn test_get_service_scopes() {
+        assert_eq!(
+            get_service_scopes(vec![SubscriptionService::Relay]),
+            vec!["profile", "https://identity.mozilla.com/apps/relay"],
+        );
+        assert_eq!(
+            get_service_scopes(vec![
+                SubscriptionService::Vpn,
+                SubscriptionService::Relay,
+                SubscriptionService::Vpn
+            ]),
+            vec![
+                "profile",
+                "https://identity.mozilla.com/apps/vpn",
+                "https://identity.mozilla.com/apps/relay",
+            ],
+        );
+        assert!(get_service_scopes(vec![]).is_empty());
+    }
 }
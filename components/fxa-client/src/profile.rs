@@ -32,7 +32,9 @@ impl FirefoxAccount {
     ///      [`Authentication`](FxaError::Authentication) error.
     #[handle_error(Error)]
     pub fn get_profile(&self, ignore_cache: bool) -> ApiResult<Profile> {
-        Ok(self.internal.lock().get_profile(ignore_cache)?.into())
+        let mut internal = self.internal.lock();
+        let result = internal.get_profile(ignore_cache);
+        Ok(internal.track_auth_recovery(result)?.into())
     }
 }
 
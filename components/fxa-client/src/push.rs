@@ -165,11 +165,15 @@ pub enum AccountEvent {
     /// still connected to the user's account by calling [`check_authorization_status`](
     /// FirefoxAccount::check_authorization_status), and updating its UI as appropriate.
     ///
+    /// If the event was caused by a password change or reset that revoked our tokens, the
+    /// account will already have been moved to the auth issues state.
+    ///
     AccountAuthStateChanged,
     /// Sent when the user deletes their Firefox Account.
     ///
     /// When receiving this event, the application should act as though the user had
-    /// signed out, discarding any persisted account state.
+    /// signed out, discarding any persisted account state. The account's tokens will
+    /// already have been cleared.
     AccountDestroyed,
     /// Sent when a new device connects to the user's account.
     ///
@@ -20,6 +20,87 @@
 use crate::{internal, ApiResult, Error, FirefoxAccount};
 use error_support::handle_error;
 use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// A part of the persisted account state which can be lost when restoring
+/// corrupt state with [`restore_from_json`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistedStateField {
+    CurrentDeviceId,
+    RefreshToken,
+    ScopedKeys,
+    LastHandledCommand,
+    CommandsData,
+    DeviceCapabilities,
+    AccessTokenCache,
+    SessionToken,
+    LastSeenProfile,
+    ServerLocalDeviceInfo,
+    LoggedOutFromAuthIssues,
+}
+
+/// How [`restore_from_json`] restored the account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateRestoreOutcome {
+    /// The state was intact, and was fully restored.
+    Restored,
+    /// The state was corrupt. The listed fields couldn't be recovered, and
+    /// were reset to their defaults. If they include the refresh or session
+    /// token or the scoped keys, the user will probably need to sign in again.
+    StateRecovered { lost: Vec<PersistedStateField> },
+}
+
+/// A change to one field of the persisted account state, as recorded by
+/// [`FirefoxAccount::get_state_debug_log`]. The field's values are never recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateFieldChange {
+    pub field: PersistedStateField,
+    /// Whether the field had a value before the change, as opposed to being empty or unset.
+    pub was_set: bool,
+    /// Whether the field has a value after the change.
+    pub is_set: bool,
+}
+
+/// An entry in the log returned by [`FirefoxAccount::get_state_debug_log`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateDebugLogEntry {
+    /// When the state changed, in milliseconds since the epoch.
+    pub timestamp: i64,
+    /// The internal operation which changed the state, e.g. `disconnect` or `on_auth_issues`.
+    pub operation: String,
+    pub changes: Vec<StateFieldChange>,
+}
+
+/// The result of [`restore_from_json`].
+pub struct RestoredAccount {
+    pub account: Arc<FirefoxAccount>,
+    pub outcome: StateRestoreOutcome,
+}
+
+/// Restore a [`FirefoxAccount`] instance from serialized state, salvaging what
+/// we can if the state is corrupt.
+///
+/// This behaves like [`FirefoxAccount::from_json`] for intact state. If the
+/// state was truncated or partly corrupted, rather than failing, this
+/// recovers the fields it can and reports the rest in the returned outcome.
+/// This still fails if the account's configuration can't be recovered.
+///
+/// **💾 This method alters the persisted account state.**
+#[handle_error(Error)]
+pub fn restore_from_json(data: &str) -> ApiResult<RestoredAccount> {
+    let (internal, lost) = internal::FirefoxAccount::from_json_with_recovery(data)?;
+    let outcome = if lost.is_empty() {
+        StateRestoreOutcome::Restored
+    } else {
+        StateRestoreOutcome::StateRecovered { lost }
+    };
+    Ok(RestoredAccount {
+        account: Arc::new(FirefoxAccount {
+            internal: Mutex::new(internal),
+        }),
+        outcome,
+    })
+}
 
 impl FirefoxAccount {
     /// Restore a [`FirefoxAccount`] instance from serialized state.
@@ -53,4 +134,25 @@ impl FirefoxAccount {
     pub fn to_json(&self) -> ApiResult<String> {
         self.internal.lock().to_json()
     }
+
+    /// Start or stop recording changes to the persisted account state.
+    ///
+    /// This is an opt-in diagnostic to help track down why an account's state changed
+    /// unexpectedly, e.g. why a user was logged out. While enabled, each change to the
+    /// persisted state is summarized in a bounded, in-memory log which can be read with
+    /// [`get_state_debug_log`](FirefoxAccount::get_state_debug_log). The log isn't persisted,
+    /// and disabling it discards it.
+    pub fn set_state_debug_log_enabled(&self, enabled: bool) {
+        self.internal.lock().set_state_debug_log_enabled(enabled)
+    }
+
+    /// Get the changes to the persisted account state recorded since
+    /// [`set_state_debug_log_enabled`](FirefoxAccount::set_state_debug_log_enabled) was
+    /// called, oldest first. Only the most recent 100 changes are kept.
+    ///
+    /// The log records which fields changed, and whether they were set before and after the
+    /// change, but never their values, so it's safe to include in bug reports.
+    pub fn get_state_debug_log(&self) -> Vec<StateDebugLogEntry> {
+        self.internal.lock().get_state_debug_log()
+    }
 }
@@ -15,7 +15,7 @@
 //!      typically managed on behalf of web content that runs within the context
 //!      of the application.
 
-use crate::{ApiResult, Error, FirefoxAccount};
+use crate::{internal::scopes, ApiResult, Error, FirefoxAccount};
 use error_support::handle_error;
 use serde_derive::*;
 use std::convert::{TryFrom, TryInto};
@@ -48,10 +48,36 @@ impl FirefoxAccount {
     pub fn get_access_token(&self, scope: &str, ttl: Option<i64>) -> ApiResult<AccessTokenInfo> {
         // Signedness converstion for Kotlin compatibility :-/
         let ttl = ttl.map(|ttl| u64::try_from(ttl).unwrap_or_default());
-        self.internal
-            .lock()
-            .get_access_token(scope, ttl)?
-            .try_into()
+        let mut internal = self.internal.lock();
+        let result = internal.get_access_token(scope, ttl);
+        internal.track_auth_recovery(result)?.try_into()
+    }
+
+    /// Get a short-lived OAuth access token for one of Mozilla's subscription services.
+    ///
+    /// **💾 This method alters the persisted account state.**
+    ///
+    /// This behaves like [`get_access_token`](FirefoxAccount::get_access_token), using the OAuth
+    /// scope for the given service, so that applications don't need to hard-code it.  The token
+    /// also carries the claims that the service requires, such as access to the user's profile.
+    ///
+    /// # Arguments
+    ///
+    ///    - `service` - the service that the token will be used to access.
+    ///        - The service's scopes (see [`get_service_scopes`]) must have been requested
+    ///          during the signin flow.
+    ///    - `ttl` - optionally, the time for which the token should be valid, in seconds.
+    #[handle_error(Error)]
+    pub fn get_service_token(
+        &self,
+        service: SubscriptionService,
+        ttl: Option<i64>,
+    ) -> ApiResult<AccessTokenInfo> {
+        // Signedness converstion for Kotlin compatibility :-/
+        let ttl = ttl.map(|ttl| u64::try_from(ttl).unwrap_or_default());
+        let mut internal = self.internal.lock();
+        let result = internal.get_access_token_for_scopes(&service.token_scopes(), ttl);
+        internal.track_auth_recovery(result)?.try_into()
     }
 
     /// Get the session token for the user's account, if one is available.
@@ -126,6 +152,56 @@ impl FirefoxAccount {
     }
 }
 
+/// A Mozilla subscription service which can be accessed with an FxA access token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SubscriptionService {
+    /// Firefox Relay
+    Relay,
+    /// Mozilla VPN
+    Vpn,
+}
+
+impl SubscriptionService {
+    /// The scope of the access tokens used to access this service.
+    fn scope(self) -> &'static str {
+        match self {
+            Self::Relay => scopes::RELAY,
+            Self::Vpn => scopes::VPN,
+        }
+    }
+
+    /// The scopes that the access tokens for this service must carry: the service's own scope,
+    /// followed by the claims it requires.  Both services read the user's profile with the
+    /// token they are given.
+    fn token_scopes(self) -> [&'static str; 2] {
+        [self.scope(), scopes::PROFILE]
+    }
+
+    /// The scopes to request during the signin flow in order to use this service.
+    fn signin_scopes(self) -> [&'static str; 2] {
+        [scopes::PROFILE, self.scope()]
+    }
+}
+
+/// Get the OAuth scopes to request during the signin flow in order to use `services`.
+///
+/// Pass these to [`begin_oauth_flow`](FirefoxAccount::begin_oauth_flow), along with any
+/// other scopes the application needs, so that [`get_service_token`](FirefoxAccount::get_service_token)
+/// can later get tokens for each of the services.  Scopes shared by several services are only
+/// included once.
+pub fn get_service_scopes(services: Vec<SubscriptionService>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for scope in services
+        .into_iter()
+        .flat_map(SubscriptionService::signin_scopes)
+    {
+        if !result.iter().any(|s| s == scope) {
+            result.push(scope.to_string());
+        }
+    }
+    result
+}
+
 /// An OAuth access token, with its associated keys and metadata.
 ///
 /// This struct represents an FxA OAuth access token, which can be used to access a resource
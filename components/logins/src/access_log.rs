@@ -0,0 +1,266 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An optional, local-only log of when saved credentials were read or filled.
+//!
+//! The log is disabled by default; the embedding app turns it on with
+//! `LoginStore::set_access_log_enabled()`, usually based on a user preference.
+//! The setting is saved in the database, so it stays on across restarts.
+//! The log is never synced, is cleared along with everything else by
+//! `wipe_local()`, and entries for a login are removed when the login is
+//! deleted, locally or by an incoming tombstone.
+
+use crate::db::LoginDb;
+use crate::error::*;
+use crate::schema;
+use crate::util;
+use rusqlite::{named_params, Row};
+use sql_support::ConnExt;
+use std::time::{Duration, SystemTime};
+
+/// Entries older than this are pruned whenever a new one is recorded.
+const ACCESS_LOG_MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// The maximum number of entries we keep, across all origins.
+const ACCESS_LOG_MAX_ENTRIES: u32 = 1000;
+
+/// How a login's credentials were accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LoginAccessType {
+    /// The decrypted login was fetched by id, eg to show it to the user.
+    Read = 1,
+    /// The login was filled into a form, as reported by `touch()`.
+    Fill = 2,
+}
+
+impl LoginAccessType {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(Self::Read),
+            2 => Some(Self::Fill),
+            _ => None,
+        }
+    }
+}
+
+/// A single entry in the access log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginAccessRecord {
+    /// The id of the login which was accessed.
+    pub id: String,
+    pub origin: String,
+    pub access_type: LoginAccessType,
+    /// Milliseconds since the Unix epoch.
+    pub time_accessed: i64,
+}
+
+impl LoginAccessRecord {
+    fn from_row(row: &Row<'_>) -> Result<Option<Self>> {
+        let Some(access_type) = LoginAccessType::from_u8(row.get("accessType")?) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            id: row.get("guid")?,
+            origin: row.get("origin")?,
+            access_type,
+            time_accessed: row.get("timeAccessed")?,
+        }))
+    }
+}
+
+impl LoginDb {
+    pub fn set_access_log_enabled(&mut self, enabled: bool) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        if !enabled {
+            // Turning the log off shouldn't leave the history behind.
+            self.execute_cached("DELETE FROM loginsAccessLog", [])?;
+        }
+        self.put_meta(schema::ACCESS_LOG_ENABLED_META_KEY, &enabled)?;
+        tx.commit()?;
+        self.access_log_enabled = enabled;
+        Ok(())
+    }
+
+    /// Reads the setting saved by `set_access_log_enabled()`.
+    pub(crate) fn load_access_log_enabled(&mut self) -> Result<()> {
+        self.access_log_enabled = self
+            .get_meta::<bool>(schema::ACCESS_LOG_ENABLED_META_KEY)?
+            .unwrap_or(false);
+        Ok(())
+    }
+
+    /// Records an access if the log is enabled. Failing to do so is logged
+    /// rather than returned, since it shouldn't prevent the access itself.
+    pub(crate) fn note_access(&self, guid: &str, origin: &str, access_type: LoginAccessType) {
+        if !self.access_log_enabled {
+            return;
+        }
+        if let Err(e) = self.record_access(
+            guid,
+            origin,
+            access_type,
+            util::system_time_ms_i64(SystemTime::now()),
+        ) {
+            log::warn!("Failed to record login access: {}", e);
+        }
+    }
+
+    fn record_access(
+        &self,
+        guid: &str,
+        origin: &str,
+        access_type: LoginAccessType,
+        now_ms: i64,
+    ) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        self.execute_cached(
+            "INSERT INTO loginsAccessLog (guid, origin, accessType, timeAccessed)
+             VALUES (:guid, :origin, :access_type, :now_ms)",
+            named_params! {
+                ":guid": guid,
+                ":origin": origin,
+                ":access_type": access_type as u8,
+                ":now_ms": now_ms,
+            },
+        )?;
+        self.execute_cached(
+            "DELETE FROM loginsAccessLog
+             WHERE timeAccessed < :cutoff
+                OR id NOT IN (SELECT id FROM loginsAccessLog
+                              ORDER BY timeAccessed DESC, id DESC
+                              LIMIT :max_entries)",
+            named_params! {
+                ":cutoff": now_ms - util::duration_ms_i64(ACCESS_LOG_MAX_AGE),
+                ":max_entries": ACCESS_LOG_MAX_ENTRIES,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the logged accesses of logins for `origin`, most recent first.
+    pub fn get_access_log(&self, origin: &str) -> Result<Vec<LoginAccessRecord>> {
+        let mut stmt = self.prepare_cached(
+            "SELECT guid, origin, accessType, timeAccessed
+             FROM loginsAccessLog
+             WHERE origin = :origin
+             ORDER BY timeAccessed DESC, id DESC",
+        )?;
+        let rows = stmt.query_and_then(
+            named_params! { ":origin": origin },
+            LoginAccessRecord::from_row,
+        )?;
+        // Skip rows with an access type we don't know about (eg, written by a
+        // newer version), rather than failing the whole query.
+        rows.filter_map(Result::transpose).collect()
+    }
+
+    pub(crate) fn delete_access_log_for_guid(&self, guid: &str) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM loginsAccessLog WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_log() {
+        let mut db = LoginDb::open_in_memory().unwrap();
+        // Disabled by default.
+        db.note_access("guid1", "https://example.com", LoginAccessType::Read);
+        assert!(db.get_access_log("https://example.com").unwrap().is_empty());
+
+        db.set_access_log_enabled(true).unwrap();
+        db.record_access("guid1", "https://example.com", LoginAccessType::Read, 1000)
+            .unwrap();
+        db.record_access("guid1", "https://example.com", LoginAccessType::Fill, 2000)
+            .unwrap();
+        db.record_access("guid2", "https://example.org", LoginAccessType::Fill, 3000)
+            .unwrap();
+        assert_eq!(
+            db.get_access_log("https://example.com").unwrap(),
+            vec![
+                LoginAccessRecord {
+                    id: "guid1".into(),
+                    origin: "https://example.com".into(),
+                    access_type: LoginAccessType::Fill,
+                    time_accessed: 2000,
+                },
+                LoginAccessRecord {
+                    id: "guid1".into(),
+                    origin: "https://example.com".into(),
+                    access_type: LoginAccessType::Read,
+                    time_accessed: 1000,
+                },
+            ]
+        );
+
+        db.delete_access_log_for_guid("guid1").unwrap();
+        assert!(db.get_access_log("https://example.com").unwrap().is_empty());
+        assert_eq!(db.get_access_log("https://example.org").unwrap().len(), 1);
+
+        // Disabling clears the log.
+        db.set_access_log_enabled(false).unwrap();
+        assert!(db.get_access_log("https://example.org").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_access_log_retention() {
+        let mut db = LoginDb::open_in_memory().unwrap();
+        db.set_access_log_enabled(true).unwrap();
+        let max_age_ms = util::duration_ms_i64(ACCESS_LOG_MAX_AGE);
+        db.record_access("guid", "https://example.com", LoginAccessType::Read, 0)
+            .unwrap();
+        db.record_access(
+            "guid",
+            "https://example.com",
+            LoginAccessType::Read,
+            max_age_ms + 1,
+        )
+        .unwrap();
+        let log = db.get_access_log("https://example.com").unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].time_accessed, max_age_ms + 1);
+
+        let start = max_age_ms + 2;
+        for i in 0..ACCESS_LOG_MAX_ENTRIES + 10 {
+            db.record_access(
+                "guid",
+                "https://example.com",
+                LoginAccessType::Fill,
+                start + i64::from(i),
+            )
+            .unwrap();
+        }
+        let log = db.get_access_log("https://example.com").unwrap();
+        assert_eq!(log.len(), ACCESS_LOG_MAX_ENTRIES as usize);
+        assert_eq!(
+            log.last().unwrap().time_accessed,
+            start + 10,
+            "the oldest entries should have been dropped"
+        );
+    }
+
+    #[test]
+    fn test_access_log_enabled_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins.sqlite");
+        LoginDb::open(&path)
+            .unwrap()
+            .set_access_log_enabled(true)
+            .unwrap();
+
+        let mut db = LoginDb::open(&path).unwrap();
+        assert!(db.access_log_enabled);
+        db.set_access_log_enabled(false).unwrap();
+        drop(db);
+        assert!(!LoginDb::open(&path).unwrap().access_log_enabled);
+    }
+}
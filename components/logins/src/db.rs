@@ -25,6 +25,7 @@
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use crate::login::*;
+use crate::recently_deleted::DEFAULT_RECENTLY_DELETED_RETENTION;
 use crate::schema;
 use crate::sync::SyncStatus;
 use crate::util;
@@ -39,13 +40,16 @@ use sql_support::ConnExt;
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use sync_guid::Guid;
 use url::{Host, Url};
 
 pub struct LoginDb {
     pub db: Connection,
     interrupt_handle: Arc<SqlInterruptHandle>,
+    pub(crate) access_log_enabled: bool,
+    pub(crate) passkey_sync_enabled: bool,
+    pub(crate) recently_deleted_retention: Duration,
 }
 
 impl LoginDb {
@@ -60,14 +64,23 @@ impl LoginDb {
         // https://github.com/mozilla/mentat/issues/505. Ideally we'd only
         // do this on Android, or allow caller to configure it.
         db.set_pragma("temp_store", 2)?;
+        schema::define_functions(&db)?;
 
         let mut logins = Self {
             interrupt_handle: Arc::new(SqlInterruptHandle::new(&db)),
             db,
+            access_log_enabled: false,
+            passkey_sync_enabled: false,
+            recently_deleted_retention: DEFAULT_RECENTLY_DELETED_RETENTION,
         };
         let tx = logins.db.transaction()?;
         schema::init(&tx)?;
         tx.commit()?;
+        logins.load_recently_deleted_retention()?;
+        logins.load_access_log_enabled()?;
+        logins.passkey_sync_enabled = logins
+            .get_meta::<bool>(schema::PASSKEY_SYNC_ENABLED_META_KEY)?
+            .unwrap_or(false);
         Ok(logins)
     }
 
@@ -132,12 +145,27 @@ impl LoginDb {
         Ok(())
     }
 
+    /// Set whether the sync server supports passkeys. This is kept until it's next set.
+    pub fn set_passkey_sync_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.put_meta(schema::PASSKEY_SYNC_ENABLED_META_KEY, &enabled)?;
+        self.passkey_sync_enabled = enabled;
+        Ok(())
+    }
+
+    /// Get all the passwords. Passkeys are returned by `get_all_passkeys()` instead.
     pub fn get_all(&self) -> Result<Vec<EncryptedLogin>> {
         let mut stmt = self.db.prepare_cached(&GET_ALL_SQL)?;
         let rows = stmt.query_and_then([], EncryptedLogin::from_row)?;
         rows.collect::<Result<_>>()
     }
 
+    pub fn get_all_passkeys(&self) -> Result<Vec<EncryptedLogin>> {
+        let mut stmt = self.db.prepare_cached(&GET_ALL_PASSKEYS_SQL)?;
+        let rows = stmt.query_and_then([], EncryptedLogin::from_row)?;
+        rows.collect::<Result<_>>()
+    }
+
+    /// Get the passwords for `base_domain` and its subdomains. Passkeys aren't returned.
     pub fn get_by_base_domain(&self, base_domain: &str) -> Result<Vec<EncryptedLogin>> {
         // We first parse the input string as a host so it is normalized.
         let base_host = match Host::parse(base_domain) {
@@ -148,15 +176,26 @@ impl LoginDb {
                 return Ok(vec![]);
             }
         };
-        // We just do a linear scan. Another option is to have an indexed
-        // reverse-host column or similar, but current thinking is that it's
-        // extra complexity for (probably) zero actual benefit given the record
-        // counts are expected to be so low.
-        // A regex would probably make this simpler, but we don't want to drag
-        // in a regex lib just for this.
-        let mut stmt = self.db.prepare_cached(&GET_ALL_SQL)?;
+        // The indexed `revHost` column narrows the search to hosts starting
+        // with the reversed base domain, ie, the domain and its subdomains.
+        // That also matches some hosts which aren't subdomains, like
+        // `badexample.com` for `example.com`, or IP addresses which share a
+        // prefix, so we check each login's host below.
+        let rev_host_start = util::rev_host(&base_host);
+        let mut rev_host_end = rev_host_start.clone();
+        // Hosts are ASCII once normalized, so this gives the next string after
+        // every string with the prefix.
+        let last = rev_host_end.pop().expect("hosts aren't empty");
+        rev_host_end.push(char::from(last as u8 + 1));
+        let mut stmt = self.db.prepare_cached(&GET_BY_REV_HOST_PREFIX_SQL)?;
         let rows = stmt
-            .query_and_then([], EncryptedLogin::from_row)?
+            .query_and_then(
+                named_params! {
+                    ":rev_host_start": rev_host_start,
+                    ":rev_host_end": rev_host_end,
+                },
+                EncryptedLogin::from_row,
+            )?
             .filter(|r| {
                 let login = r
                     .as_ref()
@@ -189,6 +228,39 @@ impl LoginDb {
         rows.collect::<Result<_>>()
     }
 
+    /// Get the logins saved for exactly `origin`, most recently used first.
+    ///
+    /// `include_forms` and `include_http` select logins for web forms (with a
+    /// `form_action_origin`) and for HTTP authentication (with an
+    /// `http_realm`). Passkeys have neither, so they're never returned.
+    pub fn find_logins_for_origin(
+        &self,
+        origin: &str,
+        include_forms: bool,
+        include_http: bool,
+    ) -> Result<Vec<EncryptedLogin>> {
+        // Origins are normalized when they're saved, so normalize this one the
+        // same way to compare them.
+        let origin = match LoginEntry::validate_and_fixup_origin(origin) {
+            Ok(fixed) => fixed.unwrap_or_else(|| origin.to_string()),
+            Err(e) => {
+                // don't log the input string as it's PII.
+                log::warn!("find_logins_for_origin was passed an invalid origin: {}", e);
+                return Ok(vec![]);
+            }
+        };
+        let mut stmt = self.db.prepare_cached(&FIND_LOGINS_FOR_ORIGIN_SQL)?;
+        let rows = stmt.query_and_then(
+            named_params! {
+                ":origin": origin,
+                ":include_forms": include_forms,
+                ":include_http": include_http,
+            },
+            EncryptedLogin::from_row,
+        )?;
+        rows.collect::<Result<_>>()
+    }
+
     pub fn get_by_id(&self, id: &str) -> Result<Option<EncryptedLogin>> {
         self.try_query_row(
             &GET_BY_GUID_SQL,
@@ -208,6 +280,9 @@ impl LoginDb {
     //  - Filling in a blank username for an existing login: `Some(login)` will be returned
     //    with a blank username.
     //
+    //  - Updating an existing passkey: `Some(login)` will be returned and the credential id will
+    //    match the one for look.
+    //
     //  Returns an Err if the new login is not valid and could not be fixed up
     pub fn find_login_to_update(
         &self,
@@ -220,6 +295,10 @@ impl LoginDb {
             .into_iter()
             .map(|enc_login| enc_login.decrypt(encdec))
             .collect::<Result<Vec<Login>>>()?;
+        // A passkey is identified by its credential id, so there's at most one match.
+        if look.credential_type == CredentialType::Passkey {
+            return Ok(logins.into_iter().next());
+        }
         Ok(logins
             // First, try to match the username
             .iter()
@@ -263,6 +342,9 @@ impl LoginDb {
                 formActionOrigin,
                 usernameField,
                 passwordField,
+                credentialType,
+                passkeyRpId,
+                passkeyCredentialId,
                 timesUsed,
                 secFields,
                 guid,
@@ -278,6 +360,9 @@ impl LoginDb {
                 :form_action_origin,
                 :username_field,
                 :password_field,
+                :credential_type,
+                :passkey_rp_id,
+                :passkey_credential_id,
                 :times_used,
                 :sec_fields,
                 :guid,
@@ -299,6 +384,9 @@ impl LoginDb {
                 ":form_action_origin": login.fields.form_action_origin,
                 ":username_field": login.fields.username_field,
                 ":password_field": login.fields.password_field,
+                ":credential_type": login.fields.credential_type as u8,
+                ":passkey_rp_id": login.fields.passkey_rp_id,
+                ":passkey_credential_id": login.fields.passkey_credential_id,
                 ":time_created": login.record.time_created,
                 ":times_used": login.record.times_used,
                 ":time_last_used": login.record.time_last_used,
@@ -322,6 +410,9 @@ impl LoginDb {
                  formActionOrigin    = :form_action_origin,
                  usernameField       = :username_field,
                  passwordField       = :password_field,
+                 credentialType      = :credential_type,
+                 passkeyRpId         = :passkey_rp_id,
+                 passkeyCredentialId = :passkey_credential_id,
                  timesUsed           = :times_used,
                  secFields           = :sec_fields,
                  origin              = :origin,
@@ -339,6 +430,9 @@ impl LoginDb {
                 ":form_action_origin": login.fields.form_action_origin,
                 ":username_field": login.fields.username_field,
                 ":password_field": login.fields.password_field,
+                ":credential_type": login.fields.credential_type as u8,
+                ":passkey_rp_id": login.fields.passkey_rp_id,
+                ":passkey_credential_id": login.fields.passkey_credential_id,
                 ":time_last_used": login.record.time_last_used,
                 ":times_used": login.record.times_used,
                 ":time_password_changed": login.record.time_password_changed,
@@ -363,6 +457,8 @@ impl LoginDb {
         let sec_fields = SecureLoginFields {
             username: new_entry.username,
             password: new_entry.password,
+            passkey_user_handle: new_entry.passkey_user_handle,
+            passkey_private_key: new_entry.passkey_private_key,
         };
         let result = EncryptedLogin {
             record: RecordFields {
@@ -378,6 +474,9 @@ impl LoginDb {
                 http_realm: new_entry.http_realm,
                 username_field: new_entry.username_field,
                 password_field: new_entry.password_field,
+                credential_type: new_entry.credential_type,
+                passkey_rp_id: new_entry.passkey_rp_id,
+                passkey_credential_id: new_entry.passkey_credential_id,
             },
             sec_fields: sec_fields.encrypt(encdec)?,
         };
@@ -434,6 +533,8 @@ impl LoginDb {
         let sec_fields = SecureLoginFields {
             username: entry.username,
             password: entry.password,
+            passkey_user_handle: entry.passkey_user_handle,
+            passkey_private_key: entry.passkey_private_key,
         };
         let result = EncryptedLogin {
             record: RecordFields {
@@ -449,6 +550,9 @@ impl LoginDb {
                 http_realm: entry.http_realm,
                 username_field: entry.username_field,
                 password_field: entry.password_field,
+                credential_type: entry.credential_type,
+                passkey_rp_id: entry.passkey_rp_id,
+                passkey_credential_id: entry.passkey_credential_id,
             },
             sec_fields: sec_fields.encrypt(encdec)?,
         };
@@ -511,6 +615,10 @@ impl LoginDb {
     ) -> Result<Option<Guid>> {
         for possible in self.get_by_entry_target(entry)? {
             if possible.guid() != *guid {
+                // Passkeys with the same credential id are dupes regardless of the username.
+                if entry.credential_type == CredentialType::Passkey {
+                    return Ok(Some(possible.guid()));
+                }
                 let pos_sec_fields = possible.decrypt_fields(encdec)?;
                 if pos_sec_fields.username == entry.username {
                     return Ok(Some(possible.guid()));
@@ -525,6 +633,7 @@ impl LoginDb {
     // This means that:
     //   - `origin` matches
     //   - Either `form_action_origin` or `http_realm` matches, depending on which one is non-null
+    //   - Or for passkeys, `passkey_rp_id` and `passkey_credential_id` match
     //
     // This is used for dupe-checking and `find_login_to_update()`
     fn get_by_entry_target(&self, entry: &LoginEntry) -> Result<Vec<EncryptedLogin>> {
@@ -560,6 +669,35 @@ impl LoginDb {
                 ",
                 common_cols = schema::COMMON_COLS
             );
+            static ref GET_BY_PASSKEY_CREDENTIAL: String = format!(
+                "SELECT {common_cols} FROM loginsL
+                WHERE is_deleted = 0
+                    AND credentialType = {passkey}
+                    AND passkeyRpId = :passkey_rp_id
+                    AND passkeyCredentialId = :passkey_credential_id
+
+                UNION ALL
+
+                SELECT {common_cols} FROM loginsM
+                WHERE is_overridden = 0
+                    AND credentialType = {passkey}
+                    AND passkeyRpId = :passkey_rp_id
+                    AND passkeyCredentialId = :passkey_credential_id
+                ",
+                common_cols = schema::COMMON_COLS,
+                passkey = CredentialType::Passkey as u8
+            );
+        }
+        if entry.credential_type == CredentialType::Passkey {
+            let params = named_params! {
+                ":passkey_rp_id": &entry.passkey_rp_id,
+                ":passkey_credential_id": &entry.passkey_credential_id,
+            };
+            return self
+                .db
+                .prepare_cached(&GET_BY_PASSKEY_CREDENTIAL)?
+                .query_and_then(params, EncryptedLogin::from_row)?
+                .collect();
         }
         match (entry.form_action_origin.as_ref(), entry.http_realm.as_ref()) {
             (Some(form_action_origin), None) => {
@@ -607,6 +745,7 @@ impl LoginDb {
         let tx = self.unchecked_transaction_imm()?;
         let exists = self.exists(id)?;
         let now_ms = util::system_time_ms_i64(SystemTime::now());
+        self.save_recently_deleted(id, now_ms)?;
 
         // For IDs that have, mark is_deleted and clear sensitive fields
         self.execute(
@@ -618,7 +757,9 @@ impl LoginDb {
                      secFields = '',
                      origin = '',
                      httpRealm = NULL,
-                     formActionOrigin = NULL
+                     formActionOrigin = NULL,
+                     passkeyRpId = NULL,
+                     passkeyCredentialId = NULL
                  WHERE guid = :guid",
                 status_changed = SyncStatus::Changed as u8
             ),
@@ -641,6 +782,7 @@ impl LoginDb {
             WHERE guid = :guid",
             changed = SyncStatus::Changed as u8),
             named_params! { ":now_ms": now_ms, ":guid": id })?;
+        self.delete_access_log_for_guid(id)?;
         tx.commit()?;
         Ok(exists)
     }
@@ -682,11 +824,20 @@ impl LoginDb {
 
     pub fn wipe_local(&self) -> Result<()> {
         log::info!("Executing wipe_local on password engine!");
+        // Local settings are kept.
+        let delete_sync_meta = format!(
+            "DELETE FROM loginsSyncMeta WHERE key NOT IN ('{}', '{}', '{}')",
+            schema::RECENTLY_DELETED_RETENTION_META_KEY,
+            schema::ACCESS_LOG_ENABLED_META_KEY,
+            schema::PASSKEY_SYNC_ENABLED_META_KEY,
+        );
         let tx = self.unchecked_transaction()?;
         self.execute_all(&[
             "DELETE FROM loginsL",
             "DELETE FROM loginsM",
-            "DELETE FROM loginsSyncMeta",
+            &delete_sync_meta,
+            "DELETE FROM loginsAccessLog",
+            "DELETE FROM loginsDeleted",
         ])?;
         tx.commit()?;
         Ok(())
@@ -695,9 +846,47 @@ impl LoginDb {
 
 lazy_static! {
     static ref GET_ALL_SQL: String = format!(
-        "SELECT {common_cols} FROM loginsL WHERE is_deleted = 0
+        "SELECT {common_cols} FROM loginsL
+         WHERE is_deleted = 0 AND credentialType = {password}
          UNION ALL
-         SELECT {common_cols} FROM loginsM WHERE is_overridden = 0",
+         SELECT {common_cols} FROM loginsM
+         WHERE is_overridden = 0 AND credentialType = {password}",
+        common_cols = schema::COMMON_COLS,
+        password = CredentialType::Password as u8,
+    );
+    static ref GET_ALL_PASSKEYS_SQL: String = format!(
+        "SELECT {common_cols} FROM loginsL
+         WHERE is_deleted = 0 AND credentialType = {passkey}
+         UNION ALL
+         SELECT {common_cols} FROM loginsM
+         WHERE is_overridden = 0 AND credentialType = {passkey}",
+        common_cols = schema::COMMON_COLS,
+        passkey = CredentialType::Passkey as u8,
+    );
+    static ref GET_BY_REV_HOST_PREFIX_SQL: String = format!(
+        "SELECT {common_cols} FROM loginsL
+         WHERE is_deleted = 0 AND credentialType = {password}
+           AND revHost >= :rev_host_start AND revHost < :rev_host_end
+         UNION ALL
+         SELECT {common_cols} FROM loginsM
+         WHERE is_overridden = 0 AND credentialType = {password}
+           AND revHost >= :rev_host_start AND revHost < :rev_host_end",
+        common_cols = schema::COMMON_COLS,
+        password = CredentialType::Password as u8,
+    );
+    static ref FIND_LOGINS_FOR_ORIGIN_SQL: String = format!(
+        "SELECT {common_cols} FROM loginsL
+         WHERE is_deleted = 0
+           AND origin = :origin
+           AND ((:include_forms AND formActionOrigin IS NOT NULL)
+                OR (:include_http AND httpRealm IS NOT NULL))
+         UNION ALL
+         SELECT {common_cols} FROM loginsM
+         WHERE is_overridden = 0
+           AND origin = :origin
+           AND ((:include_forms AND formActionOrigin IS NOT NULL)
+                OR (:include_http AND httpRealm IS NOT NULL))
+         ORDER BY timeLastUsed DESC, guid",
         common_cols = schema::COMMON_COLS,
     );
     static ref GET_BY_GUID_SQL: String = format!(
@@ -783,6 +972,9 @@ pub mod test_utils {
                 formActionOrigin,
                 usernameField,
                 passwordField,
+                credentialType,
+                passkeyRpId,
+                passkeyCredentialId,
                 secFields,
                 origin,
 
@@ -800,6 +992,9 @@ pub mod test_utils {
                 :form_action_origin,
                 :username_field,
                 :password_field,
+                :credential_type,
+                :passkey_rp_id,
+                :passkey_credential_id,
                 :sec_fields,
                 :origin,
 
@@ -819,6 +1014,9 @@ pub mod test_utils {
             ":form_action_origin": login.fields.form_action_origin,
             ":username_field": login.fields.username_field,
             ":password_field": login.fields.password_field,
+            ":credential_type": login.fields.credential_type as u8,
+            ":passkey_rp_id": login.fields.passkey_rp_id,
+            ":passkey_credential_id": login.fields.passkey_credential_id,
             ":origin": login.fields.origin,
             ":sec_fields": login.sec_fields,
             ":times_used": login.record.times_used,
@@ -949,6 +1147,7 @@ mod tests {
                     password_field: "😍".into(),
                     username: "😍".into(),
                     password: "😍".into(),
+                    ..Default::default()
                 },
                 &*TEST_ENCDEC,
             )
@@ -1098,6 +1297,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_logins_for_origin() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let add = |origin: &str, form: bool, username: &str, last_used: i64| {
+            let login = db
+                .add(
+                    LoginEntry {
+                        origin: origin.into(),
+                        form_action_origin: form.then(|| origin.into()),
+                        http_realm: (!form).then(|| "realm".into()),
+                        username: username.into(),
+                        password: "password".into(),
+                        ..Default::default()
+                    },
+                    &*TEST_ENCDEC,
+                )
+                .unwrap();
+            db.execute(
+                "UPDATE loginsL SET timeLastUsed = ? WHERE guid = ?",
+                (last_used, login.guid_str()),
+            )
+            .unwrap();
+        };
+        add("https://example.com", true, "older", 1);
+        add("https://example.com", true, "newer", 2);
+        add("https://example.com", false, "http", 3);
+        add("https://www.example.com", true, "subdomain", 4);
+        add("http://example.com", true, "insecure", 5);
+
+        let usernames = |include_forms, include_http| {
+            db.find_logins_for_origin("https://example.com/path", include_forms, include_http)
+                .unwrap()
+                .into_iter()
+                .map(|login| login.decrypt_fields(&*TEST_ENCDEC).unwrap().username)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(usernames(true, true), vec!["http", "newer", "older"]);
+        assert_eq!(usernames(true, false), vec!["newer", "older"]);
+        assert_eq!(usernames(false, true), vec!["http"]);
+        assert!(usernames(false, false).is_empty());
+        assert!(db
+            .find_logins_for_origin("invalid origin", true, true)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_add() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -1179,6 +1424,97 @@ mod tests {
         assert_eq!(login2.record.times_used, login.record.times_used + 1);
     }
 
+    #[test]
+    fn test_passkeys() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let passkey = LoginEntry {
+            origin: "https://www.example.com".into(),
+            credential_type: CredentialType::Passkey,
+            passkey_rp_id: Some("example.com".into()),
+            passkey_credential_id: Some("Y3JlZA".into()),
+            passkey_user_handle: Some("dXNlcg".into()),
+            passkey_private_key: Some("a2V5".into()),
+            username: "user".into(),
+            ..Default::default()
+        };
+        // A password for the same site isn't a dupe.
+        db.add(
+            LoginEntry {
+                origin: "https://www.example.com".into(),
+                form_action_origin: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Default::default()
+            },
+            &*TEST_ENCDEC,
+        )
+        .unwrap();
+        let added = db.add(passkey.clone(), &*TEST_ENCDEC).unwrap();
+        let fetched = db
+            .get_by_id(&added.record.id)
+            .unwrap()
+            .unwrap()
+            .decrypt(&*TEST_ENCDEC)
+            .unwrap();
+        assert_eq!(fetched.entry(), passkey);
+
+        // The same credential is a dupe, even with a different username.
+        let renamed = LoginEntry {
+            username: "renamed".into(),
+            ..passkey.clone()
+        };
+        assert!(db.add(renamed.clone(), &*TEST_ENCDEC).is_err());
+        let updated = db.add_or_update(renamed, &*TEST_ENCDEC).unwrap();
+        assert_eq!(updated.record.id, added.record.id);
+        assert_eq!(
+            updated.decrypt_fields(&*TEST_ENCDEC).unwrap().username,
+            "renamed"
+        );
+
+        // But a different credential for the same relying party isn't.
+        db.add(
+            LoginEntry {
+                passkey_credential_id: Some("b3RoZXI".into()),
+                ..passkey
+            },
+            &*TEST_ENCDEC,
+        )
+        .unwrap();
+        // Passkeys aren't returned with passwords.
+        assert_eq!(db.get_all().unwrap().len(), 1);
+        assert_eq!(db.get_by_base_domain("example.com").unwrap().len(), 1);
+        assert_eq!(db.get_all_passkeys().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_credential_type_hidden() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let mut login = crate::login::test_utils::enc_login("unknown", "password");
+        login.fields.credential_type = CredentialType::Unknown;
+        test_utils::add_mirror(&db, &login, &sync15::ServerTimestamp::default(), false).unwrap();
+        assert!(db.get_all().unwrap().is_empty());
+        assert!(db.get_all_passkeys().unwrap().is_empty());
+        // It's still there, so it isn't lost.
+        assert!(db.exists("unknown").unwrap());
+    }
+
+    #[test]
+    fn test_passkey_sync_enabled_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins.sqlite");
+        assert!(!LoginDb::open(&path).unwrap().passkey_sync_enabled);
+        LoginDb::open(&path)
+            .unwrap()
+            .set_passkey_sync_enabled(true)
+            .unwrap();
+        let db = LoginDb::open(&path).unwrap();
+        assert!(db.passkey_sync_enabled);
+        // It's a property of the server, so survives a local wipe.
+        db.wipe_local().unwrap();
+        drop(db);
+        assert!(LoginDb::open(&path).unwrap().passkey_sync_enabled);
+    }
+
     #[test]
     fn test_delete() {
         let db = LoginDb::open_in_memory().unwrap();
@@ -57,6 +57,9 @@ pub enum Error {
     #[error("The `sync_status` column in DB has an illegal value: {0}")]
     BadSyncStatus(u8),
 
+    #[error("The `credentialType` column in DB has an illegal value: {0}")]
+    BadCredentialType(u8),
+
     #[error("No record with guid exists (when one was required): {0:?}")]
     NoSuchRecord(String),
 
@@ -88,6 +91,9 @@ pub enum Error {
     #[error("CryptoError({0})")]
     CryptoError(#[from] EncryptorDecryptorError),
 
+    #[error("Error hashing password: {0}")]
+    HashError(#[from] rc_crypto::Error),
+
     #[error("{0}")]
     Interrupted(#[from] interrupt_support::Interrupted),
 
@@ -9,8 +9,12 @@
 mod error;
 mod login;
 
+mod access_log;
 mod db;
 pub mod encryption;
+mod password_health;
+mod recently_deleted;
+mod rekey;
 mod schema;
 mod store;
 mod sync;
@@ -21,10 +25,14 @@ use crate::encryption::{
 };
 uniffi::include_scaffolding!("logins");
 
+pub use crate::access_log::{LoginAccessRecord, LoginAccessType};
 pub use crate::db::LoginDb;
 use crate::encryption::{check_canary, create_canary, create_key};
 pub use crate::error::*;
 pub use crate::login::*;
+pub use crate::password_health::LoginPasswordHash;
+pub use crate::recently_deleted::DeletedLogin;
+pub use crate::rekey::RekeyResult;
 pub use crate::store::*;
 pub use crate::sync::LoginsSyncEngine;
 use std::sync::Arc;
@@ -74,6 +74,9 @@
 //! The struct used to hold the fields which are stored encrypted. It contains:
 //! - username: A string.
 //! - password: A string.
+//! - passkey_user_handle: For passkeys, the WebAuthn user handle, base64url encoded.
+//! - passkey_private_key: For passkeys, the credential's private key as a base64url encoded
+//!   PKCS#8 document.
 //!
 //! # LoginFields
 //!
@@ -160,6 +163,25 @@
 //!   then the logins store will attempt to coerce it into valid data by:
 //!   - setting to the empty string if 'form_action_origin' is not present
 //!
+//! - `credential_type`: A [`CredentialType`], which is `Password` for everything above.
+//!
+//!   Passkeys (WebAuthn public-key credentials) are stored alongside passwords, since they're
+//!   synced in the same collection, but have different requirements:
+//!   - neither `form_action_origin` nor `http_realm` may be set.
+//!   - `password` may be empty.
+//!   - `passkey_rp_id`, `passkey_credential_id` and the secure `passkey_private_key` are required.
+//!
+//!   If invalid data is received in the passkey fields of a password (either from the
+//!   application, or via sync) then the logins store will attempt to coerce it into valid data
+//!   by clearing them.
+//!
+//! - `passkey_rp_id`: For passkeys, the WebAuthn relying party id, a domain.
+//!
+//! - `passkey_credential_id`: For passkeys, the credential id, base64url encoded.
+//!
+//!   Two passkeys with the same relying party and credential id are duplicates, regardless of
+//!   their usernames.
+//!
 //! # RecordFields
 //!
 //! This contains data relating to the login database record -- both on the local instance and
@@ -284,6 +306,32 @@ use serde_derive::*;
 use sync_guid::Guid;
 use url::Url;
 
+/// The kind of credential a login holds.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum CredentialType {
+    #[default]
+    Password = 0,
+    /// A WebAuthn public-key credential.
+    Passkey = 1,
+    /// A credential type from a newer client. We keep these so they aren't lost, but never
+    /// return or upload them.
+    #[serde(other)]
+    Unknown = 2,
+}
+
+impl CredentialType {
+    pub(crate) fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::Password),
+            1 => Some(Self::Passkey),
+            2 => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+}
+
 // LoginEntry fields that are stored in cleartext
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
 pub struct LoginFields {
@@ -292,13 +340,16 @@ pub struct LoginFields {
     pub http_realm: Option<String>,
     pub username_field: String,
     pub password_field: String,
+    pub credential_type: CredentialType,
+    pub passkey_rp_id: Option<String>,
+    pub passkey_credential_id: Option<String>,
 }
 
 /// LoginEntry fields that are stored encrypted
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct SecureLoginFields {
     // - Username cannot be null, use the empty string instead
-    // - Password can't be empty or null (enforced in the ValidateAndFixup code)
+    // - Password can't be empty or null, except for passkeys (enforced in the ValidateAndFixup code)
     //
     // This matches the desktop behavior:
     // https://searchfox.org/mozilla-central/rev/d3683dbb252506400c71256ef3994cdbdfb71ada/toolkit/components/passwordmgr/LoginManager.jsm#260-267
@@ -309,6 +360,13 @@ pub struct SecureLoginFields {
     pub username: String,
     #[serde(rename = "p")]
     pub password: String,
+
+    // Only set for passkeys. These are omitted otherwise, so the secure fields of passwords
+    // are unchanged.
+    #[serde(rename = "uh", default, skip_serializing_if = "Option::is_none")]
+    pub passkey_user_handle: Option<String>,
+    #[serde(rename = "pk", default, skip_serializing_if = "Option::is_none")]
+    pub passkey_private_key: Option<String>,
 }
 
 impl SecureLoginFields {
@@ -351,10 +409,15 @@ pub struct LoginEntry {
     pub http_realm: Option<String>,
     pub username_field: String,
     pub password_field: String,
+    pub credential_type: CredentialType,
+    pub passkey_rp_id: Option<String>,
+    pub passkey_credential_id: Option<String>,
 
     // secure fields
     pub username: String,
     pub password: String,
+    pub passkey_user_handle: Option<String>,
+    pub passkey_private_key: Option<String>,
 }
 
 impl LoginEntry {
@@ -365,14 +428,19 @@ impl LoginEntry {
             http_realm: fields.http_realm,
             username_field: fields.username_field,
             password_field: fields.password_field,
+            credential_type: fields.credential_type,
+            passkey_rp_id: fields.passkey_rp_id,
+            passkey_credential_id: fields.passkey_credential_id,
 
             username: sec_fields.username,
             password: sec_fields.password,
+            passkey_user_handle: sec_fields.passkey_user_handle,
+            passkey_private_key: sec_fields.passkey_private_key,
         }
     }
     /// Internal helper for validation and fixups of an "origin" stored as
     /// a string.
-    fn validate_and_fixup_origin(origin: &str) -> Result<Option<String>> {
+    pub(crate) fn validate_and_fixup_origin(origin: &str) -> Result<Option<String>> {
         // Check we can parse the origin, then use the normalized version of it.
         match Url::parse(origin) {
             Ok(mut u) => {
@@ -421,6 +489,30 @@ impl LoginEntry {
             }
         }
     }
+
+    // Passkeys don't have a target, but do need everything that's required to use the
+    // credential. Unlike passwords, there's nothing sensible we can do to fix them up.
+    fn check_valid_passkey(&self) -> Result<()> {
+        if self.form_action_origin.is_some() || self.http_realm.is_some() {
+            return Err(InvalidLogin::IllegalFieldValue {
+                field_info: "passkeys can't have a `form_action_origin` or `http_realm`".into(),
+            }
+            .into());
+        }
+        for (field_name, field_value) in [
+            ("passkey_rp_id", &self.passkey_rp_id),
+            ("passkey_credential_id", &self.passkey_credential_id),
+            ("passkey_private_key", &self.passkey_private_key),
+        ] {
+            if field_value.as_deref().unwrap_or_default().is_empty() {
+                return Err(InvalidLogin::IllegalFieldValue {
+                    field_info: format!("`{}` is missing", field_name),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A login handed over from the store API, which has been persisted and contains persistence
@@ -440,10 +532,15 @@ pub struct Login {
     pub http_realm: Option<String>,
     pub username_field: String,
     pub password_field: String,
+    pub credential_type: CredentialType,
+    pub passkey_rp_id: Option<String>,
+    pub passkey_credential_id: Option<String>,
 
     // secure fields
     pub username: String,
     pub password: String,
+    pub passkey_user_handle: Option<String>,
+    pub passkey_private_key: Option<String>,
 }
 
 impl Login {
@@ -460,9 +557,14 @@ impl Login {
             http_realm: fields.http_realm,
             username_field: fields.username_field,
             password_field: fields.password_field,
+            credential_type: fields.credential_type,
+            passkey_rp_id: fields.passkey_rp_id,
+            passkey_credential_id: fields.passkey_credential_id,
 
             username: sec_fields.username,
             password: sec_fields.password,
+            passkey_user_handle: sec_fields.passkey_user_handle,
+            passkey_private_key: sec_fields.passkey_private_key,
         }
     }
 
@@ -478,9 +580,14 @@ impl Login {
             http_realm: self.http_realm.clone(),
             username_field: self.username_field.clone(),
             password_field: self.password_field.clone(),
+            credential_type: self.credential_type,
+            passkey_rp_id: self.passkey_rp_id.clone(),
+            passkey_credential_id: self.passkey_credential_id.clone(),
 
             username: self.username.clone(),
             password: self.password.clone(),
+            passkey_user_handle: self.passkey_user_handle.clone(),
+            passkey_private_key: self.passkey_private_key.clone(),
         }
     }
 
@@ -488,6 +595,8 @@ impl Login {
         let sec_fields = SecureLoginFields {
             username: self.username,
             password: self.password,
+            passkey_user_handle: self.passkey_user_handle,
+            passkey_private_key: self.passkey_private_key,
         };
         Ok(EncryptedLogin {
             record: RecordFields {
@@ -503,6 +612,9 @@ impl Login {
                 http_realm: self.http_realm,
                 username_field: self.username_field,
                 password_field: self.password_field,
+                credential_type: self.credential_type,
+                passkey_rp_id: self.passkey_rp_id,
+                passkey_credential_id: self.passkey_credential_id,
             },
             sec_fields: sec_fields.encrypt(encdec)?,
         })
@@ -542,6 +654,7 @@ impl EncryptedLogin {
     }
 
     pub(crate) fn from_row(row: &Row<'_>) -> Result<EncryptedLogin> {
+        let credential_type: u8 = row.get("credentialType")?;
         let login = EncryptedLogin {
             record: RecordFields {
                 id: row.get("guid")?,
@@ -562,6 +675,10 @@ impl EncryptedLogin {
 
                 username_field: string_or_default(row, "usernameField")?,
                 password_field: string_or_default(row, "passwordField")?,
+                credential_type: CredentialType::from_u8(credential_type)
+                    .ok_or(Error::BadCredentialType(credential_type))?,
+                passkey_rp_id: row.get("passkeyRpId")?,
+                passkey_credential_id: row.get("passkeyCredentialId")?,
             },
             sec_fields: row.get("secFields")?,
         };
@@ -639,12 +756,32 @@ impl ValidateAndFixup for LoginEntry {
             return Err(InvalidLogin::EmptyOrigin.into());
         }
 
-        if self.form_action_origin.is_some() && self.http_realm.is_some() {
-            get_fixed_or_throw!(InvalidLogin::BothTargets)?.http_realm = None;
-        }
+        // We don't know what a credential of an unknown type needs, so only check what's common.
+        let is_passkey = self.credential_type == CredentialType::Passkey;
+        if is_passkey {
+            self.check_valid_passkey()?;
+        } else if self.credential_type == CredentialType::Password {
+            if self.form_action_origin.is_some() && self.http_realm.is_some() {
+                get_fixed_or_throw!(InvalidLogin::BothTargets)?.http_realm = None;
+            }
+
+            if self.form_action_origin.is_none() && self.http_realm.is_none() {
+                return Err(InvalidLogin::NoTarget.into());
+            }
 
-        if self.form_action_origin.is_none() && self.http_realm.is_none() {
-            return Err(InvalidLogin::NoTarget.into());
+            if self.passkey_rp_id.is_some()
+                || self.passkey_credential_id.is_some()
+                || self.passkey_user_handle.is_some()
+                || self.passkey_private_key.is_some()
+            {
+                let fixed = get_fixed_or_throw!(InvalidLogin::IllegalFieldValue {
+                    field_info: "passkey fields are set on a password".into()
+                })?;
+                fixed.passkey_rp_id = None;
+                fixed.passkey_credential_id = None;
+                fixed.passkey_user_handle = None;
+                fixed.passkey_private_key = None;
+            }
         }
 
         let form_action_origin = self.form_action_origin.clone().unwrap_or_default();
@@ -654,6 +791,8 @@ impl ValidateAndFixup for LoginEntry {
             .http_realm
             .clone()
             .unwrap_or_default();
+        let passkey_rp_id = self.passkey_rp_id.clone().unwrap_or_default();
+        let passkey_credential_id = self.passkey_credential_id.clone().unwrap_or_default();
 
         let field_data = [
             ("form_action_origin", &form_action_origin),
@@ -661,6 +800,8 @@ impl ValidateAndFixup for LoginEntry {
             ("origin", &self.origin),
             ("username_field", &self.username_field),
             ("password_field", &self.password_field),
+            ("passkey_rp_id", &passkey_rp_id),
+            ("passkey_credential_id", &passkey_credential_id),
         ];
 
         for (field_name, field_value) in &field_data {
@@ -746,7 +887,8 @@ impl ValidateAndFixup for LoginEntry {
             }
             .into());
         }
-        if self.password.is_empty() {
+        // Passkeys don't have a password.
+        if self.password.is_empty() && !is_passkey {
             return Err(InvalidLogin::EmptyPassword.into());
         }
         if self.password.contains('\0') {
@@ -772,6 +914,7 @@ pub mod test_utils {
         let sec_fields = SecureLoginFields {
             username: "user".to_string(),
             password: password.to_string(),
+            ..Default::default()
         };
         EncryptedLogin {
             record: RecordFields {
@@ -1268,6 +1411,7 @@ mod tests {
         let sf = SecureLoginFields {
             username: "foo".into(),
             password: "pwd".into(),
+            ..Default::default()
         };
         assert_eq!(
             serde_json::to_string(&sf).unwrap(),
@@ -1277,7 +1421,55 @@ mod tests {
         let expected = SecureLoginFields {
             username: "user".into(),
             password: "p".into(),
+            ..Default::default()
         };
         assert_eq!(got, expected);
     }
+
+    #[test]
+    fn test_passkey_validation() {
+        let passkey = LoginEntry {
+            origin: "https://example.com".into(),
+            credential_type: CredentialType::Passkey,
+            passkey_rp_id: Some("example.com".into()),
+            passkey_credential_id: Some("Y3JlZA".into()),
+            passkey_user_handle: Some("dXNlcg".into()),
+            passkey_private_key: Some("a2V5".into()),
+            username: "user".into(),
+            ..Default::default()
+        };
+        // No target or password is fine for passkeys.
+        passkey.check_valid().unwrap();
+
+        for invalid in [
+            LoginEntry {
+                passkey_private_key: None,
+                ..passkey.clone()
+            },
+            LoginEntry {
+                passkey_credential_id: Some("".into()),
+                ..passkey.clone()
+            },
+            LoginEntry {
+                form_action_origin: Some("https://example.com".into()),
+                ..passkey.clone()
+            },
+        ] {
+            assert!(invalid.check_valid().is_err());
+            assert!(invalid.fixup().is_err());
+        }
+
+        // Passkey fields on a password are cleared by fixup.
+        let password = LoginEntry {
+            origin: "https://example.com".into(),
+            http_realm: Some("realm".into()),
+            password: "pwd".into(),
+            passkey_private_key: Some("a2V5".into()),
+            ..Default::default()
+        };
+        assert!(password.check_valid().is_err());
+        let fixed = password.fixup().unwrap();
+        assert_eq!(fixed.passkey_private_key, None);
+        assert_eq!(fixed.password, "pwd");
+    }
 }
@@ -0,0 +1,188 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Queries for a password health dashboard.
+//!
+//! These are computed over the decrypted passwords here, so that the embedding
+//! app can find weak spots without having every plaintext password exported to
+//! it. Passkeys don't have passwords, so they're never included.
+
+use crate::db::LoginDb;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use rc_crypto::digest;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// A login whose password hash matched one of the prefixes passed to
+/// `find_logins_with_password_hash()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginPasswordHash {
+    pub id: String,
+    /// The SHA-256 hash of the password, as uppercase hex.
+    pub password_hash: String,
+}
+
+impl LoginDb {
+    /// Returns the ids of logins whose password is also used by a login for a
+    /// different origin.
+    pub fn get_reused_passwords(&self, encdec: &dyn EncryptorDecryptor) -> Result<Vec<String>> {
+        // Group by hash rather than by password, to avoid holding on to the
+        // plaintext.
+        let mut by_hash: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for (id, origin, password_hash) in self.password_hashes(encdec)? {
+            by_hash.entry(password_hash).or_default().push((id, origin));
+        }
+        let mut reused = by_hash
+            .into_values()
+            .filter(|logins| logins.iter().any(|(_, origin)| *origin != logins[0].1))
+            .flat_map(|logins| logins.into_iter().map(|(id, _)| id))
+            .collect::<Vec<_>>();
+        reused.sort();
+        Ok(reused)
+    }
+
+    /// Returns the logins whose password hash starts with one of `prefixes`,
+    /// in the style of k-anonymity breach lookups: the app only needs to send a
+    /// short prefix of each hash to a breach service, and can then compare the
+    /// full hashes returned here against its response.
+    ///
+    /// Prefixes are hex, and matched case-insensitively. Empty prefixes are
+    /// ignored, since they'd match everything.
+    pub fn find_logins_with_password_hash(
+        &self,
+        prefixes: &[String],
+        encdec: &dyn EncryptorDecryptor,
+    ) -> Result<Vec<LoginPasswordHash>> {
+        let prefixes = prefixes
+            .iter()
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| prefix.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        if prefixes.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(self
+            .password_hashes(encdec)?
+            .into_iter()
+            .filter(|(_, _, password_hash)| {
+                prefixes
+                    .iter()
+                    .any(|prefix| password_hash.starts_with(prefix.as_str()))
+            })
+            .map(|(id, _, password_hash)| LoginPasswordHash { id, password_hash })
+            .collect())
+    }
+
+    // Returns the id, origin and password hash for every login with a password.
+    fn password_hashes(
+        &self,
+        encdec: &dyn EncryptorDecryptor,
+    ) -> Result<Vec<(String, String, String)>> {
+        let mut hashes = vec![];
+        for login in self.get_all()? {
+            let sec_fields = login.decrypt_fields(encdec)?;
+            if sec_fields.password.is_empty() {
+                continue;
+            }
+            hashes.push((
+                login.record.id,
+                login.fields.origin,
+                hash_password(&sec_fields.password)?,
+            ));
+        }
+        Ok(hashes)
+    }
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    let digest = digest::digest(&digest::SHA256, password.as_bytes())?;
+    let mut hex = String::with_capacity(digest.as_ref().len() * 2);
+    for byte in digest.as_ref() {
+        write!(hex, "{:02X}", byte).expect("writing to a string can't fail");
+    }
+    Ok(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_utils::TEST_ENCDEC;
+    use crate::{CredentialType, LoginEntry};
+
+    fn add(db: &LoginDb, origin: &str, username: &str, password: &str) -> String {
+        db.add(
+            LoginEntry {
+                origin: origin.into(),
+                http_realm: Some("realm".into()),
+                username: username.into(),
+                password: password.into(),
+                ..Default::default()
+            },
+            &*TEST_ENCDEC,
+        )
+        .unwrap()
+        .record
+        .id
+    }
+
+    #[test]
+    fn test_get_reused_passwords() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let a = add(&db, "https://a.example.com", "user", "hunter2");
+        let b = add(&db, "https://b.example.com", "user", "hunter2");
+        // Only used for one origin, even though there are two logins.
+        add(&db, "https://c.example.com", "user1", "correct horse");
+        add(&db, "https://c.example.com", "user2", "correct horse");
+        add(&db, "https://d.example.com", "user", "unique");
+        db.add(
+            LoginEntry {
+                origin: "https://a.example.com".into(),
+                credential_type: CredentialType::Passkey,
+                passkey_rp_id: Some("a.example.com".into()),
+                passkey_credential_id: Some("Y3JlZA".into()),
+                passkey_private_key: Some("a2V5".into()),
+                ..Default::default()
+            },
+            &*TEST_ENCDEC,
+        )
+        .unwrap();
+
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(db.get_reused_passwords(&*TEST_ENCDEC).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_find_logins_with_password_hash() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, "https://a.example.com", "user", "password");
+        add(&db, "https://b.example.com", "user", "something else");
+
+        // SHA-256("password")
+        let hash = "5E884898DA28047151D0E56F8DC6292773603D0D6AABBDD62A11EF721D1542D8";
+        let expected = vec![LoginPasswordHash {
+            id,
+            password_hash: hash.to_string(),
+        }];
+        assert_eq!(
+            db.find_logins_with_password_hash(&["5e884".to_string()], &*TEST_ENCDEC)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            db.find_logins_with_password_hash(&[hash.to_string()], &*TEST_ENCDEC)
+                .unwrap(),
+            expected
+        );
+        assert!(db
+            .find_logins_with_password_hash(&["".to_string()], &*TEST_ENCDEC)
+            .unwrap()
+            .is_empty());
+        assert!(db
+            .find_logins_with_password_hash(&["0000".to_string()], &*TEST_ENCDEC)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Undoing deletions.
+//!
+//! When a login is deleted, we keep a copy of it (still encrypted) in the
+//! local-only `loginsDeleted` table, so that the app can offer a "recently
+//! deleted" list and put logins back. Copies are kept until the grace period
+//! set by `LoginStore::set_recently_deleted_retention_days()` passes, or until
+//! the deletion is uploaded by a sync, since after that undoing it locally
+//! would just resurrect the login on this device.
+
+use crate::db::LoginDb;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::login::{EncryptedLogin, Login};
+use crate::schema;
+use crate::util;
+use lazy_static::lazy_static;
+use rusqlite::named_params;
+use sql_support::ConnExt;
+use std::time::{Duration, SystemTime};
+use sync_guid::Guid;
+
+/// How long deleted logins can be restored for, unless the app changes it.
+pub(crate) const DEFAULT_RECENTLY_DELETED_RETENTION: Duration =
+    Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A login which was deleted, but can still be restored with `undo_delete()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedLogin {
+    /// The login as it was just before it was deleted.
+    pub login: Login,
+    /// Milliseconds since the Unix epoch.
+    pub time_deleted: i64,
+}
+
+lazy_static! {
+    // The login is either a live local record, or a mirror record with no
+    // local changes.
+    static ref SAVE_DELETED_SQL: String = format!(
+        "INSERT OR REPLACE INTO loginsDeleted ({common_cols}, timeDeleted)
+         SELECT {common_cols}, :now_ms FROM loginsL
+         WHERE guid = :guid AND is_deleted = 0
+         UNION ALL
+         SELECT {common_cols}, :now_ms FROM loginsM
+         WHERE guid = :guid AND is_overridden = 0",
+        common_cols = schema::COMMON_COLS,
+    );
+    // `delete()` always leaves a local tombstone, so restoring a login means
+    // putting the fields back on it. It's still marked as changed, so the
+    // restored login replaces the tombstone on the next sync.
+    static ref RESTORE_DELETED_SQL: String = format!(
+        "UPDATE loginsL
+         SET ({common_cols}) = (SELECT {common_cols} FROM loginsDeleted WHERE guid = :guid),
+             is_deleted = 0,
+             local_modified = :now_ms
+         WHERE guid = :guid AND is_deleted = 1",
+        common_cols = schema::COMMON_COLS,
+    );
+}
+
+impl LoginDb {
+    pub fn set_recently_deleted_retention(&mut self, retention: Duration) -> Result<()> {
+        let tx = self.unchecked_transaction()?;
+        self.put_meta(
+            schema::RECENTLY_DELETED_RETENTION_META_KEY,
+            &util::duration_ms_i64(retention),
+        )?;
+        self.prune_recently_deleted_with_retention(SystemTime::now(), retention)?;
+        tx.commit()?;
+        self.recently_deleted_retention = retention;
+        Ok(())
+    }
+
+    /// Reads the retention saved by `set_recently_deleted_retention()`.
+    pub(crate) fn load_recently_deleted_retention(&mut self) -> Result<()> {
+        if let Some(ms) = self.get_meta::<i64>(schema::RECENTLY_DELETED_RETENTION_META_KEY)? {
+            self.recently_deleted_retention = Duration::from_millis(ms.max(0) as u64);
+        }
+        Ok(())
+    }
+
+    /// Returns the logins which can still be restored, most recently deleted
+    /// first.
+    pub fn get_recently_deleted(
+        &self,
+        encdec: &dyn EncryptorDecryptor,
+    ) -> Result<Vec<DeletedLogin>> {
+        self.prune_recently_deleted(SystemTime::now())?;
+        let deleted = self.query_rows_and_then_cached(
+            &format!(
+                "SELECT {common_cols}, timeDeleted FROM loginsDeleted
+                 ORDER BY timeDeleted DESC",
+                common_cols = schema::COMMON_COLS,
+            ),
+            [],
+            |row| -> Result<(EncryptedLogin, i64)> {
+                Ok((EncryptedLogin::from_row(row)?, row.get("timeDeleted")?))
+            },
+        )?;
+        deleted
+            .into_iter()
+            .map(|(login, time_deleted)| {
+                Ok(DeletedLogin {
+                    login: login.decrypt(encdec)?,
+                    time_deleted,
+                })
+            })
+            .collect()
+    }
+
+    /// Restores a deleted login, returning it.
+    ///
+    /// Fails with `NoSuchRecord` if the login wasn't deleted, or can no longer
+    /// be restored, and with `DuplicateLogin` if an equivalent login has been
+    /// saved since it was deleted.
+    pub fn undo_delete(&self, id: &str, encdec: &dyn EncryptorDecryptor) -> Result<Login> {
+        let tx = self.unchecked_transaction_imm()?;
+        self.prune_recently_deleted(SystemTime::now())?;
+        let deleted = self
+            .try_query_row(
+                &format!(
+                    "SELECT {common_cols} FROM loginsDeleted WHERE guid = :guid",
+                    common_cols = schema::COMMON_COLS,
+                ),
+                named_params! { ":guid": id },
+                EncryptedLogin::from_row,
+                true,
+            )?
+            .ok_or_else(|| Error::NoSuchRecord(id.to_owned()))?;
+        let login = deleted.decrypt(encdec)?;
+        self.check_for_dupes(&Guid::from_string(id.to_owned()), &login.entry(), encdec)?;
+
+        let now_ms = util::system_time_ms_i64(SystemTime::now());
+        let restored = self.execute_cached(
+            &RESTORE_DELETED_SQL,
+            named_params! { ":guid": id, ":now_ms": now_ms },
+        )?;
+        if restored == 0 {
+            // The tombstone is gone, so the deletion must have been synced.
+            return Err(Error::NoSuchRecord(id.to_owned()));
+        }
+        self.delete_recently_deleted_for_guid(id)?;
+        tx.commit()?;
+        Ok(login)
+    }
+
+    /// Keeps a copy of a login which is about to be deleted, if deletions can
+    /// be undone.
+    pub(crate) fn save_recently_deleted(&self, guid: &str, now_ms: i64) -> Result<()> {
+        if self.recently_deleted_retention.is_zero() {
+            return Ok(());
+        }
+        self.execute_cached(
+            &SAVE_DELETED_SQL,
+            named_params! { ":guid": guid, ":now_ms": now_ms },
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn delete_recently_deleted_for_guid(&self, guid: &str) -> Result<()> {
+        self.execute_cached(
+            "DELETE FROM loginsDeleted WHERE guid = :guid",
+            named_params! { ":guid": guid },
+        )?;
+        Ok(())
+    }
+
+    fn prune_recently_deleted(&self, now: SystemTime) -> Result<()> {
+        self.prune_recently_deleted_with_retention(now, self.recently_deleted_retention)
+    }
+
+    fn prune_recently_deleted_with_retention(
+        &self,
+        now: SystemTime,
+        retention: Duration,
+    ) -> Result<()> {
+        let cutoff = util::system_time_ms_i64(now) - util::duration_ms_i64(retention);
+        self.execute_cached(
+            "DELETE FROM loginsDeleted WHERE timeDeleted <= :cutoff",
+            named_params! { ":cutoff": cutoff },
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_utils::TEST_ENCDEC;
+    use crate::sync::SyncStatus;
+    use crate::LoginEntry;
+
+    fn add(db: &LoginDb, origin: &str) -> String {
+        db.add(
+            LoginEntry {
+                origin: origin.into(),
+                http_realm: Some("realm".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Default::default()
+            },
+            &*TEST_ENCDEC,
+        )
+        .unwrap()
+        .record
+        .id
+    }
+
+    #[test]
+    fn test_undo_delete() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, "https://a.example.com");
+        let other = add(&db, "https://b.example.com");
+        db.delete(&id).unwrap();
+        db.delete(&other).unwrap();
+        assert!(db.get_by_id(&id).unwrap().is_none());
+
+        let deleted = db.get_recently_deleted(&*TEST_ENCDEC).unwrap();
+        assert_eq!(deleted.len(), 2);
+        let deleted = deleted.into_iter().find(|d| d.login.id == id).unwrap();
+        assert_eq!(deleted.login.password, "password");
+
+        let restored = db.undo_delete(&id, &*TEST_ENCDEC).unwrap();
+        assert_eq!(restored, deleted.login);
+        let login = db.get_by_id(&id).unwrap().unwrap();
+        assert_eq!(login.fields.origin, "https://a.example.com");
+        assert_eq!(login.decrypt(&*TEST_ENCDEC).unwrap().password, "password");
+        let sync_status: u8 = db
+            .query_row(
+                "SELECT sync_status FROM loginsL WHERE guid = ?",
+                [&id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(sync_status, SyncStatus::Changed as u8);
+
+        // It can only be restored once.
+        assert_eq!(db.get_recently_deleted(&*TEST_ENCDEC).unwrap().len(), 1);
+        assert!(matches!(
+            db.undo_delete(&id, &*TEST_ENCDEC),
+            Err(Error::NoSuchRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_undo_delete_dupe() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, "https://a.example.com");
+        db.delete(&id).unwrap();
+        add(&db, "https://a.example.com");
+        assert!(matches!(
+            db.undo_delete(&id, &*TEST_ENCDEC),
+            Err(Error::InvalidLogin(InvalidLogin::DuplicateLogin))
+        ));
+        // It's still there to try again.
+        assert_eq!(db.get_recently_deleted(&*TEST_ENCDEC).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_recently_deleted_retention() {
+        let mut db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, "https://a.example.com");
+        db.delete(&id).unwrap();
+
+        // Pretend it was deleted a long time ago.
+        let long_ago = SystemTime::now() - DEFAULT_RECENTLY_DELETED_RETENTION * 2;
+        db.execute(
+            "UPDATE loginsDeleted SET timeDeleted = ?",
+            [util::system_time_ms_i64(long_ago)],
+        )
+        .unwrap();
+        assert!(db.get_recently_deleted(&*TEST_ENCDEC).unwrap().is_empty());
+
+        // A zero retention disables undo entirely.
+        db.set_recently_deleted_retention(Duration::ZERO).unwrap();
+        let id = add(&db, "https://b.example.com");
+        db.delete(&id).unwrap();
+        assert!(db.get_recently_deleted(&*TEST_ENCDEC).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recently_deleted_retention_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins.sqlite");
+        {
+            let mut db = LoginDb::open(&path).unwrap();
+            db.set_recently_deleted_retention(Duration::ZERO).unwrap();
+            // Wiping the logins doesn't reset it.
+            db.wipe_local().unwrap();
+        }
+
+        let db = LoginDb::open(&path).unwrap();
+        assert_eq!(db.recently_deleted_retention, Duration::ZERO);
+        let id = add(&db, "https://a.example.com");
+        db.delete(&id).unwrap();
+        assert!(db.get_recently_deleted(&*TEST_ENCDEC).unwrap().is_empty());
+    }
+}
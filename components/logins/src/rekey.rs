@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Re-encrypting the database with a new key.
+//!
+//! This lets apps rotate the local encryption key (for example, when the
+//! Android keystore invalidates the key protecting it) without wiping the
+//! database. Everything happens in a single transaction, so either every
+//! value is re-encrypted or, if anything goes wrong, none are.
+
+use crate::db::LoginDb;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use rusqlite::named_params;
+use sql_support::ConnExt;
+
+// Every encrypted column, by table.
+const ENCRYPTED_COLUMNS: &[(&str, &str)] = &[
+    ("loginsL", "secFields"),
+    ("loginsM", "secFields"),
+    ("loginsM", "enc_unknown_fields"),
+    ("loginsDeleted", "secFields"),
+];
+
+/// What `rekey_database()` did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RekeyResult {
+    /// The number of values which were re-encrypted with the new key.
+    pub num_rekeyed: u32,
+    /// The number of values which were already encrypted with the new key.
+    pub num_already_rekeyed: u32,
+}
+
+impl LoginDb {
+    /// Re-encrypts every encrypted value in the database from `old` to `new`.
+    ///
+    /// Each value is checked to decrypt with `new` to the original cleartext
+    /// before it's written. Values which are already encrypted with `new`, for
+    /// example because logins were saved after the app switched keys, are left
+    /// as they are, so it's safe to call this on a partially migrated database.
+    ///
+    /// Fails, without changing anything, if a value can't be decrypted with
+    /// either key.
+    pub fn rekey(
+        &self,
+        old: &dyn EncryptorDecryptor,
+        new: &dyn EncryptorDecryptor,
+    ) -> Result<RekeyResult> {
+        let tx = self.unchecked_transaction_imm()?;
+        let mut result = RekeyResult::default();
+        for (table, column) in ENCRYPTED_COLUMNS {
+            // Tombstones have an empty `secFields`.
+            let values = self.query_rows_and_then(
+                &format!("SELECT id, {column} FROM {table} WHERE {column} != ''"),
+                [],
+                |row| -> Result<(i64, String)> { Ok((row.get(0)?, row.get(1)?)) },
+            )?;
+            for (id, ciphertext) in values {
+                match rekey_value(&ciphertext, old, new)? {
+                    Some(new_ciphertext) => {
+                        self.execute(
+                            &format!("UPDATE {table} SET {column} = :value WHERE id = :id"),
+                            named_params! { ":value": new_ciphertext, ":id": id },
+                        )?;
+                        result.num_rekeyed += 1;
+                    }
+                    None => result.num_already_rekeyed += 1,
+                }
+            }
+        }
+        tx.commit()?;
+        log::info!(
+            "Rekeyed {} values ({} were already rekeyed)",
+            result.num_rekeyed,
+            result.num_already_rekeyed
+        );
+        Ok(result)
+    }
+}
+
+// Returns `ciphertext` re-encrypted with `new`, or `None` if it already is.
+fn rekey_value(
+    ciphertext: &str,
+    old: &dyn EncryptorDecryptor,
+    new: &dyn EncryptorDecryptor,
+) -> Result<Option<String>> {
+    let cleartext = match old.decrypt(ciphertext.as_bytes().into()) {
+        Ok(cleartext) => cleartext,
+        Err(old_err) => {
+            return match new.decrypt(ciphertext.as_bytes().into()) {
+                Ok(_) => Ok(None),
+                Err(_) => Err(Error::DecryptionFailed(format!(
+                    "value can't be decrypted with either key: {old_err}"
+                ))),
+            };
+        }
+    };
+    let new_ciphertext = new
+        .encrypt(cleartext.clone())
+        .map_err(|e| Error::EncryptionFailed(e.to_string()))?;
+    // We're about to overwrite the only copy of this value, so make sure we
+    // can get it back.
+    let roundtripped = new
+        .decrypt(new_ciphertext.clone())
+        .map_err(|e| Error::EncryptionFailed(e.to_string()))?;
+    if roundtripped != cleartext {
+        return Err(Error::EncryptionFailed(
+            "re-encrypted value doesn't match the original".into(),
+        ));
+    }
+    Ok(Some(
+        String::from_utf8(new_ciphertext).map_err(|e| Error::EncryptionFailed(e.to_string()))?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::test_utils::TEST_ENCDEC;
+    use crate::encryption::{create_key, ManagedEncryptorDecryptor, StaticKeyManager};
+    use crate::LoginEntry;
+    use std::sync::Arc;
+
+    fn encdec_for(key: String) -> ManagedEncryptorDecryptor {
+        ManagedEncryptorDecryptor::new(Arc::new(StaticKeyManager::new(key)))
+    }
+
+    fn add(db: &LoginDb, encdec: &dyn EncryptorDecryptor, origin: &str) -> String {
+        db.add(
+            LoginEntry {
+                origin: origin.into(),
+                http_realm: Some("realm".into()),
+                username: "user".into(),
+                password: "password".into(),
+                ..Default::default()
+            },
+            encdec,
+        )
+        .unwrap()
+        .record
+        .id
+    }
+
+    #[test]
+    fn test_rekey() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, &*TEST_ENCDEC, "https://a.example.com");
+        let deleted = add(&db, &*TEST_ENCDEC, "https://b.example.com");
+        db.delete(&deleted).unwrap();
+
+        let new_encdec = encdec_for(create_key().unwrap());
+        let result = db.rekey(&*TEST_ENCDEC, &new_encdec).unwrap();
+        assert_eq!(
+            result,
+            RekeyResult {
+                num_rekeyed: 1,
+                num_already_rekeyed: 0,
+            }
+        );
+
+        let login = db.get_by_id(&id).unwrap().unwrap();
+        assert!(login.decrypt_fields(&*TEST_ENCDEC).is_err());
+        assert_eq!(login.decrypt(&new_encdec).unwrap().password, "password");
+    }
+
+    #[test]
+    fn test_rekey_partially_migrated() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let new_encdec = encdec_for(create_key().unwrap());
+        let old_id = add(&db, &*TEST_ENCDEC, "https://a.example.com");
+        let new_id = add(&db, &new_encdec, "https://b.example.com");
+
+        let result = db.rekey(&*TEST_ENCDEC, &new_encdec).unwrap();
+        assert_eq!(
+            result,
+            RekeyResult {
+                num_rekeyed: 1,
+                num_already_rekeyed: 1,
+            }
+        );
+        for id in [old_id, new_id] {
+            let login = db.get_by_id(&id).unwrap().unwrap();
+            assert_eq!(login.decrypt(&new_encdec).unwrap().password, "password");
+        }
+    }
+
+    #[test]
+    fn test_rekey_wrong_key() {
+        let db = LoginDb::open_in_memory().unwrap();
+        let id = add(&db, &*TEST_ENCDEC, "https://a.example.com");
+        let other_encdec = encdec_for(create_key().unwrap());
+        let wrong_encdec = encdec_for(create_key().unwrap());
+        add(&db, &other_encdec, "https://b.example.com");
+
+        // The first login can be rekeyed, but the second can't, so nothing is.
+        assert!(db.rekey(&*TEST_ENCDEC, &wrong_encdec).is_err());
+        let login = db.get_by_id(&id).unwrap().unwrap();
+        assert_eq!(login.decrypt(&*TEST_ENCDEC).unwrap().password, "password");
+    }
+}
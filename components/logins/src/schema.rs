@@ -87,15 +87,72 @@
 //!    [GLOBAL_STATE_META_KEY]. This is a `sync15::GlobalState` stored as
 //!    JSON.
 //!
+//! It also holds local settings which have to survive restarts: how long
+//! deleted logins are kept for, under [RECENTLY_DELETED_RETENTION_META_KEY],
+//! whether the access log is enabled, under [ACCESS_LOG_ENABLED_META_KEY], and
+//! whether the sync server supports passkeys, under
+//! [PASSKEY_SYNC_ENABLED_META_KEY].
+//! These are kept by `wipe_local()`.
+//!
+//! ## `loginsAccessLog`
+//!
+//! A local-only log of when logins were read or filled, added in version 3.
+//! It's only written to when the embedding app has enabled it, and is pruned
+//! to a bounded size on every write (see the `access_log` module).
+//!
+//! - `guid`: The id of the login which was accessed.
+//!
+//! - `origin`: The login's origin at the time of the access.
+//!
+//! - `accessType`: A `LoginAccessType` enum value.
+//!
+//! - `timeAccessed`: A millisecond timestamp.
+//!
+//! ## Passkeys
+//!
+//! Both tables also store passkeys (WebAuthn public-key credentials) since
+//! version 4. The `credentialType` column of [COMMON_COLS] distinguishes them
+//! from passwords, and they have the cleartext `passkeyRpId` and
+//! `passkeyCredentialId` columns, which are NULL for passwords. The user
+//! handle and private key are stored in `secFields`, alongside the username
+//! and password.
+//!
+//! ## `revHost`
+//!
+//! Both tables have a `revHost` column, added in version 6, holding the host
+//! of the login's origin in the form returned by `util::rev_host`: domains are
+//! reversed with a trailing `.`, so that a domain and its subdomains share a
+//! prefix, and IP addresses are stored as-is. It's maintained by triggers
+//! which call the `logins_rev_host()` SQL function whenever the origin is
+//! written, and indexed so that `LoginDb::get_by_base_domain` doesn't need to
+//! scan every login.
+//!
+//! ## `loginsDeleted`
+//!
+//! A local-only copy of recently deleted logins, added in version 5, so that
+//! deletions can be undone (see the `recently_deleted` module). It has the
+//! [COMMON_COLS] of the login as it was just before it was deleted, including
+//! the encrypted `secFields`, plus:
+//!
+//! - `timeDeleted`: A millisecond timestamp.
+//!
+//! Entries are removed once they're older than the configured grace period, or
+//! when the deletion is uploaded to the server.
+//!
 
 use crate::error::*;
+use crate::util;
 use lazy_static::lazy_static;
 use rusqlite::Connection;
 use sql_support::ConnExt;
 
 /// Version 1: SQLCipher -> plaintext migration.
 /// Version 2: addition of `loginsM.enc_unknown_fields`.
-pub(super) const VERSION: i64 = 2;
+/// Version 3: addition of `loginsAccessLog`.
+/// Version 4: addition of `credentialType` and the passkey columns.
+/// Version 5: addition of `loginsDeleted`.
+/// Version 6: addition of `revHost`.
+pub(super) const VERSION: i64 = 6;
 
 /// Every column shared by both tables except for `id`
 ///
@@ -125,7 +182,10 @@ pub const COMMON_COLS: &str = "
     timeCreated,
     timeLastUsed,
     timePasswordChanged,
-    timesUsed
+    timesUsed,
+    credentialType,
+    passkeyRpId,
+    passkeyCredentialId
 ";
 
 const COMMON_SQL: &str = "
@@ -141,7 +201,11 @@ const COMMON_SQL: &str = "
     timeLastUsed        INTEGER,
     timePasswordChanged INTEGER NOT NULL,
     secFields           TEXT,
-    guid                TEXT NOT NULL UNIQUE
+    guid                TEXT NOT NULL UNIQUE,
+    -- A `CredentialType`. The passkey columns are only set for passkeys.
+    credentialType      TINYINT NOT NULL DEFAULT 0,
+    passkeyRpId         TEXT,
+    passkeyCredentialId TEXT
 ";
 
 lazy_static! {
@@ -152,7 +216,9 @@ lazy_static! {
             local_modified INTEGER,
 
             is_deleted     TINYINT NOT NULL DEFAULT 0,
-            sync_status    TINYINT NOT NULL DEFAULT 0
+            sync_status    TINYINT NOT NULL DEFAULT 0,
+            -- Maintained by triggers; see the module docs.
+            revHost        TEXT
         )",
         common_sql = COMMON_SQL
     );
@@ -165,7 +231,17 @@ lazy_static! {
             is_overridden   TINYINT NOT NULL DEFAULT 0,
             -- fields on incoming records we don't know about and roundtrip.
             -- a serde_json::Value::Object as an encrypted string.
-            enc_unknown_fields   TEXT
+            enc_unknown_fields   TEXT,
+            -- Maintained by triggers; see the module docs.
+            revHost         TEXT
+        )",
+        common_sql = COMMON_SQL
+    );
+    static ref CREATE_DELETED_TABLE_SQL: String = format!(
+        "CREATE TABLE IF NOT EXISTS loginsDeleted (
+            {common_sql},
+            -- Milliseconds.
+            timeDeleted INTEGER NOT NULL
         )",
         common_sql = COMMON_SQL
     );
@@ -180,6 +256,21 @@ const CREATE_META_TABLE_SQL: &str = "
     )
 ";
 
+const CREATE_ACCESS_LOG_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS loginsAccessLog (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        guid         TEXT NOT NULL,
+        origin       TEXT NOT NULL,
+        accessType   TINYINT NOT NULL,
+        timeAccessed INTEGER NOT NULL
+    )
+";
+
+const CREATE_ACCESS_LOG_ORIGIN_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsAccessLog_origin_timeAccessed
+    ON loginsAccessLog (origin, timeAccessed)
+";
+
 const CREATE_OVERRIDE_ORIGIN_INDEX_SQL: &str = "
     CREATE INDEX IF NOT EXISTS idx_loginsM_is_overridden_origin
     ON loginsM (is_overridden, origin)
@@ -190,16 +281,71 @@ const CREATE_DELETED_ORIGIN_INDEX_SQL: &str = "
     ON loginsL (is_deleted, origin)
 ";
 
+const CREATE_OVERRIDE_REV_HOST_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsM_is_overridden_revHost
+    ON loginsM (is_overridden, revHost)
+";
+
+const CREATE_DELETED_REV_HOST_INDEX_SQL: &str = "
+    CREATE INDEX IF NOT EXISTS idx_loginsL_is_deleted_revHost
+    ON loginsL (is_deleted, revHost)
+";
+
+const CREATE_REV_HOST_TRIGGERS_SQL: &str = "
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsL_afterinsert_revHost
+    AFTER INSERT ON loginsL
+    BEGIN
+        UPDATE loginsL SET revHost = logins_rev_host(NEW.origin) WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsL_afterupdate_origin_revHost
+    AFTER UPDATE OF origin ON loginsL
+    BEGIN
+        UPDATE loginsL SET revHost = logins_rev_host(NEW.origin) WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsM_afterinsert_revHost
+    AFTER INSERT ON loginsM
+    BEGIN
+        UPDATE loginsM SET revHost = logins_rev_host(NEW.origin) WHERE id = NEW.id;
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS loginsM_afterupdate_origin_revHost
+    AFTER UPDATE OF origin ON loginsM
+    BEGIN
+        UPDATE loginsM SET revHost = logins_rev_host(NEW.origin) WHERE id = NEW.id;
+    END;
+";
+
 pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_STATE_META_KEY: &str = "global_state_v2";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
 pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "passwords_sync_id";
+pub(crate) static RECENTLY_DELETED_RETENTION_META_KEY: &str = "recently_deleted_retention_ms";
+pub(crate) static ACCESS_LOG_ENABLED_META_KEY: &str = "access_log_enabled";
+pub(crate) static PASSKEY_SYNC_ENABLED_META_KEY: &str = "passkey_sync_enabled";
+
+/// Define the SQL functions used by the schema. This must be called on every
+/// connection, before `init`.
+pub(crate) fn define_functions(db: &Connection) -> Result<()> {
+    use rusqlite::functions::FunctionFlags;
+    db.create_scalar_function(
+        "logins_rev_host",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| -> rusqlite::Result<Option<String>> {
+            Ok(util::origin_rev_host(&ctx.get::<String>(0)?))
+        },
+    )?;
+    Ok(())
+}
 
 pub(crate) fn init(db: &Connection) -> Result<()> {
     let user_version = db.query_one::<i64>("PRAGMA user_version")?;
     log::warn!("user_version: {}", user_version);
     if user_version == 0 {
-        return create(db);
+        create(db)?;
+        return create_temp_triggers(db);
     }
     if user_version != VERSION {
         if user_version < VERSION {
@@ -213,6 +359,14 @@ pub(crate) fn init(db: &Connection) -> Result<()> {
             )
         }
     }
+    create_temp_triggers(db)
+}
+
+// The triggers call a function defined by this crate, so, like places, we
+// make them temporary rather than part of the schema. Tools which don't define
+// the function can still write to the database.
+fn create_temp_triggers(db: &Connection) -> Result<()> {
+    db.execute_batch(CREATE_REV_HOST_TRIGGERS_SQL)?;
     Ok(())
 }
 
@@ -233,9 +387,39 @@ fn upgrade(db: &Connection, from: i64) -> Result<()> {
         // Just one new nullable column makes this fairly easy
         db.execute_batch("ALTER TABLE loginsM ADD enc_unknown_fields TEXT;")?;
     }
+    if from <= 2 {
+        db.execute_all(&[
+            CREATE_ACCESS_LOG_TABLE_SQL,
+            CREATE_ACCESS_LOG_ORIGIN_INDEX_SQL,
+        ])?;
+    }
+    if from <= 3 {
+        db.execute_batch(
+            "ALTER TABLE loginsL ADD credentialType TINYINT NOT NULL DEFAULT 0;
+             ALTER TABLE loginsL ADD passkeyRpId TEXT;
+             ALTER TABLE loginsL ADD passkeyCredentialId TEXT;
+             ALTER TABLE loginsM ADD credentialType TINYINT NOT NULL DEFAULT 0;
+             ALTER TABLE loginsM ADD passkeyRpId TEXT;
+             ALTER TABLE loginsM ADD passkeyCredentialId TEXT;",
+        )?;
+    }
+    if from <= 4 {
+        db.execute_batch(&CREATE_DELETED_TABLE_SQL)?;
+    }
+    if from <= 5 {
+        db.execute_batch(
+            "ALTER TABLE loginsL ADD revHost TEXT;
+             ALTER TABLE loginsM ADD revHost TEXT;
+             UPDATE loginsL SET revHost = logins_rev_host(origin);
+             UPDATE loginsM SET revHost = logins_rev_host(origin);",
+        )?;
+        db.execute_all(&[
+            CREATE_OVERRIDE_REV_HOST_INDEX_SQL,
+            CREATE_DELETED_REV_HOST_INDEX_SQL,
+        ])?;
+    }
     // XXX - next migration, be sure to:
-    // from = 2;
-    // if from == 2 ...
+    // if from <= 6 ...
     db.execute_batch(&SET_VERSION_SQL)?;
     Ok(())
 }
@@ -247,7 +431,12 @@ pub(crate) fn create(db: &Connection) -> Result<()> {
         &*CREATE_MIRROR_TABLE_SQL,
         CREATE_OVERRIDE_ORIGIN_INDEX_SQL,
         CREATE_DELETED_ORIGIN_INDEX_SQL,
+        CREATE_OVERRIDE_REV_HOST_INDEX_SQL,
+        CREATE_DELETED_REV_HOST_INDEX_SQL,
         CREATE_META_TABLE_SQL,
+        CREATE_ACCESS_LOG_TABLE_SQL,
+        CREATE_ACCESS_LOG_ORIGIN_INDEX_SQL,
+        &*CREATE_DELETED_TABLE_SQL,
         &*SET_VERSION_SQL,
     ])?;
     Ok(())
@@ -274,6 +463,24 @@ mod tests {
         connection
             .execute_batch(
                 "
+                CREATE TABLE IF NOT EXISTS loginsL (
+                    -- this was common_sql as at v1
+                    id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+                    origin              TEXT NOT NULL,
+                    httpRealm           TEXT,
+                    formActionOrigin    TEXT,
+                    usernameField       TEXT,
+                    passwordField       TEXT,
+                    timesUsed           INTEGER NOT NULL DEFAULT 0,
+                    timeCreated         INTEGER NOT NULL,
+                    timeLastUsed        INTEGER,
+                    timePasswordChanged INTEGER NOT NULL,
+                    secFields           TEXT,
+                    guid                TEXT NOT NULL UNIQUE,
+                    local_modified      INTEGER,
+                    is_deleted          TINYINT NOT NULL DEFAULT 0,
+                    sync_status         TINYINT NOT NULL DEFAULT 0
+                );
                 CREATE TABLE IF NOT EXISTS loginsM (
                     -- this was common_sql as at v1
                     id                  INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -292,18 +499,25 @@ mod tests {
                     is_overridden       TINYINT NOT NULL DEFAULT 0
                     -- note enc_unknown_fields missing
                 );
+                INSERT INTO loginsL (origin, httpRealm, timeCreated, timePasswordChanged, guid)
+                VALUES ('https://www.example.com', 'realm', 1, 1, 'local');
+                INSERT INTO loginsM (origin, httpRealm, timeCreated, timePasswordChanged, guid,
+                                     server_modified)
+                VALUES ('https://[::1]:8080', 'realm', 1, 1, 'mirror', 1);
+                PRAGMA user_version = 1;
             ",
             )
             .unwrap();
-        // Call `create` to create the rest of the schema - the "if not exists" means loginsM
-        // will remain as v1.
-        create(&connection).unwrap();
-        // but that set the version to VERSION - set it back to 1 so our upgrade code runs.
+        // Create the rest of the v1 schema.
         connection
-            .execute_batch("PRAGMA user_version = 1;")
+            .execute_all(&[
+                CREATE_OVERRIDE_ORIGIN_INDEX_SQL,
+                CREATE_DELETED_ORIGIN_INDEX_SQL,
+                CREATE_META_TABLE_SQL,
+            ])
             .unwrap();
 
-        // Now open the DB - it will create loginsL for us and migrate loginsM.
+        // Now open the DB - it will migrate loginsL and loginsM.
         let db = LoginDb::with_connection(connection).unwrap();
         // all migrations should have succeeded.
         let version = db.query_one::<i64>("PRAGMA user_version").unwrap();
@@ -312,5 +526,67 @@ mod tests {
         // and ensure sql selecting the new column works.
         db.execute_batch("SELECT enc_unknown_fields FROM loginsM")
             .unwrap();
+        // and the access log table was created.
+        db.execute_batch("SELECT guid, origin, accessType, timeAccessed FROM loginsAccessLog")
+            .unwrap();
+        // and the passkey columns were added to both tables.
+        for table in ["loginsL", "loginsM"] {
+            assert_eq!(
+                db.query_one::<i64>(&format!(
+                    "SELECT COUNT(*) FROM pragma_table_info('{table}')
+                     WHERE name IN ('credentialType', 'passkeyRpId', 'passkeyCredentialId')"
+                ))
+                .unwrap(),
+                3
+            );
+        }
+        // and the recently deleted table was created.
+        db.execute_batch("SELECT guid, secFields, timeDeleted FROM loginsDeleted")
+            .unwrap();
+        // and `revHost` was backfilled.
+        assert_eq!(
+            db.query_one::<String>("SELECT revHost FROM loginsL")
+                .unwrap(),
+            "moc.elpmaxe.www."
+        );
+        assert_eq!(
+            db.query_one::<String>("SELECT revHost FROM loginsM")
+                .unwrap(),
+            "[::1]"
+        );
+    }
+
+    #[test]
+    fn test_rev_host_maintained() {
+        let db = LoginDb::open_in_memory().unwrap();
+        db.execute_batch(
+            "INSERT INTO loginsM (origin, httpRealm, timeCreated, timePasswordChanged, guid,
+                                  server_modified)
+             VALUES ('https://example.com', 'realm', 1, 1, 'guid', 1);
+             INSERT INTO loginsL (origin, httpRealm, timeCreated, timePasswordChanged, guid)
+             SELECT origin, httpRealm, timeCreated, timePasswordChanged, guid FROM loginsM;",
+        )
+        .unwrap();
+        for table in ["loginsL", "loginsM"] {
+            assert_eq!(
+                db.query_one::<String>(&format!("SELECT revHost FROM {table}"))
+                    .unwrap(),
+                "moc.elpmaxe."
+            );
+        }
+        db.execute_batch("UPDATE loginsL SET origin = 'http://127.0.0.1'")
+            .unwrap();
+        assert_eq!(
+            db.query_one::<String>("SELECT revHost FROM loginsL")
+                .unwrap(),
+            "127.0.0.1"
+        );
+        // Deleted logins have an empty origin.
+        db.execute_batch("UPDATE loginsL SET origin = ''").unwrap();
+        assert_eq!(
+            db.query_one::<Option<String>>("SELECT revHost FROM loginsL")
+                .unwrap(),
+            None
+        );
     }
 }
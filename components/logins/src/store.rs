@@ -1,14 +1,19 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+use crate::access_log::{LoginAccessRecord, LoginAccessType};
 use crate::db::LoginDb;
-use crate::encryption::EncryptorDecryptor;
+use crate::encryption::{EncryptorDecryptor, ManagedEncryptorDecryptor, StaticKeyManager};
 use crate::error::*;
 use crate::login::{Login, LoginEntry};
+use crate::password_health::LoginPasswordHash;
+use crate::recently_deleted::DeletedLogin;
+use crate::rekey::RekeyResult;
 use crate::LoginsSyncEngine;
 use parking_lot::Mutex;
 use std::path::Path;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use sync15::engine::{EngineSyncAssociation, SyncEngine, SyncEngineId};
 
 // Our "sync manager" will use whatever is stashed here.
@@ -86,11 +91,26 @@ impl LoginStore {
         })
     }
 
+    #[handle_error(Error)]
+    pub fn list_passkeys(&self) -> ApiResult<Vec<Login>> {
+        self.db.lock().get_all_passkeys().and_then(|logins| {
+            logins
+                .into_iter()
+                .map(|login| login.decrypt(self.encdec.as_ref()))
+                .collect()
+        })
+    }
+
     #[handle_error(Error)]
     pub fn get(&self, id: &str) -> ApiResult<Option<Login>> {
-        match self.db.lock().get_by_id(id) {
+        let db = self.db.lock();
+        match db.get_by_id(id) {
             Ok(result) => match result {
-                Some(enc_login) => enc_login.decrypt(self.encdec.as_ref()).map(Some),
+                Some(enc_login) => {
+                    let login = enc_login.decrypt(self.encdec.as_ref())?;
+                    db.note_access(id, &login.origin, LoginAccessType::Read);
+                    Ok(Some(login))
+                }
                 None => Ok(None),
             },
             Err(err) => Err(err),
@@ -118,6 +138,24 @@ impl LoginStore {
             .map(|logins| !logins.is_empty())
     }
 
+    #[handle_error(Error)]
+    pub fn find_logins_for_origin(
+        &self,
+        origin: &str,
+        include_forms: bool,
+        include_http: bool,
+    ) -> ApiResult<Vec<Login>> {
+        self.db
+            .lock()
+            .find_logins_for_origin(origin, include_forms, include_http)
+            .and_then(|logins| {
+                logins
+                    .into_iter()
+                    .map(|login| login.decrypt(self.encdec.as_ref()))
+                    .collect()
+            })
+    }
+
     #[handle_error(Error)]
     pub fn find_login_to_update(&self, entry: LoginEntry) -> ApiResult<Option<Login>> {
         self.db
@@ -127,7 +165,55 @@ impl LoginStore {
 
     #[handle_error(Error)]
     pub fn touch(&self, id: &str) -> ApiResult<()> {
-        self.db.lock().touch(id)
+        let db = self.db.lock();
+        db.touch(id)?;
+        if let Some(login) = db.get_by_id(id)? {
+            db.note_access(id, &login.fields.origin, LoginAccessType::Fill);
+        }
+        Ok(())
+    }
+
+    /// Enable or disable the access log. Disabling it also clears it.
+    #[handle_error(Error)]
+    pub fn set_access_log_enabled(&self, enabled: bool) -> ApiResult<()> {
+        self.db.lock().set_access_log_enabled(enabled)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_reused_passwords(&self) -> ApiResult<Vec<String>> {
+        self.db.lock().get_reused_passwords(self.encdec.as_ref())
+    }
+
+    #[handle_error(Error)]
+    pub fn find_logins_with_password_hash(
+        &self,
+        prefixes: Vec<String>,
+    ) -> ApiResult<Vec<LoginPasswordHash>> {
+        self.db
+            .lock()
+            .find_logins_with_password_hash(&prefixes, self.encdec.as_ref())
+    }
+
+    /// Re-encrypt everything in the database from `old_key` to `new_key`. The
+    /// store's `EncryptorDecryptor` must use `new_key` afterwards.
+    #[handle_error(Error)]
+    pub fn rekey_database(&self, old_key: &str, new_key: &str) -> ApiResult<RekeyResult> {
+        let old = ManagedEncryptorDecryptor::new(Arc::new(StaticKeyManager::new(old_key.into())));
+        let new = ManagedEncryptorDecryptor::new(Arc::new(StaticKeyManager::new(new_key.into())));
+        self.db.lock().rekey(&old, &new)
+    }
+
+    /// Set whether the sync server supports passkeys. Until it does, passkeys
+    /// aren't uploaded, so that clients which don't know about them never see them.
+    /// The setting is kept until it's next set.
+    #[handle_error(Error)]
+    pub fn set_passkey_sync_enabled(&self, enabled: bool) -> ApiResult<()> {
+        self.db.lock().set_passkey_sync_enabled(enabled)
+    }
+
+    #[handle_error(Error)]
+    pub fn get_access_log(&self, origin: &str) -> ApiResult<Vec<LoginAccessRecord>> {
+        self.db.lock().get_access_log(origin)
     }
 
     #[handle_error(Error)]
@@ -135,6 +221,27 @@ impl LoginStore {
         self.db.lock().delete(id)
     }
 
+    /// Returns the deleted logins which can still be restored with
+    /// `undo_delete()`, most recently deleted first.
+    #[handle_error(Error)]
+    pub fn get_recently_deleted(&self) -> ApiResult<Vec<DeletedLogin>> {
+        self.db.lock().get_recently_deleted(self.encdec.as_ref())
+    }
+
+    #[handle_error(Error)]
+    pub fn undo_delete(&self, id: &str) -> ApiResult<Login> {
+        self.db.lock().undo_delete(id, self.encdec.as_ref())
+    }
+
+    /// Set how many days deleted logins can be restored for. 0 disables undo,
+    /// and forgets any deleted logins which are being kept.
+    #[handle_error(Error)]
+    pub fn set_recently_deleted_retention_days(&self, days: u32) -> ApiResult<()> {
+        self.db
+            .lock()
+            .set_recently_deleted_retention(Duration::from_secs(u64::from(days) * 24 * 60 * 60))
+    }
+
     #[handle_error(Error)]
     pub fn wipe_local(&self) -> ApiResult<()> {
         self.db.lock().wipe_local()?;
@@ -330,6 +437,42 @@ mod test {
         assert_eq!(b_after_update.times_used, 2);
     }
 
+    #[test]
+    fn test_access_log() {
+        let store = LoginStore::new_in_memory(TEST_ENCDEC.clone()).unwrap();
+        let id = store
+            .add(LoginEntry {
+                origin: "https://www.example.com".into(),
+                http_realm: Some("https://www.example.com".into()),
+                username: "user".into(),
+                password: "pass".into(),
+                ..Default::default()
+            })
+            .unwrap()
+            .id;
+        store.get(&id).unwrap();
+        assert!(store
+            .get_access_log("https://www.example.com")
+            .unwrap()
+            .is_empty());
+
+        store.set_access_log_enabled(true).unwrap();
+        store.get(&id).unwrap();
+        store.touch(&id).unwrap();
+        let log = store.get_access_log("https://www.example.com").unwrap();
+        assert_eq!(
+            log.iter().map(|r| r.access_type).collect::<Vec<_>>(),
+            vec![LoginAccessType::Fill, LoginAccessType::Read]
+        );
+        assert!(log.iter().all(|r| r.id == id));
+
+        store.delete(&id).unwrap();
+        assert!(store
+            .get_access_log("https://www.example.com")
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_sync_manager_registration() {
         let store = Arc::new(LoginStore::new_in_memory(TEST_ENCDEC.clone()).unwrap());
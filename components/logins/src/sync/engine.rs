@@ -4,26 +4,39 @@
 
 use super::merge::{LocalLogin, MirrorLogin, SyncLoginData};
 use super::update_plan::UpdatePlan;
-use super::SyncStatus;
+use super::{LoginPayload, SyncStatus};
 use crate::db::CLONE_ENTIRE_MIRROR_SQL;
 use crate::error::*;
-use crate::login::EncryptedLogin;
+use crate::login::{CredentialType, EncryptedLogin};
 use crate::schema;
 use crate::util;
 use crate::LoginDb;
 use crate::LoginStore;
-use interrupt_support::SqlInterruptScope;
+use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use rusqlite::named_params;
 use sql_support::ConnExt;
 use std::cell::RefCell;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{Duration, UNIX_EPOCH};
-use sync15::bso::{IncomingBso, OutgoingBso, OutgoingEnvelope};
-use sync15::engine::{CollSyncIds, CollectionRequest, EngineSyncAssociation, SyncEngine};
+use sync15::bso::{IncomingBso, IncomingKind, OutgoingBso, OutgoingEnvelope};
+use sync15::engine::{
+    CollSyncIds, CollectionRequest, EngineSyncAssociation, IncomingValidation, SyncEngine,
+};
 use sync15::{telemetry, ServerTimestamp};
 use sync_guid::Guid;
 
+// Why we couldn't apply an incoming record, as reported in the telemetry.
+fn incoming_failure_reason(e: &Error) -> &'static str {
+    match e {
+        Error::MalformedIncomingRecord | Error::JsonError(_) => "malformed-record",
+        Error::InvalidLogin(InvalidLogin::IllegalOrigin) => "illegal-origin",
+        Error::InvalidLogin(_) => "invalid-login",
+        Error::EncryptionFailed(_) | Error::DecryptionFailed(_) | Error::CryptoError(_) => "crypto",
+        _ => "other",
+    }
+}
+
 // The sync engine.
 pub struct LoginsSyncEngine {
     pub store: Arc<LoginStore>,
@@ -152,7 +165,7 @@ impl LoginsSyncEngine {
                         };
                         // Ideally we'd track new_failed, but it's unclear how
                         // much value it has.
-                        telem.failed(1);
+                        telem.failed_with_reason(1, incoming_failure_reason(&e));
                     }
                 }
                 seen_ids.insert(id);
@@ -236,13 +249,22 @@ impl LoginsSyncEngine {
         const TOMBSTONE_SORTINDEX: i32 = 5_000_000;
         const DEFAULT_SORTINDEX: i32 = 1;
         let db = self.store.db.lock();
+        // Passkeys stay local until the server can accept them, and are uploaded on the first
+        // sync after it can, since they're still marked as changed. Credentials of types we
+        // don't know are never uploaded, since we'd replace their type with "unknown".
         let mut stmt = db.prepare_cached(&format!(
             "SELECT L.*, M.enc_unknown_fields
              FROM loginsL L LEFT JOIN loginsM M ON L.guid = M.guid
-             WHERE sync_status IS NOT {synced}",
-            synced = SyncStatus::Synced as u8
+             WHERE sync_status IS NOT {synced}
+               AND (L.is_deleted
+                    OR L.credentialType = {password}
+                    OR (L.credentialType = {passkey} AND :passkey_sync_enabled))",
+            synced = SyncStatus::Synced as u8,
+            password = CredentialType::Password as u8,
+            passkey = CredentialType::Passkey as u8,
         ))?;
-        let bsos = stmt.query_and_then([], |row| {
+        let params = named_params! { ":passkey_sync_enabled": db.passkey_sync_enabled };
+        let bsos = stmt.query_and_then(params, |row| {
             self.scope.err_if_interrupted()?;
             Ok(if row.get::<_, bool>("is_deleted")? {
                 let envelope = OutgoingEnvelope {
@@ -341,6 +363,16 @@ impl LoginsSyncEngine {
                 rusqlite::params_from_iter(chunk),
             )?;
             self.scope.err_if_interrupted()?;
+
+            // Once a deletion is on the server, it can't be undone.
+            db.execute(
+                &format!(
+                    "DELETE FROM loginsDeleted WHERE guid IN ({vars})",
+                    vars = sql_support::repeat_sql_vars(chunk.len())
+                ),
+                rusqlite::params_from_iter(chunk),
+            )?;
+            self.scope.err_if_interrupted()?;
             Ok(())
         })?;
         self.set_last_sync(&db, ts)?;
@@ -425,6 +457,35 @@ impl SyncEngine for LoginsSyncEngine {
         "passwords".into()
     }
 
+    fn sql_interrupt_handle(&self) -> Option<Arc<SqlInterruptHandle>> {
+        Some(self.store.db.lock().new_interrupt_handle())
+    }
+
+    fn validate_incoming(&self, record: &IncomingBso) -> anyhow::Result<IncomingValidation> {
+        if !matches!(
+            record.clone().into_content::<LoginPayload>().kind,
+            IncomingKind::Malformed
+        ) {
+            return Ok(IncomingValidation::Valid);
+        }
+        // If we have a good copy of the login, upload it over the corrupt one. Passkeys stay
+        // local until the server can accept them, as in `fetch_outgoing()`.
+        let db = self.store.db.lock();
+        let repair = db
+            .get_by_id(record.envelope.id.as_str())?
+            .filter(|login| match login.fields.credential_type {
+                CredentialType::Password => true,
+                CredentialType::Passkey => db.passkey_sync_enabled,
+                CredentialType::Unknown => false,
+            })
+            .map(|login| login.into_bso(self.store.encdec.as_ref(), None))
+            .transpose()?;
+        Ok(IncomingValidation::Invalid {
+            reason: "malformed login",
+            repair,
+        })
+    }
+
     fn stage_incoming(
         &self,
         mut inbound: Vec<IncomingBso>,
@@ -650,6 +711,34 @@ mod tests {
         assert!(changes["changed"].get("deleted").is_none());
     }
 
+    #[test]
+    fn test_fetch_outgoing_passkeys() {
+        let store = Arc::new(LoginStore::new_in_memory(TEST_ENCDEC.clone()).unwrap());
+        store
+            .add(LoginEntry {
+                origin: "https://www.example.com".into(),
+                credential_type: CredentialType::Passkey,
+                passkey_rp_id: Some("example.com".into()),
+                passkey_credential_id: Some("Y3JlZA".into()),
+                passkey_private_key: Some("a2V5".into()),
+                username: "user".into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let engine = LoginsSyncEngine::new(store.clone()).unwrap();
+
+        // Passkeys aren't uploaded until the server supports them...
+        assert!(engine.fetch_outgoing().unwrap().is_empty());
+
+        // ...and are then, since they're still changed.
+        store.set_passkey_sync_enabled(true).unwrap();
+        let outgoing = engine.fetch_outgoing().unwrap();
+        assert_eq!(outgoing.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&outgoing[0].payload).unwrap();
+        assert_eq!(payload["credentialType"], "passkey");
+        assert_eq!(payload["passkeyRpId"], "example.com");
+    }
+
     #[test]
     fn test_bad_record() {
         let store = LoginStore::new_in_memory(TEST_ENCDEC.clone()).unwrap();
@@ -682,12 +771,68 @@ mod tests {
             ],
         );
         assert_eq!(telem.get_failed(), 1);
+        assert_eq!(telem.get_failed_reasons().get("malformed-record"), Some(&1));
         assert_eq!(res.len(), 2);
         assert_eq!(res[0].guid, "dummy_000001");
         assert_eq!(res[1].guid, "dummy_000003");
         assert_eq!(engine.fetch_outgoing().unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_validate_incoming() {
+        let store = LoginStore::new_in_memory(TEST_ENCDEC.clone()).unwrap();
+        insert_login(
+            &store.db.lock(),
+            "dummy_000001",
+            Some("password"),
+            Some("password"),
+        );
+        let engine = LoginsSyncEngine::new(Arc::new(store)).unwrap();
+
+        let valid = IncomingBso::from_test_content(serde_json::json!({
+            "id": "dummy_000001",
+            "hostname": "https://www.example.com",
+            "username": "test",
+            "password": "test",
+        }));
+        assert!(matches!(
+            engine.validate_incoming(&valid).unwrap(),
+            IncomingValidation::Valid
+        ));
+        let tombstone = IncomingBso::new_test_tombstone(Guid::new("dummy_000002"));
+        assert!(matches!(
+            engine.validate_incoming(&tombstone).unwrap(),
+            IncomingValidation::Valid
+        ));
+
+        // A corrupt copy of a login we have is repaired with our copy.
+        let corrupt = IncomingBso::from_test_content(serde_json::json!({
+            "id": "dummy_000001",
+            "garbage": "data",
+        }));
+        match engine.validate_incoming(&corrupt).unwrap() {
+            IncomingValidation::Invalid {
+                repair: Some(repair),
+                ..
+            } => {
+                assert_eq!(repair.envelope.id, "dummy_000001");
+                let payload: serde_json::Value = serde_json::from_str(&repair.payload).unwrap();
+                assert_eq!(payload["password"], "password");
+            }
+            other => panic!("unexpected validation {:?}", other),
+        }
+
+        // We can't repair a login we don't have.
+        let corrupt = IncomingBso::from_test_content(serde_json::json!({
+            "id": "dummy_000003",
+            "garbage": "data",
+        }));
+        assert!(matches!(
+            engine.validate_incoming(&corrupt).unwrap(),
+            IncomingValidation::Invalid { repair: None, .. }
+        ));
+    }
+
     fn make_enc_login(
         username: &str,
         password: &str,
@@ -708,6 +853,7 @@ mod tests {
             sec_fields: SecureLoginFields {
                 username: username.into(),
                 password: password.into(),
+                ..Default::default()
             }
             .encrypt(&*TEST_ENCDEC)
             .unwrap(),
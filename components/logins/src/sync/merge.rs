@@ -218,6 +218,8 @@ pub(crate) struct LoginDelta {
 
     // Commutative field
     pub times_used: i64,
+    // The credential type and passkey fields aren't here, since they're fixed when a
+    // login is created.
 }
 
 macro_rules! merge_field {
@@ -11,7 +11,7 @@ use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use crate::login::ValidateAndFixup;
 use crate::SecureLoginFields;
-use crate::{EncryptedLogin, LoginEntry, LoginFields, RecordFields};
+use crate::{CredentialType, EncryptedLogin, LoginEntry, LoginFields, RecordFields};
 use serde_derive::*;
 use sync15::bso::OutgoingBso;
 use sync_guid::Guid;
@@ -70,10 +70,15 @@ impl IncomingLogin {
             http_realm: p.http_realm,
             username_field: p.username_field,
             password_field: p.password_field,
+            credential_type: p.credential_type,
+            passkey_rp_id: p.passkey_rp_id,
+            passkey_credential_id: p.passkey_credential_id,
         };
         let original_sec_fields = SecureLoginFields {
             username: p.username,
             password: p.password,
+            passkey_user_handle: p.passkey_user_handle,
+            passkey_private_key: p.passkey_private_key,
         };
         // we do a bit of a dance here to maybe_fixup() the fields via LoginEntry
         let original_login_entry = LoginEntry::new(original_fields, original_sec_fields);
@@ -86,10 +91,15 @@ impl IncomingLogin {
             http_realm: login_entry.http_realm,
             username_field: login_entry.username_field,
             password_field: login_entry.password_field,
+            credential_type: login_entry.credential_type,
+            passkey_rp_id: login_entry.passkey_rp_id,
+            passkey_credential_id: login_entry.passkey_credential_id,
         };
         let sec_fields = SecureLoginFields {
             username: login_entry.username,
             password: login_entry.password,
+            passkey_user_handle: login_entry.passkey_user_handle,
+            passkey_private_key: login_entry.passkey_private_key,
         };
 
         // We handle NULL in the DB for migrated databases and it's wasteful
@@ -164,6 +174,23 @@ pub struct LoginPayload {
     #[serde(default)]
     pub times_used: i64,
 
+    // The passkey fields are only present for passkeys, and legacy clients which don't
+    // know about them will never see them (see `LoginsSyncEngine::fetch_outgoing()`).
+    #[serde(default, skip_serializing_if = "is_password")]
+    pub credential_type: CredentialType,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passkey_rp_id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passkey_credential_id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passkey_user_handle: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passkey_private_key: Option<String>,
+
     // Additional "unknown" round-tripped fields.
     #[serde(flatten)]
     unknown_fields: UnknownFields,
@@ -191,6 +218,11 @@ impl EncryptedLogin {
                 password_field: self.fields.password_field,
                 username: sec_fields.username,
                 password: sec_fields.password,
+                credential_type: self.fields.credential_type,
+                passkey_rp_id: self.fields.passkey_rp_id,
+                passkey_credential_id: self.fields.passkey_credential_id,
+                passkey_user_handle: sec_fields.passkey_user_handle,
+                passkey_private_key: sec_fields.passkey_private_key,
                 time_created: self.record.time_created,
                 time_password_changed: self.record.time_password_changed,
                 time_last_used: self.record.time_last_used,
@@ -201,6 +233,10 @@ impl EncryptedLogin {
     }
 }
 
+fn is_password(credential_type: &CredentialType) -> bool {
+    *credential_type == CredentialType::Password
+}
+
 // Quiet clippy, since this function is passed to deserialiaze_with...
 #[allow(clippy::unnecessary_wraps)]
 fn deserialize_timestamp<'de, D>(deserializer: D) -> std::result::Result<i64, D::Error>
@@ -365,6 +401,7 @@ mod tests {
             sec_fields: encrypt_struct(&SecureLoginFields {
                 username: "user".into(),
                 password: "password".into(),
+                ..Default::default()
             }),
         };
         let bso = login.into_bso(&*TEST_ENCDEC, None).unwrap();
@@ -430,4 +467,81 @@ mod tests {
         .login;
         assert_eq!(login.fields.password_field, "");
     }
+
+    #[test]
+    fn test_unknown_credential_type() {
+        let bso = IncomingBso::from_test_content(serde_json::json!({
+            "id": "123412341234",
+            "hostname": "https://www.example.com",
+            "username": "user",
+            "password": "",
+            "credentialType": "something-new",
+        }));
+        let login = IncomingLogin::from_incoming_payload(
+            bso.into_content::<LoginPayload>().content().unwrap(),
+            &*TEST_ENCDEC,
+        )
+        .unwrap()
+        .login;
+        assert_eq!(login.fields.credential_type, CredentialType::Unknown);
+    }
+
+    #[test]
+    fn test_passkey_payload_roundtrip() {
+        let bso = IncomingBso::from_test_content(serde_json::json!({
+            "id": "123412341234",
+            "hostname": "https://www.example.com",
+            "username": "user",
+            "password": "",
+            "credentialType": "passkey",
+            "passkeyRpId": "example.com",
+            "passkeyCredentialId": "Y3JlZA",
+            "passkeyUserHandle": "dXNlcg",
+            "passkeyPrivateKey": "a2V5",
+        }));
+        let login = IncomingLogin::from_incoming_payload(
+            bso.into_content::<LoginPayload>().content().unwrap(),
+            &*TEST_ENCDEC,
+        )
+        .unwrap()
+        .login;
+        assert_eq!(login.fields.credential_type, CredentialType::Passkey);
+        assert_eq!(login.fields.passkey_rp_id, Some("example.com".into()));
+        assert_eq!(login.fields.passkey_credential_id, Some("Y3JlZA".into()));
+        // The secrets stay encrypted.
+        let sec_fields = login.decrypt_fields(&*TEST_ENCDEC).unwrap();
+        assert_eq!(sec_fields.passkey_user_handle, Some("dXNlcg".into()));
+        assert_eq!(sec_fields.passkey_private_key, Some("a2V5".into()));
+
+        let outgoing = login.into_bso(&*TEST_ENCDEC, None).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&outgoing.payload).unwrap();
+        assert_eq!(json["credentialType"], "passkey");
+        assert_eq!(json["passkeyCredentialId"], "Y3JlZA");
+        assert_eq!(json["passkeyPrivateKey"], "a2V5");
+    }
+
+    #[test]
+    fn test_password_payload_has_no_passkey_fields() {
+        let login = EncryptedLogin {
+            record: RecordFields {
+                id: "123412341234".into(),
+                ..Default::default()
+            },
+            fields: LoginFields {
+                origin: "https://www.example.com".into(),
+                http_realm: Some("test".into()),
+                ..Default::default()
+            },
+            sec_fields: encrypt_struct(&SecureLoginFields {
+                username: "user".into(),
+                password: "password".into(),
+                ..Default::default()
+            }),
+        };
+        let outgoing = login.into_bso(&*TEST_ENCDEC, None).unwrap();
+        let json: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&outgoing.payload).unwrap();
+        assert!(json.keys().all(|key| !key.starts_with("passkey")));
+        assert!(!json.contains_key("credentialType"));
+    }
 }
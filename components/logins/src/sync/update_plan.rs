@@ -127,12 +127,16 @@ impl UpdatePlan {
             Ok(())
         })?;
 
+        // Only incoming tombstones delete from the mirror, so these logins are gone for good, and
+        // so are their access log entries.
         sql_support::each_chunk(&self.delete_mirror, |chunk, _| {
+            let vars = sql_support::repeat_sql_vars(chunk.len());
             conn.execute(
-                &format!(
-                    "DELETE FROM loginsM WHERE guid IN ({vars})",
-                    vars = sql_support::repeat_sql_vars(chunk.len())
-                ),
+                &format!("DELETE FROM loginsM WHERE guid IN ({vars})"),
+                rusqlite::params_from_iter(chunk),
+            )?;
+            conn.execute(
+                &format!("DELETE FROM loginsAccessLog WHERE guid IN ({vars})"),
                 rusqlite::params_from_iter(chunk),
             )?;
             Ok(())
@@ -149,6 +153,9 @@ impl UpdatePlan {
                 formActionOrigin = :form_action_origin,
                 usernameField    = :username_field,
                 passwordField    = :password_field,
+                credentialType   = :credential_type,
+                passkeyRpId      = :passkey_rp_id,
+                passkeyCredentialId = :passkey_credential_id,
                 origin           = :origin,
                 secFields        = :sec_fields,
                 -- Avoid zeroes if the remote has been overwritten by an older client.
@@ -169,6 +176,9 @@ impl UpdatePlan {
                 ":form_action_origin": login.fields.form_action_origin,
                 ":username_field": login.fields.username_field,
                 ":password_field": login.fields.password_field,
+                ":credential_type": login.fields.credential_type as u8,
+                ":passkey_rp_id": login.fields.passkey_rp_id,
+                ":passkey_credential_id": login.fields.passkey_credential_id,
                 ":origin": login.fields.origin,
                 ":times_used": login.record.times_used,
                 ":time_last_used": login.record.time_last_used,
@@ -193,6 +203,9 @@ impl UpdatePlan {
                 formActionOrigin,
                 usernameField,
                 passwordField,
+                credentialType,
+                passkeyRpId,
+                passkeyCredentialId,
                 origin,
                 secFields,
 
@@ -211,6 +224,9 @@ impl UpdatePlan {
                 :form_action_origin,
                 :username_field,
                 :password_field,
+                :credential_type,
+                :passkey_rp_id,
+                :passkey_credential_id,
                 :origin,
                 :sec_fields,
 
@@ -234,6 +250,9 @@ impl UpdatePlan {
                 ":form_action_origin": login.fields.form_action_origin,
                 ":username_field": login.fields.username_field,
                 ":password_field": login.fields.password_field,
+                ":credential_type": login.fields.credential_type as u8,
+                ":passkey_rp_id": login.fields.passkey_rp_id,
+                ":passkey_credential_id": login.fields.passkey_credential_id,
                 ":origin": login.fields.origin,
                 ":times_used": login.record.times_used,
                 ":time_last_used": login.record.time_last_used,
@@ -255,6 +274,9 @@ impl UpdatePlan {
                  formActionOrigin    = :form_action_origin,
                  usernameField       = :username_field,
                  passwordField       = :password_field,
+                 credentialType      = :credential_type,
+                 passkeyRpId         = :passkey_rp_id,
+                 passkeyCredentialId = :passkey_credential_id,
                  timeLastUsed        = :time_last_used,
                  timePasswordChanged = :time_password_changed,
                  timesUsed           = :times_used,
@@ -276,6 +298,9 @@ impl UpdatePlan {
                 ":form_action_origin": l.login.fields.form_action_origin,
                 ":username_field": l.login.fields.username_field,
                 ":password_field": l.login.fields.password_field,
+                ":credential_type": l.login.fields.credential_type as u8,
+                ":passkey_rp_id": l.login.fields.passkey_rp_id,
+                ":passkey_credential_id": l.login.fields.passkey_credential_id,
                 ":origin": l.login.fields.origin,
                 ":time_last_used": l.login.record.time_last_used,
                 ":time_password_changed": l.login.record.time_password_changed,
@@ -325,6 +350,7 @@ mod tests {
     use crate::db::LoginDb;
     use crate::encryption::test_utils::TEST_ENCDEC;
     use crate::login::test_utils::enc_login;
+    use sql_support::ConnExt;
 
     fn inc_login(id: &str, password: &str) -> crate::sync::IncomingLogin {
         IncomingLogin {
@@ -340,6 +366,13 @@ mod tests {
         insert_login(&db, "login2", Some("password"), Some("password"));
         insert_login(&db, "login3", Some("password"), Some("password"));
         insert_login(&db, "login4", Some("password"), Some("password"));
+        db.execute(
+            "INSERT INTO loginsAccessLog (guid, origin, accessType, timeAccessed)
+             VALUES ('login1', 'https://example.com', 0, 1000),
+                    ('login3', 'https://example.com', 0, 1000)",
+            [],
+        )
+        .unwrap();
 
         UpdatePlan {
             delete_mirror: vec![Guid::new("login1"), Guid::new("login2")],
@@ -351,6 +384,11 @@ mod tests {
 
         assert_eq!(get_local_guids(&db), vec!["login1", "login4"]);
         assert_eq!(get_mirror_guids(&db), vec!["login3", "login4"]);
+        // Access log entries are removed for the logins deleted by tombstones.
+        let logged: Vec<String> = db
+            .query_rows_and_then("SELECT guid FROM loginsAccessLog", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(logged, vec!["login3"]);
     }
 
     #[test]
@@ -5,7 +5,7 @@
 use crate::error::*;
 use rusqlite::Row;
 use std::time;
-use url::Url;
+use url::{Host, Url};
 
 pub fn url_host_port(url_str: &str) -> Option<String> {
     let url = Url::parse(url_str).ok()?;
@@ -17,6 +17,25 @@ pub fn url_host_port(url_str: &str) -> Option<String> {
     })
 }
 
+/// The form of a host we store in the `revHost` column: domains are reversed,
+/// with a trailing `.`, so that a domain and its subdomains share a prefix,
+/// and IP addresses are unchanged.
+pub fn rev_host<S: AsRef<str>>(host: &Host<S>) -> String {
+    match host {
+        Host::Domain(domain) => {
+            let mut rev: String = domain.as_ref().chars().rev().collect();
+            rev.push('.');
+            rev
+        }
+        _ => host.to_string(),
+    }
+}
+
+/// The `rev_host` of an origin's host, or `None` if it doesn't have one.
+pub fn origin_rev_host(origin: &str) -> Option<String> {
+    Url::parse(origin).ok()?.host().map(|host| rev_host(&host))
+}
+
 pub fn system_time_millis_from_row(row: &Row<'_>, col_name: &str) -> Result<time::SystemTime> {
     let time_ms = row.get::<_, Option<i64>>(col_name)?.unwrap_or_default() as u64;
     Ok(time::UNIX_EPOCH + time::Duration::from_millis(time_ms))
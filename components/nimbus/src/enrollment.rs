@@ -4,7 +4,7 @@
 use crate::{
     defaults::Defaults,
     error::{NimbusError, Result},
-    evaluator::evaluate_enrollment,
+    evaluator::{evaluate_enrollment, met_unenrollment_criterion},
     json, AvailableRandomizationUnits, Experiment, FeatureConfig, NimbusTargetingHelper,
     SLUG_REPLACEMENT_PATTERN,
 };
@@ -56,6 +56,9 @@ pub enum NotEnrolledReason {
     EnrollmentsPaused,
     /// The experiment used a feature that was already under experiment.
     FeatureConflict,
+    /// The experiment is triggered on feature access, and none of its features
+    /// have been accessed yet.
+    AwaitingFeatureAccess,
 }
 
 impl Display for NotEnrolledReason {
@@ -67,6 +70,7 @@ impl Display for NotEnrolledReason {
                 NotEnrolledReason::NotTargeted => "NotTargeted",
                 NotEnrolledReason::EnrollmentsPaused => "EnrollmentsPaused",
                 NotEnrolledReason::FeatureConflict => "FeatureConflict",
+                NotEnrolledReason::AwaitingFeatureAccess => "AwaitingFeatureAccess",
             },
             f,
         )
@@ -87,6 +91,8 @@ pub enum DisqualifiedReason {
     NotTargeted,
     /// The bucketing has changed for an experiment.
     NotSelected,
+    /// One of the experiment's unenrollment criteria was met.
+    UnenrollmentCriterion { slug: String },
 }
 
 impl Display for DisqualifiedReason {
@@ -97,6 +103,7 @@ impl Display for DisqualifiedReason {
                 DisqualifiedReason::OptOut => "OptOut",
                 DisqualifiedReason::NotSelected => "NotSelected",
                 DisqualifiedReason::NotTargeted => "NotTargeted",
+                DisqualifiedReason::UnenrollmentCriterion { .. } => "UnenrollmentCriterion",
             },
             f,
         )
@@ -152,6 +159,15 @@ impl ExperimentEnrollment {
         })
     }
 
+    fn awaiting_feature_access(slug: &str) -> Self {
+        Self {
+            slug: slug.to_owned(),
+            status: EnrollmentStatus::NotEnrolled {
+                reason: NotEnrolledReason::AwaitingFeatureAccess,
+            },
+        }
+    }
+
     /// Force enroll ourselves in an experiment.
     #[cfg_attr(not(feature = "stateful"), allow(unused))]
     pub(crate) fn from_explicit_opt_in(
@@ -237,6 +253,11 @@ impl ExperimentEnrollment {
                     // we check if we opted-in an experiment, if so
                     // we don't need to update our enrollment
                     self.clone()
+                } else if let Some(updated_enrollment) =
+                    self.check_unenrollment_criteria(updated_experiment, targeting_helper)
+                {
+                    out_enrollment_events.push(updated_enrollment.get_change_event());
+                    updated_enrollment
                 } else {
                     let evaluated_enrollment = evaluate_enrollment(
                         available_randomization_units,
@@ -314,6 +335,38 @@ impl ExperimentEnrollment {
         })
     }
 
+    /// If one of the experiment's unenrollment criteria is met, returns our enrollment
+    /// disqualified because of it.
+    fn check_unenrollment_criteria(
+        &self,
+        experiment: &Experiment,
+        targeting_helper: &NimbusTargetingHelper,
+    ) -> Option<Self> {
+        match met_unenrollment_criterion(experiment, targeting_helper) {
+            Ok(None) => None,
+            Ok(Some(criterion)) => {
+                log::debug!(
+                    "Existing experiment enrollment '{}' is now disqualified (unenrollment criterion '{}')",
+                    &self.slug,
+                    &criterion.slug
+                );
+                Some(
+                    self.disqualify_from_enrolled(DisqualifiedReason::UnenrollmentCriterion {
+                        slug: criterion.slug.clone(),
+                    }),
+                )
+            }
+            Err(e) => {
+                log::warn!(
+                    "Error evaluating unenrollment criteria for '{}': {}",
+                    &self.slug,
+                    e
+                );
+                Some(self.disqualify_from_enrolled(DisqualifiedReason::Error))
+            }
+        }
+    }
+
     /// Transition our enrollment to WasEnrolled (Option::Some) or delete it (Option::None)
     /// after an experiment has disappeared from the server.
     ///
@@ -437,12 +490,15 @@ impl ExperimentEnrollment {
             EnrollmentStatus::Disqualified { branch, reason, .. } => EnrollmentChangeEvent::new(
                 &self.slug,
                 branch,
-                match reason {
-                    DisqualifiedReason::NotSelected => Some("bucketing"),
-                    DisqualifiedReason::NotTargeted => Some("targeting"),
-                    DisqualifiedReason::OptOut => Some("optout"),
-                    DisqualifiedReason::Error => Some("error"),
-                },
+                Some(&match reason {
+                    DisqualifiedReason::NotSelected => "bucketing".to_string(),
+                    DisqualifiedReason::NotTargeted => "targeting".to_string(),
+                    DisqualifiedReason::OptOut => "optout".to_string(),
+                    DisqualifiedReason::Error => "error".to_string(),
+                    DisqualifiedReason::UnenrollmentCriterion { slug } => {
+                        format!("unenrollment-criterion:{slug}")
+                    }
+                }),
                 EnrollmentChangeEventType::Disqualification,
             ),
             EnrollmentStatus::NotEnrolled { .. } | EnrollmentStatus::Error { .. } => {
@@ -536,6 +592,11 @@ pub(crate) struct EnrollmentsEvolver<'a> {
     available_randomization_units: &'a AvailableRandomizationUnits,
     targeting_helper: &'a mut NimbusTargetingHelper,
     coenrolling_feature_ids: &'a HashSet<&'a str>,
+    // The features the app has read the config of. `None` means we don't track feature
+    // access, and experiments triggered on feature access are enrolled as usual.
+    accessed_feature_ids: Option<&'a HashSet<String>>,
+    // The experiments whose enrollments may change. `None` means all of them.
+    evolving_slugs: Option<&'a HashSet<String>>,
 }
 
 impl<'a> EnrollmentsEvolver<'a> {
@@ -548,6 +609,41 @@ impl<'a> EnrollmentsEvolver<'a> {
             available_randomization_units,
             targeting_helper,
             coenrolling_feature_ids,
+            accessed_feature_ids: None,
+            evolving_slugs: None,
+        }
+    }
+
+    /// Only evolve the enrollments of the experiments in `slugs`; the others keep their
+    /// previous enrollments.
+    #[cfg_attr(not(feature = "stateful"), allow(unused))]
+    pub(crate) fn with_evolving_slugs(mut self, slugs: &'a HashSet<String>) -> Self {
+        self.evolving_slugs = Some(slugs);
+        self
+    }
+
+    /// Hold back experiments triggered on feature access until one of their features is in
+    /// `accessed_feature_ids`.
+    #[cfg_attr(not(feature = "stateful"), allow(unused))]
+    pub(crate) fn with_accessed_feature_ids(
+        mut self,
+        accessed_feature_ids: &'a HashSet<String>,
+    ) -> Self {
+        self.accessed_feature_ids = Some(accessed_feature_ids);
+        self
+    }
+
+    fn is_awaiting_feature_access(&self, experiment: &Experiment) -> bool {
+        match self.accessed_feature_ids {
+            Some(accessed)
+                if experiment.trigger_on_feature_access && !experiment.is_enrollment_paused =>
+            {
+                !experiment
+                    .get_feature_ids()
+                    .iter()
+                    .any(|feature_id| accessed.contains(feature_id))
+            }
+            _ => false,
         }
     }
 
@@ -855,6 +951,12 @@ impl<'a> EnrollmentsEvolver<'a> {
     where
         E: ExperimentMetadata + Clone,
     {
+        if let (Some(slugs), Some(enrollment)) = (self.evolving_slugs, prev_enrollment) {
+            if !slugs.contains(&enrollment.slug) {
+                return Ok(Some(enrollment.clone()));
+            }
+        }
+
         let is_already_enrolled = if let Some(enrollment) = prev_enrollment {
             enrollment.status.is_enrolled()
         } else {
@@ -869,7 +971,26 @@ impl<'a> EnrollmentsEvolver<'a> {
             .targeting_helper
             .put("is_already_enrolled", is_already_enrolled);
 
+        let awaiting_feature_access = is_user_participating
+            && next_experiment.is_some_and(|e| self.is_awaiting_feature_access(e));
+
         Ok(match (prev_experiment, next_experiment, prev_enrollment) {
+            // New experiment, which we can't bucket until its feature is accessed.
+            (None, Some(experiment), None) if awaiting_feature_access => Some(
+                ExperimentEnrollment::awaiting_feature_access(&experiment.slug),
+            ),
+            // Known experiment we haven't enrolled in, and still can't bucket.
+            (Some(_), Some(experiment), Some(enrollment))
+                if awaiting_feature_access
+                    && matches!(
+                        enrollment.status,
+                        EnrollmentStatus::NotEnrolled { .. } | EnrollmentStatus::Error { .. }
+                    ) =>
+            {
+                Some(ExperimentEnrollment::awaiting_feature_access(
+                    &experiment.slug,
+                ))
+            }
             // New experiment.
             (None, Some(experiment), None) => Some(ExperimentEnrollment::from_new_experiment(
                 is_user_participating,
@@ -8,7 +8,6 @@
 //! TODO: Implement proper error handling, this would include defining the error enum,
 //! impl std::error::Error using `thiserror` and ensuring all errors are handled appropriately
 
-#[cfg(feature = "stateful")]
 use firefox_versioning::error::VersionParsingError;
 use std::num::{ParseIntError, TryFromIntError};
 
@@ -116,7 +115,6 @@ impl<'a> From<jexl_eval::error::EvaluationError<'a>> for NimbusError {
     }
 }
 
-#[cfg(feature = "stateful")]
 impl From<VersionParsingError> for NimbusError {
     fn from(eval_error: VersionParsingError) -> Self {
         NimbusError::VersionParsingError(eval_error.to_string())
@@ -7,6 +7,7 @@ use crate::{
     enrollment::{EnrolledReason, EnrollmentStatus, ExperimentEnrollment, NotEnrolledReason},
     error::{NimbusError, Result},
     sampling, AvailableRandomizationUnits, Branch, Experiment, NimbusTargetingHelper,
+    UnenrollmentCriterion,
 };
 use serde_derive::*;
 use serde_json::Value;
@@ -245,6 +246,24 @@ pub(crate) fn targeting(
     }
 }
 
+/// Checks an experiment's unenrollment criteria against the targeting context, returning the
+/// first which is met, if any.
+///
+/// # Errors:
+///
+/// Returns an error if a criterion's expression is invalid, or doesn't evaluate to a boolean.
+pub(crate) fn met_unenrollment_criterion<'a>(
+    exp: &'a Experiment,
+    targeting_helper: &NimbusTargetingHelper,
+) -> Result<Option<&'a UnenrollmentCriterion>> {
+    for criterion in &exp.unenrollment_criteria {
+        if targeting_helper.eval_jexl(criterion.expression.clone())? {
+            return Ok(Some(criterion));
+        }
+    }
+    Ok(None)
+}
+
 #[cfg(test)]
 mod unit_tests {
     use super::*;
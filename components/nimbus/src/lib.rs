@@ -29,7 +29,10 @@ cfg_if::cfg_if! {
         pub mod stateful;
 
         pub use stateful::nimbus_client::*;
+        pub use stateful::apply_journal::{ApplyDecision, ApplyJournalEntry};
+        pub use stateful::enrollment_statuses::ExperimentEnrollmentStatus;
         pub use stateful::matcher::AppContext;
+        pub use stateful::nimbus_builder::NimbusBuilder;
         pub use remote_settings::{RemoteSettingsConfig, RemoteSettingsServer};
     } else {
         pub mod stateless;
@@ -46,6 +46,14 @@ pub struct Experiment {
     pub reference_branch: Option<String>,
     #[serde(default)]
     pub is_rollout: bool,
+    // If set, the user isn't bucketed or enrolled until the app first reads the config of one
+    // of the experiment's features, so that users who never use the feature don't dilute the
+    // results.
+    #[serde(default)]
+    pub trigger_on_feature_access: bool,
+    // Checked on each apply while the user is enrolled; if any is met, the user is unenrolled.
+    #[serde(default)]
+    pub unenrollment_criteria: Vec<UnenrollmentCriterion>,
     pub published_date: Option<chrono::DateTime<chrono::Utc>>,
     // N.B. records in RemoteSettings will have `id` and `filter_expression` fields,
     // but we ignore them because they're for internal use by RemoteSettings.
@@ -141,6 +149,19 @@ pub fn parse_experiments(payload: &str) -> Result<Vec<Experiment>> {
     Ok(res)
 }
 
+/// A condition under which enrolled users leave an experiment, eg "the feature hasn't been used in
+/// 14 days".
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UnenrollmentCriterion {
+    /// Identifies the criterion in the disqualification event when it's met.
+    pub slug: String,
+    /// A JEXL expression which is true when the user should be unenrolled. It's evaluated with
+    /// the same context as targeting, so can query the event store for time windows, eg
+    /// `'feature_used'|eventCountNonZero('Days', 14) == 0`.
+    pub expression: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct FeatureConfig {
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A bounded journal of the enrollment decisions made by each call to
+//! `apply_pending_experiments`.
+//!
+//! When a bad experiment ships, the current enrollments only tell us where a
+//! client ended up; the journal lets us reconstruct which experiments it saw
+//! on each apply, and what it decided for each of them and why.
+
+use crate::{
+    enrollment::{EnrollmentStatus, ExperimentEnrollment},
+    error::Result,
+    stateful::persistence::{Database, Readable, StoreId, Writer},
+    Experiment,
+};
+use chrono::{DateTime, Utc};
+use serde_derive::*;
+
+pub(crate) const DB_KEY_APPLY_JOURNAL: &str = "apply-journal";
+
+/// The number of apply cycles we keep; older entries are dropped.
+pub(crate) const APPLY_JOURNAL_MAX_ENTRIES: usize = 10;
+
+/// The enrollment decision made for a single experiment during an apply cycle.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApplyDecision {
+    pub experiment_slug: String,
+    /// The name of the resulting `EnrollmentStatus`, eg "Enrolled" or "NotEnrolled".
+    pub status: String,
+    pub branch: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl From<&ExperimentEnrollment> for ApplyDecision {
+    fn from(enrollment: &ExperimentEnrollment) -> Self {
+        let (branch, reason) = match &enrollment.status {
+            EnrollmentStatus::Enrolled { reason, branch } => {
+                (Some(branch.clone()), Some(reason.to_string()))
+            }
+            EnrollmentStatus::NotEnrolled { reason } => (None, Some(reason.to_string())),
+            EnrollmentStatus::Disqualified { reason, branch } => {
+                (Some(branch.clone()), Some(reason.to_string()))
+            }
+            EnrollmentStatus::WasEnrolled { branch, .. } => (Some(branch.clone()), None),
+            EnrollmentStatus::Error { reason } => (None, Some(reason.clone())),
+        };
+        Self {
+            experiment_slug: enrollment.slug.clone(),
+            status: enrollment.status.name(),
+            branch,
+            reason,
+        }
+    }
+}
+
+/// A record of a single apply cycle.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApplyJournalEntry {
+    /// When the apply happened, in milliseconds since the Unix epoch.
+    pub applied_at: i64,
+    /// The slugs of the experiments which were applied.
+    pub experiments_seen: Vec<String>,
+    /// The enrollment state of every experiment after the apply.
+    pub decisions: Vec<ApplyDecision>,
+}
+
+/// Appends an entry for the apply cycle which just evolved `experiments`,
+/// dropping the oldest entries if the journal is full.
+///
+/// This must be called with the same writer which evolved the enrollments, so
+/// that the decisions recorded are the ones being committed.
+pub(crate) fn record_apply(
+    db: &Database,
+    writer: &mut Writer,
+    applied_at: DateTime<Utc>,
+    experiments: &[Experiment],
+) -> Result<()> {
+    let enrollments: Vec<ExperimentEnrollment> =
+        db.get_store(StoreId::Enrollments).collect_all(writer)?;
+    let entry = ApplyJournalEntry {
+        applied_at: applied_at.timestamp_millis(),
+        experiments_seen: experiments.iter().map(|e| e.slug.clone()).collect(),
+        decisions: enrollments.iter().map(Into::into).collect(),
+    };
+
+    let meta = db.get_store(StoreId::Meta);
+    let mut journal: Vec<ApplyJournalEntry> =
+        meta.get(writer, DB_KEY_APPLY_JOURNAL)?.unwrap_or_default();
+    journal.push(entry);
+    if journal.len() > APPLY_JOURNAL_MAX_ENTRIES {
+        journal.drain(..journal.len() - APPLY_JOURNAL_MAX_ENTRIES);
+    }
+    meta.put(writer, DB_KEY_APPLY_JOURNAL, &journal)
+}
+
+/// Returns up to `limit` journal entries, most recent first.
+pub(crate) fn get_apply_history<'r>(
+    db: &Database,
+    reader: &'r impl Readable<'r>,
+    limit: usize,
+) -> Result<Vec<ApplyJournalEntry>> {
+    let journal: Vec<ApplyJournalEntry> = db
+        .get_store(StoreId::Meta)
+        .get(reader, DB_KEY_APPLY_JOURNAL)?
+        .unwrap_or_default();
+    Ok(journal.into_iter().rev().take(limit).collect())
+}
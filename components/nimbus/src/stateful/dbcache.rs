@@ -4,11 +4,12 @@
 
 use crate::{
     enrollment::{
-        map_features_by_feature_id, EnrolledFeature, EnrolledFeatureConfig, ExperimentEnrollment,
+        map_features_by_feature_id, EnrolledFeature, EnrolledFeatureConfig, EnrollmentStatus,
+        ExperimentEnrollment, NotEnrolledReason,
     },
     error::{NimbusError, Result},
     stateful::{
-        enrollment::get_enrollments,
+        enrollment::{get_accessed_feature_ids, get_enrollments},
         persistence::{Database, StoreId, Writer},
     },
     EnrolledExperiment, Experiment,
@@ -28,6 +29,8 @@ struct CachedData {
     pub enrollments: Vec<ExperimentEnrollment>,
     pub experiments_by_slug: HashMap<String, EnrolledExperiment>,
     pub features_by_feature_id: HashMap<String, EnrolledFeatureConfig>,
+    // Features which haven't been accessed yet, but have experiments waiting for them to be.
+    pub awaiting_feature_ids: HashSet<String>,
 }
 
 // This is the public cache API. Each NimbusClient can create one of these and
@@ -75,6 +78,26 @@ impl DatabaseCache {
         let features_by_feature_id =
             map_features_by_feature_id(&enrollments, &experiments, coenrolling_ids);
 
+        let accessed_feature_ids = get_accessed_feature_ids(db, &writer)?;
+        let awaiting_slugs: HashSet<&str> = enrollments
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.status,
+                    EnrollmentStatus::NotEnrolled {
+                        reason: NotEnrolledReason::AwaitingFeatureAccess
+                    }
+                )
+            })
+            .map(|e| e.slug.as_str())
+            .collect();
+        let awaiting_feature_ids = experiments
+            .iter()
+            .filter(|e| awaiting_slugs.contains(e.slug.as_str()))
+            .flat_map(|e| e.get_feature_ids())
+            .filter(|feature_id| !accessed_feature_ids.contains(feature_id))
+            .collect();
+
         // This is where testing tools would override i.e. replace experimental feature configurations.
         // i.e. testing tools would cause custom feature configs to be stored in a Store.
         // Here, we get those overrides out of the store, and merge it with this map.
@@ -86,6 +109,7 @@ impl DatabaseCache {
             enrollments,
             experiments_by_slug,
             features_by_feature_id,
+            awaiting_feature_ids,
         };
 
         // Try to commit the change to disk and update the cache as close
@@ -139,6 +163,10 @@ impl DatabaseCache {
         })
     }
 
+    pub fn is_awaiting_feature_access(&self, feature_id: &str) -> Result<bool> {
+        self.get_data(|data| data.awaiting_feature_ids.contains(feature_id))
+    }
+
     pub fn get_enrollment_by_feature(&self, feature_id: &str) -> Result<Option<EnrolledFeature>> {
         self.get_data(|data| {
             data.features_by_feature_id
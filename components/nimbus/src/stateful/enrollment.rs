@@ -10,9 +10,12 @@ use crate::{
     stateful::persistence::{Database, Readable, StoreId, Writer},
     EnrolledExperiment, EnrollmentStatus, Experiment,
 };
+use std::collections::HashSet;
 
 const DB_KEY_GLOBAL_USER_PARTICIPATION: &str = "user-opt-in";
 const DEFAULT_GLOBAL_USER_PARTICIPATION: bool = true;
+const DB_KEY_ACCESSED_FEATURE_IDS: &str = "accessed-feature-ids";
+const DB_KEY_QUEUED_ENROLLMENT_EVENTS: &str = "queued-enrollment-events";
 
 impl EnrollmentsEvolver<'_> {
     /// Convenient wrapper around `evolve_enrollments` that fetches the current state of experiments,
@@ -164,6 +167,62 @@ pub fn set_global_user_participation(
     store.put(writer, DB_KEY_GLOBAL_USER_PARTICIPATION, &opt_in)
 }
 
+/// Return the ids of the features whose config the app has read while an experiment
+/// triggered on feature access was waiting for them.
+pub fn get_accessed_feature_ids<'r>(
+    db: &Database,
+    reader: &'r impl Readable<'r>,
+) -> Result<HashSet<String>> {
+    let store = db.get_store(StoreId::Meta);
+    Ok(store
+        .get::<HashSet<String>, _>(reader, DB_KEY_ACCESSED_FEATURE_IDS)?
+        .unwrap_or_default())
+}
+
+/// Record that the app has read the config for `feature_id`. Returns false if this had
+/// already been recorded.
+pub fn record_feature_access(db: &Database, writer: &mut Writer, feature_id: &str) -> Result<bool> {
+    let mut accessed = get_accessed_feature_ids(db, writer)?;
+    if !accessed.insert(feature_id.to_owned()) {
+        return Ok(false);
+    }
+    let store = db.get_store(StoreId::Meta);
+    store.put(writer, DB_KEY_ACCESSED_FEATURE_IDS, &accessed)?;
+    Ok(true)
+}
+
+/// Queue enrollment change events which happened outside of `apply_pending_experiments`, so
+/// that they can be returned from its next call.
+pub fn queue_enrollment_events(
+    db: &Database,
+    writer: &mut Writer,
+    events: &[EnrollmentChangeEvent],
+) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let store = db.get_store(StoreId::Meta);
+    let mut queued: Vec<EnrollmentChangeEvent> = store
+        .get(writer, DB_KEY_QUEUED_ENROLLMENT_EVENTS)?
+        .unwrap_or_default();
+    queued.extend_from_slice(events);
+    store.put(writer, DB_KEY_QUEUED_ENROLLMENT_EVENTS, &queued)
+}
+
+/// Remove and return the queued enrollment change events.
+pub fn take_queued_enrollment_events(
+    db: &Database,
+    writer: &mut Writer,
+) -> Result<Vec<EnrollmentChangeEvent>> {
+    let store = db.get_store(StoreId::Meta);
+    let queued: Option<Vec<EnrollmentChangeEvent>> =
+        store.get(writer, DB_KEY_QUEUED_ENROLLMENT_EVENTS)?;
+    if queued.is_some() {
+        store.delete(writer, DB_KEY_QUEUED_ENROLLMENT_EVENTS)?;
+    }
+    Ok(queued.unwrap_or_default())
+}
+
 /// Reset unique identifiers in response to application-level telemetry reset.
 ///
 pub fn reset_telemetry_identifiers(
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A snapshot of the enrollment status of every known experiment, with when
+//! each last changed.
+//!
+//! Applications record the Glean `enrollment_status` metric from this. The
+//! enrollments themselves don't say when they changed, so we keep the time
+//! each one was last seen to transition alongside them.
+
+use std::collections::HashMap;
+
+use crate::{
+    enrollment::ExperimentEnrollment,
+    error::Result,
+    stateful::{
+        apply_journal::ApplyDecision,
+        persistence::{Database, Readable, StoreId, Writer},
+    },
+};
+use chrono::{DateTime, Utc};
+use serde_derive::*;
+
+pub(crate) const DB_KEY_ENROLLMENT_TRANSITIONS: &str = "enrollment-transitions";
+
+/// The enrollment status of a single experiment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExperimentEnrollmentStatus {
+    pub slug: String,
+    /// The name of the `EnrollmentStatus`, eg "Enrolled" or "NotEnrolled".
+    pub status: String,
+    pub reason: Option<String>,
+    pub branch: Option<String>,
+    /// When the status last changed, in milliseconds since the Unix epoch, or `None` if it
+    /// hasn't changed since before we started keeping track.
+    pub last_transition_time: Option<i64>,
+}
+
+/// The last status we saw for an experiment, and when it changed to it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct Transition {
+    decision: ApplyDecision,
+    at: i64,
+}
+
+/// Updates the transition times for any enrollments which have changed since
+/// the last call.
+///
+/// This must be called with the same writer which changed the enrollments, so
+/// that the transitions recorded are the ones being committed.
+pub(crate) fn record_transitions(
+    db: &Database,
+    writer: &mut Writer,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let enrollments: Vec<ExperimentEnrollment> =
+        db.get_store(StoreId::Enrollments).collect_all(writer)?;
+    let meta = db.get_store(StoreId::Meta);
+    let mut previous: HashMap<String, Transition> = meta
+        .get(writer, DB_KEY_ENROLLMENT_TRANSITIONS)?
+        .unwrap_or_default();
+    // Enrollments which have gone away are dropped.
+    let transitions: HashMap<String, Transition> = enrollments
+        .iter()
+        .map(|enrollment| {
+            let decision = ApplyDecision::from(enrollment);
+            let transition = match previous.remove(&enrollment.slug) {
+                Some(transition) if transition.decision == decision => transition,
+                _ => Transition {
+                    decision,
+                    at: now.timestamp_millis(),
+                },
+            };
+            (enrollment.slug.clone(), transition)
+        })
+        .collect();
+    meta.put(writer, DB_KEY_ENROLLMENT_TRANSITIONS, &transitions)
+}
+
+/// Returns the status of every experiment we have an enrollment for.
+pub(crate) fn get_enrollment_statuses<'r>(
+    db: &Database,
+    reader: &'r impl Readable<'r>,
+) -> Result<Vec<ExperimentEnrollmentStatus>> {
+    let enrollments: Vec<ExperimentEnrollment> =
+        db.get_store(StoreId::Enrollments).collect_all(reader)?;
+    let transitions: HashMap<String, Transition> = db
+        .get_store(StoreId::Meta)
+        .get(reader, DB_KEY_ENROLLMENT_TRANSITIONS)?
+        .unwrap_or_default();
+    Ok(enrollments
+        .iter()
+        .map(|enrollment| {
+            let decision = ApplyDecision::from(enrollment);
+            let last_transition_time = transitions
+                .get(&enrollment.slug)
+                .filter(|transition| transition.decision == decision)
+                .map(|transition| transition.at);
+            ExperimentEnrollmentStatus {
+                slug: decision.experiment_slug,
+                status: decision.status,
+                reason: decision.reason,
+                branch: decision.branch,
+                last_transition_time,
+            }
+        })
+        .collect())
+}
@@ -2,12 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+pub mod apply_journal;
 pub mod behavior;
 pub mod client;
 pub mod dbcache;
 pub mod enrollment;
+pub mod enrollment_statuses;
 pub mod evaluator;
 pub mod matcher;
+pub mod nimbus_builder;
 pub mod nimbus_client;
 pub mod persistence;
 pub mod targeting;
@@ -0,0 +1,95 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! A builder which creates a [`NimbusClient`] and runs its startup sequence
+//! in one call, so that Rust consumers don't each have to get the order of
+//! `initialize`, `set_experiments_locally` and `apply_pending_experiments`
+//! right. See [`NimbusClient::startup`].
+
+use crate::{
+    enrollment::EnrollmentChangeEvent,
+    metrics::MetricsHandler,
+    stateful::{matcher::AppContext, nimbus_client::NimbusClient, targeting::RecordedContext},
+    Result,
+};
+use remote_settings::RemoteSettingsConfig;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub struct NimbusBuilder {
+    app_context: AppContext,
+    db_path: PathBuf,
+    metrics_handler: Box<dyn MetricsHandler>,
+    recorded_context: Option<Arc<dyn RecordedContext>>,
+    coenrolling_feature_ids: Vec<String>,
+    remote_settings_config: Option<RemoteSettingsConfig>,
+    initial_experiments: Option<String>,
+    fetch_on_first_run: bool,
+}
+
+impl NimbusBuilder {
+    pub fn new<P: Into<PathBuf>>(
+        app_context: AppContext,
+        db_path: P,
+        metrics_handler: Box<dyn MetricsHandler>,
+    ) -> Self {
+        Self {
+            app_context,
+            db_path: db_path.into(),
+            metrics_handler,
+            recorded_context: None,
+            coenrolling_feature_ids: Vec::new(),
+            remote_settings_config: None,
+            initial_experiments: None,
+            fetch_on_first_run: false,
+        }
+    }
+
+    pub fn recorded_context(mut self, recorded_context: Arc<dyn RecordedContext>) -> Self {
+        self.recorded_context = Some(recorded_context);
+        self
+    }
+
+    pub fn coenrolling_feature_ids(mut self, feature_ids: Vec<String>) -> Self {
+        self.coenrolling_feature_ids = feature_ids;
+        self
+    }
+
+    pub fn remote_settings_config(mut self, config: RemoteSettingsConfig) -> Self {
+        self.remote_settings_config = Some(config);
+        self
+    }
+
+    /// Experiments to apply on the first run, usually bundled with the app,
+    /// in the same format as `NimbusClient::set_experiments_locally`.
+    pub fn initial_experiments(mut self, experiments_json: String) -> Self {
+        self.initial_experiments = Some(experiments_json);
+        self
+    }
+
+    /// Whether to fetch and apply experiments straight away on the first run,
+    /// rather than waiting for the next startup.
+    pub fn fetch_on_first_run(mut self, fetch_on_first_run: bool) -> Self {
+        self.fetch_on_first_run = fetch_on_first_run;
+        self
+    }
+
+    /// Creates the client and runs its startup sequence, returning the client
+    /// along with the enrollment changes.
+    ///
+    /// This does I/O, and may make a network request on the first run, so
+    /// it shouldn't be called on the main thread.
+    pub fn build(self) -> Result<(NimbusClient, Vec<EnrollmentChangeEvent>)> {
+        let client = NimbusClient::new(
+            self.app_context,
+            self.recorded_context,
+            self.coenrolling_feature_ids,
+            self.db_path,
+            self.remote_settings_config,
+            self.metrics_handler,
+        )?;
+        let events = client.startup(self.initial_experiments, self.fetch_on_first_run)?;
+        Ok((client, events))
+    }
+}
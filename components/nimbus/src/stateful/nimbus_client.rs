@@ -7,8 +7,8 @@ use crate::tests::helpers::{TestMetrics, TestRecordedContext};
 use crate::{
     defaults::Defaults,
     enrollment::{
-        EnrolledFeature, EnrollmentChangeEvent, EnrollmentChangeEventType, EnrollmentsEvolver,
-        ExperimentEnrollment,
+        EnrolledFeature, EnrollmentChangeEvent, EnrollmentChangeEventType, EnrollmentStatus,
+        EnrollmentsEvolver, ExperimentEnrollment, NotEnrolledReason,
     },
     error::BehaviorError,
     evaluator::{
@@ -22,13 +22,16 @@ use crate::{
     },
     schema::parse_experiments,
     stateful::{
+        apply_journal::{self, ApplyJournalEntry},
         behavior::EventStore,
         client::{create_client, SettingsClient},
         dbcache::DatabaseCache,
         enrollment::{
-            get_global_user_participation, opt_in_with_branch, opt_out,
-            reset_telemetry_identifiers, set_global_user_participation,
+            get_accessed_feature_ids, get_global_user_participation, opt_in_with_branch, opt_out,
+            queue_enrollment_events, record_feature_access, reset_telemetry_identifiers,
+            set_global_user_participation, take_queued_enrollment_events,
         },
+        enrollment_statuses::{self, ExperimentEnrollmentStatus},
         matcher::AppContext,
         persistence::{Database, StoreId, Writer},
         targeting::{validate_event_queries, RecordedContext},
@@ -53,6 +56,7 @@ pub const DB_KEY_INSTALLATION_DATE: &str = "installation-date";
 pub const DB_KEY_UPDATE_DATE: &str = "update-date";
 pub const DB_KEY_APP_VERSION: &str = "app-version";
 pub const DB_KEY_FETCH_ENABLED: &str = "fetch-enabled";
+const DB_KEY_FIRST_RUN_COMPLETE: &str = "first-run-complete";
 
 // The main `NimbusClient` struct must not expose any methods that make an `&mut self`,
 // in order to be compatible with the uniffi's requirements on objects. This is a helper
@@ -150,6 +154,67 @@ impl NimbusClient {
         Ok(())
     }
 
+    /// Performs the startup sequence: initializes the database, then applies
+    /// the experiments which are ready to be applied.
+    ///
+    /// On the first run, that's `initial_experiments`, if given, followed by a
+    /// fresh fetch if `fetch_on_first_run` is set, so that new users can be
+    /// enrolled in the first session. A failed fetch leaves the initial
+    /// experiments in place, and malformed `initial_experiments` are logged and
+    /// skipped. On later runs, it's the experiments fetched during the previous
+    /// session, and the other arguments are ignored.
+    ///
+    /// Returns the enrollment changes, from every step.
+    pub fn startup(
+        &self,
+        initial_experiments: Option<String>,
+        fetch_on_first_run: bool,
+    ) -> Result<Vec<EnrollmentChangeEvent>> {
+        // We have to check before initializing, since that creates the Nimbus ID.
+        let is_first_run = self.check_first_run()?;
+        self.initialize()?;
+        if !is_first_run {
+            return self.apply_pending_experiments();
+        }
+        log::info!("first run, applying initial experiments");
+        let mut events = Vec::new();
+        if let Some(experiments_json) = initial_experiments {
+            match self.set_experiments_locally(experiments_json) {
+                Ok(()) => events.extend(self.apply_pending_experiments()?),
+                Err(e) => log::warn!("failed to set initial experiments: {}", e),
+            }
+        }
+        if fetch_on_first_run {
+            match self.fetch_experiments() {
+                Ok(()) => events.extend(self.apply_pending_experiments()?),
+                Err(e) => log::warn!("failed to fetch experiments on first run: {}", e),
+            }
+        }
+        Ok(events)
+    }
+
+    /// Returns whether this is the first run, and records that the first run
+    /// has happened.
+    ///
+    /// This uses a dedicated flag rather than the Nimbus ID, which is removed
+    /// by `reset_telemetry_identifiers`. Databases from before the flag was
+    /// added have a Nimbus ID, so they aren't mistaken for a first run.
+    fn check_first_run(&self) -> Result<bool> {
+        let db = self.db()?;
+        let mut writer = db.write()?;
+        let store = db.get_store(StoreId::Meta);
+        if store
+            .get::<bool, _>(&writer, DB_KEY_FIRST_RUN_COMPLETE)?
+            .is_some()
+        {
+            return Ok(false);
+        }
+        let nimbus_id: Option<Uuid> = store.get(&writer, DB_KEY_NIMBUS_ID)?;
+        store.put(&mut writer, DB_KEY_FIRST_RUN_COMPLETE, &true)?;
+        writer.commit()?;
+        Ok(nimbus_id.is_none())
+    }
+
     // These are tasks which should be in the initialize and apply_pending_experiments
     // but should happen before the enrollment calculations are done.
     fn begin_initialize(
@@ -186,10 +251,11 @@ impl NimbusClient {
     fn end_initialize(
         &self,
         db: &Database,
-        writer: Writer,
+        mut writer: Writer,
         state: &mut MutexGuard<InternalMutableState>,
     ) -> Result<()> {
         self.update_ta_active_experiments(db, &writer, state)?;
+        enrollment_statuses::record_transitions(db, &mut writer, Utc::now())?;
         let coenrolling_ids = self
             .coenrolling_feature_ids
             .iter()
@@ -211,6 +277,21 @@ impl NimbusClient {
     }
 
     pub fn get_feature_config_variables(&self, feature_id: String) -> Result<Option<String>> {
+        // This is the one case where we may block on IO: the first time a feature with
+        // experiments waiting on it is accessed, those experiments have to be enrolled before
+        // we can answer.
+        if matches!(
+            self.database_cache.is_awaiting_feature_access(&feature_id),
+            Ok(true)
+        ) {
+            if let Err(e) = self.enroll_on_feature_access(&feature_id) {
+                log::warn!(
+                    "Failed to enroll experiments on access to '{}': {}",
+                    feature_id,
+                    e
+                );
+            }
+        }
         Ok(
             if let Some(s) = self
                 .database_cache
@@ -224,6 +305,66 @@ impl NimbusClient {
         )
     }
 
+    /// Record the first access to `feature_id`, and evolve the experiments waiting for it,
+    /// in a single transaction.
+    ///
+    /// No other enrollments are changed. The resulting enrollment events are queued, and
+    /// returned by the next call to `apply_pending_experiments`.
+    fn enroll_on_feature_access(&self, feature_id: &str) -> Result<()> {
+        let db = self.db()?;
+        let mut writer = db.write()?;
+        let mut state = self.mutable_state.lock().unwrap();
+        if !record_feature_access(db, &mut writer, feature_id)? {
+            // Another thread got here first.
+            return Ok(());
+        }
+        self.begin_initialize(db, &mut writer, &mut state)?;
+        self.update_ta_active_experiments(db, &writer, &mut state)?;
+
+        let existing_experiments: Vec<Experiment> =
+            db.get_store(StoreId::Experiments).collect_all(&writer)?;
+        let enrollments: Vec<ExperimentEnrollment> =
+            db.get_store(StoreId::Enrollments).collect_all(&writer)?;
+        let waiting_slugs: HashSet<String> = enrollments
+            .into_iter()
+            .filter(|e| {
+                matches!(
+                    e.status,
+                    EnrollmentStatus::NotEnrolled {
+                        reason: NotEnrolledReason::AwaitingFeatureAccess
+                    }
+                )
+            })
+            .map(|e| e.slug)
+            .filter(|slug| {
+                existing_experiments.iter().any(|experiment| {
+                    &experiment.slug == slug
+                        && experiment
+                            .get_feature_ids()
+                            .iter()
+                            .any(|id| id == feature_id)
+                })
+            })
+            .collect();
+
+        let mut events = self.evolve_experiments_inner(
+            db,
+            &mut writer,
+            &mut state,
+            &existing_experiments,
+            Some(&waiting_slugs),
+        )?;
+        events.retain(|event| waiting_slugs.contains(&event.experiment_slug));
+        log::info!(
+            "Access to '{}' changed {} enrollment(s)",
+            feature_id,
+            events.len()
+        );
+        queue_enrollment_events(db, &mut writer, &events)?;
+        self.end_initialize(db, writer, &mut state)?;
+        Ok(())
+    }
+
     pub fn get_experiment_branches(&self, slug: String) -> Result<Vec<ExperimentBranch>> {
         self.get_all_experiments()?
             .into_iter()
@@ -277,6 +418,22 @@ impl NimbusClient {
             .collect())
     }
 
+    /// Returns up to `limit` records of recent `apply_pending_experiments` calls, most
+    /// recent first.
+    pub fn get_apply_history(&self, limit: u32) -> Result<Vec<ApplyJournalEntry>> {
+        let db = self.db()?;
+        let reader = db.read()?;
+        apply_journal::get_apply_history(db, &reader, limit as usize)
+    }
+
+    /// Returns the current enrollment status of every known experiment, for recording the
+    /// `enrollment_status` metric.
+    pub fn get_enrollment_statuses(&self) -> Result<Vec<ExperimentEnrollmentStatus>> {
+        let db = self.db()?;
+        let reader = db.read()?;
+        enrollment_statuses::get_enrollment_statuses(db, &reader)
+    }
+
     pub fn opt_in_with_branch(
         &self,
         experiment_slug: String,
@@ -383,6 +540,19 @@ impl NimbusClient {
         writer: &mut Writer,
         state: &mut InternalMutableState,
         experiments: &[Experiment],
+    ) -> Result<Vec<EnrollmentChangeEvent>> {
+        self.evolve_experiments_inner(db, writer, state, experiments, None)
+    }
+
+    /// Evolve the enrollments for `experiments`. If `evolving_slugs` is given, only the
+    /// enrollments of those experiments may change.
+    fn evolve_experiments_inner(
+        &self,
+        db: &Database,
+        writer: &mut Writer,
+        state: &mut InternalMutableState,
+        experiments: &[Experiment],
+        evolving_slugs: Option<&HashSet<String>>,
     ) -> Result<Vec<EnrollmentChangeEvent>> {
         let mut targeting_helper = NimbusTargetingHelper::with_targeting_attributes(
             &state.targeting_attributes,
@@ -396,11 +566,16 @@ impl NimbusClient {
             .iter()
             .map(|s| s.as_str())
             .collect();
+        let accessed_feature_ids = get_accessed_feature_ids(db, writer)?;
         let mut evolver = EnrollmentsEvolver::new(
             &state.available_randomization_units,
             &mut targeting_helper,
             &coenrolling_feature_ids,
-        );
+        )
+        .with_accessed_feature_ids(&accessed_feature_ids);
+        if let Some(slugs) = evolving_slugs {
+            evolver = evolver.with_evolving_slugs(slugs);
+        }
         evolver.evolve_enrollments_in_db(db, writer, experiments)
     }
 
@@ -415,14 +590,24 @@ impl NimbusClient {
         let mut state = self.mutable_state.lock().unwrap();
         self.begin_initialize(db, &mut writer, &mut state)?;
 
-        let res = match pending_updates {
+        // Enrollments which happened on feature access since we were last called.
+        let mut res = take_queued_enrollment_events(db, &mut writer)?;
+        res.extend(match pending_updates {
             Some(new_experiments) => {
                 self.update_ta_active_experiments(db, &writer, &mut state)?;
                 // Perform the enrollment calculations if there are pending experiments.
-                self.evolve_experiments(db, &mut writer, &mut state, &new_experiments)?
+                let events =
+                    self.evolve_experiments(db, &mut writer, &mut state, &new_experiments)?;
+                // The journal is only for diagnostics, so it must never stop us applying.
+                if let Err(e) =
+                    apply_journal::record_apply(db, &mut writer, Utc::now(), &new_experiments)
+                {
+                    log::warn!("Failed to record apply journal entry: {}", e);
+                }
+                events
             }
             None => vec![],
-        };
+        });
 
         // Finish up any cleanup, e.g. copying from database in to memory.
         self.end_initialize(db, writer, &mut state)?;
@@ -124,6 +124,8 @@ pub enum StoreId {
     ///   * "update-date": a UTC DateTime string, defining the date the consuming app was
     ///                     last updated
     ///   * "app-version": String, the version of the app last persisted
+    ///   * "apply-journal": a serialized `Vec<ApplyJournalEntry>` recording the most
+    ///                     recent calls to `apply_pending_experiments`
     Meta,
     /// Store containing pending updates to experiment data.
     ///
@@ -4,16 +4,17 @@
 
 use crate::{NimbusError, Result};
 
+use anyhow::anyhow;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use firefox_versioning::compare::version_compare;
 use jexl_eval::Evaluator;
 use serde::Serialize;
 use serde_json::Value;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "stateful")] {
-        use anyhow::anyhow;
         use crate::{TargetingAttributes, stateful::behavior::{EventStore, EventQueryType, query_event_store}};
         use std::sync::{Arc, Mutex};
-        use firefox_versioning::compare::version_compare;
     }
 }
 
@@ -84,11 +85,16 @@ pub fn jexl_eval_raw<Context: serde::Serialize>(
     context: &Context,
     #[cfg(feature = "stateful")] event_store: Arc<Mutex<EventStore>>,
 ) -> Result<Value> {
-    let evaluator = Evaluator::new();
+    let now = Local::now();
+    let evaluator = Evaluator::new()
+        .with_transform("versionCompare", |args| Ok(version_compare(args)?))
+        .with_transform("date", date)
+        .with_transform("currentDate", move |args| current_date(args, now))
+        .with_transform("daysSince", move |args| days_since(args, now))
+        .with_transform("hoursSince", move |args| hours_since(args, now));
 
     #[cfg(feature = "stateful")]
     let evaluator = evaluator
-        .with_transform("versionCompare", |args| Ok(version_compare(args)?))
         .with_transform("eventSum", |args| {
             Ok(query_event_store(
                 event_store.clone(),
@@ -152,6 +158,79 @@ pub fn jexl_eval<Context: serde::Serialize>(
     }
 }
 
+/// Parses the subject of a date transform.
+///
+/// Strings are ISO-8601 dates (`2024-12-25`) or date-times (`2024-12-25T09:30:00Z`). If they don't
+/// have a UTC offset, they're taken to be in the device's time zone, so that a date means the
+/// user's day rather than UTC's. Numbers are seconds since the Unix epoch, like `current_date` in
+/// the targeting attributes.
+pub(crate) fn parse_date(value: &Value) -> anyhow::Result<DateTime<Local>> {
+    match value {
+        Value::String(s) => {
+            if let Ok(date_time) = DateTime::parse_from_rfc3339(s) {
+                return Ok(date_time.with_timezone(&Local));
+            }
+            let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN))
+                })
+                .map_err(|_| anyhow!("{s:?} is not an ISO-8601 date"))?;
+            // Around DST changes, a local time can be ambiguous or not exist at all.
+            Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .ok_or_else(|| anyhow!("{s:?} doesn't exist in the local time zone"))
+        }
+        Value::Number(n) => n
+            .as_f64()
+            .and_then(|secs| Local.timestamp_opt(secs as i64, 0).single())
+            .ok_or_else(|| anyhow!("{n} is out of range for a date")),
+        _ => Err(anyhow!("{value} is not a date")),
+    }
+}
+
+fn date_arg(args: &[Value]) -> anyhow::Result<DateTime<Local>> {
+    parse_date(
+        args.first()
+            .ok_or_else(|| anyhow!("date doesn't exist in jexl transform"))?,
+    )
+}
+
+/// `'2024-12-25'|date`: the date, in seconds since the Unix epoch.
+fn date(args: &[Value]) -> anyhow::Result<Value> {
+    Ok(date_arg(args)?.timestamp().into())
+}
+
+/// `'%m-%d'|currentDate`: the current local date, formatted with the subject as a `strftime`
+/// format. If the subject isn't a string, or is empty, the date is formatted as `%Y-%m-%d`, which
+/// can be compared with other ISO-8601 dates as a string.
+pub(crate) fn current_date(args: &[Value], now: DateTime<Local>) -> anyhow::Result<Value> {
+    use std::fmt::Write;
+
+    let format = match args.first() {
+        Some(Value::String(format)) if !format.is_empty() => format.as_str(),
+        _ => "%Y-%m-%d",
+    };
+    // Formatting with an invalid format string fails rather than panicking if we write it
+    // ourselves.
+    let mut formatted = String::new();
+    write!(formatted, "{}", now.format(format))
+        .map_err(|_| anyhow!("{format:?} is not a valid date format"))?;
+    Ok(Value::String(formatted))
+}
+
+/// `'2024-12-25'|daysSince`: the number of whole days since the date, or a negative number if it's
+/// in the future.
+pub(crate) fn days_since(args: &[Value], now: DateTime<Local>) -> anyhow::Result<Value> {
+    Ok((now - date_arg(args)?).num_days().into())
+}
+
+/// `'2024-12-25T09:30:00Z'|hoursSince`: the number of whole hours since the date, or a negative
+/// number if it's in the future.
+pub(crate) fn hours_since(args: &[Value], now: DateTime<Local>) -> anyhow::Result<Value> {
+    Ok((now - date_arg(args)?).num_hours().into())
+}
+
 #[cfg(feature = "stateful")]
 fn bucket_sample(args: &[Value]) -> anyhow::Result<Value> {
     fn get_arg_as_u32(args: &[Value], idx: usize, name: &str) -> anyhow::Result<u32> {
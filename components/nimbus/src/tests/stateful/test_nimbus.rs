@@ -3,10 +3,14 @@
 * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::{
-    enrollment::{DisqualifiedReason, EnrolledReason, EnrollmentStatus, ExperimentEnrollment},
+    enrollment::{
+        DisqualifiedReason, EnrolledReason, EnrollmentChangeEventType, EnrollmentStatus,
+        ExperimentEnrollment, NotEnrolledReason,
+    },
     error::Result,
     metrics::MalformedFeatureConfigExtraDef,
     stateful::{
+        apply_journal::APPLY_JOURNAL_MAX_ENTRIES,
         behavior::{
             EventStore, Interval, IntervalConfig, IntervalData, MultiIntervalCounter,
             SingleIntervalCounter,
@@ -19,8 +23,8 @@ use crate::{
         get_single_feature_rollout, get_targeted_experiment, to_local_experiments_string,
         TestMetrics, TestRecordedContext,
     },
-    AppContext, Experiment, NimbusClient, TargetingAttributes, DB_KEY_APP_VERSION,
-    DB_KEY_UPDATE_DATE,
+    AppContext, Experiment, NimbusBuilder, NimbusClient, TargetingAttributes,
+    UnenrollmentCriterion, DB_KEY_APP_VERSION, DB_KEY_UPDATE_DATE,
 };
 use chrono::{DateTime, Duration, Utc};
 use serde_json::json;
@@ -1745,3 +1749,371 @@ fn test_recorded_context_event_queries() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_apply_history() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let mut client = NimbusClient::new(
+        app_context.clone(),
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.with_targeting_attributes(TargetingAttributes {
+        app_context,
+        ..Default::default()
+    });
+    client.initialize()?;
+    assert!(client.get_apply_history(10)?.is_empty());
+
+    let exp = get_targeted_experiment("test-1", "true");
+    client.set_experiments_locally(to_local_experiments_string(&[exp])?)?;
+    client.apply_pending_experiments()?;
+
+    let exp = get_targeted_experiment("test-2", "false");
+    client.set_experiments_locally(to_local_experiments_string(&[exp])?)?;
+    client.apply_pending_experiments()?;
+
+    // Applying with nothing pending isn't an apply cycle, so isn't recorded.
+    client.apply_pending_experiments()?;
+
+    let history = client.get_apply_history(10)?;
+    assert_eq!(history.len(), 2);
+    assert!(history[0].applied_at >= history[1].applied_at);
+
+    // Most recent first.
+    assert_eq!(history[0].experiments_seen, vec!["test-2".to_string()]);
+    let mut decisions = history[0].decisions.clone();
+    decisions.sort_by(|a, b| a.experiment_slug.cmp(&b.experiment_slug));
+    assert_eq!(decisions.len(), 2);
+    assert_eq!(decisions[0].experiment_slug, "test-1");
+    assert_eq!(decisions[0].status, "WasEnrolled");
+    assert!(decisions[0].branch.is_some());
+    assert_eq!(decisions[1].experiment_slug, "test-2");
+    assert_eq!(decisions[1].status, "NotEnrolled");
+    assert_eq!(decisions[1].reason.as_deref(), Some("NotTargeted"));
+
+    assert_eq!(history[1].experiments_seen, vec!["test-1".to_string()]);
+    assert_eq!(history[1].decisions.len(), 1);
+    assert_eq!(history[1].decisions[0].status, "Enrolled");
+    assert_eq!(history[1].decisions[0].reason.as_deref(), Some("Qualified"));
+
+    assert_eq!(client.get_apply_history(1)?, history[..1]);
+
+    // The journal is bounded.
+    for _ in 0..APPLY_JOURNAL_MAX_ENTRIES {
+        client.set_experiments_locally(to_local_experiments_string::<Experiment>(&[])?)?;
+        client.apply_pending_experiments()?;
+    }
+    let history = client.get_apply_history(100)?;
+    assert_eq!(history.len(), APPLY_JOURNAL_MAX_ENTRIES);
+    assert!(history
+        .iter()
+        .all(|entry| entry.experiments_seen.is_empty()));
+
+    Ok(())
+}
+
+#[test]
+fn test_get_enrollment_statuses() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let mut client = NimbusClient::new(
+        app_context.clone(),
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.with_targeting_attributes(TargetingAttributes {
+        app_context,
+        ..Default::default()
+    });
+    client.initialize()?;
+    assert!(client.get_enrollment_statuses()?.is_empty());
+
+    let experiments = [
+        get_targeted_experiment("test-1", "true"),
+        get_targeted_experiment("test-2", "false"),
+    ];
+    client.set_experiments_locally(to_local_experiments_string(&experiments)?)?;
+    client.apply_pending_experiments()?;
+
+    let mut statuses = client.get_enrollment_statuses()?;
+    statuses.sort_by(|a, b| a.slug.cmp(&b.slug));
+    assert_eq!(statuses.len(), 2);
+    assert_eq!(statuses[0].slug, "test-1");
+    assert_eq!(statuses[0].status, "Enrolled");
+    assert_eq!(statuses[0].reason.as_deref(), Some("Qualified"));
+    assert!(statuses[0].branch.is_some());
+    assert_eq!(statuses[1].slug, "test-2");
+    assert_eq!(statuses[1].status, "NotEnrolled");
+    assert_eq!(statuses[1].reason.as_deref(), Some("NotTargeted"));
+    assert_eq!(statuses[1].branch, None);
+    let enrolled_at = statuses[0].last_transition_time.unwrap();
+    assert!(statuses[1].last_transition_time.is_some());
+
+    // Re-applying the same experiments doesn't change anything, so keeps the times.
+    client.set_experiments_locally(to_local_experiments_string(&experiments)?)?;
+    client.apply_pending_experiments()?;
+    let statuses = client.get_enrollment_statuses()?;
+    let test_1 = statuses.iter().find(|s| s.slug == "test-1").unwrap();
+    assert_eq!(test_1.last_transition_time, Some(enrolled_at));
+
+    // Opting out is a transition.
+    client.opt_out("test-1".to_string())?;
+    let statuses = client.get_enrollment_statuses()?;
+    let test_1 = statuses.iter().find(|s| s.slug == "test-1").unwrap();
+    assert_eq!(test_1.status, "Disqualified");
+    assert_eq!(test_1.reason.as_deref(), Some("OptOut"));
+    assert!(test_1.last_transition_time.unwrap() >= enrolled_at);
+
+    client.reset_enrollments()?;
+    assert!(client.get_enrollment_statuses()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_trigger_on_feature_access() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let mut client = NimbusClient::new(
+        app_context.clone(),
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.with_targeting_attributes(TargetingAttributes {
+        app_context,
+        ..Default::default()
+    });
+    client.initialize()?;
+
+    let mut gated = get_single_feature_experiment("gated", "feature-a", json!({"x": 1}));
+    gated.trigger_on_feature_access = true;
+    let ungated = get_single_feature_experiment("ungated", "feature-b", json!({"y": 2}));
+    client.set_experiments_locally(to_local_experiments_string(&[gated, ungated])?)?;
+    client.apply_pending_experiments()?;
+
+    assert_eq!(client.get_experiment_branch("gated".into())?, None);
+    assert_eq!(
+        client.get_experiment_branch("ungated".into())?,
+        Some("control".into())
+    );
+    let db = client.db()?;
+    let enrollment: ExperimentEnrollment = db
+        .get_store(StoreId::Enrollments)
+        .get(&db.read()?, "gated")?
+        .unwrap();
+    assert_eq!(
+        enrollment.status,
+        EnrollmentStatus::NotEnrolled {
+            reason: NotEnrolledReason::AwaitingFeatureAccess
+        }
+    );
+
+    // Reading another feature doesn't trigger the experiment.
+    client.get_feature_config_variables("feature-b".into())?;
+    assert_eq!(client.get_experiment_branch("gated".into())?, None);
+
+    // The first read of the feature enrolls us, and sees the experiment's config.
+    assert_eq!(
+        client.get_feature_config_variables("feature-a".into())?,
+        Some(json!({"x": 1}).to_string())
+    );
+    assert_eq!(
+        client.get_experiment_branch("gated".into())?,
+        Some("control".into())
+    );
+
+    // The enrollment event is returned by the next apply, and only once.
+    let events = client.apply_pending_experiments()?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].experiment_slug, "gated");
+    assert_eq!(events[0].branch_slug, "control");
+    assert_eq!(events[0].change, EnrollmentChangeEventType::Enrollment);
+    assert!(client.apply_pending_experiments()?.is_empty());
+
+    // The access is remembered, so later experiments on the feature enroll straight away.
+    let mut gated_2 = get_single_feature_experiment("gated-2", "feature-a", json!({"x": 2}));
+    gated_2.trigger_on_feature_access = true;
+    client.set_experiments_locally(to_local_experiments_string(&[gated_2])?)?;
+    client.apply_pending_experiments()?;
+    assert_eq!(
+        client.get_experiment_branch("gated-2".into())?,
+        Some("control".into())
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "stateful")]
+#[test]
+fn test_unenrollment_criteria_with_event_store() -> Result<()> {
+    let metrics = TestMetrics::new();
+    let temp_dir = tempfile::tempdir()?;
+
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let mut client = NimbusClient::new(
+        app_context.clone(),
+        Default::default(),
+        Default::default(),
+        temp_dir.path(),
+        None,
+        Box::new(metrics),
+    )?;
+    client.with_targeting_attributes(TargetingAttributes {
+        app_context,
+        ..Default::default()
+    });
+    client.initialize()?;
+
+    let mut exp = get_single_feature_experiment("exp", "feature-a", json!({"x": 1}));
+    exp.unenrollment_criteria = vec![UnenrollmentCriterion {
+        slug: "dismissed-recently".to_string(),
+        expression: "'feature-a.dismissed'|eventSum('Days', 14, 0) > 0".to_string(),
+    }];
+    let experiments = to_local_experiments_string(&[exp])?;
+    client.set_experiments_locally(experiments.clone())?;
+    client.apply_pending_experiments()?;
+    assert_eq!(
+        client.get_experiment_branch("exp".into())?,
+        Some("control".into())
+    );
+
+    // The criterion isn't met yet, so applying again keeps us enrolled.
+    client.set_experiments_locally(experiments.clone())?;
+    let events = client.apply_pending_experiments()?;
+    assert!(events.is_empty());
+    assert_eq!(
+        client.get_experiment_branch("exp".into())?,
+        Some("control".into())
+    );
+
+    client.record_event("feature-a.dismissed".to_string(), 1)?;
+    client.set_experiments_locally(experiments)?;
+    let events = client.apply_pending_experiments()?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].experiment_slug, "exp");
+    assert_eq!(
+        events[0].change,
+        EnrollmentChangeEventType::Disqualification
+    );
+    assert_eq!(
+        events[0].reason,
+        Some("unenrollment-criterion:dismissed-recently".to_string())
+    );
+    assert_eq!(client.get_experiment_branch("exp".into())?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_nimbus_builder_startup() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let builder = || {
+        NimbusBuilder::new(
+            app_context.clone(),
+            temp_dir.path(),
+            Box::new(TestMetrics::new()),
+        )
+    };
+    let exp_1 = get_single_feature_experiment("exp-1", "feature-1", json!({}));
+    let exp_2 = get_single_feature_experiment("exp-2", "feature-2", json!({}));
+    let initial_experiments = to_local_experiments_string(&[exp_1.clone()])?;
+
+    // On the first run, the initial experiments are applied.
+    let (client, events) = builder()
+        .initial_experiments(initial_experiments.clone())
+        .build()?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].experiment_slug, "exp-1");
+    assert_eq!(events[0].change, EnrollmentChangeEventType::Enrollment);
+    assert_eq!(client.get_active_experiments()?.len(), 1);
+
+    // Experiments which arrive during a session are left for the next startup.
+    client.set_experiments_locally(to_local_experiments_string(&[exp_1, exp_2])?)?;
+    drop(client);
+
+    // On later runs, they're applied, and the initial experiments are ignored.
+    let (client, events) = builder().initial_experiments(initial_experiments).build()?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].experiment_slug, "exp-2");
+    assert_eq!(client.get_active_experiments()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_nimbus_builder_startup_after_reset() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let app_context = AppContext {
+        app_name: "fenix".to_string(),
+        app_id: "org.mozilla.fenix".to_string(),
+        channel: "nightly".to_string(),
+        ..Default::default()
+    };
+    let builder = || {
+        NimbusBuilder::new(
+            app_context.clone(),
+            temp_dir.path(),
+            Box::new(TestMetrics::new()),
+        )
+    };
+    let exp_1 = get_single_feature_experiment("exp-1", "feature-1", json!({}));
+    let initial_experiments = to_local_experiments_string(&[exp_1])?;
+
+    // Malformed initial experiments are skipped, rather than failing startup.
+    let (client, events) = builder()
+        .initial_experiments("not json".to_string())
+        .build()?;
+    assert!(events.is_empty());
+    assert!(client.get_active_experiments()?.is_empty());
+
+    // Resetting the telemetry identifiers removes the Nimbus ID, but the next
+    // startup still isn't a first run.
+    client.reset_telemetry_identifiers()?;
+    drop(client);
+    let (client, events) = builder().initial_experiments(initial_experiments).build()?;
+    assert!(events.is_empty());
+    assert!(client.get_active_experiments()?.is_empty());
+
+    Ok(())
+}
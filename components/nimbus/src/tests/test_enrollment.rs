@@ -14,7 +14,7 @@ use crate::{
         no_coenrolling_features,
     },
     AppContext, AvailableRandomizationUnits, Branch, BucketConfig, Experiment, FeatureConfig,
-    NimbusTargetingHelper, TargetingAttributes,
+    NimbusTargetingHelper, TargetingAttributes, UnenrollmentCriterion,
 };
 
 cfg_if::cfg_if! {
@@ -785,6 +785,135 @@ fn test_evolver_experiment_update_enrolled_then_targeting_changed() -> Result<()
     Ok(())
 }
 
+#[test]
+fn test_evolver_experiment_update_enrolled_then_unenrollment_criterion_met() -> Result<()> {
+    let mut exp = get_test_experiments()[0].clone();
+    exp.unenrollment_criteria = vec![
+        UnenrollmentCriterion {
+            slug: "never".to_string(),
+            expression: "1 == 2".to_string(),
+        },
+        UnenrollmentCriterion {
+            slug: "always".to_string(),
+            expression: "is_already_enrolled".to_string(),
+        },
+    ];
+    let (_, app_ctx, aru) = local_ctx();
+    let mut th = app_ctx.into();
+    let ids = no_coenrolling_features();
+    let mut evolver = enrollment_evolver(&mut th, &aru, &ids);
+    let mut events = vec![];
+    let existing_enrollment = ExperimentEnrollment {
+        slug: exp.slug.clone(),
+        status: EnrollmentStatus::Enrolled {
+            branch: "control".to_owned(),
+            reason: EnrolledReason::Qualified,
+        },
+    };
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&existing_enrollment),
+            &mut events,
+        )?
+        .unwrap();
+    assert_eq!(
+        enrollment.status,
+        EnrollmentStatus::Disqualified {
+            reason: DisqualifiedReason::UnenrollmentCriterion {
+                slug: "always".to_string()
+            },
+            branch: "control".to_string(),
+        }
+    );
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].experiment_slug, exp.slug);
+    assert_eq!(events[0].branch_slug, "control");
+    assert_eq!(
+        events[0].reason,
+        Some("unenrollment-criterion:always".to_owned())
+    );
+    assert_eq!(
+        events[0].change,
+        EnrollmentChangeEventType::Disqualification
+    );
+
+    // Once disqualified, the user stays out of the experiment.
+    let mut events = vec![];
+    let enrollment = evolver
+        .evolve_enrollment(true, Some(&exp), Some(&exp), Some(&enrollment), &mut events)?
+        .unwrap();
+    assert!(matches!(
+        enrollment.status,
+        EnrollmentStatus::Disqualified {
+            reason: DisqualifiedReason::UnenrollmentCriterion { .. },
+            ..
+        }
+    ));
+    assert!(events.is_empty());
+
+    // Users who opted in aren't unenrolled.
+    let opted_in_enrollment = ExperimentEnrollment {
+        slug: exp.slug.clone(),
+        status: EnrollmentStatus::Enrolled {
+            branch: "control".to_owned(),
+            reason: EnrolledReason::OptIn,
+        },
+    };
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&opted_in_enrollment),
+            &mut events,
+        )?
+        .unwrap();
+    assert_eq!(enrollment, opted_in_enrollment);
+    Ok(())
+}
+
+#[test]
+fn test_evolver_experiment_update_enrolled_then_unenrollment_criterion_invalid() -> Result<()> {
+    let mut exp = get_test_experiments()[0].clone();
+    exp.unenrollment_criteria = vec![UnenrollmentCriterion {
+        slug: "not-a-boolean".to_string(),
+        expression: "1 + 1".to_string(),
+    }];
+    let (_, app_ctx, aru) = local_ctx();
+    let mut th = app_ctx.into();
+    let ids = no_coenrolling_features();
+    let mut evolver = enrollment_evolver(&mut th, &aru, &ids);
+    let mut events = vec![];
+    let existing_enrollment = ExperimentEnrollment {
+        slug: exp.slug.clone(),
+        status: EnrollmentStatus::Enrolled {
+            branch: "control".to_owned(),
+            reason: EnrolledReason::Qualified,
+        },
+    };
+    let enrollment = evolver
+        .evolve_enrollment(
+            true,
+            Some(&exp),
+            Some(&exp),
+            Some(&existing_enrollment),
+            &mut events,
+        )?
+        .unwrap();
+    assert_eq!(
+        enrollment.status,
+        EnrollmentStatus::Disqualified {
+            reason: DisqualifiedReason::Error,
+            branch: "control".to_string(),
+        }
+    );
+    assert_eq!(events[0].reason, Some("error".to_owned()));
+    Ok(())
+}
+
 #[test]
 fn test_evolver_experiment_update_enrolled_then_bucketing_changed() -> Result<()> {
     let exp = get_bucketed_rollout("test-rollout", 0);
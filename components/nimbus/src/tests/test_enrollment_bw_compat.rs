@@ -69,3 +69,27 @@ fn test_not_enrolled_reason_schema_with_feature_conflict() {
         matches!(non_enrollment.status, EnrollmentStatus::NotEnrolled{ ref reason, ..} if reason == &NotEnrolledReason::FeatureConflict)
     );
 }
+
+// We added an UnenrollmentCriterion variant, with the slug of the criterion which was met, to the
+// DisqualifiedReason schema.
+#[test]
+fn test_disqualified_reason_schema_with_unenrollment_criterion() {
+    // ⚠️ Warning : Do not change the JSON data used by this test. ⚠️
+    let disqualified: ExperimentEnrollment = serde_json::from_value(json!({
+        "slug": "secure-gold",
+        "status": {"Disqualified": {
+            "reason": {"UnenrollmentCriterion": {"slug": "unused-for-14-days"}},
+            "branch": "control",
+        }}
+    }))
+    .unwrap();
+    assert_eq!(
+        disqualified.status,
+        EnrollmentStatus::Disqualified {
+            reason: DisqualifiedReason::UnenrollmentCriterion {
+                slug: "unused-for-14-days".to_string()
+            },
+            branch: "control".to_string(),
+        }
+    );
+}
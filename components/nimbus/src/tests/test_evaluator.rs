@@ -109,7 +109,6 @@ fn test_geo_targeting_fails_properly() -> Result<()> {
     Ok(())
 }
 
-#[cfg(feature = "stateful")]
 #[test]
 fn test_minimum_version_targeting_passes() -> Result<()> {
     // Here's our valid jexl statement
@@ -122,7 +121,6 @@ fn test_minimum_version_targeting_passes() -> Result<()> {
     Ok(())
 }
 
-#[cfg(feature = "stateful")]
 #[test]
 fn test_minimum_version_targeting_fails() -> Result<()> {
     // Here's our valid jexl statement
@@ -140,7 +138,6 @@ fn test_minimum_version_targeting_fails() -> Result<()> {
     Ok(())
 }
 
-#[cfg(feature = "stateful")]
 #[test]
 fn test_targeting_specific_version() -> Result<()> {
     // Here's our valid jexl statement that targets **only** 96 versions
@@ -179,6 +176,106 @@ fn test_targeting_specific_version() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_minimum_version_targeting_edge_cases() {
+    let expression_statement = "app_version|versionCompare('9.10') >= 0";
+    for (app_version, targeted) in [
+        ("9.9", false),
+        ("9.10", true),
+        ("9.10.1", true),
+        ("9.10b1", false),
+        ("10.0a1", true),
+        ("100.0", true),
+    ] {
+        let ctx = AppContext {
+            app_version: Some(app_version.into()),
+            ..Default::default()
+        };
+        let expected = (!targeted).then_some(EnrollmentStatus::NotEnrolled {
+            reason: NotEnrolledReason::NotTargeted,
+        });
+        assert_eq!(
+            targeting(expression_statement, &ctx.into()),
+            expected,
+            "app_version {app_version}"
+        );
+    }
+}
+
+#[test]
+fn test_minimum_version_targeting_invalid_version() {
+    let expression_statement = "app_version|versionCompare('100.0') >= 0";
+    let ctx = AppContext {
+        app_version: Some("100.🦊".into()),
+        ..Default::default()
+    };
+    assert!(matches!(
+        targeting(expression_statement, &ctx.into()),
+        Some(EnrollmentStatus::Error { .. })
+    ));
+}
+
+#[test]
+fn test_date_transforms() {
+    use crate::targeting::{current_date, days_since, hours_since, parse_date};
+    use chrono::{Local, TimeZone};
+
+    let now = Local.with_ymd_and_hms(2024, 12, 25, 10, 30, 0).unwrap();
+    let christmas = Local.with_ymd_and_hms(2024, 12, 25, 0, 0, 0).unwrap();
+
+    // Dates without an offset are in the local time zone.
+    assert_eq!(parse_date(&json!("2024-12-25")).unwrap(), christmas);
+    assert_eq!(
+        parse_date(&json!("2024-12-25T00:00:00")).unwrap(),
+        christmas
+    );
+    assert_eq!(
+        parse_date(&json!("2024-12-25T00:00:00Z")).unwrap(),
+        chrono::Utc.with_ymd_and_hms(2024, 12, 25, 0, 0, 0).unwrap()
+    );
+    assert_eq!(
+        parse_date(&json!(christmas.timestamp())).unwrap(),
+        christmas
+    );
+    assert!(parse_date(&json!("25/12/2024")).is_err());
+    assert!(parse_date(&json!(true)).is_err());
+
+    assert_eq!(days_since(&[json!("2024-12-18")], now).unwrap(), json!(7));
+    assert_eq!(days_since(&[json!("2024-12-25")], now).unwrap(), json!(0));
+    assert_eq!(days_since(&[json!("2024-12-27")], now).unwrap(), json!(-1));
+    assert_eq!(hours_since(&[json!("2024-12-25")], now).unwrap(), json!(10));
+    assert!(days_since(&[], now).is_err());
+
+    assert_eq!(
+        current_date(&[json!("")], now).unwrap(),
+        json!("2024-12-25")
+    );
+    assert_eq!(
+        current_date(&[json!("%m-%d")], now).unwrap(),
+        json!("12-25")
+    );
+    assert_eq!(current_date(&[json!("%H")], now).unwrap(), json!("10"));
+    assert!(current_date(&[json!("%Q")], now).is_err());
+}
+
+#[test]
+fn test_targeting_date_transforms() {
+    let ctx = AppContext::default();
+    for expression_statement in [
+        "'2000-01-01'|daysSince > 7",
+        "'2000-01-01T00:00:00Z'|hoursSince > 24",
+        "'3000-01-01'|daysSince < 0",
+        "''|currentDate > '2000-01-01'",
+        "'2000-01-01'|date < '3000-01-01'|date",
+    ] {
+        assert_eq!(
+            targeting(expression_statement, &ctx.clone().into()),
+            None,
+            "{expression_statement}"
+        );
+    }
+}
+
 #[test]
 fn test_targeting_invalid_transform() -> Result<()> {
     let expression_statement = "app_version|invalid_transform('96+.0')";
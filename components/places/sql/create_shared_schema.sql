@@ -152,6 +152,15 @@ CREATE TABLE IF NOT EXISTS moz_meta (
     value NOT NULL
 ) WITHOUT ROWID;
 
+-- Full-text index over the titles and URLs of visited pages, keyed by
+-- moz_places.id. It's kept up to date by TEMP triggers, but databases upgraded
+-- from schemas <= 18 backfill it lazily; see `storage::history_fts`.
+CREATE VIRTUAL TABLE IF NOT EXISTS moz_places_fts USING fts5(
+    title,
+    url,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
 -- Support for tags.
 CREATE TABLE IF NOT EXISTS moz_tags(
     id INTEGER PRIMARY KEY,
@@ -267,3 +276,21 @@ CREATE TABLE IF NOT EXISTS moz_places_metadata_search_queries (
     id INTEGER PRIMARY KEY,
     term TEXT NOT NULL UNIQUE
 );
+
+-- Favicons and preview images for pages, so that apps can show them on a
+-- home screen without keeping their own cache. The total `size` of the images
+-- is capped, and the least recently accessed are evicted first; see
+-- `storage::page_images`. Not synced.
+CREATE TABLE IF NOT EXISTS moz_page_images (
+    place_id INTEGER PRIMARY KEY,
+    favicon_url TEXT,
+    favicon BLOB,
+    preview_image BLOB,
+    size INTEGER NOT NULL DEFAULT 0, -- bytes
+    last_accessed INTEGER NOT NULL DEFAULT 0,
+
+    FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS page_images_lastaccessed
+ON moz_page_images(last_accessed);
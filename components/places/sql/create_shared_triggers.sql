@@ -420,3 +420,32 @@ BEGIN
         SELECT id FROM moz_places_metadata pm WHERE pm.search_query_id = OLD.search_query_id
     );
 END;
+
+-- Keep moz_places_fts up to date. Pages are indexed when they're first
+-- visited, and updated when their title or URL changes.
+CREATE TEMP TRIGGER moz_historyvisits_afterinsert_trigger_fts
+AFTER INSERT ON moz_historyvisits
+FOR EACH ROW
+BEGIN
+    INSERT INTO moz_places_fts(rowid, title, url)
+    SELECT id, IFNULL(title, ''), url FROM moz_places
+    WHERE id = NEW.place_id
+      AND NOT EXISTS (SELECT 1 FROM moz_places_fts WHERE rowid = NEW.place_id);
+END;
+
+CREATE TEMP TRIGGER moz_places_afterupdate_trigger_fts
+AFTER UPDATE OF title, url ON moz_places
+FOR EACH ROW
+BEGIN
+    UPDATE moz_places_fts SET
+        title = IFNULL(NEW.title, ''),
+        url = NEW.url
+    WHERE rowid = NEW.id;
+END;
+
+CREATE TEMP TRIGGER moz_places_afterdelete_trigger_fts
+AFTER DELETE ON moz_places
+FOR EACH ROW
+BEGIN
+    DELETE FROM moz_places_fts WHERE rowid = OLD.id;
+END;
@@ -44,9 +44,9 @@ pub struct SearchParams {
 }
 
 /// Synchronously queries all providers for autocomplete matches, then filters
-/// the matches. This isn't cancelable yet; once a search is started, it can't
-/// be interrupted, even if the user moves on (see
-/// https://github.com/mozilla/application-services/issues/265).
+/// the matches. The search can be cancelled with the connection's interrupt
+/// handle, for example when the user moves on; it then fails with
+/// `Error::InterruptedError`.
 ///
 /// A provider can be anything that returns URL suggestions: Places history
 /// and bookmarks, synced tabs, search engine suggestions, and search keywords.
@@ -456,7 +456,10 @@ impl Matcher for Adaptive<'_> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ',')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
@@ -521,7 +524,10 @@ impl Matcher for Suggestions<'_> {
                           title NOT NULL
                     ORDER BY lastModified DESC
                     LIMIT 1) AS btitle,
-                   NULL AS tags,
+                   (SELECT GROUP_CONCAT(t.tag, ',')
+                    FROM moz_tags t
+                    JOIN moz_tags_relation r ON r.tag_id = t.id
+                    WHERE r.place_id = h.id) AS tags,
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
@@ -686,6 +692,33 @@ mod tests {
             }]
         );
     }
+    #[test]
+    fn search_tags() {
+        let conn = new_mem_connection();
+
+        let url = Url::parse("http://example.com/123").unwrap();
+        let visit = VisitObservation::new(url.clone())
+            .with_title("Example page 123".to_string())
+            .with_visit_type(VisitType::Typed)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+
+        let search_by_tag = || {
+            search_frecent(
+                &conn,
+                SearchParams {
+                    search_string: "recipes".into(),
+                    limit: 10,
+                },
+            )
+            .expect("Should search by tag")
+        };
+        assert!(search_by_tag().is_empty());
+
+        crate::storage::tags::tag_url(&conn, &url, "recipes").expect("Should tag URL");
+        assert!(search_by_tag().iter().any(|result| result.url == url));
+    }
+
     #[test]
     fn search_unicode() {
         let conn = new_mem_connection();
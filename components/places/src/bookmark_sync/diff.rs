@@ -0,0 +1,187 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A structural diff between the local bookmarks tree and the last server
+// state we know about, which is kept in the mirror (the
+// `moz_bookmarks_synced*` tables). This is for debugging - eg, an about:sync
+// page, or attaching to bug reports about corrupt trees - so it makes no
+// attempt to explain *why* the trees differ, and doesn't report differences
+// in URLs, tags or keywords. The roots are ignored.
+
+use super::engine::LAST_SYNC_META_KEY;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::{bookmarks::BookmarkRootGuid, get_meta};
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::{BTreeMap, HashMap};
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SyncedBookmarksDiff {
+    /// When we last synced bookmarks, in milliseconds, or 0 if we never have.
+    pub last_sync: i64,
+    /// Items with a different parent locally and on the server.
+    pub moved: Vec<MovedItem>,
+    /// Items with a different title locally and on the server.
+    pub retitled: Vec<RetitledItem>,
+    /// Items in the same parent, but at a different position, locally and
+    /// on the server.
+    pub repositioned: Vec<RepositionedItem>,
+    /// Items on the server which don't exist locally.
+    pub deleted_locally: Vec<SyncGuid>,
+    /// Items which exist locally, but are deleted on the server.
+    pub deleted_remotely: Vec<SyncGuid>,
+    /// Items which exist locally but which the server has never seen.
+    pub new_locally: Vec<SyncGuid>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct MovedItem {
+    pub guid: SyncGuid,
+    pub local_parent: Option<SyncGuid>,
+    pub server_parent: Option<SyncGuid>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RetitledItem {
+    pub guid: SyncGuid,
+    pub local_title: Option<String>,
+    pub server_title: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RepositionedItem {
+    pub guid: SyncGuid,
+    pub parent: Option<SyncGuid>,
+    pub local_position: u32,
+    pub server_position: u32,
+}
+
+struct LocalItem {
+    parent: Option<SyncGuid>,
+    position: u32,
+    title: Option<String>,
+}
+
+struct ServerItem {
+    parent: Option<SyncGuid>,
+    position: Option<u32>,
+    title: Option<String>,
+    is_deleted: bool,
+}
+
+// Treat missing and empty titles as the same, since we do when syncing.
+fn same_title(a: &Option<String>, b: &Option<String>) -> bool {
+    a.as_deref().unwrap_or_default() == b.as_deref().unwrap_or_default()
+}
+
+pub fn synced_bookmarks_diff(db: &PlacesDb) -> Result<SyncedBookmarksDiff> {
+    // BTreeMap so the diff is ordered by GUID.
+    let local: BTreeMap<SyncGuid, LocalItem> = db
+        .query_rows_and_then(
+            "SELECT b.guid, p.guid AS parentGuid, b.position, b.title
+             FROM moz_bookmarks b
+             LEFT JOIN moz_bookmarks p ON p.id = b.parent",
+            [],
+            |row| -> Result<_> {
+                Ok((
+                    row.get::<_, SyncGuid>("guid")?,
+                    LocalItem {
+                        parent: row.get("parentGuid")?,
+                        position: row.get("position")?,
+                        title: row.get("title")?,
+                    },
+                ))
+            },
+        )?
+        .into_iter()
+        .collect();
+
+    // The structure table is authoritative for where the server says an item
+    // lives; it's missing for tombstones, and for items whose parent we
+    // haven't seen.
+    let server_rows = db.query_rows_and_then(
+        "SELECT v.guid, IFNULL(s.parentGuid, v.parentGuid) AS parentGuid,
+                s.position, v.title, v.isDeleted
+         FROM moz_bookmarks_synced v
+         LEFT JOIN moz_bookmarks_synced_structure s ON s.guid = v.guid",
+        [],
+        |row| -> Result<_> {
+            Ok((
+                row.get::<_, SyncGuid>("guid")?,
+                ServerItem {
+                    parent: row.get("parentGuid")?,
+                    position: row.get("position")?,
+                    title: row.get("title")?,
+                    is_deleted: row.get("isDeleted")?,
+                },
+            ))
+        },
+    )?;
+    let mut server: HashMap<SyncGuid, ServerItem> = HashMap::new();
+    for (guid, item) in server_rows {
+        // If the server has the item in more than one folder, we arbitrarily
+        // use the first.
+        server.entry(guid).or_insert(item);
+    }
+
+    let mut diff = SyncedBookmarksDiff {
+        last_sync: get_meta::<i64>(db, LAST_SYNC_META_KEY)?.unwrap_or_default(),
+        ..Default::default()
+    };
+    for (guid, local_item) in &local {
+        // The roots always exist in both trees, can't be moved, and we
+        // don't sync their titles.
+        if BookmarkRootGuid::well_known(guid.as_str()).is_some() {
+            continue;
+        }
+        let server_item = match server.get(guid) {
+            Some(item) => item,
+            None => {
+                diff.new_locally.push(guid.clone());
+                continue;
+            }
+        };
+        if server_item.is_deleted {
+            diff.deleted_remotely.push(guid.clone());
+            continue;
+        }
+        if local_item.parent != server_item.parent {
+            diff.moved.push(MovedItem {
+                guid: guid.clone(),
+                local_parent: local_item.parent.clone(),
+                server_parent: server_item.parent.clone(),
+            });
+        } else if let Some(server_position) = server_item.position {
+            if local_item.position != server_position {
+                diff.repositioned.push(RepositionedItem {
+                    guid: guid.clone(),
+                    parent: local_item.parent.clone(),
+                    local_position: local_item.position,
+                    server_position,
+                });
+            }
+        }
+        if !same_title(&local_item.title, &server_item.title) {
+            diff.retitled.push(RetitledItem {
+                guid: guid.clone(),
+                local_title: local_item.title.clone(),
+                server_title: server_item.title.clone(),
+            });
+        }
+    }
+    let mut deleted_locally: Vec<_> = server
+        .into_iter()
+        .filter(|(guid, item)| !item.is_deleted && !local.contains_key(guid))
+        .map(|(guid, _)| guid)
+        .collect();
+    deleted_locally.sort();
+    diff.deleted_locally = deleted_locally;
+    Ok(diff)
+}
+
+pub fn synced_bookmarks_diff_json(db: &PlacesDb) -> Result<String> {
+    Ok(serde_json::to_string(&synced_bookmarks_diff(db)?)?)
+}
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::incoming::IncomingApplicator;
+use super::incoming::{self, IncomingApplicator};
 use super::record::{
     BookmarkItemRecord, BookmarkRecord, BookmarkRecordId, FolderRecord, QueryRecord,
     SeparatorRecord,
@@ -23,7 +23,7 @@ use dogear::{
     self, AbortSignal, CompletionOps, Content, Item, MergedRoot, TelemetryEvent, Tree, UploadItem,
     UploadTombstone,
 };
-use interrupt_support::SqlInterruptScope;
+use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use rusqlite::Row;
 use sql_support::ConnExt;
 use std::cell::RefCell;
@@ -31,7 +31,9 @@ use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 use sync15::bso::{IncomingBso, OutgoingBso};
-use sync15::engine::{CollSyncIds, CollectionRequest, EngineSyncAssociation, SyncEngine};
+use sync15::engine::{
+    CollSyncIds, CollectionRequest, EngineSyncAssociation, IncomingValidation, SyncEngine,
+};
 use sync15::{telemetry, CollectionName, ServerTimestamp};
 use sync_guid::Guid as SyncGuid;
 use types::Timestamp;
@@ -941,6 +943,29 @@ impl SyncEngine for BookmarksSyncEngine {
         COLLECTION_NAME.into()
     }
 
+    fn sql_interrupt_handle(&self) -> Option<Arc<SqlInterruptHandle>> {
+        Some(self.db.new_interrupt_handle())
+    }
+
+    fn validate_incoming(&self, record: &IncomingBso) -> anyhow::Result<IncomingValidation> {
+        if !incoming::is_malformed(record) {
+            return Ok(IncomingValidation::Valid);
+        }
+        // If we have the item, bump its change counter so the merger uploads our copy over the
+        // corrupt one. Only the merger can build a record which is consistent with the rest of
+        // the tree, so we don't supply a repair ourselves.
+        let guid = BookmarkRecordId::from_payload_id(record.envelope.id.clone());
+        self.db.lock().execute_cached(
+            "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+             WHERE guid = :guid",
+            &[(":guid", guid.as_guid().as_str())],
+        )?;
+        Ok(IncomingValidation::Invalid {
+            reason: "malformed bookmark",
+            repair: None,
+        })
+    }
+
     fn stage_incoming(
         &self,
         inbound: Vec<IncomingBso>,
@@ -1777,8 +1802,8 @@ mod tests {
     use crate::db::PlacesDb;
     use crate::storage::{
         bookmarks::{
-            get_raw_bookmark, insert_bookmark, update_bookmark, BookmarkPosition,
-            InsertableBookmark, UpdatableBookmark, USER_CONTENT_ROOTS,
+            delete_bookmark, get_raw_bookmark, insert_bookmark, update_bookmark, BookmarkPosition,
+            InsertableBookmark, UpdatableBookmark, UpdateTreeLocation, USER_CONTENT_ROOTS,
         },
         history::frecency_stale_at,
         tags,
@@ -2080,6 +2105,53 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_validate_incoming() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_local_json_tree(
+            &writer,
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "title": "the bookmark",
+                        "url": "https://www.example.com/",
+                    },
+                ]
+            }),
+        );
+        writer.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", [])?;
+        let engine = create_sync_engine(&api);
+
+        let valid = IncomingBso::from_test_content(json!({
+            "id": "bookmark1___",
+            "type": "bookmark",
+            "parentid": "unfiled",
+            "bmkUri": "https://www.example.com/",
+        }));
+        assert!(matches!(
+            engine.validate_incoming(&valid)?,
+            IncomingValidation::Valid
+        ));
+
+        // A corrupt copy of a bookmark we have is quarantined, and ours is flagged for upload.
+        let corrupt = IncomingBso::from_test_content(json!({
+            "id": "bookmark1___",
+            "type": "bookmark",
+            "parentid": "unfiled",
+            "bmkUri": 1234,
+        }));
+        assert!(matches!(
+            engine.validate_incoming(&corrupt)?,
+            IncomingValidation::Invalid { repair: None, .. }
+        ));
+        let bm = get_raw_bookmark(&writer, &"bookmark1___".into())?.expect("bookmark should exist");
+        assert_eq!(bm._sync_change_counter, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_apply_bookmark() {
         let api = new_mem_api();
@@ -3054,6 +3126,138 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_synced_bookmarks_diff() -> Result<()> {
+        use crate::bookmark_sync::diff::{
+            synced_bookmarks_diff, MovedItem, RepositionedItem, RetitledItem, SyncedBookmarksDiff,
+        };
+
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+
+        fn bookmark(guid: &str, parent: &str, title: &str) -> Value {
+            json!({
+                "id": guid,
+                "type": "bookmark",
+                "parentid": parent,
+                "title": title,
+                "bmkUri": format!("http://example.com/{title}"),
+            })
+        }
+        apply_incoming(
+            &api,
+            ServerTimestamp::from_millis(1_000),
+            json!([
+                {
+                    "id": "unfiled",
+                    "type": "folder",
+                    "parentid": "places",
+                    "title": "Unfiled",
+                    "children": ["bookmarkAAAA", "bookmarkBBBB", "bookmarkCCCC"],
+                },
+                {
+                    "id": "menu",
+                    "type": "folder",
+                    "parentid": "places",
+                    "title": "Menu",
+                    "children": ["bookmarkEEEE", "bookmarkFFFF"],
+                },
+                bookmark("bookmarkAAAA", "unfiled", "A"),
+                bookmark("bookmarkBBBB", "unfiled", "B"),
+                bookmark("bookmarkCCCC", "unfiled", "C"),
+                bookmark("bookmarkEEEE", "menu", "E"),
+                bookmark("bookmarkFFFF", "menu", "F"),
+            ]),
+        );
+        // Right after syncing, the trees match.
+        assert_eq!(
+            synced_bookmarks_diff(&writer)?,
+            SyncedBookmarksDiff {
+                last_sync: 1_000,
+                ..Default::default()
+            }
+        );
+
+        update_bookmark(
+            &writer,
+            &"bookmarkAAAA".into(),
+            &UpdatableBookmark {
+                title: Some("A (local)".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        update_bookmark(
+            &writer,
+            &"bookmarkBBBB".into(),
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Parent {
+                    guid: BookmarkRootGuid::Toolbar.as_guid(),
+                    pos: BookmarkPosition::Append,
+                },
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        update_bookmark(
+            &writer,
+            &"bookmarkFFFF".into(),
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Position {
+                    pos: BookmarkPosition::Specific { pos: 0 },
+                },
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        delete_bookmark(&writer, &"bookmarkCCCC".into())?;
+        insert_bookmark(
+            &writer,
+            InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.as_guid(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: Some("bookmarkDDDD".into()),
+                url: Url::parse("http://example.com/d").unwrap(),
+                title: Some("D".into()),
+            }
+            .into(),
+        )?;
+        // Pretend another device deleted E, and we haven't merged that yet.
+        writer.execute(
+            "UPDATE moz_bookmarks_synced SET isDeleted = 1 WHERE guid = 'bookmarkEEEE'",
+            [],
+        )?;
+
+        assert_eq!(
+            synced_bookmarks_diff(&writer)?,
+            SyncedBookmarksDiff {
+                last_sync: 1_000,
+                moved: vec![MovedItem {
+                    guid: "bookmarkBBBB".into(),
+                    local_parent: Some(BookmarkRootGuid::Toolbar.as_guid()),
+                    server_parent: Some(BookmarkRootGuid::Unfiled.as_guid()),
+                }],
+                retitled: vec![RetitledItem {
+                    guid: "bookmarkAAAA".into(),
+                    local_title: Some("A (local)".into()),
+                    server_title: Some("A".into()),
+                }],
+                repositioned: vec![RepositionedItem {
+                    guid: "bookmarkFFFF".into(),
+                    parent: Some(BookmarkRootGuid::Menu.as_guid()),
+                    local_position: 0,
+                    server_position: 1,
+                }],
+                deleted_locally: vec!["bookmarkCCCC".into()],
+                deleted_remotely: vec!["bookmarkEEEE".into()],
+                new_locally: vec!["bookmarkDDDD".into()],
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_apply_tombstones() -> Result<()> {
         let local_modified = Timestamp::now();
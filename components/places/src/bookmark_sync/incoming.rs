@@ -27,6 +27,16 @@ use url::Url;
 // as that's how we use it here.
 const RESULTS_AS_TAG_CONTENTS: &str = "7";
 
+/// Returns true if `record` can't be read as a bookmark item, even after it's fixed up.
+pub fn is_malformed(record: &IncomingBso) -> bool {
+    let content = record
+        .clone()
+        .into_content_with_fixup::<BookmarkItemRecord>(|json| {
+            fixup_bookmark_json(json);
+        });
+    matches!(content.kind, IncomingKind::Malformed)
+}
+
 /// Manages the application of incoming records into the moz_bookmarks_synced
 /// and related tables.
 pub struct IncomingApplicator<'a> {
@@ -13,7 +13,7 @@ use sql_support::ConnExt;
 
 use super::db::{Pragma, PragmaGuard};
 
-pub const VERSION: u32 = 18;
+pub const VERSION: u32 = 20;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -42,6 +42,8 @@ pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_COUNT: &str = "origin_frecency_co
 pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_SUM: &str = "origin_frecency_sum";
 pub(crate) static MOZ_META_KEY_ORIGIN_FRECENCY_SUM_OF_SQUARES: &str =
     "origin_frecency_sum_of_squares";
+pub(crate) static MOZ_META_KEY_FTS_BACKFILL_LAST_ID: &str = "fts_backfill_last_id";
+pub(crate) static MOZ_META_KEY_PAGE_IMAGES_BUDGET: &str = "page_images_budget";
 
 fn update_origin_frecency_stats(op: &str) -> String {
     format!(
@@ -325,6 +327,36 @@ pub fn upgrade_from(db: &Connection, from: u32) -> rusqlite::Result<()> {
                 rusqlite::params![NEW_SQL],
             )?;
         }
+        18 => {
+            // Add the full-text index. Populating it could take a while for a large history, so
+            // we only record where to start and let `history_fts::backfill_index` fill it in.
+            db.execute_batch(
+                "CREATE VIRTUAL TABLE moz_places_fts USING fts5(
+                    title,
+                    url,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                )",
+            )?;
+            db.execute(
+                "INSERT INTO moz_meta(key, value) VALUES (?, 0)",
+                [MOZ_META_KEY_FTS_BACKFILL_LAST_ID],
+            )?;
+        }
+        19 => {
+            // Add the `moz_page_images` table.
+            db.execute_batch(
+                "CREATE TABLE moz_page_images (
+                    place_id INTEGER PRIMARY KEY,
+                    favicon_url TEXT,
+                    favicon BLOB,
+                    preview_image BLOB,
+                    size INTEGER NOT NULL DEFAULT 0,
+                    last_accessed INTEGER NOT NULL DEFAULT 0,
+                    FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE
+                );
+                CREATE INDEX page_images_lastaccessed ON moz_page_images(last_accessed);",
+            )?;
+        }
         // Add more migrations here...
 
         // Any other from value indicates that something very wrong happened
@@ -1157,6 +1189,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_upgrade_schema_18_19() {
+        let db_file = MigratedDatabaseFile::new(PlacesInitializer::new_for_test(), CREATE_V17_DB);
+
+        db_file.upgrade_to(19);
+        let db = db_file.open();
+
+        // The index should exist, but be waiting to be backfilled.
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM moz_places_fts")
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.query_one::<i64>("SELECT value FROM moz_meta WHERE key = 'fts_backfill_last_id'")
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_upgrade_schema_19_20() {
+        let db_file = MigratedDatabaseFile::new(PlacesInitializer::new_for_test(), CREATE_V17_DB);
+
+        db_file.upgrade_to(20);
+        let db = db_file.open();
+
+        assert_eq!(
+            db.query_one::<i64>("SELECT COUNT(*) FROM moz_page_images")
+                .unwrap(),
+            0
+        );
+    }
+
     #[test]
     fn test_all_upgrades() {
         // Test the migration process in general: open a fresh DB and a DB that's gone through the migration
@@ -1190,6 +1256,8 @@ mod tests {
             "moz_keywords",
             "moz_places_metadata",
             "moz_places_metadata_search_queries",
+            "moz_places_fts",
+            "moz_page_images",
         ];
         #[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
         struct ColumnInfo {
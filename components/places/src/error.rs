@@ -113,6 +113,11 @@ pub enum Error {
 
     #[error("Invalid metadata observation: {0}")]
     InvalidMetadataObservation(#[from] InvalidMetadataObservation),
+
+    // Like Urls, the contents of the backup are private info, so the reason
+    // only ever refers to guids.
+    #[error("Invalid bookmarks backup: {0}")]
+    InvalidBookmarksBackup(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -241,6 +246,12 @@ impl GetErrorHandling for Error {
                 })
                 .log_info()
             }
+            Error::InvalidBookmarksBackup(..) => {
+                ErrorHandling::convert(PlacesApiError::InvalidBookmarkOperation {
+                    reason: self.to_string(),
+                })
+                .log_warning()
+            }
             Error::Corruption(e) => {
                 ErrorHandling::convert(PlacesApiError::UnexpectedPlacesException {
                     reason: e.to_string(),
@@ -10,6 +10,7 @@ pub use crate::error::Result;
 pub use crate::error::{ApiResult, PlacesApiError};
 pub use crate::import::common::HistoryMigrationResult;
 use crate::import::import_ios_history;
+pub use crate::import::visits::{HistoryImportProgress, ImportedVisit};
 use crate::storage;
 use crate::storage::bookmarks;
 pub use crate::storage::bookmarks::BookmarkPosition;
@@ -18,8 +19,9 @@ pub use crate::storage::history_metadata::{
     HistoryMetadataObservation, HistoryMetadataPageMissingBehavior,
     NoteHistoryMetadataObservationOptions,
 };
+pub use crate::storage::page_images::PageImages;
 pub use crate::storage::RunMaintenanceMetrics;
-use crate::storage::{history, history_metadata};
+use crate::storage::{history, history_fts, history_metadata, page_images, tags};
 use crate::types::VisitTransitionSet;
 use crate::ConnectionType;
 use crate::UniffiCustomTypeConverter;
@@ -27,8 +29,8 @@ use crate::VisitObservation;
 use crate::VisitType;
 use crate::{PlacesApi, PlacesDb};
 use error_support::handle_error;
-use interrupt_support::register_interrupt;
 pub use interrupt_support::SqlInterruptHandle;
+use interrupt_support::{register_interrupt, SqlInterruptScope};
 use parking_lot::Mutex;
 use std::sync::{Arc, Weak};
 use sync15::client::Sync15StorageClientInit;
@@ -54,6 +56,8 @@ pub type BookmarkFolder = crate::storage::bookmarks::fetch::Folder;
 pub type BookmarkSeparator = crate::storage::bookmarks::fetch::Separator;
 pub use crate::storage::bookmarks::fetch::BookmarkData;
 
+pub use crate::storage::bookmarks::backup::BookmarkImportMode;
+
 impl UniffiCustomTypeConverter for Url {
     type Builtin = String;
 
@@ -198,6 +202,27 @@ impl PlacesConnection {
         f(&conn)
     }
 
+    // Like `with_conn`, but for queries which the app may want to cancel with the interrupt
+    // handle. The interrupt scope begins before we wait for the lock, so queries which are queued
+    // behind a slow one are cancelled along with it, rather than running after it.
+    fn with_interruptible_conn<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&PlacesDb) -> crate::error::Result<T>,
+    {
+        let scope = self.interrupt_handle.begin_interrupt_scope()?;
+        self.with_conn_in_scope(&scope, f)
+    }
+
+    // Waits for the lock, then runs `f` unless `scope` was interrupted in the meantime.
+    fn with_conn_in_scope<F, T>(&self, scope: &SqlInterruptScope, f: F) -> Result<T>
+    where
+        F: FnOnce(&PlacesDb) -> crate::error::Result<T>,
+    {
+        let conn = self.db.lock();
+        scope.err_if_interrupted()?;
+        f(&conn)
+    }
+
     // pass the SqlInterruptHandle as an object through Uniffi
     pub fn new_interrupt_handle(&self) -> Arc<SqlInterruptHandle> {
         Arc::clone(&self.interrupt_handle)
@@ -236,7 +261,7 @@ impl PlacesConnection {
         query: String,
         limit: i32,
     ) -> ApiResult<Vec<HistoryMetadata>> {
-        self.with_conn(|conn| history_metadata::query(conn, query.as_str(), limit))
+        self.with_interruptible_conn(|conn| history_metadata::query(conn, query.as_str(), limit))
     }
 
     #[handle_error(crate::Error)]
@@ -280,6 +305,35 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn set_page_images(
+        &self,
+        url: Url,
+        favicon_url: Option<String>,
+        favicon: Option<Vec<u8>>,
+        preview_image: Option<Vec<u8>>,
+    ) -> ApiResult<()> {
+        self.with_conn(|conn| {
+            page_images::set_page_images(
+                conn,
+                &url,
+                favicon_url.as_deref(),
+                favicon.as_deref(),
+                preview_image.as_deref(),
+            )
+        })
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_page_images(&self, urls: Vec<Url>) -> ApiResult<Vec<PageImages>> {
+        self.with_conn(|conn| page_images::get_page_images(conn, &urls))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn set_page_images_budget(&self, budget_bytes: u64) -> ApiResult<()> {
+        self.with_conn(|conn| page_images::set_page_images_budget(conn, budget_bytes))
+    }
+
     /// Add an observation to the database.
     #[handle_error(crate::Error)]
     pub fn apply_observation(&self, visit: VisitObservation) -> ApiResult<()> {
@@ -287,6 +341,12 @@ impl PlacesConnection {
         Ok(())
     }
 
+    /// Add many observations to the database, in a single transaction.
+    #[handle_error(crate::Error)]
+    pub fn apply_observations(&self, visits: Vec<VisitObservation>) -> ApiResult<()> {
+        self.with_conn(|conn| history::apply_observations(conn, visits))
+    }
+
     #[handle_error(crate::Error)]
     pub fn get_visited_urls_in_range(
         &self,
@@ -311,7 +371,9 @@ impl PlacesConnection {
         end_date: PlacesTimestamp,
         exclude_types: VisitTransitionSet,
     ) -> ApiResult<Vec<HistoryVisitInfo>> {
-        self.with_conn(|conn| history::get_visit_infos(conn, start_date, end_date, exclude_types))
+        self.with_interruptible_conn(|conn| {
+            history::get_visit_infos(conn, start_date, end_date, exclude_types)
+        })
     }
 
     #[handle_error(crate::Error)]
@@ -326,7 +388,9 @@ impl PlacesConnection {
         count: i64,
         exclude_types: VisitTransitionSet,
     ) -> ApiResult<Vec<HistoryVisitInfo>> {
-        self.with_conn(|conn| history::get_visit_page(conn, offset, count, exclude_types))
+        self.with_interruptible_conn(|conn| {
+            history::get_visit_page(conn, offset, count, exclude_types)
+        })
     }
 
     #[handle_error(crate::Error)]
@@ -337,7 +401,7 @@ impl PlacesConnection {
         count: i64,
         exclude_types: VisitTransitionSet,
     ) -> ApiResult<HistoryVisitInfosWithBound> {
-        self.with_conn(|conn| {
+        self.with_interruptible_conn(|conn| {
             history::get_visit_page_with_bound(conn, bound, offset, count, exclude_types)
         })
     }
@@ -357,6 +421,31 @@ impl PlacesConnection {
         Ok(result)
     }
 
+    // Like get_visited, this takes strings so that we can return 0 for bad URLs.
+    #[handle_error(crate::Error)]
+    pub fn get_visit_counts_for_urls(&self, urls: Vec<String>) -> ApiResult<Vec<i64>> {
+        let mut result = vec![0; urls.len()];
+        let url_idxs = urls
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, s)| Url::parse(&s).ok().map(|url| (idx, url)))
+            .collect::<Vec<_>>();
+        self.with_conn(|conn| history::get_visit_counts_into(conn, &url_idxs, &mut result))?;
+        Ok(result)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_visit_infos_grouped_by_host(
+        &self,
+        start_date: PlacesTimestamp,
+        end_date: PlacesTimestamp,
+        exclude_types: VisitTransitionSet,
+    ) -> ApiResult<Vec<HistoryHostGroup>> {
+        self.with_interruptible_conn(|conn| {
+            history::get_visit_infos_grouped_by_host(conn, start_date, end_date, exclude_types)
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn delete_visits_for(&self, url: String) -> ApiResult<()> {
         self.with_conn(|conn| {
@@ -405,7 +494,7 @@ impl PlacesConnection {
         num_items: i32,
         threshold_option: FrecencyThresholdOption,
     ) -> ApiResult<Vec<TopFrecentSiteInfo>> {
-        self.with_conn(|conn| {
+        self.with_interruptible_conn(|conn| {
             crate::storage::history::get_top_frecent_site_infos(
                 conn,
                 num_items,
@@ -444,9 +533,23 @@ impl PlacesConnection {
         self.with_conn(storage::run_maintenance_checkpoint)
     }
 
+    #[handle_error(crate::Error)]
+    pub fn run_maintenance_expire(
+        &self,
+        policy: HistoryExpirationPolicy,
+        limit: u32,
+    ) -> ApiResult<u32> {
+        self.with_conn(|conn| history::expire_history(conn, &policy, limit))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_history_size_stats(&self) -> ApiResult<HistorySizeStats> {
+        self.with_conn(history::get_history_size_stats)
+    }
+
     #[handle_error(crate::Error)]
     pub fn query_autocomplete(&self, search: String, limit: i32) -> ApiResult<Vec<SearchResult>> {
-        self.with_conn(|conn| {
+        self.with_interruptible_conn(|conn| {
             search_frecent(
                 conn,
                 SearchParams {
@@ -458,6 +561,11 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn search_history_fts(&self, query: String, limit: u32) -> ApiResult<Vec<SearchResult>> {
+        self.with_interruptible_conn(|conn| history_fts::search_history_fts(conn, &query, limit))
+    }
+
     #[handle_error(crate::Error)]
     pub fn accept_result(&self, search_string: String, url: String) -> ApiResult<()> {
         self.with_conn(|conn| {
@@ -476,7 +584,7 @@ impl PlacesConnection {
 
     #[handle_error(crate::Error)]
     pub fn match_url(&self, query: String) -> ApiResult<Option<Url>> {
-        self.with_conn(|conn| matcher::match_url(conn, query))
+        self.with_interruptible_conn(|conn| matcher::match_url(conn, query))
     }
 
     #[handle_error(crate::Error)]
@@ -568,6 +676,41 @@ impl PlacesConnection {
         self.with_conn(|conn| bookmarks::count_bookmarks_in_trees(conn, guids))
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_export_json(&self) -> ApiResult<String> {
+        self.with_conn(bookmarks::backup::export_bookmarks_json)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_import_json(&self, json: String, mode: BookmarkImportMode) -> ApiResult<()> {
+        self.with_conn(|conn| bookmarks::backup::import_bookmarks_json(conn, &json, mode))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_sync_diff_json(&self) -> ApiResult<String> {
+        self.with_conn(crate::bookmark_sync::diff::synced_bookmarks_diff_json)
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn tag_url(&self, url: Url, tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| tags::tag_url(conn, &url, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn untag_url(&self, url: Url, tag: String) -> ApiResult<()> {
+        self.with_conn(|conn| tags::untag_url(conn, &url, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_urls_with_tag(&self, tag: String) -> ApiResult<Vec<Url>> {
+        self.with_conn(|conn| tags::get_urls_with_tag(conn, &tag))
+    }
+
+    #[handle_error(crate::Error)]
+    pub fn get_tags_for_url(&self, url: Url) -> ApiResult<Vec<String>> {
+        self.with_conn(|conn| tags::get_tags_for_url(conn, &url))
+    }
+
     #[handle_error(crate::Error)]
     pub fn places_history_import_from_ios(
         &self,
@@ -576,6 +719,17 @@ impl PlacesConnection {
     ) -> ApiResult<HistoryMigrationResult> {
         self.with_conn(|conn| import_ios_history(conn, &db_path, last_sync_timestamp))
     }
+
+    #[handle_error(crate::Error)]
+    pub fn import_visits(
+        &self,
+        visits: Vec<ImportedVisit>,
+        progress: Option<Box<dyn HistoryImportProgress>>,
+    ) -> ApiResult<HistoryMigrationResult> {
+        self.with_conn(|conn| {
+            crate::import::visits::import_visits(conn, visits, progress.as_deref())
+        })
+    }
 }
 
 impl AsRef<SqlInterruptHandle> for PlacesConnection {
@@ -601,6 +755,27 @@ pub struct HistoryVisitInfosWithBound {
     pub offset: i64,
 }
 
+#[derive(Clone, PartialEq, Eq)]
+pub struct HistoryHostGroup {
+    pub host: String,
+    pub last_visit: PlacesTimestamp,
+    pub visits: Vec<HistoryVisitInfo>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HistoryExpirationPolicy {
+    pub max_pages: Option<u32>,
+    pub max_age_days: Option<u32>,
+    pub max_db_size: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistorySizeStats {
+    pub page_count: u32,
+    pub visit_count: u32,
+    pub db_size: u32,
+}
+
 pub struct TopFrecentSiteInfo {
     pub url: Url,
     pub title: Option<String>,
@@ -649,4 +824,37 @@ mod tests {
         let invalid_url = "http://1234.56.78.90".to_string();
         assert!(PlacesConnection::bookmarks_get_all_with_url(&conn, invalid_url).is_ok());
     }
+
+    #[test]
+    fn test_interrupt_queued_query() {
+        let conn = PlacesConnection::new(new_mem_connection());
+        let (began_tx, began_rx) = std::sync::mpsc::channel();
+        std::thread::scope(|s| {
+            // Hold the lock, as a slow query would, so the search has to wait for it.
+            let guard = conn.db.lock();
+            let search = s.spawn(|| {
+                // Like `query_autocomplete`, but telling us once the interrupt scope has begun.
+                let scope = conn.interrupt_handle.begin_interrupt_scope().unwrap();
+                began_tx.send(()).unwrap();
+                conn.with_conn_in_scope(&scope, |db| {
+                    search_frecent(
+                        db,
+                        SearchParams {
+                            search_string: "example".to_string(),
+                            limit: 10,
+                        },
+                    )
+                })
+            });
+            began_rx.recv().unwrap();
+            conn.new_interrupt_handle().interrupt();
+            drop(guard);
+            assert!(matches!(
+                search.join().unwrap(),
+                Err(crate::error::Error::InterruptedError(_))
+            ));
+        });
+        // Later queries aren't affected.
+        assert!(conn.query_autocomplete("example".to_string(), 10).is_ok());
+    }
 }
@@ -6,7 +6,7 @@ use crate::db::{PlacesDb, SharedPlacesDb};
 use crate::error::*;
 use crate::storage::history::{delete_everything, history_sync::reset};
 use crate::storage::{get_meta, put_meta};
-use interrupt_support::SqlInterruptScope;
+use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use std::sync::Arc;
 use sync15::bso::{IncomingBso, OutgoingBso};
 use sync15::engine::{
@@ -15,7 +15,7 @@ use sync15::engine::{
 use sync15::{telemetry, Guid, ServerTimestamp};
 
 use super::plan::{apply_plan, finish_plan, get_planned_outgoing};
-use super::MAX_INCOMING_PLACES;
+use super::{INCOMING_BATCH_SIZE, MAX_INCOMING_PLACES};
 
 pub const LAST_SYNC_META_KEY: &str = "history_last_sync_time";
 // Note that all engines in this crate should use a *different* meta key
@@ -80,6 +80,10 @@ impl SyncEngine for HistorySyncEngine {
         "history".into()
     }
 
+    fn sql_interrupt_handle(&self) -> Option<Arc<SqlInterruptHandle>> {
+        Some(self.db.new_interrupt_handle())
+    }
+
     fn stage_incoming(
         &self,
         inbound: Vec<IncomingBso>,
@@ -93,6 +97,10 @@ impl SyncEngine for HistorySyncEngine {
         Ok(())
     }
 
+    fn get_incoming_batch_size(&self) -> Option<usize> {
+        Some(INCOMING_BATCH_SIZE)
+    }
+
     fn apply(
         &self,
         timestamp: ServerTimestamp,
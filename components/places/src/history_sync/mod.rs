@@ -16,6 +16,8 @@ pub mod record;
 pub use engine::HistorySyncEngine;
 
 const MAX_INCOMING_PLACES: usize = 5000;
+// We apply incoming records as they're staged, so can download them in batches this size.
+const INCOMING_BATCH_SIZE: usize = 1000;
 const MAX_OUTGOING_PLACES: usize = 5000;
 const MAX_VISITS: usize = 20;
 pub const HISTORY_TTL: u32 = 5_184_000; // 60 days in milliseconds
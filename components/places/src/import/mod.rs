@@ -4,4 +4,5 @@
 
 pub mod common;
 pub mod ios;
+pub mod visits;
 pub use ios::import_history as import_ios_history;
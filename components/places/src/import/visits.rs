@@ -0,0 +1,255 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::time::Instant;
+
+use crate::error::Result;
+use crate::import::common::HistoryMigrationResult;
+use crate::storage::{
+    delete_pending_temp_tables, update_all_frecencies_at_once, TITLE_LENGTH_MAX, URL_LENGTH_MAX,
+};
+use crate::types::VisitType;
+use crate::PlacesDb;
+use rusqlite::named_params;
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+/// How many visits are written in each transaction.
+pub const IMPORT_BATCH_SIZE: usize = 500;
+
+/// A visit exported from another browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedVisit {
+    pub url: String,
+    pub title: Option<String>,
+    pub visit_date: Timestamp,
+    pub visit_type: VisitType,
+}
+
+/// Notified after each batch of an [import_visits] call is written.
+pub trait HistoryImportProgress: Send + Sync {
+    fn on_progress(&self, processed: u32, total: u32);
+}
+
+/// Import visits exported from another browser, like Chrome or Safari.
+///
+/// This is used when migrating users from other browsers, so unlike `apply_observation`, it's
+/// designed for large numbers of visits:
+///
+/// - Visits are written in batches of [IMPORT_BATCH_SIZE], with one transaction per batch.
+///   `progress` is notified after each one.
+/// - Frecencies are only recalculated once, after all the visits are written.
+/// - Visits which are already in history (the same URL and date) are skipped, so it's safe to
+///   retry an import that was interrupted. They count as succeeded.
+///
+/// Visits with invalid URLs or dates count as failed, and don't stop the import.
+pub fn import_visits(
+    db: &PlacesDb,
+    visits: Vec<ImportedVisit>,
+    progress: Option<&dyn HistoryImportProgress>,
+) -> Result<HistoryMigrationResult> {
+    let scope = db.begin_interrupt_scope()?;
+    let import_start = Instant::now();
+    let now = Timestamp::now();
+    let num_total = visits.len() as u32;
+    let mut num_succeeded = 0;
+    let mut num_failed = 0;
+
+    for batch in visits.chunks(IMPORT_BATCH_SIZE) {
+        scope.err_if_interrupted()?;
+        let tx = db.begin_transaction()?;
+        for visit in batch {
+            if import_visit(db, visit, now)? {
+                num_succeeded += 1;
+            } else {
+                num_failed += 1;
+            }
+        }
+        delete_pending_temp_tables(db)?;
+        tx.commit()?;
+        if let Some(progress) = progress {
+            progress.on_progress(num_succeeded + num_failed, num_total);
+        }
+    }
+    log::info!(
+        "Imported {} of {} visits, updating frecencies",
+        num_succeeded,
+        num_total
+    );
+    update_all_frecencies_at_once(db, &scope)?;
+
+    Ok(HistoryMigrationResult {
+        num_total,
+        num_succeeded,
+        num_failed,
+        total_duration: import_start.elapsed().as_millis() as u64,
+    })
+}
+
+// Returns false if the visit is invalid.
+fn import_visit(db: &PlacesDb, visit: &ImportedVisit, now: Timestamp) -> Result<bool> {
+    let url = match Url::parse(&visit.url) {
+        Ok(url) if url.as_str().len() <= URL_LENGTH_MAX => url,
+        _ => return Ok(false),
+    };
+    if visit.visit_date < Timestamp::EARLIEST || visit.visit_date > now {
+        return Ok(false);
+    }
+    let title = visit
+        .title
+        .as_deref()
+        .map(|title| crate::util::slice_up_to(title, TITLE_LENGTH_MAX));
+
+    let existing_place_id: Option<i64> = db.try_query_row(
+        "SELECT id FROM moz_places WHERE url_hash = hash(:url) AND url = :url",
+        named_params! { ":url": url.as_str() },
+        |row| row.get(0),
+        true,
+    )?;
+    let place_id = match existing_place_id {
+        Some(place_id) => {
+            // Don't overwrite titles we already know about, they're likely to be more recent.
+            db.execute_cached(
+                "UPDATE moz_places SET title = :title WHERE id = :place_id AND title IS NULL",
+                named_params! { ":title": title, ":place_id": place_id },
+            )?;
+            place_id
+        }
+        None => {
+            db.execute_cached(
+                "INSERT INTO moz_places(guid, url, url_hash, title, frecency)
+                 VALUES (generate_guid(), :url, hash(:url), :title, -1)",
+                named_params! { ":url": url.as_str(), ":title": title },
+            )?;
+            db.last_insert_rowid()
+        }
+    };
+
+    let inserted = db.execute_cached(
+        "INSERT INTO moz_historyvisits(place_id, visit_date, visit_type, is_local)
+         SELECT :place_id, :visit_date, :visit_type, 1
+         WHERE NOT EXISTS (
+             SELECT 1 FROM moz_historyvisits
+             WHERE place_id = :place_id AND visit_date = :visit_date
+         )",
+        named_params! {
+            ":place_id": place_id,
+            ":visit_date": visit.visit_date,
+            ":visit_type": visit.visit_type,
+        },
+    )?;
+    if inserted > 0 {
+        db.execute_cached(
+            "UPDATE moz_places SET
+                 hidden = 0,
+                 typed = typed + :typed,
+                 sync_change_counter = sync_change_counter + 1
+             WHERE id = :place_id",
+            named_params! {
+                ":typed": i32::from(visit.visit_type == VisitType::Typed),
+                ":place_id": place_id,
+            },
+        )?;
+        db.execute_cached(
+            "INSERT OR IGNORE INTO moz_places_stale_frecencies(place_id, stale_at)
+             VALUES (:place_id, :now)",
+            named_params! { ":place_id": place_id, ":now": now },
+        )?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::fetch_page_info;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingProgress(Mutex<Vec<(u32, u32)>>);
+
+    impl HistoryImportProgress for RecordingProgress {
+        fn on_progress(&self, processed: u32, total: u32) {
+            self.0.lock().unwrap().push((processed, total));
+        }
+    }
+
+    fn visit(url: &str, title: Option<&str>, visit_date: Timestamp) -> ImportedVisit {
+        ImportedVisit {
+            url: url.to_string(),
+            title: title.map(str::to_string),
+            visit_date,
+            visit_type: VisitType::Link,
+        }
+    }
+
+    #[test]
+    fn test_import_visits() {
+        let conn = new_mem_connection();
+        let now = Timestamp::now();
+        let earlier = Timestamp(now.as_millis() - 10_000);
+        let visits = vec![
+            visit("https://example.com/", Some("Example"), earlier),
+            visit("https://example.com/", None, now),
+            // Duplicate
+            visit("https://example.com/", Some("Example"), earlier),
+            visit("https://example.org/", None, now),
+            visit("not a url", None, now),
+            visit("https://example.net/", None, Timestamp(0)),
+        ];
+        let result = import_visits(&conn, visits, None).unwrap();
+        assert_eq!(result.num_total, 6);
+        assert_eq!(result.num_succeeded, 4);
+        assert_eq!(result.num_failed, 2);
+
+        let url = Url::parse("https://example.com/").unwrap();
+        let info = fetch_page_info(&conn, &url).unwrap().unwrap();
+        assert_eq!(info.page.title, "Example");
+        assert_eq!(info.page.visit_count_local, 2);
+        assert!(info.page.frecency > 0);
+    }
+
+    #[test]
+    fn test_import_visits_keeps_existing_titles() {
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/").unwrap();
+        crate::storage::history::apply_observation(
+            &conn,
+            crate::VisitObservation::new(url.clone())
+                .with_title("Current title".to_string())
+                .with_visit_type(VisitType::Link),
+        )
+        .unwrap();
+        let old_visit = visit(
+            "https://example.com/",
+            Some("Old title"),
+            Timestamp(Timestamp::now().as_millis() - 10_000),
+        );
+        import_visits(&conn, vec![old_visit], None).unwrap();
+
+        let info = fetch_page_info(&conn, &url).unwrap().unwrap();
+        assert_eq!(info.page.title, "Current title");
+        assert_eq!(info.page.visit_count_local, 2);
+    }
+
+    #[test]
+    fn test_import_visits_progress() {
+        let conn = new_mem_connection();
+        let now = Timestamp::now();
+        let visits = (0..IMPORT_BATCH_SIZE + 1)
+            .map(|i| visit(&format!("https://example.com/{i}"), None, now))
+            .collect();
+        let progress = RecordingProgress::default();
+        let result = import_visits(&conn, visits, Some(&progress)).unwrap();
+        assert_eq!(result.num_succeeded as usize, IMPORT_BATCH_SIZE + 1);
+
+        let total = IMPORT_BATCH_SIZE as u32 + 1;
+        assert_eq!(
+            *progress.0.lock().unwrap(),
+            vec![(IMPORT_BATCH_SIZE as u32, total), (total, total)]
+        );
+    }
+}
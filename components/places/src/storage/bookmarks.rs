@@ -23,6 +23,7 @@ use url::Url;
 
 pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
 
+pub mod backup;
 mod conversions;
 pub mod fetch;
 pub mod json_tree;
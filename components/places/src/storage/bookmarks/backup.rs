@@ -0,0 +1,587 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Support for backing up and restoring the bookmarks tree as JSON.
+//
+// Unlike `json_tree`, which exists for tests and our desktop-import utilities,
+// this is intended for consumers: the format is versioned, includes keywords
+// and tags, preserves GUIDs (which Sync relies on to identify items), and the
+// entire backup is validated before we write anything.
+
+use super::json_tree::{fetch_tree, BookmarkTreeNode, FetchDepth};
+use super::{
+    get_raw_bookmark, insert_bookmark_in_tx, BookmarkPosition, BookmarkRootGuid,
+    InsertableBookmark, InsertableFolder, InsertableItem, InsertableSeparator, USER_CONTENT_ROOTS,
+};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::{tags, URL_LENGTH_MAX};
+use crate::types::BookmarkType;
+use serde_derive::*;
+use sql_support::ConnExt;
+use std::collections::HashSet;
+use sync_guid::Guid as SyncGuid;
+use types::Timestamp;
+use url::Url;
+
+/// The version of the backup format written by `export_bookmarks_json`.
+pub const BOOKMARKS_BACKUP_VERSION: u32 = 1;
+
+/// How `import_bookmarks_json` should treat the existing bookmarks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkImportMode {
+    /// Delete all existing bookmarks and replace them with the backup.
+    /// Synced items that aren't in the backup will be deleted on the server
+    /// on the next sync.
+    Replace,
+    /// Only add the items from the backup whose GUIDs don't exist locally.
+    /// Existing items are left untouched.
+    Merge,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BookmarksBackup {
+    version: u32,
+    roots: Vec<BackupNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum BackupNode {
+    Bookmark {
+        guid: SyncGuid,
+        date_added: Timestamp,
+        last_modified: Timestamp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        keyword: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tags: Vec<String>,
+    },
+    Folder {
+        guid: SyncGuid,
+        date_added: Timestamp,
+        last_modified: Timestamp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default)]
+        children: Vec<BackupNode>,
+    },
+    Separator {
+        guid: SyncGuid,
+        date_added: Timestamp,
+        last_modified: Timestamp,
+    },
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidBookmarksBackup(reason.into())
+}
+
+impl BackupNode {
+    fn guid(&self) -> &SyncGuid {
+        match self {
+            BackupNode::Bookmark { guid, .. }
+            | BackupNode::Folder { guid, .. }
+            | BackupNode::Separator { guid, .. } => guid,
+        }
+    }
+
+    fn bookmark_type(&self) -> BookmarkType {
+        match self {
+            BackupNode::Bookmark { .. } => BookmarkType::Bookmark,
+            BackupNode::Folder { .. } => BookmarkType::Folder,
+            BackupNode::Separator { .. } => BookmarkType::Separator,
+        }
+    }
+
+    fn from_tree(db: &PlacesDb, node: BookmarkTreeNode) -> Result<Self> {
+        let guid = node.guid().clone();
+        let (date_added, last_modified) = node.created_modified();
+        Ok(match node {
+            BookmarkTreeNode::Bookmark { b } => {
+                let keyword = db.try_query_one(
+                    "SELECT k.keyword FROM moz_keywords k
+                     JOIN moz_places h ON h.id = k.place_id
+                     WHERE h.url_hash = hash(:url) AND h.url = :url",
+                    &[(":url", &b.url.as_str())],
+                    true,
+                )?;
+                BackupNode::Bookmark {
+                    guid,
+                    date_added,
+                    last_modified,
+                    title: b.title,
+                    keyword,
+                    tags: tags::get_tags_for_url(db, &b.url)?,
+                    url: b.url.into(),
+                }
+            }
+            BookmarkTreeNode::Folder { f } => BackupNode::Folder {
+                guid,
+                date_added,
+                last_modified,
+                title: f.title,
+                children: f
+                    .children
+                    .into_iter()
+                    .map(|child| BackupNode::from_tree(db, child))
+                    .collect::<Result<_>>()?,
+            },
+            BookmarkTreeNode::Separator { .. } => BackupNode::Separator {
+                guid,
+                date_added,
+                last_modified,
+            },
+        })
+    }
+
+    /// Recursively checks this node and its descendants, collecting GUIDs and
+    /// keywords to detect duplicates.
+    fn validate(
+        &self,
+        guids: &mut HashSet<SyncGuid>,
+        keywords: &mut HashSet<String>,
+    ) -> Result<()> {
+        let guid = self.guid();
+        if !guid.is_valid_for_places() || !guid.is_valid_for_sync_server() {
+            return Err(invalid(format!("invalid guid {:?}", guid.as_str())));
+        }
+        if BookmarkRootGuid::from_guid(guid).is_some() {
+            return Err(invalid(format!(
+                "root guid {} used for a non-root item",
+                guid
+            )));
+        }
+        if !guids.insert(guid.clone()) {
+            return Err(invalid(format!("duplicate guid {}", guid)));
+        }
+        match self {
+            BackupNode::Bookmark {
+                url, keyword, tags, ..
+            } => {
+                // URLs are PII, so only report the guid.
+                match Url::parse(url) {
+                    Ok(u) if u.as_str().len() <= URL_LENGTH_MAX => {}
+                    _ => return Err(invalid(format!("invalid url for {}", guid))),
+                }
+                if let Some(keyword) = keyword {
+                    if keyword.is_empty() || keyword.contains(char::is_whitespace) {
+                        return Err(invalid(format!("invalid keyword for {}", guid)));
+                    }
+                    if !keywords.insert(keyword.clone()) {
+                        return Err(invalid(format!("duplicate keyword for {}", guid)));
+                    }
+                }
+                for tag in tags {
+                    if tags::validate_tag(tag).ensure_valid().is_err() {
+                        return Err(invalid(format!("invalid tag for {}", guid)));
+                    }
+                }
+            }
+            BackupNode::Folder { children, .. } => {
+                for child in children {
+                    child.validate(guids, keywords)?;
+                }
+            }
+            BackupNode::Separator { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Builds the item to insert, without any children - we insert those
+    /// one-by-one so that merging can skip items which already exist.
+    fn to_insertable(&self, parent_guid: &SyncGuid) -> InsertableItem {
+        let parent_guid = parent_guid.clone();
+        match self {
+            BackupNode::Bookmark {
+                guid,
+                date_added,
+                last_modified,
+                title,
+                url,
+                ..
+            } => InsertableBookmark {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: Some(*date_added),
+                last_modified: Some(*last_modified),
+                guid: Some(guid.clone()),
+                // Already validated.
+                url: Url::parse(url).expect("validated url"),
+                title: title.clone(),
+            }
+            .into(),
+            BackupNode::Folder {
+                guid,
+                date_added,
+                last_modified,
+                title,
+                ..
+            } => InsertableFolder {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: Some(*date_added),
+                last_modified: Some(*last_modified),
+                guid: Some(guid.clone()),
+                title: title.clone(),
+                children: vec![],
+            }
+            .into(),
+            BackupNode::Separator {
+                guid,
+                date_added,
+                last_modified,
+            } => InsertableSeparator {
+                parent_guid,
+                position: BookmarkPosition::Append,
+                date_added: Some(*date_added),
+                last_modified: Some(*last_modified),
+                guid: Some(guid.clone()),
+            }
+            .into(),
+        }
+    }
+}
+
+impl BookmarksBackup {
+    fn validate(&self) -> Result<()> {
+        if self.version != BOOKMARKS_BACKUP_VERSION {
+            return Err(invalid(format!("unsupported version {}", self.version)));
+        }
+        let mut seen_roots = HashSet::new();
+        let mut guids = HashSet::new();
+        let mut keywords = HashSet::new();
+        for root in &self.roots {
+            let children = match root {
+                BackupNode::Folder { guid, children, .. }
+                    if USER_CONTENT_ROOTS.iter().any(|r| r == guid) =>
+                {
+                    if !seen_roots.insert(guid.clone()) {
+                        return Err(invalid(format!("duplicate root {}", guid)));
+                    }
+                    children
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "{} is not a bookmark root folder",
+                        root.guid()
+                    )))
+                }
+            };
+            for child in children {
+                child.validate(&mut guids, &mut keywords)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the entire bookmarks tree, including keywords and tags, as JSON.
+pub fn export_bookmarks_json(db: &PlacesDb) -> Result<String> {
+    let mut roots = Vec::with_capacity(USER_CONTENT_ROOTS.len());
+    for root in USER_CONTENT_ROOTS {
+        let (tree, _, _) = fetch_tree(db, root.guid(), &FetchDepth::Deepest)?
+            .ok_or(Corruption::InvalidLocalRoots)?;
+        roots.push(BackupNode::from_tree(db, tree)?);
+    }
+    Ok(serde_json::to_string(&BookmarksBackup {
+        version: BOOKMARKS_BACKUP_VERSION,
+        roots,
+    })?)
+}
+
+/// Restores bookmarks from JSON previously created by `export_bookmarks_json`.
+///
+/// The backup is fully validated before any changes are made, and the import
+/// happens in a single transaction, so on error the bookmarks are unchanged.
+pub fn import_bookmarks_json(db: &PlacesDb, json: &str, mode: BookmarkImportMode) -> Result<()> {
+    // serde's messages may quote the offending value, so only report where
+    // the problem is.
+    let backup: BookmarksBackup = serde_json::from_str(json).map_err(|e| {
+        invalid(format!(
+            "malformed json at line {} column {}",
+            e.line(),
+            e.column()
+        ))
+    })?;
+    backup.validate()?;
+
+    let tx = db.begin_transaction()?;
+    let result = import_in_tx(db, backup, mode);
+    super::super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn import_in_tx(db: &PlacesDb, backup: BookmarksBackup, mode: BookmarkImportMode) -> Result<()> {
+    if mode == BookmarkImportMode::Replace {
+        // Tags belong to URLs rather than bookmarks, so the tags of the
+        // bookmarked URLs go too; the backup's tags are restored below.
+        // The delete trigger writes tombstones for synced items; items that
+        // are restored with the same guid have their tombstone removed again
+        // by the insert trigger. The roots lose their children, so they need
+        // to be uploaded even if nothing is restored into them.
+        db.execute_batch(&format!(
+            "DELETE FROM moz_tags_relation
+             WHERE place_id IN (SELECT fk FROM moz_bookmarks WHERE fk NOT NULL);
+             DELETE FROM moz_keywords;
+             DELETE FROM moz_bookmarks
+             WHERE guid NOT IN ('{root}', '{menu}', '{mobile}', '{toolbar}', '{unfiled}');
+             UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+             WHERE guid IN ('{menu}', '{mobile}', '{toolbar}', '{unfiled}');",
+            root = BookmarkRootGuid::Root.as_str(),
+            menu = BookmarkRootGuid::Menu.as_str(),
+            mobile = BookmarkRootGuid::Mobile.as_str(),
+            toolbar = BookmarkRootGuid::Toolbar.as_str(),
+            unfiled = BookmarkRootGuid::Unfiled.as_str(),
+        ))?;
+    }
+    for root in backup.roots {
+        if let BackupNode::Folder { guid, children, .. } = root {
+            restore_children(db, &guid, children)?;
+        }
+    }
+    Ok(())
+}
+
+fn restore_children(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    children: Vec<BackupNode>,
+) -> Result<()> {
+    for node in children {
+        match get_raw_bookmark(db, node.guid())? {
+            Some(existing) => {
+                // Only possible when merging. Keep the local item, but merge
+                // the children if both sides agree it's a folder.
+                if existing.bookmark_type != node.bookmark_type() {
+                    log::warn!(
+                        "Not restoring {} - it exists locally with a different type",
+                        node.guid()
+                    );
+                    continue;
+                }
+            }
+            None => {
+                insert_bookmark_in_tx(db, node.to_insertable(parent_guid))?;
+                if let BackupNode::Bookmark {
+                    url, keyword, tags, ..
+                } = &node
+                {
+                    restore_keyword_and_tags(db, url, keyword.as_deref(), tags)?;
+                }
+            }
+        }
+        if let BackupNode::Folder { guid, children, .. } = node {
+            restore_children(db, &guid, children)?;
+        }
+    }
+    Ok(())
+}
+
+fn restore_keyword_and_tags(
+    db: &PlacesDb,
+    url: &str,
+    keyword: Option<&str>,
+    tags: &[String],
+) -> Result<()> {
+    let url = Url::parse(url)?;
+    if let Some(keyword) = keyword {
+        // When merging, the keyword (or the URL) might already have one, in
+        // which case the local keyword wins.
+        db.execute_cached(
+            "INSERT OR IGNORE INTO moz_keywords(keyword, place_id)
+             SELECT :keyword, id FROM moz_places
+             WHERE url_hash = hash(:url) AND url = :url",
+            &[(":keyword", &keyword), (":url", &url.as_str())],
+        )?;
+    }
+    for tag in tags {
+        tags::tag_url_in_tx(db, &url, tag)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use serde_json::{json, Value};
+
+    fn populate(conn: &PlacesDb) {
+        insert_json_tree(
+            conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Menu.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "title": "the bookmark",
+                        "url": "https://www.example.com/"
+                    },
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "separator1__",
+                                "type": 3,
+                            },
+                            {
+                                "guid": "bookmark2___",
+                                "title": "bookmark in a folder",
+                                "url": "https://www.example2.com/"
+                            },
+                        ]
+                    },
+                ]
+            }),
+        );
+        conn.execute(
+            "INSERT INTO moz_keywords(keyword, place_id)
+             SELECT 'ex', id FROM moz_places WHERE url = 'https://www.example.com/'",
+            [],
+        )
+        .unwrap();
+        tags::tag_url(
+            conn,
+            &Url::parse("https://www.example2.com/").unwrap(),
+            "foo",
+        )
+        .unwrap();
+    }
+
+    fn expected_menu() -> Value {
+        json!({
+            "guid": String::from(BookmarkRootGuid::Menu.as_str()),
+            "children": [
+                {
+                    "guid": "bookmark1___",
+                    "title": "the bookmark",
+                    "url": "https://www.example.com/"
+                },
+                {
+                    "guid": "folder1_____",
+                    "title": "A folder",
+                    "children": [
+                        {
+                            "guid": "separator1__",
+                            "type": 3,
+                        },
+                        {
+                            "guid": "bookmark2___",
+                            "title": "bookmark in a folder",
+                            "url": "https://www.example2.com/"
+                        },
+                    ]
+                },
+            ]
+        })
+    }
+
+    #[test]
+    fn test_roundtrip_replace() -> Result<()> {
+        let conn = new_mem_connection();
+        populate(&conn);
+        let exported = export_bookmarks_json(&conn)?;
+
+        // Replace something entirely different with the backup.
+        crate::storage::bookmarks::delete_everything(&conn)?;
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Toolbar.as_str()),
+                "children": [{"guid": "otherbmk____", "url": "https://www.example3.com/"}]
+            }),
+        );
+        let other_url = Url::parse("https://www.example3.com/")?;
+        tags::tag_url(&conn, &other_url, "bar")?;
+        let toolbar_counter = |conn: &PlacesDb| -> Result<i64> {
+            Ok(conn.query_one(&format!(
+                "SELECT syncChangeCounter FROM moz_bookmarks WHERE guid = '{}'",
+                BookmarkRootGuid::Toolbar.as_str()
+            ))?)
+        };
+        let toolbar_counter_before = toolbar_counter(&conn)?;
+        import_bookmarks_json(&conn, &exported, BookmarkImportMode::Replace)?;
+
+        assert_json_tree(&conn, &BookmarkRootGuid::Menu.into(), expected_menu());
+        assert!(get_raw_bookmark(&conn, &"otherbmk____".into())?.is_none());
+        // The removed bookmark's tags are gone, and the now empty toolbar
+        // will be uploaded without it.
+        assert!(tags::get_tags_for_url(&conn, &other_url)?.is_empty());
+        assert!(toolbar_counter(&conn)? > toolbar_counter_before);
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "ex")?,
+            Some(Url::parse("https://www.example.com/")?)
+        );
+        assert_eq!(
+            tags::get_tags_for_url(&conn, &Url::parse("https://www.example2.com/")?)?,
+            vec!["foo".to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let conn = new_mem_connection();
+        populate(&conn);
+        let exported = export_bookmarks_json(&conn)?;
+
+        // Delete one item and rename another - merging should restore the
+        // former but leave the latter alone.
+        crate::storage::bookmarks::delete_bookmark(&conn, &"bookmark2___".into())?;
+        conn.execute(
+            "UPDATE moz_bookmarks SET title = 'renamed' WHERE guid = 'bookmark1___'",
+            [],
+        )?;
+        import_bookmarks_json(&conn, &exported, BookmarkImportMode::Merge)?;
+
+        let mut expected = expected_menu();
+        expected["children"][0]["title"] = json!("renamed");
+        assert_json_tree(&conn, &BookmarkRootGuid::Menu.into(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_backups() -> Result<()> {
+        let conn = new_mem_connection();
+        populate(&conn);
+        let exported: Value = serde_json::from_str(&export_bookmarks_json(&conn)?)?;
+
+        let check_invalid = |f: &dyn Fn(&mut Value)| {
+            let mut backup = exported.clone();
+            f(&mut backup);
+            let err =
+                import_bookmarks_json(&conn, &backup.to_string(), BookmarkImportMode::Replace)
+                    .expect_err("should fail");
+            assert!(
+                matches!(err, Error::InvalidBookmarksBackup(_)),
+                "unexpected error {:?}",
+                err
+            );
+        };
+        check_invalid(&|b| b["version"] = json!(99));
+        check_invalid(&|b| b["roots"][0]["guid"] = json!("folder1_____"));
+        check_invalid(&|b| b["roots"][0]["children"][0]["guid"] = json!("folder1_____"));
+        check_invalid(&|b| {
+            b["roots"][0]["children"][0]["guid"] = json!(BookmarkRootGuid::Toolbar.as_str())
+        });
+        check_invalid(&|b| b["roots"][0]["children"][0]["url"] = json!("not a url"));
+        check_invalid(&|b| b["roots"][0]["children"][1]["children"][1]["keyword"] = json!("ex"));
+        check_invalid(&|b| b["roots"][0]["children"][0]["tags"] = json!(["  "]));
+
+        // Nothing was changed by the failed imports.
+        assert_json_tree(&conn, &BookmarkRootGuid::Menu.into(), expected_menu());
+        Ok(())
+    }
+}
@@ -7,7 +7,10 @@ mod actions;
 use super::{fetch_page_info, new_page_info, PageInfo, RowId};
 use crate::db::PlacesDb;
 use crate::error::Result;
-use crate::ffi::{HistoryVisitInfo, HistoryVisitInfosWithBound, TopFrecentSiteInfo};
+use crate::ffi::{
+    HistoryExpirationPolicy, HistoryHostGroup, HistorySizeStats, HistoryVisitInfo,
+    HistoryVisitInfosWithBound, TopFrecentSiteInfo,
+};
 use crate::frecency;
 use crate::hash;
 use crate::history_sync::engine::{
@@ -25,7 +28,7 @@ use rusqlite::types::ToSql;
 use rusqlite::Result as RusqliteResult;
 use rusqlite::Row;
 use sql_support::{self, ConnExt};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use sync15::bso::OutgoingBso;
 use sync15::engine::EngineSyncAssociation;
@@ -50,6 +53,56 @@ pub fn apply_observation(db: &PlacesDb, visit_ob: VisitObservation) -> Result<Op
     Ok(result)
 }
 
+/// Apply many observations at once, in a single transaction.
+///
+/// This is much faster than calling [apply_observation] for each one when ingesting lots of
+/// observations, like when restoring a session. Observations which only update a page's title or
+/// preview image are coalesced, so each page is only updated once, but the end result is the same
+/// as applying the observations in order.
+pub fn apply_observations(db: &PlacesDb, visit_obs: Vec<VisitObservation>) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    for visit_ob in coalesce_observations(visit_obs) {
+        apply_observation_direct(db, visit_ob)?;
+    }
+    delete_pending_temp_tables(db)?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Merge observations without a visit into the next observation with a visit for the same URL, or
+// into a single observation per URL if there isn't one.
+fn coalesce_observations(visit_obs: Vec<VisitObservation>) -> Vec<VisitObservation> {
+    let mut coalesced = Vec::with_capacity(visit_obs.len());
+    let mut pending: HashMap<Url, VisitObservation> = HashMap::new();
+    for mut visit_ob in visit_obs {
+        if visit_ob.visit_type.is_none() {
+            match pending.get_mut(&visit_ob.url) {
+                Some(pending_ob) => {
+                    if visit_ob.title.is_some() {
+                        pending_ob.title = visit_ob.title;
+                    }
+                    if visit_ob.preview_image_url.is_some() {
+                        pending_ob.preview_image_url = visit_ob.preview_image_url;
+                    }
+                }
+                None => {
+                    pending.insert(visit_ob.url.clone(), visit_ob);
+                }
+            }
+            continue;
+        }
+        if let Some(pending_ob) = pending.remove(&visit_ob.url) {
+            // This observation is newer, so its own values win.
+            visit_ob.title = visit_ob.title.or(pending_ob.title);
+            visit_ob.preview_image_url =
+                visit_ob.preview_image_url.or(pending_ob.preview_image_url);
+        }
+        coalesced.push(visit_ob);
+    }
+    coalesced.extend(pending.into_values());
+    coalesced
+}
+
 /// Returns the RowId of a new visit in moz_historyvisits, or None if no new visit was added.
 pub fn apply_observation_direct(
     db: &PlacesDb,
@@ -491,12 +544,123 @@ fn find_exotic_visits_to_prune(
     )
 }
 
+/// Expire history according to `policy`, deleting at most `limit` visits.
+///
+/// Expiration is incremental: call this repeatedly, for example during idle maintenance, until it
+/// returns fewer than `limit`. Visits are expired in this order:
+///   - Visits older than `max_age_days`.
+///   - All visits for the least recently visited pages, if there are more than `max_pages`.
+///   - The oldest visits, if the database is larger than `max_db_size`.
+///
+/// Pages with foreign references (bookmarks, keywords and tags) are never expired, and don't count
+/// towards `max_pages`.
+///
+/// Returns the number of visits expired.
+pub fn expire_history(db: &PlacesDb, policy: &HistoryExpirationPolicy, limit: u32) -> Result<u32> {
+    let tx = db.begin_transaction()?;
+    let to_delete = find_visits_to_expire(db, policy, limit as usize, Timestamp::now())?;
+    let num_expired = to_delete.len() as u32;
+    let result = DbAction::apply_all(db, db_actions_from_visits_to_delete(to_delete));
+    tx.commit()?;
+    result.map(|_| num_expired)
+}
+
+fn find_visits_to_expire(
+    db: &PlacesDb,
+    policy: &HistoryExpirationPolicy,
+    limit: usize,
+    now: Timestamp,
+) -> Result<Vec<VisitToDelete>> {
+    let mut to_delete = HashSet::new();
+    let mut add_candidates = |candidates: Vec<VisitToDelete>| {
+        for candidate in candidates {
+            if to_delete.len() >= limit {
+                break;
+            }
+            to_delete.insert(candidate);
+        }
+    };
+    if let Some(max_age_days) = policy.max_age_days {
+        let visit_date_cutoff =
+            now.checked_sub(Duration::from_secs(60 * 60 * 24 * u64::from(max_age_days)));
+        add_candidates(db.query_rows_and_then(
+            "SELECT v.id, v.place_id
+             FROM moz_places p
+             JOIN moz_historyvisits v ON v.place_id = p.id
+             WHERE v.visit_date < :visit_date_cutoff
+               AND p.foreign_count = 0
+             ORDER BY v.visit_date
+             LIMIT :limit",
+            rusqlite::named_params! {
+                ":visit_date_cutoff": visit_date_cutoff,
+                ":limit": limit,
+            },
+            VisitToDelete::from_row,
+        )?);
+    }
+    if let Some(max_pages) = policy.max_pages {
+        let num_pages: u32 = db.query_one(
+            "SELECT COUNT(*) FROM moz_places
+             WHERE foreign_count = 0
+               AND (last_visit_date_local + last_visit_date_remote) != 0",
+        )?;
+        if num_pages > max_pages {
+            add_candidates(db.query_rows_and_then(
+                "SELECT v.id, v.place_id
+                 FROM moz_historyvisits v
+                 WHERE v.place_id IN (
+                     SELECT id FROM moz_places
+                     WHERE foreign_count = 0
+                       AND (last_visit_date_local + last_visit_date_remote) != 0
+                     ORDER BY MAX(last_visit_date_local, last_visit_date_remote)
+                     LIMIT :num_excess_pages
+                 )
+                 LIMIT :limit",
+                rusqlite::named_params! {
+                    ":num_excess_pages": num_pages - max_pages,
+                    ":limit": limit,
+                },
+                VisitToDelete::from_row,
+            )?);
+        }
+    }
+    if let Some(max_db_size) = policy.max_db_size {
+        if db.get_db_size()? > max_db_size {
+            add_candidates(db.query_rows_and_then(
+                "SELECT v.id, v.place_id
+                 FROM moz_places p
+                 JOIN moz_historyvisits v ON v.place_id = p.id
+                 WHERE p.foreign_count = 0
+                 ORDER BY v.visit_date
+                 LIMIT :limit",
+                rusqlite::named_params! { ":limit": limit },
+                VisitToDelete::from_row,
+            )?);
+        }
+    }
+    Ok(Vec::from_iter(to_delete))
+}
+
+/// Get the number of pages and visits in history, and the size of the database, so that
+/// applications can show how much storage history is using.
+pub fn get_history_size_stats(db: &PlacesDb) -> Result<HistorySizeStats> {
+    Ok(HistorySizeStats {
+        page_count: db.query_one(
+            "SELECT COUNT(*) FROM moz_places
+             WHERE (last_visit_date_local + last_visit_date_remote) != 0",
+        )?,
+        visit_count: db.query_one("SELECT COUNT(*) FROM moz_historyvisits")?,
+        db_size: db.get_db_size()?,
+    })
+}
+
 fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
     use crate::frecency::DEFAULT_FRECENCY_SETTINGS;
     db.execute_all(&[
         "DELETE FROM moz_places WHERE foreign_count == 0",
         "DELETE FROM moz_places_metadata",
         "DELETE FROM moz_places_metadata_search_queries",
+        "DELETE FROM moz_page_images",
         "DELETE FROM moz_historyvisits",
         "DELETE FROM moz_places_tombstones",
         "DELETE FROM moz_inputhistory AS i WHERE NOT EXISTS(
@@ -1266,6 +1430,45 @@ where
     Ok(result)
 }
 
+/// Low level api used to implement the FFI get_visit_counts_for_urls call. Like
+/// `get_visited_into`, takes a slice of index/url pairs, and writes the number of visits
+/// to each url to that index of `result`. The counts are the same as the page's
+/// `visit_count_local + visit_count_remote`, so don't include visits such as redirects.
+pub fn get_visit_counts_into(
+    db: &PlacesDb,
+    urls_idxs: &[(usize, Url)],
+    result: &mut [i64],
+) -> Result<()> {
+    sql_support::each_chunk_mapped(
+        urls_idxs,
+        |(_, url)| url.as_str(),
+        |chunk, offset| -> Result<()> {
+            let values_with_idx = sql_support::repeat_display(chunk.len(), ",", |i, f| {
+                let (idx, url) = &urls_idxs[i + offset];
+                write!(f, "({},{},?)", *idx, hash::hash_url(url.as_str()))
+            });
+            let sql = format!(
+                "WITH to_fetch(fetch_url_index, url_hash, url) AS (VALUES {})
+                 SELECT fetch_url_index, h.visit_count_local + h.visit_count_remote
+                 FROM moz_places h
+                 JOIN to_fetch f ON h.url_hash = f.url_hash
+                   AND h.url = f.url",
+                values_with_idx
+            );
+            let mut stmt = db.prepare(&sql)?;
+            for r in stmt.query_and_then(
+                rusqlite::params_from_iter(chunk),
+                |row| -> rusqlite::Result<_> { Ok((row.get::<_, i64>(0)? as usize, row.get(1)?)) },
+            )? {
+                let (idx, count) = r?;
+                result[idx] = count;
+            }
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
 /// Low level api used to implement both get_visited and the FFI get_visited call.
 /// Takes a slice where we should output the results, as well as a slice of
 /// index/url pairs.
@@ -1514,6 +1717,57 @@ pub fn get_visit_page_with_bound(
     }
 }
 
+/// Gets the visits between `start` and `end`, grouped by the host of the visited page. The
+/// groups, and the visits in each, are ordered with the most recent visit first.
+pub fn get_visit_infos_grouped_by_host(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    exclude_types: VisitTransitionSet,
+) -> Result<Vec<HistoryHostGroup>> {
+    let allowed_types = exclude_types.complement();
+    let rows = db.query_rows_and_then_cached(
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden, h.preview_image_url,
+                v.is_local, IFNULL(o.host, '') AS host
+         FROM moz_places h
+         JOIN moz_historyvisits v
+           ON h.id = v.place_id
+         LEFT JOIN moz_origins o
+           ON o.id = h.origin_id
+         WHERE v.visit_date BETWEEN :start AND :end
+           AND ((1 << v.visit_type) & :allowed_types) != 0 AND
+           NOT h.hidden
+         ORDER BY v.visit_date DESC, v.id",
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":allowed_types": allowed_types,
+        },
+        |row| -> Result<_> {
+            Ok((
+                row.get::<_, String>("host")?,
+                HistoryVisitInfo::from_row(row)?,
+            ))
+        },
+    )?;
+    // The visits are newest first, so adding groups as we first see their host keeps
+    // them in order, too.
+    let mut groups: Vec<HistoryHostGroup> = Vec::new();
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+    for (host, info) in rows {
+        let idx = *group_indexes.entry(host.clone()).or_insert_with(|| {
+            groups.push(HistoryHostGroup {
+                host,
+                last_visit: info.timestamp,
+                visits: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[idx].visits.push(info);
+    }
+    Ok(groups)
+}
+
 #[cfg(test)]
 mod tests {
     use super::history_sync::*;
@@ -1734,6 +1988,92 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_visit_counts_into() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let to_add = [
+            ("https://www.example.com/1", VisitType::Link),
+            ("https://www.example.com/1", VisitType::Typed),
+            ("https://www.example.com/1", VisitType::Link),
+            ("https://www.example.com/2", VisitType::Link),
+            // Redirects don't count as visits.
+            ("https://www.example.com/2", VisitType::RedirectTemporary),
+        ];
+        for (url, visit_type) in to_add {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap()).with_visit_type(visit_type),
+            )?;
+        }
+        let urls_idxs = [
+            "https://www.example.com/1",
+            "https://www.example.com/2",
+            "https://www.example.com/3",
+            "https://www.example.com/1",
+        ]
+        .into_iter()
+        .map(|url| Url::parse(url).unwrap())
+        .enumerate()
+        .collect::<Vec<_>>();
+        let mut counts = vec![0; urls_idxs.len()];
+        get_visit_counts_into(&conn, &urls_idxs, &mut counts)?;
+        assert_eq!(counts, vec![3, 1, 0, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_visit_infos_grouped_by_host() -> Result<()> {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let now: Timestamp = SystemTime::now().into();
+        let to_add = [
+            ("https://www.example.com/1", now.0 - 5000),
+            ("https://www.mozilla.org/", now.0 - 4000),
+            ("https://www.example.com/2", now.0 - 3000),
+            ("http://www.example.com/3", now.0 - 2000),
+            ("https://www.mozilla.org/about", now.0 - 1000),
+        ];
+        for (url, when) in to_add {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(Timestamp(when))
+                    .with_visit_type(VisitType::Link),
+            )?;
+        }
+        let groups = get_visit_infos_grouped_by_host(
+            &conn,
+            Timestamp(now.0 - 4500),
+            now,
+            VisitTransitionSet::empty(),
+        )?;
+        let summary = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.host.as_str(),
+                    g.last_visit.0,
+                    g.visits.iter().map(|v| v.url.as_str()).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "www.mozilla.org",
+                    now.0 - 1000,
+                    vec!["https://www.mozilla.org/about", "https://www.mozilla.org/"]
+                ),
+                (
+                    "www.example.com",
+                    now.0 - 2000,
+                    vec!["http://www.example.com/3", "https://www.example.com/2"]
+                ),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited() -> Result<()> {
         let _ = env_logger::try_init();
@@ -3409,6 +3749,151 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_coalesce_observations() {
+        let url1 = Url::parse("https://example.com/1").unwrap();
+        let url2 = Url::parse("https://example.com/2").unwrap();
+        let coalesced = coalesce_observations(vec![
+            VisitObservation::new(url1.clone()).with_title("first".to_string()),
+            VisitObservation::new(url2.clone()).with_title("a".to_string()),
+            VisitObservation::new(url1.clone()).with_title("second".to_string()),
+            VisitObservation::new(url1.clone()).with_visit_type(VisitType::Link),
+            VisitObservation::new(url2.clone()).with_title("b".to_string()),
+        ]);
+        assert_eq!(coalesced.len(), 2);
+        assert_eq!(coalesced[0].url, url1);
+        assert_eq!(coalesced[0].title.as_deref(), Some("second"));
+        assert_eq!(coalesced[0].visit_type, Some(VisitType::Link));
+        assert_eq!(coalesced[1].url, url2);
+        assert_eq!(coalesced[1].title.as_deref(), Some("b"));
+        assert_eq!(coalesced[1].visit_type, None);
+    }
+
+    #[test]
+    fn test_apply_observations() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let url1 = Url::parse("https://example.com/1").unwrap();
+        let url2 = Url::parse("https://www.example.org/2").unwrap();
+        apply_observations(
+            &conn,
+            vec![
+                VisitObservation::new(url1.clone())
+                    .with_title("Old title".to_string())
+                    .with_visit_type(VisitType::Link),
+                VisitObservation::new(url2.clone()).with_visit_type(VisitType::Typed),
+                VisitObservation::new(url1.clone()).with_visit_type(VisitType::Link),
+                VisitObservation::new(url1.clone()).with_title("New title".to_string()),
+            ],
+        )
+        .unwrap();
+
+        let page1 = fetch_page_info(&conn, &url1).unwrap().unwrap().page;
+        assert_eq!(page1.title, "New title");
+        assert_eq!(page1.visit_count_local, 2);
+        assert!(page1.frecency > 0);
+        let page2 = fetch_page_info(&conn, &url2).unwrap().unwrap().page;
+        assert_eq!(page2.visit_count_local, 1);
+        assert_eq!(page2.typed, 1);
+        // Origins are created for the new pages.
+        assert_eq!(
+            conn.query_one::<u32>("SELECT COUNT(*) FROM moz_origins")
+                .unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_expire_history() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).expect("no memory db");
+        let one_day = Duration::from_secs(60 * 60 * 24);
+        let now: Timestamp = SystemTime::now().into();
+        let visit = |url: &str, days_ago: u32| {
+            apply_observation(
+                &conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_at(now.checked_sub(one_day * days_ago))
+                    .with_visit_type(VisitType::Link),
+            )
+            .unwrap()
+            .unwrap()
+        };
+        let old_visit = visit("https://example.com/old", 40);
+        let bookmarked_visit = visit("https://example.com/bookmarked", 50);
+        let recent_visit = visit("https://example.com/recent", 1);
+        let other_recent_visit = visit("https://example.com/other", 2);
+        insert_bookmark(
+            &conn,
+            crate::InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.as_guid(),
+                position: crate::BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.com/bookmarked").unwrap(),
+                title: None,
+            }
+            .into(),
+        )
+        .unwrap();
+
+        let stats = get_history_size_stats(&conn).unwrap();
+        assert_eq!(stats.page_count, 4);
+        assert_eq!(stats.visit_count, 4);
+        assert!(stats.db_size > 0);
+
+        // Expire by age. The bookmarked page's visit is kept.
+        let by_age = HistoryExpirationPolicy {
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        check_visits_to_prune(
+            &conn,
+            find_visits_to_expire(&conn, &by_age, 10, now).unwrap(),
+            &[old_visit],
+        );
+        assert_eq!(expire_history(&conn, &by_age, 10).unwrap(), 1);
+        assert_eq!(expire_history(&conn, &by_age, 10).unwrap(), 0);
+        assert!(
+            url_to_guid(&conn, &Url::parse("https://example.com/old").unwrap())
+                .unwrap()
+                .is_none()
+        );
+        assert!(conn
+            .exists(
+                "SELECT 1 FROM moz_historyvisits WHERE id = :id",
+                rusqlite::named_params! { ":id": bookmarked_visit },
+            )
+            .unwrap());
+
+        // Expire by page count. Only unbookmarked pages count towards the limit, and the least
+        // recently visited ones go first.
+        let by_pages = HistoryExpirationPolicy {
+            max_pages: Some(1),
+            ..Default::default()
+        };
+        check_visits_to_prune(
+            &conn,
+            find_visits_to_expire(&conn, &by_pages, 10, now).unwrap(),
+            &[other_recent_visit],
+        );
+
+        // Expire by size, oldest unbookmarked visits first.
+        let by_size = HistoryExpirationPolicy {
+            max_db_size: Some(1),
+            ..Default::default()
+        };
+        check_visits_to_prune(
+            &conn,
+            find_visits_to_expire(&conn, &by_size, 1, now).unwrap(),
+            &[other_recent_visit],
+        );
+        check_visits_to_prune(
+            &conn,
+            find_visits_to_expire(&conn, &by_size, 10, now).unwrap(),
+            &[other_recent_visit, recent_visit],
+        );
+    }
+
     fn check_visits_to_prune(
         db: &PlacesDb,
         visits_to_delete: Vec<VisitToDelete>,
@@ -0,0 +1,263 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Full-text search over the titles and URLs of visited pages.
+//!
+//! The `moz_places_fts` table is kept up to date by triggers as pages are visited (see
+//! `create_shared_triggers.sql`). Databases created before the table existed have their existing
+//! history indexed lazily, a batch at a time, by [backfill_index].
+
+use super::{delete_meta, get_meta, put_meta};
+use crate::api::places_api::ConnectionType;
+use crate::db::schema::MOZ_META_KEY_FTS_BACKFILL_LAST_ID;
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::ffi::SearchResult;
+use sql_support::ConnExt;
+use url::Url;
+
+/// How many pages [backfill_index] looks at each time it's called.
+pub const BACKFILL_BATCH_SIZE: u32 = 1000;
+
+// How many full-text matches to consider for each result we return. Ranking is done on this
+// larger set, so that a frecent page with a slightly worse text match can still make the cut.
+const CANDIDATES_PER_RESULT: u32 = 5;
+
+// Relative weights of the text match and frecency in the blended rank.
+const TEXT_MATCH_WEIGHT: f64 = 1.0;
+const FRECENCY_WEIGHT: f64 = 0.5;
+
+/// Search the titles and URLs of visited pages.
+///
+/// Each word in `query` matches any word in the title or URL starting with it. Results are ranked
+/// by blending the quality of the text match with the page's frecency.
+///
+/// On writable connections, this also indexes the next batch of pages if a backfill is still in
+/// progress. Pages that haven't been indexed yet won't be returned.
+pub fn search_history_fts(db: &PlacesDb, query: &str, limit: u32) -> Result<Vec<SearchResult>> {
+    let scope = db.begin_interrupt_scope()?;
+    if db.conn_type() != ConnectionType::ReadOnly {
+        backfill_index(db, BACKFILL_BATCH_SIZE)?;
+        scope.err_if_interrupted()?;
+    }
+    let match_expr = match match_expression(query) {
+        Some(match_expr) => match_expr,
+        None => return Ok(vec![]),
+    };
+    let mut candidates = db.query_rows_and_then_cached(
+        // Title matches count for twice as much as URL matches.
+        "SELECT h.url, IFNULL(h.title, '') AS title, h.frecency,
+                bm25(moz_places_fts, 2.0, 1.0) AS text_rank
+         FROM moz_places_fts
+         JOIN moz_places h ON h.id = moz_places_fts.rowid
+         WHERE moz_places_fts MATCH :match_expr
+         ORDER BY text_rank
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":match_expr": match_expr,
+            ":limit": limit.saturating_mul(CANDIDATES_PER_RESULT),
+        },
+        |row| -> Result<_> {
+            let result = SearchResult {
+                url: Url::parse(&row.get::<_, String>("url")?)?,
+                title: row.get("title")?,
+                frecency: row.get("frecency")?,
+            };
+            Ok((blended_rank(row.get("text_rank")?, result.frecency), result))
+        },
+    )?;
+    candidates.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Ok(candidates
+        .into_iter()
+        .take(limit as usize)
+        .map(|(_, result)| result)
+        .collect())
+}
+
+/// Index the next `batch_size` pages for a database that was upgraded from a schema without
+/// `moz_places_fts`.
+///
+/// Returns true if there's more left to do.
+pub fn backfill_index(db: &PlacesDb, batch_size: u32) -> Result<bool> {
+    let last_id = match get_meta::<i64>(db, MOZ_META_KEY_FTS_BACKFILL_LAST_ID)? {
+        Some(last_id) => last_id,
+        None => return Ok(false),
+    };
+    let tx = db.begin_transaction()?;
+    let batch_end: Option<i64> = db.query_row_and_then_cachable(
+        "SELECT MAX(id) FROM (
+             SELECT id FROM moz_places WHERE id > :last_id ORDER BY id LIMIT :batch_size
+         )",
+        rusqlite::named_params! {
+            ":last_id": last_id,
+            ":batch_size": batch_size,
+        },
+        |row| row.get(0),
+        true,
+    )?;
+    let more = match batch_end {
+        Some(batch_end) => {
+            // `OR REPLACE` since pages visited since the upgrade are already indexed.
+            db.execute_cached(
+                "INSERT OR REPLACE INTO moz_places_fts(rowid, title, url)
+                 SELECT id, IFNULL(title, ''), url FROM moz_places
+                 WHERE id > :last_id AND id <= :batch_end
+                   AND (last_visit_date_local + last_visit_date_remote) != 0",
+                rusqlite::named_params! {
+                    ":last_id": last_id,
+                    ":batch_end": batch_end,
+                },
+            )?;
+            put_meta(db, MOZ_META_KEY_FTS_BACKFILL_LAST_ID, &batch_end)?;
+            true
+        }
+        None => {
+            log::debug!("Finished backfilling the history full-text index");
+            delete_meta(db, MOZ_META_KEY_FTS_BACKFILL_LAST_ID)?;
+            false
+        }
+    };
+    tx.commit()?;
+    Ok(more)
+}
+
+// Turn the user's query into an FTS5 expression that matches rows containing a word starting with
+// each of the query's words. Each word is quoted, so that FTS5 operators and punctuation are
+// treated as plain text.
+fn match_expression(query: &str) -> Option<String> {
+    let words = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+// Higher is better. `bm25()` returns more negative numbers for better matches, and frecency is
+// scaled logarithmically since it can vary by orders of magnitude between pages.
+fn blended_rank(text_rank: f64, frecency: i64) -> f64 {
+    -text_rank * TEXT_MATCH_WEIGHT + (frecency.max(0) as f64).ln_1p() * FRECENCY_WEIGHT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, delete_visits_for};
+    use crate::types::VisitType;
+
+    fn visit(conn: &PlacesDb, url: &str, title: &str, count: usize) {
+        for _ in 0..count {
+            apply_observation(
+                conn,
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_title(title.to_string())
+                    .with_visit_type(VisitType::Link),
+            )
+            .unwrap();
+        }
+    }
+
+    fn search_urls(conn: &PlacesDb, query: &str, limit: u32) -> Vec<String> {
+        search_history_fts(conn, query, limit)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.url.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_match_expression() {
+        assert_eq!(match_expression("   "), None);
+        assert_eq!(
+            match_expression("rust \"book"),
+            Some("\"rust\"* \"\"\"book\"*".to_string())
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        visit(&conn, "https://example.com/rust", "The Rust Book", 1);
+        visit(&conn, "https://example.com/crab", "Crabs of the world", 1);
+        visit(&conn, "https://rustacean.example.org/", "Home", 1);
+
+        assert_eq!(
+            search_urls(&conn, "rust book", 10),
+            vec!["https://example.com/rust"]
+        );
+        // Prefix matches on both titles and URLs.
+        let mut urls = search_urls(&conn, "rus", 10);
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec!["https://example.com/rust", "https://rustacean.example.org/"]
+        );
+        assert!(search_urls(&conn, "AND OR", 10).is_empty());
+        assert!(search_urls(&conn, "", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_blends_frecency() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        visit(&conn, "https://example.com/a", "Mozilla news", 1);
+        visit(&conn, "https://example.com/b", "Mozilla news", 20);
+        crate::storage::update_all_frecencies_at_once(
+            &conn,
+            &conn.begin_interrupt_scope().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            search_urls(&conn, "mozilla", 1),
+            vec!["https://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn test_index_updates() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        visit(&conn, "https://example.com/", "Old title", 1);
+        assert_eq!(search_urls(&conn, "old", 10), vec!["https://example.com/"]);
+
+        visit(&conn, "https://example.com/", "New title", 1);
+        assert!(search_urls(&conn, "old", 10).is_empty());
+        assert_eq!(search_urls(&conn, "new", 10), vec!["https://example.com/"]);
+
+        let guid = conn
+            .query_one::<String>("SELECT guid FROM moz_places")
+            .unwrap();
+        delete_visits_for(&conn, &guid.into()).unwrap();
+        assert!(search_urls(&conn, "new", 10).is_empty());
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_fts")
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_backfill() {
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        for i in 0..5 {
+            visit(&conn, &format!("https://example.com/{i}"), "Some page", 1);
+        }
+        // Pretend we were upgraded from a schema without the index.
+        conn.execute_batch("DELETE FROM moz_places_fts").unwrap();
+        put_meta(&conn, MOZ_META_KEY_FTS_BACKFILL_LAST_ID, &0).unwrap();
+
+        assert!(backfill_index(&conn, 2).unwrap());
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_fts")
+                .unwrap(),
+            2
+        );
+        while backfill_index(&conn, 2).unwrap() {}
+        assert_eq!(search_urls(&conn, "page", 10).len(), 5);
+        assert!(!backfill_index(&conn, 2).unwrap());
+    }
+}
@@ -7,7 +7,9 @@
 
 pub mod bookmarks;
 pub mod history;
+pub mod history_fts;
 pub mod history_metadata;
+pub mod page_images;
 pub mod tags;
 
 use crate::db::PlacesDb;
@@ -294,6 +296,7 @@ pub fn run_maintenance_vacuum(conn: &PlacesDb) -> Result<()> {
 /// Kotlin wrapper code (This is needed because we only have access to the Glean API in Kotlin and
 /// it supports a stop-watch style API, not recording specific values).
 pub fn run_maintenance_optimize(conn: &PlacesDb) -> Result<()> {
+    history_fts::backfill_index(conn, history_fts::BACKFILL_BATCH_SIZE)?;
     conn.execute_one("PRAGMA optimize")?;
     Ok(())
 }
@@ -0,0 +1,326 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Favicons and preview images for pages.
+//!
+//! Apps show these for top sites and other pages on their home screens. Storing them here, keyed
+//! by page, means they're removed along with the page's history, and apps don't need a separate
+//! image cache. The total size of the stored images is capped by a byte budget; when it's
+//! exceeded, the images for the least recently accessed pages are evicted.
+
+use super::{get_meta, put_meta};
+use crate::api::places_api::ConnectionType;
+use crate::db::schema::MOZ_META_KEY_PAGE_IMAGES_BUDGET;
+use crate::db::PlacesDb;
+use crate::error::Result;
+use sql_support::ConnExt;
+use types::Timestamp;
+use url::Url;
+
+/// The default maximum total size of the stored images, in bytes.
+pub const DEFAULT_BUDGET_BYTES: u64 = 20 * 1024 * 1024;
+
+/// The images stored for a page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageImages {
+    pub url: Url,
+    pub favicon_url: Option<String>,
+    pub favicon: Option<Vec<u8>>,
+    /// From the page's history; see `VisitObservation::preview_image_url`.
+    pub preview_image_url: Option<String>,
+    pub preview_image: Option<Vec<u8>>,
+}
+
+/// Store the favicon and preview image for a page, replacing any that were stored before.
+///
+/// Nothing is stored for pages which aren't in history or bookmarks. Images larger than the whole
+/// budget aren't stored either, though they still replace the page's old images. If storing the
+/// images puts us over budget, the least recently accessed images are evicted.
+pub fn set_page_images(
+    db: &PlacesDb,
+    url: &Url,
+    favicon_url: Option<&str>,
+    favicon: Option<&[u8]>,
+    preview_image: Option<&[u8]>,
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let place_id: Option<i64> = db.try_query_one(
+        "SELECT id FROM moz_places WHERE url_hash = hash(:url) AND url = :url",
+        &[(":url", &url.as_str())],
+        true,
+    )?;
+    let Some(place_id) = place_id else {
+        log::debug!("Not storing images for an unknown page");
+        return Ok(());
+    };
+    let size = favicon.map_or(0, <[u8]>::len) + preview_image.map_or(0, <[u8]>::len);
+    let size = i64::try_from(size).unwrap_or(i64::MAX);
+    if size > get_budget(db)? {
+        // Storing these would evict every other page's images, and then these too.
+        log::warn!("Not storing {size} bytes of images, which is over the budget");
+        db.execute_cached(
+            "DELETE FROM moz_page_images WHERE place_id = :place_id",
+            &[(":place_id", &place_id)],
+        )?;
+        tx.commit()?;
+        return Ok(());
+    }
+    db.execute_cached(
+        "INSERT OR REPLACE INTO moz_page_images
+             (place_id, favicon_url, favicon, preview_image, size, last_accessed)
+         VALUES (:place_id, :favicon_url, :favicon, :preview_image, :size, :now)",
+        rusqlite::named_params! {
+            ":place_id": place_id,
+            ":favicon_url": favicon_url,
+            ":favicon": favicon,
+            ":preview_image": preview_image,
+            ":size": size,
+            ":now": Timestamp::now(),
+        },
+    )?;
+    evict_over_budget(db)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the stored images for each of `urls`.
+///
+/// Pages without a stored image or a preview image URL are skipped, so the result may be shorter
+/// than `urls`. On writable connections, this also marks the images as recently accessed, so
+/// they're evicted last.
+pub fn get_page_images(db: &PlacesDb, urls: &[Url]) -> Result<Vec<PageImages>> {
+    let tx = db.begin_transaction()?;
+    let now = Timestamp::now();
+    let mut result = Vec::with_capacity(urls.len());
+    for url in urls {
+        let images = db.try_query_row(
+            "SELECT h.id, h.preview_image_url,
+                    i.favicon_url, i.favicon, i.preview_image, i.place_id AS has_images
+             FROM moz_places h
+             LEFT JOIN moz_page_images i ON i.place_id = h.id
+             WHERE h.url_hash = hash(:url) AND h.url = :url
+               AND (i.place_id NOT NULL OR h.preview_image_url NOT NULL)",
+            &[(":url", &url.as_str())],
+            |row| -> Result<_> {
+                Ok((
+                    row.get::<_, i64>("id")?,
+                    row.get::<_, Option<i64>>("has_images")?.is_some(),
+                    PageImages {
+                        url: url.clone(),
+                        favicon_url: row.get("favicon_url")?,
+                        favicon: row.get("favicon")?,
+                        preview_image_url: row.get("preview_image_url")?,
+                        preview_image: row.get("preview_image")?,
+                    },
+                ))
+            },
+            true,
+        )?;
+        if let Some((place_id, has_images, images)) = images {
+            if has_images && db.conn_type() != ConnectionType::ReadOnly {
+                db.execute_cached(
+                    "UPDATE moz_page_images SET last_accessed = :now WHERE place_id = :place_id",
+                    rusqlite::named_params! {
+                        ":now": now,
+                        ":place_id": place_id,
+                    },
+                )?;
+            }
+            result.push(images);
+        }
+    }
+    tx.commit()?;
+    Ok(result)
+}
+
+/// Set the maximum total size of the stored images, in bytes, evicting images if they're now over
+/// budget.
+pub fn set_page_images_budget(db: &PlacesDb, budget_bytes: u64) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    // The budget is stored as an `i64`, and anything larger is as good as unlimited.
+    let budget = i64::try_from(budget_bytes).unwrap_or(i64::MAX);
+    put_meta(db, MOZ_META_KEY_PAGE_IMAGES_BUDGET, &budget)?;
+    evict_over_budget(db)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Get the total size of the stored images, in bytes.
+pub fn get_page_images_size(db: &PlacesDb) -> Result<u64> {
+    let size: i64 = db.query_one("SELECT IFNULL(SUM(size), 0) FROM moz_page_images")?;
+    Ok(size as u64)
+}
+
+fn get_budget(db: &PlacesDb) -> Result<i64> {
+    let budget = get_meta::<i64>(db, MOZ_META_KEY_PAGE_IMAGES_BUDGET)?;
+    Ok(budget.unwrap_or(DEFAULT_BUDGET_BYTES as i64))
+}
+
+fn evict_over_budget(db: &PlacesDb) -> Result<()> {
+    let budget = get_budget(db)?;
+    // Keep the most recently accessed images that fit in the budget.
+    let evicted = db.execute_cached(
+        "DELETE FROM moz_page_images WHERE place_id IN (
+             SELECT place_id FROM (
+                 SELECT place_id,
+                        SUM(size) OVER (ORDER BY last_accessed DESC, place_id DESC) AS total
+                 FROM moz_page_images
+             )
+             WHERE total > :budget
+         )",
+        &[(":budget", &budget)],
+    )?;
+    if evicted > 0 {
+        log::debug!("Evicted images for {evicted} pages");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{
+        apply_observation, delete_everything, delete_visits_for, url_to_guid,
+    };
+    use crate::types::VisitType;
+
+    fn visit(conn: &PlacesDb, url: &str, preview_image_url: Option<&str>) -> Url {
+        let url = Url::parse(url).unwrap();
+        let mut obs = VisitObservation::new(url.clone()).with_visit_type(VisitType::Link);
+        if let Some(preview_image_url) = preview_image_url {
+            obs = obs.with_preview_image_url(Url::parse(preview_image_url).unwrap());
+        }
+        apply_observation(conn, obs).unwrap();
+        url
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let conn = new_mem_connection();
+        let a = visit(&conn, "https://a.example.com/", None);
+        let b = visit(
+            &conn,
+            "https://b.example.com/",
+            Some("https://b.example.com/preview.png"),
+        );
+        let unknown = Url::parse("https://unknown.example.com/").unwrap();
+
+        set_page_images(
+            &conn,
+            &a,
+            Some("https://a.example.com/favicon.ico"),
+            Some(b"icon"),
+            Some(b"preview"),
+        )
+        .unwrap();
+        // Nothing is stored for pages we don't know about.
+        set_page_images(&conn, &unknown, None, Some(b"icon"), None).unwrap();
+        assert_eq!(get_page_images_size(&conn).unwrap(), 11);
+
+        let images = get_page_images(&conn, &[a.clone(), unknown, b.clone()]).unwrap();
+        assert_eq!(
+            images,
+            vec![
+                PageImages {
+                    url: a.clone(),
+                    favicon_url: Some("https://a.example.com/favicon.ico".into()),
+                    favicon: Some(b"icon".to_vec()),
+                    preview_image_url: None,
+                    preview_image: Some(b"preview".to_vec()),
+                },
+                PageImages {
+                    url: b.clone(),
+                    favicon_url: None,
+                    favicon: None,
+                    preview_image_url: Some("https://b.example.com/preview.png".into()),
+                    preview_image: None,
+                },
+            ]
+        );
+
+        // Setting the images again replaces them.
+        set_page_images(&conn, &a, None, Some(b"new icon"), None).unwrap();
+        let images = get_page_images(&conn, &[a]).unwrap();
+        assert_eq!(images[0].favicon_url, None);
+        assert_eq!(images[0].favicon, Some(b"new icon".to_vec()));
+        assert_eq!(images[0].preview_image, None);
+        assert_eq!(get_page_images_size(&conn).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let conn = new_mem_connection();
+        set_page_images_budget(&conn, 10).unwrap();
+        let a = visit(&conn, "https://a.example.com/", None);
+        let b = visit(&conn, "https://b.example.com/", None);
+        let c = visit(&conn, "https://c.example.com/", None);
+
+        set_page_images(&conn, &a, None, Some(b"aaaa"), None).unwrap();
+        set_page_images(&conn, &b, None, Some(b"bbbb"), None).unwrap();
+        // Accessing `a` makes `b` the least recently used.
+        conn.execute("UPDATE moz_page_images SET last_accessed = 1", [])
+            .unwrap();
+        get_page_images(&conn, &[a.clone()]).unwrap();
+        set_page_images(&conn, &c, None, Some(b"cccc"), None).unwrap();
+
+        let stored: Vec<_> = get_page_images(&conn, &[a.clone(), b.clone(), c.clone()])
+            .unwrap()
+            .into_iter()
+            .map(|images| images.url)
+            .collect();
+        assert_eq!(stored, vec![a, c]);
+        assert_eq!(get_page_images_size(&conn).unwrap(), 8);
+
+        // Lowering the budget evicts more.
+        set_page_images_budget(&conn, 4).unwrap();
+        assert_eq!(get_page_images_size(&conn).unwrap(), 4);
+        set_page_images_budget(&conn, 0).unwrap();
+        assert_eq!(get_page_images_size(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_over_budget_images_rejected() {
+        let conn = new_mem_connection();
+        set_page_images_budget(&conn, 10).unwrap();
+        let a = visit(&conn, "https://a.example.com/", None);
+        let b = visit(&conn, "https://b.example.com/", None);
+        set_page_images(&conn, &a, None, Some(b"aaaa"), None).unwrap();
+        set_page_images(&conn, &b, None, Some(b"bbbb"), None).unwrap();
+
+        // An image bigger than the whole budget isn't stored, and doesn't evict anything else,
+        // but it still replaces the page's old images.
+        set_page_images(&conn, &b, None, Some(b"bbbbbbbbbbbb"), None).unwrap();
+        let stored: Vec<_> = get_page_images(&conn, &[a.clone(), b.clone()])
+            .unwrap()
+            .into_iter()
+            .map(|images| images.url)
+            .collect();
+        assert_eq!(stored, vec![a]);
+        assert_eq!(get_page_images_size(&conn).unwrap(), 4);
+
+        // Huge budgets are clamped, rather than wrapping around to a negative one.
+        set_page_images_budget(&conn, u64::MAX).unwrap();
+        set_page_images(&conn, &b, None, Some(b"bbbbbbbbbbbb"), None).unwrap();
+        assert_eq!(get_page_images_size(&conn).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_removed_with_history() {
+        let conn = new_mem_connection();
+        let a = visit(&conn, "https://a.example.com/", None);
+        let b = visit(&conn, "https://b.example.com/", None);
+        set_page_images(&conn, &a, None, Some(b"aaaa"), None).unwrap();
+        set_page_images(&conn, &b, None, Some(b"bbbb"), None).unwrap();
+
+        let guid = url_to_guid(&conn, &a).unwrap().unwrap();
+        delete_visits_for(&conn, &guid).unwrap();
+        assert_eq!(get_page_images(&conn, &[a]).unwrap(), vec![]);
+        assert_eq!(get_page_images_size(&conn).unwrap(), 4);
+
+        delete_everything(&conn).unwrap();
+        assert_eq!(get_page_images_size(&conn).unwrap(), 0);
+    }
+}
@@ -66,8 +66,15 @@ pub fn validate_tag(tag: &str) -> ValidatedTag<'_> {
 ///
 /// There is no success return value.
 pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
-    let tag = validate_tag(tag).ensure_valid()?;
     let tx = db.begin_transaction()?;
+    tag_url_in_tx(db, url, tag)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// As for `tag_url`, but for callers which already hold a transaction.
+pub(crate) fn tag_url_in_tx(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
+    let tag = validate_tag(tag).ensure_valid()?;
 
     // This function will not create a new place.
     // Fetch the place id, so we (a) avoid creating a new tag when we aren't
@@ -91,7 +98,6 @@ pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
             (":place_id", &place_id),
         ],
     )?;
-    tx.commit()?;
     Ok(())
 }
 
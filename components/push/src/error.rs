@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use error_support::{ErrorHandling, GetErrorHandling};
+use error_support::{ErrorCode, ErrorHandling, GetErrorHandling};
 
 pub type Result<T, E = PushError> = std::result::Result<T, E>;
 
@@ -18,6 +18,12 @@ pub enum PushApiError {
     #[error("No record for chid {0}")]
     RecordNotFoundError(String),
 
+    /// The encryption key given doesn't match the one the subscriptions
+    /// were stored with, so their private keys can't be read. The consumer
+    /// should delete the database and resubscribe.
+    #[error("Encryption key mismatch: {0}")]
+    EncryptionKeyMismatchError(String),
+
     /// Internal Error
     #[error("Internal Error: {0}")]
     InternalError(String),
@@ -77,6 +83,11 @@ pub enum PushError {
     /// Was unable to open the database
     #[error("Error opening database: {0}")]
     OpenDatabaseError(#[from] sql_support::open_database::Error),
+
+    /// The database was encrypted with a different key, or with a key which
+    /// wasn't given.
+    #[error("Encryption key mismatch: {0}")]
+    EncryptionKeyMismatchError(String),
 }
 
 impl From<bincode::Error> for PushError {
@@ -91,6 +102,12 @@ impl From<base64::DecodeError> for PushError {
     }
 }
 
+impl From<jwcrypto::EncryptorDecryptorError> for PushError {
+    fn from(value: jwcrypto::EncryptorDecryptorError) -> Self {
+        PushError::CryptoError(value.to_string())
+    }
+}
+
 impl From<rc_crypto::ece::Error> for PushError {
     fn from(value: rc_crypto::ece::Error) -> Self {
         PushError::CryptoError(value.to_string())
@@ -108,6 +125,23 @@ impl GetErrorHandling for PushError {
             }
             Self::RecordNotFoundError(s) => {
                 ErrorHandling::convert(PushApiError::RecordNotFoundError(s.clone()))
+                    .code(ErrorCode::NotFound)
+            }
+            Self::EncryptionKeyMismatchError(s) => {
+                ErrorHandling::convert(PushApiError::EncryptionKeyMismatchError(s.clone()))
+                    .code(ErrorCode::Storage)
+                    .report_error("push-encryption-key-mismatch")
+            }
+            Self::CommunicationError(_)
+            | Self::CommunicationServerError(_)
+            | Self::RequestError(_) => {
+                ErrorHandling::convert(PushApiError::InternalError(self.to_string()))
+                    .code(ErrorCode::Network)
+                    .retryable()
+            }
+            Self::StorageError(_) | Self::StorageSqlError(_) | Self::OpenDatabaseError(_) => {
+                ErrorHandling::convert(PushApiError::InternalError(self.to_string()))
+                    .code(ErrorCode::Storage)
             }
 
             _ => ErrorHandling::convert(PushApiError::InternalError(self.to_string())),
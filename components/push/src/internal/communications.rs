@@ -162,6 +162,12 @@ pub trait Connection: Sized {
     /// # Returns
     /// A list of channel ids representing all the channels the user is subscribed to
     fn channel_list(&self, uaid: &str, auth: &str) -> error::Result<Vec<String>>;
+
+    /// Deliver an already encrypted push message to a subscription, as an application server would.
+    /// # Arguments
+    /// - `endpoint`: The subscription's endpoint URL
+    /// - `body`: The message, encrypted using the "aes128gcm" content encoding
+    fn send_notification(&self, endpoint: &str, body: Vec<u8>) -> error::Result<()>;
 }
 
 /// Connect to the Autopush server via the HTTP interface
@@ -375,6 +381,19 @@ impl Connection for ConnectHttp {
             .map(|s| Store::normalize_uuid(s))
             .collect())
     }
+
+    fn send_notification(&self, endpoint: &str, body: Vec<u8>) -> error::Result<()> {
+        let response = Request::post(Url::parse(endpoint)?)
+            // If the device can't be reached within a minute, the message is no longer useful.
+            .header("TTL", "60")?
+            .header("Content-Encoding", "aes128gcm")?
+            .body(body)
+            .send()?;
+        log::info!("sent notification via {}: {}", endpoint, response.status);
+        // The push service reports errors in the same format as the registration endpoints.
+        self.check_response_error(&response)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
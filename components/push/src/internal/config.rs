@@ -67,6 +67,12 @@ pub struct PushConfiguration {
     /// the verify connection call
     /// defaults to 24 hours
     pub verify_connection_rate_limiter: Option<u64>,
+
+    /// A key created by [`create_encryption_key`](`crate::create_encryption_key`),
+    /// used to encrypt the subscriptions' private keys in the database. If
+    /// `None`, they're stored unencrypted. Once a key has been given, the same
+    /// key must be given every time the database is opened.
+    pub encryption_key: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -111,6 +117,7 @@ impl Default for PushConfiguration {
             sender_id: String::from(""),
             database_path: String::from(""),
             verify_connection_rate_limiter: Some(DEFAULT_VERIFY_CONNECTION_LIMITER_INTERVAL),
+            encryption_key: None,
         }
     }
 }
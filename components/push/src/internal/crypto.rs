@@ -35,8 +35,10 @@ pub(crate) enum VersionnedKey<'a> {
     V1(Cow<'a, KeyV1>),
 }
 
+/// The content encodings of push messages. "aesgcm" is the legacy draft encoding, which we
+/// only still support because some senders use it.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum CryptoEncoding {
+pub(crate) enum CryptoEncoding {
     Aesgcm,
     Aes128gcm,
 }
@@ -136,6 +138,9 @@ pub trait Cryptography: Default {
 
     /// Decrypt the RFC 8188 format.
     fn decrypt_aes128gcm(key: &Key, content: &[u8]) -> error::Result<Decrypted>;
+
+    /// Encrypt a payload to the public key in RFC 8188 format, as an application server would.
+    fn encrypt(key: &Key, content: &[u8]) -> error::Result<Vec<u8>>;
 }
 
 #[derive(Default)]
@@ -226,6 +231,11 @@ impl Cryptography for Crypto {
     fn decrypt_aes128gcm(key: &Key, content: &[u8]) -> error::Result<Vec<u8>> {
         Ok(ece::decrypt(key.key_pair(), key.auth_secret(), content)?)
     }
+
+    fn encrypt(key: &Key, content: &[u8]) -> error::Result<Vec<u8>> {
+        rc_crypto::ensure_initialized();
+        Ok(ece::encrypt(key.public_key(), key.auth_secret(), content)?)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -335,4 +345,12 @@ mod crypto_tests {
         let decrypted = decrypter(ciphertext, "aes128gcm", "", "").unwrap();
         assert_eq!(String::from_utf8(decrypted).unwrap(), PLAINTEXT.to_string());
     }
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let key = Crypto::generate_key().unwrap();
+        let ciphertext = Crypto::encrypt(&key, PLAINTEXT.as_bytes()).unwrap();
+        let decrypted = Crypto::decrypt_aes128gcm(&key, &ciphertext).unwrap();
+        assert_eq!(String::from_utf8(decrypted).unwrap(), PLAINTEXT.to_string());
+    }
 }
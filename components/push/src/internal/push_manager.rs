@@ -13,18 +13,86 @@
 //! - routinely check subscriptions to make sure they are in a good state.
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use types::Timestamp;
 
 use crate::error::{self, PushError, Result};
 use crate::internal::communications::{Connection, PersistedRateLimiter};
 use crate::internal::config::PushConfiguration;
 use crate::internal::crypto::KeyV1 as Key;
 use crate::internal::storage::{PushRecord, Storage};
-use crate::{KeyInfo, PushSubscriptionChanged, SubscriptionInfo, SubscriptionResponse};
+use crate::{
+    ConfigUpdate, EncodingStats, KeyInfo, PushSubscriptionChanged, SubscriptionInfo,
+    SubscriptionResponse,
+};
 
-use super::crypto::{Cryptography, PushPayload};
+use super::crypto::{get_random_bytes, CryptoEncoding, Cryptography, PushPayload};
 const UPDATE_RATE_LIMITER_INTERVAL: u64 = 24 * 60 * 60; // 24 hours.
 const UPDATE_RATE_LIMITER_MAX_CALLS: u16 = 500; // 500
+const TEST_NOTIFICATION_PREFIX: &str = "app-services-push-test:";
+// When `verify_connection` last checked our channels with the server, in ms since the epoch.
+const LAST_VERIFY_CONNECTION_META_KEY: &str = "last_verify_connection";
+// How much of the UAID we include in `debug_dump`.
+const DEBUG_DUMP_UAID_PREFIX_LEN: usize = 8;
+// How many messages we've decrypted in each content encoding.
+const AESGCM_MESSAGE_COUNT_META_KEY: &str = "aesgcm_message_count";
+const AES128GCM_MESSAGE_COUNT_META_KEY: &str = "aes128gcm_message_count";
+// How many decrypted messages we count in memory before writing the encoding stats to storage.
+const ENCODING_STATS_FLUSH_THRESHOLD: u64 = 50;
+// The `UaidIssuer` of our UAID, as JSON.
+const UAID_ISSUER_META_KEY: &str = "uaid_issuer";
+// Subscriptions dropped because the UAID issuer changed between runs, which `verify_connection`
+// returns.
+const DROPPED_SUBSCRIPTIONS_META_KEY: &str = "dropped_subscriptions";
+
+/// The parts of the configuration a UAID is tied to. A UAID only works with the server and
+/// bridge which issued it, so if any of these change, we need a new one.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct UaidIssuer {
+    server_host: String,
+    bridge_type: String,
+    sender_id: String,
+}
+
+impl UaidIssuer {
+    fn new(config: &PushConfiguration) -> Self {
+        UaidIssuer {
+            server_host: config.server_host.clone(),
+            bridge_type: config.bridge_type.to_string(),
+            sender_id: config.sender_id.clone(),
+        }
+    }
+}
+
+/// A subscription dropped because the UAID issuer changed.
+#[derive(Debug, Serialize, Deserialize)]
+struct DroppedSubscription {
+    channel_id: String,
+    scope: String,
+    #[serde(default)]
+    app_server_key: Option<String>,
+}
+
+impl From<PushRecord> for DroppedSubscription {
+    fn from(record: PushRecord) -> Self {
+        DroppedSubscription {
+            channel_id: record.channel_id,
+            scope: record.scope,
+            app_server_key: record.app_server_key,
+        }
+    }
+}
+
+impl From<DroppedSubscription> for PushSubscriptionChanged {
+    fn from(subscription: DroppedSubscription) -> Self {
+        PushSubscriptionChanged {
+            channel_id: subscription.channel_id,
+            scope: subscription.scope,
+        }
+    }
+}
 
 impl From<Key> for KeyInfo {
     fn from(key: Key) -> Self {
@@ -65,6 +133,7 @@ pub struct DecryptResponse {
 
 pub struct PushManager<Co, Cr, S> {
     _crypo: Cr,
+    config: PushConfiguration,
     connection: Co,
     uaid: Option<String>,
     auth: Option<String>,
@@ -72,21 +141,32 @@ pub struct PushManager<Co, Cr, S> {
     store: S,
     update_rate_limiter: PersistedRateLimiter,
     verify_connection_rate_limiter: PersistedRateLimiter,
+    pending_encodings: PendingEncodings,
+}
+
+/// The encoding stats of the messages decrypted since we last wrote them to storage. These are
+/// only telemetry, so we batch them rather than writing to the database for every message, and
+/// can lose a few if we're shut down before they're written.
+#[derive(Default)]
+struct PendingEncodings {
+    last_encodings: HashMap<String, String>,
+    aesgcm_messages: u64,
+    aes128gcm_messages: u64,
+}
+
+impl PendingEncodings {
+    fn messages(&self) -> u64 {
+        self.aesgcm_messages + self.aes128gcm_messages
+    }
 }
 
 impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
     pub fn new(config: PushConfiguration) -> Result<Self> {
-        let store = S::open(&config.database_path)?;
+        let store = S::open(&config.database_path, config.encryption_key.as_deref())?;
         let uaid = store.get_uaid()?;
         let auth = store.get_auth()?;
         let registration_id = store.get_registration_id()?;
-        let verify_connection_rate_limiter = PersistedRateLimiter::new(
-            "verify_connection",
-            config
-                .verify_connection_rate_limiter
-                .unwrap_or(super::config::DEFAULT_VERIFY_CONNECTION_LIMITER_INTERVAL),
-            1,
-        );
+        let verify_connection_rate_limiter = Self::verify_connection_rate_limiter(&config);
 
         let update_rate_limiter = PersistedRateLimiter::new(
             "update_token",
@@ -94,8 +174,9 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
             UPDATE_RATE_LIMITER_MAX_CALLS,
         );
 
-        Ok(Self {
-            connection: Co::connect(config),
+        let mut push_manager = Self {
+            connection: Co::connect(config.clone()),
+            config,
             _crypo: Default::default(),
             uaid,
             auth,
@@ -103,6 +184,159 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
             store,
             update_rate_limiter,
             verify_connection_rate_limiter,
+            pending_encodings: Default::default(),
+        };
+        push_manager.check_uaid_issuer()?;
+        Ok(push_manager)
+    }
+
+    // Our UAID is only known to the server and bridge which issued it, so if we're created with a
+    // configuration it can't be used with, every request with it would fail. Drop it, and keep
+    // the subscriptions for `verify_connection` to re-create, since we don't want to make
+    // requests while being constructed. Unlike `update_config`, we can't unsubscribe from the old
+    // server, because we no longer have a connection to it.
+    fn check_uaid_issuer(&mut self) -> Result<()> {
+        let issuer = UaidIssuer::new(&self.config);
+        let previous = self.get_uaid_issuer()?;
+        if previous.is_some_and(|previous| previous != issuer) && self.uaid.is_some() {
+            let mut dropped = self.get_dropped_subscriptions()?;
+            dropped.extend(self.drop_registration()?);
+            log::info!(
+                "the push server or bridge changed, dropping our UAID and {} subscriptions",
+                dropped.len()
+            );
+            self.store.set_meta(
+                DROPPED_SUBSCRIPTIONS_META_KEY,
+                &serde_json::to_string(&dropped)?,
+            )?;
+        }
+        self.set_uaid_issuer(&issuer)
+    }
+
+    // A corrupt issuer is treated as missing, so we keep our UAID rather than failing to start.
+    fn get_uaid_issuer(&self) -> Result<Option<UaidIssuer>> {
+        Ok(match self.store.get_meta(UAID_ISSUER_META_KEY)? {
+            Some(issuer) => serde_json::from_str(&issuer)
+                .map_err(|e| log::warn!("ignoring an invalid UAID issuer: {}", e))
+                .ok(),
+            None => None,
+        })
+    }
+
+    fn set_uaid_issuer(&self, issuer: &UaidIssuer) -> Result<()> {
+        self.store
+            .set_meta(UAID_ISSUER_META_KEY, &serde_json::to_string(issuer)?)
+    }
+
+    // Corrupt subscriptions are treated as missing, like a corrupt issuer. The consumer will find
+    // out about them when it next verifies the connection.
+    fn get_dropped_subscriptions(&self) -> Result<Vec<DroppedSubscription>> {
+        Ok(match self.store.get_meta(DROPPED_SUBSCRIPTIONS_META_KEY)? {
+            Some(dropped) => serde_json::from_str(&dropped).unwrap_or_else(|e| {
+                log::warn!("ignoring invalid dropped subscriptions: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        })
+    }
+
+    // Returns, and forgets, the subscriptions dropped by `check_uaid_issuer`.
+    fn take_dropped_subscriptions(&mut self) -> Result<Vec<DroppedSubscription>> {
+        let dropped = self.get_dropped_subscriptions()?;
+        if !dropped.is_empty() {
+            self.store.set_meta(DROPPED_SUBSCRIPTIONS_META_KEY, "[]")?;
+        }
+        Ok(dropped)
+    }
+
+    // Re-creates the subscriptions dropped by `check_uaid_issuer` on the new server, returning
+    // them so that the consumer can send the new endpoints to its application servers. If we
+    // don't have a registration ID yet, we can't subscribe, so the consumer has to re-subscribe
+    // itself.
+    fn resubscribe_dropped(&mut self) -> Result<Vec<PushSubscriptionChanged>> {
+        let dropped = self.get_dropped_subscriptions()?;
+        if dropped.is_empty() {
+            return Ok(Vec::new());
+        }
+        if self.registration_id.is_some() {
+            for subscription in &dropped {
+                // If the consumer already re-subscribed to this scope, this returns that
+                // subscription. If it fails, they're all retried next time.
+                self.subscribe(&subscription.scope, subscription.app_server_key.as_deref())?;
+            }
+        }
+        Ok(self
+            .take_dropped_subscriptions()?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    // Drops our UAID and all the subscriptions made with it, returning the subscriptions so that
+    // they can be re-created.
+    fn drop_registration(&mut self) -> Result<Vec<DroppedSubscription>> {
+        let mut dropped = Vec::new();
+        for channel_id in self.store.get_channel_list()? {
+            if let Some(record) = self.store.get_record(&channel_id)? {
+                dropped.push(record.into());
+            }
+        }
+        self.wipe_local_registrations()?;
+        Ok(dropped)
+    }
+
+    // The limiter's state is persisted, so recreating it with a new interval doesn't lose it.
+    fn verify_connection_rate_limiter(config: &PushConfiguration) -> PersistedRateLimiter {
+        PersistedRateLimiter::new(
+            "verify_connection",
+            config
+                .verify_connection_rate_limiter
+                .unwrap_or(super::config::DEFAULT_VERIFY_CONNECTION_LIMITER_INTERVAL),
+            1,
+        )
+    }
+
+    pub fn update_config(&mut self, config: PushConfiguration) -> Result<ConfigUpdate> {
+        if config.database_path != self.config.database_path
+            || config.encryption_key != self.config.encryption_key
+        {
+            return Err(PushError::GeneralError(
+                "The database can't be changed without creating a new PushManager".into(),
+            ));
+        }
+        let issuer = UaidIssuer::new(&config);
+        let compatible = issuer == UaidIssuer::new(&self.config);
+        let mut resubscribe = Vec::new();
+        if !compatible {
+            // Subscriptions dropped at startup that the consumer hasn't heard about yet.
+            resubscribe.extend(
+                self.take_dropped_subscriptions()?
+                    .into_iter()
+                    .map(Into::into),
+            );
+            if self.uaid.is_some() {
+                // Tell the old server we're going, but we'll never talk to it again, so a
+                // failure doesn't stop us moving to the new one.
+                let (uaid, auth) = self.ensure_auth_pair()?;
+                if let Err(e) = self.connection.unsubscribe_all(uaid, auth) {
+                    log::warn!("failed to unsubscribe from the old push server: {}", e);
+                }
+                resubscribe.extend(self.drop_registration()?.into_iter().map(Into::into));
+            }
+        }
+        self.verify_connection_rate_limiter = Self::verify_connection_rate_limiter(&config);
+        self.connection = Co::connect(config.clone());
+        self.set_uaid_issuer(&issuer)?;
+        self.config = config;
+        Ok(if compatible {
+            log::info!("updated the push configuration, keeping our UAID");
+            ConfigUpdate::Migrated
+        } else {
+            log::info!(
+                "updated the push configuration, dropping {} subscriptions",
+                resubscribe.len()
+            );
+            ConfigUpdate::Reregistered { resubscribe }
         })
     }
 
@@ -156,6 +390,13 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
             .transpose()
     }
 
+    pub fn get_subscription_matching(&self, url: &str) -> Result<Option<SubscriptionResponse>> {
+        self.store
+            .get_record_matching_scope(url)?
+            .map(TryInto::try_into)
+            .transpose()
+    }
+
     pub fn unsubscribe(&mut self, scope: &str) -> Result<bool> {
         let (uaid, auth) = self.ensure_auth_pair()?;
         let record = self.store.get_record_by_scope(scope)?;
@@ -227,6 +468,13 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
             self.verify_connection_rate_limiter.reset(&self.store);
         }
 
+        // If the server or bridge changed since we last ran, our old subscriptions are already
+        // gone, so there's nothing to verify yet.
+        let resubscribed = self.resubscribe_dropped()?;
+        if !resubscribed.is_empty() {
+            return Ok(resubscribed);
+        }
+
         // If we were rate limited or there are no subscriptions yet, we should signal to the
         // consumer that everything is ok
         if self.uaid.is_none() || !self.verify_connection_rate_limiter.check(&self.store) {
@@ -246,6 +494,10 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
                 _ => return Err(e),
             },
         };
+        self.store.set_meta(
+            LAST_VERIFY_CONNECTION_META_KEY,
+            &Timestamp::now().as_millis().to_string(),
+        )?;
 
         // verify both lists match. Either side could have lost its mind.
         match remote_channels {
@@ -273,14 +525,16 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
         Ok(subscriptions)
     }
 
-    pub fn decrypt(&self, payload: HashMap<String, String>) -> Result<DecryptResponse> {
+    pub fn decrypt(&mut self, payload: HashMap<String, String>) -> Result<DecryptResponse> {
         let payload = PushPayload::try_from(&payload)?;
         let val = self
             .store
             .get_record(payload.channel_id)?
             .ok_or_else(|| PushError::RecordNotFoundError(payload.channel_id.to_string()))?;
         let key = Key::deserialize(&val.key)?;
+        let encoding = CryptoEncoding::from_str(payload.encoding)?;
         let decrypted = Cr::decrypt(&key, payload)?;
+        self.record_encoding(&val.channel_id, encoding);
         // NOTE: this returns a `Vec<i8>` since the kotlin consumer is expecting
         // signed bytes.
         Ok(DecryptResponse {
@@ -289,6 +543,145 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
         })
     }
 
+    pub fn send_test_notification(&self, scope: &str) -> Result<String> {
+        let record = self
+            .store
+            .get_record_by_scope(scope)?
+            .ok_or_else(|| PushError::RecordNotFoundError(scope.to_string()))?;
+        let key = Key::deserialize(&record.key)?;
+        // A random suffix lets the caller tell this message apart from any earlier tests which
+        // are delivered late.
+        let payload = format!(
+            "{}{}",
+            TEST_NOTIFICATION_PREFIX,
+            URL_SAFE_NO_PAD.encode(get_random_bytes(12)?)
+        );
+        let body = Cr::encrypt(&key, payload.as_bytes())?;
+        self.connection.send_notification(&record.endpoint, body)?;
+        Ok(payload)
+    }
+
+    fn record_encoding(&mut self, channel_id: &str, encoding: CryptoEncoding) {
+        let pending = &mut self.pending_encodings;
+        pending
+            .last_encodings
+            .insert(channel_id.to_string(), encoding.to_string());
+        match encoding {
+            CryptoEncoding::Aesgcm => pending.aesgcm_messages += 1,
+            CryptoEncoding::Aes128gcm => pending.aes128gcm_messages += 1,
+        }
+        if pending.messages() >= ENCODING_STATS_FLUSH_THRESHOLD {
+            // This is only used for telemetry, so it shouldn't stop the message being delivered.
+            if let Err(e) = self.flush_encodings() {
+                log::warn!("failed to record the message encodings: {}", e);
+            }
+        }
+    }
+
+    /// Writes the pending encoding stats to storage, in a single transaction.
+    fn flush_encodings(&mut self) -> Result<()> {
+        if self.pending_encodings.messages() == 0 {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending_encodings);
+        self.store.record_encodings(
+            &pending.last_encodings,
+            &[
+                (AESGCM_MESSAGE_COUNT_META_KEY, pending.aesgcm_messages),
+                (AES128GCM_MESSAGE_COUNT_META_KEY, pending.aes128gcm_messages),
+            ],
+        )
+    }
+
+    fn get_count_meta(&self, key: &str) -> Result<u64> {
+        Ok(self
+            .store
+            .get_meta(key)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default())
+    }
+
+    pub fn get_encoding_stats(&mut self) -> Result<EncodingStats> {
+        self.flush_encodings()?;
+        Ok(EncodingStats {
+            aesgcm_messages: self.get_count_meta(AESGCM_MESSAGE_COUNT_META_KEY)?,
+            aes128gcm_messages: self.get_count_meta(AES128GCM_MESSAGE_COUNT_META_KEY)?,
+            aesgcm_channels: self.legacy_channels()?.len() as u32,
+        })
+    }
+
+    // Only the application server can change the encoding it sends with, so all we can do is drop
+    // these subscriptions; the consumer resubscribes and sends the new subscription to its
+    // server, which needs to use "aes128gcm" from then on.
+    pub fn upgrade_legacy_subscriptions(&mut self) -> Result<Vec<PushSubscriptionChanged>> {
+        self.flush_encodings()?;
+        let legacy_channels = self.legacy_channels()?;
+        if legacy_channels.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (uaid, auth) = self.ensure_auth_pair()?;
+        let mut subscriptions = Vec::with_capacity(legacy_channels.len());
+        for channel_id in legacy_channels {
+            let Some(record) = self.store.get_record(&channel_id)? else {
+                continue;
+            };
+            match self.connection.unsubscribe(&channel_id, uaid, auth) {
+                // If the server has lost our UAID, the channel is gone anyway; the next
+                // `verify_connection()` will clean up the rest.
+                Ok(()) | Err(PushError::UAIDNotRecognizedError(_)) => (),
+                Err(e) => return Err(e),
+            }
+            self.store.delete_record(&channel_id)?;
+            subscriptions.push(record.into());
+        }
+        log::info!(
+            "dropped {} subscriptions using the aesgcm encoding",
+            subscriptions.len()
+        );
+        Ok(subscriptions)
+    }
+
+    fn legacy_channels(&self) -> Result<Vec<String>> {
+        self.store
+            .get_channels_by_last_encoding(&CryptoEncoding::Aesgcm.to_string())
+    }
+
+    pub fn debug_dump(&mut self) -> Result<String> {
+        let mut channels = Vec::new();
+        for channel_id in self.store.get_channel_list()? {
+            if let Some(record) = self.store.get_record(&channel_id)? {
+                channels.push(record);
+            }
+        }
+        channels.sort_by(|a, b| a.scope.cmp(&b.scope));
+        let last_verify_connection = self
+            .store
+            .get_meta(LAST_VERIFY_CONNECTION_META_KEY)?
+            .and_then(|v| v.parse::<u64>().ok());
+        let encoding_stats = self.get_encoding_stats()?;
+        Ok(serde_json::json!({
+            "uaid_prefix": self
+                .uaid
+                .as_deref()
+                .map(|uaid| uaid.chars().take(DEBUG_DUMP_UAID_PREFIX_LEN).collect::<String>()),
+            "has_registration_id": self.registration_id.is_some(),
+            "channel_count": channels.len(),
+            "channels": channels
+                .iter()
+                .map(|record| serde_json::json!({
+                    "scope": record.scope,
+                    "created_at": record.ctime.as_millis(),
+                    "has_app_server_key": record.app_server_key.is_some(),
+                }))
+                .collect::<Vec<_>>(),
+            "last_verify_connection": last_verify_connection,
+            "aesgcm_messages": encoding_stats.aesgcm_messages,
+            "aes128gcm_messages": encoding_stats.aes128gcm_messages,
+            "aesgcm_channels": encoding_stats.aesgcm_channels,
+        })
+        .to_string())
+    }
+
     fn wipe_local_registrations(&mut self) -> error::Result<()> {
         self.store.delete_all_records()?;
         self.auth = None;
@@ -382,7 +775,7 @@ mod test {
 
     use crate::internal::{
         communications::{MockConnection, RegisterResponse, SubscribeResponse},
-        crypto::MockCryptography,
+        crypto::{Crypto, MockCryptography},
     };
 
     use super::*;
@@ -559,6 +952,69 @@ mod test {
             ("cryptokey".to_string(), "".to_string()),
         ]);
         pm.decrypt(payload).unwrap();
+        let stats = pm.get_encoding_stats()?;
+        assert_eq!(stats.aes128gcm_messages, 1);
+        assert_eq!(stats.aesgcm_channels, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_test_notification() -> Result<()> {
+        let _m = get_lock(&MTX);
+        rc_crypto::ensure_initialized();
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+        let mut pm = get_test_manager()?;
+        pm.connection
+            .expect_register()
+            .with(eq("native-id"), eq(None))
+            .times(1)
+            .returning(|_, _| {
+                Ok(RegisterResponse {
+                    uaid: TEST_UAID.to_string(),
+                    channel_id: TEST_CHANNEL_ID.to_string(),
+                    secret: TEST_AUTH.to_string(),
+                    endpoint: "https://example.com/dummy-endpoint".to_string(),
+                    sender_id: Some("test".to_string()),
+                })
+            });
+        let test_key = || Key {
+            p256key: EcKeyComponents::new(
+                URL_SAFE_NO_PAD.decode(PRIV_KEY_D).unwrap(),
+                URL_SAFE_NO_PAD.decode(PUB_KEY_RAW).unwrap(),
+            ),
+            auth: URL_SAFE_NO_PAD.decode(TEST_AUTH).unwrap(),
+        };
+        let crypto_ctx = MockCryptography::generate_key_context();
+        crypto_ctx.expect().returning(move || Ok(test_key()));
+        pm.subscribe("test-scope", None)?;
+
+        // Encrypt for real, so we can check the message the push service would deliver.
+        let encrypt_ctx = MockCryptography::encrypt_context();
+        encrypt_ctx
+            .expect()
+            .withf(move |key, _| *key == test_key())
+            .returning(|key, content| Crypto::encrypt(key, content));
+        let sent = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sent_clone = sent.clone();
+        pm.connection
+            .expect_send_notification()
+            .withf(|endpoint, _| endpoint == "https://example.com/dummy-endpoint")
+            .times(1)
+            .returning(move |_, body| {
+                *sent_clone.lock().unwrap() = body;
+                Ok(())
+            });
+
+        let payload = pm.send_test_notification("test-scope")?;
+        assert!(payload.starts_with(TEST_NOTIFICATION_PREFIX));
+        let decrypted = Crypto::decrypt_aes128gcm(&test_key(), &sent.lock().unwrap())?;
+        assert_eq!(String::from_utf8(decrypted).unwrap(), payload);
+
+        assert!(matches!(
+            pm.send_test_notification("unknown-scope"),
+            Err(PushError::RecordNotFoundError(_))
+        ));
         Ok(())
     }
 
@@ -630,7 +1086,26 @@ mod test {
             ("enc".to_string(), "".to_string()),
             ("cryptokey".to_string(), "".to_string()),
         ]);
+        pm.decrypt(payload.clone()).unwrap();
         pm.decrypt(payload).unwrap();
+        let stats = pm.get_encoding_stats()?;
+        assert_eq!(stats.aesgcm_messages, 2);
+        assert_eq!(stats.aes128gcm_messages, 0);
+        assert_eq!(stats.aesgcm_channels, 1);
+
+        // Upgrading drops the channel, so the consumer resubscribes.
+        pm.connection
+            .expect_unsubscribe()
+            .with(eq(TEST_CHANNEL_ID), eq(TEST_UAID), eq(TEST_AUTH))
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let changed = pm.upgrade_legacy_subscriptions()?;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].channel_id, TEST_CHANNEL_ID);
+        assert_eq!(changed[0].scope, "test-scope");
+        assert!(pm.get_subscription("test-scope")?.is_none());
+        assert_eq!(pm.get_encoding_stats()?.aesgcm_channels, 0);
+        assert!(pm.upgrade_legacy_subscriptions()?.is_empty());
         Ok(())
     }
 
@@ -1003,4 +1478,254 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_debug_dump() -> Result<()> {
+        let _m = get_lock(&MTX);
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+
+        let mut pm = get_test_manager()?;
+        let dump: serde_json::Value = serde_json::from_str(&pm.debug_dump()?)?;
+        assert_eq!(
+            dump,
+            serde_json::json!({
+                "uaid_prefix": null,
+                "has_registration_id": true,
+                "channel_count": 0,
+                "channels": [],
+                "last_verify_connection": null,
+                "aesgcm_messages": 0,
+                "aes128gcm_messages": 0,
+                "aesgcm_channels": 0,
+            })
+        );
+
+        pm.connection
+            .expect_register()
+            .with(eq("native-id"), eq(None))
+            .times(1)
+            .returning(|_, _| {
+                Ok(RegisterResponse {
+                    uaid: TEST_UAID.to_string(),
+                    channel_id: TEST_CHANNEL_ID.to_string(),
+                    secret: TEST_AUTH.to_string(),
+                    endpoint: "https://example.com/dummy-endpoint".to_string(),
+                    sender_id: Some("test".to_string()),
+                })
+            });
+        let crypto_ctx = MockCryptography::generate_key_context();
+        crypto_ctx.expect().returning(|| {
+            let components = EcKeyComponents::new(
+                URL_SAFE_NO_PAD.decode(PRIV_KEY_D).unwrap(),
+                URL_SAFE_NO_PAD.decode(PUB_KEY_RAW).unwrap(),
+            );
+            let auth = URL_SAFE_NO_PAD.decode(TEST_AUTH).unwrap();
+            Ok(Key {
+                p256key: components,
+                auth,
+            })
+        });
+        pm.subscribe("test-scope", None)?;
+        pm.connection
+            .expect_channel_list()
+            .with(eq(TEST_UAID), eq(TEST_AUTH))
+            .times(1)
+            .returning(|_, _| Ok(vec![TEST_CHANNEL_ID.to_string()]));
+        pm.verify_connection(true)?;
+
+        let dump_str = pm.debug_dump()?;
+        // Nothing secret makes it into the dump.
+        assert!(!dump_str.contains(TEST_UAID));
+        assert!(!dump_str.contains(TEST_AUTH));
+        assert!(!dump_str.contains("dummy-endpoint"));
+        let dump: serde_json::Value = serde_json::from_str(&dump_str)?;
+        assert_eq!(dump["uaid_prefix"], "abad1d3a");
+        assert_eq!(dump["channel_count"], 1);
+        assert_eq!(dump["channels"][0]["scope"], "test-scope");
+        assert_eq!(dump["channels"][0]["has_app_server_key"], false);
+        assert!(dump["channels"][0]["created_at"].is_u64());
+        assert!(dump["last_verify_connection"].is_u64());
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_config() -> Result<()> {
+        let _m = get_lock(&MTX);
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+
+        let mut pm = get_test_manager()?;
+        pm.connection
+            .expect_register()
+            .with(eq("native-id"), eq(None))
+            .times(1)
+            .returning(|_, _| {
+                Ok(RegisterResponse {
+                    uaid: TEST_UAID.to_string(),
+                    channel_id: TEST_CHANNEL_ID.to_string(),
+                    secret: TEST_AUTH.to_string(),
+                    endpoint: "https://example.com/dummy-endpoint".to_string(),
+                    sender_id: Some("test".to_string()),
+                })
+            });
+        let crypto_ctx = MockCryptography::generate_key_context();
+        crypto_ctx.expect().returning(|| {
+            let components = EcKeyComponents::new(
+                URL_SAFE_NO_PAD.decode(PRIV_KEY_D).unwrap(),
+                URL_SAFE_NO_PAD.decode(PUB_KEY_RAW).unwrap(),
+            );
+            let auth = URL_SAFE_NO_PAD.decode(TEST_AUTH).unwrap();
+            Ok(Key {
+                p256key: components,
+                auth,
+            })
+        });
+        pm.subscribe("test-scope", None)?;
+        pm.verify_connection_rate_limiter
+            .persist_counters(&pm.store, 123, 1);
+
+        // Changing the rate limiter interval keeps our UAID and the limiter's state.
+        let config = PushConfiguration {
+            verify_connection_rate_limiter: Some(ONE_DAY_AND_ONE_SECOND),
+            ..pm.config.clone()
+        };
+        assert!(matches!(pm.update_config(config)?, ConfigUpdate::Migrated));
+        assert_eq!(pm.store.get_uaid()?.unwrap(), TEST_UAID);
+        assert!(pm.store.get_record(TEST_CHANNEL_ID)?.is_some());
+        assert_eq!(
+            pm.verify_connection_rate_limiter.get_counters(&pm.store),
+            (123, 1)
+        );
+
+        // The database can't be changed.
+        let config = PushConfiguration {
+            database_path: "other.db".to_string(),
+            ..pm.config.clone()
+        };
+        assert!(pm.update_config(config).is_err());
+
+        // A new server needs a new UAID.
+        pm.connection
+            .expect_unsubscribe_all()
+            .with(eq(TEST_UAID), eq(TEST_AUTH))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let config = PushConfiguration {
+            server_host: "push.example.com".to_string(),
+            ..pm.config.clone()
+        };
+        match pm.update_config(config)? {
+            ConfigUpdate::Reregistered { resubscribe } => {
+                assert_eq!(resubscribe.len(), 1);
+                assert_eq!(resubscribe[0].channel_id, TEST_CHANNEL_ID);
+                assert_eq!(resubscribe[0].scope, "test-scope");
+            }
+            ConfigUpdate::Migrated => panic!("expected to re-register"),
+        }
+        assert!(pm.store.get_uaid()?.is_none());
+        assert!(pm.store.get_record(TEST_CHANNEL_ID)?.is_none());
+        assert_eq!(pm.config.server_host, "push.example.com");
+        Ok(())
+    }
+
+    #[test]
+    fn test_uaid_issuer_changed_on_restart() -> Result<()> {
+        let _m = get_lock(&MTX);
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+
+        let mut pm = get_test_manager()?;
+        pm.connection
+            .expect_register()
+            .with(eq("native-id"), eq(None))
+            .times(1)
+            .returning(|_, _| {
+                Ok(RegisterResponse {
+                    uaid: TEST_UAID.to_string(),
+                    channel_id: TEST_CHANNEL_ID.to_string(),
+                    secret: TEST_AUTH.to_string(),
+                    endpoint: "https://example.com/dummy-endpoint".to_string(),
+                    sender_id: Some("test".to_string()),
+                })
+            });
+        let crypto_ctx = MockCryptography::generate_key_context();
+        crypto_ctx.expect().returning(|| {
+            let components = EcKeyComponents::new(
+                URL_SAFE_NO_PAD.decode(PRIV_KEY_D).unwrap(),
+                URL_SAFE_NO_PAD.decode(PUB_KEY_RAW).unwrap(),
+            );
+            let auth = URL_SAFE_NO_PAD.decode(TEST_AUTH).unwrap();
+            Ok(Key {
+                p256key: components,
+                auth,
+            })
+        });
+        pm.subscribe("test-scope", None)?;
+        pm.connection.checkpoint();
+
+        // Starting with the same configuration keeps our UAID.
+        pm.check_uaid_issuer()?;
+        assert_eq!(pm.store.get_uaid()?.unwrap(), TEST_UAID);
+
+        // Starting with a different sender ID drops it, without any requests.
+        pm.config.sender_id = "other-sender".to_string();
+        pm.check_uaid_issuer()?;
+        assert!(pm.store.get_uaid()?.is_none());
+        assert!(pm.store.get_record(TEST_CHANNEL_ID)?.is_none());
+        assert_eq!(pm.get_uaid_issuer()?, Some(UaidIssuer::new(&pm.config)));
+
+        // The next `verify_connection` re-subscribes on the new server, and returns the dropped
+        // subscriptions so the consumer can fetch their new endpoints.
+        pm.connection
+            .expect_register()
+            .with(eq("native-id"), eq(None))
+            .times(1)
+            .returning(|_, _| {
+                Ok(RegisterResponse {
+                    uaid: "new-uaid".to_string(),
+                    channel_id: TEST_CHANNEL_ID2.to_string(),
+                    secret: TEST_AUTH.to_string(),
+                    endpoint: "https://push.example.com/new-endpoint".to_string(),
+                    sender_id: Some("other-sender".to_string()),
+                })
+            });
+        let changed = pm.verify_connection(false)?;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].channel_id, TEST_CHANNEL_ID);
+        assert_eq!(changed[0].scope, "test-scope");
+        pm.connection.checkpoint();
+        // Subscribing to the scope returns the new subscription, without another request.
+        let resp = pm.subscribe("test-scope", None)?;
+        assert_eq!(resp.channel_id, TEST_CHANNEL_ID2);
+        assert_eq!(
+            resp.subscription_info.endpoint,
+            "https://push.example.com/new-endpoint"
+        );
+
+        // After that, it's back to verifying as usual.
+        pm.connection
+            .expect_channel_list()
+            .with(eq("new-uaid"), eq(TEST_AUTH))
+            .times(1)
+            .returning(|_, _| Ok(vec![TEST_CHANNEL_ID2.to_string()]));
+        assert!(pm.verify_connection(false)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_uaid_issuer_meta() -> Result<()> {
+        let _m = get_lock(&MTX);
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+
+        let mut pm = get_test_manager()?;
+        pm.store.set_meta(UAID_ISSUER_META_KEY, "not json")?;
+        pm.store.set_meta(DROPPED_SUBSCRIPTIONS_META_KEY, "{")?;
+        // Corrupt meta is treated as missing, rather than failing to create the manager.
+        pm.check_uaid_issuer()?;
+        assert_eq!(pm.get_uaid_issuer()?, Some(UaidIssuer::new(&pm.config)));
+        assert!(pm.verify_connection(false)?.is_empty());
+        Ok(())
+    }
 }
@@ -1,22 +1,38 @@
 /* This Source Code Form is subject to the terms of the Mozilla Public
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
-use std::{ops::Deref, path::Path};
-
-use rusqlite::Connection;
+use std::{collections::HashMap, ops::Deref, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use jwcrypto::EncryptorDecryptor;
+use rusqlite::{
+    types::{Value, ValueRef},
+    Connection, Row,
+};
 use sql_support::{open_database, ConnExt};
 
 use crate::error::{PushError, Result};
 
 use super::{record::PushRecord, schema};
 
+/// The meta key holding the canary used to check the encryption key.
+const ENCRYPTION_CANARY_KEY: &str = "encryption_canary";
+const ENCRYPTION_CANARY_TEXT: &str = "push subscription keys";
+
 pub trait Storage: Sized {
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self>;
+    /// Opens the database at `path`. If `encryption_key` is given, the
+    /// subscriptions' private keys are encrypted with it, including any which
+    /// were previously stored unencrypted.
+    fn open<P: AsRef<Path>>(path: P, encryption_key: Option<&str>) -> Result<Self>;
 
     fn get_record(&self, chid: &str) -> Result<Option<PushRecord>>;
 
     fn get_record_by_scope(&self, scope: &str) -> Result<Option<PushRecord>>;
 
+    /// Returns the record whose scope is the longest prefix of `url`, as used
+    /// when matching a service worker scope.
+    fn get_record_matching_scope(&self, url: &str) -> Result<Option<PushRecord>>;
+
     fn put_record(&self, record: &PushRecord) -> Result<bool>;
 
     fn delete_record(&self, chid: &str) -> Result<bool>;
@@ -25,6 +41,20 @@ pub trait Storage: Sized {
 
     fn get_channel_list(&self) -> Result<Vec<String>>;
 
+    /// Records the content encoding of the last message decrypted for `chid`.
+    fn set_last_encoding(&self, chid: &str, encoding: &str) -> Result<()>;
+
+    /// Sets the last encoding of each channel in `last_encodings`, and adds each of `counts`
+    /// to the number stored in that meta key, in a single transaction.
+    fn record_encodings(
+        &self,
+        last_encodings: &HashMap<String, String>,
+        counts: &[(&str, u64)],
+    ) -> Result<()>;
+
+    /// Returns the channels whose last decrypted message used `encoding`.
+    fn get_channels_by_last_encoding(&self, encoding: &str) -> Result<Vec<String>>;
+
     #[allow(dead_code)]
     fn update_endpoint(&self, channel_id: &str, endpoint: &str) -> Result<bool>;
 
@@ -45,10 +75,11 @@ pub trait Storage: Sized {
 
 pub struct PushDb {
     pub db: Connection,
+    encdec: Option<EncryptorDecryptor<PushError>>,
 }
 
 impl PushDb {
-    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+    pub fn open(path: impl AsRef<Path>, encryption_key: Option<&str>) -> Result<Self> {
         let path = path.as_ref();
         // By default, file open errors are StorageSqlErrors and aren't super helpful.
         // Instead, remap to StorageError and provide the path to the file that couldn't be opened.
@@ -60,17 +91,117 @@ impl PushDb {
                 orig,
             ))
         })?;
-        Ok(Self { db })
+        Self::with_encryption_key(db, encryption_key)
     }
 
     #[cfg(test)]
     pub fn open_in_memory() -> Result<Self> {
+        Self::open_in_memory_with_encryption_key(None)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory_with_encryption_key(encryption_key: Option<&str>) -> Result<Self> {
         // A nod to our tests which use this.
         env_logger::try_init().ok();
 
         let initializer = schema::PushConnectionInitializer {};
         let db = open_database::open_memory_database(&initializer)?;
-        Ok(Self { db })
+        Self::with_encryption_key(db, encryption_key)
+    }
+
+    /// Checks `encryption_key` against the canary stored in the database, and
+    /// encrypts any keys which were stored before encryption was turned on.
+    fn with_encryption_key(db: Connection, encryption_key: Option<&str>) -> Result<Self> {
+        let mut push_db = Self { db, encdec: None };
+        let canary = push_db.get_meta(ENCRYPTION_CANARY_KEY)?;
+        let Some(encryption_key) = encryption_key else {
+            if canary.is_some() {
+                return Err(PushError::EncryptionKeyMismatchError(
+                    "the database is encrypted, but no key was given".to_string(),
+                ));
+            }
+            return Ok(push_db);
+        };
+        let encdec = EncryptorDecryptor::new(encryption_key)?;
+        if let Some(canary) = &canary {
+            if !encdec
+                .check_canary(canary, ENCRYPTION_CANARY_TEXT)
+                .unwrap_or(false)
+            {
+                return Err(PushError::EncryptionKeyMismatchError(
+                    "the key can't decrypt the database".to_string(),
+                ));
+            }
+        }
+        push_db.encdec = Some(encdec);
+        push_db.encrypt_plaintext_keys(canary.is_none())?;
+        Ok(push_db)
+    }
+
+    /// Encrypts the keys of any records which were stored unencrypted, and
+    /// stores the canary if `store_canary` is set, in a single transaction so
+    /// an interrupted migration is picked up again next time.
+    fn encrypt_plaintext_keys(&self, store_canary: bool) -> Result<()> {
+        let tx = self.db.unchecked_transaction()?;
+        let plaintext_keys = self.query_rows_and_then(
+            "SELECT channel_id, key FROM push_record WHERE typeof(key) = 'blob'",
+            [],
+            |row| -> Result<(String, Vec<u8>)> { Ok((row.get(0)?, row.get(1)?)) },
+        )?;
+        if !plaintext_keys.is_empty() {
+            log::info!("encrypting {} push subscription keys", plaintext_keys.len());
+        }
+        for (channel_id, key) in plaintext_keys {
+            self.execute_cached(
+                "UPDATE push_record SET key = :key WHERE channel_id = :channel_id",
+                &[
+                    (":key", &self.encrypt_key(&key)? as &dyn rusqlite::ToSql),
+                    (":channel_id", &channel_id),
+                ],
+            )?;
+        }
+        if store_canary {
+            if let Some(encdec) = &self.encdec {
+                self.set_meta(
+                    ENCRYPTION_CANARY_KEY,
+                    &encdec.create_canary(ENCRYPTION_CANARY_TEXT)?,
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the value to store in the `key` column: the serialized key
+    /// itself as a blob, or if we have an encryption key, encrypted as text.
+    fn encrypt_key(&self, key: &[u8]) -> Result<Value> {
+        Ok(match &self.encdec {
+            Some(encdec) => Value::Text(encdec.encrypt(&STANDARD.encode(key), "encrypt push key")?),
+            None => Value::Blob(key.to_vec()),
+        })
+    }
+
+    fn decrypt_key(&self, value: ValueRef<'_>) -> Result<Vec<u8>> {
+        match value {
+            ValueRef::Blob(key) => Ok(key.to_vec()),
+            ValueRef::Text(ciphertext) => {
+                let Some(encdec) = &self.encdec else {
+                    return Err(PushError::EncryptionKeyMismatchError(
+                        "found an encrypted key, but no key was given".to_string(),
+                    ));
+                };
+                let ciphertext = std::str::from_utf8(ciphertext)
+                    .map_err(|e| PushError::TranscodingError(e.to_string()))?;
+                Ok(STANDARD.decode(encdec.decrypt(ciphertext, "decrypt push key")?)?)
+            }
+            _ => Err(PushError::StorageError(
+                "unexpected type for subscription key".to_string(),
+            )),
+        }
+    }
+
+    fn record_from_row(&self, row: &Row<'_>) -> Result<PushRecord> {
+        PushRecord::from_row(row, self.decrypt_key(row.get_ref("key")?)?)
     }
 
     /// Normalize UUID values to undashed, lowercase.
@@ -104,7 +235,7 @@ impl Storage for PushDb {
         self.try_query_row(
             &query,
             &[(":chid", &Self::normalize_uuid(chid))],
-            PushRecord::from_row,
+            |row| self.record_from_row(row),
             false,
         )
     }
@@ -115,7 +246,37 @@ impl Storage for PushDb {
              FROM push_record WHERE scope = :scope",
             common_cols = schema::COMMON_COLS,
         );
-        self.try_query_row(&query, &[(":scope", scope)], PushRecord::from_row, false)
+        self.try_query_row(
+            &query,
+            &[(":scope", scope)],
+            |row| self.record_from_row(row),
+            false,
+        )
+    }
+
+    fn get_record_matching_scope(&self, url: &str) -> Result<Option<PushRecord>> {
+        // Rather than scanning every scope, look up each prefix of the url in
+        // the index backing the `scope` UNIQUE constraint. Scopes are unique,
+        // so the longest match is too.
+        let query = format!(
+            "WITH RECURSIVE prefix(len) AS (
+                 SELECT length(:url)
+                 UNION ALL
+                 SELECT len - 1 FROM prefix WHERE len > 1
+             )
+             SELECT {common_cols}
+             FROM push_record
+             WHERE scope IN (SELECT substr(:url, 1, len) FROM prefix)
+             ORDER BY length(scope) DESC
+             LIMIT 1",
+            common_cols = schema::COMMON_COLS,
+        );
+        self.try_query_row(
+            &query,
+            &[(":url", url)],
+            |row| self.record_from_row(row),
+            false,
+        )
     }
 
     fn put_record(&self, record: &PushRecord) -> Result<bool> {
@@ -141,7 +302,7 @@ impl Storage for PushDb {
                 ),
                 (":endpoint", &record.endpoint),
                 (":scope", &record.scope),
-                (":key", &record.key),
+                (":key", &self.encrypt_key(&record.key)?),
                 (":ctime", &record.ctime),
                 (":app_server_key", &record.app_server_key),
             ],
@@ -182,6 +343,49 @@ impl Storage for PushDb {
         )
     }
 
+    fn set_last_encoding(&self, chid: &str, encoding: &str) -> Result<()> {
+        self.execute_cached(
+            "UPDATE push_record SET last_encoding = :encoding
+             WHERE channel_id = :chid AND last_encoding IS NOT :encoding",
+            &[
+                (":encoding", &encoding as &dyn rusqlite::ToSql),
+                (":chid", &Self::normalize_uuid(chid)),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn record_encodings(
+        &self,
+        last_encodings: &HashMap<String, String>,
+        counts: &[(&str, u64)],
+    ) -> Result<()> {
+        let tx = self.db.unchecked_transaction()?;
+        for (chid, encoding) in last_encodings {
+            self.set_last_encoding(chid, encoding)?;
+        }
+        for (key, count) in counts {
+            if *count == 0 {
+                continue;
+            }
+            let stored: u64 = self
+                .get_meta(key)?
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default();
+            self.set_meta(key, &(stored + count).to_string())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_channels_by_last_encoding(&self, encoding: &str) -> Result<Vec<String>> {
+        self.query_rows_and_then(
+            "SELECT channel_id FROM push_record WHERE last_encoding = :encoding",
+            &[(":encoding", &encoding)],
+            |row| -> Result<String> { Ok(row.get(0)?) },
+        )
+    }
+
     fn update_endpoint(&self, channel_id: &str, endpoint: &str) -> Result<bool> {
         log::debug!("updating endpoint for '{}' to '{}'", channel_id, endpoint);
         let affected_rows = self.execute(
@@ -238,18 +442,20 @@ impl Storage for PushDb {
     }
 
     #[cfg(not(test))]
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        PushDb::open(path)
+    fn open<P: AsRef<Path>>(path: P, encryption_key: Option<&str>) -> Result<Self> {
+        PushDb::open(path, encryption_key)
     }
 
     #[cfg(test)]
-    fn open<P: AsRef<Path>>(_path: P) -> Result<Self> {
-        PushDb::open_in_memory()
+    fn open<P: AsRef<Path>>(_path: P, encryption_key: Option<&str>) -> Result<Self> {
+        PushDb::open_in_memory_with_encryption_key(encryption_key)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
     use crate::error::Result;
     use crate::internal::crypto::{Crypto, Cryptography};
 
@@ -263,7 +469,7 @@ mod test {
         env_logger::try_init().ok();
         // NOTE: In Memory tests can sometimes produce false positives. Use the following
         // for debugging
-        // PushDb::open("/tmp/push.sqlite");
+        // PushDb::open("/tmp/push.sqlite", None);
         PushDb::open_in_memory()
     }
 
@@ -312,6 +518,38 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn matching_scope() -> Result<()> {
+        let db = get_db()?;
+        let mut rec = prec(&get_uuid()?);
+        rec.scope = "https://example.com/".to_string();
+        db.put_record(&rec)?;
+        let mut rec_app = prec(&get_uuid()?);
+        rec_app.scope = "https://example.com/app/".to_string();
+        db.put_record(&rec_app)?;
+
+        assert_eq!(
+            db.get_record_matching_scope("https://example.com/app/page.html")?,
+            Some(rec_app.clone())
+        );
+        assert_eq!(
+            db.get_record_matching_scope("https://example.com/app/")?,
+            Some(rec_app)
+        );
+        assert_eq!(
+            db.get_record_matching_scope("https://example.com/application")?,
+            Some(rec.clone())
+        );
+        assert_eq!(
+            db.get_record_matching_scope("https://example.com/")?,
+            Some(rec)
+        );
+        assert_eq!(db.get_record_matching_scope("https://example.com")?, None);
+        assert_eq!(db.get_record_matching_scope("https://example.org/")?, None);
+        assert_eq!(db.get_record_matching_scope("")?, None);
+        Ok(())
+    }
+
     #[test]
     fn delete() -> Result<()> {
         let db = get_db()?;
@@ -362,6 +600,24 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn record_encodings() -> Result<()> {
+        use super::Storage;
+        let db = get_db()?;
+        let chid = &get_uuid()?;
+        db.put_record(&prec(chid))?;
+        let last_encodings = HashMap::from([(chid.to_string(), "aesgcm".to_string())]);
+        db.record_encodings(&last_encodings, &[("count_a", 2), ("count_b", 0)])?;
+        db.record_encodings(&HashMap::new(), &[("count_a", 3)])?;
+        assert_eq!(db.get_meta("count_a")?, Some("5".to_owned()));
+        assert_eq!(db.get_meta("count_b")?, None);
+        assert_eq!(
+            db.get_channels_by_last_encoding("aesgcm")?,
+            vec![chid.to_string()]
+        );
+        Ok(())
+    }
+
     #[test]
     fn dash() -> Result<()> {
         let db = get_db()?;
@@ -374,4 +630,64 @@ mod test {
         assert!(db.delete_record(chid)?);
         Ok(())
     }
+
+    #[test]
+    fn encryption() -> Result<()> {
+        use crate::error::PushError;
+        use sql_support::ConnExt;
+
+        let key = crate::create_encryption_key().unwrap();
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("push.sqlite");
+        let key_type = |db: &PushDb, chid: &str| -> String {
+            db.query_row(
+                "SELECT typeof(key) FROM push_record WHERE channel_id = ?",
+                [chid],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        // A record stored before encryption was turned on...
+        let plaintext_rec = prec(&get_uuid()?);
+        PushDb::open(&path, None)?.put_record(&plaintext_rec)?;
+
+        // ...is encrypted when a key is first given.
+        let db = PushDb::open(&path, Some(&key))?;
+        assert_eq!(key_type(&db, &plaintext_rec.channel_id), "text");
+        assert_eq!(
+            db.get_record(&plaintext_rec.channel_id)?,
+            Some(plaintext_rec.clone())
+        );
+        let mut rec = prec(&get_uuid()?);
+        rec.scope = "https://example.org/".to_string();
+        db.put_record(&rec)?;
+        assert_eq!(key_type(&db, &rec.channel_id), "text");
+        assert_eq!(
+            db.query_one::<u32>("SELECT COUNT(*) FROM push_record WHERE typeof(key) = 'blob'")?,
+            0
+        );
+        drop(db);
+
+        // The same key can read them back.
+        let db = PushDb::open(&path, Some(&key))?;
+        assert_eq!(db.get_record(&rec.channel_id)?, Some(rec));
+        assert_eq!(
+            db.get_record_by_scope(&plaintext_rec.scope)?,
+            Some(plaintext_rec)
+        );
+        drop(db);
+
+        // But losing the key, or changing it, is detected.
+        assert!(matches!(
+            PushDb::open(&path, None),
+            Err(PushError::EncryptionKeyMismatchError(_))
+        ));
+        let other_key = crate::create_encryption_key().unwrap();
+        assert!(matches!(
+            PushDb::open(&path, Some(&other_key)),
+            Err(PushError::EncryptionKeyMismatchError(_))
+        ));
+        Ok(())
+    }
 }
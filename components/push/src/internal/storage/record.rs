@@ -47,12 +47,14 @@ impl PushRecord {
         })
     }
 
-    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+    /// Create a Push Record from a database row. The `key` column may be
+    /// encrypted, so the caller passes in its decrypted value.
+    pub(crate) fn from_row(row: &Row<'_>, key: Vec<u8>) -> Result<Self> {
         Ok(PushRecord {
             channel_id: row.get("channel_id")?,
             endpoint: row.get("endpoint")?,
             scope: row.get("scope")?,
-            key: row.get("key")?,
+            key,
             ctime: row.get("ctime")?,
             app_server_key: row.get("app_server_key")?,
         })
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 use rusqlite::Transaction;
-use sql_support::open_database;
+use sql_support::{open_database, ConnExt};
 
 const CREATE_TABLE_PUSH_SQL: &str = include_str!("schema.sql");
 
@@ -10,7 +10,7 @@ pub struct PushConnectionInitializer;
 
 impl open_database::ConnectionInitializer for PushConnectionInitializer {
     const NAME: &'static str = "push db";
-    const END_VERSION: u32 = 3;
+    const END_VERSION: u32 = 4;
 
     // This is such a simple database that we do almost nothing!
     // * We have no foreign keys, so `PRAGMA foreign_keys = ON;` is pointless.
@@ -49,6 +49,15 @@ impl open_database::ConnectionInitializer for PushConnectionInitializer {
                 );
                 db.execute_batch(&sql)?;
             }
+            3 => {
+                // Add the `last_encoding` column. Databases upgraded from version 2 already
+                // have it, since that migration recreates the table from the current schema.
+                let exists_sql =
+                    "SELECT 1 FROM pragma_table_info('push_record') WHERE name = 'last_encoding'";
+                if !db.exists(exists_sql, [])? {
+                    db.execute("ALTER TABLE push_record ADD COLUMN last_encoding TEXT", [])?;
+                }
+            }
             other => {
                 log::warn!(
                     "Loaded future schema version {} (we only understand version {}). \
@@ -111,7 +120,7 @@ mod test {
 
         // reopen the database.
         drop(conn);
-        let db = PushDb::open(path).expect("should open");
+        let db = PushDb::open(path, None).expect("should open");
 
         // Should only have 1 row in push_record
         assert_eq!(
@@ -139,5 +148,12 @@ mod test {
         );
         assert_eq!(db.get_meta("key-1").unwrap().unwrap(), "value-1");
         assert_eq!(db.get_meta("key-2").unwrap().unwrap(), "value-2");
+
+        // And the column added in v4.
+        db.set_last_encoding("cid1", "aesgcm").unwrap();
+        assert_eq!(
+            db.get_channels_by_last_encoding("aesgcm").unwrap(),
+            vec!["cid1".to_string()]
+        );
     }
 }
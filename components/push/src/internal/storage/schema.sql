@@ -13,6 +13,8 @@ IF NOT EXISTS push_record
     key                TEXT     NOT NULL,
     ctime              INTEGER  NOT NULL,
     app_server_key     TEXT,
+    -- The content encoding of the last message we decrypted for this channel.
+    last_encoding      TEXT,
     -- scope must have a value!
     CHECK(length(scope) > 0)
 );
@@ -218,6 +218,7 @@ impl PushManager {
     /// # Errors
     /// Returns an error in the following cases:
     ///   - PushManager is unable to open the `database_path` given
+    ///   - The `encryption_key` given doesn't match the one the database was encrypted with
     ///   - PushManager is unable to establish a connection to the autopush server
     #[handle_error(PushError)]
     pub fn new(config: PushConfiguration) -> ApiResult<Self> {
@@ -281,6 +282,28 @@ impl PushManager {
         self.internal.lock().unwrap().get_subscription(scope)
     }
 
+    /// Retrieves the push subscription whose scope is the longest prefix of `url`
+    ///
+    /// This implements service worker scope matching: for example, given subscriptions
+    /// for `https://example.com/` and `https://example.com/app/`, the url
+    /// `https://example.com/app/page.html` matches the latter.
+    ///
+    /// # Arguments
+    ///   - `url` - The URL to find a subscription for
+    ///
+    /// # Returns
+    /// The matching subscription, in the same form as [`PushManager::get_subscription`],
+    /// or nothing if no subscription's scope is a prefix of `url`.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - PushManager was unable to access its persisted storage
+    ///   - An error occurred generating or deserializing the cryptographic keys
+    #[handle_error(PushError)]
+    pub fn get_subscription_matching(&self, url: &str) -> ApiResult<Option<SubscriptionResponse>> {
+        self.internal.lock().unwrap().get_subscription_matching(url)
+    }
+
     /// Unsubscribe from given channelID, ending that subscription for the user.
     ///
     /// # Arguments
@@ -332,6 +355,13 @@ impl PushManager {
     /// it only returns the list of channels that the client should
     /// re-subscribe to.
     ///
+    /// If the PushManager was created with a different server host, bridge
+    /// type or sender ID than the ones which issued our UAID, the UAID and
+    /// subscriptions were dropped. This re-subscribes every channel on the new
+    /// server and returns them, so that [`PushManager::subscribe`] returns the
+    /// new endpoints. Without a registration ID, it can't subscribe, so it
+    /// returns the channels for the app to re-subscribe to.
+    ///
     /// # Arguments
     ///   - `force_verify`: Force verification and ignore the rate limiter
     ///
@@ -379,6 +409,120 @@ impl PushManager {
     pub fn decrypt(&self, payload: HashMap<String, String>) -> ApiResult<DecryptResponse> {
         self.internal.lock().unwrap().decrypt(payload)
     }
+
+    /// Sends a test message to the subscription for `scope`, to check that messages can be
+    /// delivered and decrypted.
+    ///
+    /// The message is encrypted to the subscription's keys and sent to its endpoint, as an
+    /// application server would, so it's delivered through the push service and native push
+    /// system like any other. Subscriptions locked to a VAPID key (ie, with an `app_server_key`)
+    /// will reject it.
+    ///
+    /// # Arguments
+    ///   - `scope` - The scope of the subscription to send a message to
+    ///
+    /// # Returns
+    /// The message which was sent. If the whole path works, passing the delivered message to
+    /// [`PushManager::decrypt`] returns it.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - There is no subscription for `scope`
+    ///   - An error occurred while encrypting the message
+    ///   - The push service rejected the message
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn send_test_notification(&self, scope: &str) -> ApiResult<String> {
+        self.internal.lock().unwrap().send_test_notification(scope)
+    }
+
+    /// Returns a summary of the push state, to help diagnose reports of notifications
+    /// no longer being delivered.
+    ///
+    /// The summary is a JSON object with the first few characters of the UAID, whether
+    /// there's a native registration ID, the scope and creation time of each subscription,
+    /// when the subscriptions were last verified with the push server, and the
+    /// [encoding stats](PushManager::get_encoding_stats). It doesn't
+    /// include any keys, endpoints or the full UAID, so it's safe to attach to bug reports.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn debug_dump(&self) -> ApiResult<String> {
+        self.internal.lock().unwrap().debug_dump()
+    }
+
+    /// Returns how much push traffic still uses the legacy "aesgcm" content encoding.
+    ///
+    /// Each message passed to [`PushManager::decrypt`] is counted by its encoding, and
+    /// each subscription remembers the encoding of its last message. Applications can
+    /// report these to telemetry, to tell when it's safe to drop support for "aesgcm".
+    /// The counts are kept in memory and written to storage in batches, so a few messages
+    /// decrypted just before the application exits may not be counted.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn get_encoding_stats(&self) -> ApiResult<EncodingStats> {
+        self.internal.lock().unwrap().get_encoding_stats()
+    }
+
+    /// Drops the subscriptions whose last message used the legacy "aesgcm" content
+    /// encoding, so they can be recreated.
+    ///
+    /// The content encoding is chosen by the application server that sends the message,
+    /// not by us, so this can't upgrade a subscription by itself. Recreating it only helps
+    /// if the application server uses "aes128gcm" for new subscriptions.
+    ///
+    /// **NOTE**: Like [`PushManager::verify_connection`], this does not resubscribe to
+    /// any channels. Consumers should resubscribe to each channel returned, and send the
+    /// new subscription to its application server.
+    ///
+    /// # Returns
+    /// Returns a list of [`PushSubscriptionChanged`] indicating the channels the consumer
+    /// should re-subscribe to. The list is empty if no subscriptions use "aesgcm".
+    ///
+    /// # Errors
+    /// Return an error in the following cases:
+    ///   - The PushManager does not contain a valid UAID
+    ///   - An error occurred sending an unsubscribe request to the autopush server
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn upgrade_legacy_subscriptions(&self) -> ApiResult<Vec<PushSubscriptionChanged>> {
+        self.internal.lock().unwrap().upgrade_legacy_subscriptions()
+    }
+
+    /// Changes the push server or bridge this PushManager uses, without constructing a new one.
+    ///
+    /// If the server host, bridge type and sender ID are unchanged, the existing UAID and
+    /// subscriptions are kept. Otherwise the UAID can't be used with the new configuration,
+    /// so we unsubscribe from the old server and drop all the subscriptions; the next
+    /// subscription registers a new UAID. If the bridge type or sender ID changed, call
+    /// [`PushManager::update`] with the new native registration ID before subscribing again.
+    /// Either way, the rate limiter state is kept.
+    ///
+    /// # Arguments
+    ///   - `config` - The new configuration. It must use the same database and encryption key.
+    ///
+    /// # Returns
+    /// Whether the UAID was kept, or the subscriptions the consumer should re-subscribe to.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - The new configuration uses a different database or encryption key
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn update_config(&self, config: PushConfiguration) -> ApiResult<ConfigUpdate> {
+        self.internal.lock().unwrap().update_config(config)
+    }
+}
+
+/// Creates a key suitable for [`PushConfiguration::encryption_key`].
+#[handle_error(PushError)]
+pub fn create_encryption_key() -> ApiResult<String> {
+    jwcrypto::EncryptorDecryptor::<PushError>::create_key()
 }
 
 /// Key Information that can be used to encrypt payloads. These are encoded as base64
@@ -412,3 +556,28 @@ pub struct PushSubscriptionChanged {
     pub channel_id: String,
     pub scope: String,
 }
+
+/// Which path [`PushManager::update_config`] took
+#[derive(Debug, Clone)]
+pub enum ConfigUpdate {
+    /// The new configuration is compatible with our UAID, which was kept along with
+    /// the subscriptions
+    Migrated,
+    /// The new configuration uses a different server or bridge, so the subscriptions
+    /// were dropped. The consumer should re-subscribe to each of these
+    Reregistered {
+        resubscribe: Vec<PushSubscriptionChanged>,
+    },
+}
+
+/// How much push traffic uses each content encoding, returned from
+/// [`PushManager::get_encoding_stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodingStats {
+    /// The number of messages decrypted using the legacy "aesgcm" encoding
+    pub aesgcm_messages: u64,
+    /// The number of messages decrypted using the "aes128gcm" encoding
+    pub aes128gcm_messages: u64,
+    /// The number of subscriptions whose last message used "aesgcm"
+    pub aesgcm_channels: u32,
+}
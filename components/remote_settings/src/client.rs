@@ -14,6 +14,7 @@ use crate::RemoteSettingsContext;
 use crate::{
     packaged_attachments, packaged_collections, RemoteSettingsServer, UniffiCustomTypeConverter,
 };
+use camino::Utf8PathBuf;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -52,6 +53,9 @@ fn epoch_seconds() -> u64 {
 const HEADER_BACKOFF: &str = "Backoff";
 const HEADER_ETAG: &str = "ETag";
 const HEADER_RETRY_AFTER: &str = "Retry-After";
+/// The largest attachment we'll download, so that a bad record can't make us
+/// buffer an unbounded response.
+const MAX_ATTACHMENT_SIZE: u64 = 100 * 1024 * 1024;
 
 /// Hard-coded SHA256 of our root certificates. This is used by rc_crypto/pkixc to verify that the
 /// certificates chains used in content signatures verification were produced from our root certificate.
@@ -82,6 +86,8 @@ pub struct RemoteSettingsClient<C = ViaductApiClient> {
 struct RemoteSettingsClientInner<C> {
     storage: Storage,
     api_client: C,
+    attachment_policy: AttachmentPolicy,
+    supported_schemas: SupportedSchemas,
 }
 
 // Add your local packaged data you want to work with here
@@ -124,6 +130,8 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
             inner: Mutex::new(RemoteSettingsClientInner {
                 storage,
                 api_client,
+                attachment_policy: AttachmentPolicy::OnDemand,
+                supported_schemas: SupportedSchemas::default(),
             }),
         }
     }
@@ -143,11 +151,17 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         Self::get_packaged_attachment(&self.collection_name, filename)
     }
 
-    /// Filters records based on the presence and evaluation of `filter_expression`.
+    /// Filters records based on the presence and evaluation of `filter_expression`, and on
+    /// whether we understand their `schema`.
     #[cfg(feature = "jexl")]
-    fn filter_records(&self, records: Vec<RemoteSettingsRecord>) -> Vec<RemoteSettingsRecord> {
+    fn filter_records(
+        &self,
+        records: Vec<RemoteSettingsRecord>,
+        supported_schemas: &SupportedSchemas,
+    ) -> Vec<RemoteSettingsRecord> {
         records
             .into_iter()
+            .filter(|record| supported_schemas.supports(record))
             .filter(|record| match record.fields.get("filter_expression") {
                 Some(serde_json::Value::String(filter_expr)) => {
                     self.jexl_filter.evaluate(filter_expr).unwrap_or(false)
@@ -157,9 +171,17 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
             .collect()
     }
 
+    /// Filters records based on whether we understand their `schema`.
     #[cfg(not(feature = "jexl"))]
-    fn filter_records(&self, records: Vec<RemoteSettingsRecord>) -> Vec<RemoteSettingsRecord> {
+    fn filter_records(
+        &self,
+        records: Vec<RemoteSettingsRecord>,
+        supported_schemas: &SupportedSchemas,
+    ) -> Vec<RemoteSettingsRecord> {
         records
+            .into_iter()
+            .filter(|record| supported_schemas.supports(record))
+            .collect()
     }
 
     /// Get the current set of records.
@@ -195,7 +217,9 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                     packaged_data.timestamp,
                     CollectionMetadata::default(),
                 )?;
-                return Ok(Some(self.filter_records(packaged_data.data)));
+                return Ok(Some(
+                    self.filter_records(packaged_data.data, &inner.supported_schemas),
+                ));
             }
         }
 
@@ -206,7 +230,9 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
             //
             // Note: we should return these even if it's an empty list and `sync_if_empty=true`.
             // The "if empty" part refers to the cache being empty, not the list.
-            (Some(cached_records), _) => Some(self.filter_records(cached_records)),
+            (Some(cached_records), _) => {
+                Some(self.filter_records(cached_records, &inner.supported_schemas))
+            }
             // Case 3: sync_if_empty=true
             (None, true) => {
                 let changeset = inner.api_client.fetch_changeset(None)?;
@@ -216,7 +242,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                     changeset.timestamp,
                     changeset.metadata,
                 )?;
-                Some(self.filter_records(changeset.changes))
+                Some(self.filter_records(changeset.changes, &inner.supported_schemas))
             }
             // Case 4: Nothing to return
             (None, false) => None,
@@ -268,6 +294,65 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         Ok(())
     }
 
+    pub fn set_attachment_policy(&self, policy: AttachmentPolicy) {
+        self.inner.lock().attachment_policy = policy;
+    }
+
+    pub fn set_supported_schemas(&self, min: Option<u64>, max: Option<u64>) {
+        self.inner.lock().supported_schemas = SupportedSchemas { min, max };
+    }
+
+    /// Download the attachments that the attachment policy says to prefetch, if they're not
+    /// already cached.
+    ///
+    /// This is called after a sync, and first prunes the files for attachments whose records the
+    /// sync removed.  Failures are logged rather than returned, since the attachments can still be
+    /// fetched on demand by `get_attachment`.
+    pub fn prefetch_attachments(&self) {
+        let policy = {
+            let mut inner = self.inner.lock();
+            let collection_url = inner.api_client.collection_url();
+            inner.storage.prune_attachment_files(&collection_url);
+            inner.attachment_policy.clone()
+        };
+        if policy == AttachmentPolicy::OnDemand {
+            return;
+        }
+        let records = match self.get_records(false) {
+            Ok(records) => records.unwrap_or_default(),
+            Err(e) => {
+                log::warn!(
+                    "{0}: error getting records to prefetch: {e}",
+                    self.collection_name
+                );
+                return;
+            }
+        };
+        for record in records {
+            if record.attachment.is_none() || !policy.should_prefetch(&record) {
+                continue;
+            }
+            let record_id = record.id.clone();
+            if let Err(e) = self.get_attachment(record) {
+                log::warn!(
+                    "{0}: error prefetching attachment for {record_id}: {e}",
+                    self.collection_name
+                );
+            }
+        }
+    }
+
+    /// Get the number of bytes used by the cached records and attachments, in that order.
+    pub fn storage_usage(&self) -> Result<(u64, u64)> {
+        self.inner.lock().storage.get_usage()
+    }
+
+    /// Delete all cached data for this collection and free the disk space it used.
+    pub fn purge(&self) -> Result<()> {
+        log::trace!("{0}: purge local storage.", self.collection_name);
+        self.inner.lock().storage.purge()
+    }
+
     fn reset_storage(&self) -> Result<()> {
         log::trace!("{0}: reset local storage.", self.collection_name);
         let mut inner = self.inner.lock();
@@ -375,18 +460,32 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         {
             return Ok(data);
         }
+        // Attachments that have been written to files aren't also kept in the database.
+        if let Some(data) = inner.storage.read_attachment_file(&metadata)? {
+            return Ok(data);
+        }
 
-        // Then try packaged data if we're in prod
+        let attachment = self.load_attachment(&mut inner, &metadata)?;
+        // Store verified data in storage because it was either empty or outdated
+        inner
+            .storage
+            .set_attachment(&collection_url, &metadata.location, &attachment)?;
+        Ok(attachment)
+    }
+
+    /// Get an attachment that isn't in storage, from the packaged data or by downloading it.
+    fn load_attachment(
+        &self,
+        inner: &mut RemoteSettingsClientInner<C>,
+        metadata: &Attachment,
+    ) -> Result<Vec<u8>> {
+        // Try packaged data if we're in prod
         if inner.api_client.is_prod_server()? {
             if let Some((data, manifest)) = self.load_packaged_attachment(&metadata.location) {
                 if let Ok(manifest_data) = serde_json::from_str::<serde_json::Value>(manifest) {
                     if metadata.hash == manifest_data["hash"].as_str().unwrap_or_default()
                         && metadata.size == manifest_data["size"].as_u64().unwrap_or_default()
                     {
-                        // Store valid packaged data in storage because it was either empty or outdated
-                        inner
-                            .storage
-                            .set_attachment(&collection_url, &metadata.location, data)?;
                         return Ok(data.to_vec());
                     }
                 }
@@ -408,12 +507,40 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                 "Downloaded attachment hash mismatch".into(),
             ));
         }
+        Ok(attachment)
+    }
 
-        // Store verified download in storage
+    /// Get the path of a file containing the attachment for the record with `record_id`,
+    /// downloading the attachment if it's not cached.
+    ///
+    /// The file's contents have been verified, and don't change. The file is removed after a sync
+    /// removes its record from the collection, or the cached data is cleared.
+    pub fn get_attachment_path(&self, record_id: &str) -> Result<Utf8PathBuf> {
+        let record = self
+            .get_records(false)?
+            .unwrap_or_default()
+            .into_iter()
+            .find(|record| record.id == record_id)
+            .ok_or_else(|| Error::RecordNotFoundError(record_id.to_string()))?;
+        let metadata = record
+            .attachment
+            .ok_or_else(|| Error::RecordAttachmentMismatchError("No attachment metadata".into()))?;
+        let mut inner = self.inner.lock();
+        if let Some(path) = inner.storage.get_attachment_file(&metadata)? {
+            return Ok(path);
+        }
+        let collection_url = inner.api_client.collection_url();
+        // Move the attachment out of the database if it's there, so it's only stored once.
+        let data = match inner
+            .storage
+            .get_attachment(&collection_url, metadata.clone())?
+        {
+            Some(data) => data,
+            None => self.load_attachment(&mut inner, &metadata)?,
+        };
         inner
             .storage
-            .set_attachment(&collection_url, &metadata.location, &attachment)?;
-        Ok(attachment)
+            .set_attachment_file(&collection_url, &metadata, &data)
     }
 }
 
@@ -484,10 +611,13 @@ impl ViaductApiClient {
     }
 
     fn make_request(&mut self, url: Url) -> Result<Response> {
-        log::trace!("make_request: {url}");
+        self.send_request(Request::get(url))
+    }
+
+    fn send_request(&mut self, req: Request) -> Result<Response> {
+        log::trace!("send_request: {}", req.url);
         self.ensure_no_backoff()?;
 
-        let req = Request::get(url);
         let resp = req.send()?;
 
         self.handle_backoff_hint(&resp)?;
@@ -589,7 +719,9 @@ impl ApiClient for ViaductApiClient {
             }
         };
 
-        let resp = self.make_request(attachments_base_url.join(attachment_location)?)?;
+        let req = Request::get(attachments_base_url.join(attachment_location)?)
+            .max_response_size(MAX_ATTACHMENT_SIZE);
+        let resp = self.send_request(req)?;
         Ok(resp.body)
     }
 
@@ -729,15 +861,21 @@ impl Client {
             }
         };
 
-        self.make_request(attachments_base_url.join(attachment_location)?)
+        self.send_request(
+            Request::get(attachments_base_url.join(attachment_location)?)
+                .max_response_size(MAX_ATTACHMENT_SIZE),
+        )
     }
 
     fn make_request(&self, url: Url) -> Result<Response> {
+        self.send_request(Request::get(url))
+    }
+
+    fn send_request(&self, req: Request) -> Result<Response> {
         let mut current_remote_state = self.remote_state.lock();
         self.ensure_no_backoff(&mut current_remote_state.backoff)?;
         drop(current_remote_state);
 
-        let req = Request::get(url);
         let resp = req.send()?;
 
         let mut current_remote_state = self.remote_state.lock();
@@ -932,6 +1070,58 @@ pub struct Attachment {
     pub size: u64,
 }
 
+/// When a client downloads its records' attachments.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum AttachmentPolicy {
+    /// Only download attachments when they're requested with `get_attachment`.  This is the
+    /// default.
+    OnDemand,
+    /// Download all attachments when the collection is synced
+    PrefetchAll,
+    /// Download attachments when the collection is synced, but only for records whose `field`
+    /// is a string equal to one of `values`.  Other attachments are downloaded on demand.
+    PrefetchMatching { field: String, values: Vec<String> },
+}
+
+/// The range of record schema versions a client understands.
+///
+/// By convention, records whose format changes incompatibly have an integer `schema` field, which
+/// is bumped with each change.  Records outside the range are skipped by `get_records`, so older
+/// clients can ignore records they can't parse rather than failing to deserialize the whole
+/// collection.  Records without a `schema` are always included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct SupportedSchemas {
+    min: Option<u64>,
+    max: Option<u64>,
+}
+
+impl SupportedSchemas {
+    fn supports(&self, record: &RemoteSettingsRecord) -> bool {
+        let Some(schema) = record
+            .fields
+            .get("schema")
+            .and_then(serde_json::Value::as_u64)
+        else {
+            return true;
+        };
+        self.min.map_or(true, |min| schema >= min) && self.max.map_or(true, |max| schema <= max)
+    }
+}
+
+impl AttachmentPolicy {
+    fn should_prefetch(&self, record: &RemoteSettingsRecord) -> bool {
+        match self {
+            Self::OnDemand => false,
+            Self::PrefetchAll => true,
+            Self::PrefetchMatching { field, values } => record
+                .fields
+                .get(field)
+                .and_then(serde_json::Value::as_str)
+                .is_some_and(|value| values.iter().any(|v| v == value)),
+        }
+    }
+}
+
 // Define a UniFFI custom types to pass JSON objects across the FFI as a string
 //
 // This is named `RsJsonObject` because, UniFFI cannot currently rename iOS bindings and JsonObject
@@ -2480,6 +2670,211 @@ mod test_packaged_metadata {
     }
 }
 
+#[cfg(not(feature = "jexl"))]
+#[cfg(test)]
+mod test_attachment_policy {
+    use super::*;
+    use serde_json::json;
+
+    const COLLECTION_URL: &str =
+        "http://rs.example.com/v1/buckets/main/collections/test-collection";
+
+    fn attachment(location: &str, data: &[u8]) -> Attachment {
+        Attachment {
+            filename: location.to_string(),
+            mimetype: "application/octet-stream".to_string(),
+            location: location.to_string(),
+            size: data.len() as u64,
+            hash: format!("{:x}", Sha256::digest(data)),
+        }
+    }
+
+    fn record(id: &str, record_type: &str, attachment: Option<Attachment>) -> RemoteSettingsRecord {
+        RemoteSettingsRecord {
+            id: id.to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment,
+            fields: json!({ "type": record_type }).as_object().unwrap().clone(),
+        }
+    }
+
+    fn client_with_records(
+        api_client: MockApiClient,
+        records: &[RemoteSettingsRecord],
+    ) -> Result<RemoteSettingsClient<MockApiClient>> {
+        let mut storage = Storage::new(":memory:".into())?;
+        storage.insert_collection_content(
+            COLLECTION_URL,
+            records,
+            100,
+            CollectionMetadata::default(),
+        )?;
+        Ok(RemoteSettingsClient::new_from_parts(
+            "test-collection".into(),
+            storage,
+            api_client,
+        ))
+    }
+
+    fn mock_api_client() -> MockApiClient {
+        let mut api_client = MockApiClient::new();
+        api_client
+            .expect_collection_url()
+            .returning(|| COLLECTION_URL.into());
+        api_client.expect_is_prod_server().returning(|| Ok(false));
+        api_client
+    }
+
+    #[test]
+    fn test_on_demand_doesnt_prefetch() -> Result<()> {
+        let mut api_client = mock_api_client();
+        api_client.expect_fetch_attachment().never();
+        let rs_client = client_with_records(
+            api_client,
+            &[record(
+                "icon-1",
+                "icon",
+                Some(attachment("icon-1.png", b"icon")),
+            )],
+        )?;
+        rs_client.prefetch_attachments();
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_matching() -> Result<()> {
+        let records = vec![
+            record("icon-1", "icon", Some(attachment("icon-1.png", b"icon"))),
+            record("data-1", "data", Some(attachment("data-1.json", b"data"))),
+            record("icon-2", "icon", None),
+        ];
+        let mut api_client = mock_api_client();
+        api_client
+            .expect_fetch_attachment()
+            .withf(|location| location == "icon-1.png")
+            .times(1)
+            .returning(|_| Ok(b"icon".to_vec()));
+        let rs_client = client_with_records(api_client, &records)?;
+        rs_client.set_attachment_policy(AttachmentPolicy::PrefetchMatching {
+            field: "type".into(),
+            values: vec!["icon".into()],
+        });
+        rs_client.prefetch_attachments();
+        // The prefetched attachment is cached, so this doesn't fetch it again.
+        assert_eq!(rs_client.get_attachment(records[0].clone())?, b"icon");
+        // Prefetching again doesn't either.
+        rs_client.prefetch_attachments();
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_all_continues_after_errors() -> Result<()> {
+        let mut api_client = mock_api_client();
+        api_client
+            .expect_fetch_attachment()
+            .withf(|location| location == "icon-1.png")
+            .times(1)
+            .returning(|_| Err(Error::RecordAttachmentMismatchError("test".into())));
+        api_client
+            .expect_fetch_attachment()
+            .withf(|location| location == "data-1.json")
+            .times(1)
+            .returning(|_| Ok(b"data".to_vec()));
+        let rs_client = client_with_records(
+            api_client,
+            &[
+                record("icon-1", "icon", Some(attachment("icon-1.png", b"icon"))),
+                record("data-1", "data", Some(attachment("data-1.json", b"data"))),
+            ],
+        )?;
+        rs_client.set_attachment_policy(AttachmentPolicy::PrefetchAll);
+        rs_client.prefetch_attachments();
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "jexl"))]
+#[cfg(test)]
+mod test_supported_schemas {
+    use super::*;
+    use serde_json::json;
+
+    const COLLECTION_URL: &str =
+        "http://rs.example.com/v1/buckets/main/collections/test-collection";
+
+    fn record(id: &str, schema: serde_json::Value) -> RemoteSettingsRecord {
+        let mut fields = serde_json::Map::new();
+        if !schema.is_null() {
+            fields.insert("schema".to_string(), schema);
+        }
+        RemoteSettingsRecord {
+            id: id.to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment: None,
+            fields,
+        }
+    }
+
+    fn record_ids(rs_client: &RemoteSettingsClient<MockApiClient>) -> Result<Vec<String>> {
+        let mut ids: Vec<_> = rs_client
+            .get_records(false)?
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    #[test]
+    fn test_supported_schemas() -> Result<()> {
+        let mut api_client = MockApiClient::new();
+        api_client
+            .expect_collection_url()
+            .returning(|| COLLECTION_URL.into());
+        api_client.expect_is_prod_server().returning(|| Ok(false));
+        let mut storage = Storage::new(":memory:".into())?;
+        storage.insert_collection_content(
+            COLLECTION_URL,
+            &[
+                record("no-schema", json!(null)),
+                record("v1", json!(1)),
+                record("v2", json!(2)),
+                record("v3", json!(3)),
+                record("not-a-number", json!("3")),
+            ],
+            100,
+            CollectionMetadata::default(),
+        )?;
+        let rs_client =
+            RemoteSettingsClient::new_from_parts("test-collection".into(), storage, api_client);
+
+        // By default, every record is returned.
+        assert_eq!(record_ids(&rs_client)?.len(), 5);
+
+        rs_client.set_supported_schemas(Some(2), None);
+        assert_eq!(
+            record_ids(&rs_client)?,
+            vec!["no-schema", "not-a-number", "v2", "v3"]
+        );
+
+        rs_client.set_supported_schemas(None, Some(2));
+        assert_eq!(
+            record_ids(&rs_client)?,
+            vec!["no-schema", "not-a-number", "v1", "v2"]
+        );
+
+        rs_client.set_supported_schemas(Some(2), Some(2));
+        assert_eq!(
+            record_ids(&rs_client)?,
+            vec!["no-schema", "not-a-number", "v2"]
+        );
+        Ok(())
+    }
+}
+
 #[cfg(feature = "signatures")]
 #[cfg(feature = "jexl")] // Assuming tests are run with `--all-features`
 #[cfg(test)]
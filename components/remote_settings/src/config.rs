@@ -25,6 +25,9 @@ pub struct RemoteSettingsConfig2 {
     /// Bucket name to use, defaults to "main".  Use "main-preview" for a preview bucket
     #[uniffi(default = None)]
     pub bucket_name: Option<String>,
+    /// Maximum number of collections to sync at the same time, defaults to 4.
+    #[uniffi(default = None)]
+    pub sync_parallelism: Option<u32>,
 }
 
 /// Custom configuration for the client.
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use error_support::{ErrorHandling, GetErrorHandling};
+use error_support::{ErrorCode, ErrorHandling, GetErrorHandling};
 
 pub type ApiResult<T> = std::result::Result<T, RemoteSettingsError>;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -51,6 +51,8 @@ pub enum Error {
     DatabaseError(#[from] rusqlite::Error),
     #[error("No attachment in given record: {0}")]
     RecordAttachmentMismatchError(String),
+    #[error("No record with id: {0}")]
+    RecordNotFoundError(String),
     #[error("Incomplete signature data: {0}")]
     IncompleteSignatureDataError(String),
     #[cfg(feature = "signatures")]
@@ -73,6 +75,8 @@ impl GetErrorHandling for Error {
                 ErrorHandling::convert(RemoteSettingsError::Network {
                     reason: e.to_string(),
                 })
+                .code(ErrorCode::Network)
+                .retryable()
                 .log_warning()
             }
             // Backoff error shouldn't happen in practice, so let's report them for now.
@@ -80,6 +84,8 @@ impl GetErrorHandling for Error {
             // then consider switching from reporting to Sentry to counting in Glean.
             Self::BackoffError(seconds) => {
                 ErrorHandling::convert(RemoteSettingsError::Backoff { seconds: *seconds })
+                    .code(ErrorCode::Network)
+                    .retryable()
                     .report_error("suggest-backoff")
             }
             _ => ErrorHandling::convert(RemoteSettingsError::Other {
@@ -23,9 +23,12 @@ pub mod storage;
 pub(crate) mod jexl_filter;
 mod macros;
 
-pub use client::{Attachment, RemoteSettingsRecord, RemoteSettingsResponse, RsJsonObject};
+pub use client::{
+    Attachment, AttachmentPolicy, RemoteSettingsRecord, RemoteSettingsResponse, RsJsonObject,
+};
 pub use config::{RemoteSettingsConfig, RemoteSettingsConfig2, RemoteSettingsServer};
 pub use error::{ApiResult, RemoteSettingsError, Result};
+pub use service::CollectionStorageUsage;
 
 use client::Client;
 use error::Error;
@@ -111,12 +114,34 @@ impl RemoteSettingsService {
         self.internal.make_client(collection_name, app_context)
     }
 
+    /// Create a new Remote Settings client for a collection outside the configured bucket
+    ///
+    /// This is for collections that only exist in other buckets, for example `security-state`.
+    /// The client keeps using `bucket_name` even if the service's config is updated, and its
+    /// records are stored separately from collections with the same name in other buckets.
+    /// Bucket names may only contain ASCII letters, digits, `_` and `-`.
+    #[handle_error(Error)]
+    pub fn make_client_for_bucket(
+        &self,
+        collection_name: String,
+        bucket_name: String,
+        app_context: Option<RemoteSettingsContext>,
+    ) -> ApiResult<Arc<RemoteSettingsClient>> {
+        self.internal
+            .make_client_for_bucket(collection_name, bucket_name, app_context)
+    }
+
     /// Sync collections for all active clients
     #[handle_error(Error)]
     pub fn sync(&self) -> ApiResult<Vec<String>> {
         self.internal.sync()
     }
 
+    /// Async version of [Self::sync], which runs the sync on a background thread
+    pub async fn sync_async(self: Arc<Self>) -> ApiResult<Vec<String>> {
+        viaduct::spawn_blocking(move || self.sync()).await
+    }
+
     /// Update the remote settings config
     ///
     /// This will cause all current and future clients to use new config and will delete any stored
@@ -128,6 +153,23 @@ impl RemoteSettingsService {
     pub fn update_config(&self, config: RemoteSettingsConfig2) -> ApiResult<()> {
         self.internal.update_config(config)
     }
+
+    /// Get the disk space used by each collection's cached records and attachments
+    ///
+    /// This can be used to show component storage usage in a settings UI.
+    #[handle_error(Error)]
+    pub fn get_storage_usage(&self) -> ApiResult<Vec<CollectionStorageUsage>> {
+        self.internal.get_storage_usage()
+    }
+
+    /// Delete the cached records and attachments for a single collection
+    ///
+    /// Use this to implement "clear cached data" actions.  Data for other collections is not
+    /// affected.
+    #[handle_error(Error)]
+    pub fn purge_collection(&self, collection_name: String) -> ApiResult<()> {
+        self.internal.purge_collection(&collection_name)
+    }
 }
 
 /// Client for a single Remote Settings collection
@@ -190,12 +232,49 @@ impl RemoteSettingsClient {
             .map(|records| records.into_iter().map(|r| (r.id.clone(), r)).collect())
     }
 
+    /// Async version of [Self::get_records], for use with `sync_if_empty = true` without
+    /// blocking the calling thread on the network request
+    #[uniffi::method(default(sync_if_empty = false))]
+    pub async fn get_records_async(
+        self: Arc<Self>,
+        sync_if_empty: bool,
+    ) -> Option<Vec<RemoteSettingsRecord>> {
+        viaduct::spawn_blocking(move || self.get_records(sync_if_empty)).await
+    }
+
+    /// Set when this client downloads attachments
+    ///
+    /// By default attachments are only downloaded when requested with [Self::get_attachment].
+    /// Collections whose attachments are needed right away (for example, icons) can have some or
+    /// all of them downloaded by [RemoteSettingsService::sync] instead.
+    pub fn set_attachment_policy(&self, policy: AttachmentPolicy) {
+        self.internal.set_attachment_policy(policy)
+    }
+
+    /// Set the range of record schema versions this client understands
+    ///
+    /// Collections whose record format changes incompatibly can give their records an integer
+    /// `schema` field, which is bumped with each change.  Once this is set, [Self::get_records]
+    /// skips records whose `schema` is outside the range, so that older application versions
+    /// ignore records they don't know how to parse instead of failing to deserialize them.
+    /// Records without a `schema` field are always returned.  Either bound can be `None` to
+    /// leave that side of the range open.
+    pub fn set_supported_schemas(
+        &self,
+        min_supported_schema: Option<u64>,
+        max_supported_schema: Option<u64>,
+    ) {
+        self.internal
+            .set_supported_schemas(min_supported_schema, max_supported_schema)
+    }
+
     /// Get attachment data for a remote settings record
     ///
     /// Attachments are large binary blobs used for data that doesn't fit in a normal record.  They
     /// are handled differently than other record data:
     ///
-    ///   - Attachments are not downloaded in [RemoteSettingsService::sync]
+    ///   - Attachments are not downloaded in [RemoteSettingsService::sync], unless the client's
+    ///     [AttachmentPolicy] says to prefetch them
     ///   - This method will make network requests if the attachment is not cached
     ///   - This method will throw if there is a network or other error when fetching the
     ///     attachment data.
@@ -203,6 +282,29 @@ impl RemoteSettingsClient {
     pub fn get_attachment(&self, record: RemoteSettingsRecord) -> ApiResult<Vec<u8>> {
         self.internal.get_attachment(record)
     }
+
+    /// Async version of [Self::get_attachment], which fetches the attachment on a background
+    /// thread
+    pub async fn get_attachment_async(
+        self: Arc<Self>,
+        record: RemoteSettingsRecord,
+    ) -> ApiResult<Vec<u8>> {
+        viaduct::spawn_blocking(move || self.get_attachment(record)).await
+    }
+
+    /// Get the path of a file containing the attachment for the record with `record_id`
+    ///
+    /// This is for large attachments which consumers want to memory-map or stream, rather than
+    /// have copied across the FFI by [Self::get_attachment].  Like that method, it makes network
+    /// requests if the attachment is not cached.
+    ///
+    /// The file's contents have been verified against the record, and never change.  The file
+    /// is removed after a sync removes its record from the collection, or when the cached data
+    /// is cleared, so consumers should call this again rather than remember the path.
+    #[handle_error(Error)]
+    pub fn get_attachment_path(&self, record_id: String) -> ApiResult<String> {
+        Ok(self.internal.get_attachment_path(&record_id)?.into_string())
+    }
 }
 
 impl RemoteSettingsClient {
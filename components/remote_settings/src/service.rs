@@ -4,7 +4,10 @@
 
 use std::{
     collections::HashSet,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Weak,
+    },
 };
 
 use camino::Utf8PathBuf;
@@ -12,10 +15,22 @@ use parking_lot::Mutex;
 use url::Url;
 
 use crate::{
-    storage::Storage, RemoteSettingsClient, RemoteSettingsConfig2, RemoteSettingsContext,
-    RemoteSettingsServer, Result,
+    error::Error, storage::Storage, RemoteSettingsClient, RemoteSettingsConfig2,
+    RemoteSettingsContext, RemoteSettingsServer, Result,
 };
 
+const DEFAULT_SYNC_PARALLELISM: usize = 4;
+
+/// Disk space used by a single collection's cached data
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct CollectionStorageUsage {
+    pub collection_name: String,
+    /// Bytes used by the cached records
+    pub records_bytes: u64,
+    /// Bytes used by the cached attachments
+    pub attachments_bytes: u64,
+}
+
 /// Internal Remote settings service API
 pub struct RemoteSettingsService {
     inner: Mutex<RemoteSettingsServiceInner>,
@@ -25,12 +40,27 @@ struct RemoteSettingsServiceInner {
     storage_dir: Utf8PathBuf,
     base_url: Url,
     bucket_name: String,
+    sync_parallelism: usize,
     /// Weakrefs for all clients that we've created.  Note: this stores the
     /// top-level/public `RemoteSettingsClient` structs rather than `client::RemoteSettingsClient`.
     /// The reason for this is that we return Arcs to the public struct to the foreign code, so we
     /// need to use the same type for our weakrefs.  The alternative would be to create 2 Arcs for
     /// each client, which is wasteful.
-    clients: Vec<Weak<RemoteSettingsClient>>,
+    clients: Vec<ClientRef<Weak<RemoteSettingsClient>>>,
+}
+
+/// A client, along with the bucket it was created for, if it overrides the service's bucket
+struct ClientRef<T> {
+    bucket_override: Option<String>,
+    client: T,
+}
+
+impl ClientRef<Arc<RemoteSettingsClient>> {
+    /// Key that identifies the stored data for this client.  Clients with the same key share a
+    /// collection and only need to be synced once.
+    fn storage_key(&self) -> (Option<String>, String) {
+        (self.bucket_override.clone(), self.client.collection_name())
+    }
 }
 
 impl RemoteSettingsService {
@@ -44,78 +74,160 @@ impl RemoteSettingsService {
             .unwrap_or(RemoteSettingsServer::Prod)
             .get_url()?;
         let bucket_name = config.bucket_name.unwrap_or_else(|| String::from("main"));
+        let sync_parallelism = sync_parallelism(&config);
 
         Ok(Self {
             inner: Mutex::new(RemoteSettingsServiceInner {
                 storage_dir,
                 base_url,
                 bucket_name,
+                sync_parallelism,
                 clients: vec![],
             }),
         })
     }
 
     /// Create a new Remote Settings client
-    #[cfg(feature = "jexl")]
     pub fn make_client(
         &self,
         collection_name: String,
         context: Option<RemoteSettingsContext>,
+    ) -> Result<Arc<RemoteSettingsClient>> {
+        self.make_client_inner(collection_name, None, context)
+    }
+
+    /// Create a new Remote Settings client for a collection in a specific bucket
+    ///
+    /// The client fetches from `bucket_name` instead of the service's configured bucket, and keeps
+    /// using it after [Self::update_config].  Its records are stored separately from any
+    /// collection with the same name in other buckets.  If `bucket_name` is the service's
+    /// bucket, this is the same as [Self::make_client].
+    ///
+    /// Bucket names may only contain ASCII letters, digits, `_` and `-`.
+    pub fn make_client_for_bucket(
+        &self,
+        collection_name: String,
+        bucket_name: String,
+        context: Option<RemoteSettingsContext>,
+    ) -> Result<Arc<RemoteSettingsClient>> {
+        validate_storage_name("bucket", &bucket_name)?;
+        // Otherwise the collection would be stored and synced twice.
+        let bucket_override = (bucket_name != self.inner.lock().bucket_name).then_some(bucket_name);
+        self.make_client_inner(collection_name, bucket_override, context)
+    }
+
+    #[cfg(feature = "jexl")]
+    fn make_client_inner(
+        &self,
+        collection_name: String,
+        bucket_override: Option<String>,
+        context: Option<RemoteSettingsContext>,
     ) -> Result<Arc<RemoteSettingsClient>> {
         let mut inner = self.inner.lock();
-        let storage = Storage::new(inner.storage_dir.join(format!("{collection_name}.sql")))?;
+        let storage = inner.open_storage(bucket_override.as_deref(), &collection_name)?;
 
         let client = Arc::new(RemoteSettingsClient::new(
             inner.base_url.clone(),
-            inner.bucket_name.clone(),
+            bucket_override
+                .clone()
+                .unwrap_or_else(|| inner.bucket_name.clone()),
             collection_name.clone(),
             context,
             storage,
         )?);
-        inner.clients.push(Arc::downgrade(&client));
+        inner.clients.push(ClientRef {
+            bucket_override,
+            client: Arc::downgrade(&client),
+        });
         Ok(client)
     }
 
     #[cfg(not(feature = "jexl"))]
-    pub fn make_client(
+    fn make_client_inner(
         &self,
         collection_name: String,
+        bucket_override: Option<String>,
         #[allow(unused_variables)] context: Option<RemoteSettingsContext>,
     ) -> Result<Arc<RemoteSettingsClient>> {
         let mut inner = self.inner.lock();
-        let storage = Storage::new(inner.storage_dir.join(format!("{collection_name}.sql")))?;
+        let storage = inner.open_storage(bucket_override.as_deref(), &collection_name)?;
         let client = Arc::new(RemoteSettingsClient::new(
             inner.base_url.clone(),
-            inner.bucket_name.clone(),
+            bucket_override
+                .clone()
+                .unwrap_or_else(|| inner.bucket_name.clone()),
             collection_name.clone(),
             storage,
         )?);
-        inner.clients.push(Arc::downgrade(&client));
+        inner.clients.push(ClientRef {
+            bucket_override,
+            client: Arc::downgrade(&client),
+        });
         Ok(client)
     }
 
     /// Sync collections for all active clients
+    ///
+    /// Collections are synced concurrently, up to the configured `sync_parallelism`.  Each client
+    /// serializes its own storage writes, so this only overlaps work on different collections.
+    /// A failure in one collection doesn't stop the others from syncing; if any fail, the first
+    /// error is returned once all collections have been attempted.
+    ///
+    /// After a collection syncs, the attachments its clients' [crate::AttachmentPolicy] says to
+    /// prefetch are downloaded as part of the same job, so they count against the same limit.
     pub fn sync(&self) -> Result<Vec<String>> {
-        // Make sure we only sync each collection once, even if there are multiple clients
-        let mut synced_collections = HashSet::new();
+        let (clients, parallelism) = {
+            let mut inner = self.inner.lock();
+            (inner.active_clients(), inner.sync_parallelism)
+        };
+        // Make sure we only sync each collection once, even if there are multiple clients.  All
+        // clients for a collection get to prefetch attachments, since they may have different
+        // attachment policies.  Collections with the same name in different buckets are synced
+        // separately.
+        let mut seen_collections = HashSet::new();
+        let (clients, other_clients): (Vec<_>, Vec<_>) = clients
+            .into_iter()
+            .partition(|client| seen_collections.insert(client.storage_key()));
 
         // TODO: poll the server using `/buckets/monitor/collections/changes/changeset` to fetch
         // the current timestamp for all collections.  That way we can avoid fetching collections
         // we know haven't changed and also pass the `?_expected{ts}` param to the server.
 
-        for client in self.inner.lock().active_clients() {
-            if synced_collections.insert(client.collection_name()) {
-                client.internal.sync()?;
+        let results = run_bounded(&clients, parallelism, |client| {
+            client.client.internal.sync()?;
+            client.client.internal.prefetch_attachments();
+            for other in &other_clients {
+                if other.storage_key() == client.storage_key() {
+                    other.client.internal.prefetch_attachments();
+                }
+            }
+            Ok(())
+        });
+
+        let mut synced_collections = vec![];
+        let mut first_error = None;
+        for (client, result) in clients.iter().zip(results) {
+            match result {
+                Ok(()) => synced_collections.push(client.client.collection_name()),
+                Err(e) => {
+                    log::warn!("{0}: sync failed: {e}", client.client.collection_name());
+                    first_error.get_or_insert(e);
+                }
             }
         }
-        Ok(synced_collections.into_iter().collect())
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(synced_collections),
+        }
     }
 
     /// Update the remote settings config
     ///
     /// This will cause all current and future clients to use new config and will delete any stored
-    /// records causing the clients to return new results from the new config.
+    /// records causing the clients to return new results from the new config.  Clients created with
+    /// [Self::make_client_for_bucket] keep their bucket.
     pub fn update_config(&self, config: RemoteSettingsConfig2) -> Result<()> {
+        let parallelism = sync_parallelism(&config);
         let base_url = config
             .server
             .unwrap_or(RemoteSettingsServer::Prod)
@@ -123,25 +235,72 @@ impl RemoteSettingsService {
         let bucket_name = config.bucket_name.unwrap_or_else(|| String::from("main"));
         let mut inner = self.inner.lock();
         for client in inner.active_clients() {
-            client
-                .internal
-                .update_config(base_url.clone(), bucket_name.clone())?;
+            client.client.internal.update_config(
+                base_url.clone(),
+                client
+                    .bucket_override
+                    .unwrap_or_else(|| bucket_name.clone()),
+            )?;
         }
         inner.base_url = base_url;
         inner.bucket_name = bucket_name;
+        inner.sync_parallelism = parallelism;
         Ok(())
     }
+
+    /// Get the disk space used by each collection that has cached data on disk
+    ///
+    /// This includes collections that don't currently have an active client.  The results are
+    /// sorted by collection name.  Only collections in the service's bucket are included.
+    pub fn get_storage_usage(&self) -> Result<Vec<CollectionStorageUsage>> {
+        let mut inner = self.inner.lock();
+        let mut usage = vec![];
+        for collection_name in inner.stored_collection_names()? {
+            let (records_bytes, attachments_bytes) = match inner.active_client_for(&collection_name)
+            {
+                Some(client) => client.internal.storage_usage()?,
+                None => inner.open_storage(None, &collection_name)?.get_usage()?,
+            };
+            usage.push(CollectionStorageUsage {
+                collection_name,
+                records_bytes,
+                attachments_bytes,
+            });
+        }
+        Ok(usage)
+    }
+
+    /// Delete all cached data for a single collection and free the disk space it used
+    ///
+    /// Other collections are left untouched.  Clients for the collection stay usable; they'll
+    /// return the packaged data or fetch new records on their next sync.  Only collections in the
+    /// service's bucket are purged.
+    pub fn purge_collection(&self, collection_name: &str) -> Result<()> {
+        let mut inner = self.inner.lock();
+        match inner.active_client_for(collection_name) {
+            Some(client) => client.internal.purge(),
+            None => {
+                if !inner.storage_path(None, collection_name)?.exists() {
+                    return Ok(());
+                }
+                inner.open_storage(None, collection_name)?.purge()
+            }
+        }
+    }
 }
 
 impl RemoteSettingsServiceInner {
     // Find live clients in self.clients
     //
     // Also, drop dead weakrefs from the vec
-    fn active_clients(&mut self) -> Vec<Arc<RemoteSettingsClient>> {
+    fn active_clients(&mut self) -> Vec<ClientRef<Arc<RemoteSettingsClient>>> {
         let mut active_clients = vec![];
-        self.clients.retain(|weak| {
-            if let Some(client) = weak.upgrade() {
-                active_clients.push(client);
+        self.clients.retain(|client_ref| {
+            if let Some(client) = client_ref.client.upgrade() {
+                active_clients.push(ClientRef {
+                    bucket_override: client_ref.bucket_override.clone(),
+                    client,
+                });
                 true
             } else {
                 false
@@ -149,4 +308,224 @@ impl RemoteSettingsServiceInner {
         });
         active_clients
     }
+
+    // Find a live client for a collection in the service's bucket
+    fn active_client_for(&mut self, collection_name: &str) -> Option<Arc<RemoteSettingsClient>> {
+        self.active_clients()
+            .into_iter()
+            .find(|client_ref| {
+                client_ref.bucket_override.is_none()
+                    && client_ref.client.internal.collection_name() == collection_name
+            })
+            .map(|client_ref| client_ref.client)
+    }
+
+    // Collections in the service's bucket are stored directly in the storage dir.  Collections
+    // from other buckets are stored in a subdirectory per bucket, so that they don't clash with
+    // collections of the same name.
+    fn storage_path(
+        &self,
+        bucket_override: Option<&str>,
+        collection_name: &str,
+    ) -> Result<Utf8PathBuf> {
+        validate_storage_name("collection", collection_name)?;
+        let dir = match bucket_override {
+            Some(bucket_name) => {
+                validate_storage_name("bucket", bucket_name)?;
+                self.storage_dir.join(bucket_name)
+            }
+            None => self.storage_dir.clone(),
+        };
+        Ok(dir.join(format!("{collection_name}.sql")))
+    }
+
+    fn open_storage(
+        &self,
+        bucket_override: Option<&str>,
+        collection_name: &str,
+    ) -> Result<Storage> {
+        let path = self.storage_path(bucket_override, collection_name)?;
+        if bucket_override.is_some() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        Storage::new(path)
+    }
+
+    // Names of all collections with a database in the storage dir, sorted
+    fn stored_collection_names(&self) -> Result<Vec<String>> {
+        let entries = match self.storage_dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.into_path();
+            if path.extension() == Some("sql") {
+                // Skip anything we couldn't have created ourselves.
+                if let Some(name) = path
+                    .file_stem()
+                    .filter(|name| validate_storage_name("collection", name).is_ok())
+                {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+// Bucket and collection names are used as file and directory names, so they mustn't be able to
+// escape the storage directory.
+fn validate_storage_name(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::ConfigError(format!("Invalid {kind} name: {name}")));
+    }
+    Ok(())
+}
+
+fn sync_parallelism(config: &RemoteSettingsConfig2) -> usize {
+    config
+        .sync_parallelism
+        .map_or(DEFAULT_SYNC_PARALLELISM, |n| (n as usize).max(1))
+}
+
+/// Call `f` for each item, on at most `parallelism` threads at once, returning the results in the
+/// same order as `items`.
+fn run_bounded<T, R, F>(items: &[T], parallelism: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().push((index, result));
+            });
+        }
+    });
+    let mut results = results.into_inner();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_run_bounded() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..20).collect();
+        let results = run_bounded(&items, 3, |item| {
+            let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now_running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            item * 2
+        });
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_run_bounded_empty() {
+        let results = run_bounded(&Vec::<u32>::new(), 4, |_| ());
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_bucket_override_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage_dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let service = RemoteSettingsService::new(
+            storage_dir.to_string(),
+            RemoteSettingsConfig2 {
+                server: None,
+                bucket_name: None,
+                sync_parallelism: None,
+            },
+        )
+        .unwrap();
+        let _main = service
+            .make_client("intermediates".to_string(), None)
+            .unwrap();
+        let _security_state = service
+            .make_client_for_bucket(
+                "intermediates".to_string(),
+                "security-state".to_string(),
+                None,
+            )
+            .unwrap();
+        assert!(storage_dir.join("intermediates.sql").exists());
+        assert!(storage_dir
+            .join("security-state")
+            .join("intermediates.sql")
+            .exists());
+
+        let clients = service.inner.lock().active_clients();
+        let keys: HashSet<_> = clients.iter().map(ClientRef::storage_key).collect();
+        assert_eq!(keys.len(), 2);
+
+        // Overriding with the service's own bucket is the same as not overriding it.
+        let _main_again = service
+            .make_client_for_bucket("intermediates".to_string(), "main".to_string(), None)
+            .unwrap();
+        assert_eq!(service.inner.lock().active_clients().len(), 3);
+        assert!(!storage_dir.join("main").exists());
+        let clients = service.inner.lock().active_clients();
+        let keys: HashSet<_> = clients.iter().map(ClientRef::storage_key).collect();
+        assert_eq!(keys.len(), 2);
+
+        // Bucket names can't escape the storage directory.
+        for bucket_name in ["", "..", "../main", "a/b", "a\\b", "sécurité"] {
+            assert!(matches!(
+                service.make_client_for_bucket(
+                    "intermediates".to_string(),
+                    bucket_name.to_string(),
+                    None,
+                ),
+                Err(Error::ConfigError(_))
+            ));
+        }
+
+        // Nor can collection names.
+        for collection_name in ["", "..", "../intermediates", "a/b", "a\\b"] {
+            assert!(matches!(
+                service.make_client(collection_name.to_string(), None),
+                Err(Error::ConfigError(_))
+            ));
+            assert!(matches!(
+                service.purge_collection(collection_name),
+                Err(Error::ConfigError(_))
+            ));
+        }
+
+        // Storage usage only covers the service's bucket.
+        let usage = service.get_storage_usage().unwrap();
+        assert_eq!(
+            usage
+                .iter()
+                .map(|u| u.collection_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["intermediates"]
+        );
+    }
 }
@@ -4,12 +4,13 @@
 
 use crate::{
     client::CollectionMetadata, client::CollectionSignature,
-    schema::RemoteSettingsConnectionInitializer, Attachment, RemoteSettingsRecord, Result,
+    schema::RemoteSettingsConnectionInitializer, Attachment, Error, RemoteSettingsRecord, Result,
 };
 use camino::Utf8PathBuf;
 use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
 use serde_json;
 use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 
 use sql_support::{open_database::open_database_with_flags, ConnExt};
 
@@ -28,16 +29,22 @@ use sql_support::{open_database::open_database_with_flags, ConnExt};
 /// the previous config.
 pub struct Storage {
     conn: Connection,
+    /// Where attachment files are written, next to the database.  `None` for in-memory databases.
+    attachments_dir: Option<Utf8PathBuf>,
 }
 
 impl Storage {
     pub fn new(path: Utf8PathBuf) -> Result<Self> {
+        let attachments_dir = (path != ":memory:").then(|| path.with_extension("attachments"));
         let conn = open_database_with_flags(
             path,
             OpenFlags::default(),
             &RemoteSettingsConnectionInitializer,
         )?;
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            attachments_dir,
+        })
     }
 
     /// Get the last modified timestamp for the stored records
@@ -150,6 +157,148 @@ impl Storage {
         }
     }
 
+    /// Read the file written for an attachment by [Self::set_attachment_file].
+    ///
+    /// Returns None if no file has been written for the attachment, or its contents don't match
+    /// `metadata`.
+    pub fn read_attachment_file(&self, metadata: &Attachment) -> Result<Option<Vec<u8>>> {
+        let Some(path) = self.get_attachment_file(metadata)? else {
+            return Ok(None);
+        };
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let hash = format!("{:x}", Sha256::digest(&data));
+        Ok((hash == metadata.hash).then_some(data))
+    }
+
+    /// Get the path of the file written for an attachment by [Self::set_attachment_file].
+    ///
+    /// Returns None if no file has been written for the attachment, or it's been removed.
+    pub fn get_attachment_file(&self, metadata: &Attachment) -> Result<Option<Utf8PathBuf>> {
+        let Some(path) = self.attachment_file_path(metadata)? else {
+            return Ok(None);
+        };
+        match path.metadata() {
+            Ok(file_metadata) if file_metadata.len() == metadata.size => Ok(Some(path)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write attachment data to a file which consumers can read directly, and return its path.
+    ///
+    /// `data` must already have been verified against `metadata`.  The file replaces any copy of
+    /// the attachment stored by [Self::set_attachment], so the data is only stored once.  It's
+    /// kept until it's pruned after the attachment's record is removed from the collection, or
+    /// the cached data is cleared.
+    pub fn set_attachment_file(
+        &mut self,
+        collection_url: &str,
+        metadata: &Attachment,
+        data: &[u8],
+    ) -> Result<Utf8PathBuf> {
+        let path = self.attachment_file_path(metadata)?.ok_or_else(|| {
+            Error::ConfigError("Attachment files aren't supported for in-memory storage".into())
+        })?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write to a temporary file first, so the file is never seen half-written.
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, &path)?;
+        self.conn.execute(
+            "DELETE FROM attachments WHERE id = ? AND collection_url = ?",
+            (&metadata.location, collection_url),
+        )?;
+        self.prune_attachment_files(collection_url);
+        Ok(path)
+    }
+
+    fn attachment_file_path(&self, metadata: &Attachment) -> Result<Option<Utf8PathBuf>> {
+        // Files are named by the attachment's hash, so their contents never change once written.
+        // The hash comes from the server, so make sure it's safe to use as a file name.
+        if metadata.hash.len() != 64 || !metadata.hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::RecordAttachmentMismatchError(
+                "Invalid attachment hash".into(),
+            ));
+        }
+        Ok(self
+            .attachments_dir
+            .as_ref()
+            .map(|dir| dir.join(&metadata.hash)))
+    }
+
+    /// Remove the files for attachments which aren't in any of the collection's records.
+    ///
+    /// This is best-effort: a file which can't be removed now, for example because a consumer
+    /// still has it open on Windows, is logged and left for the next time.
+    pub fn prune_attachment_files(&mut self, collection_url: &str) {
+        if let Err(e) = self.try_prune_attachment_files(collection_url) {
+            log::warn!("error pruning attachment files: {e}");
+        }
+    }
+
+    fn try_prune_attachment_files(&mut self, collection_url: &str) -> Result<()> {
+        let Some(dir) = self.attachments_dir.clone() else {
+            return Ok(());
+        };
+        let live_hashes: HashSet<String> = self
+            .get_records(collection_url)?
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|record| record.attachment)
+            .map(|attachment| attachment.hash)
+            .collect();
+        let entries = match dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !live_hashes.contains(entry.file_name()) {
+                log::trace!("removing attachment file {}", entry.file_name());
+                if let Err(e) = std::fs::remove_file(entry.path()) {
+                    log::warn!("error removing attachment file {}: {e}", entry.file_name());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all the attachment files
+    fn remove_attachment_files(&self) -> Result<()> {
+        if let Some(dir) = &self.attachments_dir {
+            match std::fs::remove_dir_all(dir) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the total size of the attachment files
+    fn attachment_files_size(&self) -> Result<u64> {
+        let Some(dir) = &self.attachments_dir else {
+            return Ok(0);
+        };
+        let entries = match dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut size = 0;
+        for entry in entries {
+            size += entry?.metadata()?.len();
+        }
+        Ok(size)
+    }
+
     /// Set cached content for this collection.
     pub fn insert_collection_content(
         &mut self,
@@ -266,6 +415,38 @@ impl Storage {
         tx.execute("DELETE FROM attachments", [])?;
         tx.execute("DELETE FROM collection_metadata", [])?;
         tx.commit()?;
+        self.remove_attachment_files()?;
+        Ok(())
+    }
+
+    /// Get the number of bytes used by the cached records and attachments, in that order.
+    ///
+    /// Attachment files count towards the attachments, along with those stored in the database.
+    pub fn get_usage(&self) -> Result<(u64, u64)> {
+        let (records_bytes, attachments_bytes): (u64, u64) = self.conn.query_row(
+            "SELECT
+                (SELECT COALESCE(SUM(LENGTH(data)), 0) FROM records),
+                (SELECT COALESCE(SUM(LENGTH(data)), 0) FROM attachments)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((
+            records_bytes,
+            attachments_bytes + self.attachment_files_size()?,
+        ))
+    }
+
+    /// Empty out all cached values, then give the freed pages back to the filesystem.
+    ///
+    /// Unlike [Self::empty], this is for when the user asks to clear cached data, so it's worth
+    /// the extra time it takes to shrink the database file.
+    pub fn purge(&mut self) -> Result<()> {
+        self.empty()?;
+        self.conn.execute_batch("VACUUM")?;
+        // VACUUM writes through the WAL, so truncate that too or the space isn't freed until the
+        // next automatic checkpoint.
+        self.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
         Ok(())
     }
 }
@@ -277,6 +458,7 @@ mod tests {
         client::CollectionMetadata, client::CollectionSignature, Attachment, RemoteSettingsRecord,
         Result, RsJsonObject,
     };
+    use camino::Utf8PathBuf;
     use sha2::{Digest, Sha256};
 
     #[test]
@@ -581,6 +763,133 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_storage_usage_and_purge() -> Result<()> {
+        let mut storage = Storage::new(":memory:".into())?;
+        assert_eq!(storage.get_usage()?, (0, 0));
+
+        let collection_url = "https://example.com/api";
+        let attachment = &[0x18, 0x64, 0x33];
+        let records = vec![RemoteSettingsRecord {
+            id: "1".to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment: Some(Attachment {
+                filename: "abc".to_string(),
+                mimetype: "application/json".to_string(),
+                location: "tmp".to_string(),
+                hash: format!("{:x}", Sha256::digest(attachment)),
+                size: attachment.len() as u64,
+            }),
+            fields: serde_json::json!({"key": "value1"})
+                .as_object()
+                .unwrap()
+                .clone(),
+        }];
+        storage.insert_collection_content(
+            collection_url,
+            &records,
+            42,
+            CollectionMetadata::default(),
+        )?;
+        storage.set_attachment(collection_url, "tmp", attachment)?;
+
+        let (records_bytes, attachments_bytes) = storage.get_usage()?;
+        assert!(records_bytes > 0);
+        assert_eq!(attachments_bytes, attachment.len() as u64);
+
+        storage.purge()?;
+        assert_eq!(storage.get_usage()?, (0, 0));
+        assert!(storage.get_records(collection_url)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_attachment_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db_path = Utf8PathBuf::from_path_buf(dir.path().join("collection.sql")).unwrap();
+        let mut storage = Storage::new(db_path)?;
+
+        let collection_url = "https://example.com/api";
+        let attachment = &[0x18, 0x64, 0x33];
+        let metadata = Attachment {
+            filename: "abc".to_string(),
+            mimetype: "application/octet-stream".to_string(),
+            location: "tmp".to_string(),
+            hash: format!("{:x}", Sha256::digest(attachment)),
+            size: attachment.len() as u64,
+        };
+        let record = RemoteSettingsRecord {
+            id: "1".to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment: Some(metadata.clone()),
+            fields: RsJsonObject::new(),
+        };
+        storage.insert_collection_content(
+            collection_url,
+            &[record.clone()],
+            100,
+            CollectionMetadata::default(),
+        )?;
+        assert!(storage.get_attachment_file(&metadata)?.is_none());
+
+        // Writing the file replaces the copy in the database.
+        storage.set_attachment(collection_url, &metadata.location, attachment)?;
+        let path = storage.set_attachment_file(collection_url, &metadata, attachment)?;
+        assert_eq!(std::fs::read(&path)?, attachment);
+        assert_eq!(storage.get_attachment_file(&metadata)?, Some(path.clone()));
+        assert_eq!(
+            storage.read_attachment_file(&metadata)?,
+            Some(attachment.to_vec())
+        );
+        assert!(storage
+            .get_attachment(collection_url, metadata.clone())?
+            .is_none());
+        assert_eq!(storage.get_usage()?.1, attachment.len() as u64);
+
+        // Removing the record doesn't touch its file during the sync, but it's removed by the next
+        // prune.
+        let tombstone = RemoteSettingsRecord {
+            last_modified: 200,
+            deleted: true,
+            attachment: None,
+            ..record.clone()
+        };
+        storage.insert_collection_content(
+            collection_url,
+            &[tombstone],
+            200,
+            CollectionMetadata::default(),
+        )?;
+        assert!(path.exists());
+        storage.prune_attachment_files(collection_url);
+        assert!(!path.exists());
+        assert!(storage.get_attachment_file(&metadata)?.is_none());
+
+        // So does clearing the cached data.
+        storage.insert_collection_content(
+            collection_url,
+            &[record],
+            300,
+            CollectionMetadata::default(),
+        )?;
+        let path = storage.set_attachment_file(collection_url, &metadata, attachment)?;
+        storage.purge()?;
+        assert!(!path.exists());
+
+        // The hash comes from the server, so it mustn't be able to name other files.
+        let bad_metadata = Attachment {
+            hash: "../../collection.sql".to_string(),
+            ..metadata
+        };
+        assert!(storage
+            .set_attachment_file(collection_url, &bad_metadata, attachment)
+            .is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_storage_collection_url_isolation() -> Result<()> {
         let mut storage = Storage::new(":memory:".into())?;
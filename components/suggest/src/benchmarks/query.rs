@@ -4,13 +4,15 @@
 
 use crate::{
     benchmarks::{new_store, BenchmarkWithInput},
-    SuggestStore, SuggestionProvider, SuggestionQuery,
+    FuzzyMatchingConfig, SuggestStore, SuggestionProvider, SuggestionProviderConstraints,
+    SuggestionQuery,
 };
 
 pub struct QueryBenchmark {
     provider: SuggestionProvider,
     query: &'static str,
     should_match: bool,
+    fuzzy_matching: bool,
 }
 
 pub struct IterationInput {
@@ -30,6 +32,10 @@ impl BenchmarkWithInput for QueryBenchmark {
         let query = SuggestionQuery {
             providers: vec![self.provider],
             keyword: self.query.to_string(),
+            provider_constraints: self.fuzzy_matching.then(|| SuggestionProviderConstraints {
+                fuzzy_matching: Some(FuzzyMatchingConfig::default()),
+                ..SuggestionProviderConstraints::default()
+            }),
             ..SuggestionQuery::default()
         };
         // Format the message now so it doesn't take up time in the benchmark.
@@ -69,6 +75,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "hand s",
                 should_match: true,
+                fuzzy_matching: false,
             }
         ),
         (
@@ -77,6 +84,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "hand sa",
                 should_match: true,
+                fuzzy_matching: false,
             }
         ),
         (
@@ -85,6 +93,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "hand san",
                 should_match: true,
+                fuzzy_matching: false,
             }
         ),
         (
@@ -93,6 +102,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "sani",
                 should_match: true,
+                fuzzy_matching: false,
             }
         ),
         (
@@ -101,6 +111,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "sanit",
                 should_match: true,
+                fuzzy_matching: false,
             }
         ),
         (
@@ -109,6 +120,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Fakespot,
                 query: "saniti",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
 
@@ -119,6 +131,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "nomatch",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -127,6 +140,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "no match",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -135,6 +149,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "no match either",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -143,6 +158,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "city1 city2 state1 state2 keyword1 keyword2 keyword3",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -151,6 +167,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "this does not match anything especially not a weather suggestion but nevertheless it is a very long query which as previously mentioned doesn't match anything at all",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -159,6 +176,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "wea",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -167,6 +185,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "ny",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -175,6 +194,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "pdx",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -183,6 +203,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "pdx or",
                 should_match: false,
+                fuzzy_matching: false,
             },
         ),
 
@@ -193,6 +214,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -203,6 +225,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "new york",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -213,6 +236,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles c",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -221,6 +245,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles ca",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -229,6 +254,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "la ca",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -237,6 +263,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "ny ny",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -247,6 +274,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather n",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -255,6 +283,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather ne",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -263,6 +292,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather new",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -271,6 +301,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather new york",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -279,6 +310,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather ny",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -287,6 +319,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather pdx",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -297,6 +330,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather los angeles c",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -305,6 +339,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather los angeles ca",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -313,6 +348,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather la ca",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -321,6 +357,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather ny ny",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -329,6 +366,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "weather pdx or",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -339,6 +377,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "new york w",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -347,6 +386,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "new york we",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -355,6 +395,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "new york wea",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -363,6 +404,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "new york weather",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -371,6 +413,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "ny w",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -379,6 +422,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "ny weather",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
 
@@ -389,6 +433,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles ca w",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -397,6 +442,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles ca we",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -405,6 +451,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles ca wea",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -413,6 +460,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "los angeles ca weather",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -421,6 +469,7 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "la ca weather",
                 should_match: true,
+                fuzzy_matching: false,
             },
         ),
         (
@@ -429,6 +478,46 @@ pub fn all_benchmarks() -> Vec<(&'static str, QueryBenchmark)> {
                 provider: SuggestionProvider::Weather,
                 query: "ny ny weather",
                 should_match: true,
+                fuzzy_matching: false,
+            },
+        ),
+        // AMP and Wikipedia queries, with and without typos. The fuzzy matching ones only look for
+        // the closest keyword after the exact match fails, so the last one, which doesn't match
+        // anything, is the worst case.
+        (
+            "query-amp-amazon",
+            QueryBenchmark {
+                provider: SuggestionProvider::Amp,
+                query: "amazon",
+                should_match: true,
+                fuzzy_matching: false,
+            },
+        ),
+        (
+            "query-amp-fuzzy-amazn",
+            QueryBenchmark {
+                provider: SuggestionProvider::Amp,
+                query: "amazn",
+                should_match: true,
+                fuzzy_matching: true,
+            },
+        ),
+        (
+            "query-wikipedia-fuzzy-californa",
+            QueryBenchmark {
+                provider: SuggestionProvider::Wikipedia,
+                query: "californa",
+                should_match: true,
+                fuzzy_matching: true,
+            },
+        ),
+        (
+            "query-amp-fuzzy-no-match",
+            QueryBenchmark {
+                provider: SuggestionProvider::Amp,
+                query: "sqxzvbnq",
+                should_match: false,
+                fuzzy_matching: true,
             },
         ),
     ]
@@ -0,0 +1,209 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Ranking remote suggestions together with the app's own local results.
+//!
+//! The address bar shows suggestions from this component alongside history
+//! and bookmark matches that the app finds itself. Rather than have the app
+//! merge three ranked lists, it can pass its candidates to
+//! `SuggestStore::query_blended()`, which scores everything with the same
+//! function: a candidate's own score, multiplied by the weight for where it
+//! came from.
+
+use std::collections::HashSet;
+
+use crate::Suggestion;
+
+/// Where a local candidate came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, uniffi::Enum)]
+pub enum LocalCandidateKind {
+    History,
+    Bookmark,
+}
+
+/// A history or bookmark match the app wants ranked with the suggestions.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct LocalCandidate {
+    pub kind: LocalCandidateKind,
+    pub url: String,
+    pub title: String,
+    /// The app's score for the candidate, normalized to the same 0-1 range as
+    /// suggestion scores. For example, frecency divided by the highest
+    /// frecency among the candidates.
+    pub score: f64,
+}
+
+/// Weights for each source of results. A result's blended score is its own
+/// score multiplied by the weight for its source, so a weight of 0 hides that
+/// source's results.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct BlendingConfig {
+    #[uniffi(default = 1.0)]
+    pub remote_weight: f64,
+    #[uniffi(default = 1.0)]
+    pub history_weight: f64,
+    #[uniffi(default = 1.0)]
+    pub bookmark_weight: f64,
+}
+
+impl Default for BlendingConfig {
+    fn default() -> Self {
+        Self {
+            remote_weight: 1.0,
+            history_weight: 1.0,
+            bookmark_weight: 1.0,
+        }
+    }
+}
+
+impl BlendingConfig {
+    fn weight(&self, kind: LocalCandidateKind) -> f64 {
+        match kind {
+            LocalCandidateKind::History => self.history_weight,
+            LocalCandidateKind::Bookmark => self.bookmark_weight,
+        }
+    }
+}
+
+/// A result from `SuggestStore::query_blended()`.
+#[derive(Clone, Debug, PartialEq, uniffi::Enum)]
+pub enum BlendedSuggestion {
+    Remote {
+        suggestion: Suggestion,
+        score: f64,
+    },
+    Local {
+        candidate: LocalCandidate,
+        score: f64,
+    },
+}
+
+impl BlendedSuggestion {
+    pub fn score(&self) -> f64 {
+        match self {
+            Self::Remote { score, .. } | Self::Local { score, .. } => *score,
+        }
+    }
+
+    fn url(&self) -> Option<&str> {
+        match self {
+            Self::Remote { suggestion, .. } => suggestion.url(),
+            Self::Local { candidate, .. } => Some(&candidate.url),
+        }
+    }
+}
+
+/// Ranks `suggestions` and `candidates` together, highest blended score
+/// first.
+///
+/// Results with a weight of 0 are dropped, and when a URL appears more than
+/// once only the highest scoring result for it is kept. Ties keep remote
+/// suggestions first, in the order they were given.
+pub(crate) fn blend(
+    suggestions: Vec<Suggestion>,
+    candidates: Vec<LocalCandidate>,
+    config: &BlendingConfig,
+    limit: Option<usize>,
+) -> Vec<BlendedSuggestion> {
+    let remote = suggestions.into_iter().map(|suggestion| {
+        let score = suggestion.score() * config.remote_weight;
+        BlendedSuggestion::Remote { suggestion, score }
+    });
+    let local = candidates.into_iter().map(|candidate| {
+        let score = candidate.score * config.weight(candidate.kind);
+        BlendedSuggestion::Local { candidate, score }
+    });
+    let mut blended: Vec<_> = remote
+        .chain(local)
+        .filter(|result| result.score() > 0.0)
+        .collect();
+    blended.sort_by(|a, b| b.score().total_cmp(&a.score()));
+
+    // Results are sorted, so the first one for a URL is the one to keep.
+    let mut seen_urls = HashSet::new();
+    blended.retain(|result| match result.url() {
+        Some(url) => seen_urls.insert(url.to_owned()),
+        None => true,
+    });
+    if let Some(limit) = limit {
+        blended.truncate(limit);
+    }
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn local(kind: LocalCandidateKind, url: &str, score: f64) -> LocalCandidate {
+        LocalCandidate {
+            kind,
+            url: url.to_owned(),
+            title: url.to_owned(),
+            score,
+        }
+    }
+
+    fn urls(blended: &[BlendedSuggestion]) -> Vec<&str> {
+        blended.iter().filter_map(BlendedSuggestion::url).collect()
+    }
+
+    #[test]
+    fn test_blend() {
+        // Los Pollos has a score of 0.3.
+        let suggestions = vec![los_pollos_suggestion("los", None)];
+        let candidates = vec![
+            local(
+                LocalCandidateKind::History,
+                "https://history.example.com",
+                0.5,
+            ),
+            local(
+                LocalCandidateKind::Bookmark,
+                "https://bookmark.example.com",
+                0.2,
+            ),
+        ];
+        let blended = blend(
+            suggestions.clone(),
+            candidates.clone(),
+            &BlendingConfig::default(),
+            None,
+        );
+        assert_eq!(
+            urls(&blended),
+            vec![
+                "https://history.example.com",
+                "https://www.lph-nm.biz",
+                "https://bookmark.example.com",
+            ]
+        );
+
+        let config = BlendingConfig {
+            bookmark_weight: 2.0,
+            history_weight: 0.0,
+            ..BlendingConfig::default()
+        };
+        let blended = blend(suggestions, candidates, &config, Some(1));
+        assert_eq!(urls(&blended), vec!["https://bookmark.example.com"]);
+        assert_eq!(blended[0].score(), 0.4);
+    }
+
+    #[test]
+    fn test_blend_dedupes_urls() {
+        let suggestions = vec![los_pollos_suggestion("los", None)];
+        let candidates = vec![
+            local(LocalCandidateKind::History, "https://www.lph-nm.biz", 0.1),
+            local(LocalCandidateKind::Bookmark, "https://www.lph-nm.biz", 0.9),
+        ];
+        let blended = blend(suggestions, candidates, &BlendingConfig::default(), None);
+        assert_eq!(blended.len(), 1);
+        assert!(matches!(
+            &blended[0],
+            BlendedSuggestion::Local { candidate, .. } if candidate.kind == LocalCandidateKind::Bookmark
+        ));
+    }
+}
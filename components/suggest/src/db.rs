@@ -46,6 +46,18 @@ pub const PROVIDER_CONFIG_META_KEY_PREFIX: &str = "provider_config_";
 // Default value when Suggestion does not have a value for score
 pub const DEFAULT_SUGGESTION_SCORE: f64 = 0.2;
 
+/// A `WHERE` clause that leaves out the suggestions `s` that the user dismissed,
+/// by URL or by URL hash. We only hash the URL if any hashes were dismissed;
+/// the first subquery doesn't depend on `s`, so SQLite only runs it once.
+const NOT_DISMISSED: &str = "
+    NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url = s.url)
+    AND (
+      NOT EXISTS (SELECT 1 FROM dismissed_suggestion_url_hashes)
+      OR NOT EXISTS (
+        SELECT 1 FROM dismissed_suggestion_url_hashes WHERE url_hash = url_hash(s.url)
+      )
+    )";
+
 /// The database connection type.
 #[derive(Clone, Copy)]
 pub(crate) enum ConnectionType {
@@ -307,6 +319,55 @@ impl<'a> SuggestDao<'a> {
         self.conn
             .prepare_cached("UPDATE ingested_records SET last_modified=1")?
             .execute(())?;
+        self.conn
+            .prepare_cached("DELETE FROM ingested_record_types")?
+            .execute(())?;
+        Ok(())
+    }
+
+    /// Returns the summary of the records of `record_type` we last ingested,
+    /// if we have one.
+    pub fn get_ingested_record_type(
+        &self,
+        collection: &str,
+        record_type: &str,
+    ) -> Result<Option<RecordTypeSummary>> {
+        Ok(self.conn.try_query_row(
+            "SELECT last_modified, record_count FROM ingested_record_types
+             WHERE collection = :collection AND type = :type",
+            named_params! {
+                ":collection": collection,
+                ":type": record_type,
+            },
+            |row| -> Result<RecordTypeSummary> {
+                Ok(RecordTypeSummary {
+                    last_modified: row.get("last_modified")?,
+                    record_count: row.get("record_count")?,
+                })
+            },
+            true,
+        )?)
+    }
+
+    pub fn update_ingested_record_type(
+        &mut self,
+        collection: &str,
+        record_type: &str,
+        summary: &RecordTypeSummary,
+    ) -> Result<()> {
+        self.conn.execute_cached(
+            "INSERT OR REPLACE INTO ingested_record_types(
+                 collection, type, last_modified, record_count, last_ingested
+             )
+             VALUES(:collection, :type, :last_modified, :record_count, :last_ingested)",
+            named_params! {
+                ":collection": collection,
+                ":type": record_type,
+                ":last_modified": summary.last_modified,
+                ":record_count": summary.record_count,
+                ":last_ingested": chrono::Utc::now().timestamp_millis(),
+            },
+        )?;
         Ok(())
     }
 
@@ -379,7 +440,7 @@ impl<'a> SuggestDao<'a> {
                   s.provider = :provider
                   AND k.keyword = :keyword
                   {where_extra}
-                AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
+                AND {NOT_DISMISSED}
                 "#
             ),
             named_params! {
@@ -489,7 +550,7 @@ impl<'a> SuggestDao<'a> {
                 WHERE
                   s.provider = :provider
                   AND amp_fts match '{fts_column}: {match_arg}'
-                AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
+                AND {NOT_DISMISSED}
                 ORDER BY rank
                 LIMIT 1
                 "#
@@ -604,22 +665,24 @@ impl<'a> SuggestDao<'a> {
     pub fn fetch_wikipedia_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
         let keyword_lowercased = &query.keyword.to_lowercase();
         let suggestions = self.conn.query_rows_and_then_cached(
-            r#"
-            SELECT
-              s.id,
-              k.rank,
-              s.title,
-              s.url
-            FROM
-              suggestions s
-            JOIN
-              keywords k
-              ON k.suggestion_id = s.id
-            WHERE
-              s.provider = :provider
-              AND k.keyword = :keyword
-              AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
-            "#,
+            &format!(
+                r#"
+                SELECT
+                  s.id,
+                  k.rank,
+                  s.title,
+                  s.url
+                FROM
+                  suggestions s
+                JOIN
+                  keywords k
+                  ON k.suggestion_id = s.id
+                WHERE
+                  s.provider = :provider
+                  AND k.keyword = :keyword
+                  AND {NOT_DISMISSED}
+                "#
+            ),
             named_params! {
                 ":keyword": keyword_lowercased,
                 ":provider": SuggestionProvider::Wikipedia
@@ -683,7 +746,8 @@ impl<'a> SuggestDao<'a> {
         let (keyword_prefix, keyword_suffix) = split_keyword(keyword_lowercased);
         let suggestions_limit = query.limit.unwrap_or(-1);
         self.conn.query_rows_and_then_cached(
-            r#"
+            &format!(
+                r#"
                 SELECT
                   s.id,
                   MAX(k.rank) AS rank,
@@ -701,7 +765,7 @@ impl<'a> SuggestDao<'a> {
                   k.keyword_prefix = :keyword_prefix
                   AND (k.keyword_suffix BETWEEN :keyword_suffix AND :keyword_suffix || x'FFFF')
                   AND s.provider = :provider
-                  AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
+                  AND {NOT_DISMISSED}
                 GROUP BY
                   s.id
                 ORDER BY
@@ -709,7 +773,8 @@ impl<'a> SuggestDao<'a> {
                   rank DESC
                 LIMIT
                   :suggestions_limit
-                "#,
+                "#
+            ),
             &[
                 (":keyword_prefix", &keyword_prefix as &dyn ToSql),
                 (":keyword_suffix", &keyword_suffix as &dyn ToSql),
@@ -782,7 +847,8 @@ impl<'a> SuggestDao<'a> {
         let suggestions = self
             .conn
             .query_rows_and_then_cached(
-                r#"
+                &format!(
+                    r#"
             SELECT
               s.id,
               MAX(k.rank) AS rank,
@@ -801,14 +867,15 @@ impl<'a> SuggestDao<'a> {
               k.keyword_prefix = :keyword_prefix
               AND (k.keyword_suffix BETWEEN :keyword_suffix AND :keyword_suffix || x'FFFF')
               AND s.provider = :provider
-              AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
+              AND {NOT_DISMISSED}
             GROUP BY
               s.id,
               k.confidence
             ORDER BY
               s.score DESC,
               rank DESC
-            "#,
+            "#
+                ),
                 named_params! {
                     ":keyword_prefix": keyword_prefix,
                     ":keyword_suffix": keyword_suffix,
@@ -899,7 +966,8 @@ impl<'a> SuggestDao<'a> {
     /// Fetches Fakespot suggestions
     pub fn fetch_fakespot_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
         let fts_query = query.fts_query();
-        let sql = r#"
+        let sql = format!(
+            r#"
             SELECT
                 s.id,
                 s.title,
@@ -926,10 +994,11 @@ impl<'a> SuggestDao<'a> {
                 ON i.id = f.icon_id
             WHERE
                 fakespot_fts MATCH ?
+                AND {NOT_DISMISSED}
             ORDER BY
                 s.score DESC
             "#
-        .to_string();
+        );
 
         // Store the list of results plus the suggestion id for calculating the FTS match info
         let mut results =
@@ -1397,11 +1466,48 @@ impl<'a> SuggestDao<'a> {
         Ok(())
     }
 
+    pub fn insert_dismissal_by_url_hash(&self, url_hash: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO dismissed_suggestion_url_hashes(url_hash)
+             VALUES(:url_hash)",
+            named_params! {
+                ":url_hash": url_hash.to_ascii_lowercase(),
+            },
+        )?;
+        Ok(())
+    }
+
     pub fn clear_dismissals(&self) -> Result<()> {
-        self.conn.execute("DELETE FROM dismissed_suggestions", ())?;
+        self.conn.execute_batch(
+            "DELETE FROM dismissed_suggestions;
+             DELETE FROM dismissed_suggestion_url_hashes;",
+        )?;
         Ok(())
     }
 
+    /// Removes dismissed suggestions from `suggestions`.
+    ///
+    /// Suggestions whose URLs are stored are left out by the queries that fetch
+    /// them, using `NOT_DISMISSED`. This is for the ones whose URLs are built
+    /// from the query, like Yelp's.
+    pub fn filter_dismissed(&self, suggestions: Vec<Suggestion>) -> Result<Vec<Suggestion>> {
+        let mut kept = Vec::with_capacity(suggestions.len());
+        for suggestion in suggestions {
+            if let Some(url) = suggestion.raw_url() {
+                if self.conn.exists(
+                    "SELECT 1 FROM dismissed_suggestions WHERE url = :url
+                     UNION ALL
+                     SELECT 1 FROM dismissed_suggestion_url_hashes WHERE url_hash = url_hash(:url)",
+                    named_params! { ":url": url },
+                )? {
+                    continue;
+                }
+            }
+            kept.push(suggestion);
+        }
+        Ok(kept)
+    }
+
     /// Deletes all suggestions associated with a Remote Settings record from
     /// the database.
     pub fn drop_suggestions(&mut self, record_id: &SuggestRecordId) -> Result<()> {
@@ -1572,6 +1678,39 @@ impl IngestedRecord {
     }
 }
 
+/// Returns the hash used by `SuggestStore::dismiss_suggestion_by_url_hash()`:
+/// the SHA-256 digest of the URL, in lower case hex.
+pub(crate) fn suggestion_url_hash(url: &str) -> Result<String> {
+    let digest = rc_crypto::digest::digest(&rc_crypto::digest::SHA256, url.as_bytes())?;
+    Ok(hex::encode(digest.as_ref()))
+}
+
+/// Summarizes the records of one type, so that we can tell if any have been
+/// added, changed, or deleted since we last ingested them.
+///
+/// Remote Settings gives every new or changed record a newer `last_modified`
+/// time than any existing record, so if the newest time and the number of
+/// records are the same, then so are the records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordTypeSummary {
+    pub last_modified: u64,
+    pub record_count: u32,
+}
+
+impl RecordTypeSummary {
+    pub fn new<'a>(records: impl IntoIterator<Item = &'a Record>) -> Self {
+        let mut summary = Self {
+            last_modified: 0,
+            record_count: 0,
+        };
+        for record in records {
+            summary.last_modified = summary.last_modified.max(record.last_modified);
+            summary.record_count += 1;
+        }
+        summary
+    }
+}
+
 /// Helper struct to get full_keyword_ids for a suggestion
 ///
 /// `FullKeywordInserter` handles repeated full keywords efficiently.  The first instance will
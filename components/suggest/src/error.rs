@@ -34,6 +34,9 @@ pub enum Error {
 
     #[error("SuggestStoreBuilder {0}")]
     SuggestStoreBuilder(String),
+
+    #[error("Error hashing URL: {0}")]
+    Crypto(#[from] rc_crypto::Error),
 }
 
 impl Error {
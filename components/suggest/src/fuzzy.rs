@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Typo-tolerant keyword matching.
+//!
+//! Fuzzy matching is a fallback: a provider is first queried as usual, and
+//! only if nothing matches do we look for the keyword closest to the query,
+//! then query the provider again with that. Keywords are stored with their
+//! prefixes, so "amazn" can be corrected to "amazo" or "amazon", both of which
+//! match the same suggestions as a correctly typed query would.
+
+use rusqlite::named_params;
+use sql_support::ConnExt;
+
+use crate::{
+    db::SuggestDao, provider::FuzzyMatchingConfig, suggestion::Suggestion, Result,
+    SuggestionProvider, SuggestionQuery,
+};
+
+impl SuggestDao<'_> {
+    /// Fetches suggestions for `query` with `fetch`, falling back to the
+    /// closest keyword if the query doesn't match and fuzzy matching is
+    /// enabled for `provider`.
+    pub(crate) fn fetch_with_fuzzy_matching(
+        &self,
+        provider: SuggestionProvider,
+        query: &SuggestionQuery,
+        fetch: impl Fn(&SuggestionQuery) -> Result<Vec<Suggestion>>,
+    ) -> Result<Vec<Suggestion>> {
+        let suggestions = fetch(query)?;
+        if !suggestions.is_empty() {
+            return Ok(suggestions);
+        }
+        let Some(config) = fuzzy_matching_config(provider, query) else {
+            return Ok(suggestions);
+        };
+        match self.fetch_closest_keyword(provider, &query.keyword.to_lowercase(), config)? {
+            Some(keyword) => fetch(&SuggestionQuery {
+                keyword,
+                ..query.clone()
+            }),
+            None => Ok(suggestions),
+        }
+    }
+
+    /// Returns the keyword for `provider` closest to `keyword`, if there's
+    /// one within the limits set by `config`.
+    fn fetch_closest_keyword(
+        &self,
+        provider: SuggestionProvider,
+        keyword: &str,
+        config: &FuzzyMatchingConfig,
+    ) -> Result<Option<String>> {
+        let len = keyword.chars().count();
+        if len < config.min_keyword_length as usize {
+            return Ok(None);
+        }
+        let max_distance = config.max_edit_distance as usize;
+        // Keywords are the first column of the `keywords` primary key, so
+        // requiring the first character to match lets SQLite use the index.
+        let Some((first, next)) = keyword
+            .chars()
+            .next()
+            .and_then(|c| Some((c, char::from_u32(c as u32 + 1)?)))
+        else {
+            return Ok(None);
+        };
+        let candidates: Vec<String> = self.conn.query_rows_and_then_cached(
+            r#"
+            SELECT DISTINCT
+              k.keyword
+            FROM
+              keywords k
+            JOIN
+              suggestions s
+              ON s.id = k.suggestion_id
+            WHERE
+              k.keyword >= :first
+              AND k.keyword < :next
+              AND length(k.keyword) BETWEEN :min_length AND :max_length
+              AND s.provider = :provider
+            "#,
+            named_params! {
+                ":first": first.to_string(),
+                ":next": next.to_string(),
+                ":min_length": len.saturating_sub(max_distance),
+                ":max_length": len + max_distance,
+                ":provider": provider,
+            },
+            |row| row.get(0),
+        )?;
+        self.scope.err_if_interrupted()?;
+        Ok(candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let distance = edit_distance(keyword, &candidate);
+                let longest = len.max(candidate.chars().count());
+                let relevant =
+                    (longest - distance) * 100 >= longest * config.min_similarity_percent as usize;
+                (distance <= max_distance && relevant).then_some((distance, candidate))
+            })
+            .min()
+            .map(|(_, candidate)| candidate))
+    }
+}
+
+/// Returns the fuzzy matching settings for `provider`, if the query enables
+/// fuzzy matching for it.
+fn fuzzy_matching_config(
+    provider: SuggestionProvider,
+    query: &SuggestionQuery,
+) -> Option<&FuzzyMatchingConfig> {
+    let constraints = query.provider_constraints.as_ref()?;
+    let config = constraints.fuzzy_matching.as_ref()?;
+    let supported = match provider {
+        // FTS matching already has its own notion of how close a match is.
+        SuggestionProvider::Amp => !constraints
+            .amp_alternative_matching
+            .as_ref()
+            .is_some_and(|strategy| strategy.uses_fts()),
+        SuggestionProvider::AmpMobile | SuggestionProvider::Wikipedia => true,
+        _ => false,
+    };
+    (supported && config.providers.contains(&provider)).then_some(config)
+}
+
+/// Returns the number of characters that must be inserted, deleted, replaced,
+/// or swapped with their neighbor to turn `a` into `b`. This is the "optimal
+/// string alignment" distance, which counts swaps as a single typo, unlike the
+/// Levenshtein distance.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // `rows[0]` is the row before last, `rows[1]` the last, and `rows[2]` the
+    // current one.
+    let mut rows = [
+        vec![0; b.len() + 1],
+        (0..=b.len()).collect(),
+        vec![0; b.len() + 1],
+    ];
+    for i in 1..=a.len() {
+        rows[2][0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[1][j] + 1)
+                .min(rows[2][j - 1] + 1)
+                .min(rows[1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[0][j - 2] + 1);
+            }
+            rows[2][j] = distance;
+        }
+        rows.rotate_left(1);
+    }
+    rows[1][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("amazon", "amazon"), 0);
+        assert_eq!(edit_distance("amazn", "amazon"), 1);
+        assert_eq!(edit_distance("amazon", "amazn"), 1);
+        assert_eq!(edit_distance("amzaon", "amazon"), 1);
+        assert_eq!(edit_distance("anazon", "amazon"), 1);
+        assert_eq!(edit_distance("amazon", "amazing"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+}
@@ -5,15 +5,18 @@
 
 #[cfg(feature = "benchmark_api")]
 pub mod benchmarks;
+mod blend;
 mod config;
 mod db;
 mod error;
 mod fakespot;
+mod fuzzy;
 mod geoname;
 mod metrics;
 pub mod pocket;
 mod provider;
 mod query;
+mod query_cache;
 mod rs;
 mod schema;
 mod store;
@@ -24,11 +27,14 @@ pub mod util;
 mod weather;
 mod yelp;
 
+pub use blend::{BlendedSuggestion, BlendingConfig, LocalCandidate, LocalCandidateKind};
 pub use config::{SuggestGlobalConfig, SuggestProviderConfig};
 pub use error::{Error, SuggestApiError};
 pub use geoname::{Geoname, GeonameMatch, GeonameType};
-pub use metrics::{LabeledTimingSample, SuggestIngestionMetrics};
-pub use provider::{AmpMatchingStrategy, SuggestionProvider, SuggestionProviderConstraints};
+pub use metrics::{LabeledTimingSample, RecordTypeIngestionStats, SuggestIngestionMetrics};
+pub use provider::{
+    AmpMatchingStrategy, FuzzyMatchingConfig, SuggestionProvider, SuggestionProviderConstraints,
+};
 pub use query::{QueryWithMetricsResult, SuggestionQuery};
 pub use store::{InterruptKind, SuggestIngestionConstraints, SuggestStore, SuggestStoreBuilder};
 pub use suggestion::{raw_suggestion_url_matches, Suggestion};
@@ -27,6 +27,24 @@ pub struct SuggestIngestionMetrics {
     pub ingestion_times: Vec<LabeledTimingSample>,
     /// Samples for the `suggest.ingestion_download_time` metric
     pub download_times: Vec<LabeledTimingSample>,
+    /// Progress stats for each record type that was checked
+    pub record_type_stats: Vec<RecordTypeIngestionStats>,
+}
+
+/// What ingestion did for a record type
+#[derive(Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct RecordTypeIngestionStats {
+    pub record_type: String,
+    /// New, changed, or reprocessed records that were ingested
+    pub records_processed: u32,
+    /// Records that were removed from Remote Settings, so their data was
+    /// deleted
+    pub records_deleted: u32,
+    /// True if none of the records had changed since the last ingestion, so
+    /// they weren't looked at
+    pub skipped: bool,
+    /// Time in microseconds, including downloads
+    pub elapsed: u64,
 }
 
 impl SuggestIngestionMetrics {
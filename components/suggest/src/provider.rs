@@ -123,7 +123,7 @@ impl ToSql for SuggestionProvider {
 
 /// Some providers manage multiple suggestion subtypes. Queries, ingests, and
 /// other operations on those providers must be constrained to a desired subtype.
-#[derive(Clone, Default, Debug, uniffi::Record)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, uniffi::Record)]
 pub struct SuggestionProviderConstraints {
     /// `Exposure` provider - For each desired exposure suggestion type, this
     /// should contain the value of the `suggestion_type` field of its remote
@@ -134,9 +134,13 @@ pub struct SuggestionProviderConstraints {
     /// Use None for the default strategy.
     #[uniffi(default = None)]
     pub amp_alternative_matching: Option<AmpMatchingStrategy>,
+    /// Typo-tolerant matching, for queries which don't match any keywords
+    /// exactly. Use None to only match exactly.
+    #[uniffi(default = None)]
+    pub fuzzy_matching: Option<FuzzyMatchingConfig>,
 }
 
-#[derive(Clone, Debug, uniffi::Enum)]
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
 pub enum AmpMatchingStrategy {
     /// Disable keywords added via keyword expansion.
     /// This eliminates keywords that for terms related to the "real" keywords, for example
@@ -153,3 +157,45 @@ impl AmpMatchingStrategy {
         matches!(self, Self::FtsAgainstFullKeywords | Self::FtsAgainstTitle)
     }
 }
+
+/// Settings for matching keywords with typos in them, like "amazn".
+///
+/// When a query doesn't match any of a provider's keywords exactly, the
+/// closest keyword within the limits below is matched instead. The first
+/// character must be right, since that keeps the search cheap and typos there
+/// are rare.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct FuzzyMatchingConfig {
+    /// The providers to use fuzzy matching for. Only `Amp`, `AmpMobile` and
+    /// `Wikipedia` support it; AMP only does when not using an FTS matching
+    /// strategy.
+    pub providers: Vec<SuggestionProvider>,
+    /// The most typos to allow, where a typo is a character inserted, deleted,
+    /// replaced, or swapped with its neighbor.
+    #[uniffi(default = 1)]
+    pub max_edit_distance: u32,
+    /// Shorter queries are only matched exactly, since a typo in a short
+    /// keyword is too likely to match something unrelated.
+    #[uniffi(default = 4)]
+    pub min_keyword_length: u32,
+    /// The relevance threshold: how much of the longer of the query and the
+    /// keyword must be right, as a percentage. For example, "amazn" is 83%
+    /// similar to "amazon".
+    #[uniffi(default = 75)]
+    pub min_similarity_percent: u32,
+}
+
+impl Default for FuzzyMatchingConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![
+                SuggestionProvider::Amp,
+                SuggestionProvider::AmpMobile,
+                SuggestionProvider::Wikipedia,
+            ],
+            max_edit_distance: 1,
+            min_keyword_length: 4,
+            min_similarity_percent: 75,
+        }
+    }
+}
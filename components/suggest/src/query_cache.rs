@@ -0,0 +1,319 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! A small in-memory cache of per-provider query results.
+//!
+//! Consumers query once per keystroke, so consecutive queries are usually
+//! either repeats (eg, after a backspace) or extend the previous keyword by a
+//! character. The cache handles both:
+//!
+//! - Repeated queries are answered from the cache outright.
+//! - For providers whose matching is prefix-monotonic -- every suggestion
+//!   matching a keyword also matches all of its prefixes -- the results for an
+//!   extended keyword must be a subset of the cached results for its prefix.
+//!   Suggestions don't record the keyword they matched, so we can't filter a
+//!   non-empty result set, but an empty one stays empty and the query can be
+//!   skipped. Most keystrokes don't match anything, so this is the common case.
+//!
+//! Most providers aren't prefix-monotonic (eg, AMP only matches the keywords
+//! listed in its records, which needn't include every prefix of a full
+//! keyword), so only exact hits are used for those.
+//!
+//! The cache must be cleared whenever the database changes.
+
+use std::{borrow::Cow, collections::VecDeque};
+
+use crate::{pocket::split_keyword, Suggestion, SuggestionProvider, SuggestionQuery};
+
+/// The number of results to keep, across all providers.
+const QUERY_CACHE_SIZE: usize = 32;
+
+struct CacheEntry {
+    provider: SuggestionProvider,
+    /// The normalized keyword.
+    keyword: String,
+    query: SuggestionQuery,
+    suggestions: Vec<Suggestion>,
+}
+
+impl CacheEntry {
+    /// Whether the entry was for the same query as `query`, apart from its
+    /// keyword.
+    fn matches_query(&self, provider: SuggestionProvider, query: &SuggestionQuery) -> bool {
+        self.provider == provider
+            && self.query.provider_constraints == query.provider_constraints
+            && self.query.limit == query.limit
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct QueryCache {
+    /// Most recently used first.
+    entries: VecDeque<CacheEntry>,
+    /// Incremented on every `clear()`, so that results read before a change
+    /// to the database aren't inserted after it.
+    generation: u64,
+}
+
+impl QueryCache {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the cached results for `provider`, if we have them.
+    pub fn get(
+        &mut self,
+        provider: SuggestionProvider,
+        query: &SuggestionQuery,
+    ) -> Option<Vec<Suggestion>> {
+        let keyword = normalize_keyword(provider, query);
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.matches_query(provider, query) && entry.keyword == keyword);
+        if let Some(index) = index {
+            let entry = self.entries.remove(index)?;
+            let suggestions = entry.suggestions.clone();
+            self.entries.push_front(entry);
+            return Some(suggestions);
+        }
+        let narrowed_to_empty = self.entries.iter().any(|entry| {
+            entry.matches_query(provider, query)
+                && entry.suggestions.is_empty()
+                && extends_prefix_monotonically(provider, &entry.keyword, &keyword)
+        });
+        narrowed_to_empty.then(Vec::new)
+    }
+
+    /// Caches `suggestions` for `provider`, unless the cache has been cleared
+    /// since `generation`.
+    pub fn insert(
+        &mut self,
+        generation: u64,
+        provider: SuggestionProvider,
+        query: &SuggestionQuery,
+        suggestions: &[Suggestion],
+    ) {
+        if generation != self.generation {
+            return;
+        }
+        let keyword = normalize_keyword(provider, query).into_owned();
+        self.entries.push_front(CacheEntry {
+            provider,
+            keyword,
+            query: SuggestionQuery {
+                keyword: String::new(),
+                providers: vec![],
+                ..query.clone()
+            },
+            suggestions: suggestions.to_vec(),
+        });
+        self.entries.truncate(QUERY_CACHE_SIZE);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.generation += 1;
+    }
+}
+
+/// Normalizes the query's keyword to the form the provider matches on, so
+/// that eg "Relay" and "relay" share a cache entry.
+///
+/// Only providers whose results don't depend on the keyword's case are
+/// normalized: some (eg Yelp) build their suggestions from the keyword as
+/// typed, and AMP's FTS matching reports on the keyword terms as typed.
+fn normalize_keyword(provider: SuggestionProvider, query: &SuggestionQuery) -> Cow<'_, str> {
+    let case_insensitive = match provider {
+        SuggestionProvider::Amp => !query
+            .provider_constraints
+            .as_ref()
+            .and_then(|c| c.amp_alternative_matching.as_ref())
+            .is_some_and(|strategy| strategy.uses_fts()),
+        SuggestionProvider::AmpMobile
+        | SuggestionProvider::Wikipedia
+        | SuggestionProvider::Amo
+        | SuggestionProvider::Pocket
+        | SuggestionProvider::Mdn => true,
+        _ => false,
+    };
+    if case_insensitive {
+        Cow::Owned(query.keyword.to_lowercase())
+    } else {
+        Cow::Borrowed(&query.keyword)
+    }
+}
+
+/// Returns true if every suggestion `provider` returns for `keyword` must also
+/// have been returned for `prefix`.
+fn extends_prefix_monotonically(provider: SuggestionProvider, prefix: &str, keyword: &str) -> bool {
+    match provider {
+        // These match the first word of the keyword exactly, and the rest of
+        // it as a prefix of the suggestion's keyword suffix. Note that Pocket
+        // also uses prefix keywords, but its high-confidence keywords must
+        // match their suffix exactly, so aren't prefix-monotonic.
+        SuggestionProvider::Amo | SuggestionProvider::Mdn => {
+            let (prefix_first_word, prefix_suffix) = split_keyword(prefix);
+            let (first_word, suffix) = split_keyword(keyword);
+            prefix_first_word == first_word && suffix.starts_with(prefix_suffix)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wiki(title: &str) -> Suggestion {
+        Suggestion::Wikipedia {
+            title: title.into(),
+            url: format!("https://wikipedia.org/{title}"),
+            icon: None,
+            icon_mimetype: None,
+            full_keyword: title.to_lowercase(),
+        }
+    }
+
+    #[test]
+    fn test_exact_hits() {
+        let mut cache = QueryCache::default();
+        let generation = cache.generation();
+        let results = vec![wiki("California")];
+        cache.insert(
+            generation,
+            SuggestionProvider::Wikipedia,
+            &SuggestionQuery::wikipedia("cal"),
+            &results,
+        );
+        // Keywords are matched case-insensitively for Wikipedia.
+        assert_eq!(
+            cache.get(
+                SuggestionProvider::Wikipedia,
+                &SuggestionQuery::wikipedia("CAL")
+            ),
+            Some(results)
+        );
+        // But the rest of the query must match.
+        assert_eq!(
+            cache.get(
+                SuggestionProvider::Wikipedia,
+                &SuggestionQuery::wikipedia("cal").limit(1)
+            ),
+            None
+        );
+        assert_eq!(
+            cache.get(SuggestionProvider::Amo, &SuggestionQuery::amo("cal")),
+            None
+        );
+        // Wikipedia isn't prefix-monotonic.
+        assert_eq!(
+            cache.get(
+                SuggestionProvider::Wikipedia,
+                &SuggestionQuery::wikipedia("cali")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_narrowing() {
+        let mut cache = QueryCache::default();
+        let generation = cache.generation();
+        cache.insert(
+            generation,
+            SuggestionProvider::Amo,
+            &SuggestionQuery::amo("relay f"),
+            &[],
+        );
+        assert_eq!(
+            cache.get(SuggestionProvider::Amo, &SuggestionQuery::amo("relay fo")),
+            Some(vec![])
+        );
+        // Changing the first word isn't an extension.
+        assert_eq!(
+            cache.get(SuggestionProvider::Amo, &SuggestionQuery::amo("relayx f")),
+            None
+        );
+
+        // An empty result for the first word alone also narrows.
+        cache.insert(
+            generation,
+            SuggestionProvider::Mdn,
+            &SuggestionQuery::mdn("array"),
+            &[],
+        );
+        assert_eq!(
+            cache.get(SuggestionProvider::Mdn, &SuggestionQuery::mdn("array fi")),
+            Some(vec![])
+        );
+        assert_eq!(
+            cache.get(SuggestionProvider::Mdn, &SuggestionQuery::mdn("arrays")),
+            None
+        );
+
+        // Pocket isn't prefix-monotonic.
+        cache.insert(
+            generation,
+            SuggestionProvider::Pocket,
+            &SuggestionQuery::pocket("soy b"),
+            &[],
+        );
+        assert_eq!(
+            cache.get(
+                SuggestionProvider::Pocket,
+                &SuggestionQuery::pocket("soy bean")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = QueryCache::default();
+        let generation = cache.generation();
+        let query = SuggestionQuery::wikipedia("cal");
+        cache.insert(
+            generation,
+            SuggestionProvider::Wikipedia,
+            &query,
+            &[wiki("California")],
+        );
+        cache.clear();
+        assert_eq!(cache.get(SuggestionProvider::Wikipedia, &query), None);
+
+        // Results read before the clear aren't cached.
+        cache.insert(
+            generation,
+            SuggestionProvider::Wikipedia,
+            &query,
+            &[wiki("California")],
+        );
+        assert_eq!(cache.get(SuggestionProvider::Wikipedia, &query), None);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let mut cache = QueryCache::default();
+        let generation = cache.generation();
+        for i in 0..=QUERY_CACHE_SIZE {
+            cache.insert(
+                generation,
+                SuggestionProvider::Wikipedia,
+                &SuggestionQuery::wikipedia(&format!("query {i}")),
+                &[],
+            );
+        }
+        assert_eq!(cache.entries.len(), QUERY_CACHE_SIZE);
+        // The oldest entry was evicted.
+        assert_eq!(
+            cache.get(
+                SuggestionProvider::Wikipedia,
+                &SuggestionQuery::wikipedia("query 0")
+            ),
+            None
+        );
+    }
+}
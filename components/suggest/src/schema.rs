@@ -3,8 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
-use crate::db::Sqlite3Extension;
-use rusqlite::{Connection, Transaction};
+use crate::db::{suggestion_url_hash, Sqlite3Extension};
+use rusqlite::{functions::FunctionFlags, Connection, Transaction};
 use sql_support::{
     open_database::{self, ConnectionInitializer},
     ConnExt,
@@ -23,7 +23,7 @@ use sql_support::{
 ///     `clear_database()` by adding their names to `conditional_tables`, unless
 ///     they are cleared via a deletion trigger or there's some other good
 ///     reason not to do so.
-pub const VERSION: u32 = 32;
+pub const VERSION: u32 = 34;
 
 /// The current Suggest database schema.
 pub const SQL: &str = "
@@ -45,6 +45,18 @@ CREATE TABLE ingested_records(
     PRIMARY KEY (id, collection)
 ) WITHOUT ROWID;
 
+-- The newest `last_modified` time and the number of records for each record
+-- type we've ingested, so that ingestion can skip record types that haven't
+-- changed.
+CREATE TABLE ingested_record_types(
+    collection TEXT,
+    type TEXT,
+    last_modified INTEGER NOT NULL,
+    record_count INTEGER NOT NULL,
+    last_ingested INTEGER NOT NULL,
+    PRIMARY KEY (collection, type)
+) WITHOUT ROWID;
+
 CREATE TABLE keywords(
     keyword TEXT NOT NULL,
     suggestion_id INTEGER NOT NULL,
@@ -237,6 +249,12 @@ CREATE TABLE geonames_metrics(
 CREATE TABLE dismissed_suggestions (
     url TEXT PRIMARY KEY
 ) WITHOUT ROWID;
+
+-- Suggestions dismissed by the SHA-256 hash of their URL, for apps which only
+-- keep the hashes.
+CREATE TABLE dismissed_suggestion_url_hashes (
+    url_hash TEXT PRIMARY KEY
+) WITHOUT ROWID;
 ";
 
 /// Initializes an SQLite connection to the Suggest database, performing
@@ -279,6 +297,16 @@ impl ConnectionInitializer for SuggestConnectionInitializer<'_> {
         ";
         conn.execute_batch(initial_pragmas)?;
         sql_support::debug_tools::define_debug_functions(conn)?;
+        // Used to leave out suggestions dismissed by URL hash.
+        conn.create_scalar_function(
+            "url_hash",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                let url = ctx.get::<String>(0)?;
+                suggestion_url_hash(&url).map_err(|e| rusqlite::Error::UserFunctionError(e.into()))
+            },
+        )?;
 
         Ok(())
     }
@@ -622,6 +650,34 @@ CREATE VIRTUAL TABLE IF NOT EXISTS amp_fts USING FTS5(
                 )?;
                 Ok(())
             }
+            32 => {
+                // Add the `ingested_record_types` table. It starts out empty, so
+                // the next ingestion checks every record type as before.
+                tx.execute_batch(
+                    "
+CREATE TABLE ingested_record_types(
+    collection TEXT,
+    type TEXT,
+    last_modified INTEGER NOT NULL,
+    record_count INTEGER NOT NULL,
+    last_ingested INTEGER NOT NULL,
+    PRIMARY KEY (collection, type)
+) WITHOUT ROWID;
+                    ",
+                )?;
+                Ok(())
+            }
+            33 => {
+                // Add the `dismissed_suggestion_url_hashes` table.
+                tx.execute_batch(
+                    "
+CREATE TABLE dismissed_suggestion_url_hashes (
+    url_hash TEXT PRIMARY KEY
+) WITHOUT ROWID;
+                    ",
+                )?;
+                Ok(())
+            }
             _ => Err(open_database::Error::IncompatibleVersion(version)),
         }
     }
@@ -647,6 +703,7 @@ pub fn clear_database(db: &Connection) -> rusqlite::Result<()> {
         "fakespot_fts",
         "geonames",
         "geonames_metrics",
+        "ingested_record_types",
         "ingested_records",
         "keywords_metrics",
         "rs_cache",
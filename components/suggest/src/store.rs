@@ -7,6 +7,7 @@ use std::{
     collections::{hash_map::Entry, BTreeSet, HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
+    time::Instant,
 };
 
 use error_support::{breadcrumb, handle_error};
@@ -17,12 +18,18 @@ use remote_settings::{self, RemoteSettingsConfig, RemoteSettingsServer};
 use serde::de::DeserializeOwned;
 
 use crate::{
+    blend::{blend, BlendedSuggestion, BlendingConfig, LocalCandidate},
     config::{SuggestGlobalConfig, SuggestProviderConfig},
-    db::{ConnectionType, IngestedRecord, Sqlite3Extension, SuggestDao, SuggestDb},
+    db::{
+        ConnectionType, IngestedRecord, RecordTypeSummary, Sqlite3Extension, SuggestDao, SuggestDb,
+    },
     error::Error,
     geoname::{Geoname, GeonameMatch, GeonameType},
-    metrics::{MetricsContext, SuggestIngestionMetrics, SuggestQueryMetrics},
+    metrics::{
+        MetricsContext, RecordTypeIngestionStats, SuggestIngestionMetrics, SuggestQueryMetrics,
+    },
     provider::{SuggestionProvider, SuggestionProviderConstraints, DEFAULT_INGEST_PROVIDERS},
+    query_cache::QueryCache,
     rs::{
         Client, Collection, DownloadedExposureRecord, Record, RemoteSettingsClient,
         SuggestAttachment, SuggestRecord, SuggestRecordId, SuggestRecordType,
@@ -195,6 +202,21 @@ impl SuggestStore {
         Ok(self.inner.query(query)?.suggestions)
     }
 
+    /// Queries the database for suggestions, and ranks them together with the
+    /// app's own history and bookmark matches, using `config` to weight each
+    /// source. The query's limit applies to the blended results.
+    #[handle_error(Error)]
+    pub fn query_blended(
+        &self,
+        query: SuggestionQuery,
+        local_candidates: Vec<LocalCandidate>,
+        config: BlendingConfig,
+    ) -> SuggestApiResult<Vec<BlendedSuggestion>> {
+        let limit = query.limit.and_then(|limit| usize::try_from(limit).ok());
+        let suggestions = self.inner.query(query)?.suggestions;
+        Ok(blend(suggestions, local_candidates, &config, limit))
+    }
+
     /// Queries the database for suggestions.
     #[handle_error(Error)]
     pub fn query_with_metrics(
@@ -214,7 +236,17 @@ impl SuggestStore {
         self.inner.dismiss_suggestion(suggestion_url)
     }
 
-    /// Clear dismissed suggestions
+    /// Dismiss a suggestion by the hash of its URL, for apps which only keep
+    /// hashes of the URLs the user dismissed.
+    ///
+    /// The hash is the SHA-256 digest of the URL passed to
+    /// `dismiss_suggestion()`, as hex.
+    #[handle_error(Error)]
+    pub fn dismiss_suggestion_by_url_hash(&self, url_hash: String) -> SuggestApiResult<()> {
+        self.inner.dismiss_suggestion_by_url_hash(url_hash)
+    }
+
+    /// Clear dismissed suggestions, including those dismissed by URL hash
     #[handle_error(Error)]
     pub fn clear_dismissed_suggestions(&self) -> SuggestApiResult<()> {
         self.inner.clear_dismissed_suggestions()
@@ -384,6 +416,9 @@ pub(crate) struct SuggestStoreInner<S> {
     dbs: OnceCell<SuggestStoreDbs>,
     extensions_to_load: Vec<Sqlite3Extension>,
     settings_client: S,
+    /// Results of recent queries. This must be cleared whenever we write to
+    /// the database.
+    query_cache: Mutex<QueryCache>,
 }
 
 impl<S> SuggestStoreInner<S> {
@@ -397,6 +432,7 @@ impl<S> SuggestStoreInner<S> {
             extensions_to_load,
             dbs: OnceCell::new(),
             settings_client,
+            query_cache: Mutex::default(),
         }
     }
 
@@ -415,22 +451,43 @@ impl<S> SuggestStoreInner<S> {
         let reader = &self.dbs()?.reader;
         for provider in unique_providers {
             let new_suggestions = metrics.measure_query(provider.to_string(), || {
-                reader.read(|dao| match provider {
+                let generation = {
+                    let mut cache = self.query_cache.lock();
+                    if let Some(suggestions) = cache.get(*provider, &query) {
+                        return Ok(suggestions);
+                    }
+                    cache.generation()
+                };
+                let suggestions = reader.read(|dao| match provider {
                     SuggestionProvider::Amp => {
-                        dao.fetch_amp_suggestions(&query, AmpSuggestionType::Desktop)
+                        dao.fetch_with_fuzzy_matching(*provider, &query, |query| {
+                            dao.fetch_amp_suggestions(query, AmpSuggestionType::Desktop)
+                        })
                     }
                     SuggestionProvider::AmpMobile => {
-                        dao.fetch_amp_suggestions(&query, AmpSuggestionType::Mobile)
+                        dao.fetch_with_fuzzy_matching(*provider, &query, |query| {
+                            dao.fetch_amp_suggestions(query, AmpSuggestionType::Mobile)
+                        })
+                    }
+                    SuggestionProvider::Wikipedia => {
+                        dao.fetch_with_fuzzy_matching(*provider, &query, |query| {
+                            dao.fetch_wikipedia_suggestions(query)
+                        })
                     }
-                    SuggestionProvider::Wikipedia => dao.fetch_wikipedia_suggestions(&query),
                     SuggestionProvider::Amo => dao.fetch_amo_suggestions(&query),
                     SuggestionProvider::Pocket => dao.fetch_pocket_suggestions(&query),
-                    SuggestionProvider::Yelp => dao.fetch_yelp_suggestions(&query),
+                    SuggestionProvider::Yelp => dao
+                        .fetch_yelp_suggestions(&query)
+                        .and_then(|suggestions| dao.filter_dismissed(suggestions)),
                     SuggestionProvider::Mdn => dao.fetch_mdn_suggestions(&query),
                     SuggestionProvider::Weather => dao.fetch_weather_suggestions(&query),
                     SuggestionProvider::Fakespot => dao.fetch_fakespot_suggestions(&query),
                     SuggestionProvider::Exposure => dao.fetch_exposure_suggestions(&query),
-                })
+                })?;
+                self.query_cache
+                    .lock()
+                    .insert(generation, *provider, &query, &suggestions);
+                Ok(suggestions)
             })?;
             suggestions.extend(new_suggestions);
         }
@@ -450,14 +507,27 @@ impl<S> SuggestStoreInner<S> {
     }
 
     fn dismiss_suggestion(&self, suggestion_url: String) -> Result<()> {
-        self.dbs()?
+        let result = self
+            .dbs()?
             .writer
-            .write(|dao| dao.insert_dismissal(&suggestion_url))
+            .write(|dao| dao.insert_dismissal(&suggestion_url));
+        self.query_cache.lock().clear();
+        result
+    }
+
+    fn dismiss_suggestion_by_url_hash(&self, url_hash: String) -> Result<()> {
+        let result = self
+            .dbs()?
+            .writer
+            .write(|dao| dao.insert_dismissal_by_url_hash(&url_hash));
+        self.query_cache.lock().clear();
+        result
     }
 
     fn clear_dismissed_suggestions(&self) -> Result<()> {
-        self.dbs()?.writer.write(|dao| dao.clear_dismissals())?;
-        Ok(())
+        let result = self.dbs()?.writer.write(|dao| dao.clear_dismissals());
+        self.query_cache.lock().clear();
+        result
     }
 
     fn interrupt(&self, kind: Option<InterruptKind>) {
@@ -479,7 +549,9 @@ impl<S> SuggestStoreInner<S> {
     }
 
     fn clear(&self) -> Result<()> {
-        self.dbs()?.writer.write(|dao| dao.clear())
+        let result = self.dbs()?.writer.write(|dao| dao.clear());
+        self.query_cache.lock().clear();
+        result
     }
 
     pub fn fetch_global_config(&self) -> Result<SuggestGlobalConfig> {
@@ -526,6 +598,17 @@ where
     pub fn ingest(
         &self,
         constraints: SuggestIngestionConstraints,
+    ) -> Result<SuggestIngestionMetrics> {
+        // Clear the cache even if ingestion fails, since some changes may
+        // have been written before it did.
+        let result = self.ingest_collections(constraints);
+        self.query_cache.lock().clear();
+        result
+    }
+
+    fn ingest_collections(
+        &self,
+        constraints: SuggestIngestionConstraints,
     ) -> Result<SuggestIngestionMetrics> {
         breadcrumb!("Ingestion starting");
         let writer = &self.dbs()?.writer;
@@ -576,18 +659,52 @@ where
             // [Self::ingest_records]
             for record_type in record_types {
                 breadcrumb!("Ingesting record_type: {record_type}");
-                metrics.measure_ingest(record_type.to_string(), |context| {
-                    let changes = RecordChanges::new(
-                        records.iter().filter(|r| r.record_type() == record_type),
-                        ingested_records.iter().filter(|i| {
-                            i.record_type == record_type.as_str()
-                                && i.collection == collection.name()
-                        }),
-                    );
-                    write_scope.write(|dao| {
-                        self.process_changes(dao, collection, changes, &constraints, context)
-                    })
+                let timer = Instant::now();
+                let mut stats = RecordTypeIngestionStats {
+                    record_type: record_type.to_string(),
+                    ..RecordTypeIngestionStats::default()
+                };
+                let current_records = records
+                    .iter()
+                    .filter(|r| r.record_type() == record_type)
+                    .collect::<Vec<_>>();
+                let summary = RecordTypeSummary::new(current_records.iter().copied());
+                let ingested_summary = write_scope.read(|dao| {
+                    dao.get_ingested_record_type(collection.name(), record_type.as_str())
                 })?;
+                if ingested_summary == Some(summary)
+                    && !may_reprocess_unchanged_records(record_type, &constraints)
+                {
+                    log::trace!("Skipping unchanged record_type: {record_type}");
+                    stats.skipped = true;
+                } else {
+                    metrics.measure_ingest(record_type.to_string(), |context| {
+                        let changes = RecordChanges::new(
+                            current_records.iter().copied(),
+                            ingested_records.iter().filter(|i| {
+                                i.record_type == record_type.as_str()
+                                    && i.collection == collection.name()
+                            }),
+                        );
+                        stats.records_deleted = changes.deleted.len() as u32;
+                        write_scope.write(|dao| {
+                            stats.records_processed = self.process_changes(
+                                dao,
+                                collection,
+                                changes,
+                                &constraints,
+                                context,
+                            )?;
+                            dao.update_ingested_record_type(
+                                collection.name(),
+                                record_type.as_str(),
+                                &summary,
+                            )
+                        })
+                    })?;
+                }
+                stats.elapsed = timer.elapsed().as_micros() as u64;
+                metrics.record_type_stats.push(stats);
                 write_scope.err_if_interrupted()?;
             }
         }
@@ -603,10 +720,12 @@ where
         changes: RecordChanges<'_>,
         constraints: &SuggestIngestionConstraints,
         context: &mut MetricsContext,
-    ) -> Result<()> {
+    ) -> Result<u32> {
+        let mut processed = 0;
         for record in &changes.new {
             log::trace!("Ingesting record ID: {}", record.id.as_str());
             self.process_record(dao, record, constraints, context)?;
+            processed += 1;
         }
         for record in &changes.updated {
             // Drop any data that we previously ingested from this record.
@@ -616,11 +735,13 @@ where
             log::trace!("Reingesting updated record ID: {}", record.id.as_str());
             dao.delete_record_data(&record.id)?;
             self.process_record(dao, record, constraints, context)?;
+            processed += 1;
         }
         for record in &changes.unchanged {
             if self.should_reprocess_record(dao, record, constraints)? {
                 log::trace!("Reingesting unchanged record ID: {}", record.id.as_str());
                 self.process_record(dao, record, constraints, context)?;
+                processed += 1;
             } else {
                 log::trace!("Skipping unchanged record ID: {}", record.id.as_str());
             }
@@ -635,7 +756,7 @@ where
             &changes.updated,
             &changes.deleted,
         )?;
-        Ok(())
+        Ok(processed)
     }
 
     fn process_record(
@@ -776,6 +897,20 @@ where
     }
 }
 
+/// Returns true if `should_reprocess_record()` might reprocess some unchanged
+/// records of `record_type`, so their record type can't be skipped even if
+/// it's unchanged.
+fn may_reprocess_unchanged_records(
+    record_type: SuggestRecordType,
+    constraints: &SuggestIngestionConstraints,
+) -> bool {
+    match record_type {
+        SuggestRecordType::Exposure => true,
+        SuggestRecordType::AmpWikipedia => constraints.amp_matching_uses_fts(),
+        _ => false,
+    }
+}
+
 /// Tracks changes in suggest records since the last ingestion
 struct RecordChanges<'a> {
     new: Vec<&'a Record>,
@@ -868,6 +1003,7 @@ where
                 )
             })
             .unwrap();
+        self.query_cache.lock().clear();
     }
 
     pub fn table_row_counts(&self) -> Vec<(String, u32)> {
@@ -931,7 +1067,10 @@ pub(crate) mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
 
     use crate::{
-        provider::AmpMatchingStrategy, suggestion::FtsMatchInfo, testing::*, SuggestionProvider,
+        provider::{AmpMatchingStrategy, FuzzyMatchingConfig},
+        suggestion::FtsMatchInfo,
+        testing::*,
+        SuggestionProvider,
     };
 
     // Extra methods for the tests
@@ -985,8 +1124,8 @@ pub(crate) mod tests {
                 .unwrap_or_else(|e| panic!("SQL error in count: {e}"))
         }
 
-        pub fn ingest(&self, constraints: SuggestIngestionConstraints) {
-            self.inner.ingest(constraints).unwrap();
+        pub fn ingest(&self, constraints: SuggestIngestionConstraints) -> SuggestIngestionMetrics {
+            self.inner.ingest(constraints).unwrap()
         }
 
         pub fn fetch_suggestions(&self, query: SuggestionQuery) -> Vec<Suggestion> {
@@ -1237,6 +1376,89 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn fuzzy_matching() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record("data", "1234", json!([los_pollos_amp(), california_wiki()]))
+                .with_icon(los_pollos_icon())
+                .with_icon(california_icon()),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        let fuzzy = |keyword: &str, config: FuzzyMatchingConfig| SuggestionQuery {
+            provider_constraints: Some(SuggestionProviderConstraints {
+                fuzzy_matching: Some(config),
+                ..SuggestionProviderConstraints::default()
+            }),
+            ..SuggestionQuery::all_providers(keyword)
+        };
+
+        // Typos only match when fuzzy matching is enabled.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::all_providers("los polos")),
+            vec![]
+        );
+        assert_eq!(
+            store.fetch_suggestions(fuzzy("los polos", FuzzyMatchingConfig::default())),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        assert_eq!(
+            store.fetch_suggestions(fuzzy("califrnia", FuzzyMatchingConfig::default())),
+            vec![california_suggestion("california")],
+        );
+        // Swapped characters are one typo.
+        assert_eq!(
+            store.fetch_suggestions(fuzzy("calfiornia", FuzzyMatchingConfig::default())),
+            vec![california_suggestion("california")],
+        );
+        // Too many typos.
+        assert_eq!(
+            store.fetch_suggestions(fuzzy("clifrnia", FuzzyMatchingConfig::default())),
+            vec![]
+        );
+        assert_eq!(
+            store.fetch_suggestions(fuzzy(
+                "clifrnia",
+                FuzzyMatchingConfig {
+                    max_edit_distance: 2,
+                    ..FuzzyMatchingConfig::default()
+                }
+            )),
+            vec![california_suggestion("california")],
+        );
+        // Too short.
+        assert_eq!(
+            store.fetch_suggestions(fuzzy("cak", FuzzyMatchingConfig::default())),
+            vec![]
+        );
+        // Not similar enough.
+        assert_eq!(
+            store.fetch_suggestions(fuzzy(
+                "clifrnia",
+                FuzzyMatchingConfig {
+                    max_edit_distance: 2,
+                    min_similarity_percent: 90,
+                    ..FuzzyMatchingConfig::default()
+                }
+            )),
+            vec![]
+        );
+        // Only the configured providers use it.
+        assert_eq!(
+            store.fetch_suggestions(fuzzy(
+                "califrnia",
+                FuzzyMatchingConfig {
+                    providers: vec![SuggestionProvider::Amp],
+                    ..FuzzyMatchingConfig::default()
+                }
+            )),
+            vec![]
+        );
+        Ok(())
+    }
+
     #[test]
     fn amp_fts_against_full_keywords() -> anyhow::Result<()> {
         before_each();
@@ -1536,6 +1758,63 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    /// Tests that record types are skipped when none of their records have
+    /// changed.
+    #[test]
+    fn ingest_skips_unchanged_record_types() -> anyhow::Result<()> {
+        before_each();
+
+        let mut store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record("data", "1234", json!([los_pollos_amp()]))
+                .with_icon(los_pollos_icon()),
+        );
+        let amp_stats = |metrics: SuggestIngestionMetrics| {
+            metrics
+                .record_type_stats
+                .into_iter()
+                .find(|stats| stats.record_type == SuggestRecordType::AmpWikipedia.to_string())
+                .map(|stats| {
+                    (
+                        stats.records_processed,
+                        stats.records_deleted,
+                        stats.skipped,
+                    )
+                })
+                .unwrap()
+        };
+
+        let metrics = store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(amp_stats(metrics), (1, 0, false));
+        let metrics = store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(amp_stats(metrics), (0, 0, true));
+
+        store
+            .client_mut()
+            .update_record("data", "1234", json!([good_place_eats_amp()]));
+        let metrics = store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(amp_stats(metrics), (1, 0, false));
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+
+        // Replacing a record with another is noticed too.
+        store
+            .client_mut()
+            .delete_record("quicksuggest", "1234")
+            .add_record("data", "5678", json!([los_pollos_amp()]));
+        let metrics = store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(amp_stats(metrics), (1, 1, false));
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos_suggestion("los", None)],
+        );
+
+        // `force_reingest()` reprocesses everything.
+        store.inner.force_reingest();
+        let metrics = store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(amp_stats(metrics), (1, 0, false));
+        Ok(())
+    }
+
     /// Tests ingestion when previously-ingested suggestions/icons have been deleted.
     #[test]
     fn ingest_with_deletions() -> anyhow::Result<()> {
@@ -2442,6 +2721,65 @@ pub(crate) mod tests {
         store.inner.clear_dismissed_suggestions()?;
         assert_eq!(store.fetch_suggestions(query.clone()).len(), 6);
 
+        // Dismissing by URL hash works too.
+        let results = store.fetch_suggestions(query.clone());
+        for result in &results {
+            let url_hash = crate::db::suggestion_url_hash(result.raw_url().unwrap())?;
+            store
+                .inner
+                .dismiss_suggestion_by_url_hash(url_hash.to_uppercase())?;
+        }
+        assert_eq!(store.fetch_suggestions(query.clone()).len(), 0);
+        store.inner.clear_dismissed_suggestions()?;
+        assert_eq!(store.fetch_suggestions(query.clone()).len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dismissed_suggestions_dont_count_towards_limit() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            "amo-suggestions",
+            "amo-1",
+            json!([
+                relay_amo().merge(json!({"keywords": ["cats"]})),
+                dark_mode_amo().merge(json!({"keywords": ["cats"]})),
+            ]),
+        ));
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let query = SuggestionQuery::amo("cats").limit(1);
+        let results = store.fetch_suggestions(query.clone());
+        assert_eq!(results.len(), 1);
+        let url_hash = crate::db::suggestion_url_hash(results[0].raw_url().unwrap())?;
+        store.inner.dismiss_suggestion_by_url_hash(url_hash)?;
+
+        // The other suggestion takes the dismissed one's place.
+        let next_results = store.fetch_suggestions(query);
+        assert_eq!(next_results.len(), 1);
+        assert_ne!(next_results[0].raw_url(), results[0].raw_url());
+        Ok(())
+    }
+
+    #[test]
+    fn dismissed_suggestions_filtered_for_all_providers() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            "fakespot-suggestions",
+            "fakespot-1",
+            json!([snowglobe_fakespot()]),
+        ));
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        let query = SuggestionQuery::fakespot("globe");
+        let results = store.fetch_suggestions(query.clone());
+        assert_eq!(results.len(), 1);
+        store
+            .inner
+            .dismiss_suggestion(results[0].raw_url().unwrap().to_string())?;
+        assert_eq!(store.fetch_suggestions(query), vec![]);
         Ok(())
     }
 
@@ -158,7 +158,8 @@ impl Suggestion {
             | Self::Wikipedia { url, .. }
             | Self::Amo { url, .. }
             | Self::Yelp { url, .. }
-            | Self::Mdn { url, .. } => Some(url),
+            | Self::Mdn { url, .. }
+            | Self::Fakespot { url, .. } => Some(url),
             _ => None,
         }
     }
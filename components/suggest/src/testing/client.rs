@@ -85,6 +85,13 @@ impl MockRemoteSettingsClient {
 
     // Non-Consuming Builder API, this is best for updating an existing client
 
+    /// Like Remote Settings, give each added or updated record a newer
+    /// `last_modified` time than all the others.
+    fn next_last_modified(&mut self) -> u64 {
+        self.last_modified_timestamp += 1;
+        self.last_modified_timestamp
+    }
+
     /// Add a record to the mock data
     ///
     /// A single record typically contains multiple items in the attachment data.  Pass all of them
@@ -103,9 +110,10 @@ impl MockRemoteSettingsClient {
         let icon_id = icon.id;
         let record_id = format!("icon-{icon_id}");
         let location = format!("icon-{icon_id}.png");
+        let last_modified = self.next_last_modified();
         self.records.push(Record {
             id: SuggestRecordId::new(record_id.to_string()),
-            last_modified: self.last_modified_timestamp,
+            last_modified,
             collection: Collection::Quicksuggest,
             attachment: Some(Attachment {
                 filename: location.clone(),
@@ -153,10 +161,11 @@ impl MockRemoteSettingsClient {
         items: Option<JsonValue>,
     ) -> &mut Self {
         let location = format!("{record_type}-{record_id}.json");
+        let last_modified = self.next_last_modified();
         self.records.push(Record {
             id: SuggestRecordId::new(record_id.to_string()),
             collection: record_type_for_str(record_type).collection(),
-            last_modified: self.last_modified_timestamp,
+            last_modified,
             payload: serde_json::from_value(
                 json!({
                     "type": record_type,
@@ -191,6 +200,7 @@ impl MockRemoteSettingsClient {
         record_id: &str,
         items: JsonValue,
     ) -> &mut Self {
+        let last_modified = self.next_last_modified();
         let record = self
             .records
             .iter_mut()
@@ -207,7 +217,7 @@ impl MockRemoteSettingsClient {
             )
             .unwrap_or_else(|| panic!("update_record: attachment not found for {record_id}"));
 
-        record.last_modified += 1;
+        record.last_modified = last_modified;
         record.payload = serde_json::from_value(json!({"type": record_type})).unwrap();
         *attachment_data = serde_json::to_vec(&items).expect("error serializing attachment data");
         self
@@ -217,6 +227,7 @@ impl MockRemoteSettingsClient {
     pub fn update_icon(&mut self, icon: MockIcon) -> &mut Self {
         let icon_id = &icon.id;
         let record_id = format!("icon-{icon_id}");
+        let last_modified = self.next_last_modified();
         let record = self
             .records
             .iter_mut()
@@ -233,7 +244,7 @@ impl MockRemoteSettingsClient {
             )
             .unwrap_or_else(|| panic!("update_icon: attachment not found for {icon_id}"));
 
-        record.last_modified += 1;
+        record.last_modified = last_modified;
         *attachment_data = icon.data.as_bytes().to_vec();
         self
     }
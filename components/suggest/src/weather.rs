@@ -99,6 +99,7 @@ impl SuggestDao<'_> {
                     .filter(|s| !s.is_empty())
             })
             .collect();
+        let words = self.strip_in_after_weather_keyword(words)?;
 
         let mut matches =
             // Step 2: Parse the query words into a list of token paths.
@@ -291,6 +292,28 @@ impl SuggestDao<'_> {
         }
     }
 
+    /// Removes an "in" that directly follows a full weather keyword at the
+    /// start of the query, so that "weather in new york" is parsed the same as
+    /// "weather new york". The "in" is kept if nothing follows it, since it may
+    /// be the start of a place name.
+    fn strip_in_after_weather_keyword<'a>(&self, mut words: Vec<&'a str>) -> Result<Vec<&'a str>> {
+        let max_i = std::cmp::min(
+            self.weather_cache().max_keyword_word_count,
+            words.len().saturating_sub(2),
+        );
+        for i in 1..=max_i {
+            if words[i] == "in"
+                && !self
+                    .match_weather_keywords(&words[..i].join(" "), false)?
+                    .is_empty()
+            {
+                words.remove(i);
+                break;
+            }
+        }
+        Ok(words)
+    }
+
     fn match_weather_keywords(&self, candidate: &str, prefix: bool) -> Result<Vec<String>> {
         self.conn.query_rows_and_then_cached(
             r#"
@@ -1390,6 +1413,27 @@ mod tests {
                 "new york , ny weather",
                 vec![geoname::tests::nyc().into()],
             ),
+            (
+                "weather in waco",
+                vec![geoname::tests::waco().into()],
+            ),
+            (
+                "weather in new york",
+                vec![geoname::tests::nyc().into()],
+            ),
+            (
+                "weather in new york ny",
+                vec![geoname::tests::nyc().into()],
+            ),
+            (
+                // "in" is only skipped after a full weather keyword.
+                "weath in waco",
+                vec![],
+            ),
+            (
+                "waco in weather",
+                vec![],
+            ),
             (
                 &format!("{} weather", geoname::tests::LONG_NAME),
                 vec![geoname::tests::long_name_city().into()],
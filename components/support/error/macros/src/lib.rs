@@ -91,6 +91,7 @@ fn impl_handle_error(
         let mut new_fn = item_fn.clone();
         new_fn.block = parse_quote! {
             {
+                ::error_support::clear_last_error_details();
                 (|| -> ::std::result::Result<_, #err_path> {
                     #original_body
                 })().map_err(::error_support::convert_log_report_error)
@@ -107,3 +108,95 @@ fn impl_handle_error(
         ))
     }
 }
+
+/// A procedural macro that catches panics in a function returning a `Result`, converting them
+/// into the function's error type rather than letting them unwind into the consuming
+/// application, which would crash it. The error type must implement
+/// [`error_support::FromPanic`].
+///
+/// Like `handle_error`, the panic is logged, reported using the external error reporter and
+/// recorded for `error_support::get_last_error_details()`. The two macros can be combined, in
+/// which case `handle_panics` should come first.
+///
+/// # Example
+/// ```ignore
+/// use error_support::{handle_panics, FromPanic};
+///
+/// #[derive(Debug, thiserror::Error)]
+/// enum ExternalError {
+///     #[error("Unexpected error: {reason}")]
+///     UnexpectedError { reason: String },
+/// }
+///
+/// impl FromPanic for ExternalError {
+///     fn from_panic(message: String) -> Self {
+///         ExternalError::UnexpectedError { reason: message }
+///     }
+/// }
+///
+/// #[handle_panics]
+/// fn do_something() -> std::result::Result<String, ExternalError> {
+///     panic!("Oh no!")
+/// }
+///
+/// // The panic is returned as an `ExternalError::UnexpectedError`.
+/// let _: ExternalError = do_something().unwrap_err();
+/// ```
+#[proc_macro_attribute]
+pub fn handle_panics(args: TokenStream, input: TokenStream) -> TokenStream {
+    if !args.is_empty() {
+        return syn::Error::new(
+            proc_macro2::TokenStream::from(args).span(),
+            "Expected #[handle_panics]",
+        )
+        .to_compile_error()
+        .into();
+    }
+    TokenStream::from(
+        match syn::parse::<syn::Item>(input).and_then(|parsed| impl_handle_panics(&parsed)) {
+            Ok(res) => res,
+            Err(e) => e.to_compile_error(),
+        },
+    )
+}
+
+fn impl_handle_panics(input: &syn::Item) -> syn::Result<proc_macro2::TokenStream> {
+    let syn::Item::Fn(item_fn) = input else {
+        return Err(syn::Error::new(
+            input.span(),
+            "#[handle_panics] can only be used on functions",
+        ));
+    };
+    if let Some(asyncness) = &item_fn.sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "#[handle_panics] can't be used on async functions",
+        ));
+    }
+    let syn::ReturnType::Type(_, return_type) = &item_fn.sig.output else {
+        return Err(syn::Error::new(
+            item_fn.sig.span(),
+            "#[handle_panics] can only be used on functions returning a `Result`",
+        ));
+    };
+    let original_body = &item_fn.block;
+
+    let mut new_fn = item_fn.clone();
+    new_fn.block = parse_quote! {
+        {
+            ::error_support::clear_last_error_details();
+            match ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(
+                || -> #return_type #original_body
+            )) {
+                ::std::result::Result::Ok(result) => result,
+                ::std::result::Result::Err(payload) => ::std::result::Result::Err(
+                    ::error_support::convert_panic(payload, ::std::module_path!()),
+                ),
+            }
+        }
+    };
+
+    Ok(quote! {
+        #new_fn
+    })
+}
@@ -0,0 +1,101 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A trail of recent breadcrumbs for each component.
+//!
+//! Breadcrumbs are sent to the application's error reporter as they happen, but whether they end
+//! up next to a later error report depends on the reporting system. So we also keep the last few
+//! breadcrumbs logged by each component, and send them along with the errors reported on its
+//! behalf. They're kept separate from the error message, so that reports of the same error can
+//! still be grouped together.
+
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+/// The number of breadcrumbs kept for each component.
+const MAX_BREADCRUMBS_PER_COMPONENT: usize = 20;
+
+struct Breadcrumb {
+    time: SystemTime,
+    message: String,
+    module: String,
+    line: u32,
+}
+
+lazy_static::lazy_static! {
+    // Keyed by component (ie, crate) name.
+    static ref BREADCRUMB_TRAILS: Mutex<HashMap<String, VecDeque<Breadcrumb>>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the component that `module` is in, from its module path.
+pub(crate) fn component_for_module(module: &str) -> &str {
+    module.split("::").next().unwrap_or(module)
+}
+
+pub(crate) fn record_breadcrumb(message: &str, module: &str, line: u32) {
+    let mut trails = BREADCRUMB_TRAILS.lock();
+    let trail = trails
+        .entry(component_for_module(module).to_string())
+        .or_default();
+    if trail.len() == MAX_BREADCRUMBS_PER_COMPONENT {
+        trail.pop_front();
+    }
+    trail.push_back(Breadcrumb {
+        time: SystemTime::now(),
+        message: message.to_string(),
+        module: module.to_string(),
+        line,
+    });
+}
+
+/// Returns the recent breadcrumbs for the component that `module` is in, oldest first. Each is
+/// labelled with how long before now it was logged.
+pub(crate) fn breadcrumb_trail(module: &str) -> Vec<String> {
+    let trails = BREADCRUMB_TRAILS.lock();
+    let Some(trail) = trails.get(component_for_module(module)) else {
+        return Vec::new();
+    };
+    let now = SystemTime::now();
+    trail
+        .iter()
+        .map(|breadcrumb| {
+            // The clock may have gone backwards, in which case just say it was now.
+            let age = now
+                .duration_since(breadcrumb.time)
+                .unwrap_or(Duration::ZERO);
+            format!(
+                "[-{:.3}s] {} ({}:{})",
+                age.as_secs_f64(),
+                breadcrumb.message,
+                breadcrumb.module,
+                breadcrumb.line,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breadcrumb_trail() {
+        // Other tests may log breadcrumbs, so use components of our own.
+        assert!(breadcrumb_trail("trail_test_a::db").is_empty());
+
+        for i in 0..25 {
+            record_breadcrumb(&format!("step {i}"), "trail_test_a::db", i);
+        }
+        record_breadcrumb("unrelated", "trail_test_b", 1);
+
+        let crumbs = breadcrumb_trail("trail_test_a::store");
+        assert_eq!(crumbs.len(), MAX_BREADCRUMBS_PER_COMPONENT);
+        // The oldest breadcrumbs were dropped.
+        assert!(crumbs[0].starts_with("[-"));
+        assert!(crumbs[0].ends_with("] step 5 (trail_test_a::db:5)"));
+        assert!(crumbs[19].ends_with("] step 24 (trail_test_a::db:24)"));
+        assert!(!crumbs.iter().any(|crumb| crumb.contains("unrelated")));
+    }
+}
@@ -0,0 +1,128 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Structured details about the last error returned to the application.
+//!
+//! Each component exposes its own error type, which foreign code usually only gets a message
+//! from. Errors converted by `handle_error` also record an [`AppServicesError`] describing them
+//! in a shared form, which the application can fetch with [`get_last_error_details`] right after
+//! catching an error, to decide what to do without matching on the message.
+//!
+//! The details are per-thread, so must be fetched on the thread which made the failing call. Each
+//! call to a function using `handle_error` or `handle_panics` clears them first, so they only
+//! ever describe the most recent call, and are `None` if it succeeded. Async functions may finish
+//! on a different thread than the caller's, so there are no reliable details after an async call.
+
+use std::cell::RefCell;
+
+/// A broad classification of an error.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// A network request failed, or the server returned an error.
+    Network,
+    /// The user needs to re-authenticate.
+    Authentication,
+    /// The component's database or files couldn't be read or written.
+    Storage,
+    /// The application passed an invalid argument.
+    InvalidInput,
+    /// The requested item doesn't exist.
+    NotFound,
+    /// The operation was interrupted, eg by shutdown.
+    Interrupted,
+    /// Anything else.
+    #[default]
+    Internal,
+}
+
+/// Structured details about an error returned by a component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppServicesError {
+    /// The crate that returned the error, eg "logins".
+    pub component: String,
+    pub code: ErrorCode,
+    /// Whether retrying the same call later might succeed.
+    pub retryable: bool,
+    pub message: String,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<AppServicesError>> = const { RefCell::new(None) };
+}
+
+/// Returns the details of the last error returned on this thread, if any.
+pub fn get_last_error_details() -> Option<AppServicesError> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+/// Forget the details of the last error returned on this thread.
+/// Called by our `handle_error` and `handle_panics` macros so needs to be public.
+pub fn clear_last_error_details() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+pub(crate) fn set_last_error_details(details: AppServicesError) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(details));
+}
+
+/// Returns the name of the crate which defines `T`, from its type name.
+pub(crate) fn component_name<T: ?Sized>() -> &'static str {
+    let type_name = std::any::type_name::<T>();
+    type_name.split("::").next().unwrap_or(type_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert_log_report_error, ErrorHandling, GetErrorHandling};
+
+    #[derive(Debug)]
+    struct InternalError;
+
+    impl std::fmt::Display for InternalError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "internal: offline")
+        }
+    }
+
+    impl std::error::Error for InternalError {}
+
+    #[derive(Debug)]
+    struct ExternalError;
+
+    impl std::fmt::Display for ExternalError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "external: offline")
+        }
+    }
+
+    impl std::error::Error for ExternalError {}
+
+    impl GetErrorHandling for InternalError {
+        type ExternalError = ExternalError;
+
+        fn get_error_handling(&self) -> ErrorHandling<ExternalError> {
+            ErrorHandling::convert(ExternalError)
+                .code(ErrorCode::Network)
+                .retryable()
+        }
+    }
+
+    #[test]
+    fn test_last_error_details() {
+        assert_eq!(get_last_error_details(), None);
+        let _: ExternalError = convert_log_report_error(InternalError);
+        assert_eq!(
+            get_last_error_details(),
+            Some(AppServicesError {
+                component: "error_support".into(),
+                code: ErrorCode::Network,
+                retryable: true,
+                message: "external: offline".into(),
+            })
+        );
+        clear_last_error_details();
+        assert_eq!(get_last_error_details(), None);
+    }
+}
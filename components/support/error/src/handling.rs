@@ -4,6 +4,9 @@
 
 //! Helpers for components to "handle" errors.
 
+use crate::details::{self, AppServicesError, ErrorCode};
+use std::any::Any;
+
 /// Describes what error reporting action should be taken.
 #[derive(Debug, Default)]
 pub struct ErrorReporting {
@@ -20,6 +23,10 @@ pub struct ErrorHandling<E> {
     pub err: E,
     /// How the error should be reported.
     pub reporting: ErrorReporting,
+    /// The classification recorded in the error's [`AppServicesError`] details.
+    pub code: ErrorCode,
+    /// Whether retrying the call later might succeed.
+    pub retryable: bool,
 }
 
 impl<E> ErrorHandling<E> {
@@ -31,28 +38,43 @@ impl<E> ErrorHandling<E> {
         Self {
             err,
             reporting: ErrorReporting::default(),
+            code: ErrorCode::default(),
+            retryable: false,
         }
     }
 
     /// Add logging to an ErrorHandling instance
     pub fn log(self, level: log::Level) -> Self {
         Self {
-            err: self.err,
             reporting: ErrorReporting {
                 log_level: Some(level),
                 ..self.reporting
             },
+            ..self
         }
     }
 
     /// Add reporting to an ErrorHandling instance
     pub fn report(self, report_class: impl Into<String>) -> Self {
         Self {
-            err: self.err,
             reporting: ErrorReporting {
                 report_class: Some(report_class.into()),
                 ..self.reporting
             },
+            ..self
+        }
+    }
+
+    /// Set the classification recorded in the error's details
+    pub fn code(self, code: ErrorCode) -> Self {
+        Self { code, ..self }
+    }
+
+    /// Mark the error as one where retrying the call later might succeed
+    pub fn retryable(self) -> Self {
+        Self {
+            retryable: true,
+            ..self
         }
     }
 
@@ -71,11 +93,11 @@ impl<E> ErrorHandling<E> {
     /// Add reporting to an ErrorHandling instance and also log an Error
     pub fn report_error(self, report_class: impl Into<String>) -> Self {
         Self {
-            err: self.err,
             reporting: ErrorReporting {
                 log_level: Some(log::Level::Error),
                 report_class: Some(report_class.into()),
             },
+            ..self
         }
     }
 }
@@ -89,7 +111,8 @@ pub trait GetErrorHandling {
 }
 
 /// Handle the specified "internal" error, taking any logging or error
-/// reporting actions and converting the error to the public error.
+/// reporting actions, recording its details for `get_last_error_details()`
+/// and converting the error to the public error.
 /// Called by our `handle_error` macro so needs to be public.
 pub fn convert_log_report_error<IE, EE>(e: IE) -> EE
 where
@@ -97,6 +120,13 @@ where
     EE: std::error::Error,
 {
     let handling = e.get_error_handling();
+    let component = details::component_name::<IE>();
+    details::set_last_error_details(AppServicesError {
+        component: component.to_string(),
+        code: handling.code,
+        retryable: handling.retryable,
+        message: handling.err.to_string(),
+    });
     let reporting = handling.reporting;
     if let Some(level) = reporting.log_level {
         match &reporting.report_class {
@@ -109,7 +139,38 @@ where
         // XXX - should we arrange for the `report_class` to have the
         // original crate calling this as a prefix, or will we still be
         // able to identify that?
-        crate::report_error_to_app(report_class, e.to_string());
+        crate::report_component_error_to_app(component, report_class, e.to_string());
     }
     handling.err
 }
+
+/// Implemented by public errors which can represent an unexpected panic, so that functions using
+/// our `handle_panics` macro can return one instead of unwinding into foreign code.
+pub trait FromPanic {
+    /// Create the error from the panic message.
+    fn from_panic(message: String) -> Self;
+}
+
+/// Handle a panic in a function in `module`, logging and reporting it, recording its details for
+/// `get_last_error_details()` and converting it to the public error.
+/// Called by our `handle_panics` macro so needs to be public.
+pub fn convert_panic<EE: FromPanic>(payload: Box<dyn Any + Send>, module: &str) -> EE {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic".to_string()
+    };
+    let component = crate::breadcrumbs::component_for_module(module);
+    log::error!("{component}: caught panic: {message}");
+    // The component's recent breadcrumbs are sent with the report.
+    crate::report_component_error_to_app(module, format!("{component}-panic"), message.clone());
+    details::set_last_error_details(AppServicesError {
+        component: component.to_string(),
+        code: ErrorCode::Internal,
+        retryable: false,
+        message: message.clone(),
+    });
+    EE::from_panic(message)
+}
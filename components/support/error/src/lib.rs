@@ -27,16 +27,32 @@ pub mod backtrace {
 mod redact;
 pub use redact::*;
 
+mod breadcrumbs;
+
+mod limits;
+pub use limits::{set_default_error_report_limits, set_error_report_limits, ErrorReportLimits};
+
 mod reporting;
 pub use reporting::{
-    report_breadcrumb, report_error_to_app, set_application_error_reporter,
-    unset_application_error_reporter, ApplicationErrorReporter,
+    report_breadcrumb, report_component_error_to_app, report_error_to_app,
+    set_application_error_reporter, unset_application_error_reporter, ApplicationErrorReporter,
 };
 
-pub use error_support_macros::handle_error;
+pub use error_support_macros::{handle_error, handle_panics};
+
+mod details;
+pub use details::{clear_last_error_details, get_last_error_details, AppServicesError, ErrorCode};
+
+mod operation;
+pub use operation::{
+    begin_operation, current_operation_id, end_operation, enter_operation, OperationScope,
+};
 
 mod handling;
-pub use handling::{convert_log_report_error, ErrorHandling, ErrorReporting, GetErrorHandling};
+pub use handling::{
+    convert_log_report_error, convert_panic, ErrorHandling, ErrorReporting, FromPanic,
+    GetErrorHandling,
+};
 
 /// XXX - Most of this is now considered deprecated - only FxA uses it, and
 /// should be replaced with the facilities in the `handling` module.
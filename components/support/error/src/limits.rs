@@ -0,0 +1,161 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Rate limiting and sampling for error reports.
+//!
+//! Some errors, like network failures during sync, can happen many times in quick succession and
+//! flood the error reporter. The application can limit how many reports of each kind (ie, each
+//! `type_name`) are sent. When reports are dropped, the next report of that kind that is sent
+//! says how many were.
+//!
+//! There are no limits by default.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Limits on how often reports of an error kind are sent to the error reporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorReportLimits {
+    /// Only send one of every `sample_one_in` reports. 1 sends every report.
+    pub sample_one_in: u32,
+    /// The most reports to send in each interval, after sampling.
+    pub max_reports_per_interval: u32,
+    /// The length of the interval for `max_reports_per_interval`, in seconds.
+    pub interval_secs: u64,
+}
+
+#[derive(Default)]
+struct KindState {
+    occurrences: u64,
+    interval_start: Option<Instant>,
+    sent_in_interval: u32,
+    suppressed: u64,
+}
+
+#[derive(Default)]
+struct Limiter {
+    default_limits: Option<ErrorReportLimits>,
+    kind_limits: HashMap<String, ErrorReportLimits>,
+    kinds: HashMap<String, KindState>,
+}
+
+impl Limiter {
+    /// Decides whether to send a report of `type_name`. If it should be sent, returns the number
+    /// of reports of that kind which were suppressed since the last one sent.
+    fn check(&mut self, type_name: &str, now: Instant) -> Option<u64> {
+        let limits = match self
+            .kind_limits
+            .get(type_name)
+            .or(self.default_limits.as_ref())
+        {
+            Some(limits) => limits,
+            None => return Some(self.kinds.remove(type_name).map_or(0, |s| s.suppressed)),
+        };
+        let state = self.kinds.entry(type_name.to_string()).or_default();
+        state.occurrences += 1;
+        // Send the first occurrence, then every `sample_one_in`th one after.
+        if (state.occurrences - 1) % u64::from(limits.sample_one_in.max(1)) != 0 {
+            state.suppressed += 1;
+            return None;
+        }
+        let interval = Duration::from_secs(limits.interval_secs);
+        match state.interval_start {
+            Some(start) if now.duration_since(start) < interval => (),
+            _ => {
+                state.interval_start = Some(now);
+                state.sent_in_interval = 0;
+            }
+        }
+        if state.sent_in_interval >= limits.max_reports_per_interval {
+            state.suppressed += 1;
+            return None;
+        }
+        state.sent_in_interval += 1;
+        Some(std::mem::take(&mut state.suppressed))
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LIMITER: Mutex<Limiter> = Mutex::new(Limiter::default());
+}
+
+/// Set the limits for error kinds which don't have their own, or `None` to not limit them.
+pub fn set_default_error_report_limits(limits: Option<ErrorReportLimits>) {
+    LIMITER.lock().default_limits = limits;
+}
+
+/// Set the limits for reports with `type_name`, or `None` to use the default limits.
+pub fn set_error_report_limits(type_name: String, limits: Option<ErrorReportLimits>) {
+    let mut limiter = LIMITER.lock();
+    match limits {
+        Some(limits) => limiter.kind_limits.insert(type_name, limits),
+        None => limiter.kind_limits.remove(&type_name),
+    };
+}
+
+/// Decides whether to send a report of `type_name`. If it should be sent, returns the number of
+/// reports of that kind which were suppressed since the last one sent.
+pub(crate) fn check_error_report(type_name: &str) -> Option<u64> {
+    LIMITER.lock().check(type_name, Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_count(limiter: &mut Limiter, type_name: &str, times: u32, now: Instant) -> u32 {
+        (0..times)
+            .filter(|_| limiter.check(type_name, now).is_some())
+            .count() as u32
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let mut limiter = Limiter::default();
+        assert_eq!(send_count(&mut limiter, "kind", 100, Instant::now()), 100);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = Limiter {
+            default_limits: Some(ErrorReportLimits {
+                sample_one_in: 1,
+                max_reports_per_interval: 2,
+                interval_secs: 60,
+            }),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        assert_eq!(limiter.check("kind", start), Some(0));
+        assert_eq!(limiter.check("kind", start), Some(0));
+        assert_eq!(send_count(&mut limiter, "kind", 5, start), 0);
+        // Other kinds have their own limit.
+        assert_eq!(limiter.check("other", start), Some(0));
+        // The next report after the interval says how many were dropped.
+        let later = start + Duration::from_secs(61);
+        assert_eq!(limiter.check("kind", later), Some(5));
+        assert_eq!(limiter.check("kind", later), Some(0));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let mut limiter = Limiter::default();
+        limiter.kind_limits.insert(
+            "noisy".to_string(),
+            ErrorReportLimits {
+                sample_one_in: 10,
+                max_reports_per_interval: u32::MAX,
+                interval_secs: 60,
+            },
+        );
+        let now = Instant::now();
+        assert_eq!(limiter.check("noisy", now), Some(0));
+        assert_eq!(send_count(&mut limiter, "noisy", 9, now), 0);
+        assert_eq!(limiter.check("noisy", now), Some(9));
+        assert_eq!(send_count(&mut limiter, "noisy", 90, now), 9);
+        // Kinds without their own limits aren't affected.
+        assert_eq!(send_count(&mut limiter, "quiet", 10, now), 10);
+    }
+}
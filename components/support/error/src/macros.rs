@@ -21,7 +21,11 @@ macro_rules! report_error {
     ($type_name:expr, $($arg:tt)*) => {
         let message = std::format!($($arg)*);
         ::log::warn!("report {}: {}", $type_name, message);
-        $crate::report_error_to_app($type_name.to_string(), message.to_string());
+        $crate::report_component_error_to_app(
+            std::module_path!(),
+            $type_name.to_string(),
+            message.to_string(),
+        );
     };
 }
 
@@ -44,7 +48,8 @@ macro_rules! trace_error {
 /// Tell the application to log a breadcrumb
 ///
 /// Breadcrumbs are log-like entries that get tracked by the error reporting system.  When we
-/// report an error, recent breadcrumbs will be associated with it.
+/// report an error, recent breadcrumbs will be associated with it.  We also keep the last few
+/// breadcrumbs from each component, and include them in the message of errors it reports.
 #[macro_export]
 macro_rules! breadcrumb {
     ($($arg:tt)*) => {
@@ -0,0 +1,99 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Operation ids, for correlating a user action with everything it triggers.
+//!
+//! The embedder (or a component like the sync manager) opens an operation
+//! scope with an id before calling into our components. Until the scope ends,
+//! errors and breadcrumbs reported to the app, forwarded log records and
+//! viaduct request events all carry the id.
+//!
+//! Scopes are per-thread: they only apply to calls made on the thread that
+//! opened them. Scopes can be nested, in which case the innermost id is used.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+thread_local! {
+    static OPERATION_IDS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// An open operation scope, which ends when dropped.
+#[must_use = "the operation scope ends when this is dropped"]
+pub struct OperationScope {
+    // Scopes must be dropped on the thread that opened them.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        end_operation();
+    }
+}
+
+/// Opens an operation scope with `id` on the current thread.
+pub fn enter_operation(id: impl Into<String>) -> OperationScope {
+    begin_operation(id.into());
+    OperationScope {
+        _not_send: PhantomData,
+    }
+}
+
+/// Returns the id of the innermost operation scope open on the current thread.
+pub fn current_operation_id() -> Option<String> {
+    OPERATION_IDS.with(|ids| ids.borrow().last().cloned())
+}
+
+/// Opens an operation scope with `id` on the current thread. This is the
+/// foreign-language equivalent of [`enter_operation`]; each call must be
+/// matched by a call to [`end_operation`] on the same thread.
+pub fn begin_operation(id: String) {
+    OPERATION_IDS.with(|ids| ids.borrow_mut().push(id));
+}
+
+/// Ends the innermost operation scope open on the current thread.
+pub fn end_operation() {
+    OPERATION_IDS.with(|ids| {
+        if ids.borrow_mut().pop().is_none() {
+            log::warn!("end_operation() called without an open operation");
+        }
+    });
+}
+
+/// Appends the current operation id, if any, to `message`.
+pub(crate) fn tag_with_operation_id(message: String) -> String {
+    match current_operation_id() {
+        Some(id) => format!("{message} [operation: {id}]"),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_scopes() {
+        assert_eq!(current_operation_id(), None);
+        {
+            let _outer = enter_operation("outer");
+            assert_eq!(current_operation_id().as_deref(), Some("outer"));
+            {
+                let _inner = enter_operation("inner");
+                assert_eq!(current_operation_id().as_deref(), Some("inner"));
+                assert_eq!(
+                    tag_with_operation_id("failed".to_string()),
+                    "failed [operation: inner]"
+                );
+            }
+            assert_eq!(current_operation_id().as_deref(), Some("outer"));
+            // Scopes don't leak across threads.
+            std::thread::spawn(|| assert_eq!(current_operation_id(), None))
+                .join()
+                .unwrap();
+        }
+        assert_eq!(current_operation_id(), None);
+        assert_eq!(tag_with_operation_id("failed".to_string()), "failed");
+    }
+}
@@ -31,8 +31,10 @@ fn get_breadcrumb_counter_value() -> u32 {
 pub trait ApplicationErrorReporter: Sync + Send {
     /// Send an error report to a Sentry-like error reporting system
     ///
-    /// type_name should be used to group errors together
-    fn report_error(&self, type_name: String, message: String);
+    /// type_name should be used to group errors together. `breadcrumbs` are the recent
+    /// breadcrumbs from the component reporting the error, oldest first, which should be attached
+    /// to the report without changing how it's grouped.
+    fn report_error(&self, type_name: String, message: String, breadcrumbs: Vec<String>);
     /// Send a breadcrumb to a Sentry-like error reporting system
     fn report_breadcrumb(&self, message: String, module: String, line: u32, column: u32);
 }
@@ -40,7 +42,7 @@ pub trait ApplicationErrorReporter: Sync + Send {
 // ApplicationErrorReporter to use if the app doesn't set one
 struct DefaultApplicationErrorReporter;
 impl ApplicationErrorReporter for DefaultApplicationErrorReporter {
-    fn report_error(&self, _type_name: String, _message: String) {}
+    fn report_error(&self, _type_name: String, _message: String, _breadcrumbs: Vec<String>) {}
     fn report_breadcrumb(&self, _message: String, _module: String, _line: u32, _column: u32) {}
 }
 
@@ -58,13 +60,45 @@ pub fn unset_application_error_reporter() {
 }
 
 pub fn report_error_to_app(type_name: String, message: String) {
+    let Some(suppressed) = crate::limits::check_error_report(&type_name) else {
+        return;
+    };
+    let message = crate::operation::tag_with_operation_id(message);
+    send_error_report(type_name, message, Vec::new(), suppressed);
+}
+
+/// Like `report_error_to_app()`, but also attaches the recent breadcrumbs from the component that
+/// `module` is in.
+/// Called by our `report_error` macro so needs to be public.
+pub fn report_component_error_to_app(module: &str, type_name: String, message: String) {
+    let Some(suppressed) = crate::limits::check_error_report(&type_name) else {
+        return;
+    };
+    let message = crate::operation::tag_with_operation_id(message);
+    let breadcrumbs = crate::breadcrumbs::breadcrumb_trail(module);
+    send_error_report(type_name, message, breadcrumbs, suppressed);
+}
+
+fn send_error_report(
+    type_name: String,
+    message: String,
+    breadcrumbs: Vec<String>,
+    suppressed: u64,
+) {
+    let message = if suppressed > 0 {
+        format!("{message}\n\n({suppressed} earlier reports of this error were suppressed)")
+    } else {
+        message
+    };
     APPLICATION_ERROR_REPORTER
         .read()
-        .report_error(type_name, message);
+        .report_error(type_name, message, breadcrumbs);
 }
 
 pub fn report_breadcrumb(message: String, module: String, line: u32, column: u32) {
+    crate::breadcrumbs::record_breadcrumb(&message, &module, line);
     let message = format!("{} ({})", message, get_breadcrumb_counter_value());
+    let message = crate::operation::tag_with_operation_id(message);
     APPLICATION_ERROR_REPORTER
         .read()
         .report_breadcrumb(message, module, line, column);
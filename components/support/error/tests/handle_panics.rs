@@ -0,0 +1,75 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use error_support::{
+    get_last_error_details, handle_panics, report_breadcrumb, ErrorCode, FromPanic,
+};
+
+#[derive(Debug)]
+enum ExternalError {
+    UnexpectedError { reason: String },
+    Other,
+}
+
+impl FromPanic for ExternalError {
+    fn from_panic(message: String) -> Self {
+        ExternalError::UnexpectedError { reason: message }
+    }
+}
+
+#[handle_panics]
+fn func(fail: bool) -> ::std::result::Result<u32, ExternalError> {
+    if fail {
+        return Err(ExternalError::Other);
+    }
+    Ok(1)
+}
+
+#[handle_panics]
+fn func_panics(record: u32) -> ::std::result::Result<u32, ExternalError> {
+    report_breadcrumb(
+        format!("processing record {record}"),
+        module_path!().to_string(),
+        line!(),
+        column!(),
+    );
+    panic!("Bad record {record}")
+}
+
+struct Store;
+
+impl Store {
+    #[handle_panics]
+    fn method(&self, s: &str) -> ::std::result::Result<String, ExternalError> {
+        if s.is_empty() {
+            panic!("Empty string");
+        }
+        Ok(s.to_string())
+    }
+}
+
+fn main() {
+    std::panic::set_hook(Box::new(|_| {}));
+
+    assert!(matches!(func(false), Ok(1)));
+    assert!(matches!(func(true), Err(ExternalError::Other)));
+
+    match func_panics(42) {
+        Err(ExternalError::UnexpectedError { reason }) => {
+            // The breadcrumbs are sent with the error report, not included in the error.
+            assert_eq!(reason, "Bad record 42");
+        }
+        r => panic!("Unexpected result: {r:?}"),
+    }
+    assert_eq!(get_last_error_details().unwrap().code, ErrorCode::Internal);
+    // The next call clears the details, so they don't describe an earlier failure.
+    assert!(matches!(func(false), Ok(1)));
+    assert_eq!(get_last_error_details(), None);
+
+    assert_eq!(Store.method("a").unwrap(), "a");
+    assert!(matches!(
+        Store.method(""),
+        Err(ExternalError::UnexpectedError { .. })
+    ));
+}
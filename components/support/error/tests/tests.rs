@@ -6,6 +6,7 @@
 fn tests() {
     let t = trybuild::TestCases::new();
     t.pass("parse.rs");
+    t.pass("handle_panics.rs");
     t.compile_fail("returns_not_result.rs");
     t.compile_fail("returns_result_but_not_error.rs");
     t.compile_fail("returns_result_but_incorrect_error.rs");
@@ -212,3 +212,43 @@ fn test_exclamation_mark() -> Result<()> {
     assert!(v1 < v2 && v1 < v3 && v1 < v4);
     Ok(())
 }
+
+#[test]
+fn test_version_parts_compare_numerically() -> Result<()> {
+    assert!(Version::try_from("9.10")? > Version::try_from("9.9")?);
+    assert!(Version::try_from("100.0")? > Version::try_from("99.0")?);
+    assert!(Version::try_from("100.0.1")? > Version::try_from("100.0")?);
+    assert!(Version::try_from("100.0")? == Version::try_from("100")?);
+    Ok(())
+}
+
+#[test]
+fn test_pre_release_versions() -> Result<()> {
+    // Nightly and beta versions are older than the release they lead up to.
+    let nightly = Version::try_from("100.0a1")?;
+    let beta = Version::try_from("100.0b2")?;
+    let release = Version::try_from("100.0")?;
+    assert!(nightly < beta);
+    assert!(beta < release);
+    assert!(Version::try_from("100.0b10")? > Version::try_from("100.0b9")?);
+    assert!(Version::try_from("100.0a1")? > Version::try_from("99.0")?);
+    Ok(())
+}
+
+#[test]
+fn test_version_compare_transform() -> Result<()> {
+    use firefox_versioning::compare::version_compare;
+    use serde_json::json;
+
+    assert_eq!(version_compare(&[json!("9.10"), json!("9.9")])?, json!(1));
+    assert_eq!(version_compare(&[json!("100.0"), json!("100")])?, json!(0));
+    assert_eq!(
+        version_compare(&[json!("100.0b1"), json!("100.0")])?,
+        json!(-1)
+    );
+
+    assert!(version_compare(&[json!("100.0")]).is_err());
+    assert!(version_compare(&[json!(100), json!("100.0")]).is_err());
+    assert!(version_compare(&[json!("100.0"), json!(null)]).is_err());
+    Ok(())
+}
@@ -4,6 +4,7 @@
 
 #[derive(Debug, thiserror::Error)]
 pub enum ErrorKind {
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("NSS error: {0}")]
     NSSError(#[from] nss::Error),
     #[error("Internal crypto error")]
@@ -30,9 +31,17 @@ pub enum ErrorKind {
     SignatureMismatchError(String),
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 error_support::define_error! {
     ErrorKind {
         (ConversionError, std::num::TryFromIntError),
         (NSSError, nss::Error),
     }
 }
+
+#[cfg(target_arch = "wasm32")]
+error_support::define_error! {
+    ErrorKind {
+        (ConversionError, std::num::TryFromIntError),
+    }
+}
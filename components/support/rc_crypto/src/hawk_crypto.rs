@@ -154,4 +154,48 @@ mod test {
             ]
         );
     }
+
+    // Checks that a `Server-Authorization` header, covering the response
+    // payload, round-trips through our cryptographer.
+    #[test]
+    fn test_hawk_response_validation() {
+        crate::ensure_initialized();
+        let credentials = hawk::Credentials {
+            id: "dh37fgj492je".to_string(),
+            key: hawk::Key::new("werxhqb98rpaxn39848xrunpaw3489ruxnpa98w4rxn", hawk::SHA256)
+                .unwrap(),
+        };
+        let request_hash =
+            hawk::PayloadHasher::hash("application/json", hawk::SHA256, "{}").unwrap();
+        let request = hawk::RequestBuilder::new("POST", "example.com", 443, "/v1/account")
+            .hash(&request_hash[..])
+            .request();
+        let request_header = request.make_header(&credentials).unwrap();
+
+        let response_hash =
+            hawk::PayloadHasher::hash("application/json", hawk::SHA256, r#"{"ok":true}"#).unwrap();
+        let server_header = request
+            .make_response_builder(&request_header)
+            .hash(&response_hash[..])
+            .response()
+            .make_header(&credentials.key)
+            .unwrap();
+        // The client only sees the header as a string.
+        let server_header: hawk::Header = server_header.to_string().parse().unwrap();
+
+        let response = request
+            .make_response_builder(&request_header)
+            .hash(&response_hash[..])
+            .response();
+        assert!(response.validate_header(&server_header, &credentials.key));
+
+        // A tampered payload doesn't validate.
+        let tampered_hash =
+            hawk::PayloadHasher::hash("application/json", hawk::SHA256, r#"{"ok":false}"#).unwrap();
+        let response = request
+            .make_response_builder(&request_header)
+            .hash(&tampered_hash[..])
+            .response();
+        assert!(!response.validate_header(&server_header, &credentials.key));
+    }
 }
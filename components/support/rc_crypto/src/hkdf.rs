@@ -64,6 +64,45 @@ mod tests {
         assert_eq!(out, expected_out);
     }
 
+    // Test cases 1 and 3 from RFC 5869, checking the extract and expand steps
+    // separately.
+    #[test]
+    fn hkdf_extract_and_expand_match_rfc5869() {
+        struct TestCase {
+            salt: &'static str,
+            prk: &'static str,
+            info: &'static str,
+            okm: &'static str,
+        }
+        let secret = hex::decode("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b").unwrap();
+        let cases = [
+            TestCase {
+                salt: "000102030405060708090a0b0c",
+                prk: "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5",
+                info: "f0f1f2f3f4f5f6f7f8f9",
+                okm: "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865",
+            },
+            TestCase {
+                salt: "",
+                prk: "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04",
+                info: "",
+                okm: "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8",
+            },
+        ];
+        for case in cases {
+            let salt = hmac::SigningKey::new(&digest::SHA256, &hex::decode(case.salt).unwrap());
+            let prk = extract(&salt, &secret).unwrap();
+            assert_eq!(hex::encode(&prk.key_value), case.prk);
+
+            // Expanding a PRK we didn't extract ourselves, as the FxA protocol
+            // does with its tokens.
+            let prk = hmac::SigningKey::new(&digest::SHA256, &hex::decode(case.prk).unwrap());
+            let mut okm = vec![0u8; case.okm.len() / 2];
+            expand(&prk, &hex::decode(case.info).unwrap(), &mut okm).unwrap();
+            assert_eq!(hex::encode(okm), case.okm);
+        }
+    }
+
     #[test]
     fn hkdf_rejects_gigantic_salt() {
         if (u32::MAX as usize) < usize::MAX {
@@ -24,20 +24,33 @@
 /// This crate provides all the cryptographic primitives required by
 /// this workspace, backed by the NSS library.
 /// The exposed API is pretty much the same as the `ring` crate.
+///
+/// NSS isn't available on `wasm32`, so on that target only the error types
+/// are exposed; consumers need another backend for the primitives there.
+#[cfg(not(target_arch = "wasm32"))]
 pub mod aead;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod agreement;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod constant_time;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod contentsignature;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod digest;
-#[cfg(feature = "ece")]
+#[cfg(all(feature = "ece", not(target_arch = "wasm32")))]
 pub mod ece_crypto;
 mod error;
-#[cfg(feature = "hawk")]
+#[cfg(all(feature = "hawk", not(target_arch = "wasm32")))]
 mod hawk_crypto;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod hkdf;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod hmac;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod pbkdf2;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod rand;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod signature;
 
 // Expose `hawk` if the hawk feature is on. This avoids consumers needing to
@@ -54,6 +67,7 @@ pub use crate::error::{Error, ErrorKind, Result};
 
 /// Only required to be called if you intend to use this library in conjunction
 /// with the `hawk` or the `ece` crate.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn ensure_initialized() {
     nss::ensure_initialized();
     #[cfg(any(feature = "hawk", feature = "ece"))]
@@ -8,25 +8,80 @@
 //! feeding them to the application logging system.
 
 pub use log::Level;
+use serde_json::{json, Map, Value};
 
 /// log::Record, except it exposes it's data as fields rather than methods
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Record {
     pub level: Level,
     pub target: String,
     pub message: String,
+    /// The id of the operation the record was logged in, if any; see
+    /// `error_support::enter_operation()`.
+    pub operation_id: Option<String>,
 }
 
 pub trait AppServicesLogger: Sync + Send {
     fn log(&self, record: Record);
 }
 
+/// Logger which receives records serialized as JSON, including the key-value fields attached to
+/// them with the `log` macros' key-value syntax, for example
+/// `log::info!(engine = "bookmarks", count = 3; "Sync complete")`.
+pub trait AppServicesStructuredLogger: Sync + Send {
+    fn log_json(&self, record: String);
+}
+
 impl From<&log::Record<'_>> for Record {
     fn from(record: &log::Record) -> Self {
         Self {
             level: record.level(),
             target: record.target().to_string(),
             message: record.args().to_string(),
+            operation_id: error_support::current_operation_id(),
         }
     }
 }
+
+/// Serializes a log record as a JSON object, with its key-value pairs in `fields`.
+pub(crate) fn record_to_json(record: &log::Record<'_>) -> String {
+    let mut fields = FieldCollector(Map::new());
+    // Our visitor never fails.
+    let _ = record.key_values().visit(&mut fields);
+    json!({
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "operation_id": error_support::current_operation_id(),
+        "fields": fields.0,
+    })
+    .to_string()
+}
+
+struct FieldCollector(Map<String, Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.insert(key.as_str().to_string(), field_value(&value));
+        Ok(())
+    }
+}
+
+// Keep booleans and numbers as JSON values, and format everything else as a string.
+fn field_value(value: &log::kv::Value<'_>) -> Value {
+    if let Some(b) = value.to_bool() {
+        b.into()
+    } else if let Some(n) = value.to_u64() {
+        n.into()
+    } else if let Some(n) = value.to_i64() {
+        n.into()
+    } else if let Some(n) = value.to_f64() {
+        n.into()
+    } else {
+        value.to_string().into()
+    }
+}
@@ -4,9 +4,10 @@
 
 use std::sync::atomic::{AtomicBool, Ordering};
 mod foreign_logger;
+mod log_buffer;
 mod rust_logger;
 
-pub use foreign_logger::{AppServicesLogger, Level, Record};
+pub use foreign_logger::{AppServicesLogger, AppServicesStructuredLogger, Level, Record};
 
 static HAVE_SET_MAX_LEVEL: AtomicBool = AtomicBool::new(false);
 
@@ -21,6 +22,35 @@ pub fn set_logger(logger: Option<Box<dyn AppServicesLogger>>) {
     rust_logger::set_foreign_logger(logger)
 }
 
+/// Set the logger to forward records to as JSON, including any key-value fields attached to them.
+/// This can be used alongside, or instead of, the logger passed to `set_logger()`.
+///
+/// Pass in None to disable structured logging.
+pub fn set_structured_logger(logger: Option<Box<dyn AppServicesStructuredLogger>>) {
+    // Set a default max level, if none has already been set
+    if !HAVE_SET_MAX_LEVEL.load(Ordering::Relaxed) {
+        set_max_level(Level::Debug);
+    }
+    rust_logger::set_structured_foreign_logger(logger)
+}
+
+/// Keep the last `size` log records in memory, so they can be fetched with `dump_recent_logs()`.
+/// URLs and email addresses are removed from the kept records' messages.
+///
+/// Pass in 0 to stop keeping records, which is the default.
+pub fn set_log_buffer_size(size: u32) {
+    // Set a default max level, if none has already been set
+    if !HAVE_SET_MAX_LEVEL.load(Ordering::Relaxed) {
+        set_max_level(Level::Debug);
+    }
+    rust_logger::set_log_buffer_capacity(size as usize)
+}
+
+/// Get the log records kept because of `set_log_buffer_size()`, oldest first.
+pub fn dump_recent_logs() -> Vec<Record> {
+    rust_logger::recent_records()
+}
+
 /// Set the maximum log level filter.  Records below this level will not be sent to the logger.
 pub fn set_max_level(level: Level) {
     log::set_max_level(level.to_level_filter());
@@ -78,11 +108,13 @@ mod test {
                 level: Level::Info,
                 target: "rust_log_forwarder::test".into(),
                 message: "Test message".into(),
+                operation_id: None,
             },
             Record {
                 level: Level::Warn,
                 target: "rust_log_forwarder::test".into(),
                 message: "Test message2".into(),
+                operation_id: None,
             },
         ]);
         logger.clear_records();
@@ -92,6 +124,125 @@ mod test {
         logger.check_records(vec![]);
     }
 
+    #[test]
+    fn test_operation_id() {
+        let _lock = TEST_LOCK.lock().unwrap();
+        let logger = TestLogger::new();
+        set_logger(Some(Box::new(logger.clone())));
+        set_max_level(Level::Debug);
+        {
+            let _operation = error_support::enter_operation("op-1");
+            log::info!("Test message");
+        }
+        logger.check_records(vec![Record {
+            level: Level::Info,
+            target: "rust_log_forwarder::test".into(),
+            message: "Test message".into(),
+            operation_id: Some("op-1".into()),
+        }]);
+        set_logger(None);
+    }
+
+    #[derive(Clone)]
+    struct TestStructuredLogger {
+        records: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl AppServicesStructuredLogger for TestStructuredLogger {
+        fn log_json(&self, record: String) {
+            self.records
+                .lock()
+                .unwrap()
+                .push(serde_json::from_str(&record).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_structured_logging() {
+        let _lock = TEST_LOCK.lock().unwrap();
+        let logger = TestLogger::new();
+        let structured_logger = TestStructuredLogger {
+            records: Arc::new(Mutex::new(Vec::new())),
+        };
+        set_logger(Some(Box::new(logger.clone())));
+        set_structured_logger(Some(Box::new(structured_logger.clone())));
+        set_max_level(Level::Debug);
+        {
+            let _operation = error_support::enter_operation("op-1");
+            log::info!(
+                engine = "bookmarks", incoming = 3, skipped = -1, took_secs = 0.5, ok = true;
+                "Synced {}", "bookmarks"
+            );
+        }
+        log::warn!("No fields");
+        assert_eq!(
+            *structured_logger.records.lock().unwrap(),
+            vec![
+                serde_json::json!({
+                    "level": "INFO",
+                    "target": "rust_log_forwarder::test",
+                    "message": "Synced bookmarks",
+                    "operation_id": "op-1",
+                    "fields": {
+                        "engine": "bookmarks",
+                        "incoming": 3,
+                        "skipped": -1,
+                        "took_secs": 0.5,
+                        "ok": true,
+                    },
+                }),
+                serde_json::json!({
+                    "level": "WARN",
+                    "target": "rust_log_forwarder::test",
+                    "message": "No fields",
+                    "operation_id": null,
+                    "fields": {},
+                }),
+            ]
+        );
+        // The plain logger still gets the records.
+        assert_eq!(logger.records.lock().unwrap().len(), 2);
+
+        // Structured logging works without a plain logger, too.
+        set_logger(None);
+        structured_logger.records.lock().unwrap().clear();
+        log::info!("Test message");
+        assert_eq!(structured_logger.records.lock().unwrap().len(), 1);
+        set_structured_logger(None);
+        log::info!("Test message");
+        assert_eq!(structured_logger.records.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_log_buffer() {
+        let _lock = TEST_LOCK.lock().unwrap();
+        set_logger(None);
+        set_max_level(Level::Debug);
+        set_log_buffer_size(2);
+        log::info!("First");
+        log::debug!("Second");
+        log::warn!("Couldn't fetch https://example.com/secret");
+        let record = |level, message: &str| Record {
+            level,
+            target: "rust_log_forwarder::test".into(),
+            message: message.into(),
+            operation_id: None,
+        };
+        assert_eq!(
+            dump_recent_logs(),
+            vec![
+                record(Level::Debug, "Second"),
+                record(Level::Warn, "Couldn't fetch <URL>"),
+            ]
+        );
+        // Dumping the records doesn't remove them.
+        assert_eq!(dump_recent_logs().len(), 2);
+
+        set_log_buffer_size(0);
+        log::info!("Test message");
+        assert_eq!(dump_recent_logs(), vec![]);
+    }
+
     #[test]
     fn test_max_level() {
         let _lock = TEST_LOCK.lock().unwrap();
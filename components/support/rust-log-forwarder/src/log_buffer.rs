@@ -0,0 +1,135 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Buffer of recent log records
+//!
+//! When enabled, we keep the last few records in memory so the application can attach them to a
+//! bug report, even when the platform's logs aren't available. The buffered records might end up
+//! anywhere, so URLs and email addresses are removed from their messages first.
+
+use crate::foreign_logger::Record;
+use std::collections::VecDeque;
+
+pub(crate) struct LogBuffer {
+    capacity: usize,
+    records: VecDeque<Record>,
+}
+
+impl LogBuffer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            capacity: 0,
+            records: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Change how many records are kept, dropping the oldest if there are too many. 0 disables the
+    /// buffer.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.records.len() > capacity {
+            self.records.pop_front();
+        }
+        self.records.shrink_to(capacity);
+    }
+
+    pub(crate) fn push(&mut self, record: Record) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(Record {
+            message: sanitize_message(&record.message),
+            ..record
+        });
+    }
+
+    /// Returns copies of the buffered records, oldest first.
+    pub(crate) fn records(&self) -> Vec<Record> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+/// Replaces anything in `message` which looks like a URL or an email address.
+fn sanitize_message(message: &str) -> String {
+    let mut sanitized = String::with_capacity(message.len());
+    for (i, word) in message.split(' ').enumerate() {
+        if i > 0 {
+            sanitized.push(' ');
+        }
+        if word.contains("://") {
+            sanitized.push_str(&error_support::redact_url(word));
+        } else if looks_like_email(word) {
+            sanitized.push_str("<email>");
+        } else {
+            sanitized.push_str(word);
+        }
+    }
+    sanitized
+}
+
+fn looks_like_email(word: &str) -> bool {
+    match word.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use log::Level;
+
+    fn record(message: &str) -> Record {
+        Record {
+            level: Level::Info,
+            target: "test".into(),
+            message: message.into(),
+            operation_id: None,
+        }
+    }
+
+    fn messages(buffer: &LogBuffer) -> Vec<String> {
+        buffer.records().into_iter().map(|r| r.message).collect()
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut buffer = LogBuffer::new();
+        buffer.push(record("dropped"));
+        assert!(buffer.records().is_empty());
+
+        buffer.set_capacity(3);
+        for i in 0..5 {
+            buffer.push(record(&format!("message {i}")));
+        }
+        assert_eq!(
+            messages(&buffer),
+            vec!["message 2", "message 3", "message 4"]
+        );
+
+        buffer.set_capacity(1);
+        assert_eq!(messages(&buffer), vec!["message 4"]);
+        buffer.set_capacity(0);
+        assert!(buffer.records().is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_message() {
+        assert_eq!(
+            sanitize_message("Fetching https://example.com/path?q=secret for someone@example.com"),
+            "Fetching <URL> for <email>"
+        );
+        assert_eq!(
+            sanitize_message("Synced 3 records @ 12:00 (a@b)"),
+            "Synced 3 records @ 12:00 (a@b)"
+        );
+    }
+}
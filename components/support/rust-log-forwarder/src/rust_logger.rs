@@ -5,10 +5,15 @@
 //! Rust Logger implementation
 //!
 //! This is responsible for taking logs from the rust log crate and forwarding them to a
-//! foreign_logger::Logger instance.
+//! foreign_logger::Logger instance, and/or a foreign_logger::AppServicesStructuredLogger one. It
+//! can also keep recent records in a log_buffer::LogBuffer.
 
-use crate::foreign_logger::AppServicesLogger as ForeignLogger;
-use parking_lot::RwLock;
+use crate::foreign_logger::{
+    record_to_json, AppServicesLogger as ForeignLogger,
+    AppServicesStructuredLogger as StructuredForeignLogger, Record,
+};
+use crate::log_buffer::LogBuffer;
+use parking_lot::{Mutex, RwLock};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Once,
@@ -21,6 +26,8 @@ static INIT: Once = Once::new();
 
 struct Logger {
     foreign_logger: RwLock<Option<Box<dyn ForeignLogger>>>,
+    structured_foreign_logger: RwLock<Option<Box<dyn StructuredForeignLogger>>>,
+    log_buffer: Mutex<LogBuffer>,
     is_enabled: AtomicBool,
 }
 
@@ -28,14 +35,35 @@ impl Logger {
     const fn new() -> Self {
         Self {
             foreign_logger: RwLock::new(None),
+            structured_foreign_logger: RwLock::new(None),
+            log_buffer: Mutex::new(LogBuffer::new()),
             is_enabled: AtomicBool::new(false),
         }
     }
 
     fn set_foreign_logger(&self, foreign_logger: Option<Box<dyn ForeignLogger>>) {
-        self.is_enabled
-            .store(foreign_logger.is_some(), Ordering::Relaxed);
         *self.foreign_logger.write() = foreign_logger;
+        self.update_is_enabled();
+    }
+
+    fn set_structured_foreign_logger(
+        &self,
+        foreign_logger: Option<Box<dyn StructuredForeignLogger>>,
+    ) {
+        *self.structured_foreign_logger.write() = foreign_logger;
+        self.update_is_enabled();
+    }
+
+    fn set_log_buffer_capacity(&self, capacity: usize) {
+        self.log_buffer.lock().set_capacity(capacity);
+        self.update_is_enabled();
+    }
+
+    fn update_is_enabled(&self) {
+        let is_enabled = self.foreign_logger.read().is_some()
+            || self.structured_foreign_logger.read().is_some()
+            || self.log_buffer.lock().is_enabled();
+        self.is_enabled.store(is_enabled, Ordering::Relaxed);
     }
 }
 
@@ -45,15 +73,23 @@ impl log::Log for Logger {
     }
 
     fn log(&self, record: &log::Record<'_>) {
+        let mut log_buffer = self.log_buffer.lock();
+        if log_buffer.is_enabled() {
+            log_buffer.push(record.into());
+        }
+        drop(log_buffer);
         if let Some(foreign_logger) = &*self.foreign_logger.read() {
             foreign_logger.log(record.into())
         }
+        if let Some(foreign_logger) = &*self.structured_foreign_logger.read() {
+            foreign_logger.log_json(record_to_json(record))
+        }
     }
 
     fn flush(&self) {}
 }
 
-pub fn set_foreign_logger(foreign_logger: Option<Box<dyn ForeignLogger>>) {
+fn init() {
     INIT.call_once(|| {
         // This should be the only component that calls `log::set_logger()`.  If not, then
         // panic'ing seems reasonable.
@@ -61,5 +97,23 @@ pub fn set_foreign_logger(foreign_logger: Option<Box<dyn ForeignLogger>>) {
             "Failed to initialize rust-log-forwarder::Logger, other log implementation already initialized?",
         );
     });
+}
+
+pub fn set_foreign_logger(foreign_logger: Option<Box<dyn ForeignLogger>>) {
+    init();
     RUST_LOGGER.set_foreign_logger(foreign_logger);
 }
+
+pub fn set_structured_foreign_logger(foreign_logger: Option<Box<dyn StructuredForeignLogger>>) {
+    init();
+    RUST_LOGGER.set_structured_foreign_logger(foreign_logger);
+}
+
+pub fn set_log_buffer_capacity(capacity: usize) {
+    init();
+    RUST_LOGGER.set_log_buffer_capacity(capacity);
+}
+
+pub fn recent_records() -> Vec<Record> {
+    RUST_LOGGER.log_buffer.lock().records()
+}
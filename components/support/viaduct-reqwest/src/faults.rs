@@ -0,0 +1,215 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Programmable fault injection, so components can test how they handle a
+//! misbehaving network.
+//!
+//! This is only available with the `fault-injection` feature, which tests
+//! should enable in their `dev-dependencies`.
+//!
+//! Faults are configured per host, and apply to every request the reqwest
+//! backend sends to that host until they're cleared. Faults are applied
+//! deterministically rather than randomly (eg, a 25% drop rate drops every
+//! fourth request), so tests using them are reproducible.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! use viaduct_reqwest::faults::{set_host_faults, clear_all_faults, HostFaults};
+//!
+//! viaduct_reqwest::use_reqwest_backend();
+//! set_host_faults(
+//!     "localhost",
+//!     HostFaults {
+//!         latency: Duration::from_millis(200),
+//!         status_sequence: vec![503, 503],
+//!         ..Default::default()
+//!     },
+//! );
+//! // ... the next two requests to localhost get a 503 response ...
+//! clear_all_faults();
+//! ```
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{collections::HashMap, time::Duration};
+
+/// The faults to inject into requests to a single host.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostFaults {
+    /// Delay every request by this long before doing anything else.
+    pub latency: Duration,
+    /// The percentage of requests which fail with a network error, as if the
+    /// connection was dropped.
+    pub drop_percent: u8,
+    /// The percentage of requests which fail with a network error, as if the
+    /// host name couldn't be resolved.
+    pub dns_failure_percent: u8,
+    /// Status codes to respond with, one per request and in order, without
+    /// sending the request. Once exhausted, requests are sent normally.
+    pub status_sequence: Vec<u16>,
+}
+
+/// What to do with a single request.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Fault {
+    Drop,
+    DnsFailure,
+    Status(u16),
+}
+
+#[derive(Default)]
+struct HostState {
+    faults: HostFaults,
+    // Accumulators for the percentage-based faults: each request adds the
+    // percentage, and the fault fires whenever this reaches 100.
+    drop_acc: u32,
+    dns_acc: u32,
+    status_index: usize,
+}
+
+impl HostState {
+    fn next_fault(&mut self) -> Option<Fault> {
+        if Self::fires(&mut self.dns_acc, self.faults.dns_failure_percent) {
+            return Some(Fault::DnsFailure);
+        }
+        if Self::fires(&mut self.drop_acc, self.faults.drop_percent) {
+            return Some(Fault::Drop);
+        }
+        let status = self.faults.status_sequence.get(self.status_index)?;
+        self.status_index += 1;
+        Some(Fault::Status(*status))
+    }
+
+    fn fires(acc: &mut u32, percent: u8) -> bool {
+        *acc += u32::from(percent.min(100));
+        if *acc >= 100 {
+            *acc -= 100;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+static FAULTS: Lazy<Mutex<HashMap<String, HostState>>> = Lazy::new(Default::default);
+
+/// Inject `faults` into all future requests to `host`, replacing any faults
+/// previously set for it.
+pub fn set_host_faults(host: impl Into<String>, faults: HostFaults) {
+    FAULTS.lock().insert(
+        host.into(),
+        HostState {
+            faults,
+            ..Default::default()
+        },
+    );
+}
+
+/// Stop injecting faults into requests to `host`.
+pub fn clear_host_faults(host: &str) {
+    FAULTS.lock().remove(host);
+}
+
+/// Stop injecting faults into requests to any host.
+pub fn clear_all_faults() {
+    FAULTS.lock().clear();
+}
+
+/// Applies any configured latency for `url`'s host, and returns the fault to
+/// inject into this request, if any.
+pub(crate) fn next_fault(url: &reqwest::Url) -> Option<Fault> {
+    let host = url.host_str()?;
+    let (latency, fault) = {
+        let mut faults = FAULTS.lock();
+        let state = faults.get_mut(host)?;
+        (state.faults.latency, state.next_fault())
+    };
+    // Sleep without holding the lock, so other hosts aren't delayed.
+    if !latency.is_zero() {
+        std::thread::sleep(latency);
+    }
+    fault
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faults_for(faults: HostFaults, count: usize) -> Vec<Option<Fault>> {
+        let mut state = HostState {
+            faults,
+            ..Default::default()
+        };
+        (0..count).map(|_| state.next_fault()).collect()
+    }
+
+    #[test]
+    fn test_percentages() {
+        let faults = faults_for(
+            HostFaults {
+                drop_percent: 25,
+                ..Default::default()
+            },
+            8,
+        );
+        assert_eq!(
+            faults,
+            vec![
+                None,
+                None,
+                None,
+                Some(Fault::Drop),
+                None,
+                None,
+                None,
+                Some(Fault::Drop)
+            ]
+        );
+
+        let faults = faults_for(
+            HostFaults {
+                dns_failure_percent: 100,
+                drop_percent: 100,
+                ..Default::default()
+            },
+            2,
+        );
+        assert_eq!(
+            faults,
+            vec![Some(Fault::DnsFailure), Some(Fault::DnsFailure)]
+        );
+    }
+
+    #[test]
+    fn test_status_sequence() {
+        let faults = faults_for(
+            HostFaults {
+                status_sequence: vec![503, 500],
+                ..Default::default()
+            },
+            3,
+        );
+        assert_eq!(
+            faults,
+            vec![Some(Fault::Status(503)), Some(Fault::Status(500)), None]
+        );
+    }
+
+    #[test]
+    fn test_per_host() {
+        set_host_faults(
+            "faulty.example.com",
+            HostFaults {
+                status_sequence: vec![502],
+                ..Default::default()
+            },
+        );
+        let faulty = reqwest::Url::parse("https://faulty.example.com/path").unwrap();
+        let other = reqwest::Url::parse("https://example.com/path").unwrap();
+        assert_eq!(next_fault(&other), None);
+        assert_eq!(next_fault(&faulty), Some(Fault::Status(502)));
+        assert_eq!(next_fault(&faulty), None);
+        clear_host_faults("faulty.example.com");
+    }
+}
@@ -3,23 +3,72 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use once_cell::sync::Lazy;
-use std::{io::Read, sync::Once};
+use parking_lot::Mutex;
+use std::{collections::HashMap, io::Read, sync::Once, time::Duration};
 use viaduct::{settings::GLOBAL_SETTINGS, Backend};
 
+#[cfg(any(test, feature = "fault-injection"))]
+pub mod faults;
+mod settings;
+
+pub use settings::{set_connection_settings, ConnectionSettings};
+
 // Note: we don't `use` things from reqwest or the viaduct crate because
 // it would be rather confusing given that we have the same name for
 // most things as them.
 
-static CLIENT: Lazy<reqwest::blocking::Client> = Lazy::new(|| {
+// reqwest only supports connect timeouts and proxies per-Client, so we keep
+// a client for each combination requests have asked for. Every component in
+// the process shares these, so they share pooled connections too, rather than
+// each paying for new TCP and TLS handshakes. The read timeout is set on each
+// request, so doesn't need this.
+type ClientKey = (Option<Duration>, Option<reqwest::Url>);
+
+static CLIENTS: Lazy<Mutex<HashMap<ClientKey, reqwest::blocking::Client>>> =
+    Lazy::new(Default::default);
+
+// More distinct combinations than this suggests something is building them
+// dynamically, so we start over rather than growing without bound.
+const MAX_CLIENTS: usize = 8;
+
+fn get_client(
+    connect_timeout: Option<Duration>,
+    proxy: Option<reqwest::Url>,
+) -> Result<reqwest::blocking::Client, viaduct::Error> {
+    let mut clients = CLIENTS.lock();
+    let key = (connect_timeout, proxy);
+    if let Some(client) = clients.get(&key) {
+        // Clients are reference counted, so this is cheap.
+        return Ok(client.clone());
+    }
+    let client = build_client(key.0, key.1.as_ref())?;
+    if clients.len() >= MAX_CLIENTS {
+        clients.clear();
+    }
+    clients.insert(key, client.clone());
+    Ok(client)
+}
+
+// Drop the shared clients, so that the next request builds new ones with the
+// current settings.
+pub(crate) fn reset_clients() {
+    CLIENTS.lock().clear();
+}
+
+fn build_client(
+    connect_timeout: Option<Duration>,
+    proxy: Option<&reqwest::Url>,
+) -> Result<reqwest::blocking::Client, viaduct::Error> {
     let settings = GLOBAL_SETTINGS.read();
     let mut builder = reqwest::blocking::ClientBuilder::new()
-        .timeout(settings.read_timeout)
-        .connect_timeout(settings.connect_timeout)
+        .timeout(None)
+        .connect_timeout(connect_timeout)
         .redirect(if settings.follow_redirects {
             reqwest::redirect::Policy::default()
         } else {
             reqwest::redirect::Policy::none()
         });
+    builder = settings::connection_settings().apply(builder);
     if cfg!(target_os = "ios") {
         // The FxA servers rely on the UA agent to filter
         // some push messages directed to iOS devices.
@@ -28,11 +77,16 @@ static CLIENT: Lazy<reqwest::blocking::Client> = Lazy::new(|| {
         // instead, but this will unblock us for now.
         builder = builder.user_agent("Firefox-iOS-FxA/24");
     }
+    if let Some(proxy) = proxy {
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .map_err(|e| viaduct::Error::BackendError(format!("Invalid proxy: {}", e)))?;
+        builder = builder.proxy(proxy);
+    }
     // Note: no cookie or cache support.
-    builder
+    Ok(builder
         .build()
-        .expect("Failed to initialize global reqwest::Client")
-});
+        .expect("Failed to initialize reqwest::Client"))
+}
 
 #[allow(clippy::unnecessary_wraps)] // not worth the time to untangle
 fn into_reqwest(request: viaduct::Request) -> Result<reqwest::blocking::Request, viaduct::Error> {
@@ -57,6 +111,7 @@ fn into_reqwest(request: viaduct::Request) -> Result<reqwest::blocking::Request,
             .insert(HeaderName::from_bytes(h.name().as_bytes()).unwrap(), value);
     }
     *result.body_mut() = request.body.map(reqwest::blocking::Body::from);
+    *result.timeout_mut() = request.read_timeout;
     Ok(result)
 }
 
@@ -65,17 +120,70 @@ impl Backend for ReqwestBackend {
     fn send(&self, request: viaduct::Request) -> Result<viaduct::Response, viaduct::Error> {
         viaduct::note_backend("reqwest (untrusted)");
         let request_method = request.method;
+        #[cfg(any(test, feature = "fault-injection"))]
+        match faults::next_fault(&request.url) {
+            Some(faults::Fault::Drop) => {
+                return Err(viaduct::Error::NetworkError(
+                    "Injected fault: connection dropped".into(),
+                ))
+            }
+            Some(faults::Fault::DnsFailure) => {
+                return Err(viaduct::Error::NetworkError(
+                    "Injected fault: failed to resolve host".into(),
+                ))
+            }
+            Some(faults::Fault::Status(status)) => {
+                return Ok(viaduct::Response {
+                    request_method,
+                    url: request.url,
+                    status,
+                    headers: viaduct::Headers::new(),
+                    body: vec![],
+                })
+            }
+            None => (),
+        }
+        let connect_timeout = request.connect_timeout;
+        let max_response_size = request.max_response_size;
+        let proxy = match &request.routing {
+            viaduct::Routing::Direct => None,
+            viaduct::Routing::Proxy(url) => Some(url.clone()),
+            viaduct::Routing::Ohttp(_) => {
+                // viaduct handles OHTTP itself, and only hands us the request
+                // to the relay.
+                return Err(viaduct::Error::UnsupportedRouting("ohttp"));
+            }
+        };
+        let client = get_client(connect_timeout, proxy)?;
         let req = into_reqwest(request)?;
-        let mut resp = CLIENT
+        let mut resp = client
             .execute(req)
             .map_err(|e| viaduct::Error::NetworkError(e.to_string()))?;
         let status = resp.status().as_u16();
         let url = resp.url().clone();
+        // Don't bother reading bodies we know are too large, and stop reading
+        // once we're past the limit if the server didn't tell us the length.
+        if let (Some(length), Some(limit)) = (resp.content_length(), max_response_size) {
+            if length > limit {
+                return Err(viaduct::Error::ResponseTooLarge { limit });
+            }
+        }
         let mut body = Vec::with_capacity(resp.content_length().unwrap_or_default() as usize);
-        resp.read_to_end(&mut body).map_err(|e| {
+        let read_result = match max_response_size {
+            Some(limit) => (&mut resp)
+                .take(limit.saturating_add(1))
+                .read_to_end(&mut body),
+            None => resp.read_to_end(&mut body),
+        };
+        read_result.map_err(|e| {
             log::error!("Failed to get body from response: {:?}", e);
             viaduct::Error::NetworkError(e.to_string())
         })?;
+        if let Some(limit) = max_response_size {
+            if body.len() as u64 > limit {
+                return Err(viaduct::Error::ResponseTooLarge { limit });
+            }
+        }
         let mut headers = viaduct::Headers::with_capacity(resp.headers().len());
         for (k, v) in resp.headers() {
             let val = String::from_utf8_lossy(v.as_bytes()).to_string();
@@ -98,6 +206,16 @@ impl Backend for ReqwestBackend {
             body,
         })
     }
+
+    fn capabilities(&self) -> viaduct::BackendCapabilities {
+        // We build reqwest without its `brotli` feature, and always read the
+        // body in full.
+        viaduct::BackendCapabilities {
+            http2: settings::connection_settings().prefer_http2,
+            proxy: true,
+            ..Default::default()
+        }
+    }
 }
 
 static INIT_REQWEST_BACKEND: Once = Once::new();
@@ -114,3 +232,23 @@ pub fn use_reqwest_backend() {
 pub extern "C" fn viaduct_use_reqwest_backend() {
     use_reqwest_backend();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_clients() {
+        let key = (Some(Duration::from_secs(42)), None);
+        get_client(key.0, None).unwrap();
+        assert!(CLIENTS.lock().contains_key(&key));
+
+        // Changing the settings drops the clients built with the old ones.
+        set_connection_settings(ConnectionSettings {
+            prefer_http2: false,
+            ..Default::default()
+        });
+        assert!(!CLIENTS.lock().contains_key(&key));
+        set_connection_settings(ConnectionSettings::default());
+    }
+}
@@ -0,0 +1,72 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Connection settings for the reqwest backend.
+//!
+//! These apply to the clients shared by every component in the process, so
+//! they should be set once by the embedding application, before the first
+//! request if possible.
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::time::Duration;
+
+/// How the reqwest backend manages its connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionSettings {
+    /// The maximum number of idle connections kept open to each host.
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle connection is kept open. `None` keeps them open until
+    /// the server closes them.
+    pub pool_idle_timeout: Option<Duration>,
+    /// The interval between TCP keep-alive probes on open connections. `None`
+    /// disables them.
+    pub tcp_keepalive: Option<Duration>,
+    /// Whether to negotiate HTTP/2 with servers which support it. If false,
+    /// only HTTP/1.1 is used.
+    pub prefer_http2: bool,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            // reqwest's own defaults.
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: None,
+            prefer_http2: true,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    pub(crate) fn apply(
+        &self,
+        builder: reqwest::blocking::ClientBuilder,
+    ) -> reqwest::blocking::ClientBuilder {
+        let builder = builder
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if self.prefer_http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+}
+
+static CONNECTION_SETTINGS: Lazy<RwLock<ConnectionSettings>> = Lazy::new(Default::default);
+
+/// Replace the connection settings. Open connections are closed once any
+/// in-flight requests using them complete, and later requests use the new
+/// settings.
+pub fn set_connection_settings(settings: ConnectionSettings) {
+    *CONNECTION_SETTINGS.write() = settings;
+    crate::reset_clients();
+}
+
+pub(crate) fn connection_settings() -> ConnectionSettings {
+    CONNECTION_SETTINGS.read().clone()
+}
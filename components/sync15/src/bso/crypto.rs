@@ -8,7 +8,7 @@
 use super::{IncomingBso, IncomingEnvelope, OutgoingBso, OutgoingEnvelope};
 use crate::error;
 use crate::key_bundle::KeyBundle;
-use crate::EncryptedPayload;
+use crate::{EncryptedPayload, PayloadCompression};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 // The BSO implementation we use for encrypted payloads.
@@ -63,6 +63,19 @@ impl OutgoingBso {
             payload: EncryptedPayload::from_cleartext(key, self.payload)?,
         })
     }
+
+    /// Like `into_encrypted`, but compresses the payload first if that makes it smaller. Only
+    /// use this when meta/global and every client record say they can read compressed payloads.
+    pub fn into_encrypted_compressed(
+        self,
+        key: &KeyBundle,
+        compression: PayloadCompression,
+    ) -> error::Result<OutgoingEncryptedBso> {
+        Ok(OutgoingEncryptedBso {
+            envelope: self.envelope,
+            payload: EncryptedPayload::from_cleartext_compressed(key, self.payload, compression)?,
+        })
+    }
 }
 
 // The BSOs we write to the servers expect a "payload" attribute which is a JSON serialized
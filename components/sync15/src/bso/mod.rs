@@ -130,7 +130,7 @@ impl From<Guid> for OutgoingEnvelope {
 /// * From environments where the encryption is done externally (eg, Rust syncing in Desktop
 ///   Firefox has the encryption/decryption done by Firefox and the cleartext BSOs are passed in.
 /// * Read from the server as an EncryptedBso; see EncryptedBso description above.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct IncomingBso {
     #[serde(flatten)]
     pub envelope: IncomingEnvelope,
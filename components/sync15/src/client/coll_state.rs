@@ -7,6 +7,7 @@ use super::{CollectionKeys, GlobalState};
 use crate::engine::{CollSyncIds, EngineSyncAssociation, SyncEngine};
 use crate::error;
 use crate::KeyBundle;
+use crate::PayloadCompression;
 use crate::ServerTimestamp;
 
 /// Holds state for a collection necessary to perform a sync of it. Lives for the lifetime
@@ -18,6 +19,8 @@ pub struct CollState {
     // from meta/global, used for XIUS when we POST outgoing record based on this state.
     pub last_modified: ServerTimestamp,
     pub key: KeyBundle,
+    // from meta/global and the client records, how to compress outgoing payloads, if at all.
+    pub compression: Option<PayloadCompression>,
 }
 
 /// This mini state-machine helps build a CollState
@@ -91,6 +94,7 @@ impl<'state> LocalCollStateMachine<'state> {
                                 config,
                                 last_modified,
                                 key,
+                                compression: meta_global.outgoing_compression(),
                             };
                             Ok(LocalCollState::Ready { coll_state })
                         }
@@ -199,6 +203,7 @@ mod tests {
                 .map(|(key, value)| (key.to_owned(), value))
                 .collect(),
                 declined: vec![],
+                payload_compression: Vec::new(),
             },
             global_timestamp: ServerTimestamp::default(),
             keys,
@@ -7,13 +7,20 @@ use super::{
     CollState, Sync15ClientResponse, Sync15StorageClient,
 };
 use crate::bso::{IncomingBso, OutgoingBso, OutgoingEncryptedBso};
-use crate::engine::CollectionRequest;
+use crate::engine::{CollectionRequest, RequestOrder};
 use crate::error::{self, Error, Result};
-use crate::{CollectionName, KeyBundle, ServerTimestamp};
+use crate::{CollectionName, KeyBundle, PayloadCompression, ServerTimestamp};
 
-fn encrypt_outgoing(o: Vec<OutgoingBso>, key: &KeyBundle) -> Result<Vec<OutgoingEncryptedBso>> {
+fn encrypt_outgoing(
+    o: Vec<OutgoingBso>,
+    key: &KeyBundle,
+    compression: Option<PayloadCompression>,
+) -> Result<Vec<OutgoingEncryptedBso>> {
     o.into_iter()
-        .map(|change| change.into_encrypted(key))
+        .map(|change| match compression {
+            Some(compression) => change.into_encrypted_compressed(key, compression),
+            None => change.into_encrypted(key),
+        })
         .collect()
 }
 
@@ -42,6 +49,87 @@ pub fn fetch_incoming(
     Ok(result)
 }
 
+/// Fetches incoming records in pages of at most `batch_size`, decrypting each page and passing
+/// it to `stage` before fetching the next, so only one page is held in memory at a time. Any
+/// limit in the request caps the total number of records fetched. Returns that total.
+///
+/// If the records are fetched oldest first, `stage` is also passed a checkpoint for each page
+/// but the last - see [crate::engine::SyncEngine::set_incoming_checkpoint].
+pub fn fetch_incoming_batched(
+    client: &Sync15StorageClient,
+    state: &CollState,
+    collection_request: CollectionRequest,
+    batch_size: usize,
+    stage: impl FnMut(Vec<IncomingBso>, Option<ServerTimestamp>) -> Result<()>,
+) -> Result<usize> {
+    fetch_pages(
+        collection_request,
+        batch_size,
+        |request| {
+            // If the collection changes while we're paging through it, the server fails the
+            // request and we'll start again next sync.
+            let (records, next_offset) =
+                match client.get_encrypted_records_page(request, state.last_modified)? {
+                    (Sync15ClientResponse::Success { record, .. }, next_offset) => {
+                        (record, next_offset)
+                    }
+                    (other, _) => return Err(other.create_storage_error()),
+                };
+            let records = records
+                .into_iter()
+                .map(|record| record.into_decrypted(&state.key))
+                .collect::<Result<_>>()?;
+            Ok((records, next_offset))
+        },
+        stage,
+    )
+}
+
+fn fetch_pages(
+    collection_request: CollectionRequest,
+    batch_size: usize,
+    mut fetch_page: impl FnMut(CollectionRequest) -> Result<(Vec<IncomingBso>, Option<String>)>,
+    mut stage: impl FnMut(Vec<IncomingBso>, Option<ServerTimestamp>) -> Result<()>,
+) -> Result<usize> {
+    let (max_records, order) = match collection_request.limit {
+        Some(limit) => (Some(limit.num), limit.order),
+        None => (None, RequestOrder::Oldest),
+    };
+    let mut fetched = 0;
+    let mut offset = None;
+    loop {
+        let page_size = max_records.map_or(batch_size, |max| batch_size.min(max - fetched));
+        let mut request = collection_request.clone().limit(page_size, order);
+        request.offset = offset.take();
+        let (records, next_offset) = fetch_page(request)?;
+        fetched += records.len();
+        let has_more = next_offset.is_some() && max_records.map_or(true, |max| fetched < max);
+        let checkpoint = if has_more && order == RequestOrder::Oldest {
+            page_checkpoint(&records)
+        } else {
+            None
+        };
+        log::info!("Downloaded a batch of {} remote changes", records.len());
+        stage(records, checkpoint)?;
+        if !has_more {
+            return Ok(fetched);
+        }
+        offset = next_offset;
+    }
+}
+
+/// Returns the latest timestamp at or before which every record in a page sorted oldest first
+/// has been seen. Records uploaded together share a timestamp, so the next page might hold
+/// more records with the same timestamp as the last one in this page.
+fn page_checkpoint(records: &[IncomingBso]) -> Option<ServerTimestamp> {
+    let last = records.last()?.envelope.modified;
+    records
+        .iter()
+        .rev()
+        .map(|record| record.envelope.modified)
+        .find(|modified| *modified < last)
+}
+
 pub struct CollectionUpdate<'a> {
     client: &'a Sync15StorageClient,
     state: &'a CollState,
@@ -77,7 +165,7 @@ impl<'a> CollectionUpdate<'a> {
         changeset: Vec<OutgoingBso>,
         fully_atomic: bool,
     ) -> Result<CollectionUpdate<'a>> {
-        let to_update = encrypt_outgoing(changeset, &state.key)?;
+        let to_update = encrypt_outgoing(changeset, &state.key, state.compression)?;
         Ok(CollectionUpdate::new(
             client,
             state,
@@ -119,3 +207,87 @@ impl<'a> CollectionUpdate<'a> {
         Ok(info)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bso::OutgoingBso;
+    use crate::Guid;
+
+    fn incoming(id: &str, modified: i64) -> IncomingBso {
+        OutgoingBso::new_tombstone(Guid::new(id).into())
+            .to_test_incoming_ts(ServerTimestamp(modified))
+    }
+
+    #[test]
+    fn test_page_checkpoint() {
+        assert_eq!(page_checkpoint(&[]), None);
+        assert_eq!(
+            page_checkpoint(&[incoming("a", 10), incoming("b", 10)]),
+            None
+        );
+        assert_eq!(
+            page_checkpoint(&[incoming("a", 10), incoming("b", 20), incoming("c", 20)]),
+            Some(ServerTimestamp(10))
+        );
+    }
+
+    #[test]
+    fn test_fetch_pages() {
+        let server = [("a", 10), ("b", 20), ("c", 20), ("d", 30), ("e", 40)];
+        // Pages through `server`, using the index of the next record as the offset.
+        let fetch_page = |request: CollectionRequest| {
+            let start = request.offset.map_or(0, |offset| offset.parse().unwrap());
+            let end = (start + request.limit.unwrap().num).min(server.len());
+            let next_offset = (end < server.len()).then(|| end.to_string());
+            let records = server[start..end]
+                .iter()
+                .map(|(id, modified)| incoming(id, *modified))
+                .collect();
+            Ok((records, next_offset))
+        };
+
+        let mut staged = Vec::new();
+        let fetched = fetch_pages(
+            CollectionRequest::new("test".into()),
+            2,
+            fetch_page,
+            |records, checkpoint| {
+                let ids: Vec<_> = records.iter().map(|r| r.envelope.id.to_string()).collect();
+                staged.push((ids, checkpoint));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(fetched, 5);
+        assert_eq!(
+            staged,
+            vec![
+                (
+                    vec!["a".to_string(), "b".to_string()],
+                    Some(ServerTimestamp(10))
+                ),
+                (
+                    vec!["c".to_string(), "d".to_string()],
+                    Some(ServerTimestamp(20))
+                ),
+                (vec!["e".to_string()], None),
+            ]
+        );
+
+        // The request's limit caps the total, and newest first means no checkpoints.
+        let mut staged = Vec::new();
+        let fetched = fetch_pages(
+            CollectionRequest::new("test".into()).limit(3, RequestOrder::Newest),
+            2,
+            fetch_page,
+            |records, checkpoint| {
+                staged.push((records.len(), checkpoint));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(fetched, 3);
+        assert_eq!(staged, vec![(2, None), (1, None)]);
+    }
+}
@@ -16,6 +16,7 @@
 mod coll_state;
 mod coll_update;
 mod collection_keys;
+mod progress;
 mod request;
 mod state;
 mod status;
@@ -26,8 +27,9 @@ mod token;
 mod util;
 
 pub(crate) use coll_state::{CollState, LocalCollStateMachine};
-pub(crate) use coll_update::{fetch_incoming, CollectionUpdate};
+pub(crate) use coll_update::{fetch_incoming, fetch_incoming_batched, CollectionUpdate};
 pub(crate) use collection_keys::CollectionKeys;
+pub use progress::{EngineSyncProgress, SyncProgressObserver};
 pub(crate) use request::InfoConfiguration;
 pub(crate) use state::GlobalState;
 pub use status::{ServiceStatus, SyncResult};
@@ -35,5 +37,8 @@ pub use storage_client::{
     SetupStorageClient, Sync15ClientResponse, Sync15StorageClient, Sync15StorageClientInit,
 };
 pub use sync_multiple::{
-    sync_multiple, sync_multiple_with_command_processor, MemoryCachedState, SyncRequestInfo,
+    persist_engine_states, persisted_declined, persisted_engine_states, persisted_next_sync_after,
+    persisted_retry_after, sync_multiple, sync_multiple_with_command_processor, MemoryCachedState,
+    SyncRequestInfo,
 };
+pub use token::NodeReassignment;
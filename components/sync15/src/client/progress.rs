@@ -0,0 +1,31 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::time::Duration;
+
+/// An event in the lifecycle of a single engine's sync.
+///
+/// For each engine which isn't declined, observers see `Started`, then
+/// optionally `Downloaded` and `Uploaded`, and finally exactly one of
+/// `Finished` or `Failed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineSyncProgress {
+    /// The engine is about to sync.
+    Started,
+    /// Incoming records were downloaded from the server.
+    Downloaded { count: usize },
+    /// Outgoing records were uploaded to the server.
+    Uploaded { sent: usize, failed: usize },
+    /// The engine synced successfully.
+    Finished { duration: Duration },
+    /// The engine failed to sync. `reason` is the same string reported for
+    /// the engine in the sync result.
+    Failed { reason: String, duration: Duration },
+}
+
+/// Notified of each engine's progress while a sync is running. Observers are
+/// called on the syncing thread, so should return quickly.
+pub trait SyncProgressObserver {
+    fn on_engine_progress(&self, engine: &str, progress: EngineSyncProgress);
+}
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::request::{InfoCollections, InfoConfiguration};
 use super::storage_client::{SetupStorageClient, Sync15ClientResponse};
@@ -46,6 +47,9 @@ const DEFAULT_DECLINED: &[&str] = &[];
 /// want to move away from "globally declined" engines anyway, moving towards
 /// allowing engines to be enabled or disabled per client rather than globally.
 ///
+/// It also tracks when the server last asked us to back off until, so that
+/// an app which restarts doesn't sync before then.
+///
 /// Apps are expected to treat this as opaque, so we support serializing it.
 /// Note that this structure is *not* used to *change* the declined engines
 /// list - that will be done in the future, but the API exposed for that
@@ -58,16 +62,40 @@ pub enum PersistedGlobalState {
     /// V2 is just tracking the globally declined list.
     /// None means "I've no idea" and theoretically should only happen on the
     /// very first sync for an app.
-    V2 { declined: Option<Vec<String>> },
+    V2 {
+        declined: Option<Vec<String>>,
+        /// Milliseconds since the epoch. Older versions ignore this.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_sync_after: Option<u64>,
+        /// When a hard backoff ends, in milliseconds since the epoch. See
+        /// `SyncResult::retry_after`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+        /// The engine states the app declared for the last sync which reconciled them with
+        /// `meta/global`, so that later changes to them can be told apart from changes made
+        /// on other devices. Older versions ignore this.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        engine_states: Option<HashMap<String, bool>>,
+    },
 }
 
 impl Default for PersistedGlobalState {
     #[inline]
     fn default() -> PersistedGlobalState {
-        PersistedGlobalState::V2 { declined: None }
+        PersistedGlobalState::V2 {
+            declined: None,
+            next_sync_after: None,
+            retry_after: None,
+            engine_states: None,
+        }
     }
 }
 
+/// The longest backoff we'll believe from a persisted state. Anything later
+/// is ignored, because persisting an invalid value far in the future would
+/// otherwise break sync for good.
+const MAX_PERSISTED_BACKOFF: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub(crate) struct EngineChangesNeeded {
     pub local_resets: HashSet<String>,
@@ -145,15 +173,63 @@ fn compute_engine_states(input: EngineStateInput) -> EngineStateOutput {
 impl PersistedGlobalState {
     fn set_declined(&mut self, new_declined: Vec<String>) {
         match self {
-            Self::V2 { ref mut declined } => *declined = Some(new_declined),
+            Self::V2 {
+                ref mut declined, ..
+            } => *declined = Some(new_declined),
         }
     }
     pub(crate) fn get_declined(&self) -> &[String] {
         match self {
-            Self::V2 { declined: Some(d) } => d,
-            Self::V2 { declined: None } => &[],
+            Self::V2 {
+                declined: Some(d), ..
+            } => d,
+            Self::V2 { declined: None, .. } => &[],
         }
     }
+    pub(crate) fn set_next_sync_after(
+        &mut self,
+        when: Option<SystemTime>,
+        retry_when: Option<SystemTime>,
+    ) {
+        fn to_millis(when: Option<SystemTime>) -> Option<u64> {
+            when.map(|when| {
+                when.duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64
+            })
+        }
+        match self {
+            Self::V2 {
+                ref mut next_sync_after,
+                ref mut retry_after,
+                ..
+            } => {
+                *next_sync_after = to_millis(when);
+                *retry_after = to_millis(retry_when);
+            }
+        }
+    }
+    /// When the server asked us to back off until, if that's in the future.
+    pub(crate) fn get_next_sync_after(&self) -> Option<SystemTime> {
+        let Self::V2 {
+            next_sync_after, ..
+        } = self;
+        Self::backoff_from_millis(*next_sync_after)
+    }
+    /// When a hard backoff ends, if that's in the future.
+    pub(crate) fn set_engine_states(&mut self, states: HashMap<String, bool>) {
+        let Self::V2 { engine_states, .. } = self;
+        *engine_states = Some(states);
+    }
+    pub(crate) fn get_retry_after(&self) -> Option<SystemTime> {
+        let Self::V2 { retry_after, .. } = self;
+        Self::backoff_from_millis(*retry_after)
+    }
+    fn backoff_from_millis(millis: Option<u64>) -> Option<SystemTime> {
+        let when = UNIX_EPOCH + Duration::from_millis(millis?);
+        let now = SystemTime::now();
+        (when > now && when <= now + MAX_PERSISTED_BACKOFF).then_some(when)
+    }
 }
 
 /// Holds global Sync state, including server upload limits, the
@@ -190,7 +266,9 @@ fn new_global(pgs: &PersistedGlobalState) -> MetaGlobalRecord {
     // we previously saw a meta/global then we would have updated it with what
     // it was at the time.
     let declined = match pgs {
-        PersistedGlobalState::V2 { declined: Some(d) } => d.clone(),
+        PersistedGlobalState::V2 {
+            declined: Some(d), ..
+        } => d.clone(),
         _ => DEFAULT_DECLINED.iter().map(ToString::to_string).collect(),
     };
 
@@ -199,6 +277,9 @@ fn new_global(pgs: &PersistedGlobalState) -> MetaGlobalRecord {
         storage_version: STORAGE_VERSION,
         engines,
         declined,
+        // Only opts the account in; we still don't compress until every client record says
+        // it can read compressed payloads.
+        payload_compression: vec![crate::PayloadCompression::Deflate.as_str().to_string()],
     }
 }
 
@@ -779,6 +860,7 @@ mod tests {
             .collect(),
             // We ensure that the record we upload doesn't have a logins record.
             declined: vec!["logins".to_string()],
+            payload_compression: Vec::new(),
         };
         let client = InMemoryClient {
             info_configuration: mocked_success(InfoConfiguration::default()),
@@ -791,7 +873,12 @@ mod tests {
             meta_global: mocked_success_ts(mg, 999_000),
             crypto_keys: mocked_success_keys(keys, &root_key),
         };
-        let mut pgs = PersistedGlobalState::V2 { declined: None };
+        let mut pgs = PersistedGlobalState::V2 {
+            declined: None,
+            next_sync_after: None,
+            retry_after: None,
+            engine_states: None,
+        };
 
         let mut state_machine =
             SetupStateMachine::for_full_sync(&client, &root_key, &mut pgs, None, &NeverInterrupts);
@@ -874,6 +961,7 @@ mod tests {
             .collect(),
             // We ensure that the record we upload doesn't have a logins record.
             declined: vec!["logins".to_string()],
+            payload_compression: Vec::new(),
         };
         let collections = InfoCollections::new(
             vec![("meta", ts_metaglobal), ("crypto", ts_keys)]
@@ -890,7 +978,12 @@ mod tests {
 
         // First a test where the "previous" global state is OK to reuse.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::V2 {
+                declined: None,
+                next_sync_after: None,
+                retry_after: None,
+                engine_states: None,
+            };
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -914,7 +1007,12 @@ mod tests {
 
         // Now where the meta/global record on the server is later.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::V2 {
+                declined: None,
+                next_sync_after: None,
+                retry_after: None,
+                engine_states: None,
+            };
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -938,7 +1036,12 @@ mod tests {
 
         // Where keys on the server is later.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::V2 {
+                declined: None,
+                next_sync_after: None,
+                retry_after: None,
+                engine_states: None,
+            };
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -962,7 +1065,12 @@ mod tests {
 
         // Where there are engine-state changes.
         {
-            let mut pgs = PersistedGlobalState::V2 { declined: None };
+            let mut pgs = PersistedGlobalState::V2 {
+                declined: None,
+                next_sync_after: None,
+                retry_after: None,
+                engine_states: None,
+            };
             // A "previous" global state.
             let old_state = GlobalState {
                 config: InfoConfiguration::default(),
@@ -985,13 +1093,53 @@ mod tests {
                 &sm_seq_restarted,
             );
             let declined = match pgs {
-                PersistedGlobalState::V2 { declined: d } => d,
+                PersistedGlobalState::V2 { declined: d, .. } => d,
             };
             // and check we now consider logins as declined.
             assert_eq!(declined, Some(vec!["logins".to_string()]));
         }
     }
 
+    #[test]
+    fn test_persisted_next_sync_after() {
+        let mut pgs = PersistedGlobalState::default();
+        assert_eq!(pgs.get_next_sync_after(), None);
+        // Older versions don't write the field.
+        assert_eq!(
+            serde_json::to_string(&pgs).unwrap(),
+            r#"{"schema_version":"V2","declined":null}"#
+        );
+
+        let soon = SystemTime::now() + Duration::from_secs(60);
+        pgs.set_next_sync_after(Some(soon), None);
+        let pgs: PersistedGlobalState =
+            serde_json::from_str(&serde_json::to_string(&pgs).unwrap()).unwrap();
+        let when = pgs.get_next_sync_after().expect("should have a backoff");
+        assert!(soon.duration_since(when).unwrap() < Duration::from_millis(1));
+        assert_eq!(pgs.get_retry_after(), None);
+
+        // A hard backoff is kept separately.
+        let mut pgs = PersistedGlobalState::default();
+        pgs.set_next_sync_after(Some(soon), Some(soon));
+        let pgs: PersistedGlobalState =
+            serde_json::from_str(&serde_json::to_string(&pgs).unwrap()).unwrap();
+        let when = pgs.get_retry_after().expect("should have a hard backoff");
+        assert!(soon.duration_since(when).unwrap() < Duration::from_millis(1));
+
+        let mut pgs = PersistedGlobalState::default();
+        let expired = SystemTime::now() - Duration::from_secs(60);
+        pgs.set_next_sync_after(Some(expired), Some(expired));
+        assert_eq!(pgs.get_next_sync_after(), None);
+        assert_eq!(pgs.get_retry_after(), None);
+        pgs.set_next_sync_after(
+            Some(SystemTime::now() + Duration::from_secs(7 * 24 * 60 * 60)),
+            None,
+        );
+        assert_eq!(pgs.get_next_sync_after(), None);
+        pgs.set_next_sync_after(None, None);
+        assert_eq!(pgs.get_next_sync_after(), None);
+    }
+
     fn string_set(s: &[&str]) -> HashSet<String> {
         s.iter().map(ToString::to_string).collect()
     }
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::token::NodeReassignment;
 use crate::error::{Error, ErrorResponse};
 use crate::telemetry::SyncTelemetryPing;
 use std::collections::HashMap;
@@ -80,6 +81,15 @@ pub struct SyncResult {
     pub telemetry: SyncTelemetryPing,
 
     pub next_sync_after: Option<std::time::SystemTime>,
+
+    /// Set if the server asked us not to sync at all until then, with a `Retry-After` or a
+    /// tokenserver backoff, rather than the softer `X-Weave-Backoff` which user-initiated syncs
+    /// can ignore. This is never later than `next_sync_after`.
+    pub retry_after: Option<std::time::SystemTime>,
+
+    /// Set if the tokenserver moved us to a new storage node during this sync, in which case
+    /// every engine was reset before syncing with the new node.
+    pub node_reassignment: Option<NodeReassignment>,
 }
 
 // If `r` has a BackoffError, then returns the later backoff value.
@@ -93,14 +103,59 @@ fn advance_backoff(cur_best: SystemTime, r: &Result<(), Error>) -> SystemTime {
 }
 
 impl SyncResult {
-    pub(crate) fn set_sync_after(&mut self, backoff_duration: Duration) {
+    pub(crate) fn set_sync_after(&mut self, backoff_duration: Duration, retry_after: Duration) {
         let now = SystemTime::now();
-        let toplevel = advance_backoff(now + backoff_duration, &self.result);
-        let sync_after = self.engine_results.values().fold(toplevel, advance_backoff);
-        if sync_after <= now {
-            self.next_sync_after = None;
-        } else {
-            self.next_sync_after = Some(sync_after);
+        // Backoff errors come from the tokenserver, which doesn't have a soft backoff.
+        let toplevel = advance_backoff(now + retry_after, &self.result);
+        let retry_after = self.engine_results.values().fold(toplevel, advance_backoff);
+        let sync_after = retry_after.max(now + backoff_duration);
+        self.retry_after = (retry_after > now).then_some(retry_after);
+        self.next_sync_after = (sync_after > now).then_some(sync_after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync_result() -> SyncResult {
+        SyncResult {
+            service_status: ServiceStatus::Ok,
+            declined: None,
+            result: Ok(()),
+            engine_results: HashMap::new(),
+            telemetry: SyncTelemetryPing::new(),
+            next_sync_after: None,
+            retry_after: None,
+            node_reassignment: None,
         }
     }
+
+    #[test]
+    fn test_set_sync_after() {
+        let mut result = sync_result();
+        result.set_sync_after(Duration::ZERO, Duration::ZERO);
+        assert_eq!(result.next_sync_after, None);
+        assert_eq!(result.retry_after, None);
+
+        // A soft backoff doesn't set `retry_after`.
+        result.set_sync_after(Duration::from_secs(60), Duration::ZERO);
+        assert!(result.next_sync_after.is_some());
+        assert_eq!(result.retry_after, None);
+
+        // A hard backoff sets both, to the later of the two.
+        result.set_sync_after(Duration::from_secs(60), Duration::from_secs(120));
+        assert_eq!(result.next_sync_after, result.retry_after);
+        assert!(result.retry_after.is_some());
+
+        // As does a tokenserver backoff error.
+        let mut result = sync_result();
+        let when = SystemTime::now() + Duration::from_secs(600);
+        result
+            .engine_results
+            .insert("tabs".into(), Err(Error::BackoffError(when)));
+        result.set_sync_after(Duration::from_secs(60), Duration::ZERO);
+        assert_eq!(result.retry_after, Some(when));
+        assert_eq!(result.next_sync_after, Some(when));
+    }
 }
@@ -298,6 +298,56 @@ impl Sync15StorageClient {
         self.collection_request(Method::Get, collection_request)
     }
 
+    /// Fetches one page of records, returning the `X-Weave-Next-Offset` to use for the next page
+    /// if there are more. `xius` is sent as `X-If-Unmodified-Since`, so the request fails with a
+    /// 412 if the collection changed after we started paging through it.
+    pub fn get_encrypted_records_page(
+        &self,
+        collection_request: CollectionRequest,
+        xius: ServerTimestamp,
+    ) -> error::Result<(
+        Sync15ClientResponse<Vec<IncomingEncryptedBso>>,
+        Option<String>,
+    )> {
+        let url = build_collection_request_url(
+            Url::parse(&self.tsc.api_endpoint()?)?,
+            collection_request,
+        )?;
+        let req = self
+            .build_request(Method::Get, url)?
+            .header(header_names::X_IF_UNMODIFIED_SINCE, format!("{}", xius))?;
+        log::trace!("request: GET {} ({:?})", req.url.path(), req.url.query());
+        let resp = self.send_authorized(req)?;
+        let next_offset = resp
+            .headers
+            .get(header_names::X_WEAVE_NEXT_OFFSET)
+            .map(ToString::to_string);
+        Ok((
+            Sync15ClientResponse::from_response(resp, &self.backoff)?,
+            next_offset,
+        ))
+    }
+
+    /// Sends an already-authorized request. If the server rejects our token we fetch a new one
+    /// and retry once, unless the new token is for a different node, in which case this fails
+    /// with `Error::StorageResetError` and `take_node_reassignment()` says what happened.
+    fn send_authorized(&self, req: Request) -> error::Result<Response> {
+        let resp = req.clone().send()?;
+        if resp.status != 401 {
+            return Ok(resp);
+        }
+        log::info!("Storage server rejected our token, retrying with a new one");
+        self.tsc.invalidate_token();
+        Ok(self.authorized(req)?.send()?)
+    }
+
+    /// Returns the node reassignment the tokenserver told us about, if any. Until this is
+    /// called, every request fails with `Error::StorageResetError`; afterwards, requests go
+    /// to the new node.
+    pub fn take_node_reassignment(&self) -> Option<token::NodeReassignment> {
+        self.tsc.take_node_reassignment()
+    }
+
     #[inline]
     fn authorized(&self, req: Request) -> error::Result<Request> {
         let hawk_header_value = self.tsc.authorization(&req)?;
@@ -338,7 +388,7 @@ impl Sync15StorageClient {
             req.url.path(),
             req.url.query()
         );
-        let resp = req.send()?;
+        let resp = self.send_authorized(req)?;
 
         let result = Sync15ClientResponse::from_response(resp, &self.backoff)?;
         match result {
@@ -492,6 +542,9 @@ fn build_collection_request_url(mut base_url: Url, r: CollectionRequest) -> erro
         pairs.append_pair("sort", l.order.as_str());
         pairs.append_pair("limit", &l.num.to_string());
     }
+    if let Some(offset) = &r.offset {
+        pairs.append_pair("offset", offset);
+    }
     pairs.finish();
     drop(pairs);
     build_collection_url(base_url, r.collection)
@@ -569,6 +622,19 @@ mod test {
         .unwrap();
         assert_eq!(complex.as_str(),
             "https://example.com/sync/storage/specific?full=1&older=9876.54&newer=1234.56&sort=oldest&limit=10");
+
+        let page = build_collection_request_url(
+            Url::parse("https://example.com/sync").unwrap(),
+            CollectionRequest::new("history".into())
+                .full()
+                .limit(100, RequestOrder::Oldest)
+                .offset("abc123"),
+        )
+        .unwrap();
+        assert_eq!(
+            page.as_str(),
+            "https://example.com/sync/storage/history?full=1&sort=oldest&limit=100&offset=abc123"
+        );
     }
 
     #[cfg(feature = "sync-client")]
@@ -2,13 +2,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{CollectionUpdate, GlobalState, LocalCollStateMachine, Sync15StorageClient};
+use super::{
+    CollState, CollectionUpdate, EngineSyncProgress, GlobalState, LocalCollStateMachine,
+    Sync15StorageClient, SyncProgressObserver,
+};
+use crate::bso::{IncomingBso, OutgoingBso};
 use crate::clients_engine;
-use crate::engine::SyncEngine;
+use crate::engine::{IncomingValidation, SyncEngine};
 use crate::error::Error;
 use crate::telemetry;
-use crate::KeyBundle;
+use crate::{Guid, KeyBundle};
 use interrupt_support::Interruptee;
+use std::collections::HashSet;
 
 #[allow(clippy::too_many_arguments)]
 pub fn synchronize_with_clients_engine(
@@ -19,6 +24,7 @@ pub fn synchronize_with_clients_engine(
     engine: &dyn SyncEngine,
     fully_atomic: bool,
     telem_engine: &mut telemetry::Engine,
+    progress: Option<&dyn SyncProgressObserver>,
     interruptee: &dyn Interruptee,
 ) -> Result<(), Error> {
     let collection = engine.collection_name();
@@ -37,37 +43,75 @@ pub fn synchronize_with_clients_engine(
         }
     };
 
+    // Clients which can't read compressed payloads would fail to sync, so we only compress when
+    // every client record says it can. Without the clients engine we can't know that.
+    let coll_state = CollState {
+        compression: clients.and_then(|clients| clients.outgoing_compression(&global_state.global)),
+        ..coll_state
+    };
+
     if let Some(clients) = clients {
         engine.prepare_for_sync(&|| clients.get_client_data())?;
     }
     interruptee.err_if_interrupted()?;
+    // Uploaded in place of any corrupt incoming records.
+    let mut repairs = Vec::new();
     // We assume an "engine" manages exactly one "collection" with the engine's name.
     match engine.get_collection_request(coll_state.last_modified)? {
         None => {
             log::info!("skipping incoming for {} - not needed.", collection);
         }
-        Some(collection_request) => {
-            // Ideally we would "batch" incoming records (eg, fetch just 1000 at a time)
-            // and ask the engine to "stage" them as they come in - but currently we just read
-            // them all in one request.
-
-            // Doing this batching will involve specifying a "limit=" param and
-            // "x-if-unmodified-since" for each request, looking for an
-            // "X-Weave-Next-Offset header in the response and using that in subsequent
-            // requests.
-            // See https://mozilla-services.readthedocs.io/en/latest/storage/apis-1.5.html#syncstorage-paging
-            //
-            // But even if we had that, we need to deal with a 412 response on a subsequent batch,
-            // so we can't know if we've staged *every* record for that timestamp; the next
-            // sync must use an earlier one.
-            //
-            // For this reason, an engine can't really trust a server timestamp until the
-            // very end when we know we've staged them all.
-            let incoming = super::fetch_incoming(client, &coll_state, collection_request)?;
-            log::info!("Downloaded {} remote changes", incoming.len());
-            engine.stage_incoming(incoming, telem_engine)?;
-            interruptee.err_if_interrupted()?;
-        }
+        Some(collection_request) => match engine.get_incoming_batch_size() {
+            Some(batch_size) => {
+                // Each page is fetched with "x-if-unmodified-since", so if the collection
+                // changes part way through, we fail and the next sync starts again - from the
+                // engine's last checkpoint, if the records are sorted oldest first.
+                // See https://mozilla-services.readthedocs.io/en/latest/storage/apis-1.5.html#syncstorage-paging
+                let count = super::fetch_incoming_batched(
+                    client,
+                    &coll_state,
+                    collection_request,
+                    batch_size,
+                    |incoming, checkpoint| {
+                        if let Some(progress) = progress {
+                            progress.on_engine_progress(
+                                &collection,
+                                EngineSyncProgress::Downloaded {
+                                    count: incoming.len(),
+                                },
+                            );
+                        }
+                        let incoming =
+                            quarantine_invalid(engine, incoming, telem_engine, &mut repairs)?;
+                        engine.stage_incoming(incoming, telem_engine)?;
+                        if let Some(checkpoint) = checkpoint {
+                            engine.set_incoming_checkpoint(checkpoint)?;
+                        }
+                        interruptee.err_if_interrupted()?;
+                        Ok(())
+                    },
+                )?;
+                log::info!("Downloaded {} remote changes in batches", count);
+            }
+            None => {
+                // Engines which can't apply records as they're staged get all of them at once.
+                // An engine can't really trust a server timestamp until the very end, when we
+                // know we've staged them all.
+                let incoming = super::fetch_incoming(client, &coll_state, collection_request)?;
+                log::info!("Downloaded {} remote changes", incoming.len());
+                if let Some(progress) = progress {
+                    progress.on_engine_progress(
+                        &collection,
+                        EngineSyncProgress::Downloaded {
+                            count: incoming.len(),
+                        },
+                    );
+                }
+                let incoming = quarantine_invalid(engine, incoming, telem_engine, &mut repairs)?;
+                engine.stage_incoming(incoming, telem_engine)?;
+                interruptee.err_if_interrupted()?;
+            }
+        },
     };
 
     // Should consider adding a new `fetch_outgoing()` and having `apply()` only apply.
@@ -75,7 +119,8 @@ pub fn synchronize_with_clients_engine(
     // but that's not clear - see the discussion at
     // https://github.com/mozilla/application-services/pull/5441/files/f36274f455a6299f10e7ce56b167882c369aa806#r1189267540
     log::info!("Applying changes");
-    let outgoing = engine.apply(coll_state.last_modified, telem_engine)?;
+    let mut outgoing = engine.apply(coll_state.last_modified, telem_engine)?;
+    let repair_ids = add_repairs(&mut outgoing, repairs);
     interruptee.err_if_interrupted()?;
 
     // XXX - this upload strategy is buggy due to batching. With enough records, we will commit
@@ -98,12 +143,25 @@ pub fn synchronize_with_clients_engine(
         upload_info.failed_ids.len()
     );
 
+    if let Some(progress) = progress {
+        progress.on_engine_progress(
+            &collection,
+            EngineSyncProgress::Uploaded {
+                sent: upload_info.successful_ids.len() + upload_info.failed_ids.len(),
+                failed: upload_info.failed_ids.len(),
+            },
+        );
+    }
+
     let mut telem_outgoing = telemetry::EngineOutgoing::new();
     telem_outgoing.sent(upload_info.successful_ids.len() + upload_info.failed_ids.len());
     telem_outgoing.failed(upload_info.failed_ids.len());
     telem_engine.outgoing(telem_outgoing);
 
-    engine.set_uploaded(upload_info.modified_timestamp, upload_info.successful_ids)?;
+    // The engine doesn't know about the repairs we uploaded for it.
+    let mut successful_ids = upload_info.successful_ids;
+    successful_ids.retain(|id| !repair_ids.contains(id));
+    engine.set_uploaded(upload_info.modified_timestamp, successful_ids)?;
 
     // The above should all be per-batch :(
 
@@ -112,3 +170,82 @@ pub fn synchronize_with_clients_engine(
     log::info!("Sync finished!");
     Ok(())
 }
+
+/// Removes the records `engine` says are invalid from `incoming`, counting them as failed in
+/// telemetry and collecting any repairs to upload for them.
+fn quarantine_invalid(
+    engine: &dyn SyncEngine,
+    incoming: Vec<IncomingBso>,
+    telem_engine: &mut telemetry::Engine,
+    repairs: &mut Vec<OutgoingBso>,
+) -> Result<Vec<IncomingBso>, Error> {
+    let mut valid = Vec::with_capacity(incoming.len());
+    let mut quarantined = 0;
+    for record in incoming {
+        match engine.validate_incoming(&record)? {
+            IncomingValidation::Valid => valid.push(record),
+            IncomingValidation::Invalid { reason, repair } => {
+                log::warn!(
+                    "Quarantining incoming record {}: {}",
+                    record.envelope.id,
+                    reason
+                );
+                quarantined += 1;
+                repairs.extend(repair);
+            }
+        }
+    }
+    if quarantined > 0 {
+        let mut telem_incoming = telemetry::EngineIncoming::new();
+        telem_incoming.failed(quarantined);
+        telem_engine.incoming(telem_incoming);
+    }
+    Ok(valid)
+}
+
+/// Adds `repairs` to `outgoing`, except for those with the same ID as a record the engine is
+/// already uploading, and returns the IDs of the repairs which were added.
+fn add_repairs(outgoing: &mut Vec<OutgoingBso>, repairs: Vec<OutgoingBso>) -> HashSet<Guid> {
+    let engine_ids: HashSet<Guid> = outgoing.iter().map(|o| o.envelope.id.clone()).collect();
+    let mut repair_ids = HashSet::new();
+    for repair in repairs {
+        if !engine_ids.contains(&repair.envelope.id)
+            && repair_ids.insert(repair.envelope.id.clone())
+        {
+            outgoing.push(repair);
+        }
+    }
+    repair_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bso::OutgoingEnvelope;
+
+    fn tombstone(id: &str) -> OutgoingBso {
+        OutgoingBso::new_tombstone(OutgoingEnvelope::from(Guid::new(id)))
+    }
+
+    #[test]
+    fn test_add_repairs() {
+        let mut outgoing = vec![tombstone("aaaaaaaaaaaa")];
+        let repair_ids = add_repairs(
+            &mut outgoing,
+            vec![
+                tombstone("aaaaaaaaaaaa"),
+                tombstone("bbbbbbbbbbbb"),
+                tombstone("bbbbbbbbbbbb"),
+            ],
+        );
+        // The engine's own record wins, and duplicate repairs are only uploaded once.
+        assert_eq!(
+            outgoing
+                .iter()
+                .map(|o| o.envelope.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["aaaaaaaaaaaa", "bbbbbbbbbbbb"]
+        );
+        assert_eq!(repair_ids, HashSet::from([Guid::new("bbbbbbbbbbbb")]));
+    }
+}
@@ -5,9 +5,11 @@
 // This helps you perform a sync of multiple engines and helps you manage
 // global and local state between syncs.
 
+use super::progress::{EngineSyncProgress, SyncProgressObserver};
 use super::state::{EngineChangesNeeded, GlobalState, PersistedGlobalState, SetupStateMachine};
 use super::status::{ServiceStatus, SyncResult};
 use super::storage_client::{BackoffListener, Sync15StorageClient, Sync15StorageClientInit};
+use super::token::NodeReassignment;
 use crate::clients_engine::{self, CommandProcessor, CLIENTS_TTL_REFRESH};
 use crate::engine::{EngineSyncAssociation, SyncEngine};
 use crate::error::Error;
@@ -15,8 +17,9 @@ use crate::telemetry;
 use crate::KeyBundle;
 use interrupt_support::Interruptee;
 use std::collections::HashMap;
+use std::fmt;
 use std::result;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Info about the client to use. We reuse the client unless
 /// we discover the client_init has changed, in which case we re-create one.
@@ -47,6 +50,7 @@ pub struct MemoryCachedState {
     // These are just engined in memory, as persisting an invalid value far in the
     // future has the potential to break sync for good.
     next_sync_after: Option<SystemTime>,
+    retry_after: Option<SystemTime>,
     next_client_refresh_after: Option<SystemTime>,
 }
 
@@ -61,6 +65,11 @@ impl MemoryCachedState {
     pub fn get_next_sync_after(&self) -> Option<SystemTime> {
         self.next_sync_after
     }
+    /// Like `get_next_sync_after()`, but only for a hard backoff, which applies even to
+    /// user-initiated syncs. See `SyncResult::retry_after`.
+    pub fn get_retry_after(&self) -> Option<SystemTime> {
+        self.retry_after
+    }
     pub fn should_refresh_client(&self) -> bool {
         match self.next_client_refresh_after {
             Some(t) => SystemTime::now() > t,
@@ -131,8 +140,10 @@ pub fn sync_multiple_with_command_processor(
         result: Ok(()),
         declined: None,
         next_sync_after: None,
+        retry_after: None,
         engine_results: HashMap::with_capacity(engines.len()),
         telemetry: telemetry::SyncTelemetryPing::new(),
+        node_reassignment: None,
     };
     let backoff = super::storage_client::new_backoff_listener();
     let req_info = req_info.unwrap_or_default();
@@ -149,6 +160,7 @@ pub fn sync_multiple_with_command_processor(
         mem_cached_state,
         saw_auth_error: false,
         ignore_soft_backoff: req_info.is_user_action,
+        progress: req_info.progress,
     };
     match driver.sync() {
         Ok(()) => {
@@ -168,19 +180,118 @@ pub fn sync_multiple_with_command_processor(
     }
     // Respect `backoff` value when computing the next sync time even if we were
     // ignoring it during the sync
-    sync_result.set_sync_after(backoff.get_required_wait(false).unwrap_or_default());
+    sync_result.set_sync_after(
+        Duration::from_secs(backoff.get_backoff_secs().into()),
+        Duration::from_secs(backoff.get_retry_after_secs().into()),
+    );
     mem_cached_state.next_sync_after = sync_result.next_sync_after;
+    mem_cached_state.retry_after = sync_result.retry_after;
+    persist_next_sync_after(
+        persisted_global_state,
+        sync_result.next_sync_after,
+        sync_result.retry_after,
+    );
     log::trace!("Sync result: {:?}", sync_result);
     sync_result
 }
 
+/// Records the next sync times in the persisted state, so that they survive the app restarting.
+fn persist_next_sync_after(
+    persisted_global_state: &mut Option<String>,
+    next_sync_after: Option<SystemTime>,
+    retry_after: Option<SystemTime>,
+) {
+    let mut pgs = match persisted_global_state.as_deref() {
+        Some(persisted_string) if !persisted_string.is_empty() => {
+            match serde_json::from_str::<PersistedGlobalState>(persisted_string) {
+                Ok(pgs) => pgs,
+                // We've already reported this when preparing the state, and
+                // there's nothing useful we can add it to.
+                Err(_) => return,
+            }
+        }
+        _ => PersistedGlobalState::default(),
+    };
+    pgs.set_next_sync_after(next_sync_after, retry_after);
+    match serde_json::to_string(&pgs) {
+        Ok(persisted_string) => *persisted_global_state = Some(persisted_string),
+        Err(e) => log::warn!("Failed to persist the next sync time: {}", e),
+    }
+}
+
+/// Returns when the server asked us not to sync again until, as recorded in a persisted state
+/// returned by a previous sync, or `None` if that time has passed. This lets apps respect a
+/// backoff after they restart, when the in-memory state has been lost.
+pub fn persisted_next_sync_after(persisted_global_state: &str) -> Option<SystemTime> {
+    serde_json::from_str::<PersistedGlobalState>(persisted_global_state)
+        .ok()?
+        .get_next_sync_after()
+}
+
+/// Like `persisted_next_sync_after()`, but only for a hard backoff, which applies even to
+/// user-initiated syncs. See `SyncResult::retry_after`.
+pub fn persisted_retry_after(persisted_global_state: &str) -> Option<SystemTime> {
+    serde_json::from_str::<PersistedGlobalState>(persisted_global_state)
+        .ok()?
+        .get_retry_after()
+}
+
+/// Returns the engines which were declined after the sync that returned a persisted state, or
+/// `None` if it doesn't know, for example because that sync failed before fetching `meta/global`.
+pub fn persisted_declined(persisted_global_state: &str) -> Option<Vec<String>> {
+    match serde_json::from_str::<PersistedGlobalState>(persisted_global_state).ok()? {
+        PersistedGlobalState::V2 { declined, .. } => declined,
+    }
+}
+
+/// Returns the engine states recorded in a persisted state by `persist_engine_states()`.
+pub fn persisted_engine_states(persisted_global_state: &str) -> Option<HashMap<String, bool>> {
+    match serde_json::from_str::<PersistedGlobalState>(persisted_global_state).ok()? {
+        PersistedGlobalState::V2 { engine_states, .. } => engine_states,
+    }
+}
+
+/// Records the engine states the app declared for a sync in the persisted state it returned.
+/// This should only be done once those states have been reconciled with `meta/global`.
+pub fn persist_engine_states(
+    persisted_global_state: &mut Option<String>,
+    engine_states: &HashMap<String, bool>,
+) {
+    let mut pgs = match persisted_global_state.as_deref() {
+        Some(persisted_string) if !persisted_string.is_empty() => {
+            match serde_json::from_str::<PersistedGlobalState>(persisted_string) {
+                Ok(pgs) => pgs,
+                Err(_) => return,
+            }
+        }
+        _ => PersistedGlobalState::default(),
+    };
+    pgs.set_engine_states(engine_states.clone());
+    match serde_json::to_string(&pgs) {
+        Ok(persisted_string) => *persisted_global_state = Some(persisted_string),
+        Err(e) => log::warn!("Failed to persist the engine states: {}", e),
+    }
+}
+
 /// This is essentially a bag of information that the sync manager knows, but
 /// otherwise we won't. It should probably be rethought if it gains many more
 /// fields.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct SyncRequestInfo<'a> {
     pub engines_to_state_change: Option<&'a HashMap<String, bool>>,
     pub is_user_action: bool,
+    /// Notified as each engine syncs.
+    pub progress: Option<&'a dyn SyncProgressObserver>,
+}
+
+impl fmt::Debug for SyncRequestInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncRequestInfo")
+            .field("engines_to_state_change", &self.engines_to_state_change)
+            .field("is_user_action", &self.is_user_action)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
 }
 
 // The sync multiple driver
@@ -197,6 +308,7 @@ struct SyncMultipleDriver<'info, 'res, 'pgs, 'mcs> {
     mem_cached_state: &'mcs mut MemoryCachedState,
     ignore_soft_backoff: bool,
     saw_auth_error: bool,
+    progress: Option<&'info dyn SyncProgressObserver>,
 }
 
 impl SyncMultipleDriver<'_, '_, '_, '_> {
@@ -208,17 +320,43 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
         log::info!("Preparing client info");
         let client_info = self.prepare_client_info()?;
 
+        let mut result = self.sync_with_client(&client_info, &mut pgs);
+        if let Some(reassignment) = client_info.client.take_node_reassignment() {
+            self.reset_for_node_reassignment(reassignment)?;
+            // Our token is now for the new node, so start again using it.
+            self.saw_auth_error = false;
+            result = self.sync_with_client(&client_info, &mut pgs);
+        }
+
+        if let Some(global_state) = result? {
+            if !self.saw_auth_error {
+                log::trace!("Updating persisted global state");
+                self.mem_cached_state.last_client_info = Some(client_info);
+                self.mem_cached_state.last_global_state = Some(global_state);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Syncs everything using `client_info`, returning the global state to cache if the sync
+    /// wasn't interrupted.
+    fn sync_with_client(
+        &mut self,
+        client_info: &ClientInfo,
+        pgs: &mut PersistedGlobalState,
+    ) -> result::Result<Option<GlobalState>, Error> {
         if self.was_interrupted() {
-            return Ok(());
+            return Ok(None);
         }
 
         log::info!("Entering sync state machine");
         // Advance the state machine to the point where it can perform a full
         // sync. This may involve uploading meta/global, crypto/keys etc.
-        let mut global_state = self.run_state_machine(&client_info, &mut pgs)?;
+        let mut global_state = self.run_state_machine(client_info, pgs)?;
 
         if self.was_interrupted() {
-            return Ok(());
+            return Ok(None);
         }
 
         // Set the service status to OK here - we may adjust it based on an individual
@@ -250,7 +388,7 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
             // expect the counts to be the same most times, and a
             // failure aborts the entire sync.
             if self.was_interrupted() {
-                return Ok(());
+                return Ok(None);
             }
             self.mem_cached_state.note_client_refresh();
             Some(engine)
@@ -260,18 +398,25 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
 
         log::info!("Synchronizing engines");
 
-        let telem_sync =
-            self.sync_engines(&client_info, &mut global_state, clients_engine.as_ref());
+        let telem_sync = self.sync_engines(client_info, &mut global_state, clients_engine.as_ref());
         self.result.telemetry.sync(telem_sync);
 
         log::info!("Finished syncing engines.");
+        Ok(Some(global_state))
+    }
 
-        if !self.saw_auth_error {
-            log::trace!("Updating persisted global state");
-            self.mem_cached_state.last_client_info = Some(client_info);
-            self.mem_cached_state.last_global_state = Some(global_state);
+    /// The new node has none of our data, so forget everything we know about the old one's
+    /// collections and treat each engine as never having synced.
+    fn reset_for_node_reassignment(
+        &mut self,
+        reassignment: NodeReassignment,
+    ) -> result::Result<(), Error> {
+        log::warn!("Node reassigned, resetting all engines: {:?}", reassignment);
+        self.mem_cached_state.last_global_state = None;
+        for engine in self.engines {
+            engine.reset(&EngineSyncAssociation::Disconnected)?;
         }
-
+        self.result.node_reassignment = Some(reassignment);
         Ok(())
     }
 
@@ -307,6 +452,8 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
                 continue;
             }
             log::info!("Syncing {} engine!", name);
+            self.report_progress(&name, EngineSyncProgress::Started);
+            let started = Instant::now();
 
             let mut telem_engine = telemetry::Engine::new(&*name);
             let result = super::sync::synchronize_with_clients_engine(
@@ -317,13 +464,29 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
                 *engine,
                 true,
                 &mut telem_engine,
+                self.progress,
                 self.interruptee,
             );
 
             match result {
-                Ok(()) => log::info!("Sync of {} was successful!", name),
+                Ok(()) => {
+                    log::info!("Sync of {} was successful!", name);
+                    self.report_progress(
+                        &name,
+                        EngineSyncProgress::Finished {
+                            duration: started.elapsed(),
+                        },
+                    );
+                }
                 Err(ref e) => {
                     log::warn!("Sync of {} failed! {:?}", name, e);
+                    self.report_progress(
+                        &name,
+                        EngineSyncProgress::Failed {
+                            reason: e.to_string(),
+                            duration: started.elapsed(),
+                        },
+                    );
                     let this_status = ServiceStatus::from_err(e);
                     // The only error which forces us to discard our state is an
                     // auth error.
@@ -349,6 +512,12 @@ impl SyncMultipleDriver<'_, '_, '_, '_> {
         telem_sync
     }
 
+    fn report_progress(&self, engine: &str, progress: EngineSyncProgress) {
+        if let Some(observer) = self.progress {
+            observer.on_engine_progress(engine, progress);
+        }
+    }
+
     fn run_state_machine(
         &mut self,
         client_info: &ClientInfo,
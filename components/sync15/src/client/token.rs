@@ -15,6 +15,11 @@ use viaduct::{header_names, Request};
 
 const RETRY_AFTER_DEFAULT_MS: u64 = 10000;
 
+// We renew tokens this long before they expire (or after 90% of their lifetime
+// for very short-lived tokens), so we never present one which expires before
+// the storage server sees it.
+const TOKEN_RENEWAL_MARGIN: Duration = Duration::from_secs(5 * 60);
+
 // The TokenserverToken is the token as received directly from the token server
 // and deserialized from JSON.
 #[derive(Deserialize, Clone, PartialEq, Eq)]
@@ -141,6 +146,15 @@ impl TokenFetcher for TokenServerFetcher {
     }
 }
 
+/// The tokenserver moved this account to a different storage node. The new
+/// node has none of our data, so everything we know about the server's
+/// collections must be reset before syncing with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeReassignment {
+    pub previous_endpoint: String,
+    pub new_endpoint: String,
+}
+
 // The context stored by our TokenProvider when it has a TokenState::Token
 // state.
 struct TokenContext {
@@ -148,6 +162,7 @@ struct TokenContext {
     credentials: hawk::Credentials,
     server_timestamp: ServerTimestamp,
     valid_until: SystemTime,
+    renew_after: SystemTime,
 }
 
 // hawk::Credentials doesn't implement debug -_-
@@ -158,6 +173,7 @@ impl fmt::Debug for TokenContext {
             .field("credentials", &"(omitted)")
             .field("server_timestamp", &self.server_timestamp)
             .field("valid_until", &self.valid_until)
+            .field("renew_after", &self.renew_after)
             .finish()
     }
 }
@@ -167,24 +183,25 @@ impl TokenContext {
         token: TokenserverToken,
         credentials: hawk::Credentials,
         server_timestamp: ServerTimestamp,
-        valid_until: SystemTime,
+        fetched_at: SystemTime,
     ) -> Self {
+        let lifetime = Duration::from_secs(token.duration);
+        let valid_until = fetched_at + lifetime;
+        let renew_after = valid_until - TOKEN_RENEWAL_MARGIN.min(lifetime / 10);
         Self {
             token,
             credentials,
             server_timestamp,
             valid_until,
+            renew_after,
         }
     }
 
-    fn is_valid(&self, now: SystemTime) -> bool {
-        // We could consider making the duration a little shorter - if it
-        // only has 1 second validity there seems a reasonable chance it will
-        // have expired by the time it gets presented to the remote that wants
-        // it.
-        // Either way though, we will eventually need to handle a token being
-        // rejected as a non-fatal error and recover, so maybe we don't care?
-        now < self.valid_until
+    fn needs_renewal(&self, now: SystemTime) -> bool {
+        // Even a token we renew early can be rejected (eg, if our clock is
+        // wrong), in which case the storage client invalidates it and we
+        // fetch a new one.
+        now >= self.renew_after
     }
 
     fn authorization(&self, req: &Request) -> Result<String> {
@@ -230,9 +247,12 @@ enum TokenState {
     // elt is the api_endpoint we had before we hit the backoff error.
     // XXX - should we roll Backoff and Failed together?
     Backoff(SystemTime, Option<String>),
-    // api_endpoint changed - we are never going to get a token nor move out
-    // of this state.
-    NodeReassigned,
+    // The server rejected our token before it expired, so we need a new one.
+    // elt is the api_endpoint of the rejected token.
+    Rejected(String),
+    // api_endpoint changed. Requests fail until the caller takes the
+    // reassignment (and resets its state), after which we use the new token.
+    NodeReassigned(NodeReassignment, TokenContext),
 }
 
 /// The generic TokenProvider implementation - long lived and fetches tokens
@@ -260,7 +280,7 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
     fn fetch_context(&self) -> Result<TokenContext> {
         let result = self.fetcher.fetch_token()?;
         let token = result.token;
-        let valid_until = SystemTime::now() + Duration::from_secs(token.duration);
+        let fetched_at = self.fetcher.now();
 
         let credentials = hawk::Credentials {
             id: token.id.clone(),
@@ -271,7 +291,7 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
             token,
             credentials,
             result.server_timestamp,
-            valid_until,
+            fetched_at,
         ))
     }
 
@@ -293,7 +313,11 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
                                 prev,
                                 tc.token.api_endpoint
                             );
-                            TokenState::NodeReassigned
+                            let reassignment = NodeReassignment {
+                                previous_endpoint: prev.to_string(),
+                                new_endpoint: tc.token.api_endpoint.clone(),
+                            };
+                            TokenState::NodeReassigned(reassignment, tc)
                         }
                     }
                     None => {
@@ -323,12 +347,15 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
                 Some(self.fetch_token(existing_endpoint.as_ref().map(String::as_str)))
             }
             TokenState::Token(existing_context) => {
-                if existing_context.is_valid(self.fetcher.now()) {
-                    None
-                } else {
+                if existing_context.needs_renewal(self.fetcher.now()) {
                     Some(self.fetch_token(Some(existing_context.token.api_endpoint.as_str())))
+                } else {
+                    None
                 }
             }
+            TokenState::Rejected(existing_endpoint) => {
+                Some(self.fetch_token(Some(existing_endpoint.as_str())))
+            }
             TokenState::Backoff(ref until, ref existing_endpoint) => {
                 if let Ok(remaining) = until.duration_since(self.fetcher.now()) {
                     log::debug!("enforcing existing backoff - {:?} remains", remaining);
@@ -338,8 +365,8 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
                     Some(self.fetch_token(existing_endpoint.as_ref().map(String::as_str)))
                 }
             }
-            TokenState::NodeReassigned => {
-                // We never leave this state.
+            TokenState::NodeReassigned(..) => {
+                // We only leave this state via `take_node_reassignment()`.
                 None
             }
         }
@@ -359,9 +386,9 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
         // Now re-fetch the state we should use for this call - if it's
         // anything other than TokenState::Token we will fail.
         match state {
-            TokenState::NoToken => {
+            TokenState::NoToken | TokenState::Rejected(_) => {
                 // it should be impossible to get here.
-                panic!("Can't be in NoToken state after advancing");
+                panic!("Can't be in {:?} state after advancing", state);
             }
             TokenState::Token(ref token_context) => {
                 // make the call.
@@ -371,8 +398,8 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
                 // We swap the error out of the state enum and return it.
                 Err(e.take().unwrap())
             }
-            TokenState::NodeReassigned => {
-                // this is unrecoverable.
+            TokenState::NodeReassigned(..) => {
+                // Our caller needs to reset before using the new node.
                 Err(ErrorKind::StorageResetError)
             }
             TokenState::Backoff(ref remaining, _) => Err(ErrorKind::BackoffError(*remaining)),
@@ -390,9 +417,30 @@ impl<TF: TokenFetcher> TokenProviderImpl<TF> {
     fn api_endpoint(&self) -> Result<String> {
         self.with_token(|ctx| Ok(ctx.token.api_endpoint.clone()))
     }
-    // TODO: we probably want a "drop_token/context" type method so that when
-    // using a token with some validity fails the caller can force a new one
-    // (in which case the new token request will probably fail with a 401)
+
+    // Called when the server rejects our token, so the next request fetches
+    // a new one.
+    fn invalidate_token(&self) {
+        let state: &mut TokenState = &mut self.current_state.borrow_mut();
+        if let TokenState::Token(ctx) = state {
+            log::info!("Token was rejected, will fetch a new one");
+            *state = TokenState::Rejected(ctx.token.api_endpoint.clone());
+        }
+    }
+
+    fn take_node_reassignment(&self) -> Option<NodeReassignment> {
+        let state: &mut TokenState = &mut self.current_state.borrow_mut();
+        match std::mem::replace(state, TokenState::NoToken) {
+            TokenState::NodeReassigned(reassignment, ctx) => {
+                *state = TokenState::Token(ctx);
+                Some(reassignment)
+            }
+            other => {
+                *state = other;
+                None
+            }
+        }
+    }
 }
 
 // The public concrete object exposed by this module
@@ -420,6 +468,14 @@ impl TokenProvider {
     pub fn api_endpoint(&self) -> Result<String> {
         self.imp.api_endpoint()
     }
+
+    pub fn invalidate_token(&self) {
+        self.imp.invalidate_token()
+    }
+
+    pub fn take_node_reassignment(&self) -> Option<NodeReassignment> {
+        self.imp.take_node_reassignment()
+    }
 }
 
 #[cfg(test)]
@@ -552,6 +608,92 @@ mod tests {
         assert_eq!(counter.get(), 2);
     }
 
+    #[test]
+    fn test_renewal_margin() {
+        let counter: Cell<u32> = Cell::new(0);
+        let fetch = || {
+            counter.set(counter.get() + 1);
+            Ok(TokenFetchResult {
+                token: TokenserverToken {
+                    id: "id".to_string(),
+                    key: "key".to_string(),
+                    api_endpoint: "api_endpoint".to_string(),
+                    uid: 1,
+                    duration: 3600,
+                    hashed_fxa_uid: "hash".to_string(),
+                },
+                server_timestamp: ServerTimestamp(0i64),
+            })
+        };
+        let now: Cell<SystemTime> = Cell::new(SystemTime::now());
+        let tsc = make_tsc(fetch, || now.get());
+
+        tsc.api_endpoint().expect("should get a valid token");
+        now.set(now.get() + Duration::from_secs(3600 - 301));
+        tsc.api_endpoint().expect("should reuse existing token");
+        assert_eq!(counter.get(), 1);
+
+        // The token is still valid, but close enough to expiring that we
+        // renew it.
+        now.set(now.get() + Duration::from_secs(2));
+        tsc.api_endpoint().expect("should renew");
+        assert_eq!(counter.get(), 2);
+    }
+
+    #[test]
+    fn test_invalidate_and_reassign() {
+        let counter: Cell<u32> = Cell::new(0);
+        let endpoint: RefCell<String> = RefCell::new("endpoint-1".to_string());
+        let fetch = || {
+            counter.set(counter.get() + 1);
+            Ok(TokenFetchResult {
+                token: TokenserverToken {
+                    id: "id".to_string(),
+                    key: "key".to_string(),
+                    api_endpoint: endpoint.borrow().clone(),
+                    uid: 1,
+                    duration: 1000,
+                    hashed_fxa_uid: "hash".to_string(),
+                },
+                server_timestamp: ServerTimestamp(0i64),
+            })
+        };
+        let tsc = make_tsc(fetch, SystemTime::now);
+
+        assert_eq!(tsc.api_endpoint().unwrap(), "endpoint-1");
+        assert_eq!(counter.get(), 1);
+
+        // A rejected token is re-fetched even though it hasn't expired.
+        tsc.invalidate_token();
+        assert_eq!(tsc.api_endpoint().unwrap(), "endpoint-1");
+        assert_eq!(counter.get(), 2);
+        assert_eq!(tsc.take_node_reassignment(), None);
+
+        // If the new token is for a different node, we fail until the
+        // reassignment is taken, then use the new node.
+        *endpoint.borrow_mut() = "endpoint-2".to_string();
+        tsc.invalidate_token();
+        assert!(matches!(
+            tsc.api_endpoint(),
+            Err(ErrorKind::StorageResetError)
+        ));
+        assert!(matches!(
+            tsc.api_endpoint(),
+            Err(ErrorKind::StorageResetError)
+        ));
+        assert_eq!(counter.get(), 3);
+        assert_eq!(
+            tsc.take_node_reassignment(),
+            Some(NodeReassignment {
+                previous_endpoint: "endpoint-1".to_string(),
+                new_endpoint: "endpoint-2".to_string(),
+            })
+        );
+        assert_eq!(tsc.take_node_reassignment(), None);
+        assert_eq!(tsc.api_endpoint().unwrap(), "endpoint-2");
+        assert_eq!(counter.get(), 3);
+    }
+
     #[test]
     fn test_server_url() {
         assert_eq!(
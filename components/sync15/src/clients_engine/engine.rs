@@ -11,7 +11,8 @@ use crate::client::{
 };
 use crate::client_types::{ClientData, RemoteClient};
 use crate::engine::CollectionRequest;
-use crate::{error::Result, Guid, KeyBundle};
+use crate::record_types::MetaGlobalRecord;
+use crate::{error::Result, Guid, KeyBundle, PayloadCompression};
 use interrupt_support::Interruptee;
 
 use super::{
@@ -29,6 +30,7 @@ struct Driver<'a> {
     interruptee: &'a dyn Interruptee,
     config: &'a InfoConfiguration,
     recent_clients: HashMap<String, RemoteClient>,
+    all_clients_read_compressed: bool,
 }
 
 impl<'a> Driver<'a> {
@@ -42,11 +44,20 @@ impl<'a> Driver<'a> {
             interruptee,
             config,
             recent_clients: HashMap::new(),
+            all_clients_read_compressed: true,
         }
     }
 
     fn note_recent_client(&mut self, client: &ClientRecord) {
         self.recent_clients.insert(client.id.clone(), client.into());
+        let deflate = PayloadCompression::Deflate.as_str();
+        if !client
+            .payload_compression
+            .iter()
+            .any(|name| name == deflate)
+        {
+            self.all_clients_read_compressed = false;
+        }
     }
 
     fn sync(
@@ -200,6 +211,7 @@ impl<'a> Driver<'a> {
             typ: settings.device_type,
             commands: Vec::new(),
             fxa_device_id: Some(settings.fxa_device_id.clone()),
+            payload_compression: vec![PayloadCompression::Deflate.as_str().into()],
             version: None,
             protocols: vec!["1.5".into()],
             form_factor: None,
@@ -237,6 +249,7 @@ pub struct Engine<'a> {
     pub command_processor: &'a dyn CommandProcessor,
     pub interruptee: &'a dyn Interruptee,
     pub recent_clients: HashMap<String, RemoteClient>,
+    all_clients_read_compressed: bool,
 }
 
 impl Engine<'_> {
@@ -250,6 +263,7 @@ impl Engine<'_> {
             command_processor,
             interruptee,
             recent_clients: HashMap::new(),
+            all_clients_read_compressed: false,
         }
     }
 
@@ -291,6 +305,9 @@ impl Engine<'_> {
                 .cloned()
                 .unwrap_or_default(),
             key: coll_keys.key_for_collection(COLLECTION_NAME).clone(),
+            // We can't know if every client reads compressed payloads until we've read
+            // their records, and they're small anyway.
+            compression: None,
         };
 
         let inbound = self.fetch_incoming(storage_client, &coll_state)?;
@@ -303,6 +320,7 @@ impl Engine<'_> {
 
         let outgoing = driver.sync(inbound, should_refresh_client)?;
         self.recent_clients = driver.recent_clients;
+        self.all_clients_read_compressed = driver.all_clients_read_compressed;
 
         self.interruptee.err_if_interrupted()?;
         let upload_info = CollectionUpdate::new_from_changeset(
@@ -346,6 +364,15 @@ impl Engine<'_> {
         self.command_processor.settings().fxa_device_id.clone()
     }
 
+    /// How outgoing payloads should be compressed, if at all. We only compress
+    /// when meta/global opts in and every client record says it can read
+    /// compressed payloads, so this is `None` until we've synced.
+    pub fn outgoing_compression(&self, global: &MetaGlobalRecord) -> Option<PayloadCompression> {
+        global
+            .outgoing_compression()
+            .filter(|_| self.all_clients_read_compressed)
+    }
+
     pub fn get_client_data(&self) -> ClientData {
         ClientData {
             local_client_id: self.local_client_id(),
@@ -512,6 +539,7 @@ mod tests {
                 "args": [],
             }],
             "fxaDeviceId": "deviceAAAAAA",
+            "payloadCompression": ["deflate"],
             "protocols": ["1.5"],
         }, {
             "id": "deviceBBBBBB",
@@ -654,6 +682,7 @@ mod tests {
             "type": "desktop",
             "commands": [],
             "fxaDeviceId": "deviceAAAAAA",
+            "payloadCompression": ["deflate"],
             "protocols": ["1.5"],
         }]);
 
@@ -684,6 +713,7 @@ mod tests {
             "type": "desktop",
             "commands": [],
             "fxaDeviceId": "deviceAAAAAA",
+            "payloadCompression": ["deflate"],
             "protocols": ["1.5"],
         }]));
         let outgoing = driver.sync(inbound, false).expect("Should sync clients");
@@ -691,6 +721,45 @@ mod tests {
         assert_eq!(outgoing.len(), 1);
     }
 
+    #[test]
+    fn test_clients_compression_support() {
+        let processor = TestProcessor {
+            settings: Settings {
+                fxa_device_id: "deviceAAAAAA".into(),
+                device_name: "Laptop".into(),
+                device_type: DeviceType::Desktop,
+            },
+            outgoing_commands: HashSet::new(),
+        };
+        let config = InfoConfiguration::default();
+
+        let iphone = json!({
+            "id": "deviceBBBBBB",
+            "name": "iPhone",
+            "type": "mobile",
+            "fxaDeviceId": "iPhooooooone",
+            "payloadCompression": ["zstd", "deflate"],
+        });
+        let mut driver = Driver::new(&processor, &NeverInterrupts, &config);
+        driver
+            .sync(inbound_from_clients(json!([iphone.clone()])), false)
+            .expect("Should sync clients");
+        assert!(driver.all_clients_read_compressed);
+
+        // A single client which doesn't know about compression turns it off.
+        let fenix = json!({
+            "id": "deviceCCCCCC",
+            "name": "Fenix",
+            "type": "mobile",
+            "fxaDeviceId": "deviceCCCCCC",
+        });
+        let mut driver = Driver::new(&processor, &NeverInterrupts, &config);
+        driver
+            .sync(inbound_from_clients(json!([iphone, fenix])), false)
+            .expect("Should sync clients");
+        assert!(!driver.all_clients_read_compressed);
+    }
+
     #[test]
     fn test_fresh_client_record() {
         let processor = TestProcessor {
@@ -763,6 +832,7 @@ mod tests {
             "name": "Laptop",
             "type": "desktop",
             "fxaDeviceId": "deviceAAAAAA",
+            "payloadCompression": ["deflate"],
             "protocols": ["1.5"],
             "ttl": CLIENTS_TTL,
         }]);
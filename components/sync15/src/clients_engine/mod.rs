@@ -90,4 +90,11 @@ pub enum Command {
     ResetAll,
     /// Resets local sync state for a specific engine.
     Reset(String),
+    /// Asks the client to open a URI. Desktop sends this for "send tab" to
+    /// older clients; `sender_id` is the record ID of the sending client.
+    DisplayUri {
+        uri: String,
+        sender_id: String,
+        title: Option<String>,
+    },
 }
@@ -24,6 +24,11 @@ pub struct ClientRecord {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fxa_device_id: Option<String>,
 
+    /// The payload compressions this client can read. We only compress
+    /// payloads once every client on the account lists the compression here.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub payload_compression: Vec<String>,
+
     /// `version`, `protocols`, `formfactor`, `os`, `appPackage`, `application`,
     /// and `device` are unused and optional in all implementations (Desktop,
     /// iOS, and Fennec), but we round-trip them.
@@ -112,12 +117,34 @@ impl CommandRecord {
         }
     }
 
+    // `displayURI` has a URI and sender ID, and a title which older desktop versions
+    // don't send.
+    fn get_display_uri_args(&self) -> Option<Command> {
+        match self.args.as_slice() {
+            [Some(uri), Some(sender_id)] => Some(Command::DisplayUri {
+                uri: uri.clone(),
+                sender_id: sender_id.clone(),
+                title: None,
+            }),
+            [Some(uri), Some(sender_id), title] => Some(Command::DisplayUri {
+                uri: uri.clone(),
+                sender_id: sender_id.clone(),
+                title: title.clone(),
+            }),
+            _ => {
+                log::error!("Invalid arguments for 'displayURI' command");
+                None
+            }
+        }
+    }
+
     /// Converts a serialized command into one that we can apply. Returns `None`
     /// if we don't support the command.
     pub fn as_command(&self) -> Option<Command> {
         match self.name.as_str() {
             "wipeEngine" => self.get_single_string_arg().map(Command::Wipe),
             "resetEngine" => self.get_single_string_arg().map(Command::Reset),
+            "displayURI" => self.get_display_uri_args(),
             "resetAll" => {
                 if self.args.is_empty() {
                     Some(Command::ResetAll)
@@ -150,6 +177,15 @@ impl From<Command> for CommandRecord {
                 args: Vec::new(),
                 flow_id: None,
             },
+            Command::DisplayUri {
+                uri,
+                sender_id,
+                title,
+            } => CommandRecord {
+                name: "displayURI".into(),
+                args: vec![Some(uri), Some(sender_id), title],
+                flow_id: None,
+            },
         }
     }
 }
@@ -171,6 +207,54 @@ mod test {
         let ser = serde_json::json!({"command": "resetAll"});
         let record: CommandRecord = serde_json::from_value(ser).unwrap();
         assert_eq!(record.as_command(), Some(Command::ResetAll));
+
+        let ser = serde_json::json!({
+            "command": "displayURI",
+            "args": ["https://example.com", "deviceAAAAAA", "Example"],
+        });
+        let record: CommandRecord = serde_json::from_value(ser).unwrap();
+        assert_eq!(
+            record.as_command(),
+            Some(Command::DisplayUri {
+                uri: "https://example.com".to_string(),
+                sender_id: "deviceAAAAAA".to_string(),
+                title: Some("Example".to_string()),
+            })
+        );
+
+        let ser = serde_json::json!({
+            "command": "displayURI",
+            "args": ["https://example.com", "deviceAAAAAA"],
+        });
+        let record: CommandRecord = serde_json::from_value(ser).unwrap();
+        assert_eq!(
+            record.as_command(),
+            Some(Command::DisplayUri {
+                uri: "https://example.com".to_string(),
+                sender_id: "deviceAAAAAA".to_string(),
+                title: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        let commands = [
+            Command::Wipe("bookmarks".to_string()),
+            Command::Reset("history".to_string()),
+            Command::ResetAll,
+            Command::DisplayUri {
+                uri: "https://example.com".to_string(),
+                sender_id: "deviceAAAAAA".to_string(),
+                title: None,
+            },
+        ];
+        for command in commands {
+            let record = CommandRecord::from(command.clone());
+            let ser = serde_json::to_value(&record).unwrap();
+            let record: CommandRecord = serde_json::from_value(ser).unwrap();
+            assert_eq!(record.as_command(), Some(command));
+        }
     }
 
     #[test]
@@ -193,6 +277,14 @@ mod test {
         let ser = serde_json::json!({"command": "resetAll", "args": ["foo"]});
         let record: CommandRecord = serde_json::from_value(ser).unwrap();
         assert_eq!(record.as_command(), None);
+
+        let ser = serde_json::json!({"command": "displayURI", "args": ["https://example.com"]});
+        let record: CommandRecord = serde_json::from_value(ser).unwrap();
+        assert_eq!(record.as_command(), None);
+
+        let ser = serde_json::json!({"command": "displayURI", "args": [null, "deviceAAAAAA"]});
+        let record: CommandRecord = serde_json::from_value(ser).unwrap();
+        assert_eq!(record.as_command(), None);
     }
 
     #[test]
@@ -4,8 +4,87 @@
 
 use crate::error;
 use crate::key_bundle::KeyBundle;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The largest cleartext we'll inflate a compressed payload to. The server limits the size of
+/// the compressed record, but a small stream can inflate to gigabytes, so we need our own
+/// limit. This is well above what any real record compresses from.
+const MAX_DECOMPRESSED_PAYLOAD_BYTES: u64 = 16 * 1024 * 1024;
+
+// The cleartext of a compressed payload is a NUL, the name of the compression, another NUL,
+// and then the compressed bytes. JSON can't start with a NUL, so this can't be confused with an
+// uncompressed payload, and because it's part of what we encrypt, it's covered by the HMAC.
+const COMPRESSION_MARKER: u8 = 0;
+
+/// How the cleartext of an [EncryptedPayload] was compressed before it was encrypted.
+///
+/// Clients which don't know about compression can't read compressed payloads, so we only
+/// compress when meta/global and the record of every client on the account say they can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadCompression {
+    Deflate,
+}
+
+impl PayloadCompression {
+    /// The name of this compression in meta/global, client records and payloads.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PayloadCompression::Deflate => "deflate",
+        }
+    }
+
+    fn from_name(name: &[u8]) -> error::Result<Self> {
+        match name {
+            b"deflate" => Ok(PayloadCompression::Deflate),
+            _ => Err(error::Error::PayloadCompressionError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Unknown payload compression {:?}",
+                    String::from_utf8_lossy(name)
+                ),
+            ))),
+        }
+    }
+
+    fn compress(self, cleartext: &[u8]) -> error::Result<Vec<u8>> {
+        match self {
+            PayloadCompression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(cleartext)
+                    .and_then(|_| encoder.finish())
+                    .map_err(error::Error::PayloadCompressionError)
+            }
+        }
+    }
+
+    fn decompress(self, compressed: &[u8]) -> error::Result<String> {
+        self.decompress_with_limit(compressed, MAX_DECOMPRESSED_PAYLOAD_BYTES)
+    }
+
+    fn decompress_with_limit(self, compressed: &[u8], limit: u64) -> error::Result<String> {
+        match self {
+            PayloadCompression::Deflate => {
+                let mut cleartext = String::new();
+                // Read one byte past the limit, so we can tell if the stream went over it.
+                DeflateDecoder::new(compressed)
+                    .take(limit + 1)
+                    .read_to_string(&mut cleartext)
+                    .map_err(error::Error::PayloadCompressionError)?;
+                if cleartext.len() as u64 > limit {
+                    return Err(error::Error::PayloadCompressionError(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("Decompressed payload is larger than {limit} bytes"),
+                    )));
+                }
+                Ok(cleartext)
+            }
+        }
+    }
+}
 
 /// A representation of an encrypted payload. Used as the payload in EncryptedBso and
 /// also anywhere else the sync keys might be used to encrypt/decrypt, such as send-tab payloads.
@@ -24,7 +103,23 @@ impl EncryptedPayload {
     }
 
     pub fn decrypt(&self, key: &KeyBundle) -> error::Result<String> {
-        key.decrypt(&self.ciphertext, &self.iv, &self.hmac)
+        let cleartext = key.decrypt_bytes(&self.ciphertext, &self.iv, &self.hmac)?;
+        match cleartext.strip_prefix(&[COMPRESSION_MARKER]) {
+            None => Ok(String::from_utf8(cleartext)?),
+            Some(marked) => {
+                let (name, compressed) = marked
+                    .iter()
+                    .position(|&b| b == COMPRESSION_MARKER)
+                    .map(|end| (&marked[..end], &marked[end + 1..]))
+                    .ok_or_else(|| {
+                        error::Error::PayloadCompressionError(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Unterminated payload compression name",
+                        ))
+                    })?;
+                PayloadCompression::from_name(name)?.decompress(compressed)
+            }
+        }
     }
 
     pub fn decrypt_into<T>(&self, key: &KeyBundle) -> error::Result<T>
@@ -44,6 +139,27 @@ impl EncryptedPayload {
         })
     }
 
+    /// Like `from_cleartext`, but compresses the cleartext first if that makes it smaller.
+    pub fn from_cleartext_compressed(
+        key: &KeyBundle,
+        cleartext: String,
+        compression: PayloadCompression,
+    ) -> error::Result<Self> {
+        let mut marked = vec![COMPRESSION_MARKER];
+        marked.extend_from_slice(compression.as_str().as_bytes());
+        marked.push(COMPRESSION_MARKER);
+        marked.extend(compression.compress(cleartext.as_bytes())?);
+        if marked.len() >= cleartext.len() {
+            return Self::from_cleartext(key, cleartext);
+        }
+        let (enc_base64, iv_base64, hmac_base16) = key.encrypt_bytes_rand_iv(&marked)?;
+        Ok(EncryptedPayload {
+            iv: iv_base64,
+            hmac: hmac_base16,
+            ciphertext: enc_base64,
+        })
+    }
+
     pub fn from_cleartext_payload<T: Serialize>(
         key: &KeyBundle,
         cleartext_payload: &T,
@@ -107,4 +223,101 @@ mod tests {
         // Note: ErrorKind isn't PartialEq, so.
         assert!(matches!(e, error::Error::CryptoError(_)));
     }
+
+    #[test]
+    fn test_roundtrip_compressed_record() {
+        let key = KeyBundle::new_random().unwrap();
+        let cleartext = serde_json::to_string(&json!({
+            "id": "aaaaaaaaaaaa",
+            "urlHistory": vec!["https://example.com/a/long/path/to/compress"; 10],
+        }))
+        .unwrap();
+        let payload = EncryptedPayload::from_cleartext_compressed(
+            &key,
+            cleartext.clone(),
+            PayloadCompression::Deflate,
+        )
+        .unwrap();
+        let decrypted = key
+            .decrypt_bytes(&payload.ciphertext, &payload.iv, &payload.hmac)
+            .unwrap();
+        assert!(decrypted.starts_with(b"\0deflate\0"));
+        let uncompressed = EncryptedPayload::from_cleartext(&key, cleartext.clone()).unwrap();
+        assert!(payload.ciphertext.len() < uncompressed.ciphertext.len());
+        assert_eq!(payload.decrypt(&key).unwrap(), cleartext);
+
+        // Nothing outside the encrypted cleartext says it's compressed.
+        let val_rec = serde_json::to_string(&serde_json::to_value(&payload).unwrap()).unwrap();
+        assert!(!val_rec.contains("compression"));
+        assert_eq!(payload.serialized_len(), val_rec.len());
+        let parsed: EncryptedPayload = serde_json::from_str(&val_rec).unwrap();
+        assert_eq!(parsed.decrypt(&key).unwrap(), cleartext);
+    }
+
+    #[test]
+    fn test_compression_skipped_when_larger() {
+        let key = KeyBundle::new_random().unwrap();
+        let payload = EncryptedPayload::from_cleartext_compressed(
+            &key,
+            "{}".to_string(),
+            PayloadCompression::Deflate,
+        )
+        .unwrap();
+        let decrypted = key
+            .decrypt_bytes(&payload.ciphertext, &payload.iv, &payload.hmac)
+            .unwrap();
+        assert_eq!(decrypted, b"{}");
+        assert_eq!(payload.decrypt(&key).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_unknown_compression() {
+        let key = KeyBundle::new_random().unwrap();
+        for cleartext in [&b"\0zstd\0abc"[..], &b"\0deflate"[..]] {
+            let (ciphertext, iv, hmac) = key.encrypt_bytes_rand_iv(cleartext).unwrap();
+            let payload = EncryptedPayload {
+                iv,
+                hmac,
+                ciphertext,
+            };
+            let e = payload
+                .decrypt(&key)
+                .expect_err("Should fail because the compression isn't known");
+            assert!(matches!(e, error::Error::PayloadCompressionError(_)));
+        }
+    }
+
+    #[test]
+    fn test_decompressed_size_limit() {
+        let deflate = PayloadCompression::Deflate;
+        let cleartext = "a".repeat(1000);
+        let compressed = deflate.compress(cleartext.as_bytes()).unwrap();
+        assert_eq!(
+            deflate.decompress_with_limit(&compressed, 1000).unwrap(),
+            cleartext
+        );
+        let e = deflate
+            .decompress_with_limit(&compressed, 999)
+            .expect_err("Should fail because the payload is too large");
+        assert!(matches!(e, error::Error::PayloadCompressionError(_)));
+
+        // A few KB which inflates past the real limit.
+        let bomb = deflate
+            .compress(&vec![b' '; MAX_DECOMPRESSED_PAYLOAD_BYTES as usize + 1])
+            .unwrap();
+        assert!(bomb.len() < 64 * 1024);
+        let key = KeyBundle::new_random().unwrap();
+        let (ciphertext, iv, hmac) = key
+            .encrypt_bytes_rand_iv(&[&b"\0deflate\0"[..], &bomb].concat())
+            .unwrap();
+        let payload = EncryptedPayload {
+            iv,
+            hmac,
+            ciphertext,
+        };
+        let e = payload
+            .decrypt(&key)
+            .expect_err("Should fail because the payload is too large");
+        assert!(matches!(e, error::Error::PayloadCompressionError(_)));
+    }
 }
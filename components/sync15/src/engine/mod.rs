@@ -36,4 +36,6 @@ pub use bridged_engine::{ApplyResults, BridgedEngine, BridgedEngineAdaptor};
 pub(crate) use request::CollectionPost;
 
 pub use request::{CollectionRequest, RequestOrder};
-pub use sync_engine::{CollSyncIds, EngineSyncAssociation, SyncEngine, SyncEngineId};
+pub use sync_engine::{
+    CollSyncIds, EngineSyncAssociation, IncomingValidation, SyncEngine, SyncEngineId,
+};
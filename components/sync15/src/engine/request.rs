@@ -11,6 +11,8 @@ pub struct CollectionRequest {
     pub limit: Option<RequestLimit>,
     pub older: Option<ServerTimestamp>,
     pub newer: Option<ServerTimestamp>,
+    // The `X-Weave-Next-Offset` from the previous page of a batched fetch.
+    pub offset: Option<String>,
 }
 
 impl CollectionRequest {
@@ -55,6 +57,12 @@ impl CollectionRequest {
         self.limit = Some(RequestLimit { num, order });
         self
     }
+
+    #[inline]
+    pub fn offset(mut self, offset: impl Into<String>) -> CollectionRequest {
+        self.offset = Some(offset.into());
+        self
+    }
 }
 
 // This is just used interally - consumers just provide the content, not request params.
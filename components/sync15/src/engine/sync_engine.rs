@@ -7,7 +7,9 @@ use crate::bso::{IncomingBso, OutgoingBso};
 use crate::client_types::ClientData;
 use crate::{telemetry, CollectionName, Guid, ServerTimestamp};
 use anyhow::Result;
+use interrupt_support::SqlInterruptHandle;
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CollSyncIds {
@@ -29,6 +31,23 @@ pub enum EngineSyncAssociation {
     Connected(CollSyncIds),
 }
 
+/// What to do with an incoming record, as decided by [SyncEngine::validate_incoming].
+#[derive(Debug)]
+pub enum IncomingValidation {
+    /// The record should be staged as usual.
+    Valid,
+    /// The record is corrupt, so is quarantined - it isn't staged, and is counted as failed in
+    /// telemetry - rather than failing the engine's sync.
+    Invalid {
+        /// Why the record is invalid, for logging.
+        reason: &'static str,
+        /// If set, uploaded in place of the corrupt record so other clients don't trip over it
+        /// too - eg, a tombstone, or the record rebuilt from local data. It's dropped if the
+        /// engine's `apply()` returns a record with the same ID.
+        repair: Option<OutgoingBso>,
+    },
+}
+
 /// The concrete `SyncEngine` implementations
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SyncEngineId {
@@ -60,6 +79,17 @@ impl SyncEngineId {
         .into_iter()
     }
 
+    /// Engines which must sync before this one when they're syncing too. This is the one place
+    /// dependencies between engines are declared. The clients collection isn't listed, because
+    /// it always syncs before any engine.
+    pub fn sync_after(&self) -> &'static [SyncEngineId] {
+        match self {
+            // Frecency depends on whether a URL is bookmarked.
+            Self::History => &[Self::Bookmarks],
+            _ => &[],
+        }
+    }
+
     // Get the string identifier for this engine.  This must match the strings in SyncEngineSelection.
     pub fn name(&self) -> &'static str {
         match self {
@@ -168,6 +198,20 @@ pub trait SyncEngine {
         unimplemented!("This engine does not support local encryption");
     }
 
+    /// The interrupt handle for the engine's database connection, if it has one. When a sync is
+    /// interrupted, the sync manager uses it to stop the engine's SQL statements, including
+    /// those in `apply()`, rather than waiting for the engine to reach its next checkpoint.
+    fn sql_interrupt_handle(&self) -> Option<Arc<SqlInterruptHandle>> {
+        None
+    }
+
+    /// Checks an incoming record before it's staged. Invalid records are quarantined instead of
+    /// being passed to `stage_incoming()`, and may be repaired on the server - see
+    /// [IncomingValidation]. The default accepts every record.
+    fn validate_incoming(&self, _record: &IncomingBso) -> Result<IncomingValidation> {
+        Ok(IncomingValidation::Valid)
+    }
+
     /// Stage some incoming records. This might be called multiple times in the same sync
     /// if we fetch the incoming records in batches.
     ///
@@ -180,6 +224,23 @@ pub trait SyncEngine {
         telem: &mut telemetry::Engine,
     ) -> Result<()>;
 
+    /// Engines which apply incoming records as they're staged can return a batch size here, so
+    /// that incoming records are downloaded and staged in batches of at most that many rather
+    /// than all being held in memory at once. The default, `None`, fetches everything in a
+    /// single request.
+    fn get_incoming_batch_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// When incoming records are downloaded in batches, oldest first, this is called after
+    /// staging each batch but the last, with a timestamp at or before which every record has
+    /// been staged. Engines should persist it and, if it's later than their last sync time,
+    /// use it as the `newer` bound in `get_collection_request()`, so an interrupted sync
+    /// resumes where it left off. It's superseded by the timestamp passed to `apply()`.
+    fn set_incoming_checkpoint(&self, _timestamp: ServerTimestamp) -> Result<()> {
+        Ok(())
+    }
+
     /// Apply the staged records, returning outgoing records.
     /// Ideally we would adjust this model to better support batching of outgoing records
     /// without needing to keep them all in memory (ie, an iterator or similar?)
@@ -45,6 +45,10 @@ pub enum Error {
     #[error("Bad cleartext UTF8: {0}")]
     BadCleartextUtf8(#[from] std::string::FromUtf8Error),
 
+    #[cfg(feature = "crypto")]
+    #[error("Failed to compress or decompress payload: {0}")]
+    PayloadCompressionError(#[source] std::io::Error),
+
     #[cfg(feature = "crypto")]
     #[error("HAWK error: {0}")]
     HawkError(#[from] rc_crypto::hawk::Error),
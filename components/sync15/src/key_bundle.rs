@@ -103,6 +103,18 @@ impl KeyBundle {
     /// Decrypt the provided ciphertext with the given iv, and decodes the
     /// result as a utf8 string.
     pub fn decrypt(&self, enc_base64: &str, iv_base64: &str, hmac_base16: &str) -> Result<String> {
+        let cleartext_bytes = self.decrypt_bytes(enc_base64, iv_base64, hmac_base16)?;
+        let cleartext = String::from_utf8(cleartext_bytes)?;
+        Ok(cleartext)
+    }
+
+    /// Decrypt the provided ciphertext with the given iv.
+    pub fn decrypt_bytes(
+        &self,
+        enc_base64: &str,
+        iv_base64: &str,
+        hmac_base16: &str,
+    ) -> Result<Vec<u8>> {
         // Decode the expected_hmac into bytes to avoid issues if a client happens to encode
         // this as uppercase. This shouldn't happen in practice, but doing it this way is more
         // robust and avoids an allocation.
@@ -120,9 +132,12 @@ impl KeyBundle {
             &iv,
         )?;
         let ciphertext_and_hmac = [ciphertext_bytes, decoded_hmac].concat();
-        let cleartext_bytes = aead::open(&key, nonce, aead::Aad::empty(), &ciphertext_and_hmac)?;
-        let cleartext = String::from_utf8(cleartext_bytes)?;
-        Ok(cleartext)
+        Ok(aead::open(
+            &key,
+            nonce,
+            aead::Aad::empty(),
+            &ciphertext_and_hmac,
+        )?)
     }
 
     /// Encrypt using the provided IV.
@@ -31,7 +31,7 @@ pub use crate::client_types::{ClientData, RemoteClient};
 pub use crate::device_type::DeviceType;
 pub use crate::error::{Error, Result};
 #[cfg(feature = "crypto")]
-pub use enc_payload::EncryptedPayload;
+pub use enc_payload::{EncryptedPayload, PayloadCompression};
 #[cfg(feature = "crypto")]
 pub use key_bundle::KeyBundle;
 pub use server_timestamp::ServerTimestamp;
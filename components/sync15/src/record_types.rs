@@ -25,6 +25,28 @@ pub struct MetaGlobalRecord {
     pub engines: HashMap<String, MetaGlobalEngine>,
     #[serde(default)]
     pub declined: Vec<String>,
+    /// The compressions this account has opted in to for outgoing payloads. We only write it
+    /// when we create a new meta/global, and clients which don't know about it drop it if they
+    /// replace meta/global, which turns compression off again. Even when it's set, we only
+    /// compress if every client record also says it can read compressed payloads.
+    #[serde(
+        default,
+        rename = "payloadCompression",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub payload_compression: Vec<String>,
+}
+
+#[cfg(feature = "crypto")]
+impl MetaGlobalRecord {
+    /// How outgoing payloads should be compressed, if at all.
+    pub fn outgoing_compression(&self) -> Option<crate::PayloadCompression> {
+        let deflate = crate::PayloadCompression::Deflate;
+        self.payload_compression
+            .iter()
+            .any(|name| name == deflate.as_str())
+            .then_some(deflate)
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
@@ -48,4 +70,33 @@ fn test_deserialize_meta_global() {
     assert_eq!(r.storage_version, 1);
     assert_eq!(r.engines.len(), 0);
     assert_eq!(r.declined.len(), 0);
+    assert_eq!(r.payload_compression.len(), 0);
+    assert!(!serde_json::to_string(&r)
+        .unwrap()
+        .contains("payloadCompression"));
+}
+
+#[cfg(all(test, feature = "crypto"))]
+#[test]
+fn test_meta_global_payload_compression() {
+    let record = serde_json::json!({
+        "syncID": "abcd1234abcd",
+        "storageVersion": 5,
+        "payloadCompression": ["zstd", "deflate"],
+    })
+    .to_string();
+    let r = serde_json::from_str::<MetaGlobalRecord>(&record).unwrap();
+    assert_eq!(
+        r.outgoing_compression(),
+        Some(crate::PayloadCompression::Deflate)
+    );
+
+    let record = serde_json::json!({
+        "syncID": "abcd1234abcd",
+        "storageVersion": 5,
+        "payloadCompression": ["zstd"],
+    })
+    .to_string();
+    let r = serde_json::from_str::<MetaGlobalRecord>(&record).unwrap();
+    assert_eq!(r.outgoing_compression(), None);
 }
@@ -9,7 +9,7 @@ use crate::error::Error;
 #[cfg(feature = "sync-client")]
 use crate::error::ErrorResponse;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::time;
 
 use serde::{ser, Serialize, Serializer};
@@ -298,6 +298,32 @@ pub struct EngineIncoming {
 
     #[serde(skip_serializing_if = "crate::skip_if_default")]
     reconciled: u32,
+
+    // Counts of the `failed` records by why they failed. Not every failure has a reason.
+    #[serde(rename = "failedReasons")]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(serialize_with = "serialize_failed_reasons")]
+    failed_reasons: BTreeMap<&'static str, u32>,
+}
+
+// Desktop reports these as a list of `{"name": ..., "count": ...}` objects.
+fn serialize_failed_reasons<S>(
+    reasons: &BTreeMap<&'static str, u32>,
+    s: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct FailedReason {
+        name: &'static str,
+        count: u32,
+    }
+    s.collect_seq(
+        reasons
+            .iter()
+            .map(|(&name, &count)| FailedReason { name, count }),
+    )
 }
 
 impl EngineIncoming {
@@ -327,6 +353,14 @@ impl EngineIncoming {
         self.failed += n;
     }
 
+    /// Increment the value of `failed` by `n`, recording that they failed because of `reason`,
+    /// eg "decrypt" or "invalid-record".
+    #[inline]
+    pub fn failed_with_reason(&mut self, n: u32, reason: &'static str) {
+        self.failed += n;
+        *self.failed_reasons.entry(reason).or_default() += n;
+    }
+
     /// Increment the value of `new_failed` by `n`.
     #[inline]
     pub fn new_failed(&mut self, n: u32) {
@@ -346,6 +380,9 @@ impl EngineIncoming {
         self.failed += other.failed;
         self.new_failed += other.new_failed;
         self.reconciled += other.reconciled;
+        for (&reason, &n) in &other.failed_reasons {
+            *self.failed_reasons.entry(reason).or_default() += n;
+        }
     }
 
     /// Get the value of `applied`. Mostly useful for testing.
@@ -371,6 +408,12 @@ impl EngineIncoming {
     pub fn get_reconciled(&self) -> u32 {
         self.reconciled
     }
+
+    /// Get the number of failed records for each reason.
+    #[inline]
+    pub fn get_failed_reasons(&self) -> &BTreeMap<&'static str, u32> {
+        &self.failed_reasons
+    }
 }
 
 /// Outgoing record for an engine's sync.
@@ -399,6 +442,18 @@ impl EngineOutgoing {
     pub fn failed(&mut self, n: usize) {
         self.failed += n;
     }
+
+    /// Get the value of `sent`.
+    #[inline]
+    pub fn get_sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Get the value of `failed`.
+    #[inline]
+    pub fn get_failed(&self) -> usize {
+        self.failed
+    }
 }
 
 /// One engine's sync.
@@ -451,6 +506,14 @@ impl Engine {
         self.outgoing.push(out);
     }
 
+    pub fn get_outgoing(&self) -> &[EngineOutgoing] {
+        &self.outgoing
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn failure(&mut self, err: impl Into<SyncFailure>) {
         // Currently we take the first error, under the assumption that the
         // first is the most important and all others stem from that.
@@ -561,6 +624,30 @@ mod engine_tests {
         );
     }
 
+    #[test]
+    fn test_incoming_failed_reasons() {
+        let mut e = Engine::new("TestEngine");
+        let mut i1 = EngineIncoming::new();
+        i1.failed_with_reason(2, "decrypt");
+        i1.failed(1);
+        e.incoming(i1);
+        let mut i2 = EngineIncoming::new();
+        i2.failed_with_reason(1, "invalid-record");
+        i2.failed_with_reason(1, "decrypt");
+        e.incoming(i2);
+        e.finished();
+        assert_json(
+            &e,
+            serde_json::json!({"name": "TestEngine", "when": 0.0, "incoming": {
+                "failed": 5,
+                "failedReasons": [
+                    {"name": "decrypt", "count": 3},
+                    {"name": "invalid-record", "count": 1},
+                ],
+            }}),
+        );
+    }
+
     #[test]
     fn test_outgoing() {
         let mut o = EngineOutgoing::new();
@@ -638,6 +725,10 @@ impl SyncTelemetry {
         self.failure = Some(failure);
     }
 
+    pub fn engines(&self) -> &[Engine] {
+        &self.engines
+    }
+
     // Note that unlike other 'finished' methods, this isn't private - someone
     // needs to explicitly call this before handling the json payload to
     // whatever ends up submitting it.
@@ -780,6 +871,10 @@ impl SyncTelemetryPing {
     pub fn event(&mut self, e: Event) {
         self.events.push(e);
     }
+
+    pub fn syncs(&self) -> &[SyncTelemetry] {
+        &self.syncs
+    }
 }
 
 #[cfg(test)]
@@ -3,12 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::error::*;
-use crate::types::{ServiceStatus, SyncEngineSelection, SyncParams, SyncReason, SyncResult};
+use crate::types::{
+    EngineSyncProgress, EngineSyncSummary, PostSyncHook, RemoteCommand, ServiceStatus,
+    SyncEngineSelection, SyncParams, SyncProgressObserver, SyncReason, SyncResult,
+};
 use crate::{reset, reset_all, wipe};
 use error_support::breadcrumb;
+use interrupt_support::{Interruptee, SqlInterruptHandle};
 use parking_lot::Mutex;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::SystemTime;
 use sync15::client::{
     sync_multiple_with_command_processor, MemoryCachedState, Sync15StorageClientInit,
@@ -16,10 +22,50 @@ use sync15::client::{
 };
 use sync15::clients_engine::{Command, CommandProcessor, CommandStatus, Settings};
 use sync15::engine::{EngineSyncAssociation, SyncEngine, SyncEngineId};
+use sync15::telemetry::SyncTelemetryPing;
+use sync15::Guid;
+
+struct RegisteredHook {
+    // `None` means every engine.
+    engines: Option<HashSet<SyncEngineId>>,
+    hook: Arc<dyn PostSyncHook>,
+}
 
 #[derive(Default)]
 pub struct SyncManager {
     mem_cached_state: Mutex<Option<MemoryCachedState>>,
+    post_sync_hooks: Mutex<Vec<RegisteredHook>>,
+    progress_observers: Mutex<Vec<(u64, Arc<dyn SyncProgressObserver>)>>,
+    next_progress_observer_id: AtomicU64,
+    // Commands for the other clients, which haven't been sent yet.
+    outgoing_commands: Mutex<HashSet<Command>>,
+    // Bumped by `interrupt_current_sync()`. This isn't behind a lock, since the sync holds
+    // `mem_cached_state` for as long as it runs.
+    interrupt_counter: AtomicUsize,
+    // The database interrupt handles of the engines in the running sync.
+    sql_interrupt_handles: Mutex<Vec<Arc<SqlInterruptHandle>>>,
+}
+
+/// Interrupts a sync when `SyncManager::interrupt_current_sync()` is called after it started,
+/// or when the app is shutting down.
+struct SyncInterruptee<'a> {
+    counter: &'a AtomicUsize,
+    start_value: usize,
+}
+
+impl<'a> SyncInterruptee<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        Self {
+            counter,
+            start_value: counter.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl Interruptee for SyncInterruptee<'_> {
+    fn was_interrupted(&self) -> bool {
+        interrupt_support::in_shutdown() || self.counter.load(Ordering::SeqCst) != self.start_value
+    }
 }
 
 impl SyncManager {
@@ -83,43 +129,217 @@ impl SyncManager {
     }
 
     /// Perform a sync.  See [SyncParams] and [SyncResult] for details on how this works
-    pub fn sync(&self, params: SyncParams) -> Result<SyncResult> {
+    pub fn sync(&self, mut params: SyncParams) -> Result<SyncResult> {
+        // Tag everything the sync does with an operation id, unless the app
+        // already opened an operation for it.
+        let _operation = error_support::current_operation_id()
+            .is_none()
+            .then(|| error_support::enter_operation(format!("sync-{}", Guid::random())));
         breadcrumb!("SyncManager::sync started");
-        let mut state = self.mem_cached_state.lock();
-        let engines = self.calc_engines_to_sync(&params.engines)?;
-        let next_sync_after = state.as_ref().and_then(|mcs| mcs.get_next_sync_after());
-        let result = if !backoff_in_effect(next_sync_after, &params) {
-            log::info!("No backoff in effect (or we decided to ignore it), starting sync");
-            self.do_sync(params, &mut state, engines)
-        } else {
-            breadcrumb!(
-                "Backoff still in effect (until {:?}), bailing out early",
-                next_sync_after
-            );
-            Ok(SyncResult {
-                status: ServiceStatus::BackedOff,
-                successful: Default::default(),
-                failures: Default::default(),
-                declined: None,
-                next_sync_allowed_at: next_sync_after,
-                persisted_state: params.persisted_state.unwrap_or_default(),
-                // It would be nice to record telemetry here.
-                telemetry_json: None,
-            })
+        // Capture this before waiting for a sync which is already running, so that
+        // `interrupt_current_sync()` also cancels syncs which are waiting to start.
+        let interruptee = SyncInterruptee::new(&self.interrupt_counter);
+        if let Some(engine_states) = &params.engine_states {
+            let persisted_state = params.persisted_state.as_deref();
+            let declared = persisted_state.and_then(sync15::client::persisted_engine_states);
+            let declined = persisted_state.and_then(sync15::client::persisted_declined);
+            for (engine, enabled) in
+                engine_state_changes(engine_states, declared.as_ref(), declined.as_deref())
+            {
+                params.enabled_changes.entry(engine).or_insert(enabled);
+            }
+        }
+        let (result, synced) = {
+            let mut state = self.mem_cached_state.lock();
+            let priority = params
+                .priority_engines
+                .iter()
+                .flatten()
+                .map(|name| Self::get_engine_id(name))
+                .collect::<Result<Vec<_>>>()?;
+            let mut engines = self.calc_engines_to_sync(&params.engines, &priority)?;
+            let next_sync_after =
+                Self::next_sync_after(state.as_ref(), params.persisted_state.as_deref());
+            let retry_after = Self::retry_after(state.as_ref(), params.persisted_state.as_deref());
+            if !backoff_in_effect(next_sync_after, &params) {
+                log::info!("No backoff in effect (or we decided to ignore it), starting sync");
+                let is_user_action = matches!(params.reason, SyncReason::User);
+                self.do_sync(params, &mut state, engines, is_user_action, &interruptee)?
+            } else if retry_after.is_none() && engines.iter().any(|(id, _)| priority.contains(id)) {
+                // Priority engines only ignore a soft backoff; a `Retry-After` applies to them too.
+                breadcrumb!(
+                    "Soft backoff still in effect (until {:?}), only syncing priority engines",
+                    next_sync_after
+                );
+                engines.retain(|(id, _)| priority.contains(id));
+                self.do_sync(params, &mut state, engines, true, &interruptee)?
+            } else {
+                breadcrumb!(
+                    "Backoff still in effect (until {:?}), bailing out early",
+                    next_sync_after
+                );
+                let result = SyncResult {
+                    status: ServiceStatus::BackoffActive,
+                    successful: Default::default(),
+                    failures: Default::default(),
+                    declined: None,
+                    next_sync_allowed_at: next_sync_after,
+                    persisted_state: params.persisted_state.unwrap_or_default(),
+                    // It would be nice to record telemetry here.
+                    telemetry_json: None,
+                    engine_summaries: HashMap::new(),
+                    node_reassignment: None,
+                };
+                (result, Vec::new())
+            }
         };
         breadcrumb!("SyncManager sync ended");
-        result
+        // The engines and our state lock have been released by now, so hooks
+        // are free to use the stores.
+        self.run_post_sync_hooks(synced);
+        Ok(result)
+    }
+
+    /// Interrupt the sync which is currently running, if any.
+    ///
+    /// The sync stops at its next checkpoint: between engines, between batches of records it
+    /// downloads or uploads, and before it applies incoming records. Any SQL the engines are
+    /// running, for example while applying records, is interrupted too. Each engine applies its
+    /// records in a single transaction, so an engine is either synced or left untouched. The
+    /// interrupted sync, and any sync waiting for it to finish, return a result with
+    /// `ServiceStatus::Interrupted`. Syncs started after this call aren't affected.
+    pub fn interrupt_current_sync(&self) {
+        breadcrumb!("SyncManager interrupt_current_sync()");
+        self.interrupt_counter.fetch_add(1, Ordering::SeqCst);
+        for handle in self.sql_interrupt_handles.lock().iter() {
+            handle.interrupt();
+        }
+    }
+
+    /// The earliest time the server will allow us to sync again, or `None` if it isn't asking
+    /// us to back off.  `persisted_state` is used when this manager hasn't synced yet, so the
+    /// backoff survives app restarts.
+    pub fn next_allowed_sync_time(&self, persisted_state: Option<String>) -> Option<SystemTime> {
+        let state = self.mem_cached_state.lock();
+        Self::next_sync_after(state.as_ref(), persisted_state.as_deref())
+    }
+
+    fn next_sync_after(
+        mem_cached_state: Option<&MemoryCachedState>,
+        persisted_state: Option<&str>,
+    ) -> Option<SystemTime> {
+        match mem_cached_state {
+            Some(mcs) => mcs.get_next_sync_after(),
+            None => persisted_state.and_then(sync15::client::persisted_next_sync_after),
+        }
+    }
+
+    /// Like `next_sync_after()`, but only for a hard backoff, which even priority engines
+    /// respect, and only if it hasn't ended yet.
+    fn retry_after(
+        mem_cached_state: Option<&MemoryCachedState>,
+        persisted_state: Option<&str>,
+    ) -> Option<SystemTime> {
+        match mem_cached_state {
+            Some(mcs) => mcs.get_retry_after(),
+            None => persisted_state.and_then(sync15::client::persisted_retry_after),
+        }
+        .filter(|when| *when > SystemTime::now())
+    }
+
+    /// Register a hook to call after each successful sync of the named engines, or of every
+    /// engine if `engines` is `None`.
+    pub fn register_post_sync_hook(
+        &self,
+        engines: Option<Vec<String>>,
+        hook: Box<dyn PostSyncHook>,
+    ) -> Result<()> {
+        let engines = engines
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| Self::get_engine_id(name))
+                    .collect::<Result<HashSet<_>>>()
+            })
+            .transpose()?;
+        self.post_sync_hooks.lock().push(RegisteredHook {
+            engines,
+            hook: Arc::from(hook),
+        });
+        Ok(())
+    }
+
+    /// Register an observer to be notified of each engine's progress during every subsequent
+    /// sync. Returns an ID to pass to `unregister_sync_progress_observer()`.
+    pub fn register_sync_progress_observer(&self, observer: Box<dyn SyncProgressObserver>) -> u64 {
+        let id = self
+            .next_progress_observer_id
+            .fetch_add(1, Ordering::Relaxed);
+        self.progress_observers
+            .lock()
+            .push((id, Arc::from(observer)));
+        id
+    }
+
+    /// Stop notifying the observer registered with `id`. Unknown IDs are ignored. A sync which
+    /// is already running may still notify it.
+    pub fn unregister_sync_progress_observer(&self, id: u64) {
+        self.progress_observers
+            .lock()
+            .retain(|(observer_id, _)| *observer_id != id);
+    }
+
+    /// Queue a command to send to the other clients on the account during the next sync.
+    pub fn queue_remote_command(&self, command: RemoteCommand) -> Result<()> {
+        let command = match command {
+            RemoteCommand::Wipe { engine } => {
+                Self::get_engine_id(&engine)?;
+                Command::Wipe(engine)
+            }
+            RemoteCommand::Reset { engine } => {
+                Self::get_engine_id(&engine)?;
+                Command::Reset(engine)
+            }
+            RemoteCommand::ResetAll => Command::ResetAll,
+        };
+        self.outgoing_commands.lock().insert(command);
+        Ok(())
+    }
+
+    fn run_post_sync_hooks(&self, synced: Vec<(SyncEngineId, EngineSyncSummary)>) {
+        if synced.is_empty() {
+            return;
+        }
+        // Collect the hooks first, so they aren't called with the lock held.
+        let hooks: Vec<_> = self
+            .post_sync_hooks
+            .lock()
+            .iter()
+            .map(|registered| (registered.engines.clone(), Arc::clone(&registered.hook)))
+            .collect();
+        for (engine_id, summary) in synced {
+            for (engines, hook) in &hooks {
+                if engines
+                    .as_ref()
+                    .map_or(true, |engines| engines.contains(&engine_id))
+                {
+                    hook.on_engine_synced(engine_id.name().to_string(), summary.clone());
+                }
+            }
+        }
     }
 
     fn do_sync(
         &self,
         mut params: SyncParams,
         state: &mut Option<MemoryCachedState>,
-        mut engines: Vec<Box<dyn SyncEngine>>,
-    ) -> Result<SyncResult> {
+        engines: Vec<(SyncEngineId, Box<dyn SyncEngine>)>,
+        is_user_action: bool,
+        interruptee: &SyncInterruptee<'_>,
+    ) -> Result<(SyncResult, Vec<(SyncEngineId, EngineSyncSummary)>)> {
+        let (order, mut engines): (Vec<_>, Vec<_>) = engines.into_iter().unzip();
         let key_bundle = sync15::KeyBundle::from_ksync_base64(&params.auth_info.sync_key)?;
         let tokenserver_url = url::Url::parse(&params.auth_info.tokenserver_url)?;
-        let interruptee = interrupt_support::ShutdownInterruptee;
         let mut mem_cached_state = state.take().unwrap_or_default();
         let mut disk_cached_state = params.persisted_state.take();
 
@@ -148,7 +368,14 @@ impl SyncManager {
             device_name: params.device_settings.name,
             device_type: params.device_settings.kind,
         };
-        let c = SyncClient::new(settings);
+        let outgoing_commands = self.outgoing_commands.lock().clone();
+        let c = SyncClient::new(settings, outgoing_commands.clone());
+        // Clone the observers so that registering one mid-sync doesn't block.
+        let progress = ProgressObservers::new(&self.progress_observers.lock());
+        *self.sql_interrupt_handles.lock() = engine_refs
+            .iter()
+            .filter_map(|engine| engine.sql_interrupt_handle())
+            .collect();
         let result = sync_multiple_with_command_processor(
             Some(&c),
             &engine_refs,
@@ -156,13 +383,22 @@ impl SyncManager {
             &mut mem_cached_state,
             &client_init,
             &key_bundle,
-            &interruptee,
+            interruptee,
             Some(SyncRequestInfo {
                 engines_to_state_change: engines_to_change,
-                is_user_action: matches!(params.reason, SyncReason::User),
+                is_user_action,
+                progress: Some(&progress),
             }),
         );
+        self.sql_interrupt_handles.lock().clear();
         *state = Some(mem_cached_state);
+        if result.service_status == sync15::client::ServiceStatus::Ok {
+            // The clients engine has written the commands into the other clients' records. Keep
+            // any that were queued while we were syncing.
+            self.outgoing_commands
+                .lock()
+                .retain(|command| !outgoing_commands.contains(command));
+        }
 
         log::info!("Sync finished with status {:?}", result.service_status);
         let status = ServiceStatus::from(result.service_status);
@@ -171,9 +407,15 @@ impl SyncManager {
         }
         let mut successful: Vec<String> = Vec::new();
         let mut failures: HashMap<String, String> = HashMap::new();
+        let engine_summaries = engine_sync_summaries(&result.telemetry);
+        let mut synced = Vec::new();
         for (engine, result) in result.engine_results.into_iter() {
             match result {
                 Ok(_) => {
+                    if let Ok(engine_id) = Self::get_engine_id(&engine) {
+                        let summary = engine_summaries.get(&engine).cloned().unwrap_or_default();
+                        synced.push((engine_id, summary));
+                    }
                     successful.push(engine);
                 }
                 Err(err) => {
@@ -182,8 +424,13 @@ impl SyncManager {
             }
         }
         let telemetry_json = serde_json::to_string(&result.telemetry).unwrap();
+        // Once the sync has reconciled the declared engine states with `meta/global`, remember
+        // them, so that the next sync only applies the ones the app changes after this.
+        if let (Some(engine_states), Some(_)) = (&params.engine_states, &result.declined) {
+            sync15::client::persist_engine_states(&mut disk_cached_state, engine_states);
+        }
 
-        Ok(SyncResult {
+        let sync_result = SyncResult {
             status,
             successful,
             failures,
@@ -191,7 +438,12 @@ impl SyncManager {
             next_sync_allowed_at: result.next_sync_after,
             persisted_state: disk_cached_state.unwrap_or_default(),
             telemetry_json: Some(telemetry_json),
-        })
+            engine_summaries,
+            node_reassignment: result.node_reassignment.map(Into::into),
+        };
+        // Call the hooks in the order the engines synced.
+        synced.sort_by_key(|(engine_id, _)| order.iter().position(|id| id == engine_id));
+        Ok((sync_result, synced))
     }
 
     fn iter_registered_engines(&self) -> impl Iterator<Item = (SyncEngineId, Box<dyn SyncEngine>)> {
@@ -207,7 +459,8 @@ impl SyncManager {
     fn calc_engines_to_sync(
         &self,
         selection: &SyncEngineSelection,
-    ) -> Result<Vec<Box<dyn SyncEngine>>> {
+        priority: &[SyncEngineId],
+    ) -> Result<Vec<(SyncEngineId, Box<dyn SyncEngine>)>> {
         // BTreeMap to ensure we sync the engines in priority order.
         let mut engine_map: BTreeMap<_, _> = self.iter_registered_engines().collect();
         breadcrumb!(
@@ -234,8 +487,87 @@ impl SyncManager {
             // Filter engines based on the selection
             engine_map.retain(|engine_id, _| selected_engine_ids.contains(engine_id))
         }
-        Ok(engine_map.into_values().collect())
+        Ok(sync_order(engine_map.keys().cloned().collect(), priority)
+            .into_iter()
+            .filter_map(|engine_id| {
+                let engine = engine_map.remove(&engine_id)?;
+                Some((engine_id, engine))
+            })
+            .collect())
+    }
+}
+
+/// Orders the engines to sync: the priority engines first, in the order given, then the
+/// others in their default order - except that each engine syncs after its dependencies.
+fn sync_order(mut engines: Vec<SyncEngineId>, priority: &[SyncEngineId]) -> Vec<SyncEngineId> {
+    fn add(engine_id: &SyncEngineId, engines: &[SyncEngineId], ordered: &mut Vec<SyncEngineId>) {
+        if ordered.contains(engine_id) || !engines.contains(engine_id) {
+            return;
+        }
+        // `sync_after()` never has cycles, so this always finishes.
+        for dependency in engine_id.sync_after() {
+            add(dependency, engines, ordered);
+        }
+        ordered.push(engine_id.clone());
+    }
+
+    engines.sort_by_key(|engine_id| {
+        let rank = priority.iter().position(|id| id == engine_id);
+        (rank.unwrap_or(priority.len()), engine_id.clone())
+    });
+    let mut ordered = Vec::with_capacity(engines.len());
+    for engine_id in &engines {
+        add(engine_id, &engines, &mut ordered);
+    }
+    ordered
+}
+
+/// The engines whose state in `engine_states` differs from the account's state after the last
+/// sync, when `declined` were declined, and which the app has changed since it `declared` the
+/// states for that sync. Those are the engines the user has enabled or disabled locally; any
+/// other differences are changes made on another device, which we keep even if the app hasn't
+/// caught up with them yet.
+fn engine_state_changes(
+    engine_states: &HashMap<String, bool>,
+    declared: Option<&HashMap<String, bool>>,
+    declined: Option<&[String]>,
+) -> HashMap<String, bool> {
+    let Some(declined) = declined else {
+        // We don't know the account's state yet.
+        return HashMap::new();
+    };
+    engine_states
+        .iter()
+        .filter(|(engine, enabled)| **enabled == declined.contains(engine))
+        // Without the previously declared states, which older versions didn't persist, we
+        // have to assume the app has kept its states in sync with the account.
+        .filter(|(engine, enabled)| declared.map_or(true, |d| d.get(*engine) != Some(enabled)))
+        .map(|(engine, enabled)| (engine.clone(), *enabled))
+        .collect()
+}
+
+/// Totals the changes recorded in the telemetry for each engine.
+fn engine_sync_summaries(telemetry: &SyncTelemetryPing) -> HashMap<String, EngineSyncSummary> {
+    let mut summaries: HashMap<String, EngineSyncSummary> = HashMap::new();
+    for engine in telemetry.syncs().iter().flat_map(|sync| sync.engines()) {
+        let summary = summaries.entry(engine.name().to_string()).or_default();
+        if let Some(incoming) = engine.get_incoming() {
+            summary.incoming_applied += incoming.get_applied();
+            summary.incoming_failed += incoming.get_failed();
+            summary.incoming_reconciled += incoming.get_reconciled();
+            for (reason, n) in incoming.get_failed_reasons() {
+                *summary
+                    .incoming_failed_reasons
+                    .entry(reason.to_string())
+                    .or_default() += n;
+            }
+        }
+        for outgoing in engine.get_outgoing() {
+            summary.outgoing_sent += outgoing.get_sent() as u32;
+            summary.outgoing_failed += outgoing.get_failed() as u32;
+        }
     }
+    summaries
 }
 
 fn backoff_in_effect(next_sync_after: Option<SystemTime>, p: &SyncParams) -> bool {
@@ -274,23 +606,84 @@ impl From<sync15::client::ServiceStatus> for ServiceStatus {
             ServiceError => ServiceStatus::ServiceError,
             AuthenticationError => ServiceStatus::AuthError,
             BackedOff => ServiceStatus::BackedOff,
-            Interrupted => ServiceStatus::OtherError, // Eh...
+            Interrupted => ServiceStatus::Interrupted,
             OtherError => ServiceStatus::OtherError,
         }
     }
 }
 
-struct SyncClient(Settings);
+impl From<sync15::client::NodeReassignment> for NodeReassignment {
+    fn from(reassignment: sync15::client::NodeReassignment) -> Self {
+        NodeReassignment {
+            previous_endpoint: reassignment.previous_endpoint,
+            new_endpoint: reassignment.new_endpoint,
+        }
+    }
+}
+
+impl From<sync15::client::EngineSyncProgress> for EngineSyncProgress {
+    fn from(progress: sync15::client::EngineSyncProgress) -> Self {
+        use sync15::client::EngineSyncProgress::*;
+        match progress {
+            Started => EngineSyncProgress::Started,
+            Downloaded { count } => EngineSyncProgress::Downloaded {
+                count: count as u32,
+            },
+            Uploaded { sent, failed } => EngineSyncProgress::Uploaded {
+                sent: sent as u32,
+                failed: failed as u32,
+            },
+            Finished { duration } => EngineSyncProgress::Finished {
+                duration_ms: duration.as_millis() as u64,
+            },
+            Failed { reason, duration } => EngineSyncProgress::Failed {
+                reason,
+                duration_ms: duration.as_millis() as u64,
+            },
+        }
+    }
+}
+
+/// Forwards the progress of a sync to the registered observers.
+struct ProgressObservers(Vec<Arc<dyn SyncProgressObserver>>);
+
+impl ProgressObservers {
+    fn new(registered: &[(u64, Arc<dyn SyncProgressObserver>)]) -> Self {
+        Self(
+            registered
+                .iter()
+                .map(|(_, observer)| observer.clone())
+                .collect(),
+        )
+    }
+}
+
+impl sync15::client::SyncProgressObserver for ProgressObservers {
+    fn on_engine_progress(&self, engine: &str, progress: sync15::client::EngineSyncProgress) {
+        let progress = EngineSyncProgress::from(progress);
+        for observer in &self.0 {
+            observer.on_engine_progress(engine.to_string(), progress.clone());
+        }
+    }
+}
+
+struct SyncClient {
+    settings: Settings,
+    outgoing_commands: HashSet<Command>,
+}
 
 impl SyncClient {
-    pub fn new(settings: Settings) -> SyncClient {
-        SyncClient(settings)
+    pub fn new(settings: Settings, outgoing_commands: HashSet<Command>) -> SyncClient {
+        SyncClient {
+            settings,
+            outgoing_commands,
+        }
     }
 }
 
 impl CommandProcessor for SyncClient {
     fn settings(&self) -> &Settings {
-        &self.0
+        &self.settings
     }
 
     fn apply_incoming_command(&self, command: Command) -> anyhow::Result<CommandStatus> {
@@ -298,6 +691,12 @@ impl CommandProcessor for SyncClient {
             Command::Wipe(engine) => wipe(&engine),
             Command::Reset(engine) => reset(&engine),
             Command::ResetAll => reset_all(),
+            // Tabs are sent to us using FxA device commands, so we drop these rather than
+            // leaving them in our record forever.
+            Command::DisplayUri { .. } => {
+                log::debug!("Ignoring displayURI command");
+                return Ok(CommandStatus::Ignored);
+            }
         };
         match result {
             Ok(()) => Ok(CommandStatus::Applied),
@@ -309,7 +708,7 @@ impl CommandProcessor for SyncClient {
     }
 
     fn fetch_outgoing_commands(&self) -> anyhow::Result<HashSet<Command>> {
-        Ok(HashSet::new())
+        Ok(self.outgoing_commands.clone())
     }
 }
 
@@ -323,4 +722,342 @@ mod test {
             assert_eq!(engine_id, SyncEngineId::try_from(engine_id.name()).unwrap());
         }
     }
+
+    #[test]
+    fn test_interrupt_current_sync() {
+        let manager = SyncManager::new();
+        // Interrupting before a sync starts doesn't affect it.
+        manager.interrupt_current_sync();
+        let interruptee = SyncInterruptee::new(&manager.interrupt_counter);
+        assert!(!interruptee.was_interrupted());
+        manager.interrupt_current_sync();
+        assert!(interruptee.was_interrupted());
+        assert!(!SyncInterruptee::new(&manager.interrupt_counter).was_interrupted());
+    }
+
+    #[test]
+    fn test_interrupt_current_sync_interrupts_sql() {
+        let manager = SyncManager::new();
+        let db = logins::LoginDb::open_in_memory().unwrap();
+        let scope = db.begin_interrupt_scope().unwrap();
+        // The handles are only interrupted while a sync is running.
+        manager.interrupt_current_sync();
+        assert!(!scope.was_interrupted());
+        *manager.sql_interrupt_handles.lock() = vec![db.new_interrupt_handle()];
+        manager.interrupt_current_sync();
+        assert!(scope.was_interrupted());
+    }
+
+    #[test]
+    fn test_sync_order() {
+        use SyncEngineId::*;
+        assert_eq!(
+            sync_order(vec![History, Tabs, Passwords, Bookmarks], &[]),
+            vec![Passwords, Tabs, Bookmarks, History]
+        );
+        assert_eq!(
+            sync_order(vec![History, Tabs, Passwords], &[Tabs]),
+            vec![Tabs, Passwords, History]
+        );
+        // History depends on bookmarks, so bookmarks syncs first even though it isn't a
+        // priority engine.
+        assert_eq!(
+            sync_order(vec![History, Tabs, Passwords, Bookmarks], &[History, Tabs]),
+            vec![Bookmarks, History, Tabs, Passwords]
+        );
+    }
+
+    #[test]
+    fn test_next_allowed_sync_time() {
+        use std::time::{Duration, UNIX_EPOCH};
+        fn persisted_state(next_sync_after: SystemTime) -> Option<String> {
+            let millis = next_sync_after
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            Some(format!(
+                r#"{{"schema_version":"V2","declined":null,"next_sync_after":{}}}"#,
+                millis
+            ))
+        }
+
+        let manager = SyncManager::new();
+        assert_eq!(manager.next_allowed_sync_time(None), None);
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let allowed = manager
+            .next_allowed_sync_time(persisted_state(later))
+            .expect("should be backed off");
+        assert_eq!(
+            allowed.duration_since(UNIX_EPOCH).unwrap().as_millis(),
+            later.duration_since(UNIX_EPOCH).unwrap().as_millis()
+        );
+        // Backoff which has already expired is ignored.
+        let earlier = SystemTime::now() - Duration::from_secs(60);
+        assert_eq!(
+            manager.next_allowed_sync_time(persisted_state(earlier)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        use std::time::{Duration, UNIX_EPOCH};
+        let millis = |when: SystemTime| when.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        // A soft backoff doesn't stop priority engines from syncing...
+        let soft = format!(
+            r#"{{"schema_version":"V2","declined":null,"next_sync_after":{}}}"#,
+            millis(later)
+        );
+        assert_eq!(SyncManager::retry_after(None, Some(&soft)), None);
+        // ...but a hard one does.
+        let hard = format!(
+            r#"{{"schema_version":"V2","declined":null,"next_sync_after":{0},"retry_after":{0}}}"#,
+            millis(later)
+        );
+        let retry_after =
+            SyncManager::retry_after(None, Some(&hard)).expect("should be backed off");
+        assert_eq!(millis(retry_after), millis(later));
+    }
+
+    #[test]
+    fn test_engine_state_changes() {
+        let engine_states = HashMap::from([
+            ("tabs".to_string(), true),
+            ("passwords".to_string(), false),
+            ("bookmarks".to_string(), true),
+            ("history".to_string(), false),
+        ]);
+        // Nothing changes until we know the account's state.
+        assert!(engine_state_changes(&engine_states, None, None).is_empty());
+        // The user enabled tabs and disabled passwords since the last sync.
+        // Bookmarks and history are as they were after the last sync, so if
+        // another device has changed them since, we keep its changes.
+        let declined = ["tabs".to_string(), "history".to_string()];
+        assert_eq!(
+            engine_state_changes(&engine_states, None, Some(&declined)),
+            HashMap::from([("tabs".to_string(), true), ("passwords".to_string(), false)])
+        );
+        // Another device disabled bookmarks and enabled passwords and history, and the app still
+        // declares the states it did for the last sync, so we keep those changes.
+        // Tabs was enabled locally since then.
+        let declared = HashMap::from([
+            ("tabs".to_string(), false),
+            ("passwords".to_string(), false),
+            ("bookmarks".to_string(), true),
+            ("history".to_string(), false),
+        ]);
+        let declined = ["tabs".to_string(), "bookmarks".to_string()];
+        assert_eq!(
+            engine_state_changes(&engine_states, Some(&declared), Some(&declined)),
+            HashMap::from([("tabs".to_string(), true)])
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingHook {
+        calls: Arc<Mutex<Vec<(String, EngineSyncSummary)>>>,
+    }
+
+    impl PostSyncHook for RecordingHook {
+        fn on_engine_synced(&self, engine: String, summary: EngineSyncSummary) {
+            self.calls.lock().push((engine, summary));
+        }
+    }
+
+    #[test]
+    fn test_post_sync_hooks() {
+        let manager = SyncManager::new();
+        let all_calls = Arc::new(Mutex::new(Vec::new()));
+        let tabs_calls = Arc::new(Mutex::new(Vec::new()));
+        manager
+            .register_post_sync_hook(
+                None,
+                Box::new(RecordingHook {
+                    calls: all_calls.clone(),
+                }),
+            )
+            .unwrap();
+        manager
+            .register_post_sync_hook(
+                Some(vec!["tabs".into()]),
+                Box::new(RecordingHook {
+                    calls: tabs_calls.clone(),
+                }),
+            )
+            .unwrap();
+        assert!(matches!(
+            manager.register_post_sync_hook(
+                Some(vec!["not-an-engine".into()]),
+                Box::<RecordingHook>::default(),
+            ),
+            Err(SyncManagerError::UnknownEngine(_))
+        ));
+
+        let tabs_summary = EngineSyncSummary {
+            incoming_applied: 2,
+            ..Default::default()
+        };
+        manager.run_post_sync_hooks(vec![
+            (SyncEngineId::History, EngineSyncSummary::default()),
+            (SyncEngineId::Tabs, tabs_summary.clone()),
+        ]);
+        assert_eq!(
+            *all_calls.lock(),
+            vec![
+                ("history".to_string(), EngineSyncSummary::default()),
+                ("tabs".to_string(), tabs_summary.clone()),
+            ]
+        );
+        assert_eq!(*tabs_calls.lock(), vec![("tabs".to_string(), tabs_summary)]);
+    }
+
+    #[test]
+    fn test_queue_remote_command() {
+        let manager = SyncManager::new();
+        manager
+            .queue_remote_command(RemoteCommand::Wipe {
+                engine: "bookmarks".into(),
+            })
+            .unwrap();
+        manager
+            .queue_remote_command(RemoteCommand::Reset {
+                engine: "history".into(),
+            })
+            .unwrap();
+        manager
+            .queue_remote_command(RemoteCommand::ResetAll)
+            .unwrap();
+        // Queuing the same command twice only sends it once.
+        manager
+            .queue_remote_command(RemoteCommand::ResetAll)
+            .unwrap();
+        assert!(matches!(
+            manager.queue_remote_command(RemoteCommand::Wipe {
+                engine: "not-an-engine".into(),
+            }),
+            Err(SyncManagerError::UnknownEngine(_))
+        ));
+
+        let client = SyncClient::new(
+            Settings {
+                fxa_device_id: "device".into(),
+                device_name: "Phone".into(),
+                device_type: sync15::DeviceType::Mobile,
+            },
+            manager.outgoing_commands.lock().clone(),
+        );
+        assert_eq!(
+            client.fetch_outgoing_commands().unwrap(),
+            HashSet::from([
+                Command::Wipe("bookmarks".into()),
+                Command::Reset("history".into()),
+                Command::ResetAll,
+            ])
+        );
+        assert_eq!(
+            client
+                .apply_incoming_command(Command::DisplayUri {
+                    uri: "https://example.com".into(),
+                    sender_id: "other-device".into(),
+                    title: None,
+                })
+                .unwrap(),
+            CommandStatus::Ignored
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Arc<Mutex<Vec<(String, EngineSyncProgress)>>>,
+    }
+
+    impl SyncProgressObserver for RecordingObserver {
+        fn on_engine_progress(&self, engine: String, progress: EngineSyncProgress) {
+            self.events.lock().push((engine, progress));
+        }
+    }
+
+    #[test]
+    fn test_progress_observers() {
+        use std::time::Duration;
+        use sync15::client::SyncProgressObserver as _;
+
+        let manager = SyncManager::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let ids: Vec<u64> = (0..3)
+            .map(|_| {
+                manager.register_sync_progress_observer(Box::new(RecordingObserver {
+                    events: events.clone(),
+                }))
+            })
+            .collect();
+        // Unregistered observers aren't notified.
+        manager.unregister_sync_progress_observer(ids[1]);
+        let observers = ProgressObservers::new(&manager.progress_observers.lock());
+        observers.on_engine_progress("tabs", sync15::client::EngineSyncProgress::Started);
+        observers.on_engine_progress(
+            "tabs",
+            sync15::client::EngineSyncProgress::Uploaded { sent: 3, failed: 1 },
+        );
+        observers.on_engine_progress(
+            "tabs",
+            sync15::client::EngineSyncProgress::Failed {
+                reason: "oops".into(),
+                duration: Duration::from_millis(1500),
+            },
+        );
+        let expected = [
+            EngineSyncProgress::Started,
+            EngineSyncProgress::Uploaded { sent: 3, failed: 1 },
+            EngineSyncProgress::Failed {
+                reason: "oops".into(),
+                duration_ms: 1500,
+            },
+        ];
+        assert_eq!(
+            *events.lock(),
+            expected
+                .iter()
+                .flat_map(|progress| [
+                    ("tabs".to_string(), progress.clone()),
+                    ("tabs".to_string(), progress.clone()),
+                ])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_engine_sync_summaries() {
+        use sync15::telemetry::{Engine, EngineIncoming, EngineOutgoing, SyncTelemetry};
+
+        let mut telem_engine = Engine::new("history");
+        let mut incoming = EngineIncoming::new();
+        incoming.applied(3);
+        incoming.reconciled(1);
+        incoming.failed_with_reason(2, "crypto");
+        telem_engine.incoming(incoming);
+        for sent in [5, 2] {
+            let mut outgoing = EngineOutgoing::new();
+            outgoing.sent(sent);
+            telem_engine.outgoing(outgoing);
+        }
+        let mut telem_sync = SyncTelemetry::new();
+        telem_sync.engine(telem_engine);
+        let mut ping = SyncTelemetryPing::new();
+        ping.sync(telem_sync);
+
+        let summaries = engine_sync_summaries(&ping);
+        assert_eq!(
+            summaries.get("history"),
+            Some(&EngineSyncSummary {
+                incoming_applied: 3,
+                incoming_failed: 2,
+                incoming_reconciled: 1,
+                outgoing_sent: 7,
+                incoming_failed_reasons: HashMap::from([("crypto".to_string(), 2)]),
+                ..Default::default()
+            })
+        );
+    }
 }
@@ -16,6 +16,11 @@ pub struct SyncParams {
     // example, if the UI was used to change an engine's state since the last
     // sync).
     pub enabled_changes: HashMap<String, bool>,
+    // The engines offered by the app, and whether the user has enabled them.
+    // Only the engines whose state differs from the account's state after the
+    // last sync, and which changed since the states passed for that sync, are
+    // changed, so this can be passed on every sync.
+    pub engine_states: Option<HashMap<String, bool>>,
     // Keys to encrypt/decrypt data from local database files.  These are
     // separate from the key we use to encrypt the sync payload as a whole.
     pub local_encryption_keys: HashMap<String, String>,
@@ -30,6 +35,10 @@ pub struct SyncParams {
     // Information about the current device, such as its name, formfactor and
     // FxA device ID.
     pub device_settings: DeviceSettings,
+    // Engines to sync first, in this order. These engines also sync while the
+    // server has asked us to back off with `X-Weave-Backoff`, although the
+    // other engines don't. No engines sync during a `Retry-After`.
+    pub priority_engines: Option<Vec<String>>,
 }
 
 #[derive(Debug)]
@@ -85,6 +94,70 @@ pub struct SyncResult {
     pub next_sync_allowed_at: Option<SystemTime>,
     // JSON string encoding a `SyncTelemetryPing` object
     pub telemetry_json: Option<String>,
+    // Maps the names of the engines which synced, successfully or not, to a summary of the
+    // records they handled
+    pub engine_summaries: HashMap<String, EngineSyncSummary>,
+    // Set if the account was moved to a different storage node during this sync, in which case
+    // every engine was reset before syncing with the new node
+    pub node_reassignment: Option<NodeReassignment>,
+}
+
+// The storage node an account was moved from and to. See `SyncResult::node_reassignment`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeReassignment {
+    pub previous_endpoint: String,
+    pub new_endpoint: String,
+}
+
+// A summary of the changes made by an engine's sync, as passed to a
+// `PostSyncHook`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EngineSyncSummary {
+    // Incoming records applied to the local store
+    pub incoming_applied: u32,
+    // Incoming records which couldn't be applied
+    pub incoming_failed: u32,
+    // Incoming records which were reconciled with local changes
+    pub incoming_reconciled: u32,
+    // Local records uploaded to the server
+    pub outgoing_sent: u32,
+    // Local records which couldn't be uploaded
+    pub outgoing_failed: u32,
+    // Counts of the `incoming_failed` records by why they failed, for engines which
+    // report it
+    pub incoming_failed_reasons: HashMap<String, u32>,
+}
+
+// Called after an engine syncs successfully. See
+// `SyncManager::register_post_sync_hook()`.
+pub trait PostSyncHook: Send + Sync {
+    fn on_engine_synced(&self, engine: String, summary: EngineSyncSummary);
+}
+
+// An event in the lifecycle of a single engine's sync, as passed to a
+// `SyncProgressObserver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineSyncProgress {
+    Started,
+    Downloaded { count: u32 },
+    Uploaded { sent: u32, failed: u32 },
+    Finished { duration_ms: u64 },
+    Failed { reason: String, duration_ms: u64 },
+}
+
+// Notified of each engine's progress while a sync is running. See
+// `SyncManager::register_sync_progress_observer()`.
+pub trait SyncProgressObserver: Send + Sync {
+    fn on_engine_progress(&self, engine: String, progress: EngineSyncProgress);
+}
+
+// A command to send to the other clients on the account. See
+// `SyncManager::queue_remote_command()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCommand {
+    Wipe { engine: String },
+    Reset { engine: String },
+    ResetAll,
 }
 
 #[derive(Debug)]
@@ -94,6 +167,10 @@ pub enum ServiceStatus {
     ServiceError,
     AuthError,
     BackedOff,
+    // The sync didn't start because the server previously asked us to back off.
+    BackoffActive,
+    // The sync was stopped by `interrupt_current_sync()` or by shutdown.
+    Interrupted,
     OtherError,
 }
 
@@ -67,3 +67,21 @@ pub struct PendingCommand {
     pub time_requested: Timestamp,
     pub time_sent: Option<Timestamp>,
 }
+
+// A tab another device sent us, held in the inbox until it's read.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ReceivedTab {
+    pub id: i64,
+    pub sender_device_id: Option<String>,
+    pub title: String,
+    pub url: String,
+    pub time_received: Timestamp,
+    pub time_read: Option<Timestamp>,
+}
+
+// The unsent tabs to close on a device, in the shape FxA's `close_tabs()` command takes them.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PendingCloseTabs {
+    pub device_id: String,
+    pub urls: Vec<String>,
+}
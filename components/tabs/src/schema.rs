@@ -41,6 +41,23 @@ const CREATE_PENDING_REMOTE_DELETE_TABLE_SQL: &str = "
     CREATE UNIQUE INDEX IF NOT EXISTS remote_tab_commands_index ON remote_tab_commands(device_id, command, url);
 ";
 
+// Tabs other devices sent us, kept until the user reads them. This is local-only, so it's left
+// alone when the tabs collection is reset or wiped.
+const CREATE_RECEIVED_TABS_TABLE_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS received_tabs (
+        id                      INTEGER PRIMARY KEY,
+        sender_device_id        TEXT, -- null if we don't know who sent it.
+        title                   TEXT NOT NULL,
+        url                     TEXT NOT NULL,
+        time_received           INTEGER NOT NULL, -- local timestamp.
+        time_read               INTEGER -- local timestamp, null == unread.
+    );
+
+    -- The same tab can be delivered more than once (eg, via push and by polling).
+    CREATE UNIQUE INDEX IF NOT EXISTS received_tabs_unread_index
+        ON received_tabs(IFNULL(sender_device_id, ''), url) WHERE time_read IS NULL;
+";
+
 pub(crate) static LAST_SYNC_META_KEY: &str = "last_sync_time";
 pub(crate) static GLOBAL_SYNCID_META_KEY: &str = "global_sync_id";
 pub(crate) static COLLECTION_SYNCID_META_KEY: &str = "tabs_sync_id";
@@ -53,6 +70,7 @@ fn init_schema(db: &Connection) -> rusqlite::Result<()> {
     db.execute_batch(CREATE_TABS_TABLE_SQL)?;
     db.execute_batch(CREATE_META_TABLE_SQL)?;
     db.execute_batch(CREATE_PENDING_REMOTE_DELETE_TABLE_SQL)?;
+    db.execute_batch(CREATE_RECEIVED_TABS_TABLE_SQL)?;
     Ok(())
 }
 
@@ -60,7 +78,7 @@ pub struct TabsMigrationLogic;
 
 impl MigrationLogic for TabsMigrationLogic {
     const NAME: &'static str = "tabs storage db";
-    const END_VERSION: u32 = 5;
+    const END_VERSION: u32 = 6;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> MigrationResult<()> {
         let initial_pragmas = "
@@ -85,6 +103,7 @@ impl MigrationLogic for TabsMigrationLogic {
 
     fn upgrade_from(&self, db: &Transaction<'_>, version: u32) -> MigrationResult<()> {
         match version {
+            5 => upgrade_from_v5(db),
             3 | 4 => upgrade_simple_commands_drop(db),
             2 => upgrade_from_v2(db),
             1 => upgrade_from_v1(db),
@@ -93,6 +112,11 @@ impl MigrationLogic for TabsMigrationLogic {
     }
 }
 
+fn upgrade_from_v5(db: &Connection) -> MigrationResult<()> {
+    db.execute_batch(CREATE_RECEIVED_TABS_TABLE_SQL)?;
+    Ok(())
+}
+
 // while we can get away with this, we should :)
 fn upgrade_simple_commands_drop(db: &Connection) -> MigrationResult<()> {
     // v3 changed the table schema. v5 changed the name.
@@ -12,11 +12,14 @@ const TAB_ENTRIES_LIMIT: usize = 5;
 // Matches COMMAND_TTL in close_tabs.rs in fxa-client.
 const REMOTE_COMMAND_TTL_MS: u64 = 2 * 24 * 60 * 60 * 1000; // 48 hours.
 
+// How many received tabs we keep. Unread tabs are kept in preference to read ones.
+const RECEIVED_TABS_MAX: u32 = 100;
+
 use crate::error::*;
 use crate::schema;
-use crate::sync::record::TabsRecord;
+use crate::sync::record::{TabsRecord, TabsRecordTab};
 use crate::DeviceType;
-use crate::{PendingCommand, RemoteCommand, Timestamp};
+use crate::{PendingCloseTabs, PendingCommand, ReceivedTab, RemoteCommand, Timestamp};
 use rusqlite::{
     types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
     Connection, OpenFlags,
@@ -353,6 +356,59 @@ impl TabsStorage {
         filtered_crts
     }
 
+    /// Like `get_remote_tabs()`, but for a single device, and a page at a time. If `url_filter` is
+    /// given, only tabs whose current URL contains it (ignoring ASCII case) are returned, so it
+    /// can be a host or any other part of the URL. The filtering and paging are done in SQL, so
+    /// we only deserialize the tabs being returned.
+    pub fn get_remote_tabs_for_device(
+        &mut self,
+        device_id: &str,
+        url_filter: Option<&str>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<RemoteTab>> {
+        // Tabs records are keyed by the sync client id, but callers know devices by their FxA
+        // device id, which is what `get_remote_tabs()` reports when it knows it.
+        let remote_clients: HashMap<String, RemoteClient> =
+            match self.get_meta::<String>(schema::REMOTE_CLIENTS_KEY)? {
+                None => HashMap::default(),
+                Some(json) => serde_json::from_str(&json)?,
+            };
+        let client_id = remote_clients
+            .iter()
+            .find(|(_, client)| client.fxa_device_id.as_deref() == Some(device_id))
+            .map_or(device_id, |(id, _)| id.as_str());
+        let Some(conn) = self.open_if_exists()? else {
+            return Ok(Vec::new());
+        };
+        // As with `get_remote_tabs()`, tabs we've asked the device to close are hidden.
+        let tabs: Vec<TabsRecordTab> = conn.query_rows_and_then_cached(
+            "SELECT t.value
+             FROM tabs, json_each(tabs.record, '$.tabs') AS t
+             WHERE tabs.guid = :client_id
+               AND (:url_filter IS NULL
+                    OR instr(lower(json_extract(t.value, '$.urlHistory[0]')), lower(:url_filter)) > 0)
+               AND json_extract(t.value, '$.urlHistory[0]') NOT IN (
+                    SELECT url FROM remote_tab_commands
+                    WHERE device_id = :device_id
+                      AND command = :command_close_tab
+                      AND url IS NOT NULL
+               )
+             ORDER BY t.key
+             LIMIT :limit OFFSET :offset",
+            rusqlite::named_params! {
+                ":client_id": client_id,
+                ":device_id": device_id,
+                ":url_filter": url_filter,
+                ":command_close_tab": CommandKind::CloseTab,
+                ":limit": limit,
+                ":offset": offset,
+            },
+            |row| -> Result<_> { Ok(serde_json::from_str(&row.get::<_, String>(0)?)?) },
+        )?;
+        Ok(tabs.iter().map(RemoteTab::from_record_tab).collect())
+    }
+
     // Keep DB from growing infinitely since we only ask for records since our last sync
     // and may or may not know about the client it's associated with -- but we could at some point
     // and should start returning those tabs immediately. If that client hasn't been seen in 3 weeks,
@@ -574,6 +630,55 @@ impl TabsStorage {
         })
     }
 
+    /// Unsent close-tab commands grouped by device, so each device can be sent all its
+    /// URLs in one FxA `close_tabs()` call. Devices are ordered by their oldest request.
+    pub fn get_unsent_close_tabs(&mut self) -> Result<Vec<PendingCloseTabs>> {
+        let mut pending: Vec<PendingCloseTabs> = Vec::new();
+        for command in self.get_unsent_commands()? {
+            let RemoteCommand::CloseTab { url } = command.command;
+            match pending
+                .iter_mut()
+                .find(|p| p.device_id == command.device_id)
+            {
+                Some(p) => p.urls.push(url),
+                None => pending.push(PendingCloseTabs {
+                    device_id: command.device_id,
+                    urls: vec![url],
+                }),
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Flag the close-tab commands for these URLs as sent. URLs FxA couldn't send should be
+    /// left out, so they're retried. Returns how many commands were updated.
+    pub fn set_close_tabs_sent(&mut self, device_id: &str, urls: &[String]) -> Result<u32> {
+        let connection = self.open_or_create()?;
+        log::info!(
+            "setting {} remote tab closes sent: client={device_id}",
+            urls.len()
+        );
+        let tx = connection.unchecked_transaction()?;
+        let ts = Timestamp::now();
+        let mut changes = 0;
+        for url in urls {
+            changes += tx.execute_cached(
+                "UPDATE remote_tab_commands
+                 SET time_sent = :ts
+                 WHERE device_id = :device_id AND command = :command AND url = :url
+                    AND time_sent IS NULL;",
+                rusqlite::named_params! {
+                    ":command": CommandKind::CloseTab,
+                    ":device_id": &device_id,
+                    ":url": url,
+                    ":ts": &ts,
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(changes as u32)
+    }
+
     pub fn set_pending_command_sent(&mut self, command: &PendingCommand) -> Result<bool> {
         let connection = self.open_or_create()?;
         let RemoteCommand::CloseTab { url } = &command.command;
@@ -680,6 +785,92 @@ impl TabsStorage {
     }
 }
 
+// Implementations related to the inbox of tabs sent to us by other devices.
+impl TabsStorage {
+    /// Add a tab another device sent us. If the same tab is already waiting to be read, that's
+    /// returned instead of adding it again.
+    pub fn add_received_tab(
+        &mut self,
+        sender_device_id: Option<&str>,
+        title: &str,
+        url: &str,
+    ) -> Result<ReceivedTab> {
+        let connection = self.open_or_create()?;
+        log::info!("Adding received tab from {sender_device_id:?}");
+        let tx = connection.unchecked_transaction()?;
+        tx.execute_cached(
+            "INSERT OR IGNORE INTO received_tabs
+                (sender_device_id, title, url, time_received, time_read)
+            VALUES (:sender_device_id, :title, :url, :time_received, null)",
+            rusqlite::named_params! {
+                ":sender_device_id": sender_device_id,
+                ":title": slice_up_to(title.to_string(), MAX_TITLE_CHAR_LENGTH),
+                ":url": url,
+                ":time_received": Timestamp::now(),
+            },
+        )?;
+        // Drop the oldest tabs, read ones first, once we have too many.
+        tx.execute_cached(
+            "DELETE FROM received_tabs WHERE id NOT IN (
+                SELECT id FROM received_tabs
+                ORDER BY time_read IS NULL DESC, time_received DESC, id DESC
+                LIMIT :max
+            )",
+            rusqlite::named_params! { ":max": RECEIVED_TABS_MAX },
+        )?;
+        let tab = tx.query_row_and_then_cachable(
+            "SELECT id, sender_device_id, title, url, time_received, time_read
+             FROM received_tabs
+             WHERE IFNULL(sender_device_id, '') = IFNULL(:sender_device_id, '')
+                AND url = :url AND time_read IS NULL",
+            rusqlite::named_params! {
+                ":sender_device_id": sender_device_id,
+                ":url": url,
+            },
+            received_tab_from_row,
+            true,
+        )?;
+        tx.commit()?;
+        Ok(tab)
+    }
+
+    /// Unread received tabs, most recently received first.
+    pub fn get_unread_received_tabs(&mut self) -> Result<Vec<ReceivedTab>> {
+        let Some(conn) = self.open_if_exists()? else {
+            return Ok(Vec::new());
+        };
+        conn.query_rows_and_then_cached(
+            "SELECT id, sender_device_id, title, url, time_received, time_read
+             FROM received_tabs
+             WHERE time_read IS NULL
+             ORDER BY time_received DESC, id DESC",
+            [],
+            received_tab_from_row,
+        )
+    }
+
+    /// Returns false if there's no unread tab with that id.
+    pub fn mark_received_tab_read(&mut self, id: i64) -> Result<bool> {
+        let connection = self.open_or_create()?;
+        let changes = connection.execute_cached(
+            "UPDATE received_tabs SET time_read = :ts WHERE id = :id AND time_read IS NULL",
+            rusqlite::named_params! { ":id": id, ":ts": Timestamp::now() },
+        )?;
+        Ok(changes != 0)
+    }
+}
+
+fn received_tab_from_row(row: &rusqlite::Row<'_>) -> Result<ReceivedTab> {
+    Ok(ReceivedTab {
+        id: row.get("id")?,
+        sender_device_id: row.get("sender_device_id")?,
+        title: row.get("title")?,
+        url: row.get("url")?,
+        time_received: row.get("time_received")?,
+        time_read: row.get("time_read")?,
+    })
+}
+
 // Simple enum for the DB.
 #[derive(Debug, Copy, Clone)]
 #[repr(u8)]
@@ -1249,6 +1440,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_remote_tabs_for_device() {
+        env_logger::try_init().ok();
+        let mut storage = TabsStorage::new_with_mem_path("test_remote_tabs_for_device");
+        let tab = |url: &str| TabsRecordTab {
+            title: "the title".to_string(),
+            url_history: vec![url.to_string()],
+            last_used: 1711929600, // 4/1/2024
+            ..Default::default()
+        };
+        let record = TabsRecord {
+            id: "client-1".to_string(),
+            client_name: "Device #1".to_string(),
+            tabs: vec![
+                tab("https://mozilla.org/"),
+                tab("https://example.com/1"),
+                tab("https://EXAMPLE.com/2"),
+                tab("https://example.com/3"),
+                tab("https://example.org/"),
+            ],
+        };
+        let db = storage.open_or_create().unwrap();
+        db.execute(
+            "INSERT INTO tabs (guid, record, last_modified) VALUES (:guid, :record, 0)",
+            rusqlite::named_params! {
+                ":guid": &record.id,
+                ":record": serde_json::to_string(&record).unwrap(),
+            },
+        )
+        .unwrap();
+        // The client is known by its FxA device id.
+        let remote_clients: HashMap<String, RemoteClient> = HashMap::from([(
+            "client-1".to_string(),
+            RemoteClient {
+                fxa_device_id: Some("device-1".to_string()),
+                device_name: "Device #1".to_string(),
+                device_type: DeviceType::Desktop,
+            },
+        )]);
+        storage
+            .put_meta(
+                schema::REMOTE_CLIENTS_KEY,
+                &serde_json::to_string(&remote_clients).unwrap(),
+            )
+            .unwrap();
+        storage
+            .add_remote_tab_command(
+                "device-1",
+                &RemoteCommand::close_tab("https://example.com/3"),
+            )
+            .unwrap();
+
+        let urls = |tabs: Vec<RemoteTab>| -> Vec<String> {
+            tabs.into_iter()
+                .map(|tab| tab.url_history[0].clone())
+                .collect()
+        };
+        assert_eq!(
+            urls(
+                storage
+                    .get_remote_tabs_for_device("device-1", None, 10, 0)
+                    .unwrap()
+            ),
+            vec![
+                "https://mozilla.org/",
+                "https://example.com/1",
+                "https://EXAMPLE.com/2",
+                "https://example.org/",
+            ]
+        );
+        assert_eq!(
+            urls(
+                storage
+                    .get_remote_tabs_for_device("device-1", None, 2, 1)
+                    .unwrap()
+            ),
+            vec!["https://example.com/1", "https://EXAMPLE.com/2"]
+        );
+        assert_eq!(
+            urls(
+                storage
+                    .get_remote_tabs_for_device("device-1", Some("example.com"), 10, 0)
+                    .unwrap()
+            ),
+            vec!["https://example.com/1", "https://EXAMPLE.com/2"]
+        );
+        let tabs = storage
+            .get_remote_tabs_for_device("device-1", Some("mozilla"), 10, 0)
+            .unwrap();
+        assert_eq!(tabs[0].last_used, 1711929600000);
+        assert!(storage
+            .get_remote_tabs_for_device("device-2", None, 10, 0)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_remove_old_pending_closures_timed_removal() {
         env_logger::try_init().ok();
@@ -1494,6 +1781,128 @@ mod tests {
         assert_eq!(storage.get_unsent_commands().unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_close_tabs_batched() {
+        env_logger::try_init().ok();
+        let mut storage = TabsStorage::new_with_mem_path("test_close_tabs_batched");
+        let now = Timestamp::now().0;
+        for (device_id, url, offset) in [
+            ("device-2", "https://example2.com", 0),
+            ("device-1", "https://example1.com", 1),
+            ("device-2", "https://example3.com", 2),
+        ] {
+            storage
+                .add_remote_tab_command_at(
+                    device_id,
+                    &RemoteCommand::close_tab(url),
+                    Timestamp(now + offset),
+                )
+                .expect("should work");
+        }
+        assert_eq!(
+            storage.get_unsent_close_tabs().unwrap(),
+            vec![
+                PendingCloseTabs {
+                    device_id: "device-2".to_string(),
+                    urls: vec![
+                        "https://example2.com".to_string(),
+                        "https://example3.com".to_string()
+                    ],
+                },
+                PendingCloseTabs {
+                    device_id: "device-1".to_string(),
+                    urls: vec!["https://example1.com".to_string()],
+                },
+            ]
+        );
+
+        // FxA couldn't send one of the URLs, so only the other is marked as sent.
+        assert_eq!(
+            storage
+                .set_close_tabs_sent("device-2", &["https://example2.com".to_string()])
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            storage.get_unsent_close_tabs().unwrap(),
+            vec![
+                PendingCloseTabs {
+                    device_id: "device-2".to_string(),
+                    urls: vec!["https://example3.com".to_string()],
+                },
+                PendingCloseTabs {
+                    device_id: "device-1".to_string(),
+                    urls: vec!["https://example1.com".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_received_tabs() {
+        env_logger::try_init().ok();
+        let mut storage = TabsStorage::new_with_mem_path("test_received_tabs");
+        assert!(storage.get_unread_received_tabs().unwrap().is_empty());
+
+        let first = storage
+            .add_received_tab(Some("device-1"), "Example", "https://example.com")
+            .unwrap();
+        let second = storage
+            .add_received_tab(None, "Example 2", "https://example2.com")
+            .unwrap();
+        // The same tab delivered again isn't added twice.
+        assert_eq!(
+            storage
+                .add_received_tab(Some("device-1"), "Example", "https://example.com")
+                .unwrap()
+                .id,
+            first.id
+        );
+        assert_eq!(
+            storage.get_unread_received_tabs().unwrap(),
+            vec![second.clone(), first.clone()]
+        );
+
+        assert!(storage.mark_received_tab_read(first.id).unwrap());
+        assert!(!storage.mark_received_tab_read(first.id).unwrap());
+        assert_eq!(storage.get_unread_received_tabs().unwrap(), vec![second]);
+
+        // Once read, the same tab can be received again.
+        let again = storage
+            .add_received_tab(Some("device-1"), "Example", "https://example.com")
+            .unwrap();
+        assert_ne!(again.id, first.id);
+    }
+
+    #[test]
+    fn test_received_tabs_capped() {
+        env_logger::try_init().ok();
+        let mut storage = TabsStorage::new_with_mem_path("test_received_tabs_capped");
+        let oldest = storage
+            .add_received_tab(None, "Unread", "https://example.com/unread")
+            .unwrap();
+        let read = storage
+            .add_received_tab(None, "Read", "https://example.com/read")
+            .unwrap();
+        storage.mark_received_tab_read(read.id).unwrap();
+        for i in 0..RECEIVED_TABS_MAX - 1 {
+            storage
+                .add_received_tab(None, "Tab", &format!("https://example.com/{i}"))
+                .unwrap();
+        }
+        // The read tab was dropped first, so the oldest unread one is still there.
+        let count: u32 = storage
+            .open_if_exists()
+            .unwrap()
+            .unwrap()
+            .query_one("SELECT COUNT(*) FROM received_tabs")
+            .unwrap();
+        assert_eq!(count, RECEIVED_TABS_MAX);
+        let unread = storage.get_unread_received_tabs().unwrap();
+        assert_eq!(unread.len() as u32, RECEIVED_TABS_MAX);
+        assert_eq!(unread.last().unwrap().id, oldest.id);
+    }
+
     #[test]
     fn test_remove_pending_closures_only_affects_target_device() {
         env_logger::try_init().ok();
@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::storage::{ClientRemoteTabs, RemoteTab, TabsStorage};
-use crate::{ApiResult, PendingCommand, RemoteCommand};
+use crate::{ApiResult, PendingCloseTabs, PendingCommand, ReceivedTab, RemoteCommand};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
@@ -44,6 +44,49 @@ impl TabsStore {
         self.storage.lock().unwrap().get_remote_tabs()
     }
 
+    // A page of a single device's tabs, optionally filtered by URL, for when the full list from
+    // `get_all()` is more than the app needs.
+    #[error_support::handle_error(crate::Error)]
+    pub fn get_remote_tabs(
+        &self,
+        device_id: String,
+        url_filter: Option<String>,
+        limit: u32,
+        offset: u32,
+    ) -> ApiResult<Vec<RemoteTab>> {
+        self.storage.lock().unwrap().get_remote_tabs_for_device(
+            &device_id,
+            url_filter.as_deref(),
+            limit,
+            offset,
+        )
+    }
+
+    // The inbox of tabs sent to us by other devices. It's local-only, so it survives the tabs
+    // engine being reset or wiped, and it's capped in size.
+    #[error_support::handle_error(crate::Error)]
+    pub fn add_received_tab(
+        &self,
+        sender_device_id: Option<String>,
+        title: String,
+        url: String,
+    ) -> ApiResult<ReceivedTab> {
+        self.storage
+            .lock()
+            .unwrap()
+            .add_received_tab(sender_device_id.as_deref(), &title, &url)
+    }
+
+    #[error_support::handle_error(crate::Error)]
+    pub fn get_unread_received_tabs(&self) -> ApiResult<Vec<ReceivedTab>> {
+        self.storage.lock().unwrap().get_unread_received_tabs()
+    }
+
+    #[error_support::handle_error(crate::Error)]
+    pub fn mark_received_tab_read(&self, id: i64) -> ApiResult<bool> {
+        self.storage.lock().unwrap().mark_received_tab_read(id)
+    }
+
     pub fn new_remote_command_store(self: Arc<Self>) -> Arc<RemoteCommandStore> {
         Arc::new(RemoteCommandStore {
             store: Arc::clone(&self),
@@ -103,6 +146,34 @@ impl RemoteCommandStore {
             .remove_remote_tab_command(device_id, command)
     }
 
+    // Request that a tab is closed on another device. Until the app removes the command, the tab
+    // is hidden from remote_tabs(), as though it had already been closed.
+    #[error_support::handle_error(crate::Error)]
+    pub fn close_remote_tab(&self, device_id: &str, url: &str) -> ApiResult<bool> {
+        self.store
+            .storage
+            .lock()
+            .unwrap()
+            .add_remote_tab_command(device_id, &RemoteCommand::CloseTab { url: url.into() })
+    }
+
+    // Delivery is via fxa-client's `close_tabs()`: for each entry here the app calls
+    // `close_tabs(device_id, urls)`, then set_close_tabs_sent() with the URLs which were sent -
+    // ie, all of them, less any returned in `CloseTabsResult::TabsNotClosed`.
+    #[error_support::handle_error(crate::Error)]
+    pub fn get_unsent_close_tabs(&self) -> ApiResult<Vec<PendingCloseTabs>> {
+        self.store.storage.lock().unwrap().get_unsent_close_tabs()
+    }
+
+    #[error_support::handle_error(crate::Error)]
+    pub fn set_close_tabs_sent(&self, device_id: &str, urls: Vec<String>) -> ApiResult<u32> {
+        self.store
+            .storage
+            .lock()
+            .unwrap()
+            .set_close_tabs_sent(device_id, &urls)
+    }
+
     #[error_support::handle_error(crate::Error)]
     pub fn get_unsent_commands(&self) -> ApiResult<Vec<PendingCommand>> {
         self.store.storage.lock().unwrap().get_unsent_commands()
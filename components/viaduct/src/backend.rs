@@ -14,7 +14,11 @@ pub fn note_backend(which: &str) {
     static NOTE_BACKEND_ONCE: std::sync::Once = std::sync::Once::new();
     let mut called = false;
     NOTE_BACKEND_ONCE.call_once(|| {
-        log::info!("Using HTTP backend {}", which);
+        log::info!(
+            "Using HTTP backend {} ({})",
+            which,
+            get_backend().capabilities()
+        );
         called = true;
     });
     if !called {
@@ -24,6 +28,46 @@ pub fn note_backend(which: &str) {
 
 pub trait Backend: Send + Sync + 'static {
     fn send(&self, request: crate::Request) -> Result<crate::Response, crate::Error>;
+
+    /// The optional features this backend supports. Defaults to none of them.
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+}
+
+/// Optional features which only some backends support.
+///
+/// Use [`backend_capabilities`] to check these at runtime before relying on
+/// one of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// Response bodies can be consumed incrementally rather than being
+    /// buffered in full before `send()` returns.
+    pub streaming: bool,
+    /// HTTP/2 is negotiated with servers which support it.
+    pub http2: bool,
+    /// Brotli-encoded responses are transparently decoded.
+    pub brotli: bool,
+    /// Server certificates can be pinned.
+    pub certificate_pinning: bool,
+    /// Requests can be sent through an explicit proxy with
+    /// [`Routing::Proxy`](crate::Routing::Proxy).
+    pub proxy: bool,
+}
+
+impl std::fmt::Display for BackendCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "streaming: {}, http2: {}, brotli: {}, certificate_pinning: {}, proxy: {}",
+            self.streaming, self.http2, self.brotli, self.certificate_pinning, self.proxy
+        )
+    }
+}
+
+/// Returns the capabilities of the backend requests will be sent with.
+pub fn backend_capabilities() -> BackendCapabilities {
+    get_backend().capabilities()
 }
 
 static BACKEND: OnceCell<&'static dyn Backend> = OnceCell::new();
@@ -39,8 +83,95 @@ pub(crate) fn get_backend() -> &'static dyn Backend {
 }
 
 pub fn send(request: crate::Request) -> Result<crate::Response, crate::Error> {
+    let start = std::time::Instant::now();
+    let method = request.method;
+    let host = request.url.host_str().map(ToOwned::to_owned);
+    let retry_count = std::cell::Cell::new(0);
+    let result = send_observed(request, &retry_count);
+    crate::observer::notify(method, host, &result, start.elapsed(), retry_count.get());
+    result
+}
+
+fn send_observed(
+    mut request: crate::Request,
+    retry_count: &std::cell::Cell<u32>,
+) -> Result<crate::Response, crate::Error> {
     validate_request(&request)?;
-    get_backend().send(request)
+    let backend = get_backend();
+    crate::routing::validate_routing(backend, &request)?;
+    apply_default_timeouts(&mut request);
+    apply_default_limits(&mut request);
+    validate_body_size(&request)?;
+    let max_response_size = request.max_response_size;
+    #[cfg(feature = "ohttp")]
+    if let crate::Routing::Ohttp(relay) = &request.routing {
+        validate_request(&crate::Request::post(relay.relay_url.clone()))?;
+        let transport = crate::routing::OhttpTransport {
+            backend,
+            relay: relay.clone(),
+        };
+        let response = send_with_policy(&transport, request, retry_count)?;
+        return validate_response_size(response, max_response_size);
+    }
+    let response = send_with_policy(backend, request, retry_count)?;
+    validate_response_size(response, max_response_size)
+}
+
+fn send_with_policy(
+    backend: &dyn Backend,
+    mut request: crate::Request,
+    retry_count: &std::cell::Cell<u32>,
+) -> Result<crate::Response, crate::Error> {
+    match request.retry_policy.take() {
+        Some(policy) => crate::retry::send_with_retry(backend, request, &policy, |delay| {
+            retry_count.set(retry_count.get() + 1);
+            std::thread::sleep(delay)
+        }),
+        None => backend.send(request),
+    }
+}
+
+// Fill in any timeouts the request didn't set from the global settings, so
+// backends only ever need to look at the request itself.
+fn apply_default_timeouts(request: &mut crate::Request) {
+    let settings = GLOBAL_SETTINGS.read();
+    request.connect_timeout = request.connect_timeout.or(settings.connect_timeout);
+    request.read_timeout = request.read_timeout.or(settings.read_timeout);
+}
+
+// As above, for the size limits.
+fn apply_default_limits(request: &mut crate::Request) {
+    let settings = GLOBAL_SETTINGS.read();
+    request.max_request_body_size = request
+        .max_request_body_size
+        .or(settings.max_request_body_size);
+    request.max_response_size = request.max_response_size.or(settings.max_response_size);
+}
+
+fn validate_body_size(request: &crate::Request) -> Result<(), crate::Error> {
+    match (&request.body, request.max_request_body_size) {
+        (Some(body), Some(limit)) if body.len() as u64 > limit => {
+            Err(crate::Error::RequestBodyTooLarge {
+                size: body.len() as u64,
+                limit,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Backends should stop reading a response once it's over the limit, but not
+// all of them can, so we check again here.
+fn validate_response_size(
+    response: crate::Response,
+    limit: Option<u64>,
+) -> Result<crate::Response, crate::Error> {
+    match limit {
+        Some(limit) if response.body.len() as u64 > limit => {
+            Err(crate::Error::ResponseTooLarge { limit })
+        }
+        _ => Ok(response),
+    }
 }
 
 pub fn validate_request(request: &crate::Request) -> Result<(), crate::Error> {
@@ -118,6 +249,56 @@ mod tests {
         assert!(validate_request(&localhost_request_ipv6).is_ok());
     }
 
+    #[test]
+    fn test_apply_default_timeouts() {
+        let mut request = crate::Request::get(url::Url::parse("https://example.com").unwrap())
+            .connect_timeout(std::time::Duration::from_secs(1));
+        apply_default_timeouts(&mut request);
+        assert_eq!(
+            request.connect_timeout,
+            Some(std::time::Duration::from_secs(1))
+        );
+        assert_eq!(request.read_timeout, GLOBAL_SETTINGS.read().read_timeout);
+    }
+
+    #[test]
+    fn test_validate_body_size() {
+        let url = url::Url::parse("https://example.com").unwrap();
+        let mut request = crate::Request::post(url.clone()).body(vec![0; 10]);
+        apply_default_limits(&mut request);
+        assert!(validate_body_size(&request).is_ok());
+
+        let request = crate::Request::post(url.clone())
+            .body(vec![0; 10])
+            .max_request_body_size(9);
+        assert!(matches!(
+            validate_body_size(&request),
+            Err(crate::Error::RequestBodyTooLarge { size: 10, limit: 9 })
+        ));
+
+        // Requests without a body are always fine.
+        let request = crate::Request::get(url).max_request_body_size(0);
+        assert!(validate_body_size(&request).is_ok());
+    }
+
+    #[test]
+    fn test_validate_response_size() {
+        let url = url::Url::parse("https://example.com").unwrap();
+        let response = || crate::Response {
+            request_method: crate::Method::Get,
+            url: url.clone(),
+            status: 200,
+            headers: crate::Headers::new(),
+            body: vec![0; 10],
+        };
+        assert!(validate_response_size(response(), None).is_ok());
+        assert!(validate_response_size(response(), Some(10)).is_ok());
+        assert!(matches!(
+            validate_response_size(response(), Some(9)),
+            Err(crate::Error::ResponseTooLarge { limit: 9 })
+        ));
+    }
+
     #[test]
     fn test_validate_request_addn_allowed_insecure_url() {
         let request_root = crate::Request::new(
@@ -2,7 +2,10 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::{backend::Backend, settings::GLOBAL_SETTINGS};
+use crate::{
+    backend::{Backend, BackendCapabilities},
+    settings::GLOBAL_SETTINGS,
+};
 use crate::{msg_types, Error};
 use ffi_support::{ByteBuffer, FfiStr};
 
@@ -20,8 +23,20 @@ impl From<crate::Request> for msg_types::Request {
             headers: request.headers.into(),
             follow_redirects: settings.follow_redirects,
             use_caches: settings.use_caches,
-            connect_timeout_secs: settings.connect_timeout.map_or(0, |d| d.as_secs() as i32),
-            read_timeout_secs: settings.read_timeout.map_or(0, |d| d.as_secs() as i32),
+            connect_timeout_secs: timeout_secs(request.connect_timeout),
+            read_timeout_secs: timeout_secs(request.read_timeout),
+        }
+    }
+}
+
+// The fetch callbacks only deal in whole seconds, and treat 0 as "no timeout",
+// so round up rather than turning a short timeout into an infinite one.
+fn timeout_secs(timeout: Option<std::time::Duration>) -> i32 {
+    match timeout {
+        None => 0,
+        Some(d) => {
+            let secs = d.as_secs() + u64::from(d.subsec_nanos() > 0);
+            i32::try_from(secs).unwrap_or(i32::MAX)
         }
     }
 }
@@ -100,6 +115,18 @@ impl Backend for FfiBackend {
             headers,
         })
     }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        // The response is passed back to us as a single protobuf message, so
+        // nothing can be streamed. Both OkHttp and NSURLSession negotiate
+        // HTTP/2, but only NSURLSession decodes brotli out of the box. The
+        // request message has no way to specify a proxy.
+        BackendCapabilities {
+            http2: true,
+            brotli: cfg!(target_os = "ios"),
+            ..Default::default()
+        }
+    }
 }
 
 /// Type of the callback we need callers on the other side of the FFI to
@@ -180,7 +207,15 @@ pub extern "C" fn viaduct_alloc_bytebuffer(sz: i32) -> ByteBuffer {
 pub extern "C" fn viaduct_log_error(s: FfiStr<'_>) {
     let mut error = ffi_support::ExternError::default();
     ffi_support::call_with_output(&mut error, || {
-        log::error!("Viaduct Ffi Error: {}", s.as_str())
+        // `as_str()` panics on null or invalid UTF-8, which would lose the message we're trying
+        // to log. Replace any invalid UTF-8 instead.
+        // TODO: use `FfiStr::as_str_lossy()` and a maximum length once ffi-support has them, and
+        // switch any new `FfiStr` arguments to `try_as_str()` rather than `as_str()`.
+        let message = s.into_opt_string();
+        log::error!(
+            "Viaduct Ffi Error: {}",
+            message.as_deref().unwrap_or("<null>")
+        )
     });
     error.consume_and_log_if_error();
 }
@@ -0,0 +1,225 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Support for async versions of network-bound component APIs.
+//!
+//! Requests block the thread that sends them, so components can't expose async APIs by just
+//! awaiting them. Instead, [`spawn_blocking`] runs the blocking work on a background thread and
+//! returns a future which completes with its result. The future doesn't depend on any particular
+//! executor, so it works with UniFFI's foreign executors (eg, Kotlin coroutines or Swift tasks).
+//!
+//! The background threads come from a small pool, so a burst of calls queues up rather than
+//! starting a thread per request.
+
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+/// The most threads the pool will start.
+const MAX_THREADS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Default)]
+struct PoolState {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+}
+
+#[derive(Default)]
+struct Pool {
+    state: Mutex<PoolState>,
+    job_available: Condvar,
+}
+
+static POOL: Lazy<Pool> = Lazy::new(Pool::default);
+
+impl Pool {
+    fn submit(&'static self, job: Job) {
+        let mut state = self.state.lock();
+        state.jobs.push_back(job);
+        if state.idle == 0 && state.threads < MAX_THREADS {
+            let spawned = std::thread::Builder::new()
+                .name("viaduct-blocking".into())
+                .spawn(move || self.run_worker());
+            match spawned {
+                Ok(_) => state.threads += 1,
+                Err(e) => log::warn!("Failed to start a viaduct-blocking thread: {e}"),
+            }
+        }
+        if state.threads == 0 {
+            // We couldn't start any thread, so rather than leave the job queued forever, run
+            // it here. This blocks the caller, but still produces a result.
+            let job = state.jobs.pop_back().expect("job was just queued");
+            drop(state);
+            job();
+            return;
+        }
+        self.job_available.notify_one();
+    }
+
+    fn run_worker(&self) {
+        loop {
+            let job = {
+                let mut state = self.state.lock();
+                loop {
+                    if let Some(job) = state.jobs.pop_front() {
+                        break job;
+                    }
+                    state.idle += 1;
+                    self.job_available.wait(&mut state);
+                    state.idle -= 1;
+                }
+            };
+            // A panicking job marks its task as panicked when its `Completion` is dropped; catch
+            // the panic here so that the worker keeps running.
+            let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+        }
+    }
+}
+
+enum TaskState<T> {
+    Running(Option<Waker>),
+    Done(T),
+    Panicked,
+    Taken,
+}
+
+/// The future returned by [`spawn_blocking`].
+///
+/// If the background work panics, polling the future panics too.
+pub struct BlockingTask<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+/// Runs `f` on a background thread, returning a future which completes with its result.
+///
+/// At most [`MAX_THREADS`] calls run at once; the rest wait for a thread to become free. In the
+/// unlikely case that no thread can be started at all, `f` runs on the calling thread instead.
+pub fn spawn_blocking<F, T>(f: F) -> BlockingTask<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let state = Arc::new(Mutex::new(TaskState::Running(None)));
+    let completion = Completion {
+        state: Arc::clone(&state),
+    };
+    POOL.submit(Box::new(move || completion.complete(f())));
+    BlockingTask { state }
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock();
+        match std::mem::replace(&mut *state, TaskState::Taken) {
+            TaskState::Running(_) => {
+                *state = TaskState::Running(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            TaskState::Done(result) => Poll::Ready(result),
+            TaskState::Panicked => panic!("background task panicked"),
+            TaskState::Taken => panic!("BlockingTask polled after completion"),
+        }
+    }
+}
+
+/// Stores the result of the background work and wakes the future. If the work panics, this is
+/// dropped without a result, which marks the task as panicked so the future doesn't hang.
+struct Completion<T> {
+    state: Arc<Mutex<TaskState<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn complete(self, result: T) {
+        self.finish(TaskState::Done(result));
+    }
+
+    fn finish(&self, new_state: TaskState<T>) {
+        let old_state = std::mem::replace(&mut *self.state.lock(), new_state);
+        // Wake without holding the lock, in case the executor polls immediately.
+        if let TaskState::Running(Some(waker)) = old_state {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let running = matches!(*self.state.lock(), TaskState::Running(_));
+        if running {
+            self.finish(TaskState::Panicked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn_blocking() {
+        let caller = std::thread::current().id();
+        let result = block_on(spawn_blocking(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            std::thread::current().id() != caller
+        }));
+        assert!(result, "should run on another thread");
+    }
+
+    #[test]
+    fn test_spawn_blocking_panic() {
+        let result = std::panic::catch_unwind(|| block_on(spawn_blocking(|| panic!("oops"))));
+        assert!(result.is_err());
+        // The pool keeps working after a job panics.
+        assert_eq!(block_on(spawn_blocking(|| 1 + 1)), 2);
+    }
+
+    #[test]
+    fn test_spawn_blocking_more_tasks_than_threads() {
+        let tasks: Vec<_> = (0..MAX_THREADS * 4)
+            .map(|i| {
+                spawn_blocking(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                    i
+                })
+            })
+            .collect();
+        let results: Vec<_> = tasks.into_iter().map(block_on).collect();
+        assert_eq!(results, (0..MAX_THREADS * 4).collect::<Vec<_>>());
+        assert!(POOL.state.lock().threads <= MAX_THREADS);
+    }
+}
@@ -26,6 +26,43 @@ pub enum Error {
 
     #[error("[no-sentry] Validation error: URL does not use TLS protocol.")]
     NonTlsUrl,
+
+    /// The request asked to be routed in a way the backend (or this build
+    /// of viaduct) doesn't support.
+    #[error("Unsupported request routing: {0}")]
+    UnsupportedRouting(&'static str),
+
+    #[error("[no-sentry] OHTTP error: {0}")]
+    OhttpError(String),
+
+    #[error("[no-sentry] Request body of {size} bytes exceeds the limit of {limit} bytes")]
+    RequestBodyTooLarge { size: u64, limit: u64 },
+
+    /// The response body was larger than the request's
+    /// [`max_response_size`](crate::Request::max_response_size).
+    #[error("[no-sentry] Response body exceeds the limit of {limit} bytes")]
+    ResponseTooLarge { limit: u64 },
+}
+
+impl Error {
+    /// A short, stable name for the kind of error, suitable for use as a
+    /// telemetry label. Unlike the `Display` output, this never contains
+    /// details of the request.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Error::RequestHeaderError(_) => "request_header",
+            Error::BackendError(_) => "backend",
+            Error::NetworkError(_) => "network",
+            Error::BackendNotInitialized => "backend_not_initialized",
+            Error::SetBackendError => "set_backend",
+            Error::UrlError(_) => "url",
+            Error::NonTlsUrl => "non_tls_url",
+            Error::UnsupportedRouting(_) => "unsupported_routing",
+            Error::OhttpError(_) => "ohttp",
+            Error::RequestBodyTooLarge { .. } => "request_body_too_large",
+            Error::ResponseTooLarge { .. } => "response_too_large",
+        }
+    }
 }
 
 impl From<url::ParseError> for Error {
@@ -5,16 +5,25 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+use std::time::Duration;
 use url::Url;
 #[macro_use]
 mod headers;
 
 mod backend;
+mod blocking;
 pub mod error;
+mod observer;
+mod retry;
+mod routing;
 pub mod settings;
+pub use blocking::{spawn_blocking, BlockingTask};
 pub use error::*;
+pub use observer::{set_observer, RequestEvent, ViaductObserver};
+pub use retry::RetryPolicy;
+pub use routing::{OhttpRelay, Routing};
 
-pub use backend::{note_backend, set_backend, Backend};
+pub use backend::{backend_capabilities, note_backend, set_backend, Backend, BackendCapabilities};
 pub use headers::{consts as header_names, Header, HeaderName, Headers, InvalidHeaderName};
 pub use settings::GLOBAL_SETTINGS;
 
@@ -69,6 +78,22 @@ pub struct Request {
     pub url: Url,
     pub headers: Headers,
     pub body: Option<Vec<u8>>,
+    /// If set, `send()` retries the request according to this policy.
+    pub retry_policy: Option<RetryPolicy>,
+    /// How long to wait for a connection to be established. If `None`, the
+    /// process-wide default from [`settings::Settings`] is used.
+    pub connect_timeout: Option<Duration>,
+    /// How long to wait for the response. If `None`, the process-wide default
+    /// from [`settings::Settings`] is used.
+    pub read_timeout: Option<Duration>,
+    /// The largest body, in bytes, this request may send. If `None`, the
+    /// process-wide default from [`settings::Settings`] is used.
+    pub max_request_body_size: Option<u64>,
+    /// The largest response body, in bytes, this request will accept. If
+    /// `None`, the process-wide default from [`settings::Settings`] is used.
+    pub max_response_size: Option<u64>,
+    /// How the request reaches the server. Defaults to [`Routing::Direct`].
+    pub routing: Routing,
 }
 
 impl Request {
@@ -80,6 +105,12 @@ impl Request {
             url,
             headers: Headers::new(),
             body: None,
+            retry_policy: None,
+            connect_timeout: None,
+            read_timeout: None,
+            max_request_body_size: None,
+            max_response_size: None,
+            routing: Routing::Direct,
         }
     }
 
@@ -196,6 +227,93 @@ impl Request {
         Ok(self)
     }
 
+    /// Retry this request on failure according to `policy`.
+    ///
+    /// ## Example
+    /// ```
+    /// # use viaduct::{Request, RetryPolicy};
+    /// # use url::Url;
+    /// # let some_url = url::Url::parse("https://www.example.com").unwrap();
+    /// let req = Request::get(some_url).retry_policy(RetryPolicy {
+    ///     max_attempts: 5,
+    ///     ..Default::default()
+    /// });
+    /// assert_eq!(req.retry_policy.unwrap().max_attempts, 5);
+    /// ```
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Override the default connect timeout for this request.
+    ///
+    /// ## Example
+    /// ```
+    /// # use viaduct::Request;
+    /// # use std::time::Duration;
+    /// # let some_url = url::Url::parse("https://www.example.com").unwrap();
+    /// let req = Request::get(some_url)
+    ///     .connect_timeout(Duration::from_secs(5))
+    ///     .read_timeout(Duration::from_secs(30));
+    /// assert_eq!(req.connect_timeout, Some(Duration::from_secs(5)));
+    /// assert_eq!(req.read_timeout, Some(Duration::from_secs(30)));
+    /// ```
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Override the default read timeout for this request.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the size of this request's body. There's no limit by default.
+    ///
+    /// `send()` fails with [`Error::RequestBodyTooLarge`] without sending
+    /// anything if the body is larger.
+    pub fn max_request_body_size(mut self, bytes: u64) -> Self {
+        self.max_request_body_size = Some(bytes);
+        self
+    }
+
+    /// Limit the size of the response body, for example to bound the memory
+    /// used by a download. There's no limit by default.
+    ///
+    /// `send()` fails with [`Error::ResponseTooLarge`] if the response body is
+    /// larger.
+    ///
+    /// ## Example
+    /// ```
+    /// # use viaduct::Request;
+    /// # let some_url = url::Url::parse("https://www.example.com").unwrap();
+    /// let req = Request::get(some_url).max_response_size(100 * 1024 * 1024);
+    /// assert_eq!(req.max_response_size, Some(100 * 1024 * 1024));
+    /// ```
+    pub fn max_response_size(mut self, bytes: u64) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Send this request through a proxy or an OHTTP relay.
+    ///
+    /// `send()` fails with [`Error::UnsupportedRouting`] if the backend can't
+    /// route the request this way.
+    ///
+    /// ## Example
+    /// ```
+    /// # use viaduct::{Request, Routing};
+    /// # let some_url = url::Url::parse("https://www.example.com").unwrap();
+    /// let proxy = url::Url::parse("http://proxy.example.com:3128").unwrap();
+    /// let req = Request::get(some_url).routing(Routing::Proxy(proxy.clone()));
+    /// assert_eq!(req.routing, Routing::Proxy(proxy));
+    /// ```
+    pub fn routing(mut self, routing: Routing) -> Self {
+        self.routing = routing;
+        self
+    }
+
     /// Set this request's body.
     pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
         self.body = Some(body.into());
@@ -0,0 +1,112 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Hooks which let the application observe every request made through viaduct, for example to
+//! build network telemetry.
+//!
+//! Events deliberately carry only metadata: never URLs beyond the host, bodies or headers (and
+//! so never auth tokens).
+
+use crate::{Error, Method, Response};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receives an event for each completed call to `Request::send()`.
+///
+/// Observers are called synchronously on the thread which sent the request, so should return
+/// quickly.
+pub trait ViaductObserver: Send + Sync {
+    fn on_request_complete(&self, event: &RequestEvent);
+}
+
+/// Describes a completed request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestEvent {
+    pub method: Method,
+    pub host: Option<String>,
+    /// The status of the final response, or `None` if the request failed.
+    pub status: Option<u16>,
+    /// The total time taken, including any retries and the delays between them.
+    pub duration: Duration,
+    /// The number of retries made by the request's [`RetryPolicy`](crate::RetryPolicy).
+    pub retry_count: u32,
+    /// If the request failed, the kind of error; see [`Error::class`].
+    pub error_class: Option<&'static str>,
+    /// The id of the operation the request was sent in, if any; see
+    /// `error_support::enter_operation()`.
+    pub operation_id: Option<String>,
+}
+
+static OBSERVER: Lazy<RwLock<Option<Arc<dyn ViaductObserver>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Registers the observer to notify of every request, replacing any previous one. Pass `None`
+/// to stop observing.
+pub fn set_observer(observer: Option<Arc<dyn ViaductObserver>>) {
+    *OBSERVER.write() = observer;
+}
+
+pub(crate) fn notify(
+    method: Method,
+    host: Option<String>,
+    result: &Result<Response, Error>,
+    duration: Duration,
+    retry_count: u32,
+) {
+    // Clone the observer so it isn't called with the lock held.
+    let Some(observer) = OBSERVER.read().clone() else {
+        return;
+    };
+    observer.on_request_complete(&RequestEvent {
+        method,
+        host,
+        status: result.as_ref().ok().map(|resp| resp.status),
+        duration,
+        retry_count,
+        error_class: result.as_ref().err().map(Error::class),
+        operation_id: error_support::current_operation_id(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+    use parking_lot::Mutex;
+    use url::Url;
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<RequestEvent>>,
+    }
+
+    impl ViaductObserver for RecordingObserver {
+        fn on_request_complete(&self, event: &RequestEvent) {
+            self.events.lock().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let observer = Arc::new(RecordingObserver::default());
+        set_observer(Some(observer.clone()));
+        // Fails validation before reaching a backend, but is still observed.
+        let url = Url::parse("http://example.com/path?token=secret").unwrap();
+        {
+            let _operation = error_support::enter_operation("op-1");
+            assert!(Request::get(url).send().is_err());
+        }
+        set_observer(None);
+
+        let events = observer.events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].method, Method::Get);
+        assert_eq!(events[0].host.as_deref(), Some("example.com"));
+        assert_eq!(events[0].status, None);
+        assert_eq!(events[0].retry_count, 0);
+        assert_eq!(events[0].error_class, Some("non_tls_url"));
+        assert_eq!(events[0].operation_id.as_deref(), Some("op-1"));
+    }
+}
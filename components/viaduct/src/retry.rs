@@ -0,0 +1,267 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Automatic retries with exponential backoff.
+//!
+//! Attach a [`RetryPolicy`] to a request with [`Request::retry_policy`](crate::Request::retry_policy)
+//! to have `send()` transparently retry it on network errors or on a configurable set of response
+//! status codes.  The retry loop lives here rather than in the individual backends, so that every
+//! backend (and every component) gets the same behavior.
+
+use crate::{backend::Backend, header_names, status_codes, Error, Request, Response};
+use std::time::Duration;
+
+/// Describes if and how a failed request should be retried.
+///
+/// Note that retries are only safe for idempotent requests, or for requests where the server
+/// deduplicates repeated submissions. Callers are responsible for only attaching a retry policy
+/// to requests where this holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the initial one. A value of 0 or 1 disables
+    /// retries.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry.
+    pub initial_backoff: Duration,
+    /// The upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+    /// The delay is multiplied by this value after every attempt.
+    pub backoff_multiplier: u32,
+    /// Response status codes that should be retried.
+    pub retry_on_status: Vec<u16>,
+    /// Whether to retry when the backend returns a [`Error::NetworkError`].
+    pub retry_on_network_error: bool,
+    /// Whether to honor the `Retry-After` header of a retryable response.
+    ///
+    /// Only the delay-seconds form of the header is supported. If the server asks us to wait
+    /// longer than `max_backoff`, we stop retrying and return the response to the caller, which
+    /// is expected to handle the backoff itself.
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            backoff_multiplier: 2,
+            retry_on_status: vec![
+                status_codes::TOO_MANY_REQUESTS,
+                status_codes::BAD_GATEWAY,
+                status_codes::SERVICE_UNAVAILABLE,
+                status_codes::GATEWAY_TIMEOUT,
+            ],
+            retry_on_network_error: true,
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `retry` (starting at 1), ignoring `Retry-After`.
+    fn backoff_for(&self, retry: u32) -> Duration {
+        let factor = self
+            .backoff_multiplier
+            .max(1)
+            .saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    /// Decide whether `result` should be retried, and if so, how long to wait first.
+    fn should_retry(&self, result: &Result<Response, Error>, retry: u32) -> Option<Duration> {
+        match result {
+            Err(Error::NetworkError(_)) if self.retry_on_network_error => {
+                Some(self.backoff_for(retry))
+            }
+            Ok(resp) if self.retry_on_status.contains(&resp.status) => {
+                let retry_after = if self.respect_retry_after {
+                    resp.headers
+                        .get_as::<u64, _>(header_names::RETRY_AFTER)
+                        .and_then(Result::ok)
+                        .map(Duration::from_secs)
+                } else {
+                    None
+                };
+                match retry_after {
+                    Some(d) if d > self.max_backoff => None,
+                    Some(d) => Some(d),
+                    None => Some(self.backoff_for(retry)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn send_with_retry(
+    backend: &dyn Backend,
+    request: Request,
+    policy: &RetryPolicy,
+    sleep: impl Fn(Duration),
+) -> Result<Response, Error> {
+    let mut attempt = 1;
+    loop {
+        let result = backend.send(request.clone());
+        if attempt >= policy.max_attempts {
+            return result;
+        }
+        match policy.should_retry(&result, attempt) {
+            Some(delay) => {
+                log::info!(
+                    "{} {} failed (attempt {} of {}), retrying in {:?}",
+                    request.method,
+                    request.url.path(),
+                    attempt,
+                    policy.max_attempts,
+                    delay
+                );
+                sleep(delay);
+                attempt += 1;
+            }
+            None => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Headers, Method};
+    use parking_lot::Mutex;
+    use url::Url;
+
+    /// A backend that replays a fixed list of results.
+    struct ScriptedBackend {
+        results: Mutex<Vec<Result<(u16, Option<&'static str>), &'static str>>>,
+        calls: Mutex<u32>,
+    }
+
+    impl ScriptedBackend {
+        fn new(mut results: Vec<Result<(u16, Option<&'static str>), &'static str>>) -> Self {
+            results.reverse();
+            Self {
+                results: Mutex::new(results),
+                calls: Mutex::new(0),
+            }
+        }
+    }
+
+    impl Backend for ScriptedBackend {
+        fn send(&self, request: Request) -> Result<Response, Error> {
+            *self.calls.lock() += 1;
+            match self.results.lock().pop().expect("too many requests") {
+                Ok((status, retry_after)) => {
+                    let mut headers = Headers::new();
+                    if let Some(v) = retry_after {
+                        headers.insert(header_names::RETRY_AFTER, v).unwrap();
+                    }
+                    Ok(Response {
+                        request_method: request.method,
+                        url: request.url,
+                        status,
+                        headers,
+                        body: vec![],
+                    })
+                }
+                Err(msg) => Err(Error::NetworkError(msg.to_string())),
+            }
+        }
+    }
+
+    fn request() -> Request {
+        Request::new(Method::Get, Url::parse("https://example.com/").unwrap())
+    }
+
+    fn run(
+        backend: &ScriptedBackend,
+        policy: &RetryPolicy,
+    ) -> (Result<Response, Error>, Vec<Duration>) {
+        let sleeps = Mutex::new(vec![]);
+        let result = send_with_retry(backend, request(), policy, |d| sleeps.lock().push(d));
+        (result, sleeps.into_inner())
+    }
+
+    #[test]
+    fn test_backoff_for() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff_for(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff_for(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff_for(4), Duration::from_secs(5));
+        assert_eq!(policy.backoff_for(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let backend = ScriptedBackend::new(vec![Err("offline"), Ok((503, None)), Ok((200, None))]);
+        let (result, sleeps) = run(&backend, &RetryPolicy::default());
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(*backend.calls.lock(), 3);
+        assert_eq!(
+            sleeps,
+            vec![Duration::from_millis(500), Duration::from_millis(1000)]
+        );
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let backend = ScriptedBackend::new(vec![Ok((503, None)), Ok((503, None))]);
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let (result, sleeps) = run(&backend, &policy);
+        assert_eq!(result.unwrap().status, 503);
+        assert_eq!(sleeps.len(), 1);
+    }
+
+    #[test]
+    fn test_non_retryable() {
+        let backend = ScriptedBackend::new(vec![Ok((404, None))]);
+        let (result, sleeps) = run(&backend, &RetryPolicy::default());
+        assert_eq!(result.unwrap().status, 404);
+        assert!(sleeps.is_empty());
+
+        let backend = ScriptedBackend::new(vec![Err("offline")]);
+        let policy = RetryPolicy {
+            retry_on_network_error: false,
+            ..Default::default()
+        };
+        let (result, sleeps) = run(&backend, &policy);
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert!(sleeps.is_empty());
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let backend = ScriptedBackend::new(vec![Ok((429, Some("7"))), Ok((200, None))]);
+        let (result, sleeps) = run(&backend, &RetryPolicy::default());
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(sleeps, vec![Duration::from_secs(7)]);
+
+        // Asking for a longer delay than we're willing to wait returns the response.
+        let backend = ScriptedBackend::new(vec![Ok((429, Some("3600")))]);
+        let (result, sleeps) = run(&backend, &RetryPolicy::default());
+        assert_eq!(result.unwrap().status, 429);
+        assert!(sleeps.is_empty());
+
+        // Unless we've been asked to ignore it.
+        let backend = ScriptedBackend::new(vec![Ok((429, Some("3600"))), Ok((200, None))]);
+        let policy = RetryPolicy {
+            respect_retry_after: false,
+            ..Default::default()
+        };
+        let (result, sleeps) = run(&backend, &policy);
+        assert_eq!(result.unwrap().status, 200);
+        assert_eq!(sleeps, vec![Duration::from_millis(500)]);
+    }
+}
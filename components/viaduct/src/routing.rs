@@ -0,0 +1,176 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Per-request routing: sending a request directly, through an explicit proxy, or through an
+//! [Oblivious HTTP](https://www.rfc-editor.org/rfc/rfc9458) relay.
+//!
+//! OHTTP is handled here rather than in the backends: the request is encapsulated, the resulting
+//! message is POSTed to the relay as an ordinary request, and the relay's reply is decapsulated
+//! back into the response for the original request. Proxies need support from the backend; see
+//! [`BackendCapabilities::proxy`](crate::BackendCapabilities::proxy).
+
+use crate::{backend::Backend, Error, Request, Response};
+use url::Url;
+
+/// How a request reaches its server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// Connect to the server directly.
+    #[default]
+    Direct,
+    /// Send the request through the HTTP proxy at the given URL.
+    Proxy(Url),
+    /// Encapsulate the request and send it through an OHTTP relay, so that the relay can't see
+    /// its contents and the server can't see who sent it.
+    ///
+    /// Requires viaduct to be built with the `ohttp` feature.
+    Ohttp(OhttpRelay),
+}
+
+/// An OHTTP relay, and the gateway behind it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OhttpRelay {
+    /// The URL the encapsulated request is POSTed to.
+    pub relay_url: Url,
+    /// The gateway's encoded key configuration, as served by its `ohttp-keys` endpoint.
+    ///
+    /// Fetching (and caching) this is left to the caller, since how often to do that is a
+    /// privacy decision: every fetch is a non-relayed request to the gateway.
+    pub gateway_key_config: Vec<u8>,
+}
+
+/// Wraps a backend to send each request through an OHTTP relay.
+///
+/// This is itself a `Backend` so that it composes with `send_with_retry`: every attempt gets a
+/// fresh OHTTP session, since a session can only be used for a single request.
+#[cfg(feature = "ohttp")]
+pub(crate) struct OhttpTransport {
+    pub(crate) backend: &'static dyn Backend,
+    pub(crate) relay: OhttpRelay,
+}
+
+#[cfg(feature = "ohttp")]
+impl Backend for OhttpTransport {
+    fn send(&self, request: Request) -> Result<Response, Error> {
+        use crate::header_names;
+        use as_ohttp_client::OhttpSession;
+
+        let ohttp_error = |e: as_ohttp_client::OhttpError| Error::OhttpError(e.to_string());
+        let session = OhttpSession::new(&self.relay.gateway_key_config).map_err(ohttp_error)?;
+        let authority = match request.url.port() {
+            Some(port) => format!("{}:{}", request.url.host_str().unwrap_or_default(), port),
+            None => request.url.host_str().unwrap_or_default().to_owned(),
+        };
+        let capsule = session
+            .encapsulate(
+                request.method.as_str(),
+                request.url.scheme(),
+                &authority,
+                &request.url[url::Position::BeforePath..url::Position::AfterQuery],
+                request.headers.into(),
+                request.body.as_deref().unwrap_or_default(),
+            )
+            .map_err(ohttp_error)?;
+
+        let mut relay_request = Request::post(self.relay.relay_url.clone())
+            .header(header_names::CONTENT_TYPE, "message/ohttp-req")?
+            .body(capsule);
+        relay_request.connect_timeout = request.connect_timeout;
+        relay_request.read_timeout = request.read_timeout;
+        let relay_response = self.backend.send(relay_request)?;
+        if !relay_response.is_success() {
+            // Treat this like a failure to reach the server, since that's what it is from the
+            // caller's point of view. It also means retry policies apply to it.
+            return Err(Error::NetworkError(format!(
+                "OHTTP relay returned {}",
+                relay_response.status
+            )));
+        }
+
+        let inner = session
+            .decapsulate(&relay_response.body)
+            .map_err(ohttp_error)?;
+        let mut headers = crate::Headers::with_capacity(inner.headers.len());
+        for (name, value) in inner.headers {
+            match crate::HeaderName::new(name) {
+                Ok(name) => {
+                    headers.insert_header(crate::Header::new_unchecked(name, value));
+                }
+                Err(e) => log::warn!("Gateway sent back invalid header name: '{}'", e),
+            }
+        }
+        Ok(Response {
+            request_method: request.method,
+            url: request.url,
+            status: inner.status_code,
+            headers,
+            body: inner.payload,
+        })
+    }
+}
+
+/// Check that the backend can route `request` the way it asks to be.
+pub(crate) fn validate_routing(backend: &dyn Backend, request: &Request) -> Result<(), Error> {
+    match &request.routing {
+        Routing::Direct => Ok(()),
+        Routing::Proxy(_) if backend.capabilities().proxy => Ok(()),
+        Routing::Proxy(_) => Err(Error::UnsupportedRouting("proxy")),
+        Routing::Ohttp(_) if cfg!(feature = "ohttp") => Ok(()),
+        Routing::Ohttp(_) => Err(Error::UnsupportedRouting("ohttp")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BackendCapabilities;
+
+    struct NoProxyBackend;
+
+    impl Backend for NoProxyBackend {
+        fn send(&self, _request: Request) -> Result<Response, Error> {
+            unreachable!()
+        }
+    }
+
+    struct ProxyBackend;
+
+    impl Backend for ProxyBackend {
+        fn send(&self, _request: Request) -> Result<Response, Error> {
+            unreachable!()
+        }
+
+        fn capabilities(&self) -> BackendCapabilities {
+            BackendCapabilities {
+                proxy: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_routing() {
+        let url = Url::parse("https://example.com").unwrap();
+        let proxy = Url::parse("http://proxy.example.com:3128").unwrap();
+
+        let request = Request::get(url.clone());
+        assert!(validate_routing(&NoProxyBackend, &request).is_ok());
+
+        let request = Request::get(url.clone()).routing(Routing::Proxy(proxy));
+        assert!(matches!(
+            validate_routing(&NoProxyBackend, &request),
+            Err(Error::UnsupportedRouting("proxy"))
+        ));
+        assert!(validate_routing(&ProxyBackend, &request).is_ok());
+
+        let request = Request::get(url).routing(Routing::Ohttp(OhttpRelay {
+            relay_url: Url::parse("https://relay.example.com").unwrap(),
+            gateway_key_config: vec![],
+        }));
+        assert_eq!(
+            validate_routing(&NoProxyBackend, &request).is_ok(),
+            cfg!(feature = "ohttp")
+        );
+    }
+}
@@ -16,11 +16,27 @@ use url::Url;
 /// with custom settings. In the reqwest backend this would store a Client, and
 /// in the concept-fetch backend it would only store the settings, and populate
 /// things on the fly.
+///
+/// The timeouts and size limits are the exception: they're process-wide defaults
+/// which can be overridden for an individual request with
+/// `Request::connect_timeout()`, `Request::read_timeout()`,
+/// `Request::max_request_body_size()` and `Request::max_response_size()`, and
+/// changes to them take effect on the next request.
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct Settings {
+    /// Default read timeout, used for requests which don't specify their own.
+    /// `None` means no timeout.
     pub read_timeout: Option<Duration>,
+    /// Default connect timeout, used for requests which don't specify their own.
+    /// `None` means no timeout.
     pub connect_timeout: Option<Duration>,
+    /// Default limit on the size of request bodies, in bytes, used for requests
+    /// which don't specify their own. `None`, the default, means no limit.
+    pub max_request_body_size: Option<u64>,
+    /// Default limit on the size of response bodies, in bytes, used for requests
+    /// which don't specify their own. `None`, the default, means no limit.
+    pub max_response_size: Option<u64>,
     pub follow_redirects: bool,
     pub use_caches: bool,
     // For testing purposes, we allow exactly one additional Url which is
@@ -39,6 +55,10 @@ pub static GLOBAL_SETTINGS: Lazy<RwLock<Settings>> = Lazy::new(|| {
     RwLock::new(Settings {
         read_timeout: Some(TIMEOUT_DURATION),
         connect_timeout: Some(TIMEOUT_DURATION),
+        // Consumers know how big their requests and responses can get, so they
+        // opt in to limits per request.
+        max_request_body_size: None,
+        max_response_size: None,
         follow_redirects: true,
         use_caches: false,
         addn_allowed_insecure_url: None,
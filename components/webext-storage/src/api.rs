@@ -8,6 +8,7 @@ use serde::{ser::SerializeMap, Serialize, Serializer};
 
 use serde_json::{Map, Value as JsonValue};
 use sql_support::{self, ConnExt};
+use std::collections::{BTreeMap, BTreeSet};
 
 // These constants are defined by the chrome.storage.sync spec. We export them
 // publicly from this module, then from the crate, so they wind up in the
@@ -26,7 +27,6 @@ type JsonMap = Map<String, JsonValue>;
 enum StorageChangeOp {
     Clear,
     Set(JsonValue),
-    SetWithoutQuota(JsonValue),
 }
 
 fn get_from_db(conn: &Connection, ext_id: &str) -> Result<Option<JsonMap>> {
@@ -52,10 +52,10 @@ fn save_to_db(tx: &Transaction<'_>, ext_id: &str, val: &StorageChangeOp) -> Resu
     // This function also handles removals. Either an empty map or explicit null
     // is a removal. If there's a mirror record for this extension ID, then we
     // must leave a tombstone behind for syncing.
+    // Quota is enforced by `set()`, not here, so that removals always work.
     let is_delete = match val {
         StorageChangeOp::Clear => true,
         StorageChangeOp::Set(JsonValue::Object(v)) => v.is_empty(),
-        StorageChangeOp::SetWithoutQuota(JsonValue::Object(v)) => v.is_empty(),
         _ => false,
     };
     if is_delete {
@@ -91,16 +91,8 @@ fn save_to_db(tx: &Transaction<'_>, ext_id: &str, val: &StorageChangeOp) -> Resu
             )?;
         }
     } else {
-        // Convert to bytes so we can enforce the quota if necessary.
         let sval = match val {
-            StorageChangeOp::Set(v) => {
-                let sv = v.to_string();
-                if sv.len() > SYNC_QUOTA_BYTES {
-                    return Err(Error::QuotaError(QuotaReason::TotalBytes));
-                }
-                sv
-            }
-            StorageChangeOp::SetWithoutQuota(v) => v.to_string(),
+            StorageChangeOp::Set(v) => v.to_string(),
             StorageChangeOp::Clear => unreachable!(),
         };
 
@@ -187,6 +179,10 @@ pub fn get_quota_size_of(key: &str, v: &JsonValue) -> usize {
     key.len() + v.to_string().len()
 }
 
+fn get_total_quota_size(items: &JsonMap) -> usize {
+    items.iter().map(|(k, v)| get_quota_size_of(k, v)).sum()
+}
+
 /// The implementation of `storage[.sync].set()`. On success this returns the
 /// StorageChanges defined by the chrome API - it's assumed the caller will
 /// arrange to deliver this to observers as defined in that API.
@@ -220,6 +216,11 @@ pub fn set(tx: &Transaction<'_>, ext_id: &str, val: JsonValue) -> Result<Storage
         changes.push(change);
         current.insert(k, v);
     }
+    // As on desktop, the total is the sum of the item sizes, so it matches
+    // `getBytesInUse()`.
+    if get_total_quota_size(&current) > SYNC_QUOTA_BYTES {
+        return Err(Error::QuotaError(QuotaReason::TotalBytes));
+    }
 
     save_to_db(
         tx,
@@ -303,7 +304,7 @@ pub fn remove(tx: &Transaction<'_>, ext_id: &str, keys: JsonValue) -> Result<Sto
         save_to_db(
             tx,
             ext_id,
-            &StorageChangeOp::SetWithoutQuota(JsonValue::Object(existing)),
+            &StorageChangeOp::Set(JsonValue::Object(existing)),
         )?;
     }
     Ok(result)
@@ -329,6 +330,55 @@ pub fn clear(tx: &Transaction<'_>, ext_id: &str) -> Result<StorageChanges> {
     Ok(result)
 }
 
+/// Returns the data stored for every extension, by extension ID. Used with
+/// `changes_between()` to work out what changed when data is replaced in
+/// bulk, rather than with `set()`, `remove()` or `clear()`.
+pub fn get_all(conn: &Connection) -> Result<BTreeMap<String, JsonMap>> {
+    let mut stmt =
+        conn.prepare("SELECT ext_id, data FROM storage_sync_data WHERE data IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut all = BTreeMap::new();
+    for row in rows {
+        let (ext_id, data) = row?;
+        if let JsonValue::Object(m) = serde_json::from_str(&data)? {
+            all.insert(ext_id, m);
+        }
+    }
+    Ok(all)
+}
+
+/// Returns the changes for each extension between two results of `get_all()`.
+pub fn changes_between(
+    mut before: BTreeMap<String, JsonMap>,
+    mut after: BTreeMap<String, JsonMap>,
+) -> Vec<(String, StorageChanges)> {
+    let ext_ids: BTreeSet<String> = before.keys().chain(after.keys()).cloned().collect();
+    let mut result = Vec::new();
+    for ext_id in ext_ids {
+        let mut old = before.remove(&ext_id).unwrap_or_default();
+        let mut new = after.remove(&ext_id).unwrap_or_default();
+        let keys: BTreeSet<String> = old.keys().chain(new.keys()).cloned().collect();
+        let mut changes = StorageChanges::new();
+        for key in keys {
+            let old_value = old.remove(&key);
+            let new_value = new.remove(&key);
+            if old_value != new_value {
+                changes.push(StorageValueChange {
+                    key,
+                    old_value,
+                    new_value,
+                });
+            }
+        }
+        if !changes.is_empty() {
+            result.push((ext_id, changes));
+        }
+    }
+    result
+}
+
 /// The implementation of `storage[.sync].getBytesInUse()`.
 pub fn get_bytes_in_use(conn: &Connection, ext_id: &str, keys: JsonValue) -> Result<usize> {
     let maybe_existing = get_from_db(conn, ext_id)?;
@@ -354,6 +404,29 @@ pub fn get_bytes_in_use(conn: &Connection, ext_id: &str, keys: JsonValue) -> Res
     Ok(size)
 }
 
+/// How much of its `storage.sync` quota an extension is using.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// The same as `get_bytes_in_use()` for all of the extension's keys.
+    pub bytes_in_use: u64,
+    pub num_items: u64,
+    pub quota_bytes: u64,
+    pub quota_bytes_per_item: u64,
+    pub max_items: u64,
+}
+
+/// Returns an extension's usage, along with the limits it's subject to.
+pub fn get_quota_usage(conn: &Connection, ext_id: &str) -> Result<QuotaUsage> {
+    let existing = get_from_db(conn, ext_id)?.unwrap_or_default();
+    Ok(QuotaUsage {
+        bytes_in_use: get_total_quota_size(&existing) as u64,
+        num_items: existing.len() as u64,
+        quota_bytes: SYNC_QUOTA_BYTES as u64,
+        quota_bytes_per_item: SYNC_QUOTA_BYTES_PER_ITEM as u64,
+        max_items: SYNC_MAX_ITEMS as u64,
+    })
+}
+
 /// Information about the usage of a single extension.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UsageInfo {
@@ -657,11 +730,7 @@ mod tests {
         let val = "x".repeat(SYNC_QUOTA_BYTES + 1);
 
         // Init an over quota db with a single key.
-        save_to_db(
-            &tx,
-            ext_id,
-            &StorageChangeOp::SetWithoutQuota(json!({ "x": val })),
-        )?;
+        save_to_db(&tx, ext_id, &StorageChangeOp::Set(json!({ "x": val })))?;
 
         // Adding more data fails.
         let e = set(&tx, ext_id, json!({ "y": "newvalue" })).unwrap_err();
@@ -674,11 +743,7 @@ mod tests {
         remove(&tx, ext_id, json!["x"])?;
 
         // Restore the over quota data.
-        save_to_db(
-            &tx,
-            ext_id,
-            &StorageChangeOp::SetWithoutQuota(json!({ "y": val })),
-        )?;
+        save_to_db(&tx, ext_id, &StorageChangeOp::Set(json!({ "y": val })))?;
 
         // Overwrite with less data does not fail.
         set(&tx, ext_id, json!({ "y": "lessdata" }))?;
@@ -686,6 +751,52 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_quota_usage() -> Result<()> {
+        let db = new_mem_db();
+        let conn = db.get_connection().expect("should retrieve connection");
+        let tx = conn.unchecked_transaction()?;
+        let ext_id = "xyz";
+        assert_eq!(get_quota_usage(&tx, ext_id)?.bytes_in_use, 0);
+
+        // 100 items of exactly 1024 bytes each (a 4 byte key, and the value
+        // with its quotes) fill the quota. The JSON for the whole object is
+        // bigger than that, but as on desktop, only the items count.
+        let items: JsonMap = (0..100)
+            .map(|i| (format!("k{:03}", i), json!("x".repeat(1018))))
+            .collect();
+        set(&tx, ext_id, JsonValue::Object(items))?;
+        assert_eq!(
+            get_quota_usage(&tx, ext_id)?,
+            QuotaUsage {
+                bytes_in_use: SYNC_QUOTA_BYTES as u64,
+                num_items: 100,
+                quota_bytes: SYNC_QUOTA_BYTES as u64,
+                quota_bytes_per_item: SYNC_QUOTA_BYTES_PER_ITEM as u64,
+                max_items: SYNC_MAX_ITEMS as u64,
+            }
+        );
+        assert_eq!(
+            get_bytes_in_use(&tx, ext_id, json!(null))?,
+            SYNC_QUOTA_BYTES
+        );
+
+        let e = set(&tx, ext_id, json!({ "k100": "" })).unwrap_err();
+        match e {
+            Error::QuotaError(QuotaReason::TotalBytes) => {}
+            _ => panic!("unexpected error type"),
+        };
+        let api_error = WebExtStorageApiError::from(e);
+        assert!(matches!(
+            api_error,
+            WebExtStorageApiError::QuotaError {
+                reason: QuotaReason::TotalBytes,
+                ref message,
+            } if message == QUOTA_EXCEEDED_MESSAGE
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_get_bytes_in_use() -> Result<()> {
         let db = new_mem_db();
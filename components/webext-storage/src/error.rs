@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use error_support::{ErrorHandling, GetErrorHandling};
+use error_support::{ErrorHandling, FromPanic, GetErrorHandling};
 use interrupt_support::Interrupted;
 use std::fmt;
 
@@ -36,10 +36,19 @@ pub enum WebExtStorageApiError {
     #[error("Error parsing JSON data: {reason}")]
     JsonError { reason: String },
 
+    /// `message` is the message desktop Firefox gives extensions for quota errors.
     #[error("Quota exceeded: {reason}")]
-    QuotaError { reason: QuotaReason },
+    QuotaError {
+        reason: QuotaReason,
+        message: String,
+    },
 }
 
+/// The message desktop Firefox gives extensions when `storage.sync` quota is exceeded, so
+/// extensions see the same error everywhere.
+pub const QUOTA_EXCEEDED_MESSAGE: &str =
+    "QuotaExceededError: storage.sync API call exceeded its quota limitations.";
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Quota exceeded: {0:?}")]
@@ -96,7 +105,10 @@ impl GetErrorHandling for Error {
         match self {
             Error::QuotaError(reason) => {
                 log::info!("webext-storage-quota-error");
-                ErrorHandling::convert(WebExtStorageApiError::QuotaError { reason: *reason })
+                ErrorHandling::convert(WebExtStorageApiError::QuotaError {
+                    reason: *reason,
+                    message: QUOTA_EXCEEDED_MESSAGE.to_string(),
+                })
             }
             Error::JsonError(e) => {
                 log::info!("webext-storage-json-error");
@@ -120,7 +132,10 @@ impl From<Error> for WebExtStorageApiError {
             Error::JsonError(e) => WebExtStorageApiError::JsonError {
                 reason: e.to_string(),
             },
-            Error::QuotaError(reason) => WebExtStorageApiError::QuotaError { reason },
+            Error::QuotaError(reason) => WebExtStorageApiError::QuotaError {
+                reason,
+                message: QUOTA_EXCEEDED_MESSAGE.to_string(),
+            },
             _ => WebExtStorageApiError::UnexpectedError {
                 reason: err.to_string(),
             },
@@ -144,6 +159,12 @@ impl From<serde_json::Error> for WebExtStorageApiError {
     }
 }
 
+impl FromPanic for WebExtStorageApiError {
+    fn from_panic(message: String) -> Self {
+        WebExtStorageApiError::UnexpectedError { reason: message }
+    }
+}
+
 impl From<anyhow::Error> for WebExtStorageApiError {
     fn from(value: anyhow::Error) -> Self {
         WebExtStorageApiError::UnexpectedError {
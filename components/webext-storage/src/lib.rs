@@ -24,9 +24,9 @@ pub use api::SYNC_QUOTA_BYTES;
 pub use api::SYNC_QUOTA_BYTES_PER_ITEM;
 
 pub use crate::error::{QuotaReason, WebExtStorageApiError};
-pub use crate::store::WebExtStorageStore;
+pub use crate::store::{StorageChangeObserver, WebExtStorageStore};
 pub use crate::sync::{bridge::WebExtStorageBridgedEngine, SyncedExtensionChange};
-pub use api::UsageInfo;
+pub use api::{QuotaUsage, UsageInfo};
 pub use api::{StorageChanges, StorageValueChange};
 
 uniffi::include_scaffolding!("webext-storage");
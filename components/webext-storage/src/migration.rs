@@ -443,7 +443,23 @@ mod tests {
 
         // now migrate
         let db = crate::store::test::new_mem_store();
+        let observer = std::sync::Arc::new(crate::store::test::RecordingObserver::default());
+        db.register_change_observer(Box::new(observer.clone()));
         db.migrate(&path).expect("migration should work");
+        // Observers are told about each migrated extension.
+        let ext_ids: Vec<_> = observer
+            .0
+            .lock()
+            .iter()
+            .map(|(ext_id, _)| ext_id.clone())
+            .collect();
+        assert_eq!(
+            ext_ids,
+            vec![
+                "https-everywhere@eff.org",
+                "{e7fefcf3-b39c-4f17-5215-ebfe120a7031}"
+            ]
+        );
         let mi = db
             .take_migration_info()
             .expect("take failed with info present");
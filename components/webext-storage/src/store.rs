@@ -8,9 +8,11 @@ use crate::error::*;
 use crate::migration::{migrate, MigrationInfo};
 use crate::sync;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
 use interrupt_support::SqlInterruptHandle;
+use parking_lot::Mutex;
 use serde_json::Value as JsonValue;
 
 /// A store is used to access `storage.sync` data. It manages an underlying
@@ -30,6 +32,57 @@ use serde_json::Value as JsonValue;
 /// around the same object.
 pub struct WebExtStorageStore {
     pub(crate) db: Arc<ThreadSafeStorageDb>,
+    pub(crate) observers: Arc<ChangeObservers>,
+}
+
+/// Notified of every change to `storage.sync` data, whether it was made
+/// through the store or applied by a sync. See
+/// `WebExtStorageStore::register_change_observer()`.
+pub trait StorageChangeObserver: Send + Sync {
+    fn on_changed(&self, ext_id: String, changes: StorageChanges);
+}
+
+/// The observers registered with a store, by ID. This is shared with the
+/// store's bridged engine, so that changes applied by a sync are reported too.
+#[derive(Default)]
+pub(crate) struct ChangeObservers {
+    observers: Mutex<Vec<(u64, Arc<dyn StorageChangeObserver>)>>,
+    next_id: AtomicU64,
+}
+
+impl ChangeObservers {
+    fn register(&self, observer: Arc<dyn StorageChangeObserver>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.observers.lock().push((id, observer));
+        id
+    }
+
+    fn unregister(&self, id: u64) {
+        self.observers
+            .lock()
+            .retain(|(observer_id, _)| *observer_id != id);
+    }
+
+    /// Notifies all observers of `changes`, unless there aren't any. This
+    /// must be called without the database lock held, so that observers can
+    /// call back into the store.
+    pub(crate) fn notify(&self, ext_id: &str, changes: &StorageChanges) {
+        if changes.is_empty() {
+            return;
+        }
+        // Clone the list first, so observers aren't called with the lock held.
+        let observers = self.observers.lock().clone();
+        for (_, observer) in observers {
+            observer.on_changed(ext_id.to_string(), changes.clone());
+        }
+    }
+
+    /// Notifies all observers of the changes to each extension.
+    pub(crate) fn notify_all(&self, changes: &[(String, StorageChanges)]) {
+        for (ext_id, changes) in changes {
+            self.notify(ext_id, changes);
+        }
+    }
 }
 
 impl WebExtStorageStore {
@@ -39,6 +92,7 @@ impl WebExtStorageStore {
         let db = StorageDb::new(db_path)?;
         Ok(Self {
             db: Arc::new(ThreadSafeStorageDb::new(db)),
+            observers: Default::default(),
         })
     }
 
@@ -48,6 +102,7 @@ impl WebExtStorageStore {
         let db = StorageDb::new_memory(db_path)?;
         Ok(Self {
             db: Arc::new(ThreadSafeStorageDb::new(db)),
+            observers: Default::default(),
         })
     }
 
@@ -56,14 +111,33 @@ impl WebExtStorageStore {
         self.db.interrupt_handle()
     }
 
+    /// Registers an observer to be notified of all subsequent changes to
+    /// `storage.sync` data, including changes applied by a sync, a wipe or a
+    /// migration. Observers are called on the thread which made the change,
+    /// after it's been committed. Returns an ID to pass to
+    /// `unregister_change_observer()`.
+    pub fn register_change_observer(&self, observer: Box<dyn StorageChangeObserver>) -> u64 {
+        self.observers.register(Arc::from(observer))
+    }
+
+    /// Stops notifying the observer registered with `id`. Unknown IDs are
+    /// ignored.
+    pub fn unregister_change_observer(&self, id: u64) {
+        self.observers.unregister(id);
+    }
+
     /// Sets one or more JSON key-value pairs for an extension ID. Returns a
     /// list of changes, with existing and new values for each key in `val`.
     pub fn set(&self, ext_id: &str, val: JsonValue) -> Result<StorageChanges> {
-        let db = &self.db.lock();
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        let result = api::set(&tx, ext_id, val)?;
-        tx.commit()?;
+        let result = {
+            let db = &self.db.lock();
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let result = api::set(&tx, ext_id, val)?;
+            tx.commit()?;
+            result
+        };
+        self.observers.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -102,11 +176,15 @@ impl WebExtStorageStore {
     /// of changes, where each change contains the old value for each deleted
     /// key.
     pub fn remove(&self, ext_id: &str, keys: JsonValue) -> Result<StorageChanges> {
-        let db = &self.db.lock();
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        let result = api::remove(&tx, ext_id, keys)?;
-        tx.commit()?;
+        let result = {
+            let db = &self.db.lock();
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let result = api::remove(&tx, ext_id, keys)?;
+            tx.commit()?;
+            result
+        };
+        self.observers.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -114,11 +192,15 @@ impl WebExtStorageStore {
     /// a list of changes, where each change contains the old value for each
     /// deleted key.
     pub fn clear(&self, ext_id: &str) -> Result<StorageChanges> {
-        let db = &self.db.lock();
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        let result = api::clear(&tx, ext_id)?;
-        tx.commit()?;
+        let result = {
+            let db = &self.db.lock();
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let result = api::clear(&tx, ext_id)?;
+            tx.commit()?;
+            result
+        };
+        self.observers.notify(ext_id, &result);
         Ok(result)
     }
 
@@ -130,6 +212,14 @@ impl WebExtStorageStore {
         Ok(api::get_bytes_in_use(conn, ext_id, keys)? as u64)
     }
 
+    /// Returns how much of its quota the extension is using, and what the
+    /// limits are.
+    pub fn get_quota_usage(&self, ext_id: &str) -> Result<api::QuotaUsage> {
+        let db = &self.db.lock();
+        let conn = db.get_connection()?;
+        api::get_quota_usage(conn, ext_id)
+    }
+
     /// Closes the store and its database connection. See the docs for
     /// `StorageDb::close` for more details on when this can fail.
     pub fn close(&self) -> Result<()> {
@@ -153,16 +243,22 @@ impl WebExtStorageStore {
     ///
     /// Note that `filename` isn't normalized or canonicalized.
     pub fn migrate(&self, filename: impl AsRef<Path>) -> Result<()> {
-        let db = &self.db.lock();
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        let result = migrate(&tx, filename.as_ref())?;
-        tx.commit()?;
-        // Failing to store this information should not cause migration failure.
-        if let Err(e) = result.store(conn) {
-            debug_assert!(false, "Migration error: {:?}", e);
-            log::warn!("Failed to record migration telmetry: {}", e);
-        }
+        let changes = {
+            let db = &self.db.lock();
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let before = api::get_all(&tx)?;
+            let result = migrate(&tx, filename.as_ref())?;
+            let changes = api::changes_between(before, api::get_all(&tx)?);
+            tx.commit()?;
+            // Failing to store this information should not cause migration failure.
+            if let Err(e) = result.store(conn) {
+                debug_assert!(false, "Migration error: {:?}", e);
+                log::warn!("Failed to record migration telmetry: {}", e);
+            }
+            changes
+        };
+        self.observers.notify_all(&changes);
         Ok(())
     }
 
@@ -191,6 +287,46 @@ pub mod test {
     pub fn new_mem_store() -> WebExtStorageStore {
         WebExtStorageStore {
             db: Arc::new(ThreadSafeStorageDb::new(crate::db::test::new_mem_db())),
+            observers: Default::default(),
         }
     }
+
+    #[derive(Default)]
+    pub struct RecordingObserver(pub Mutex<Vec<(String, StorageChanges)>>);
+
+    impl StorageChangeObserver for Arc<RecordingObserver> {
+        fn on_changed(&self, ext_id: String, changes: StorageChanges) {
+            self.0.lock().push((ext_id, changes));
+        }
+    }
+
+    #[test]
+    fn test_change_observers() -> Result<()> {
+        let store = new_mem_store();
+        let observer = Arc::new(RecordingObserver::default());
+        store.register_change_observer(Box::new(observer.clone()));
+
+        let changes = store.set("ext-id", serde_json::json!({"a": 1}))?;
+        store.get("ext-id", JsonValue::Null)?;
+        // Removing a key which doesn't exist isn't a change.
+        store.remove("ext-id", serde_json::json!("b"))?;
+        let cleared = store.clear("ext-id")?;
+
+        assert_eq!(
+            *observer.0.lock(),
+            vec![
+                ("ext-id".to_string(), changes),
+                ("ext-id".to_string(), cleared)
+            ]
+        );
+
+        // Unregistered observers aren't notified.
+        let other = Arc::new(RecordingObserver::default());
+        let other_id = store.register_change_observer(Box::new(other.clone()));
+        store.unregister_change_observer(other_id);
+        store.set("ext-id", serde_json::json!({"b": 2}))?;
+        assert_eq!(observer.0.lock().len(), 3);
+        assert!(other.0.lock().is_empty());
+        Ok(())
+    }
 }
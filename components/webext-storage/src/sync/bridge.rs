@@ -3,14 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use anyhow::Result;
+use error_support::handle_panics;
 use rusqlite::Transaction;
 use std::sync::{Arc, Weak};
 use sync15::bso::{IncomingBso, OutgoingBso};
 use sync15::engine::{ApplyResults, BridgedEngine as Sync15BridgedEngine};
 use sync_guid::Guid as SyncGuid;
 
+use crate::api::{self, StorageChanges};
 use crate::db::{delete_meta, get_meta, put_meta, ThreadSafeStorageDb};
+use crate::error::WebExtStorageApiError;
 use crate::schema;
+use crate::store::ChangeObservers;
 use crate::sync::incoming::{apply_actions, get_incoming, plan_incoming, stage_incoming};
 use crate::sync::outgoing::{get_outgoing, record_uploaded, stage_outgoing};
 use crate::WebExtStorageStore;
@@ -21,7 +25,8 @@ const SYNC_ID_META_KEY: &str = "sync_id";
 impl WebExtStorageStore {
     // Returns a bridged sync engine for this store.
     pub fn bridged_engine(self: Arc<Self>) -> Arc<WebExtStorageBridgedEngine> {
-        let engine = Box::new(BridgedEngine::new(&self.db));
+        let mut engine = Box::new(BridgedEngine::new(&self.db));
+        engine.observers = Arc::downgrade(&self.observers);
         let bridged_engine = WebExtStorageBridgedEngine {
             bridge_impl: engine,
         };
@@ -40,6 +45,8 @@ impl WebExtStorageStore {
 /// engines all took lifetime params to ensure they don't outlive the store.
 pub struct BridgedEngine {
     db: Weak<ThreadSafeStorageDb>,
+    // Notified of the changes applied by each sync.
+    observers: Weak<ChangeObservers>,
 }
 
 impl BridgedEngine {
@@ -47,6 +54,7 @@ impl BridgedEngine {
     pub fn new(db: &Arc<ThreadSafeStorageDb>) -> Self {
         BridgedEngine {
             db: Arc::downgrade(db),
+            observers: Weak::new(),
         }
     }
 
@@ -59,6 +67,13 @@ impl BridgedEngine {
         Ok(())
     }
 
+    /// Notifies observers of changes, without the database lock held.
+    fn notify_observers(&self, changes: &[(String, StorageChanges)]) {
+        if let Some(observers) = self.observers.upgrade() {
+            observers.notify_all(changes);
+        }
+    }
+
     fn thread_safe_storage_db(&self) -> Result<Arc<ThreadSafeStorageDb>> {
         self.db
             .upgrade()
@@ -145,20 +160,23 @@ impl Sync15BridgedEngine for BridgedEngine {
 
     fn apply(&self) -> Result<ApplyResults> {
         let shared_db = self.thread_safe_storage_db()?;
-        let db = shared_db.lock();
-        let signal = db.begin_interrupt_scope()?;
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        let incoming = get_incoming(&tx)?;
-        let actions = incoming
-            .into_iter()
-            .map(|(item, state)| (item, plan_incoming(state)))
-            .collect();
-        apply_actions(&tx, actions, &signal)?;
-        stage_outgoing(&tx)?;
-        tx.commit()?;
-
-        Ok(get_outgoing(conn, &signal)?.into())
+        let (applied, outgoing) = {
+            let db = shared_db.lock();
+            let signal = db.begin_interrupt_scope()?;
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let incoming = get_incoming(&tx)?;
+            let actions = incoming
+                .into_iter()
+                .map(|(item, state)| (item, plan_incoming(state)))
+                .collect();
+            let applied = apply_actions(&tx, actions, &signal)?;
+            stage_outgoing(&tx)?;
+            tx.commit()?;
+            (applied, get_outgoing(conn, &signal)?)
+        };
+        self.notify_observers(&applied);
+        Ok(outgoing.into())
     }
 
     fn set_uploaded(&self, _server_modified_millis: i64, ids: &[SyncGuid]) -> Result<()> {
@@ -194,14 +212,19 @@ impl Sync15BridgedEngine for BridgedEngine {
 
     fn wipe(&self) -> Result<()> {
         let shared_db = self.thread_safe_storage_db()?;
-        let db = shared_db.lock();
-        let conn = db.get_connection()?;
-        let tx = conn.unchecked_transaction()?;
-        // We assume the meta table is only used by sync.
-        tx.execute_batch(
-            "DELETE FROM storage_sync_data; DELETE FROM storage_sync_mirror; DELETE FROM meta;",
-        )?;
-        tx.commit()?;
+        let changes = {
+            let db = shared_db.lock();
+            let conn = db.get_connection()?;
+            let tx = conn.unchecked_transaction()?;
+            let before = api::get_all(&tx)?;
+            // We assume the meta table is only used by sync.
+            tx.execute_batch(
+                "DELETE FROM storage_sync_data; DELETE FROM storage_sync_mirror; DELETE FROM meta;",
+            )?;
+            tx.commit()?;
+            api::changes_between(before, Default::default())
+        };
+        self.notify_observers(&changes);
         Ok(())
     }
 }
@@ -239,14 +262,22 @@ impl WebExtStorageBridgedEngine {
         self.bridge_impl.prepare_for_sync(client_data)
     }
 
-    pub fn store_incoming(&self, incoming: Vec<String>) -> Result<()> {
-        self.bridge_impl
-            .store_incoming(self.convert_incoming_bsos(incoming)?)
+    // These two handle the records from the server, so a panic on a bad record is returned as an
+    // error instead of crashing the app.
+    #[handle_panics]
+    pub fn store_incoming(
+        &self,
+        incoming: Vec<String>,
+    ) -> std::result::Result<(), WebExtStorageApiError> {
+        Ok(self
+            .bridge_impl
+            .store_incoming(self.convert_incoming_bsos(incoming)?)?)
     }
 
-    pub fn apply(&self) -> Result<Vec<String>> {
+    #[handle_panics]
+    pub fn apply(&self) -> std::result::Result<Vec<String>, WebExtStorageApiError> {
         let apply_results = self.bridge_impl.apply()?;
-        self.convert_outgoing_bsos(apply_results.records)
+        Ok(self.convert_outgoing_bsos(apply_results.records)?)
     }
 
     pub fn set_uploaded(&self, server_modified_millis: i64, guids: Vec<SyncGuid>) -> Result<()> {
@@ -299,6 +330,9 @@ mod tests {
     use super::*;
     use crate::db::test::new_mem_thread_safe_storage_db;
     use crate::db::StorageDb;
+    use crate::store::test::RecordingObserver;
+    use crate::{StorageChanges, StorageValueChange};
+    use serde_json::json;
     use sync15::engine::BridgedEngine;
 
     fn query_count(db: &StorageDb, table: &str) -> u32 {
@@ -502,4 +536,119 @@ mod tests {
         assert_eq!(engine.sync_id()?, Some(new_id));
         Ok(())
     }
+
+    #[test]
+    fn test_apply_notifies_observers() -> Result<()> {
+        let store = Arc::new(crate::store::test::new_mem_store());
+        let observer = Arc::new(RecordingObserver::default());
+        store.register_change_observer(Box::new(observer.clone()));
+        let engine = store.clone().bridged_engine();
+
+        engine.bridge_impl.sync_started()?;
+        engine
+            .bridge_impl
+            .store_incoming(vec![IncomingBso::from_test_content(json!({
+                "id": "guid",
+                "extId": "ext-id",
+                "data": json!({"a": 1}).to_string(),
+            }))])?;
+        engine.bridge_impl.apply()?;
+
+        let seen = observer.0.lock();
+        assert_eq!(
+            *seen,
+            vec![(
+                "ext-id".to_string(),
+                StorageChanges {
+                    changes: vec![StorageValueChange {
+                        key: "a".to_string(),
+                        old_value: None,
+                        new_value: Some(json!(1)),
+                    }],
+                },
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wipe_notifies_observers() -> Result<()> {
+        let store = Arc::new(crate::store::test::new_mem_store());
+        store.set("ext-id", json!({"a": 1}))?;
+        let observer = Arc::new(RecordingObserver::default());
+        store.register_change_observer(Box::new(observer.clone()));
+        let engine = store.clone().bridged_engine();
+
+        engine.wipe()?;
+
+        assert_eq!(
+            *observer.0.lock(),
+            vec![(
+                "ext-id".to_string(),
+                StorageChanges {
+                    changes: vec![StorageValueChange {
+                        key: "a".to_string(),
+                        old_value: Some(json!(1)),
+                        new_value: None,
+                    }],
+                },
+            )]
+        );
+        Ok(())
+    }
+
+    // Stands in for an engine which panics on a bad record.
+    struct PanickingEngine;
+
+    impl BridgedEngine for PanickingEngine {
+        fn last_sync(&self) -> Result<i64> {
+            unimplemented!()
+        }
+        fn set_last_sync(&self, _last_sync_millis: i64) -> Result<()> {
+            unimplemented!()
+        }
+        fn sync_id(&self) -> Result<Option<String>> {
+            unimplemented!()
+        }
+        fn reset_sync_id(&self) -> Result<String> {
+            unimplemented!()
+        }
+        fn ensure_current_sync_id(&self, _new_sync_id: &str) -> Result<String> {
+            unimplemented!()
+        }
+        fn sync_started(&self) -> Result<()> {
+            unimplemented!()
+        }
+        fn store_incoming(&self, _incoming_records: Vec<IncomingBso>) -> Result<()> {
+            panic!("bad incoming record")
+        }
+        fn apply(&self) -> Result<ApplyResults> {
+            panic!("bad staged record")
+        }
+        fn set_uploaded(&self, _server_modified_millis: i64, _ids: &[SyncGuid]) -> Result<()> {
+            unimplemented!()
+        }
+        fn sync_finished(&self) -> Result<()> {
+            unimplemented!()
+        }
+        fn reset(&self) -> Result<()> {
+            unimplemented!()
+        }
+        fn wipe(&self) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_panics_are_returned_as_errors() {
+        let engine = WebExtStorageBridgedEngine::new(Box::new(PanickingEngine));
+        assert!(matches!(
+            engine.store_incoming(vec![]),
+            Err(WebExtStorageApiError::UnexpectedError { reason }) if reason == "bad incoming record"
+        ));
+        assert!(matches!(
+            engine.apply(),
+            Err(WebExtStorageApiError::UnexpectedError { reason }) if reason == "bad staged record"
+        ));
+    }
 }
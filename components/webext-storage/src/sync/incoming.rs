@@ -404,12 +404,15 @@ fn insert_changes(tx: &Transaction<'_>, ext_id: &str, changes: &StorageChanges)
     Ok(())
 }
 
-// Apply the actions necessary to fully process the incoming items.
+// Apply the actions necessary to fully process the incoming items. Returns
+// the changes applied to each extension's data, which are also stashed in the
+// storage_sync_applied table.
 pub fn apply_actions(
     tx: &Transaction<'_>,
     actions: Vec<(SyncGuid, IncomingAction)>,
     signal: &dyn Interruptee,
-) -> Result<()> {
+) -> Result<Vec<(String, StorageChanges)>> {
+    let mut applied = Vec::new();
     for (item, action) in actions {
         signal.err_if_interrupted()?;
 
@@ -422,6 +425,7 @@ pub fn apply_actions(
                     &[(":ext_id", &ext_id)],
                 )?;
                 insert_changes(tx, &ext_id, &changes)?;
+                applied.push((ext_id, changes));
             }
             // We want to update the local record with 'data' and after this update the item no longer is considered dirty.
             IncomingAction::TakeRemote {
@@ -438,6 +442,7 @@ pub fn apply_actions(
                     },
                 )?;
                 insert_changes(tx, &ext_id, &changes)?;
+                applied.push((ext_id, changes));
             }
 
             // We merged this data, so need to update locally but still consider
@@ -455,6 +460,7 @@ pub fn apply_actions(
                     },
                 )?;
                 insert_changes(tx, &ext_id, &changes)?;
+                applied.push((ext_id, changes));
             }
 
             // Both local and remote ended up the same - only need to nuke the
@@ -470,7 +476,7 @@ pub fn apply_actions(
             IncomingAction::Nothing => {}
         }
     }
-    Ok(())
+    Ok(applied)
 }
 
 #[cfg(test)]
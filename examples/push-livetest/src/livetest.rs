@@ -24,6 +24,7 @@ fn test_live_server() {
         sender_id: "".to_string(),
         database_path: tempdir.path().join("test.db").to_string_lossy().to_string(),
         verify_connection_rate_limiter: Some(0),
+        encryption_key: None,
     };
 
     let pm = PushManager::new(push_config).unwrap();
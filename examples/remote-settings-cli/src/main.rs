@@ -116,6 +116,7 @@ fn build_service(cli: &Cli) -> Result<RemoteSettingsService> {
             RemoteSettingsServerArg::Prod => RemoteSettingsServer::Prod,
         }),
         bucket_name: cli.bucket.clone(),
+        sync_parallelism: None,
     };
     Ok(RemoteSettingsService::new(
         cli.storage_dir
@@ -60,6 +60,7 @@ fn read_form_based_login() -> LoginEntry {
         origin,
         username,
         password,
+        ..Default::default()
     }
 }
 
@@ -78,6 +79,7 @@ fn read_auth_based_login() -> LoginEntry {
         origin,
         username,
         password,
+        ..Default::default()
     }
 }
 
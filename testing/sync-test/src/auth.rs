@@ -89,6 +89,7 @@ impl TestClient {
                 engines: engines.to_vec(),
             },
             enabled_changes: HashMap::new(),
+            engine_states: None,
             local_encryption_keys,
             auth_info: self.cli.as_auth_info(),
             persisted_state: self.persisted_state.take(),
@@ -97,6 +98,7 @@ impl TestClient {
                 name: self.device.display_name.clone(),
                 kind: self.device.device_type,
             },
+            priority_engines: None,
         };
         let result = self.sync_manager.sync(params)?;
         // We expect all syncs in these tests to pass, so let's catch that here